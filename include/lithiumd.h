@@ -0,0 +1,50 @@
+/* C API of lithiumd, built with the `ffi` feature:
+ *
+ *     cargo rustc --release --lib --features ffi --crate-type cdylib
+ *
+ * or `--crate-type staticlib`, which also needs -ludev when linking.
+ *
+ * Poll lid_fd() for reading, with lid_timeout() as the timeout, and call
+ * lid_update() whenever the poll returns. Functions returning int report
+ * failures as a negative errno.
+ */
+#ifndef LITHIUMD_H
+#define LITHIUMD_H
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+enum lid_status {
+	LID_STATUS_UNKNOWN = 0,
+	LID_STATUS_DISCHARGING = 1,
+	LID_STATUS_CHARGING = 2,
+	LID_STATUS_FULL = 3,
+	/* On AC, but the battery does not charge, e.g. at a charge limit. */
+	LID_STATUS_NOT_CHARGING = 4,
+};
+
+struct lid;
+
+/* Returns NULL if the power supply cannot be read, with the negative errno
+ * in *error unless error is NULL. */
+struct lid *lid_new(int *error);
+void lid_free(struct lid *lid);
+
+/* May change after lid_update(). */
+int lid_fd(struct lid *lid);
+/* Milliseconds, or -1 for no timeout. */
+int lid_timeout(const struct lid *lid);
+/* Returns 1 if the status or the capacity changed, 0 if not. */
+int lid_update(struct lid *lid);
+
+/* One of enum lid_status. */
+int lid_status(const struct lid *lid);
+/* Percent, or -1 if unknown. */
+int lid_capacity(const struct lid *lid);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
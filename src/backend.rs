@@ -0,0 +1,524 @@
+use std::collections::BTreeMap;
+#[cfg(target_os = "linux")]
+use std::collections::BTreeSet;
+#[cfg(target_os = "linux")]
+use std::ffi::OsStr;
+#[cfg(target_os = "linux")]
+use std::ffi::OsString;
+use std::fs;
+use std::io;
+#[cfg(target_os = "linux")]
+use std::mem;
+#[cfg(target_os = "linux")]
+use std::os::fd::AsRawFd;
+#[cfg(target_os = "linux")]
+use std::os::fd::FromRawFd;
+#[cfg(target_os = "linux")]
+use std::os::fd::OwnedFd;
+#[cfg(target_os = "linux")]
+use std::os::fd::RawFd;
+use std::path::Path;
+use std::path::PathBuf;
+use std::time::Duration;
+
+#[cfg(target_os = "linux")]
+use udev::MonitorSocket;
+
+#[cfg(target_os = "linux")]
+use crate::dock::Dock;
+
+/// Where the kernel lists the power supply devices.
+#[cfg(target_os = "linux")]
+const POWER_SUPPLY_CLASS: &str = "/sys/class/power_supply";
+
+/// A source of power supply devices and of their changes, behind
+/// [`PowerSupply`](crate::PowerSupply). The devices are read from their
+/// sysfs-like directories either way, so a backend only has to tell which
+/// devices there are and when they change:
+///
+/// - [`UdevBackend`], the default, listens to uevents.
+/// - [`SysfsPoller`] rescans sysfs periodically, where there is no netlink,
+///   e.g. in some containers.
+/// - [`UPowerBackend`](crate::UPowerBackend) follows the devices of UPower over
+///   the system bus, e.g. in a Flatpak.
+/// - [`MockBackend`](crate::MockBackend) reads a copy of the tree.
+///
+/// On Windows, where only [`WindowsBackend`](crate::WindowsBackend) exists,
+/// the devices have no directory and carry their state in the properties
+/// instead, and backends are polled at their timeout rather than through a
+/// file descriptor.
+pub trait PowerBackend
+{
+    /// Returns all devices.
+    fn enumerate(&mut self) -> io::Result<Vec<BackendDevice>>;
+
+    /// Returns the file descriptor that is readable while
+    /// [`PowerBackend::drain_events`] has something to return. It may change
+    /// when that reports [`Uevents::reconnected`].
+    #[cfg(target_os = "linux")]
+    fn fd(&mut self) -> io::Result<RawFd>;
+
+    /// Returns the devices that were added, changed or removed since the
+    /// last call, without blocking.
+    fn drain_events(&mut self) -> io::Result<Uevents>;
+
+    /// Returns how long the poll may sleep before
+    /// [`PowerBackend::drain_events`] has to be called even though the file
+    /// descriptor is not readable. `None` if it only has to be called when it
+    /// is.
+    fn timeout(&self) -> Option<Duration>
+    {
+        None
+    }
+}
+
+/// What [`PowerBackend::drain_events`] returns.
+#[derive(Debug, Default)]
+pub struct Uevents
+{
+    pub devices: Vec<BackendDevice>,
+    /// Whether changes were lost, e.g. because the socket buffer overflowed,
+    /// so that the devices have to be enumerated again.
+    pub overrun: bool,
+    /// Whether the backend replaced its file descriptor, e.g. after the
+    /// socket failed. Changes may have been lost as well.
+    pub reconnected: bool,
+    /// Docks that were attached, with `true`, or detached.
+    #[cfg(target_os = "linux")]
+    pub docks: Vec<(Dock, bool)>,
+}
+
+/// A power supply device as a [`PowerBackend`] reports it: a directory of
+/// sysfs attributes and the udev properties.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BackendDevice
+{
+    /// Kernel name of the device, e.g. `BAT0`.
+    pub sysname: String,
+    /// Directory of the attributes, e.g. `/sys/class/power_supply/BAT0`.
+    pub syspath: PathBuf,
+    /// The udev properties, e.g. `POWER_SUPPLY_TYPE`.
+    pub properties: BTreeMap<String, String>,
+    /// Driver of the parent device, e.g. `battery` or `ac`, which is how
+    /// batteries and adapters are told apart.
+    pub driver: Option<String>,
+    /// Whether the device was removed.
+    pub removed: bool,
+}
+
+impl BackendDevice
+{
+    /// Reads the device in `syspath`: the properties from its `uevent` file
+    /// and its `type`, `scope` and `model_name` attributes, and the driver
+    /// from its `device/driver` link. Without that link, the type stands in:
+    /// `Battery` for `battery`, `Mains` for `ac` and others for themselves.
+    pub fn read(syspath: impl Into<PathBuf>) -> io::Result<Self>
+    {
+        let syspath = syspath.into();
+        let mut properties = BTreeMap::new();
+        match fs::read_to_string(syspath.join("uevent")) {
+            Ok(uevent) => {
+                for line in uevent.lines() {
+                    if let Some((key, value)) = line.split_once('=') {
+                        properties.insert(key.to_string(), value.to_string());
+                    }
+                }
+            }
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+            Err(err) => return Err(err),
+        }
+        for (attribute, property) in [
+            ("type", "POWER_SUPPLY_TYPE"),
+            ("scope", "POWER_SUPPLY_SCOPE"),
+            ("model_name", "POWER_SUPPLY_MODEL_NAME"),
+        ] {
+            if let Ok(value) = fs::read_to_string(syspath.join(attribute)) {
+                properties
+                    .entry(property.to_string())
+                    .or_insert_with(|| value.trim_end_matches('\n').to_string());
+            }
+        }
+        let driver = fs::read_link(syspath.join("device/driver"))
+            .ok()
+            .and_then(|link| Some(link.file_name()?.to_string_lossy().into_owned()))
+            .or_else(|| {
+                Some(match properties.get("POWER_SUPPLY_TYPE")?.as_str() {
+                    "Battery" => "battery".to_string(),
+                    "Mains" => "ac".to_string(),
+                    other => other.to_string(),
+                })
+            });
+        Ok(Self {
+            sysname: sysname(&syspath),
+            syspath,
+            properties,
+            driver,
+            removed: false,
+        })
+    }
+
+    /// Returns the device in `syspath` that was removed, which only has its
+    /// name left.
+    #[must_use]
+    pub fn removed(syspath: impl Into<PathBuf>) -> Self
+    {
+        let syspath = syspath.into();
+        Self {
+            sysname: sysname(&syspath),
+            syspath,
+            properties: BTreeMap::new(),
+            driver: None,
+            removed: true,
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    fn from_udev(dev: &udev::Device) -> Self
+    {
+        Self {
+            sysname: dev.sysname().to_string_lossy().into_owned(),
+            syspath: dev.syspath().to_path_buf(),
+            properties: dev
+                .properties()
+                .map(|entry| {
+                    (
+                        entry.name().to_string_lossy().into_owned(),
+                        entry.value().to_string_lossy().into_owned(),
+                    )
+                })
+                .collect(),
+            driver: dev
+                .parent()
+                .and_then(|parent| Some(parent.driver()?.to_str()?.to_string())),
+            removed: dev.property_value("ACTION") == Some(OsStr::new("remove")),
+        }
+    }
+
+    #[must_use]
+    pub fn property_value(&self, name: &str) -> Option<&str>
+    {
+        self.properties.get(name).map(String::as_str)
+    }
+
+    /// Returns the names of the attributes.
+    #[cfg(target_os = "linux")]
+    pub(crate) fn attribute_names(&self) -> Vec<OsString>
+    {
+        fs::read_dir(&self.syspath)
+            .into_iter()
+            .flatten()
+            .filter_map(Result::ok)
+            .filter(|entry| entry.path().is_file())
+            .map(|entry| entry.file_name())
+            .collect()
+    }
+}
+
+fn sysname(syspath: &Path) -> String
+{
+    syspath
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+/// Reads the devices in `dir`, a directory laid out like
+/// `/sys/class/power_supply`, in the order of their names.
+#[cfg(target_os = "linux")]
+pub(crate) fn scan(dir: &Path) -> io::Result<Vec<BackendDevice>>
+{
+    let mut paths = fs::read_dir(dir)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<io::Result<Vec<_>>>()?;
+    paths.retain(|path| path.is_dir());
+    paths.sort();
+    paths.into_iter().map(BackendDevice::read).collect()
+}
+
+/// The devices of the `power_supply` subsystem of udev, changing with the
+/// uevents of a monitor socket. Uevents of Thunderbolt devices are received
+/// too, to track docks.
+#[cfg(target_os = "linux")]
+#[derive(Default)]
+pub struct UdevBackend
+{
+    socket: Option<MonitorSocket>,
+    receive_buffer: Option<usize>,
+    tag_matches: Vec<String>,
+}
+
+#[cfg(target_os = "linux")]
+impl UdevBackend
+{
+    #[must_use]
+    pub fn new() -> Self
+    {
+        Self::default()
+    }
+
+    /// Uses a monitor socket made by the application instead of opening one,
+    /// see [`PowerSupply::with_monitor`](crate::PowerSupply::with_monitor).
+    #[must_use]
+    pub fn with_socket(socket: MonitorSocket) -> Self
+    {
+        Self {
+            socket: Some(socket),
+            ..Self::default()
+        }
+    }
+
+    /// Only enumerates and receives devices that udev rules tagged with
+    /// `tag`. The socket filters in the kernel, so this applies to docks as
+    /// well, but only to sockets opened afterwards.
+    pub fn match_tag(&mut self, tag: impl Into<String>)
+    {
+        self.tag_matches.push(tag.into());
+    }
+
+    /// Sets the receive buffer of the socket in bytes, right away if it is
+    /// open and for sockets opened later.
+    pub fn set_receive_buffer(&mut self, size: Option<usize>) -> io::Result<()>
+    {
+        self.receive_buffer = size;
+        match (&self.socket, size) {
+            (Some(socket), Some(size)) => set_receive_buffer(socket.as_raw_fd(), size),
+            _ => Ok(()),
+        }
+    }
+
+    fn socket(&mut self) -> io::Result<&MonitorSocket>
+    {
+        if self.socket.is_none() {
+            let mut builder = udev::MonitorBuilder::new()?
+                .match_subsystem("power_supply")?
+                .match_subsystem("thunderbolt")?;
+            for tag in &self.tag_matches {
+                builder = builder.match_tag(tag)?;
+            }
+            let socket = builder.listen()?;
+            if let Some(size) = self.receive_buffer {
+                set_receive_buffer(socket.as_raw_fd(), size)?;
+            }
+            self.socket = Some(socket);
+        }
+        Ok(unsafe { self.socket.as_ref().unwrap_unchecked() })
+    }
+
+    /// Replaces a socket that failed with a new one. The new socket is made
+    /// before the old one is closed so that it gets another file descriptor,
+    /// which event loops polling [`PowerBackend::fd`] use to notice.
+    fn reconnect(&mut self) -> io::Result<()>
+    {
+        let old = self.socket.take();
+        self.socket()?;
+        drop(old);
+        Ok(())
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl PowerBackend for UdevBackend
+{
+    fn enumerate(&mut self) -> io::Result<Vec<BackendDevice>>
+    {
+        let mut enumerator = udev::Enumerator::new()?;
+        enumerator.match_subsystem("power_supply")?;
+        for tag in &self.tag_matches {
+            enumerator.match_tag(tag)?;
+        }
+        Ok(enumerator
+            .scan_devices()?
+            .map(|dev| BackendDevice::from_udev(&dev))
+            .collect())
+    }
+
+    fn fd(&mut self) -> io::Result<RawFd>
+    {
+        Ok(self.socket()?.as_raw_fd())
+    }
+
+    fn drain_events(&mut self) -> io::Result<Uevents>
+    {
+        let mut uevents = Uevents::default();
+        let mut events = self.socket()?.iter();
+        loop {
+            // libudev only reports why it did not return a device through
+            // errno.
+            unsafe { *libc::__errno_location() = 0 };
+            match events.next() {
+                Some(event) if event.subsystem() == Some(OsStr::new("power_supply")) => {
+                    uevents
+                        .devices
+                        .push(BackendDevice::from_udev(&event.device()));
+                }
+                Some(event) if event.subsystem() == Some(OsStr::new("thunderbolt")) => {
+                    let attached = match event.event_type() {
+                        udev::EventType::Add => true,
+                        udev::EventType::Remove => false,
+                        _ => continue,
+                    };
+                    if let Some(dock) = Dock::of(&event.device()) {
+                        uevents.docks.push((dock, attached));
+                    }
+                }
+                Some(_) => {}
+                None => {
+                    let err = io::Error::last_os_error();
+                    match err.raw_os_error() {
+                        // The socket keeps working after an overrun, so drain
+                        // the rest of the queue.
+                        Some(libc::ENOBUFS) => uevents.overrun = true,
+                        // A signal arrived before the queue was drained.
+                        Some(libc::EINTR) => {}
+                        // The queue is drained, or it was empty to begin with
+                        // after a spurious wakeup.
+                        Some(0 | libc::EAGAIN) => break,
+                        _ => {
+                            drop(events);
+                            self.reconnect()?;
+                            uevents.reconnected = true;
+                            return Ok(uevents);
+                        }
+                    }
+                }
+            }
+        }
+        Ok(uevents)
+    }
+}
+
+/// Sets the receive buffer of the socket `fd` to `size` bytes, past
+/// `net.core.rmem_max` if the process may.
+#[cfg(target_os = "linux")]
+fn set_receive_buffer(fd: RawFd, size: usize) -> io::Result<()>
+{
+    let size = libc::c_int::try_from(size).unwrap_or(libc::c_int::MAX);
+    let set = |option| unsafe {
+        libc::setsockopt(
+            fd,
+            libc::SOL_SOCKET,
+            option,
+            (&size as *const libc::c_int).cast(),
+            mem::size_of::<libc::c_int>() as libc::socklen_t,
+        )
+    };
+    if set(libc::SO_RCVBUFFORCE) == 0 || set(libc::SO_RCVBUF) == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
+
+/// The devices in `/sys/class/power_supply`, rescanned at an interval
+/// instead of listening to uevents, for systems without netlink access.
+/// Changes of the attributes are only noticed at the next scan.
+#[cfg(target_os = "linux")]
+pub struct SysfsPoller
+{
+    dir: PathBuf,
+    interval: Duration,
+    /// A timerfd firing every interval.
+    timer: OwnedFd,
+    known: BTreeSet<PathBuf>,
+}
+
+#[cfg(target_os = "linux")]
+impl SysfsPoller
+{
+    /// Rescans sysfs every `interval`.
+    pub fn new(interval: Duration) -> io::Result<Self>
+    {
+        Self::with_dir(POWER_SUPPLY_CLASS, interval)
+    }
+
+    /// Rescans `dir`, laid out like `/sys/class/power_supply`, instead.
+    pub fn with_dir(dir: impl Into<PathBuf>, interval: Duration) -> io::Result<Self>
+    {
+        let fd = unsafe {
+            libc::timerfd_create(
+                libc::CLOCK_MONOTONIC,
+                libc::TFD_NONBLOCK | libc::TFD_CLOEXEC,
+            )
+        };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let timer = unsafe { OwnedFd::from_raw_fd(fd) };
+        let interval = interval.max(Duration::from_millis(1));
+        let spec = libc::timespec {
+            tv_sec: interval.as_secs() as libc::time_t,
+            tv_nsec: libc::c_long::from(interval.subsec_nanos() as i32),
+        };
+        let value = libc::itimerspec {
+            it_interval: spec,
+            it_value: spec,
+        };
+        if unsafe { libc::timerfd_settime(fd, 0, &value, std::ptr::null_mut()) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Self {
+            dir: dir.into(),
+            interval,
+            timer,
+            known: BTreeSet::new(),
+        })
+    }
+
+    #[must_use]
+    pub fn interval(&self) -> Duration
+    {
+        self.interval
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl PowerBackend for SysfsPoller
+{
+    fn enumerate(&mut self) -> io::Result<Vec<BackendDevice>>
+    {
+        let devices = scan(&self.dir)?;
+        self.known = devices.iter().map(|dev| dev.syspath.clone()).collect();
+        Ok(devices)
+    }
+
+    fn fd(&mut self) -> io::Result<RawFd>
+    {
+        Ok(self.timer.as_raw_fd())
+    }
+
+    /// Returns the devices that appeared or disappeared since the last scan.
+    /// The attributes of the others are read again anyway whenever the power
+    /// supply is updated.
+    fn drain_events(&mut self) -> io::Result<Uevents>
+    {
+        let mut expirations = 0u64;
+        let read = unsafe {
+            libc::read(
+                self.timer.as_raw_fd(),
+                (&mut expirations as *mut u64).cast(),
+                mem::size_of::<u64>(),
+            )
+        };
+        if read < 0 {
+            let err = io::Error::last_os_error();
+            if err.kind() == io::ErrorKind::WouldBlock {
+                return Ok(Uevents::default());
+            }
+            return Err(err);
+        }
+        let known = mem::take(&mut self.known);
+        let devices = self.enumerate()?;
+        let mut uevents = Uevents::default();
+        uevents.devices.extend(
+            known
+                .difference(&self.known)
+                .map(|syspath| BackendDevice::removed(syspath.clone())),
+        );
+        uevents.devices.extend(
+            devices
+                .into_iter()
+                .filter(|dev| !known.contains(&dev.syspath)),
+        );
+        Ok(uevents)
+    }
+}
@@ -0,0 +1,195 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use crate::privileges;
+
+const BACKLIGHT: &str = "/sys/class/backlight";
+const LEDS: &str = "/sys/class/leds";
+/// Number of writes a fade is made of.
+const FADE_STEPS: u32 = 20;
+
+/// A brightness to change the backlight to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Brightness
+{
+    /// An absolute brightness in percent.
+    Percent(u8),
+    /// A change of the current brightness by percentage points.
+    Step(i8),
+}
+
+/// Changes the brightness of all backlights, fading over `fade`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BrightnessChange
+{
+    pub brightness: Brightness,
+    pub fade: Duration,
+}
+
+/// Applies [`BrightnessChange`]s to the backlights under
+/// `/sys/class/backlight`. Fades run on a thread of their own; starting a new
+/// one stops the fade that is still running.
+#[derive(Debug, Default)]
+pub struct BacklightFader
+{
+    generation: Arc<AtomicU64>,
+}
+
+impl BacklightFader
+{
+    #[must_use]
+    pub fn new() -> Self
+    {
+        Self::default()
+    }
+
+    pub fn apply(&self, change: BrightnessChange) -> io::Result<()>
+    {
+        let generation = self.generation.fetch_add(1, Ordering::SeqCst) + 1;
+        let mut fades = Vec::new();
+        for dir in read_dir(BACKLIGHT)? {
+            let max = read_u64(&dir.join("max_brightness"))?;
+            let current = read_u64(&dir.join("brightness"))?;
+            let percent = match change.brightness {
+                Brightness::Percent(percent) => u64::from(percent.min(100)),
+                Brightness::Step(step) => {
+                    let percent = (current * 100).checked_div(max).unwrap_or(0) as i64;
+                    (percent + i64::from(step)).clamp(0, 100) as u64
+                }
+            };
+            fades.push((dir.join("brightness"), current, percent * max / 100));
+        }
+        if change.fade.is_zero() {
+            for (path, _, target) in fades {
+                write(&path, target)?;
+            }
+            return Ok(());
+        }
+
+        let current = self.generation.clone();
+        thread::spawn(move || {
+            let interval = change.fade / FADE_STEPS;
+            for step in 1..=u64::from(FADE_STEPS) {
+                thread::sleep(interval);
+                if current.load(Ordering::SeqCst) != generation {
+                    return;
+                }
+                for (path, from, to) in &fades {
+                    let value = *from as i64
+                        + (*to as i64 - *from as i64) * step as i64 / i64::from(FADE_STEPS);
+                    // A backlight that rejects a step ends the fade.
+                    if write(path, value as u64).is_err() {
+                        return;
+                    }
+                }
+            }
+        });
+        Ok(())
+    }
+}
+
+/// A level to set the keyboard backlight to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyboardBrightness
+{
+    /// A raw level between 0, which turns the backlight off, and the
+    /// device's `max_brightness`.
+    Level(u64),
+    /// The level from before the first [`KeyboardBrightness::Level`] was set.
+    Restore,
+}
+
+/// The keyboard backlights under `/sys/class/leds`, named `*::kbd_backlight`.
+/// The level they had before they were first changed is kept, so that it can
+/// be restored.
+#[derive(Debug)]
+pub struct KeyboardBacklight
+{
+    dirs: Vec<PathBuf>,
+    saved: Option<Vec<u64>>,
+}
+
+impl KeyboardBacklight
+{
+    /// Finds the keyboard backlights. There may be none.
+    pub fn new() -> io::Result<Self>
+    {
+        let dirs = read_dir(LEDS)?
+            .into_iter()
+            .filter(|dir| {
+                dir.file_name()
+                    .is_some_and(|name| name.to_string_lossy().ends_with("kbd_backlight"))
+            })
+            .collect();
+        Ok(Self { dirs, saved: None })
+    }
+
+    /// Returns the current level and the maximum level of each keyboard
+    /// backlight.
+    pub fn levels(&self) -> io::Result<Vec<(u64, u64)>>
+    {
+        self.dirs
+            .iter()
+            .map(|dir| {
+                Ok((
+                    read_u64(&dir.join("brightness"))?,
+                    read_u64(&dir.join("max_brightness"))?,
+                ))
+            })
+            .collect()
+    }
+
+    pub fn set(&mut self, brightness: KeyboardBrightness) -> io::Result<()>
+    {
+        let levels = match brightness {
+            KeyboardBrightness::Level(level) => {
+                let levels = self.levels()?;
+                if self.saved.is_none() {
+                    self.saved = Some(levels.iter().map(|(current, _)| *current).collect());
+                }
+                levels.iter().map(|(_, max)| level.min(*max)).collect()
+            }
+            KeyboardBrightness::Restore => match self.saved.take() {
+                Some(saved) => saved,
+                None => return Ok(()),
+            },
+        };
+        for (dir, level) in self.dirs.iter().zip(levels) {
+            write(&dir.join("brightness"), level)?;
+        }
+        Ok(())
+    }
+}
+
+fn read_dir(path: &str) -> io::Result<Vec<PathBuf>>
+{
+    match fs::read_dir(path) {
+        Ok(entries) => entries
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect(),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(err) => Err(err),
+    }
+}
+
+fn read_u64(path: &Path) -> io::Result<u64>
+{
+    fs::read_to_string(path)?.trim().parse().map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{}: not a number", path.display()),
+        )
+    })
+}
+
+fn write(path: &Path, value: u64) -> io::Result<()>
+{
+    privileges::write(path, &value.to_string())
+}
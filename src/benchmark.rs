@@ -0,0 +1,291 @@
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::Write;
+use std::path::PathBuf;
+use std::time::Duration;
+use std::time::Instant;
+
+use lithiumd::PowerSupply;
+use lithiumd::Snapshot;
+use lithiumd::Status;
+use mio::event::Source;
+use mio::Events;
+use mio::Interest;
+use mio::Poll;
+use mio::Token;
+
+use crate::signals::Signal;
+use crate::signals::Signals;
+
+const SAMPLE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Options of `lithiumd benchmark`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Options
+{
+    pub duration: Duration,
+    /// Brightness of all backlights in percent.
+    pub brightness: u8,
+    /// Cpufreq governor of all CPUs.
+    pub governor: String,
+    /// File to write the samples to, as CSV.
+    pub output: Option<PathBuf>,
+}
+
+impl Default for Options
+{
+    fn default() -> Self
+    {
+        Self {
+            duration: Duration::from_secs(30 * 60),
+            brightness: 50,
+            governor: "powersave".to_string(),
+            output: None,
+        }
+    }
+}
+
+impl Options
+{
+    pub fn parse(args: &[String]) -> Result<Self, String>
+    {
+        let mut options = Self::default();
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            let mut value = || {
+                args.next()
+                    .ok_or_else(|| format!("`{}` needs a value", arg))
+            };
+            match arg.as_str() {
+                "--duration" => options.duration = parse_duration(value()?)?,
+                "--brightness" => {
+                    options.brightness = value()?
+                        .parse()
+                        .ok()
+                        .filter(|percent| *percent <= 100)
+                        .ok_or_else(|| "`--brightness` must be between 0 and 100".to_string())?;
+                }
+                "--governor" => options.governor = value()?.clone(),
+                "--output" => options.output = Some(PathBuf::from(value()?)),
+                _ => return Err(format!("unknown option `{}`", arg)),
+            }
+        }
+        Ok(options)
+    }
+}
+
+/// Parses durations such as `90s`, `30m` or `1h30m`.
+pub fn parse_duration(s: &str) -> Result<Duration, String>
+{
+    let invalid = || format!("invalid duration `{}`, expected e.g. `30m` or `1h30m`", s);
+    let mut total = 0;
+    let mut number = String::new();
+    for c in s.chars() {
+        if c.is_ascii_digit() {
+            number.push(c);
+            continue;
+        }
+        let unit = match c {
+            's' => 1,
+            'm' => 60,
+            'h' => 60 * 60,
+            _ => return Err(invalid()),
+        };
+        total += number.parse::<u64>().map_err(|_| invalid())? * unit;
+        number.clear();
+    }
+    if !number.is_empty() || total == 0 {
+        return Err(invalid());
+    }
+    Ok(Duration::from_secs(total))
+}
+
+/// Values of sysfs attributes that were overwritten, restored on drop.
+struct Locked
+{
+    saved: Vec<(PathBuf, String)>,
+}
+
+impl Locked
+{
+    fn new() -> Self
+    {
+        Self { saved: Vec::new() }
+    }
+
+    fn set(&mut self, path: PathBuf, value: &str) -> io::Result<()>
+    {
+        let old = fs::read_to_string(&path)?;
+        fs::write(&path, value)
+            .map_err(|err| io::Error::new(err.kind(), format!("{}: {}", path.display(), err)))?;
+        self.saved.push((path, old.trim().to_string()));
+        Ok(())
+    }
+
+    fn lock_brightness(&mut self, percent: u8) -> io::Result<()>
+    {
+        for entry in read_dir("/sys/class/backlight")? {
+            let max = fs::read_to_string(entry.join("max_brightness"))?;
+            let max = max.trim().parse::<u64>().unwrap_or(0);
+            let value = max * u64::from(percent) / 100;
+            self.set(entry.join("brightness"), &value.to_string())?;
+        }
+        Ok(())
+    }
+
+    fn lock_governor(&mut self, governor: &str) -> io::Result<()>
+    {
+        for entry in read_dir("/sys/devices/system/cpu")? {
+            let path = entry.join("cpufreq/scaling_governor");
+            if path.exists() {
+                self.set(path, governor)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Locked
+{
+    fn drop(&mut self)
+    {
+        for (path, value) in self.saved.drain(..).rev() {
+            if let Err(err) = fs::write(&path, value) {
+                eprintln!("Failed to restore {}: {}", path.display(), err);
+            }
+        }
+    }
+}
+
+fn read_dir(path: &str) -> io::Result<Vec<PathBuf>>
+{
+    match fs::read_dir(path) {
+        Ok(entries) => entries
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect(),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(err) => Err(err),
+    }
+}
+
+/// Sums up `f` over the system batteries, if all of them report it.
+fn total(snapshot: &Snapshot, f: impl Fn(&lithiumd::DeviceSnapshot) -> Option<u64>) -> Option<u64>
+{
+    let mut batteries = snapshot.batteries().peekable();
+    batteries.peek()?;
+    batteries.map(f).sum()
+}
+
+/// Runs the benchmark: locks brightness and governor, samples the discharge
+/// every second and reports the runtime projected for a full charge. The
+/// benchmark can be cut short with `SIGINT` and still reports its results.
+pub fn run(options: &Options) -> io::Result<()>
+{
+    let mut signals = Signals::new()?;
+    let mut poll = Poll::new()?;
+    signals.register(poll.registry(), Token(0), Interest::READABLE)?;
+
+    let mut power_supply = PowerSupply::new();
+    power_supply.rescan()?;
+    if power_supply.charging_status() != Status::Discharging {
+        return Err(io::Error::other(
+            "the benchmark has to run on battery, unplug the charger",
+        ));
+    }
+
+    let mut output = options.output.as_ref().map(File::create).transpose()?;
+    if let Some(output) = &mut output {
+        writeln!(output, "seconds,capacity,energy_now,power_now")?;
+    }
+
+    let mut locked = Locked::new();
+    locked.lock_brightness(options.brightness)?;
+    locked.lock_governor(&options.governor)?;
+    println!(
+        "Benchmarking for {}s at {}% brightness with the {} governor, press Ctrl-C to stop early",
+        options.duration.as_secs(),
+        options.brightness,
+        options.governor
+    );
+
+    let start = Instant::now();
+    let first = power_supply.snapshot();
+    let mut last = first.clone();
+    let mut power_samples = Vec::new();
+    let mut events = Events::with_capacity(8);
+    while start.elapsed() < options.duration {
+        poll.poll(&mut events, Some(SAMPLE_INTERVAL))?;
+        if !events.is_empty() && signals.read()?.contains(&Signal::Terminate) {
+            break;
+        }
+        power_supply.refresh()?;
+        last = power_supply.snapshot();
+        if last.status != Status::Discharging {
+            eprintln!("The charger was plugged in, stopping early");
+            break;
+        }
+        let power = total(&last, |bat| bat.power_now);
+        power_samples.extend(power);
+        if let Some(output) = &mut output {
+            let field = |value: Option<u64>| value.map(|v| v.to_string()).unwrap_or_default();
+            writeln!(
+                output,
+                "{},{},{},{}",
+                start.elapsed().as_secs_f64(),
+                field(last.capacity().map(u64::from)),
+                field(total(&last, |bat| bat.energy_now)),
+                field(power),
+            )?;
+        }
+    }
+    drop(locked);
+    report(start.elapsed(), &first, &last, &power_samples);
+    Ok(())
+}
+
+fn report(elapsed: Duration, first: &Snapshot, last: &Snapshot, power_samples: &[u64])
+{
+    println!(
+        "Duration: {}m {}s",
+        elapsed.as_secs() / 60,
+        elapsed.as_secs() % 60
+    );
+    // Batteries report either energy or charge. Working with fractions of a
+    // full charge gives the same results for both.
+    let Some(full) = total(first, |bat| bat.energy_full).filter(|full| *full > 0) else {
+        println!("The batteries do not report their full energy, no score can be computed");
+        return;
+    };
+    let full = full as f64;
+    let hours = elapsed.as_secs_f64() / 3600.0;
+    let used = match (
+        total(first, |bat| bat.energy_now),
+        total(last, |bat| bat.energy_now),
+    ) {
+        (Some(first), Some(last)) if first > last => Some((first - last) as f64 / full),
+        _ => None,
+    };
+    // The drop in energy is more accurate than the instantaneous power
+    // readings, which are only averaged if the energy did not change.
+    let draw = match used {
+        Some(used) if hours > 0.0 => Some(used / hours),
+        _ if !power_samples.is_empty() => {
+            let mean = power_samples.iter().sum::<u64>() as f64 / power_samples.len() as f64;
+            Some(mean / full)
+        }
+        _ => None,
+    };
+    if let Some(used) = used {
+        println!("Used: {:.1}% of a full charge", used * 100.0);
+    }
+    match draw.filter(|draw| *draw > 0.0) {
+        Some(draw) => {
+            let minutes = (60.0 / draw).round() as u64;
+            println!("Average draw: {:.1}% per hour", draw * 100.0);
+            println!("Projected runtime: {}h {}m", minutes / 60, minutes % 60);
+            println!("Score: {}", minutes);
+        }
+        None => println!("Not enough data to compute a score"),
+    }
+}
@@ -0,0 +1,153 @@
+use std::io;
+
+#[cfg(feature = "mio")]
+use mio::event::Source;
+
+use crate::dbus::Connection;
+use crate::dbus::Error;
+use crate::dbus::Message;
+use crate::dbus::MessageType;
+use crate::dbus::Value;
+use crate::power_supply::DeviceType;
+use crate::power_supply::Scope;
+use crate::power_supply::Status;
+use crate::snapshot::DeviceSnapshot;
+
+const BLUEZ: &str = "org.bluez";
+
+/// Battery levels of Bluetooth devices, read from the `org.bluez.Battery1`
+/// interface of BlueZ. Headsets and earbuds usually report their battery
+/// only through BlueZ and never show up under `power_supply`.
+///
+/// Register it with a mio `Poll` and call [`Bluez::process`] when it becomes
+/// readable.
+pub struct Bluez
+{
+    conn: Connection,
+}
+
+impl Bluez
+{
+    /// Connects to the system bus and subscribes to changes of BlueZ
+    /// devices. BlueZ itself does not have to be running yet.
+    pub fn new() -> io::Result<Self>
+    {
+        let mut conn = Connection::system()?;
+        for member in ["InterfacesAdded", "InterfacesRemoved"] {
+            conn.add_match(&format!(
+                "type='signal',sender='{}',interface='org.freedesktop.DBus.ObjectManager',member='{}'",
+                BLUEZ, member
+            ))?;
+        }
+        conn.add_match(&format!(
+            "type='signal',sender='{}',interface='org.freedesktop.DBus.Properties',member='PropertiesChanged',arg0='org.bluez.Battery1'",
+            BLUEZ
+        ))?;
+        conn.add_match(&format!(
+            "type='signal',sender='org.freedesktop.DBus',member='NameOwnerChanged',arg0='{}'",
+            BLUEZ
+        ))?;
+        Ok(Self { conn })
+    }
+
+    /// Reads the pending signals and returns whether any of them changed the
+    /// battery of a device, in which case [`Bluez::devices`] has to be called
+    /// again.
+    pub fn process(&mut self) -> io::Result<bool>
+    {
+        let mut changed = false;
+        while let Some(message) = self.conn.read()? {
+            changed |= message.message_type == MessageType::Signal;
+        }
+        Ok(changed)
+    }
+
+    /// Returns the Bluetooth devices that report their battery level. They
+    /// have [`Scope::Device`] and are named after their BlueZ object, e.g.
+    /// `dev_00_1B_66_AA_BB_CC`. No devices are returned while BlueZ is not
+    /// running.
+    pub fn devices(&mut self) -> io::Result<Vec<DeviceSnapshot>>
+    {
+        let reply = self.conn.call(Message::method_call(
+            BLUEZ,
+            "/",
+            "org.freedesktop.DBus.ObjectManager",
+            "GetManagedObjects",
+            Vec::new(),
+        ));
+        let reply = match reply {
+            Ok(reply) => reply,
+            Err(err) if Error::is_service_unknown(&err) => return Ok(Vec::new()),
+            Err(err) => return Err(err),
+        };
+
+        let mut devices = Vec::new();
+        let Some(objects) = reply.body.first() else {
+            return Ok(devices);
+        };
+        for (path, interfaces) in objects.entries() {
+            let Some(battery) = interfaces.get("org.bluez.Battery1") else {
+                continue;
+            };
+            let Some(path) = path.as_str() else {
+                continue;
+            };
+            let device = interfaces.get("org.bluez.Device1");
+            let model = device
+                .and_then(|device| device.get("Alias").or_else(|| device.get("Name")))
+                .and_then(Value::as_str)
+                .map(str::to_string);
+            devices.push(DeviceSnapshot {
+                sysname: path.rsplit('/').next().unwrap_or(path).to_string(),
+                model,
+                device_type: DeviceType::Battery,
+                scope: Scope::Device,
+                status: Status::Unknown,
+                capacity: battery
+                    .get("Percentage")
+                    .and_then(Value::as_i64)
+                    .map(|percent| percent.clamp(0, 100) as u8),
+                energy_full: None,
+                energy_now: None,
+                power_now: None,
+                reports_energy: false,
+                voltage_now: None,
+                current_now: None,
+                alarm: None,
+                charge_limit: None,
+                input_power: None,
+                peak_input_power: None,
+            });
+        }
+        Ok(devices)
+    }
+}
+
+#[cfg(feature = "mio")]
+impl Source for Bluez
+{
+    fn register(
+        &mut self,
+        registry: &mio::Registry,
+        token: mio::Token,
+        interests: mio::Interest,
+    ) -> io::Result<()>
+    {
+        self.conn.register(registry, token, interests)
+    }
+
+    fn reregister(
+        &mut self,
+        registry: &mio::Registry,
+        token: mio::Token,
+        interests: mio::Interest,
+    ) -> io::Result<()>
+    {
+        self.conn.reregister(registry, token, interests)
+    }
+
+    fn deregister(&mut self, registry: &mio::Registry) -> io::Result<()>
+    {
+        self.conn.deregister(registry)
+    }
+}
@@ -0,0 +1,61 @@
+use std::io;
+
+use lithiumd::dbus::Connection;
+use lithiumd::dbus::Message;
+use lithiumd::dbus::Value;
+use lithiumd::Change;
+use lithiumd::PowerEvent;
+
+use crate::json;
+
+const PATH: &str = "/org/lithiumd";
+const INTERFACE: &str = "org.lithiumd.Broadcast";
+
+/// Broadcasts plain signals on the session bus, for screen lockers and bars
+/// that match on them with `dbus-monitor` or a match rule instead of
+/// subscribing to the service:
+///
+/// - `StatusChanged(s status)`, e.g. `"discharging"`,
+/// - `CapacityChanged(y capacity)` in percent,
+/// - `LidClosed()` and `LidOpened()`.
+pub struct Broadcaster
+{
+    conn: Connection,
+}
+
+impl Broadcaster
+{
+    pub fn new() -> io::Result<Self>
+    {
+        Ok(Self {
+            conn: Connection::session()?,
+        })
+    }
+
+    /// Broadcasts `event` if it has a signal of its own.
+    pub fn emit(&mut self, event: &PowerEvent) -> io::Result<()>
+    {
+        match event {
+            PowerEvent::Changed(Change::Status { new, .. }) => self.send(
+                "StatusChanged",
+                vec![Value::String(json::status_name(*new).to_string())],
+            ),
+            PowerEvent::CapacityChanged { new, .. } => {
+                self.send("CapacityChanged", vec![Value::Byte(*new)])
+            }
+            _ => Ok(()),
+        }
+    }
+
+    pub fn lid(&mut self, closed: bool) -> io::Result<()>
+    {
+        self.send(if closed { "LidClosed" } else { "LidOpened" }, Vec::new())
+    }
+
+    fn send(&mut self, member: &str, body: Vec<Value>) -> io::Result<()>
+    {
+        self.conn
+            .send(Message::signal(PATH, INTERFACE, member, body))
+            .map(|_| ())
+    }
+}
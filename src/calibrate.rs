@@ -0,0 +1,268 @@
+use std::collections::BTreeMap;
+use std::io;
+use std::time::Duration;
+use std::time::Instant;
+
+use lithiumd::Health;
+use lithiumd::History;
+use lithiumd::InhibitMode;
+use lithiumd::Logind;
+use lithiumd::PowerSupply;
+use lithiumd::Snapshot;
+use lithiumd::Status;
+use mio::event::Source;
+use mio::Events;
+use mio::Interest;
+use mio::Poll;
+use mio::Token;
+
+use crate::limits::TopUp;
+use crate::signals::Signal;
+use crate::signals::Signals;
+
+/// How often the batteries are read, as they do not send uevents for every
+/// change of their energy.
+const SAMPLE_INTERVAL: Duration = Duration::from_secs(10);
+
+/// How long the charge limits are lifted for at most.
+const TOP_UP_DURATION: Duration = Duration::from_secs(48 * 60 * 60);
+
+/// Options of `lithiumd calibrate`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Options
+{
+    /// Capacity in percent at which the battery counts as empty. It has to
+    /// stay above whatever suspends or hibernates the machine on low battery.
+    pub empty: u8,
+}
+
+impl Options
+{
+    pub fn parse(args: &[String]) -> Result<Self, String>
+    {
+        let mut options = Self { empty: 3 };
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--empty" => {
+                    let value = args
+                        .next()
+                        .ok_or_else(|| format!("`{}` needs a value", arg))?;
+                    options.empty = value
+                        .trim_end_matches('%')
+                        .parse()
+                        .ok()
+                        .filter(|percent| *percent < 50)
+                        .ok_or_else(|| "`--empty` must be a percentage below 50".to_string())?;
+                }
+                _ => return Err(format!("unknown option `{}`", arg)),
+            }
+        }
+        Ok(options)
+    }
+}
+
+/// The steps of a calibration run.
+#[derive(Debug, Clone, PartialEq)]
+enum Phase
+{
+    /// Charging to full with the charge limits lifted.
+    Charge,
+    /// Full, waiting for the charger to be unplugged.
+    Unplug,
+    /// Discharging, with the energy or charge delivered by each battery so
+    /// far, integrated from its power or current readings.
+    Discharge
+    {
+        delivered: BTreeMap<String, f64>,
+        at: Instant,
+    },
+    /// Empty, charging to full again with the measured full charges.
+    Recharge(BTreeMap<String, u64>),
+}
+
+/// Guides through a full charge, a full discharge and another full charge,
+/// after which the fuel gauges of most batteries report their full charge
+/// anew. Sleep is inhibited during the run, the full charge measured while
+/// discharging is recorded in `history`, and the wear before and after is
+/// compared at the end. `SIGINT` stops the run.
+pub fn run(options: Options, mut history: Option<History>) -> io::Result<()>
+{
+    let mut signals = Signals::new()?;
+    let mut poll = Poll::new()?;
+    signals.register(poll.registry(), Token(0), Interest::READABLE)?;
+
+    let mut power_supply = PowerSupply::new();
+    power_supply.rescan()?;
+    if power_supply.snapshot().batteries().next().is_none() {
+        return Err(io::Error::new(io::ErrorKind::NotFound, "no system battery"));
+    }
+    let before = power_supply.health(None);
+
+    // The lock is released when the descriptor is dropped at the end.
+    let _inhibitor = Logind::new()
+        .and_then(|mut logind| {
+            logind.inhibit("sleep:idle", "Calibrating the battery", InhibitMode::Block)
+        })
+        .map_err(|err| eprintln!("Failed to inhibit sleep, keep the machine awake: {}", err))
+        .ok();
+    let top_up = match TopUp::start(100, TOP_UP_DURATION) {
+        Ok(top_up) => Some(top_up),
+        Err(err) if err.kind() == io::ErrorKind::Unsupported => None,
+        Err(err) => return Err(err),
+    };
+    let result = calibrate(&mut poll, &mut signals, &mut power_supply, options);
+    if let Some(top_up) = top_up {
+        if let Err(err) = top_up.restore() {
+            eprintln!("Failed to restore the charge limits: {}", err);
+        }
+    }
+    let Some(measured) = result? else {
+        println!("Calibration stopped");
+        return Ok(());
+    };
+
+    if let Some(history) = &mut history {
+        for (sysname, measured) in &measured {
+            history.record_calibration(sysname, *measured);
+        }
+        if let Err(err) = history.save() {
+            eprintln!("Failed to write {}: {}", history.path().display(), err);
+        }
+    }
+    println!("Calibration done");
+    report(&before, &power_supply.health(None), &measured);
+    Ok(())
+}
+
+/// Runs the phases until the battery is charged again, and returns the full
+/// charges measured. `None` if stopped early.
+fn calibrate(
+    poll: &mut Poll,
+    signals: &mut Signals,
+    power_supply: &mut PowerSupply,
+    options: Options,
+) -> io::Result<Option<BTreeMap<String, u64>>>
+{
+    let mut phase = Phase::Charge;
+    println!("Charging to full, keep the charger plugged in");
+    let mut reported = None;
+    let mut events = Events::with_capacity(8);
+    loop {
+        let snapshot = power_supply.snapshot();
+        let capacity = snapshot.capacity();
+        phase = match phase {
+            Phase::Charge if is_full(&snapshot) => {
+                println!("Fully charged, unplug the charger");
+                Phase::Unplug
+            }
+            Phase::Unplug if snapshot.status == Status::Discharging => {
+                println!(
+                    "Discharging down to {}%, keep the machine running and plugged out",
+                    options.empty
+                );
+                Phase::Discharge {
+                    delivered: BTreeMap::new(),
+                    at: Instant::now(),
+                }
+            }
+            Phase::Discharge { .. } if snapshot.status != Status::Discharging => {
+                eprintln!("The charger was plugged in before the battery was empty, starting over");
+                Phase::Charge
+            }
+            Phase::Discharge { mut delivered, at } => {
+                let now = Instant::now();
+                let hours = now.duration_since(at).as_secs_f64() / 3600.0;
+                for bat in snapshot.batteries() {
+                    if let Some(power) = bat.power_now {
+                        *delivered.entry(bat.sysname.clone()).or_default() += power as f64 * hours;
+                    }
+                }
+                match capacity {
+                    Some(capacity) if capacity <= options.empty => {
+                        println!("Empty, plug in the charger to charge fully again");
+                        Phase::Recharge(measured(&delivered, &snapshot))
+                    }
+                    _ => Phase::Discharge { delivered, at: now },
+                }
+            }
+            Phase::Recharge(measured) if is_full(&snapshot) => return Ok(Some(measured)),
+            phase => phase,
+        };
+        if matches!(phase, Phase::Discharge { .. } | Phase::Recharge(_)) && reported != capacity {
+            if let Some(capacity) = capacity {
+                println!("{}% ({})", capacity, snapshot.status);
+            }
+            reported = capacity;
+        }
+
+        poll.poll(&mut events, Some(SAMPLE_INTERVAL))?;
+        if !events.is_empty() && signals.read()?.contains(&Signal::Terminate) {
+            return Ok(None);
+        }
+        power_supply.refresh()?;
+    }
+}
+
+fn is_full(snapshot: &Snapshot) -> bool
+{
+    snapshot.status == Status::Full
+        || (snapshot.status == Status::NotCharging && snapshot.capacity() == Some(100))
+}
+
+/// Returns the full charge of each battery from what it `delivered` from
+/// full down to its capacity in `snapshot`. What is left below is
+/// extrapolated, as it cannot be measured without running flat.
+fn measured(delivered: &BTreeMap<String, f64>, snapshot: &Snapshot) -> BTreeMap<String, u64>
+{
+    snapshot
+        .batteries()
+        .filter_map(|bat| {
+            let delivered = delivered.get(&bat.sysname)?;
+            let used = 100 - bat.capacity?.min(99);
+            Some((
+                bat.sysname.clone(),
+                (delivered * 100.0 / f64::from(used)) as u64,
+            ))
+        })
+        .collect()
+}
+
+fn report(before: &[Health], after: &[Health], measured: &BTreeMap<String, u64>)
+{
+    for after in after {
+        let before = before.iter().find(|before| before.sysname == after.sysname);
+        println!("{}:", after.sysname);
+        let amount = |amount: Option<u64>| match amount {
+            Some(amount) => format!("{:.2}", amount as f64 / 1_000_000.0),
+            None => "unknown".to_string(),
+        };
+        let percent = |health: Option<u8>| match health {
+            Some(health) => format!(" ({}%)", health),
+            None => String::new(),
+        };
+        println!("  design:        {}", amount(after.energy_full_design));
+        if let Some(before) = before {
+            println!(
+                "  full before:   {}{}",
+                amount(before.energy_full),
+                percent(before.health_percent())
+            );
+        }
+        println!(
+            "  full now:      {}{}",
+            amount(after.energy_full),
+            percent(after.health_percent())
+        );
+        let measured = measured.get(&after.sysname).copied();
+        let measured_health = measured
+            .zip(after.energy_full_design.filter(|design| *design > 0))
+            .map(|(measured, design)| (u128::from(measured) * 100 / u128::from(design)) as u8);
+        println!(
+            "  measured full: {}{}",
+            amount(measured),
+            percent(measured_health)
+        );
+    }
+    println!("Energies are in Wh, or charges in Ah for batteries that do not report energy");
+}
@@ -0,0 +1,128 @@
+use std::io;
+use std::time::Duration;
+use std::time::Instant;
+
+use lithiumd::Notification;
+use lithiumd::NotificationEvent;
+use lithiumd::Notifications;
+use lithiumd::Snapshot;
+use lithiumd::Status;
+use lithiumd::Urgency;
+
+/// Shows a desktop notification once the batteries are charged while
+/// plugged in, to full or to their charge limit, telling that the charger
+/// can be unplugged. With `repeat`, it is shown again that often while the
+/// charger stays plugged in, and it is closed once it is unplugged.
+pub struct ChargedNotifier
+{
+    repeat: Option<Duration>,
+    notifications: Notifications,
+    /// The notification that was shown, while it is open.
+    shown: Option<u32>,
+    /// When the target was reached, or `None` while it is not.
+    reached: Option<Instant>,
+    /// When the notification is shown again.
+    due: Option<Instant>,
+}
+
+impl ChargedNotifier
+{
+    pub fn new(repeat: Option<Duration>) -> io::Result<Self>
+    {
+        Ok(Self {
+            repeat,
+            notifications: Notifications::new()?,
+            shown: None,
+            reached: None,
+            due: None,
+        })
+    }
+
+    pub fn repeat(&self) -> Option<Duration>
+    {
+        self.repeat
+    }
+
+    pub fn notifications(&mut self) -> &mut Notifications
+    {
+        &mut self.notifications
+    }
+
+    /// Shows, repeats or closes the notification for `snapshot`.
+    pub fn update(&mut self, snapshot: &Snapshot, now: Instant) -> io::Result<()>
+    {
+        let Some(body) = body(snapshot) else {
+            self.reached = None;
+            self.due = None;
+            if let Some(id) = self.shown.take() {
+                self.notifications.close(id)?;
+            }
+            return Ok(());
+        };
+        let due = match (self.reached, self.due) {
+            (None, _) => true,
+            (Some(_), Some(due)) => now >= due,
+            (Some(_), None) => false,
+        };
+        if !due {
+            return Ok(());
+        }
+        let reached = *self.reached.get_or_insert(now);
+        let minutes = now.duration_since(reached).as_secs() / 60;
+        let mut body = body;
+        if minutes > 0 {
+            body += &format!(" Charged for {}h {}m.", minutes / 60, minutes % 60);
+        }
+        let notification = Notification {
+            summary: "Battery charged".to_string(),
+            body,
+            icon: "battery-level-100-charged-symbolic".to_string(),
+            urgency: Urgency::Normal,
+            progress: snapshot.capacity(),
+            actions: Vec::new(),
+        };
+        self.shown = Some(
+            self.notifications
+                .notify(self.shown.unwrap_or(0), &notification)?,
+        );
+        self.due = self.repeat.map(|repeat| now + repeat);
+        Ok(())
+    }
+
+    /// Reads the signals of the notification server, to learn whether the
+    /// notification was closed.
+    pub fn process(&mut self) -> io::Result<()>
+    {
+        for event in self.notifications.process()? {
+            if let NotificationEvent::Closed { id } = event {
+                if self.shown == Some(id) {
+                    self.shown = None;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns how long until the notification is shown again.
+    pub fn timeout(&self, now: Instant) -> Option<Duration>
+    {
+        self.due.map(|due| due.saturating_duration_since(now))
+    }
+}
+
+/// Returns the body of the notification if the batteries reached their
+/// target on AC, or `None` if they did not.
+fn body(snapshot: &Snapshot) -> Option<String>
+{
+    if !snapshot.status.is_on_ac() {
+        return None;
+    }
+    if let Some(limit) = snapshot.charge_limited() {
+        return Some(format!(
+            "The charge limit of {}% is reached, you can unplug the charger now.",
+            limit
+        ));
+    }
+    let full = snapshot.status == Status::Full || snapshot.capacity() == Some(100);
+    full.then(|| "The battery is full, you can unplug the charger now.".to_string())
+}
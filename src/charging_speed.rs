@@ -0,0 +1,65 @@
+use std::fmt::Display;
+
+/// How fast a battery charges, from the rate at which it charges relative to
+/// what it holds when full, the C-rate. A laptop charging at 0.5C gains half
+/// its capacity in an hour:
+///
+/// ```
+/// use lithiumd::ChargingSpeed;
+///
+/// // 30 W into a 57 Wh battery.
+/// assert_eq!(ChargingSpeed::of(30_000_000, 57_000_000), Some(ChargingSpeed::Normal));
+/// // 4.5 W from a phone charger.
+/// assert_eq!(ChargingSpeed::of(4_500_000, 57_000_000), Some(ChargingSpeed::Trickle));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ChargingSpeed
+{
+    /// Below 0.1C, which hardly keeps up with the system, e.g. on a weak
+    /// USB-C port.
+    Trickle,
+    /// Below 0.3C.
+    Slow,
+    /// Below 0.7C.
+    Normal,
+    /// At or above 0.7C.
+    Fast,
+}
+
+impl ChargingSpeed
+{
+    /// Classifies charging at `power` in µW into a battery holding `full` µWh,
+    /// or at a current in µA into one holding a charge in µAh. `None` if the
+    /// battery does not report when it is full.
+    #[must_use]
+    pub fn of(power: u64, full: u64) -> Option<Self>
+    {
+        if full == 0 {
+            return None;
+        }
+        let rate = power as f64 / full as f64;
+        Some(if rate < 0.1 {
+            ChargingSpeed::Trickle
+        } else if rate < 0.3 {
+            ChargingSpeed::Slow
+        } else if rate < 0.7 {
+            ChargingSpeed::Normal
+        } else {
+            ChargingSpeed::Fast
+        })
+    }
+}
+
+impl Display for ChargingSpeed
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result
+    {
+        match *self {
+            ChargingSpeed::Trickle => write!(f, "trickle"),
+            ChargingSpeed::Slow => write!(f, "slow"),
+            ChargingSpeed::Normal => write!(f, "normal"),
+            ChargingSpeed::Fast => write!(f, "fast"),
+        }
+    }
+}
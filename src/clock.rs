@@ -0,0 +1,96 @@
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
+use std::time::Instant;
+
+/// A source of time. Everything in the library that waits or measures goes
+/// through a clock, so that it can be driven by a [`MockClock`] in tests and
+/// simulations.
+pub trait Clock
+{
+    fn now(&self) -> Instant;
+}
+
+/// The real monotonic clock.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock
+{
+    fn now(&self) -> Instant
+    {
+        Instant::now()
+    }
+}
+
+/// A clock that only moves when told to. Clones share the same time, so one
+/// clone can be handed to a [`PowerSupply`](crate::PowerSupply) and the other
+/// used to fast-forward it:
+///
+/// ```
+/// use std::time::Duration;
+///
+/// use lithiumd::Clock;
+/// use lithiumd::MockClock;
+///
+/// let clock = MockClock::new();
+/// let start = clock.now();
+/// clock.clone().advance(Duration::from_secs(5));
+/// assert_eq!(clock.now() - start, Duration::from_secs(5));
+/// ```
+#[derive(Debug, Clone)]
+pub struct MockClock
+{
+    now: Arc<Mutex<Instant>>,
+}
+
+impl MockClock
+{
+    #[must_use]
+    pub fn new() -> Self
+    {
+        Self {
+            now: Arc::new(Mutex::new(Instant::now())),
+        }
+    }
+
+    pub fn advance(&self, duration: Duration)
+    {
+        *self.now.lock().unwrap() += duration;
+    }
+}
+
+impl Default for MockClock
+{
+    fn default() -> Self
+    {
+        Self::new()
+    }
+}
+
+impl Clock for MockClock
+{
+    fn now(&self) -> Instant
+    {
+        *self.now.lock().unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn clones_share_the_time()
+    {
+        let clock = MockClock::new();
+        let clone = clock.clone();
+        let start = clock.now();
+        assert_eq!(clock.now(), start);
+        clone.advance(Duration::from_secs(3));
+        clock.advance(Duration::from_millis(250));
+        assert_eq!(clone.now() - start, Duration::from_millis(3250));
+        assert_eq!(clock.now(), clone.now());
+    }
+}
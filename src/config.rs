@@ -0,0 +1,1744 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use lithiumd::rules::Action;
+use lithiumd::rules::Condition;
+use lithiumd::rules::Hibernate;
+use lithiumd::rules::Rule;
+use lithiumd::rules::RunHook;
+use lithiumd::rules::SetPowerProfile;
+use lithiumd::BatteryLevel;
+use lithiumd::Brightness;
+use lithiumd::BrightnessChange;
+use lithiumd::CpuPolicy;
+use lithiumd::DrainLimit;
+use lithiumd::Expr;
+use lithiumd::History;
+use lithiumd::HookPolicy;
+use lithiumd::IdleTimeout;
+use lithiumd::KeyboardBrightness;
+use lithiumd::Labels;
+use lithiumd::LevelThresholds;
+use lithiumd::OnFailure;
+use lithiumd::PciePolicy;
+use lithiumd::PowerSupply;
+use lithiumd::Pretty;
+use lithiumd::Smoothing;
+use lithiumd::SourceKind;
+use lithiumd::Status;
+use lithiumd::SysfsPoller;
+use lithiumd::Template;
+use lithiumd::ThermalLimit;
+use lithiumd::UPowerBackend;
+use lithiumd::UsbAutosuspend;
+use lithiumd::WifiPowerSave;
+
+use crate::cooldown;
+use crate::dbus_service::Bus;
+use crate::idle_policy::IdleAction;
+use crate::idle_policy::SessionIdle;
+use crate::lid_policy::LidPolicy;
+use crate::lid_policy::LockCheck;
+use crate::lid_policy::LockFailure;
+use crate::lid_policy::ScreenLock;
+use crate::low_battery::Button;
+use crate::low_battery::NotifyOptions;
+use crate::mqtt;
+use crate::mqtt::MqttOptions;
+use crate::power_button::ButtonAction;
+use crate::sound::Sound;
+use crate::speech::Speech;
+use crate::speech::Verbosity;
+use crate::sway::SwayOptions;
+use crate::toml;
+use crate::toml::Table;
+use crate::toml::Value;
+
+/// Daemon configuration, read from `config.toml`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Config
+{
+    /// How long the power supply has to stay quiet after a uevent before the
+    /// new status is reported.
+    pub debounce: Duration,
+    /// Size in bytes of the receive buffer of the monitor socket.
+    pub receive_buffer: Option<usize>,
+    /// Where the power supply devices come from. Only read at startup.
+    pub backend: Backend,
+    /// Step in percent at which changes of the combined capacity are
+    /// reported.
+    pub capacity_step: u8,
+    /// Whether batteries of peripherals (`POWER_SUPPLY_SCOPE=Device`) take
+    /// part in device selection.
+    pub include_device_scope: bool,
+    /// Glob patterns pinning the battery and the adapter by sysname.
+    pub battery: Option<String>,
+    pub adapter: Option<String>,
+    /// The kinds of sources that power the system, most preferred first.
+    pub sources: Vec<SourceKind>,
+    /// How often the batteries are read again while discharging.
+    pub refresh_interval: Option<Duration>,
+    /// Whether batteries of peripherals are tracked as devices of their own.
+    pub monitor_peripherals: bool,
+    /// Capacity in percent at which a peripheral battery is reported as low.
+    pub peripheral_low_capacity: u8,
+    /// Whether battery levels of Bluetooth devices are read from BlueZ.
+    pub bluetooth: bool,
+    /// Whether the USB PD contracts of chargers are tracked.
+    pub usb_pd: bool,
+    /// Whether fans are reported turning on and off, from the `[fans]`
+    /// table.
+    pub fans: bool,
+    /// Whether docking stations are monitored, from the `[dock]` table.
+    pub dock: bool,
+    /// Command to run when a dock is attached.
+    pub docked_hook: Option<String>,
+    /// Command to run when a dock is detached.
+    pub undocked_hook: Option<String>,
+    /// Whether a charger that cannot keep up is reported, from the
+    /// `[weak_charger]` table.
+    pub weak_charger: bool,
+    /// USB PD contracts below this many watts count as a weak charger.
+    pub weak_charger_watts: Option<f64>,
+    /// Command to run when the charger is too weak.
+    pub weak_charger_hook: Option<String>,
+    /// Energies in µWh left in the batteries to warn at, from the
+    /// `[energy_budget]` table.
+    pub energy_budgets: Vec<u64>,
+    /// Whether to show a notification when a budget is crossed.
+    pub energy_budget_notify: bool,
+    /// Command to run when a budget is crossed.
+    pub energy_budget_hook: Option<String>,
+    /// Bus to offer the `org.lithiumd` service on, if any.
+    pub dbus: Option<Bus>,
+    /// Whether to broadcast signals on the session bus, from the
+    /// `[broadcast]` table.
+    pub broadcast: bool,
+    /// File to write the state to as environment variables on each change.
+    pub env_file: Option<PathBuf>,
+    /// File to keep the battery history in, from the `[history]` table.
+    pub history: Option<PathBuf>,
+    /// File to record the state and events to, from `--record`.
+    pub record: Option<PathBuf>,
+    /// User to switch to once the privileged resources are open, from
+    /// `--user`.
+    pub user: Option<String>,
+    /// Whether the daemon confines itself with a seccomp filter, from
+    /// `--sandbox`.
+    pub sandbox: bool,
+    /// Whether a running daemon is asked to exit rather than refusing to
+    /// start, from `--replace`.
+    pub replace: bool,
+    /// Whether the daemon detaches from the terminal, from `--daemonize`.
+    pub daemonize: bool,
+    /// File the output of a detached daemon goes to, from `--log`.
+    pub log: Option<PathBuf>,
+    /// Whether rule hooks hold a logind delay lock, so that the system only
+    /// sleeps once they finished.
+    pub delay_sleep: bool,
+    /// User that hooks run as, in the environment of their desktop session.
+    pub hook_user: Option<String>,
+    /// Timeout, retries and failure policy of hooks, from the `[hooks]`
+    /// table. Rules and profiles may set their own.
+    pub hook_policy: HookPolicy,
+    /// How long each kind of action cools down after it was taken, from the
+    /// `[cooldown]` table.
+    pub cooldowns: BTreeMap<&'static str, Duration>,
+    /// Rules from the `[[rule]]` tables.
+    pub rules: Vec<Rule>,
+    /// Actions of the profiles in the `profiles` directory next to the
+    /// configuration file, by name. The `battery` and `ac` profiles are
+    /// taken whenever the power source changes.
+    pub profiles: BTreeMap<String, Vec<Action>>,
+    /// Idle timeouts from the `[[idle]]` tables.
+    pub idle: Vec<IdleTimeout>,
+    /// Actions on logind's idle hint, from the `[[session_idle]]` tables.
+    pub session_idle: Vec<SessionIdle>,
+    /// Lid switch handling, from the `[lid]` table.
+    pub lid: Option<LidPolicy>,
+    /// Whether the tablet mode switch is watched, from the `[tablet_mode]`
+    /// table.
+    pub tablet_mode: bool,
+    /// Commands to run when a convertible is folded into a tablet and back.
+    pub tablet_hook: Option<String>,
+    pub laptop_hook: Option<String>,
+    /// Power button handling, from the `[power_button]` table.
+    pub power_button: Option<ButtonAction>,
+    /// Spoken announcements, from the `[speech]` table.
+    pub speech: Option<Speech>,
+    /// Sounds played on changes, from the `[sound]` table.
+    pub sound: Option<Sound>,
+    /// Commands sent to sway or i3, from the `[sway]` table.
+    pub sway: Option<SwayOptions>,
+    /// Whether a notification tells when the batteries are charged, from
+    /// the `[charged]` table, and how often it is shown again.
+    pub charged: Option<Option<Duration>>,
+    /// How long suspending and hibernating by rules can be canceled, from
+    /// the `[countdown]` table.
+    pub countdown: Option<Duration>,
+    /// Desktop notifications on low battery, from the `[notifications]`
+    /// table.
+    pub notifications: Option<NotifyOptions>,
+    /// Abnormal drain detection, from the `[drain]` table.
+    pub drain: Option<DrainLimit>,
+    /// Command to run when the drain exceeds the limit.
+    pub drain_hook: Option<String>,
+    /// Thermal zone monitoring, from the `[thermal]` table.
+    pub thermal: Option<ThermalLimit>,
+    /// Commands to run when a zone gets hot and when it cooled down.
+    pub hot_hook: Option<String>,
+    pub cooled_hook: Option<String>,
+    /// Whether thermal throttling of the CPUs is reported.
+    pub throttling: bool,
+    /// Capacities at which the battery levels start, from the `[levels]`
+    /// table.
+    pub levels: LevelThresholds,
+    /// Smoothing of the power readings for time estimates, from the
+    /// `[estimate]` table.
+    pub smoothing: Smoothing,
+    /// Line printed on each change instead of the events, from `format` or
+    /// `--format`.
+    pub format: Option<Template>,
+    /// What is printed on each change without a `format`, from `output` or
+    /// `--output`.
+    pub output: Output,
+    /// Glyphs and colors of the pretty output, from the `[icons]` table.
+    pub icons: Pretty,
+    /// Names of the statuses and levels, from the `[labels]` table.
+    pub labels: Labels,
+    /// Whether to show a battery icon in the system tray.
+    pub tray: bool,
+    /// MQTT broker to publish the state to, from the `[mqtt]` table.
+    pub mqtt: Option<MqttOptions>,
+    /// Secret that agents and `remote list` show the collector, from
+    /// `[remote] token`.
+    pub remote_token: Option<String>,
+}
+
+/// The [`PowerBackend`](lithiumd::PowerBackend) the power supply is read
+/// through.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backend
+{
+    /// Uevents, see [`UdevBackend`](lithiumd::UdevBackend).
+    Udev,
+    /// Rescans of sysfs, see [`SysfsPoller`].
+    Sysfs,
+    /// The devices of UPower, see [`UPowerBackend`].
+    UPower,
+}
+
+impl Backend
+{
+    /// How often the sysfs backend rescans.
+    const SYSFS_INTERVAL: Duration = Duration::from_secs(5);
+
+    /// Opens the power supply through this backend.
+    pub fn power_supply(self) -> io::Result<PowerSupply>
+    {
+        Ok(match self {
+            Self::Udev => PowerSupply::new(),
+            Self::Sysfs => {
+                PowerSupply::with_backend(Box::new(SysfsPoller::new(Self::SYSFS_INTERVAL)?))
+            }
+            Self::UPower => PowerSupply::with_backend(Box::new(UPowerBackend::new()?)),
+        })
+    }
+}
+
+/// How the daemon reports changes on stdout.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Output
+{
+    /// Every event on a line of its own.
+    Events,
+    /// A battery glyph and the capacity, see [`Pretty`].
+    Pretty,
+    /// The format, or the pretty output without colors, set as the name of
+    /// the X root window for dwm instead of printed.
+    Xroot,
+    /// The battery glyph and the capacity in polybar's color tags, see
+    /// [`Polybar`](crate::polybar::Polybar).
+    Polybar,
+}
+
+impl Default for Config
+{
+    fn default() -> Self
+    {
+        Self {
+            debounce: Duration::from_millis(200),
+            receive_buffer: None,
+            backend: Backend::Udev,
+            capacity_step: 5,
+            include_device_scope: false,
+            battery: None,
+            adapter: None,
+            sources: SourceKind::DEFAULT_PRIORITY.to_vec(),
+            refresh_interval: None,
+            monitor_peripherals: false,
+            peripheral_low_capacity: 20,
+            bluetooth: false,
+            usb_pd: false,
+            fans: false,
+            dock: false,
+            docked_hook: None,
+            undocked_hook: None,
+            weak_charger: false,
+            weak_charger_watts: None,
+            weak_charger_hook: None,
+            energy_budgets: Vec::new(),
+            energy_budget_notify: false,
+            energy_budget_hook: None,
+            dbus: None,
+            broadcast: false,
+            env_file: None,
+            history: None,
+            record: None,
+            user: None,
+            sandbox: false,
+            replace: false,
+            daemonize: false,
+            log: None,
+            delay_sleep: true,
+            hook_user: None,
+            hook_policy: HookPolicy::default(),
+            cooldowns: BTreeMap::new(),
+            rules: Vec::new(),
+            profiles: BTreeMap::new(),
+            idle: Vec::new(),
+            session_idle: Vec::new(),
+            lid: None,
+            tablet_mode: false,
+            tablet_hook: None,
+            laptop_hook: None,
+            power_button: None,
+            speech: None,
+            sound: None,
+            sway: None,
+            charged: None,
+            countdown: None,
+            notifications: None,
+            drain: None,
+            drain_hook: None,
+            thermal: None,
+            hot_hook: None,
+            cooled_hook: None,
+            throttling: false,
+            levels: LevelThresholds::default(),
+            smoothing: Smoothing::default(),
+            format: None,
+            output: Output::Events,
+            icons: Pretty::default(),
+            labels: Labels::default(),
+            tray: false,
+            mqtt: None,
+            remote_token: None,
+        }
+    }
+}
+
+impl Config
+{
+    /// Returns the configuration file used when none is given explicitly:
+    /// the first of `$XDG_CONFIG_HOME/lithiumd/config.toml`,
+    /// `~/.config/lithiumd/config.toml`, and `/etc/lithiumd/config.toml` that
+    /// exists.
+    #[must_use]
+    pub fn default_path() -> Option<PathBuf>
+    {
+        Self::user_path()
+            .into_iter()
+            .chain([PathBuf::from("/etc/lithiumd/config.toml")])
+            .find(|path| path.exists())
+    }
+
+    /// Returns `$XDG_CONFIG_HOME/lithiumd/config.toml`, or
+    /// `~/.config/lithiumd/config.toml` without `XDG_CONFIG_HOME`.
+    #[must_use]
+    pub fn user_path() -> Option<PathBuf>
+    {
+        env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".config")))
+            .map(|dir| dir.join("lithiumd/config.toml"))
+    }
+
+    /// Loads the configuration from the default location, falling back to the
+    /// defaults if there is no configuration file. Either way `LID_*`
+    /// environment variables override it.
+    pub fn load() -> io::Result<Self>
+    {
+        match Self::default_path() {
+            Some(path) => Self::from_path(&path),
+            None => {
+                let mut table = Table::new();
+                apply_env(&mut table)
+                    .and_then(|()| Self::from_table(&table))
+                    .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+            }
+        }
+    }
+
+    pub fn from_path(path: &Path) -> io::Result<Self>
+    {
+        let invalid = |message: String| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{}: {}", path.display(), message),
+            )
+        };
+        let contents = fs::read_to_string(path)?;
+        let mut table = toml::parse(&contents).map_err(|err| invalid(err.to_string()))?;
+        apply_env(&mut table).map_err(invalid)?;
+        let mut config = Self::from_table(&table).map_err(invalid)?;
+        config.load_profiles(&path.with_file_name("profiles"))?;
+        Ok(config)
+    }
+
+    /// Reads every `NAME.toml` in `dir` as a profile, a table with the same
+    /// action keys as a `[[rule]]`, and adds rules taking the `battery` or
+    /// `ac` profile with the power source. It is fine for `dir` to not
+    /// exist.
+    fn load_profiles(&mut self, dir: &Path) -> io::Result<()>
+    {
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(err) => return Err(err),
+        };
+        for entry in entries {
+            let path = entry?.path();
+            if path.extension().is_none_or(|extension| extension != "toml") {
+                continue;
+            }
+            let Some(name) = path
+                .file_stem()
+                .map(|name| name.to_string_lossy().into_owned())
+            else {
+                continue;
+            };
+            let invalid = |message: String| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("{}: {}", path.display(), message),
+                )
+            };
+            let table =
+                toml::parse(&fs::read_to_string(&path)?).map_err(|err| invalid(err.to_string()))?;
+            let actions = parse_actions(&table, &self.hook_policy).map_err(invalid)?;
+            let condition = match name.as_str() {
+                "battery" => Some(Condition::OnBattery),
+                "ac" => Some(Condition::OnAc),
+                _ => None,
+            };
+            if let (Some(condition), Some((first, rest))) = (condition, actions.split_first()) {
+                let rule = Rule::when(condition).then(first.clone());
+                self.rules
+                    .push(rest.iter().cloned().fold(rule, Rule::and_then));
+            }
+            self.profiles.insert(name, actions);
+        }
+        Ok(())
+    }
+
+    /// Applies the command line options of the daemon, which take precedence
+    /// over the configuration file.
+    pub fn apply_args(&mut self, args: &[String]) -> Result<(), String>
+    {
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            let mut value = || {
+                args.next()
+                    .cloned()
+                    .ok_or_else(|| format!("`{}` needs a value", arg))
+            };
+            match arg.as_str() {
+                "--battery" => self.battery = Some(value()?),
+                "--adapter" => self.adapter = Some(value()?),
+                "--format" => {
+                    let format = value()?;
+                    self.format = Some(
+                        format
+                            .parse()
+                            .map_err(|err| format!("`--format`: {}", err))?,
+                    );
+                }
+                "--output" => {
+                    self.output =
+                        parse_output(&value()?).map_err(|err| format!("`--output`: {}", err))?
+                }
+                "--record" => self.record = Some(PathBuf::from(value()?)),
+                "--user" => self.user = Some(value()?),
+                "--sandbox" => self.sandbox = true,
+                "--replace" => self.replace = true,
+                "--daemonize" => self.daemonize = true,
+                "--log" => self.log = Some(PathBuf::from(value()?)),
+                _ => return Err(format!("unknown option `{}`", arg)),
+            }
+        }
+        Ok(())
+    }
+
+    pub fn from_table(table: &Table) -> Result<Self, String>
+    {
+        let mut config = Self::default();
+        if let Some(debounce) = duration_ms(table, "debounce_ms")? {
+            config.debounce = debounce;
+        }
+        if let Some(kb) = number(table, "receive_buffer_kb")? {
+            config.receive_buffer = Some((kb * 1024.0) as usize);
+        }
+        config.refresh_interval = match table.get("refresh_interval_s") {
+            None => None,
+            Some(Value::Integer(seconds @ 1..)) => Some(Duration::from_secs(*seconds as u64)),
+            Some(_) => return Err("`refresh_interval_s` must be a positive integer".to_string()),
+        };
+        if let Some(backend) = string(table, "backend")? {
+            config.backend = parse_backend(backend).map_err(|err| format!("backend: {}", err))?;
+        }
+        if let Some(step) = percentage(table, "capacity_step")? {
+            if step == 0 {
+                return Err("`capacity_step` must be at least 1".to_string());
+            }
+            config.capacity_step = step;
+        }
+        if let Some(output) = string(table, "output")? {
+            config.output = parse_output(output).map_err(|err| format!("output: {}", err))?;
+        }
+        if let Some(icons) = table_value(table, "icons")? {
+            config.icons = parse_icons(icons).map_err(|err| format!("icons: {}", err))?;
+        }
+        if let Some(labels) = table_value(table, "labels")? {
+            config.labels = parse_labels(labels).map_err(|err| format!("labels: {}", err))?;
+        }
+        if let Some(include) = boolean(table, "include_device_scope")? {
+            config.include_device_scope = include;
+        }
+        config.battery = string(table, "battery")?.map(str::to_string);
+        config.adapter = string(table, "adapter")?.map(str::to_string);
+        if table.get("sources").is_some() {
+            config.sources = strings(table, "sources")?
+                .iter()
+                .map(|kind| parse_source_kind(kind))
+                .collect::<Result<_, _>>()
+                .map_err(|err| format!("sources: {}", err))?;
+        }
+        if let Some(format) = string(table, "format")? {
+            config.format = Some(format.parse().map_err(|err| format!("format: {}", err))?);
+        }
+        if let Some(peripherals) = table_value(table, "peripherals")? {
+            if let Some(enabled) = boolean(peripherals, "enabled")? {
+                config.monitor_peripherals = enabled;
+            }
+            if let Some(low) = percentage(peripherals, "low_capacity")? {
+                config.peripheral_low_capacity = low;
+            }
+        }
+        if let Some(usb_pd) = table_value(table, "usb_pd")? {
+            if let Some(enabled) = boolean(usb_pd, "enabled")? {
+                config.usb_pd = enabled;
+            }
+        }
+        if let Some(fans) = table_value(table, "fans")? {
+            if let Some(enabled) = boolean(fans, "enabled")? {
+                config.fans = enabled;
+            }
+        }
+        if let Some(dock) = table_value(table, "dock")? {
+            if let Some(enabled) = boolean(dock, "enabled")? {
+                config.dock = enabled;
+            }
+            config.docked_hook = string(dock, "docked")?.map(str::to_string);
+            config.undocked_hook = string(dock, "undocked")?.map(str::to_string);
+        }
+        if let Some(weak) = table_value(table, "weak_charger")? {
+            if let Some(enabled) = boolean(weak, "enabled")? {
+                config.weak_charger = enabled;
+            }
+            config.weak_charger_watts = number(weak, "min_watts")?;
+            config.weak_charger_hook = string(weak, "run")?.map(str::to_string);
+        }
+        if let Some(budget) = table_value(table, "energy_budget")? {
+            config.energy_budgets =
+                parse_energy_budgets(budget).map_err(|err| format!("energy_budget: {}", err))?;
+            config.energy_budget_notify = boolean(budget, "notify")?.unwrap_or(true);
+            config.energy_budget_hook = string(budget, "run")?.map(str::to_string);
+        }
+        if let Some(bluetooth) = table_value(table, "bluetooth")? {
+            if let Some(enabled) = boolean(bluetooth, "enabled")? {
+                config.bluetooth = enabled;
+            }
+        }
+        if let Some(tray) = table_value(table, "tray")? {
+            if let Some(enabled) = boolean(tray, "enabled")? {
+                config.tray = enabled;
+            }
+        }
+        if let Some(mqtt) = table_value(table, "mqtt")? {
+            config.mqtt = parse_mqtt(mqtt).map_err(|err| format!("mqtt: {}", err))?;
+        }
+        if let Some(remote) = table_value(table, "remote")? {
+            config.remote_token = string(remote, "token")?.map(str::to_string);
+        }
+        if let Some(dbus) = table_value(table, "dbus")? {
+            if boolean(dbus, "enabled")? == Some(true) {
+                config.dbus = Some(match string(dbus, "bus")? {
+                    None | Some("system") => Bus::System,
+                    Some("session") => Bus::Session,
+                    Some(other) => {
+                        return Err(format!(
+                            "dbus: `bus` must be \"system\" or \"session\", found \"{}\"",
+                            other
+                        ))
+                    }
+                });
+            }
+        }
+        if let Some(broadcast) = table_value(table, "broadcast")? {
+            config.broadcast = boolean(broadcast, "enabled")? == Some(true);
+        }
+        config.env_file = string(table, "env_file")?.map(PathBuf::from);
+        if let Some(history) = table_value(table, "history")? {
+            if boolean(history, "enabled")? == Some(true) {
+                config.history = Some(
+                    string(history, "path")?.map_or_else(History::default_path, PathBuf::from),
+                );
+            }
+        }
+        if let Some(hooks) = table_value(table, "hooks")? {
+            if let Some(delay_sleep) = boolean(hooks, "delay_sleep")? {
+                config.delay_sleep = delay_sleep;
+            }
+            config.hook_user = string(hooks, "user")?.map(str::to_string);
+            config.hook_policy = parse_hook_policy(hooks, "", &HookPolicy::default())
+                .map_err(|err| format!("hooks: {}", err))?;
+        }
+        if let Some(cooldown) = table_value(table, "cooldown")? {
+            config.cooldowns =
+                parse_cooldowns(cooldown).map_err(|err| format!("cooldown: {}", err))?;
+        }
+        if let Some(profiles) = table_value(table, "power_profiles")? {
+            config.rules.extend(parse_power_profiles(profiles)?);
+        }
+        if let Some(backlight) = table_value(table, "backlight")? {
+            config.rules.extend(parse_backlight(backlight)?);
+        }
+        if let Some(keyboard) = table_value(table, "keyboard_backlight")? {
+            config.rules.extend(parse_keyboard_backlight(keyboard)?);
+        }
+        if let Some(cpufreq) = table_value(table, "cpufreq")? {
+            config.rules.extend(parse_cpufreq(cpufreq)?);
+        }
+        if let Some(pcie) = table_value(table, "pcie")? {
+            config.rules.extend(parse_pcie(pcie)?);
+        }
+        if let Some(wifi) = table_value(table, "wifi")? {
+            config
+                .rules
+                .extend(parse_wifi(wifi).map_err(|err| format!("wifi: {}", err))?);
+        }
+        if let Some(usb) = table_value(table, "usb_autosuspend")? {
+            config.rules.extend(
+                parse_usb_autosuspend(usb).map_err(|err| format!("usb_autosuspend: {}", err))?,
+            );
+        }
+        config.rules.extend(array_of_tables(table, "rule", |rule| {
+            parse_rule(rule, &config.hook_policy)
+        })?);
+        config.idle = array_of_tables(table, "idle", parse_idle)?;
+        config.session_idle = array_of_tables(table, "session_idle", parse_session_idle)?;
+        if let Some(speech) = table_value(table, "speech")? {
+            config.speech = parse_speech(speech).map_err(|err| format!("speech: {}", err))?;
+        }
+        if let Some(sound) = table_value(table, "sound")? {
+            config.sound = parse_sound(sound).map_err(|err| format!("sound: {}", err))?;
+        }
+        if let Some(sway) = table_value(table, "sway")? {
+            config.sway = parse_sway(sway).map_err(|err| format!("sway: {}", err))?;
+        }
+        if let Some(charged) = table_value(table, "charged")? {
+            if boolean(charged, "enabled")? == Some(true) {
+                let minutes = match charged.get("repeat_min") {
+                    None => 0,
+                    Some(Value::Integer(minutes @ 0..)) => *minutes as u64,
+                    Some(_) => {
+                        return Err(
+                            "charged: `repeat_min` must be a non-negative integer".to_string()
+                        )
+                    }
+                };
+                config.charged =
+                    Some(Some(Duration::from_secs(minutes * 60)).filter(|_| minutes > 0));
+            }
+        }
+        if let Some(countdown) = table_value(table, "countdown")? {
+            if boolean(countdown, "enabled")? == Some(true) {
+                let seconds = match countdown.get("seconds") {
+                    None => 30,
+                    Some(Value::Integer(seconds @ 1..)) => *seconds as u64,
+                    Some(_) => {
+                        return Err("countdown: `seconds` must be a positive integer".to_string())
+                    }
+                };
+                config.countdown = Some(Duration::from_secs(seconds));
+            }
+        }
+        if let Some(notifications) = table_value(table, "notifications")? {
+            config.notifications = parse_notifications(notifications)
+                .map_err(|err| format!("notifications: {}", err))?;
+        }
+        if let Some(drain) = table_value(table, "drain")? {
+            config.drain = parse_drain(drain).map_err(|err| format!("drain: {}", err))?;
+            config.drain_hook = string(drain, "run")?.map(str::to_string);
+        }
+        if let Some(thermal) = table_value(table, "thermal")? {
+            config.thermal = parse_thermal(thermal).map_err(|err| format!("thermal: {}", err))?;
+            config.hot_hook = string(thermal, "hot")?.map(str::to_string);
+            config.cooled_hook = string(thermal, "cooled")?.map(str::to_string);
+            if let Some(throttling) = boolean(thermal, "throttling")? {
+                config.throttling = throttling;
+            }
+        }
+        if let Some(levels) = table_value(table, "levels")? {
+            config.levels = parse_levels(levels).map_err(|err| format!("levels: {}", err))?;
+        }
+        if let Some(estimate) = table_value(table, "estimate")? {
+            config.smoothing =
+                parse_smoothing(estimate).map_err(|err| format!("estimate: {}", err))?;
+        }
+        if let Some(lid) = table_value(table, "lid")? {
+            config.lid = parse_lid(lid).map_err(|err| format!("lid: {}", err))?;
+        }
+        if let Some(tablet) = table_value(table, "tablet_mode")? {
+            if let Some(enabled) = boolean(tablet, "enabled")? {
+                config.tablet_mode = enabled;
+            }
+            config.tablet_hook = string(tablet, "tablet")?.map(str::to_string);
+            config.laptop_hook = string(tablet, "laptop")?.map(str::to_string);
+        }
+        if let Some(button) = table_value(table, "power_button")? {
+            config.power_button =
+                parse_power_button(button).map_err(|err| format!("power_button: {}", err))?;
+        }
+        Ok(config)
+    }
+}
+
+/// Parses every table of the array of tables `key` with `parse`.
+fn array_of_tables<T>(
+    table: &Table,
+    key: &str,
+    parse: impl Fn(&Table) -> Result<T, String>,
+) -> Result<Vec<T>, String>
+{
+    let not_tables = || format!("`{}` must be an array of tables", key);
+    match table.get(key) {
+        None => Ok(Vec::new()),
+        Some(Value::Array(values)) => values
+            .iter()
+            .enumerate()
+            .map(|(i, value)| match value {
+                Value::Table(value) => {
+                    parse(value).map_err(|err| format!("{} {}: {}", key, i + 1, err))
+                }
+                _ => Err(not_tables()),
+            })
+            .collect(),
+        Some(_) => Err(not_tables()),
+    }
+}
+
+/// Parses the `[power_profiles]` table, which maps the power source to a
+/// profile, into one rule per power source.
+fn parse_power_profiles(table: &Table) -> Result<Vec<Rule>, String>
+{
+    let mut rules = Vec::new();
+    for (key, condition) in [("battery", Condition::OnBattery), ("ac", Condition::OnAc)] {
+        if let Some(profile) = string(table, key)? {
+            rules.push(Rule::when(condition).then(SetPowerProfile::new(profile)));
+        }
+    }
+    Ok(rules)
+}
+
+/// Parses the `[backlight]` table, which holds the brightness of each power
+/// source and of a low battery, into one rule per state.
+fn parse_backlight(table: &Table) -> Result<Vec<Rule>, String>
+{
+    let fade = duration_ms(table, "fade_ms")?.unwrap_or(Duration::from_millis(500));
+    let change = |key| {
+        Ok::<_, String>(
+            brightness(table, key)?.map(|brightness| BrightnessChange { brightness, fade }),
+        )
+    };
+    let mut rules = Vec::new();
+    if let Some(change) = change("battery")? {
+        rules.push(Rule::when(Condition::OnBattery).then(change));
+    }
+    if let Some(change) = change("ac")? {
+        rules.push(Rule::when(Condition::OnAc).then(change));
+    }
+    if let Some(change) = change("low")? {
+        let below = percentage(table, "low_capacity")?.unwrap_or(20);
+        rules.push(
+            Rule::when(Condition::OnBattery)
+                .and(Condition::CapacityBelow(below))
+                .then(change),
+        );
+    }
+    Ok(rules)
+}
+
+/// Reads a brightness: an integer percentage, or a string such as `"-20"` or
+/// `"+10"` that changes the current brightness by that much.
+fn brightness(table: &Table, key: &str) -> Result<Option<Brightness>, String>
+{
+    match table.get(key) {
+        Some(Value::String(step)) => step
+            .trim_end_matches('%')
+            .parse::<i8>()
+            .ok()
+            .filter(|_| step.starts_with(['+', '-']))
+            .filter(|step| (-100..=100).contains(step))
+            .map(|step| Some(Brightness::Step(step)))
+            .ok_or_else(|| format!("`{}` must be a percentage or a step such as \"-20\"", key)),
+        _ => Ok(percentage(table, key)?.map(Brightness::Percent)),
+    }
+}
+
+/// Parses the `[keyboard_backlight]` table, which holds the level of each
+/// power source, into one rule per power source.
+fn parse_keyboard_backlight(table: &Table) -> Result<Vec<Rule>, String>
+{
+    let mut rules = Vec::new();
+    for (key, condition) in [("battery", Condition::OnBattery), ("ac", Condition::OnAc)] {
+        if let Some(brightness) = keyboard_brightness(table, key)? {
+            rules.push(Rule::when(condition).then(brightness));
+        }
+    }
+    Ok(rules)
+}
+
+/// Reads a keyboard backlight level: an integer, `"off"`, or `"restore"`
+/// for the level from before lithiumd changed it.
+fn keyboard_brightness(table: &Table, key: &str) -> Result<Option<KeyboardBrightness>, String>
+{
+    match table.get(key) {
+        None => Ok(None),
+        Some(Value::Integer(level)) if *level >= 0 => {
+            Ok(Some(KeyboardBrightness::Level(*level as u64)))
+        }
+        Some(Value::String(value)) if value == "off" => Ok(Some(KeyboardBrightness::Level(0))),
+        Some(Value::String(value)) if value == "restore" => Ok(Some(KeyboardBrightness::Restore)),
+        Some(_) => Err(format!("`{}` must be a level, \"off\" or \"restore\"", key)),
+    }
+}
+
+/// Parses the `[cpufreq]` table, with a `battery` and an `ac` table holding
+/// the CPU policy of each power source, into one rule per power source.
+fn parse_cpufreq(table: &Table) -> Result<Vec<Rule>, String>
+{
+    let mut rules = Vec::new();
+    for (key, condition) in [("battery", Condition::OnBattery), ("ac", Condition::OnAc)] {
+        let Some(policy) = table_value(table, key)? else {
+            continue;
+        };
+        let policy = parse_cpu_policy(policy).map_err(|err| format!("cpufreq.{}: {}", key, err))?;
+        if !policy.is_empty() {
+            rules.push(Rule::when(condition).then(policy));
+        }
+    }
+    Ok(rules)
+}
+
+fn parse_cpu_policy(table: &Table) -> Result<CpuPolicy, String>
+{
+    Ok(CpuPolicy {
+        governor: string(table, "governor")?.map(str::to_string),
+        energy_performance_preference: string(table, "energy_performance_preference")?
+            .map(str::to_string),
+    })
+}
+
+/// Parses the `[pcie]` table, with a `battery` and an `ac` table holding the
+/// PCIe power policy of each power source, into one rule per power source.
+fn parse_pcie(table: &Table) -> Result<Vec<Rule>, String>
+{
+    let mut rules = Vec::new();
+    for (key, condition) in [("battery", Condition::OnBattery), ("ac", Condition::OnAc)] {
+        let Some(policy) = table_value(table, key)? else {
+            continue;
+        };
+        let policy = parse_pcie_policy(policy).map_err(|err| format!("pcie.{}: {}", key, err))?;
+        if !policy.is_empty() {
+            rules.push(Rule::when(condition).then(policy));
+        }
+    }
+    Ok(rules)
+}
+
+fn parse_pcie_policy(table: &Table) -> Result<PciePolicy, String>
+{
+    Ok(PciePolicy {
+        runtime_pm: boolean(table, "runtime_pm")?,
+        aspm: string(table, "aspm")?.map(str::to_string),
+    })
+}
+
+/// Parses the `[wifi]` table, with the power saving of each power source for
+/// the `interfaces`, or all wireless interfaces, into one rule per power
+/// source.
+fn parse_wifi(table: &Table) -> Result<Vec<Rule>, String>
+{
+    let interfaces = strings(table, "interfaces")?;
+    let mut rules = Vec::new();
+    for (key, condition) in [("battery", Condition::OnBattery), ("ac", Condition::OnAc)] {
+        if let Some(enabled) = boolean(table, key)? {
+            rules.push(Rule::when(condition).then(WifiPowerSave {
+                interfaces: interfaces.clone(),
+                enabled,
+            }));
+        }
+    }
+    Ok(rules)
+}
+
+/// Parses the `[usb_autosuspend]` table into a rule that lets USB devices
+/// autosuspend on battery and one that keeps them on with AC.
+fn parse_usb_autosuspend(table: &Table) -> Result<Vec<Rule>, String>
+{
+    if boolean(table, "enabled")? != Some(true) {
+        return Ok(Vec::new());
+    }
+    let autosuspend = UsbAutosuspend {
+        enabled: true,
+        deny: strings(table, "deny")?,
+        input: boolean(table, "input")?.unwrap_or(false),
+    };
+    Ok(vec![
+        Rule::when(Condition::OnBattery).then(autosuspend.clone()),
+        Rule::when(Condition::OnAc).then(UsbAutosuspend {
+            enabled: false,
+            ..autosuspend
+        }),
+    ])
+}
+
+/// Parses a `[[rule]]` table. Every key other than the actions,
+/// `inhibitable` and `hysteresis` adds a condition, `when` as an [`Expr`].
+fn parse_rule(table: &Table, hook_policy: &HookPolicy) -> Result<Rule, String>
+{
+    let conditions = parse_conditions(table)?;
+    let expr = string(table, "when")?
+        .map(Expr::parse)
+        .transpose()
+        .map_err(|err| format!("when: {}", err))?;
+    let actions = parse_actions(table, hook_policy)?;
+
+    let builder = match (conditions.split_first(), expr) {
+        (Some((first, rest)), expr) => {
+            let builder = rest
+                .iter()
+                .fold(Rule::when(*first), |rule, condition| rule.and(*condition));
+            match expr {
+                Some(expr) => builder.and_expr(expr),
+                None => builder,
+            }
+        }
+        (None, Some(expr)) => Rule::when_expr(expr),
+        (None, None) => return Err("a rule needs at least one condition".to_string()),
+    };
+    let mut actions = actions.into_iter();
+    let action = actions
+        .next()
+        .ok_or_else(|| "missing `run` or another action".to_string())?;
+    let rule = builder.then(action);
+    let rule = actions
+        .fold(rule, Rule::and_then)
+        .hysteresis(percentage(table, "hysteresis")?.unwrap_or(0));
+    Ok(match boolean(table, "inhibitable")? {
+        Some(true) => rule.inhibitable(),
+        _ => rule,
+    })
+}
+
+/// Parses the action keys of a `[[rule]]` table or a profile, in the order
+/// they are taken. The `run_*` keys override `hook_policy` for `run`.
+fn parse_actions(table: &Table, hook_policy: &HookPolicy) -> Result<Vec<Action>, String>
+{
+    let mut actions = Vec::new();
+    if let Some(run) = string(table, "run")? {
+        let policy = parse_hook_policy(table, "run_", hook_policy)?;
+        actions.push(Action::from(RunHook::new(run).policy(policy)));
+    }
+    if let Some(profile) = string(table, "power_profile")? {
+        actions.push(Action::from(SetPowerProfile::new(profile)));
+    }
+    let policy = parse_cpu_policy(table)?;
+    if !policy.is_empty() {
+        actions.push(Action::from(policy));
+    }
+    let pcie = parse_pcie_policy(table)?;
+    if !pcie.is_empty() {
+        actions.push(Action::from(pcie));
+    }
+    if let Some(enabled) = boolean(table, "wifi_power_save")? {
+        actions.push(Action::from(WifiPowerSave {
+            interfaces: Vec::new(),
+            enabled,
+        }));
+    }
+    if let Some(enabled) = boolean(table, "usb_autosuspend")? {
+        actions.push(Action::from(UsbAutosuspend {
+            enabled,
+            deny: strings(table, "usb_deny")?,
+            input: boolean(table, "usb_input")?.unwrap_or(false),
+        }));
+    }
+    if let Some(brightness) = keyboard_brightness(table, "keyboard_backlight")? {
+        actions.push(Action::from(brightness));
+    }
+    if let Some(brightness) = brightness(table, "brightness")? {
+        actions.push(Action::from(BrightnessChange {
+            brightness,
+            fade: duration_ms(table, "fade_ms")?.unwrap_or_default(),
+        }));
+    }
+    let wake_after = match table.get("wake_after_h") {
+        None => None,
+        Some(Value::Integer(h)) if *h > 0 => Some(Duration::from_secs(*h as u64 * 60 * 60)),
+        Some(_) => return Err("`wake_after_h` must be a positive integer".to_string()),
+    };
+    match boolean(table, "hibernate")? {
+        Some(true) => actions.push(Action::from(Hibernate { wake_after })),
+        _ if wake_after.is_some() => {
+            return Err("`wake_after_h` needs `hibernate = true`".to_string())
+        }
+        _ => {}
+    }
+    Ok(actions)
+}
+
+/// Overrides keys of the configuration with the environment variables named
+/// `LID_` and the key in upper case, e.g. `LID_FORMAT` for `format`, with
+/// `__` between a table and its keys, e.g. `LID_LEVELS__LOW` for `low` in
+/// `[levels]`. Values are read as TOML, or as a string if they are not valid
+/// TOML, so that `LID_OUTPUT=pretty` needs no quotes. Variables that do not
+/// name a key are ignored like unknown keys in the file.
+fn apply_env(table: &mut Table) -> Result<(), String>
+{
+    for (name, value) in env::vars_os() {
+        let (Some(name), Some(value)) = (name.to_str(), value.to_str()) else {
+            continue;
+        };
+        let Some(key) = name.strip_prefix("LID_") else {
+            continue;
+        };
+        let key = key.to_lowercase();
+        let mut path = key.split("__").collect::<Vec<_>>();
+        let Some(last) = path.pop().filter(|last| !last.is_empty()) else {
+            continue;
+        };
+        let mut current = &mut *table;
+        for part in path {
+            let entry = current
+                .entry(part.to_string())
+                .or_insert_with(|| Value::Table(Table::new()));
+            current = match entry {
+                Value::Table(table) => table,
+                _ => return Err(format!("{}: `{}` is not a table", name, part)),
+            };
+        }
+        let value = toml::parse(&format!("value = {}", value))
+            .ok()
+            .and_then(|mut table| table.remove("value"))
+            .unwrap_or_else(|| Value::String(value.to_string()));
+        current.insert(last.to_string(), value);
+    }
+    Ok(())
+}
+
+/// Parses an `[[idle]]` table. Its conditions are optional.
+fn parse_idle(table: &Table) -> Result<IdleTimeout, String>
+{
+    Ok(IdleTimeout {
+        timeout: idle_timeout(table)?,
+        conditions: parse_conditions(table)?,
+        command: string(table, "run")?
+            .ok_or_else(|| "missing `run`".to_string())?
+            .to_string(),
+        resume: string(table, "resume")?.map(str::to_string),
+    })
+}
+
+/// Parses a `[[session_idle]]` table, which either suspends or runs a
+/// command.
+fn parse_session_idle(table: &Table) -> Result<SessionIdle, String>
+{
+    let action = match (boolean(table, "suspend")?, string(table, "run")?) {
+        (Some(true), None) => IdleAction::Suspend,
+        (None | Some(false), Some(command)) => IdleAction::Run(command.to_string()),
+        (Some(true), Some(_)) => return Err("`suspend` and `run` are exclusive".to_string()),
+        (_, None) => return Err("missing `run` or `suspend = true`".to_string()),
+    };
+    Ok(SessionIdle {
+        timeout: idle_timeout(table)?,
+        conditions: parse_conditions(table)?,
+        action,
+    })
+}
+
+/// Parses the `timeout_s`, `retries` and `on_failure` keys of hooks, each
+/// with `prefix`, falling back to `default` for those that are missing.
+fn parse_hook_policy(
+    table: &Table,
+    prefix: &str,
+    default: &HookPolicy,
+) -> Result<HookPolicy, String>
+{
+    let key = |name: &str| format!("{}{}", prefix, name);
+    let timeout = match table.get(&key("timeout_s")) {
+        None => default.timeout,
+        Some(Value::Integer(s @ 1..)) => Some(Duration::from_secs(*s as u64)),
+        Some(_) => return Err(format!("`{}` must be a positive integer", key("timeout_s"))),
+    };
+    let retries = match table.get(&key("retries")) {
+        None => default.retries,
+        Some(Value::Integer(retries @ 0..=10)) => *retries as u32,
+        Some(_) => {
+            return Err(format!(
+                "`{}` must be an integer between 0 and 10",
+                key("retries")
+            ))
+        }
+    };
+    let on_failure = match string(table, &key("on_failure"))? {
+        None => default.on_failure,
+        Some("log") => OnFailure::Log,
+        Some("notify") => OnFailure::Notify,
+        Some("abort") => OnFailure::Abort,
+        Some(other) => {
+            return Err(format!(
+                "`{}` must be \"log\", \"notify\" or \"abort\", found \"{}\"",
+                key("on_failure"),
+                other
+            ))
+        }
+    };
+    Ok(HookPolicy {
+        timeout,
+        retries,
+        on_failure,
+    })
+}
+
+/// Parses the `wh` array of the `[energy_budget]` table into µWh.
+fn parse_energy_budgets(table: &Table) -> Result<Vec<u64>, String>
+{
+    let Some(value) = table.get("wh") else {
+        return Ok(Vec::new());
+    };
+    let Value::Array(values) = value else {
+        return Err(format!(
+            "`wh` must be an array, found {}",
+            value.type_name()
+        ));
+    };
+    values
+        .iter()
+        .map(|value| match value {
+            Value::Integer(wh) if *wh > 0 => (*wh as u64)
+                .checked_mul(1_000_000)
+                .ok_or_else(|| format!("{} Wh is out of range", wh)),
+            Value::Float(wh) if *wh > 0.0 => Ok((*wh * 1_000_000.0) as u64),
+            _ => Err("`wh` must hold positive numbers of watt-hours".to_string()),
+        })
+        .collect()
+}
+
+/// Parses the `[cooldown]` table, which maps kinds of actions to seconds.
+fn parse_cooldowns(table: &Table) -> Result<BTreeMap<&'static str, Duration>, String>
+{
+    let mut cooldowns = BTreeMap::new();
+    for kind in cooldown::KINDS {
+        match table.get(*kind) {
+            None => {}
+            Some(Value::Integer(s @ 0..)) => {
+                cooldowns.insert(*kind, Duration::from_secs(*s as u64));
+            }
+            Some(_) => return Err(format!("`{}` must be a non-negative integer", kind)),
+        }
+    }
+    Ok(cooldowns)
+}
+
+/// Reads the `timeout_s` of an idle table.
+fn idle_timeout(table: &Table) -> Result<Duration, String>
+{
+    match table.get("timeout_s") {
+        None => Err("missing `timeout_s`".to_string()),
+        Some(Value::Integer(s)) if *s > 0 => Ok(Duration::from_secs(*s as u64)),
+        Some(Value::Integer(_)) => Err("`timeout_s` must be positive".to_string()),
+        Some(value) => Err(format!(
+            "`timeout_s` must be an integer, found {}",
+            value.type_name()
+        )),
+    }
+}
+
+/// Parses the `[drain]` table. Nothing is monitored without a threshold.
+fn parse_drain(table: &Table) -> Result<Option<DrainLimit>, String>
+{
+    let watts = number(table, "watts")?;
+    let percent_per_hour = number(table, "percent_per_hour")?;
+    if watts.is_none() && percent_per_hour.is_none() {
+        return Ok(None);
+    }
+    let window = match table.get("window_min") {
+        None => Duration::from_secs(10 * 60),
+        Some(Value::Integer(min)) if *min > 0 => Duration::from_secs(*min as u64 * 60),
+        Some(_) => return Err("`window_min` must be a positive integer".to_string()),
+    };
+    Ok(Some(DrainLimit {
+        watts,
+        percent_per_hour,
+        window,
+    }))
+}
+
+/// Parses the `[thermal]` table. Nothing is monitored without `hot_c`.
+fn parse_thermal(table: &Table) -> Result<Option<ThermalLimit>, String>
+{
+    let Some(hot) = number(table, "hot_c")? else {
+        return Ok(None);
+    };
+    let hysteresis = number(table, "hysteresis_c")?.unwrap_or(5.0);
+    Ok(Some(ThermalLimit {
+        zones: strings(table, "zones")?,
+        hot,
+        hysteresis,
+    }))
+}
+
+fn parse_levels(table: &Table) -> Result<LevelThresholds, String>
+{
+    let default = LevelThresholds::default();
+    let levels = LevelThresholds {
+        critical: percentage(table, "critical")?.unwrap_or(default.critical),
+        low: percentage(table, "low")?.unwrap_or(default.low),
+        high: percentage(table, "high")?.unwrap_or(default.high),
+        full: percentage(table, "full")?.unwrap_or(default.full),
+    };
+    if !(levels.critical <= levels.low && levels.low < levels.high && levels.high <= levels.full) {
+        return Err("expected `critical` <= `low` < `high` <= `full`".to_string());
+    }
+    Ok(levels)
+}
+
+fn parse_smoothing(table: &Table) -> Result<Smoothing, String>
+{
+    let window = match table.get("window") {
+        None => 10,
+        Some(Value::Integer(n @ 1..=1000)) => *n as usize,
+        Some(_) => return Err("`window` must be an integer between 1 and 1000".to_string()),
+    };
+    match string(table, "smoothing")? {
+        None | Some("ewma") => Ok(Smoothing::Ewma { window }),
+        Some("median") => Ok(Smoothing::Median { window }),
+        Some(other) => Err(format!(
+            "`smoothing` must be \"ewma\" or \"median\", found \"{}\"",
+            other
+        )),
+    }
+}
+
+fn parse_backend(s: &str) -> Result<Backend, String>
+{
+    match s {
+        "udev" => Ok(Backend::Udev),
+        "sysfs" => Ok(Backend::Sysfs),
+        "upower" => Ok(Backend::UPower),
+        _ => Err(format!(
+            "must be \"udev\", \"sysfs\" or \"upower\", found \"{}\"",
+            s
+        )),
+    }
+}
+
+fn parse_source_kind(s: &str) -> Result<SourceKind, String>
+{
+    match s {
+        "mains" => Ok(SourceKind::Mains),
+        "usb" => Ok(SourceKind::Usb),
+        "wireless" => Ok(SourceKind::Wireless),
+        _ => Err(format!(
+            "must be \"mains\", \"usb\" or \"wireless\", found \"{}\"",
+            s
+        )),
+    }
+}
+
+fn parse_output(s: &str) -> Result<Output, String>
+{
+    match s {
+        "events" => Ok(Output::Events),
+        "pretty" => Ok(Output::Pretty),
+        "xroot" => Ok(Output::Xroot),
+        "polybar" => Ok(Output::Polybar),
+        _ => Err(format!(
+            "must be \"events\", \"pretty\", \"xroot\" or \"polybar\", found \"{}\"",
+            s
+        )),
+    }
+}
+
+/// Parses the `[labels]` table, whose `status` and `level` tables map the
+/// names of statuses and levels to labels.
+fn parse_labels(table: &Table) -> Result<Labels, String>
+{
+    let mut labels = Labels::default();
+    if let Some(statuses) = table_value(table, "status")? {
+        for (status, name) in [
+            (Status::Charging, "charging"),
+            (Status::Discharging, "discharging"),
+            (Status::Full, "full"),
+            (Status::NotCharging, "not_charging"),
+            (Status::Unknown, "unknown"),
+        ] {
+            if let Some(label) = string(statuses, name)? {
+                labels.statuses.push((status, label.to_string()));
+            }
+        }
+    }
+    if let Some(levels) = table_value(table, "level")? {
+        for (level, name) in [
+            (BatteryLevel::Critical, "critical"),
+            (BatteryLevel::Low, "low"),
+            (BatteryLevel::Normal, "normal"),
+            (BatteryLevel::High, "high"),
+            (BatteryLevel::Full, "full"),
+        ] {
+            if let Some(label) = string(levels, name)? {
+                labels.levels.push((level, label.to_string()));
+            }
+        }
+    }
+    Ok(labels)
+}
+
+/// Parses the `[icons]` table. Glyphs that are not given keep the Nerd Font
+/// defaults.
+fn parse_icons(table: &Table) -> Result<Pretty, String>
+{
+    let mut icons = Pretty::default();
+    match table.get("ramp") {
+        None => {}
+        Some(Value::Array(values)) => {
+            icons.ramp = values
+                .iter()
+                .map(|value| match value {
+                    Value::String(icon) => Ok(icon.clone()),
+                    _ => Err("`ramp` must be an array of strings".to_string()),
+                })
+                .collect::<Result<_, _>>()?;
+            if icons.ramp.is_empty() {
+                return Err("`ramp` must not be empty".to_string());
+            }
+        }
+        Some(value) => {
+            return Err(format!(
+                "`ramp` must be an array, found {}",
+                value.type_name()
+            ))
+        }
+    }
+    if let Some(charging) = string(table, "charging")? {
+        icons.charging = charging.to_string();
+    }
+    if let Some(unknown) = string(table, "unknown")? {
+        icons.unknown = unknown.to_string();
+    }
+    if let Some(color) = boolean(table, "color")? {
+        icons.color = color;
+    }
+    Ok(icons)
+}
+
+/// Parses the `[mqtt]` table. Nothing is published unless it is enabled.
+fn parse_mqtt(table: &Table) -> Result<Option<MqttOptions>, String>
+{
+    if boolean(table, "enabled")? != Some(true) {
+        return Ok(None);
+    }
+    let host = string(table, "host")?.ok_or("`host` is missing")?;
+    let port = match table.get("port") {
+        None => 1883,
+        Some(Value::Integer(port @ 1..=65535)) => *port as u16,
+        Some(Value::Integer(_)) => return Err("`port` must be between 1 and 65535".to_string()),
+        Some(value) => {
+            return Err(format!(
+                "`port` must be an integer, found {}",
+                value.type_name()
+            ))
+        }
+    };
+    Ok(Some(MqttOptions {
+        host: host.to_string(),
+        port,
+        username: string(table, "username")?.map(str::to_string),
+        password: string(table, "password")?.map(str::to_string),
+        node_id: match string(table, "node_id")? {
+            Some(node_id) => node_id.to_string(),
+            None => mqtt::hostname(),
+        },
+        topic_prefix: string(table, "topic_prefix")?
+            .unwrap_or("lithiumd")
+            .to_string(),
+        discovery_prefix: string(table, "discovery_prefix")?
+            .unwrap_or("homeassistant")
+            .to_string(),
+    }))
+}
+
+/// Parses the `[speech]` table. Nothing is spoken unless it is enabled.
+fn parse_speech(table: &Table) -> Result<Option<Speech>, String>
+{
+    if boolean(table, "enabled")? != Some(true) {
+        return Ok(None);
+    }
+    let verbosity = match string(table, "verbosity")? {
+        Some("minimal") => Verbosity::Minimal,
+        None | Some("normal") => Verbosity::Normal,
+        Some("verbose") => Verbosity::Verbose,
+        Some(other) => {
+            return Err(format!(
+                "`verbosity` must be \"minimal\", \"normal\" or \"verbose\", found \"{}\"",
+                other
+            ))
+        }
+    };
+    Ok(Some(Speech {
+        command: string(table, "command")?.unwrap_or("spd-say").to_string(),
+        verbosity,
+    }))
+}
+
+/// Parses the `[sound]` table. No sound is played unless it is enabled.
+fn parse_sound(table: &Table) -> Result<Option<Sound>, String>
+{
+    if boolean(table, "enabled")? != Some(true) {
+        return Ok(None);
+    }
+    let file = |key: &str| Ok::<_, String>(string(table, key)?.map(PathBuf::from));
+    Ok(Some(Sound {
+        player: string(table, "player")?.unwrap_or("paplay").to_string(),
+        connected: file("connected")?,
+        disconnected: file("disconnected")?,
+        critical: file("critical")?,
+    }))
+}
+
+/// Parses the `[sway]` table. Nothing is sent unless it is enabled.
+fn parse_sway(table: &Table) -> Result<Option<SwayOptions>, String>
+{
+    if boolean(table, "enabled")? != Some(true) {
+        return Ok(None);
+    }
+    let command = |key: &str| Ok::<_, String>(string(table, key)?.map(str::to_string));
+    let update = match string(table, "update")? {
+        Some(update) => Some(update.parse().map_err(|err| format!("update: {}", err))?),
+        None => None,
+    };
+    Ok(Some(SwayOptions {
+        socket: string(table, "socket")?.map(PathBuf::from),
+        update,
+        connected: command("connected")?,
+        disconnected: command("disconnected")?,
+        critical: command("critical")?,
+    }))
+}
+
+fn parse_notifications(table: &Table) -> Result<Option<NotifyOptions>, String>
+{
+    if boolean(table, "enabled")? != Some(true) {
+        return Ok(None);
+    }
+    let minutes = |key: &str, default: u64| match table.get(key) {
+        None => Ok(default),
+        Some(Value::Integer(min)) if *min >= 0 => Ok(*min as u64),
+        Some(_) => Err(format!("`{}` must be a non-negative integer", key)),
+    };
+    let repeat = minutes("repeat_min", 5)?;
+    let snooze = minutes("snooze_min", 10)?;
+    if snooze == 0 {
+        return Err("`snooze_min` must be at least 1".to_string());
+    }
+    let buttons = match table.get("buttons") {
+        None => vec![Button::Snooze],
+        Some(_) => strings(table, "buttons")?
+            .iter()
+            .map(|button| match button.as_str() {
+                "snooze" => Ok(Button::Snooze),
+                "suspend" => Ok(Button::Suspend),
+                "power_saver" => Ok(Button::PowerSaver),
+                "dismiss" => Ok(Button::Dismiss),
+                other => Err(format!(
+                    "unknown button `{}`, expected `snooze`, `suspend`, `power_saver` or `dismiss`",
+                    other
+                )),
+            })
+            .collect::<Result<_, _>>()?,
+    };
+    Ok(Some(NotifyOptions {
+        repeat: Some(Duration::from_secs(repeat * 60)).filter(|_| repeat > 0),
+        snooze: Duration::from_secs(snooze * 60),
+        buttons,
+    }))
+}
+
+/// Parses the `[lid]` table. Unless it is enabled, logind keeps handling the
+/// lid.
+fn parse_lid(table: &Table) -> Result<Option<LidPolicy>, String>
+{
+    if boolean(table, "enabled")? != Some(true) {
+        return Ok(None);
+    }
+    let delay = match table.get("suspend_delay_s") {
+        None => Duration::ZERO,
+        Some(Value::Integer(s)) if *s >= 0 => Duration::from_secs(*s as u64),
+        Some(_) => return Err("`suspend_delay_s` must be a non-negative integer".to_string()),
+    };
+    Ok(Some(LidPolicy {
+        lock: parse_screen_lock(table)?,
+        suspend: boolean(table, "suspend")?.unwrap_or(true),
+        conditions: parse_conditions(table)?,
+        delay,
+        suspend_when_docked: boolean(table, "suspend_when_docked")?.unwrap_or(false),
+    }))
+}
+
+fn parse_power_button(table: &Table) -> Result<Option<ButtonAction>, String>
+{
+    if boolean(table, "enabled")? != Some(true) {
+        return Ok(None);
+    }
+    let action = match string(table, "action")? {
+        None | Some("suspend") => ButtonAction::Suspend,
+        Some("ignore") => ButtonAction::Ignore,
+        Some("run") => match string(table, "run")? {
+            Some(command) => ButtonAction::Run(command.to_string()),
+            None => return Err("`action = \"run\"` needs a `run` command".to_string()),
+        },
+        Some(action) => {
+            return Err(format!(
+                "unknown action `{}`, expected `suspend`, `ignore` or `run`",
+                action
+            ))
+        }
+    };
+    Ok(Some(action))
+}
+
+fn parse_screen_lock(table: &Table) -> Result<Option<ScreenLock>, String>
+{
+    let Some(command) = string(table, "lock")? else {
+        return Ok(None);
+    };
+    let check = match string(table, "verify")? {
+        None | Some("locked-hint") => LockCheck::LockedHint,
+        // The process is named after the locker, e.g. `swaylock` for
+        // `swaylock -f`.
+        Some("process") => {
+            let program = command.split_whitespace().next().unwrap_or_default();
+            LockCheck::Process(program.rsplit('/').next().unwrap_or(program).to_string())
+        }
+        Some(other) => {
+            return Err(format!(
+                "`verify` must be \"locked-hint\" or \"process\", found \"{}\"",
+                other
+            ))
+        }
+    };
+    let on_failure = match string(table, "on_lock_failure")? {
+        None | Some("abort") => LockFailure::Abort,
+        Some("suspend") => LockFailure::Suspend,
+        Some(other) => {
+            return Err(format!(
+                "`on_lock_failure` must be \"abort\" or \"suspend\", found \"{}\"",
+                other
+            ))
+        }
+    };
+    let retries = match table.get("retries") {
+        None => 1,
+        Some(Value::Integer(retries @ 0..=10)) => *retries as u32,
+        Some(_) => return Err("`retries` must be an integer between 0 and 10".to_string()),
+    };
+    Ok(Some(ScreenLock {
+        command: command.to_string(),
+        check,
+        timeout: duration_ms(table, "verify_timeout_ms")?.unwrap_or(Duration::from_secs(3)),
+        retries,
+        on_failure,
+    }))
+}
+
+/// Parses the conditions shared by `[[rule]]`, `[[idle]]`,
+/// `[[session_idle]]` and `[lid]` tables.
+fn parse_conditions(table: &Table) -> Result<Vec<Condition>, String>
+{
+    let mut conditions = Vec::new();
+    match string(table, "on")? {
+        None => {}
+        Some("battery") => conditions.push(Condition::OnBattery),
+        Some("ac") => conditions.push(Condition::OnAc),
+        Some(other) => {
+            return Err(format!(
+                "`on` must be \"battery\" or \"ac\", found \"{}\"",
+                other
+            ))
+        }
+    }
+    if let Some(percent) = percentage(table, "capacity_below")? {
+        conditions.push(Condition::CapacityBelow(percent));
+    }
+    if let Some(percent) = percentage(table, "capacity_above")? {
+        conditions.push(Condition::CapacityAbove(percent));
+    }
+    Ok(conditions)
+}
+
+/// Reads a non-negative integer number of milliseconds.
+fn duration_ms(table: &Table, key: &str) -> Result<Option<Duration>, String>
+{
+    match table.get(key) {
+        None => Ok(None),
+        Some(Value::Integer(ms)) if *ms >= 0 => Ok(Some(Duration::from_millis(*ms as u64))),
+        Some(Value::Integer(_)) => Err(format!("`{}` must not be negative", key)),
+        Some(value) => Err(format!(
+            "`{}` must be an integer, found {}",
+            key,
+            value.type_name()
+        )),
+    }
+}
+
+fn boolean(table: &Table, key: &str) -> Result<Option<bool>, String>
+{
+    match table.get(key) {
+        None => Ok(None),
+        Some(Value::Boolean(value)) => Ok(Some(*value)),
+        Some(value) => Err(format!(
+            "`{}` must be a boolean, found {}",
+            key,
+            value.type_name()
+        )),
+    }
+}
+
+/// Reads an array of strings, which is empty if the key is missing.
+fn strings(table: &Table, key: &str) -> Result<Vec<String>, String>
+{
+    match table.get(key) {
+        None => Ok(Vec::new()),
+        Some(Value::Array(values)) => values
+            .iter()
+            .map(|value| match value {
+                Value::String(value) => Ok(value.clone()),
+                _ => Err(format!("`{}` must be an array of strings", key)),
+            })
+            .collect(),
+        Some(value) => Err(format!(
+            "`{}` must be an array, found {}",
+            key,
+            value.type_name()
+        )),
+    }
+}
+
+/// Reads an integer between 0 and 100.
+fn percentage(table: &Table, key: &str) -> Result<Option<u8>, String>
+{
+    match table.get(key) {
+        None => Ok(None),
+        Some(Value::Integer(value @ 0..=100)) => Ok(Some(*value as u8)),
+        Some(Value::Integer(_)) => Err(format!("`{}` must be between 0 and 100", key)),
+        Some(value) => Err(format!(
+            "`{}` must be an integer, found {}",
+            key,
+            value.type_name()
+        )),
+    }
+}
+
+fn table_value<'a>(table: &'a Table, key: &str) -> Result<Option<&'a Table>, String>
+{
+    match table.get(key) {
+        None => Ok(None),
+        Some(Value::Table(table)) => Ok(Some(table)),
+        Some(value) => Err(format!(
+            "`{}` must be a table, found {}",
+            key,
+            value.type_name()
+        )),
+    }
+}
+
+/// Reads a positive number, which may be written as an integer or a float.
+fn number(table: &Table, key: &str) -> Result<Option<f64>, String>
+{
+    let number = match table.get(key) {
+        None => return Ok(None),
+        Some(Value::Integer(value)) => *value as f64,
+        Some(Value::Float(value)) => *value,
+        Some(value) => {
+            return Err(format!(
+                "`{}` must be a number, found {}",
+                key,
+                value.type_name()
+            ))
+        }
+    };
+    if number <= 0.0 {
+        return Err(format!("`{}` must be positive", key));
+    }
+    Ok(Some(number))
+}
+
+fn string<'a>(table: &'a Table, key: &str) -> Result<Option<&'a str>, String>
+{
+    match table.get(key) {
+        None => Ok(None),
+        Some(Value::String(value)) => Ok(Some(value)),
+        Some(value) => Err(format!(
+            "`{}` must be a string, found {}",
+            key,
+            value.type_name()
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn energy_budgets_in_range()
+    {
+        let budgets = |source: &str| parse_energy_budgets(&toml::parse(source).unwrap());
+        assert_eq!(budgets("wh = [20, 7.5]"), Ok(vec![20_000_000, 7_500_000]));
+        assert_eq!(budgets(""), Ok(Vec::new()));
+        assert_eq!(
+            budgets("wh = [9223372036854775807]"),
+            Err("9223372036854775807 Wh is out of range".to_string())
+        );
+        assert!(budgets("wh = [0]").is_err());
+    }
+}
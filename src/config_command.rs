@@ -0,0 +1,427 @@
+use std::env;
+use std::fs;
+use std::io;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+use std::path::PathBuf;
+
+use lithiumd::rules::Action;
+
+use crate::config::Config;
+use crate::cooldown;
+use crate::idle_policy::IdleAction;
+use crate::power_button::ButtonAction;
+use crate::toml;
+use crate::toml::Table;
+use crate::toml::Value;
+
+/// The README documents the configuration file in full, so `config init`
+/// writes its example with every setting commented out.
+const README: &str = include_str!("../README.md");
+
+const CONDITIONS: &[&str] = &["on", "capacity_below", "capacity_above"];
+const ACTIONS: &[&str] = &[
+    "run",
+    "power_profile",
+    "wifi_power_save",
+    "usb_autosuspend",
+    "usb_deny",
+    "usb_input",
+    "keyboard_backlight",
+    "brightness",
+    "fade_ms",
+    "hibernate",
+    "wake_after_h",
+    "run_timeout_s",
+    "run_retries",
+    "run_on_failure",
+];
+const POLICIES: &[&str] = &["battery", "ac"];
+
+/// Commands that are built into `sh` rather than found in `PATH`.
+const BUILTINS: &[&str] = &[
+    "exec", "cd", "echo", "test", "[", "true", "false", ":", "export", "command", "printf",
+];
+
+/// Returns the keys a table of the configuration may hold, by its path,
+/// e.g. `cpufreq.battery`, or `None` if there is no such table.
+fn known_keys(path: &str) -> Option<Vec<&'static str>>
+{
+    let keys: &[&[&str]] = match path {
+        "" => &[&[
+            "debounce_ms",
+            "receive_buffer_kb",
+            "refresh_interval_s",
+            "backend",
+            "capacity_step",
+            "include_device_scope",
+            "battery",
+            "adapter",
+            "sources",
+            "env_file",
+            "format",
+            "output",
+        ]],
+        "cooldown" => &[cooldown::KINDS],
+        "energy_budget" => &[&["wh", "notify", "run"]],
+        "hooks" => &[&["delay_sleep", "user", "timeout_s", "retries", "on_failure"]],
+        "icons" => &[&["ramp", "charging", "unknown", "color"]],
+        "labels" | "cpufreq" | "pcie" => &[],
+        "labels.status" => &[&["charging", "discharging", "full", "not_charging", "unknown"]],
+        "labels.level" => &[&["critical", "low", "normal", "high", "full"]],
+        "tray" | "bluetooth" | "usb_pd" | "fans" | "broadcast" => &[&["enabled"]],
+        "mqtt" => &[&[
+            "enabled",
+            "host",
+            "port",
+            "username",
+            "password",
+            "node_id",
+            "topic_prefix",
+            "discovery_prefix",
+        ]],
+        "remote" => &[&["token"]],
+        "dbus" => &[&["enabled", "bus"]],
+        "peripherals" => &[&["enabled", "low_capacity"]],
+        "history" => &[&["enabled", "path"]],
+        "dock" => &[&["enabled", "docked", "undocked"]],
+        "weak_charger" => &[&["enabled", "min_watts", "run"]],
+        "power_profiles" | "keyboard_backlight" => &[POLICIES],
+        "backlight" => &[POLICIES, &["low", "low_capacity", "fade_ms"]],
+        "cpufreq.battery" | "cpufreq.ac" => &[&["governor", "energy_performance_preference"]],
+        "pcie.battery" | "pcie.ac" => &[&["runtime_pm", "aspm"]],
+        "wifi" => &[POLICIES, &["interfaces"]],
+        "usb_autosuspend" => &[&["enabled", "deny", "input"]],
+        "speech" => &[&["enabled", "command", "verbosity"]],
+        "sound" => &[&["enabled", "player", "connected", "disconnected", "critical"]],
+        "sway" => &[&[
+            "enabled",
+            "socket",
+            "update",
+            "connected",
+            "disconnected",
+            "critical",
+        ]],
+        "notifications" => &[&["enabled", "repeat_min", "snooze_min", "buttons"]],
+        "countdown" => &[&["enabled", "seconds"]],
+        "charged" => &[&["enabled", "repeat_min"]],
+        "levels" => &[&["critical", "low", "high", "full"]],
+        "estimate" => &[&["smoothing", "window"]],
+        "drain" => &[&["watts", "percent_per_hour", "window_min", "run"]],
+        "thermal" => &[&[
+            "hot_c",
+            "hysteresis_c",
+            "zones",
+            "hot",
+            "cooled",
+            "throttling",
+        ]],
+        "lid" => &[
+            CONDITIONS,
+            &[
+                "enabled",
+                "suspend",
+                "suspend_delay_s",
+                "suspend_when_docked",
+                "lock",
+                "verify",
+                "verify_timeout_ms",
+                "retries",
+                "on_lock_failure",
+            ],
+        ],
+        "tablet_mode" => &[&["enabled", "tablet", "laptop"]],
+        "power_button" => &[&["enabled", "action", "run"]],
+        "rule" => &[CONDITIONS, ACTIONS, &["when", "inhibitable", "hysteresis"]],
+        "idle" => &[CONDITIONS, &["timeout_s", "run", "resume"]],
+        "session_idle" => &[CONDITIONS, &["timeout_s", "run", "suspend"]],
+        _ => return None,
+    };
+    Some(keys.concat())
+}
+
+/// Writes the commented configuration to `path`, or to the user's
+/// configuration file if `None`. An existing file is left alone.
+pub fn init(path: Option<PathBuf>) -> io::Result<PathBuf>
+{
+    let path = path.or_else(Config::user_path).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            "neither XDG_CONFIG_HOME nor HOME is set",
+        )
+    })?;
+    if path.exists() {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("{} already exists", path.display()),
+        ));
+    }
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(&path, default_config())?;
+    Ok(path)
+}
+
+/// Returns the example of the README with every setting commented out, so
+/// that it changes nothing until lines are uncommented.
+fn default_config() -> String
+{
+    let example = README
+        .split_once("## Configuration")
+        .and_then(|(_, rest)| rest.split_once("```toml\n"))
+        .and_then(|(_, rest)| rest.split_once("```"))
+        .map_or("", |(example, _)| example);
+    let mut config = "# Configuration of lithiumd. Uncomment what you want to change; every\n\
+         # setting is shown with its default or an example value.\n\n"
+        .to_string();
+    for line in example.lines() {
+        if !line.is_empty() && !line.starts_with('#') {
+            config.push('#');
+        }
+        config += line;
+        config.push('\n');
+    }
+    config
+}
+
+/// Checks the configuration file at `path`, or the one the daemon would
+/// read, and prints every problem with its line. Returns whether there were
+/// none.
+pub fn check(path: Option<PathBuf>) -> io::Result<bool>
+{
+    let Some(path) = path.or_else(Config::default_path) else {
+        println!("No configuration file, the defaults are used");
+        return Ok(true);
+    };
+    let contents = fs::read_to_string(&path)
+        .map_err(|err| io::Error::new(err.kind(), format!("{}: {}", path.display(), err)))?;
+    let print = |line: Option<usize>, message: &str| match line {
+        Some(line) => println!("{}:{}: {}", path.display(), line, message),
+        None => println!("{}: {}", path.display(), message),
+    };
+    let table = match toml::parse(&contents) {
+        Ok(table) => table,
+        Err(err) => {
+            print(Some(err.line), &err.message);
+            return Ok(false);
+        }
+    };
+    let mut ok = true;
+    for (table_path, key) in unknown_keys(&table, "") {
+        ok = false;
+        let tables = [table_path.as_str()].into_iter().filter(|t| !t.is_empty());
+        let line = locate(&contents, &tables.collect::<Vec<_>>(), Some(&key));
+        match table_path.as_str() {
+            "" => print(line, &format!("unknown key `{}`", key)),
+            table => print(line, &format!("unknown key `{}` in [{}]", key, table)),
+        }
+    }
+    let config = match Config::from_table(&table) {
+        Ok(config) => config,
+        Err(err) => {
+            let (tables, message) = split_context(&err);
+            let key = message
+                .split('`')
+                .nth(1)
+                .filter(|key| !key.contains(' ') && !key.is_empty());
+            print(locate(&contents, &tables, key), &err);
+            return Ok(false);
+        }
+    };
+    for command in hook_commands(&config) {
+        let Some(program) = program(command) else {
+            continue;
+        };
+        if !is_executable(program) {
+            ok = false;
+            let line = contents
+                .lines()
+                .position(|line| line.contains(command))
+                .map(|i| i + 1);
+            print(
+                line,
+                &format!("`{}` is not found or not executable", program),
+            );
+        }
+    }
+    if ok {
+        println!("{}: ok", path.display());
+    }
+    Ok(ok)
+}
+
+/// Returns the path of the tables and the keys in `table` that are not known,
+/// in the order of the file's tables.
+fn unknown_keys(table: &Table, path: &str) -> Vec<(String, String)>
+{
+    let known = known_keys(path).unwrap_or_default();
+    let mut unknown = Vec::new();
+    for (key, value) in table {
+        let child = match path {
+            "" => key.clone(),
+            path => format!("{}.{}", path, key),
+        };
+        let tables = match value {
+            Value::Table(table) => vec![table],
+            Value::Array(values) => values
+                .iter()
+                .filter_map(|value| match value {
+                    Value::Table(table) => Some(table),
+                    _ => None,
+                })
+                .collect(),
+            _ => Vec::new(),
+        };
+        if !tables.is_empty() && known_keys(&child).is_some() {
+            for table in tables {
+                unknown.extend(unknown_keys(table, &child));
+            }
+        } else if !known.contains(&key.as_str()) {
+            unknown.push((path.to_string(), key.clone()));
+        }
+    }
+    unknown
+}
+
+/// Splits an error of [`Config::from_table`] into the tables it names, e.g.
+/// `rule 2` in `rule 2: missing run`, and the rest of the message.
+fn split_context(err: &str) -> (Vec<&str>, &str)
+{
+    let mut tables = Vec::new();
+    let mut rest = err;
+    while let Some((table, message)) = rest.split_once(": ") {
+        let (name, index) = table.split_once(' ').unwrap_or((table, "1"));
+        let is_table = name.chars().all(|c| c.is_ascii_lowercase() || c == '_')
+            && index.chars().all(|c| c.is_ascii_digit());
+        if !is_table || known_keys(name).is_none() {
+            break;
+        }
+        tables.push(table);
+        rest = message;
+    }
+    (tables, rest)
+}
+
+/// Returns the line of `key` in the table named by `tables`, e.g. `["rule
+/// 2"]` for the second `[[rule]]`, or of the table's header if the key is
+/// not in it. Subtables count as part of their table.
+fn locate(contents: &str, tables: &[&str], key: Option<&str>) -> Option<usize>
+{
+    let target = tables
+        .iter()
+        .map(|table| table.split_once(' ').map_or(*table, |(name, _)| name))
+        .collect::<Vec<_>>()
+        .join(".");
+    let index = tables
+        .last()
+        .and_then(|table| table.split_once(' '))
+        .and_then(|(_, index)| index.parse::<usize>().ok())
+        .unwrap_or(1);
+    let mut current = String::new();
+    let mut seen = 0;
+    let mut header = None;
+    for (i, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if let Some(name) = line.strip_prefix("[[").and_then(|l| l.split_once("]]")) {
+            current = name.0.trim().to_string();
+            if current == target {
+                seen += 1;
+            }
+        } else if let Some(name) = line.strip_prefix('[').and_then(|l| l.split_once(']')) {
+            current = name.0.trim().to_string();
+        }
+        let inside = !target.is_empty()
+            && (current == target || current.starts_with(&format!("{}.", target)))
+            && (seen == index || seen == 0);
+        let at_root = target.is_empty() && current.is_empty();
+        if !inside && !at_root {
+            continue;
+        }
+        if line.starts_with('[') {
+            header.get_or_insert(i + 1);
+            continue;
+        }
+        let found = key.is_some_and(|key| {
+            line.split_once('=')
+                .is_some_and(|(name, _)| name.trim().trim_matches('"') == key)
+        });
+        if found {
+            return Some(i + 1);
+        }
+    }
+    header
+}
+
+/// Returns the commands the configuration runs.
+fn hook_commands(config: &Config) -> Vec<&str>
+{
+    let mut commands = Vec::new();
+    for rule in &config.rules {
+        for action in rule.actions() {
+            if let Action::RunHook(hook) = action {
+                commands.push(hook.command.as_str());
+            }
+        }
+    }
+    for idle in &config.idle {
+        commands.push(idle.command.as_str());
+        commands.extend(idle.resume.as_deref());
+    }
+    for idle in &config.session_idle {
+        if let IdleAction::Run(command) = &idle.action {
+            commands.push(command.as_str());
+        }
+    }
+    if let Some(ButtonAction::Run(command)) = &config.power_button {
+        commands.push(command.as_str());
+    }
+    if let Some(lock) = config.lid.as_ref().and_then(|lid| lid.lock.as_ref()) {
+        commands.push(lock.command.as_str());
+    }
+    if let Some(speech) = &config.speech {
+        commands.push(speech.command.as_str());
+    }
+    if let Some(sound) = &config.sound {
+        commands.push(sound.player.as_str());
+    }
+    commands.extend(
+        [
+            &config.docked_hook,
+            &config.undocked_hook,
+            &config.weak_charger_hook,
+            &config.tablet_hook,
+            &config.laptop_hook,
+            &config.drain_hook,
+            &config.hot_hook,
+            &config.cooled_hook,
+        ]
+        .into_iter()
+        .flatten()
+        .map(String::as_str),
+    );
+    commands
+}
+
+/// Returns the program a shell command starts, skipping variable
+/// assignments, or `None` for shell builtins.
+fn program(command: &str) -> Option<&str>
+{
+    let program = command
+        .split_whitespace()
+        .find(|word| !word.contains('=') || word.starts_with('='))?;
+    (!BUILTINS.contains(&program)).then_some(program)
+}
+
+fn is_executable(program: &str) -> bool
+{
+    let executable = |path: &Path| {
+        fs::metadata(path)
+            .is_ok_and(|meta| meta.is_file() && meta.permissions().mode() & 0o111 != 0)
+    };
+    if program.contains('/') {
+        return executable(Path::new(program));
+    }
+    env::var_os("PATH")
+        .is_some_and(|paths| env::split_paths(&paths).any(|dir| executable(&dir.join(program))))
+}
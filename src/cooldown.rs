@@ -0,0 +1,166 @@
+use std::collections::BTreeMap;
+use std::time::Duration;
+use std::time::Instant;
+
+use lithiumd::rules::Action;
+
+/// The kinds of actions, by the keys that take them in rules. The low battery
+/// and charged notifications are not actions and do not cool down; they
+/// have their own `repeat_min` and snooze instead.
+pub const KINDS: &[&str] = &[
+    "run",
+    "power_profile",
+    "cpufreq",
+    "pcie",
+    "wifi_power_save",
+    "usb_autosuspend",
+    "keyboard_backlight",
+    "brightness",
+    "hibernate",
+    "suspend",
+];
+
+/// Takes each kind of action at most once per its cooldown, so that a
+/// charger that keeps connecting and disconnecting does not switch the
+/// governor back and forth. An action that comes in during the cooldown is
+/// put off until it ends, replacing one of the same kind that already waits,
+/// so that the last one wins. Suspending and hibernating are dropped instead,
+/// since the rule that asked for them may no longer match once the cooldown
+/// ends. Hooks cool down by their command.
+#[derive(Debug, Default)]
+pub struct Cooldowns
+{
+    periods: BTreeMap<&'static str, Duration>,
+    /// When each kind of action was last taken, by [`key`].
+    taken: BTreeMap<String, Instant>,
+    /// Actions put off until their cooldown ends, by [`key`].
+    deferred: BTreeMap<String, Action>,
+}
+
+impl Cooldowns
+{
+    /// Cools down the kinds in `periods`, one of [`KINDS`] each.
+    pub fn new(periods: BTreeMap<&'static str, Duration>) -> Self
+    {
+        Self {
+            periods,
+            ..Self::default()
+        }
+    }
+
+    /// Returns whether `action` may be taken now, and records that it is.
+    /// Otherwise it is put off, see [`Cooldowns::due`], unless it suspends or
+    /// hibernates.
+    pub fn admit(&mut self, action: &Action, now: Instant) -> bool
+    {
+        let Some(period) = self.periods.get(kind(action)) else {
+            return true;
+        };
+        let key = key(action);
+        let cooling = self
+            .taken
+            .get(&key)
+            .is_some_and(|taken| now < *taken + *period);
+        if cooling {
+            if !matches!(action, Action::Suspend | Action::Hibernate(_)) {
+                self.deferred.insert(key, action.clone());
+            }
+            return false;
+        }
+        self.deferred.remove(&key);
+        self.taken.insert(key, now);
+        true
+    }
+
+    /// Returns the actions that were put off and whose cooldown ended, and
+    /// records that they are taken.
+    pub fn due(&mut self, now: Instant) -> Vec<Action>
+    {
+        let due = self
+            .deferred
+            .keys()
+            .filter(|key| self.ends(key).is_some_and(|end| now >= end))
+            .cloned()
+            .collect::<Vec<_>>();
+        due.into_iter()
+            .filter_map(|key| {
+                let action = self.deferred.remove(&key)?;
+                self.taken.insert(key, now);
+                Some(action)
+            })
+            .collect()
+    }
+
+    /// Returns how long until the first action that was put off is due.
+    pub fn timeout(&self, now: Instant) -> Option<Duration>
+    {
+        self.deferred
+            .keys()
+            .filter_map(|key| self.ends(key))
+            .min()
+            .map(|end| end.saturating_duration_since(now))
+    }
+
+    /// Returns when the cooldown of the actions of `key` ends.
+    fn ends(&self, key: &str) -> Option<Instant>
+    {
+        let action = self.deferred.get(key)?;
+        Some(*self.taken.get(key)? + *self.periods.get(kind(action))?)
+    }
+}
+
+fn kind(action: &Action) -> &'static str
+{
+    match action {
+        Action::RunHook(_) => "run",
+        Action::SetPowerProfile(_) => "power_profile",
+        Action::SetCpuPolicy(_) => "cpufreq",
+        Action::SetPciePolicy(_) => "pcie",
+        Action::SetWifiPowerSave(_) => "wifi_power_save",
+        Action::SetUsbAutosuspend(_) => "usb_autosuspend",
+        Action::SetKeyboardBacklight(_) => "keyboard_backlight",
+        Action::SetBrightness(_) => "brightness",
+        Action::Hibernate(_) => "hibernate",
+        Action::Suspend => "suspend",
+    }
+}
+
+/// Returns what `action` cools down by: its kind, and the command of hooks.
+fn key(action: &Action) -> String
+{
+    match action {
+        Action::RunHook(hook) => format!("run {}", hook.command),
+        action => kind(action).to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn puts_off_actions_but_drops_suspending()
+    {
+        let periods = [
+            ("power_profile", Duration::from_secs(30)),
+            ("suspend", Duration::from_secs(30)),
+        ];
+        let mut cooldowns = Cooldowns::new(periods.into_iter().collect());
+        let start = Instant::now();
+        let profile = |name: &str| Action::SetPowerProfile(name.to_string());
+
+        assert!(cooldowns.admit(&profile("power-saver"), start));
+        assert!(cooldowns.admit(&Action::Suspend, start));
+        let later = start + Duration::from_secs(10);
+        assert!(!cooldowns.admit(&profile("balanced"), later));
+        assert!(!cooldowns.admit(&profile("performance"), later));
+        assert!(!cooldowns.admit(&Action::Suspend, later));
+        assert_eq!(cooldowns.timeout(later), Some(Duration::from_secs(20)));
+
+        assert!(cooldowns.due(later).is_empty());
+        let end = start + Duration::from_secs(30);
+        assert_eq!(cooldowns.due(end), [profile("performance")]);
+        assert_eq!(cooldowns.timeout(end), None);
+    }
+}
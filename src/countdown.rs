@@ -0,0 +1,225 @@
+use std::io;
+use std::time::Duration;
+use std::time::Instant;
+
+use lithiumd::rules::Action;
+use lithiumd::Notification;
+use lithiumd::NotificationEvent;
+use lithiumd::Notifications;
+use lithiumd::Snapshot;
+use lithiumd::Urgency;
+
+/// Puts off suspending and hibernating by rules for a while, showing a
+/// notification that counts down the seconds with a button to cancel.
+/// Plugging in the charger cancels as well.
+///
+/// It is a small state machine driven by the event loop: [`Countdown::start`]
+/// arms it, [`Countdown::update`] ticks it on every wakeup until it returns
+/// the action to take, and [`Countdown::timeout`] tells when the next tick is
+/// due.
+pub struct Countdown
+{
+    duration: Duration,
+    /// Connection to the notification server, if there is one. Without it,
+    /// only the charger cancels.
+    notifications: Option<Notifications>,
+    pending: Option<Pending>,
+}
+
+/// A countdown that is running.
+struct Pending
+{
+    action: Action,
+    deadline: Instant,
+    /// The notification showing it, if it could be shown.
+    id: Option<u32>,
+    /// Seconds left that the notification shows.
+    shown: u64,
+}
+
+impl Countdown
+{
+    /// Counts down for `duration`. The notification server does not have to
+    /// be running, which only leaves out the notification.
+    pub fn new(duration: Duration) -> Self
+    {
+        let notifications = Notifications::new()
+            .inspect_err(|err| {
+                eprintln!(
+                    "Failed to connect to the notification server, critical actions cannot be canceled: {}",
+                    err
+                );
+            })
+            .ok();
+        Self {
+            duration,
+            notifications,
+            pending: None,
+        }
+    }
+
+    pub fn duration(&self) -> Duration
+    {
+        self.duration
+    }
+
+    pub fn notifications(&mut self) -> Option<&mut Notifications>
+    {
+        self.notifications.as_mut()
+    }
+
+    /// Starts counting down to `action`, unless a countdown is running
+    /// already, which then keeps its deadline.
+    pub fn start(&mut self, action: Action, now: Instant)
+    {
+        if self.pending.is_some() {
+            return;
+        }
+        println!(
+            "{} in {}s unless canceled",
+            verb(&action),
+            self.duration.as_secs()
+        );
+        self.pending = Some(Pending {
+            action,
+            deadline: now + self.duration,
+            id: None,
+            shown: 0,
+        });
+        self.show(now);
+    }
+
+    /// Ticks the countdown. Returns the action once the deadline passed,
+    /// and cancels it if the system runs on AC by now.
+    pub fn update(&mut self, snapshot: &Snapshot, now: Instant) -> Option<Action>
+    {
+        let pending = self.pending.as_ref()?;
+        if snapshot.status.is_on_ac() {
+            println!(
+                "{} canceled, the charger was plugged in",
+                verb(&pending.action)
+            );
+            self.cancel();
+            return None;
+        }
+        if now >= pending.deadline {
+            let pending = self.pending.take()?;
+            self.close(pending.id);
+            return Some(pending.action);
+        }
+        self.show(now);
+        None
+    }
+
+    /// Reads the buttons clicked since the last call and cancels the
+    /// countdown if its button was.
+    pub fn process(&mut self) -> io::Result<()>
+    {
+        let Some(notifications) = &mut self.notifications else {
+            return Ok(());
+        };
+        let events = notifications.process();
+        let events = match events {
+            Ok(events) => events,
+            Err(err) => {
+                self.notifications = None;
+                return Err(err);
+            }
+        };
+        for event in events {
+            let Some(pending) = &mut self.pending else {
+                continue;
+            };
+            match event {
+                NotificationEvent::Action { id, key }
+                    if Some(id) == pending.id && key == "cancel" =>
+                {
+                    println!("{} canceled", verb(&pending.action));
+                    self.cancel();
+                }
+                // Dismissing the notification does not cancel, since it
+                // may just have expired.
+                NotificationEvent::Closed { id } if Some(id) == pending.id => pending.id = None,
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns how long until the notification has to count down the next
+    /// second, or the deadline passes.
+    pub fn timeout(&self, now: Instant) -> Option<Duration>
+    {
+        let pending = self.pending.as_ref()?;
+        let left = pending.deadline.saturating_duration_since(now);
+        let tick = Duration::from_nanos((left.as_nanos() % 1_000_000_000) as u64);
+        Some(if tick.is_zero() {
+            left.min(Duration::from_secs(1))
+        } else {
+            tick
+        })
+    }
+
+    fn cancel(&mut self)
+    {
+        if let Some(pending) = self.pending.take() {
+            self.close(pending.id);
+        }
+    }
+
+    /// Shows the notification, or updates it if the seconds left changed.
+    fn show(&mut self, now: Instant)
+    {
+        let (Some(notifications), Some(pending)) = (&mut self.notifications, &mut self.pending)
+        else {
+            return;
+        };
+        // Rounded up, so that it never shows 0 before the deadline.
+        let left = pending
+            .deadline
+            .saturating_duration_since(now)
+            .as_millis()
+            .div_ceil(1000) as u64;
+        if pending.id.is_some() && left == pending.shown {
+            return;
+        }
+        let notification = Notification {
+            summary: format!("{} in {}s", verb(&pending.action), left),
+            body: "The battery is critical. Plug in the charger or cancel to stay awake."
+                .to_string(),
+            icon: "battery-caution-symbolic".to_string(),
+            urgency: Urgency::Critical,
+            progress: Some((left * 100 / self.duration.as_secs().max(1)).min(100) as u8),
+            actions: vec![("cancel".to_string(), "Cancel".to_string())],
+        };
+        match notifications.notify(pending.id.unwrap_or(0), &notification) {
+            Ok(id) => {
+                pending.id = Some(id);
+                pending.shown = left;
+            }
+            Err(err) => {
+                eprintln!("Lost the connection to the notification server: {}", err);
+                self.notifications = None;
+            }
+        }
+    }
+
+    fn close(&mut self, id: Option<u32>)
+    {
+        if let (Some(notifications), Some(id)) = (&mut self.notifications, id) {
+            if let Err(err) = notifications.close(id) {
+                eprintln!("Failed to close the countdown notification: {}", err);
+            }
+        }
+    }
+}
+
+/// Returns what suspending or hibernating is called at the start of a
+/// message.
+pub fn verb(action: &Action) -> &'static str
+{
+    match action {
+        Action::Hibernate(_) => "Hibernating",
+        _ => "Suspending",
+    }
+}
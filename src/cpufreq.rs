@@ -0,0 +1,45 @@
+use std::fs;
+use std::io;
+
+use crate::privileges::write;
+
+const POLICIES: &str = "/sys/devices/system/cpu/cpufreq";
+
+/// Frequency policy for all CPUs, applied through the cpufreq policies in
+/// sysfs. Attributes that are `None` are left alone.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CpuPolicy
+{
+    /// Value of `scaling_governor`, e.g. `powersave` or `performance`.
+    pub governor: Option<String>,
+    /// Value of `energy_performance_preference`, e.g. `power` or
+    /// `balance_performance`. Only intel_pstate and amd-pstate in active
+    /// mode have it.
+    pub energy_performance_preference: Option<String>,
+}
+
+impl CpuPolicy
+{
+    #[must_use]
+    pub fn is_empty(&self) -> bool
+    {
+        self.governor.is_none() && self.energy_performance_preference.is_none()
+    }
+
+    /// Writes the policy to every cpufreq policy. The governor is written
+    /// first, since intel_pstate rejects energy preferences other than
+    /// `performance` while the `performance` governor is active.
+    pub fn apply(&self) -> io::Result<()>
+    {
+        for entry in fs::read_dir(POLICIES)? {
+            let dir = entry?.path();
+            if let Some(governor) = &self.governor {
+                write(&dir.join("scaling_governor"), governor)?;
+            }
+            if let Some(epp) = &self.energy_performance_preference {
+                write(&dir.join("energy_performance_preference"), epp)?;
+            }
+        }
+        Ok(())
+    }
+}
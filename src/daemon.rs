@@ -0,0 +1,2380 @@
+use std::collections::BTreeMap;
+use std::io;
+use std::os::fd::OwnedFd;
+use std::path::PathBuf;
+use std::time::Duration;
+use std::time::Instant;
+use std::time::SystemTime;
+
+use lithiumd::rtc;
+use lithiumd::rules::Action;
+use lithiumd::rules::Condition;
+use lithiumd::rules::Hibernate;
+use lithiumd::rules::RuleEngine;
+use lithiumd::rules::RunHook;
+use lithiumd::BacklightFader;
+use lithiumd::Bluez;
+use lithiumd::Displays;
+use lithiumd::Fields;
+use lithiumd::HealthTrend;
+use lithiumd::History;
+use lithiumd::HookOutcome;
+use lithiumd::HookRunner;
+use lithiumd::HookUser;
+use lithiumd::IdleMonitor;
+use lithiumd::IdleTimeout;
+use lithiumd::InhibitMode;
+use lithiumd::KeyboardBacklight;
+use lithiumd::Labels;
+use lithiumd::Latency;
+use lithiumd::LevelThresholds;
+use lithiumd::Logind;
+use lithiumd::Notification;
+use lithiumd::Notifications;
+use lithiumd::OnFailure;
+use lithiumd::PowerEvent;
+use lithiumd::PowerProfiles;
+use lithiumd::PowerSupply;
+use lithiumd::Pretty;
+use lithiumd::ResumeDetector;
+use lithiumd::Snapshot;
+use lithiumd::Status;
+use lithiumd::TabletModeSwitch;
+use lithiumd::Template;
+use lithiumd::Urgency;
+use lithiumd::UsbPd;
+use mio::event::Source;
+use mio::Events;
+use mio::Interest;
+use mio::Poll;
+use mio::Token;
+#[cfg(feature = "tray")]
+use mio::Waker;
+
+use crate::broadcast::Broadcaster;
+use crate::charged::ChargedNotifier;
+use crate::config::Config;
+use crate::config::Output;
+use crate::cooldown::Cooldowns;
+use crate::countdown;
+use crate::countdown::Countdown;
+use crate::dbus_service::Bus;
+use crate::dbus_service::DbusService;
+use crate::env_file::EnvFile;
+use crate::idle_policy::IdlePolicy;
+use crate::idle_policy::SessionIdle;
+use crate::ipc::ControlSocket;
+use crate::ipc::Request;
+use crate::json;
+use crate::lid_policy::LidHandler;
+use crate::lid_policy::LidPolicy;
+use crate::limits::TopUp;
+use crate::low_battery::LowBatteryNotifier;
+use crate::low_battery::NotifyOptions;
+use crate::mqtt::MqttPublisher;
+use crate::polybar::Polybar;
+use crate::power_button::ButtonAction;
+use crate::power_button::PowerButtonHandler;
+use crate::replay::Recorder;
+use crate::signals::Signal;
+use crate::signals::Signals;
+use crate::sound::Sound;
+use crate::speech::Speech;
+use crate::state_file::StateFile;
+use crate::stats;
+use crate::stats::Stats;
+use crate::sway::Sway;
+use crate::sway::SwayOptions;
+#[cfg(feature = "tray")]
+use crate::tray::Tray;
+#[cfg(feature = "tray")]
+use crate::tray::TrayAction;
+use crate::xroot::XRoot;
+
+const POWER_SUPPLY: Token = Token(0);
+const SIGNALS: Token = Token(1);
+const BLUETOOTH: Token = Token(2);
+const IDLE: Token = Token(3);
+const CONTROL: Token = Token(4);
+
+/// How many control requests are answered before the daemon goes back to its
+/// other events. Every idle client can hold the loop for up to the request
+/// timeout, so a flood of them must not starve it.
+const MAX_REQUESTS_PER_WAKEUP: usize = 8;
+const LID: Token = Token(5);
+const DBUS: Token = Token(6);
+const DISPLAYS: Token = Token(7);
+const RESUME: Token = Token(8);
+const USB_PD: Token = Token(10);
+const NOTIFICATIONS: Token = Token(11);
+const POWER_BUTTON: Token = Token(12);
+const TABLET_MODE: Token = Token(13);
+const STDIN: Token = Token(14);
+const COUNTDOWN: Token = Token(15);
+const CHARGED: Token = Token(16);
+#[cfg(feature = "tray")]
+const TRAY: Token = Token(9);
+
+/// The daemon's event loop and everything it drives.
+pub struct Daemon
+{
+    poll: Poll,
+    signals: Signals,
+    power_supply: PowerSupply,
+    resume: ResumeDetector,
+    /// Connection to BlueZ, if Bluetooth devices are monitored.
+    bluez: Option<Bluez>,
+    /// Idle timeouts, if any are configured and the compositor supports them.
+    idle: Option<IdleMonitor>,
+    /// Actions on logind's idle hint, if any are configured.
+    session_idle: Option<IdlePolicy>,
+    /// Lid switch handling, if it is enabled.
+    lid: Option<LidHandler>,
+    /// Power button handling, if it is enabled.
+    power_button: Option<PowerButtonHandler>,
+    /// The tablet mode switch of a convertible, if it is watched.
+    tablet_mode: Option<TabletModeSwitch>,
+    /// Commands to run when folded into a tablet and back.
+    tablet_hook: Option<String>,
+    laptop_hook: Option<String>,
+    /// External displays, tracked while docking keeps the lid from
+    /// suspending.
+    displays: Option<Displays>,
+    /// The tray icon, if enabled.
+    #[cfg(feature = "tray")]
+    tray: Option<Tray>,
+    /// USB PD contracts of chargers, if they are tracked.
+    usb_pd: Option<UsbPd>,
+    /// The `org.lithiumd` D-Bus service, if enabled.
+    dbus: Option<DbusService>,
+    /// The signals broadcast on the session bus, if enabled.
+    broadcast: Option<Broadcaster>,
+    /// Socket for commands from `lithiumd <command>`, unless it could not be
+    /// bound.
+    control: Option<ControlSocket>,
+    rules: RuleEngine,
+    /// Actions of the profiles, for `lithiumd profile apply`.
+    profiles: BTreeMap<String, Vec<Action>>,
+    hooks: HookRunner,
+    /// Suspends and hibernations of rules that wait for their hooks to
+    /// succeed, see [`OnFailure::Abort`].
+    held: Vec<HeldAction>,
+    cooldowns: Cooldowns,
+    backlight: BacklightFader,
+    keyboard_backlight: KeyboardBacklight,
+    /// Connection to power-profiles-daemon, made when a rule first switches
+    /// the profile.
+    power_profiles: Option<PowerProfiles>,
+    /// Whether rule hooks delay sleep, see [`Config::delay_sleep`].
+    delay_sleep: bool,
+    /// Connection to logind for the delay locks and for hibernating, made
+    /// when a rule first needs it.
+    logind: Option<Logind>,
+    /// Set when the RTC was programmed to wake the system from hibernation.
+    wake_alarm: bool,
+    env_file: Option<EnvFile>,
+    history: Option<History>,
+    /// Where the state is saved for the next start.
+    state_file: StateFile,
+    /// Where `--record` writes to.
+    recorder: Option<Recorder>,
+    /// Broker to publish the state to, and the connection to it, made on the
+    /// first change after it was configured or lost.
+    mqtt: Option<MqttPublisher>,
+    /// Commands for sway or i3, and the connection to its socket, made like
+    /// the one to the broker.
+    sway_options: Option<SwayOptions>,
+    sway: Option<Sway>,
+    /// Connection to the X server for the `xroot` output, made on the first
+    /// change and again after it was lost.
+    xroot: Option<XRoot>,
+    /// Toggles of the `polybar` output, read from stdin.
+    polybar: Option<Polybar>,
+    speech: Option<Speech>,
+    sound: Option<Sound>,
+    low_battery: Option<LowBatteryNotifier>,
+    /// Notification that the batteries are charged, if enabled.
+    charged: Option<ChargedNotifier>,
+    /// Countdown before rules suspend or hibernate, if enabled.
+    countdown: Option<Countdown>,
+    levels: LevelThresholds,
+    /// Line printed on each change instead of the events.
+    format: Option<Template>,
+    output: Output,
+    icons: Pretty,
+    labels: Labels,
+    /// Command to run when the batteries drain abnormally fast.
+    drain_hook: Option<String>,
+    /// Commands to run when a thermal zone gets hot and cools down.
+    hot_hook: Option<String>,
+    cooled_hook: Option<String>,
+    /// Commands to run when a dock is attached and detached.
+    docked_hook: Option<String>,
+    undocked_hook: Option<String>,
+    /// USB PD contracts below this many watts count as a weak charger.
+    weak_charger_watts: Option<f64>,
+    weak_charger_hook: Option<String>,
+    energy_budget_notify: bool,
+    energy_budget_hook: Option<String>,
+    /// Charge limits lifted by the `top-up` command.
+    top_up: Option<TopUp>,
+    /// Set in presentation mode, see [`Daemon::set_inhibited`].
+    inhibited: bool,
+    /// Command line options, applied again whenever the configuration is
+    /// reloaded.
+    args: Vec<String>,
+    /// Time spent handling a single wakeup of the poll.
+    loop_latency: Latency,
+}
+
+/// An action of a rule that is taken once the hooks that ran before it
+/// succeeded, and dropped if one of them failed.
+struct HeldAction
+{
+    hooks: Vec<u64>,
+    action: Action,
+}
+
+impl Daemon
+{
+    pub fn new(mut signals: Signals, config: Config, args: Vec<String>) -> io::Result<Self>
+    {
+        let poll = Poll::new()?;
+        let mut power_supply = config.backend.power_supply()?;
+        let state_file = StateFile::new();
+        match state_file.load() {
+            Ok(Some((state, elapsed))) => power_supply.restore(state, elapsed),
+            Ok(None) => {}
+            Err(err) => eprintln!("Failed to read {}: {}", state_file.path().display(), err),
+        }
+        power_supply.register(
+            poll.registry(),
+            POWER_SUPPLY,
+            Interest::READABLE | Interest::WRITABLE,
+        )?;
+        signals.register(poll.registry(), SIGNALS, Interest::READABLE)?;
+        let mut resume = ResumeDetector::new();
+        if let Some(err) = resume.logind_error() {
+            eprintln!("Cannot watch logind for suspends: {}", err);
+        }
+        resume.register(poll.registry(), RESUME, Interest::READABLE)?;
+        let control = ControlSocket::bind().and_then(|mut control| {
+            control.register(poll.registry(), CONTROL, Interest::READABLE)?;
+            Ok(control)
+        });
+        let control = match control {
+            Ok(control) => Some(control),
+            Err(err) => {
+                eprintln!(
+                    "Commands are disabled, failed to bind the control socket: {}",
+                    err
+                );
+                None
+            }
+        };
+
+        let mut daemon = Self {
+            poll,
+            signals,
+            power_supply,
+            resume,
+            bluez: None,
+            idle: None,
+            session_idle: None,
+            lid: None,
+            power_button: None,
+            tablet_mode: None,
+            tablet_hook: None,
+            laptop_hook: None,
+            displays: None,
+            usb_pd: None,
+            #[cfg(feature = "tray")]
+            tray: None,
+            dbus: None,
+            broadcast: None,
+            control,
+            rules: RuleEngine::default(),
+            profiles: BTreeMap::new(),
+            hooks: HookRunner::new(),
+            held: Vec::new(),
+            cooldowns: Cooldowns::default(),
+            backlight: BacklightFader::new(),
+            keyboard_backlight: KeyboardBacklight::new()?,
+            power_profiles: None,
+            delay_sleep: true,
+            logind: None,
+            wake_alarm: false,
+            env_file: None,
+            history: None,
+            state_file,
+            recorder: None,
+            mqtt: None,
+            sway_options: None,
+            sway: None,
+            xroot: None,
+            polybar: None,
+            speech: None,
+            sound: None,
+            low_battery: None,
+            charged: None,
+            countdown: None,
+            drain_hook: None,
+            hot_hook: None,
+            cooled_hook: None,
+            docked_hook: None,
+            undocked_hook: None,
+            weak_charger_watts: None,
+            weak_charger_hook: None,
+            energy_budget_notify: false,
+            energy_budget_hook: None,
+            levels: LevelThresholds::default(),
+            format: None,
+            output: Output::Events,
+            icons: Pretty::default(),
+            labels: Labels::default(),
+            top_up: None,
+            inhibited: false,
+            args,
+            loop_latency: Latency::default(),
+        };
+        daemon.apply_config(config);
+        Ok(daemon)
+    }
+
+    /// Runs the event loop until a signal asks the daemon to terminate.
+    pub fn run(&mut self) -> io::Result<()>
+    {
+        let mut events = Events::with_capacity(1024);
+        loop {
+            self.poll.poll(&mut events, self.timeout())?;
+            let woken = Instant::now();
+            if events.is_empty() {
+                // The debounce window, the top-up deadline or a second of
+                // the countdown elapsed.
+                self.update()?;
+                self.reap_hooks();
+                self.take_due_actions();
+                self.check_top_up();
+                self.update_notifications();
+                let snapshot = self.power_supply.snapshot();
+                self.check_countdown(&snapshot);
+                self.update_charged(&snapshot);
+            }
+            for event in events.iter() {
+                match event.token() {
+                    POWER_SUPPLY => self.update()?,
+                    BLUETOOTH => self.update_bluetooth(false),
+                    IDLE => self.dispatch_idle(),
+                    CONTROL => self.handle_requests(),
+                    LID => self.read_lid(),
+                    POWER_BUTTON => self.read_power_button(),
+                    TABLET_MODE => self.read_tablet_mode(),
+                    STDIN => self.read_polybar(),
+                    DISPLAYS => self.process_displays(),
+                    USB_PD => self.process_usb_pd(),
+                    NOTIFICATIONS => self.process_notifications(),
+                    COUNTDOWN => self.process_countdown(),
+                    CHARGED => self.process_charged(),
+                    DBUS => self.process_dbus(),
+                    #[cfg(feature = "tray")]
+                    TRAY => self.process_tray(),
+                    RESUME => match self.resume.process() {
+                        Ok(true) => self.resumed(),
+                        Ok(false) => {}
+                        Err(err) => eprintln!("Failed to read from logind: {}", err),
+                    },
+                    SIGNALS => {
+                        for signal in self.signals.read()? {
+                            if signal == Signal::Terminate {
+                                return self.shutdown();
+                            }
+                            self.handle_signal(signal)?;
+                        }
+                    }
+                    _ => unreachable!(),
+                }
+            }
+            if self.resume.check() {
+                self.resumed();
+            }
+            if let Some(lid) = &mut self.lid {
+                lid.check(&mut self.hooks, &self.power_supply.snapshot());
+            }
+            self.check_session_idle();
+            self.loop_latency.record(woken.elapsed());
+        }
+    }
+
+    /// Returns how long the poll may sleep.
+    fn timeout(&self) -> Option<Duration>
+    {
+        let top_up = self.top_up.as_ref().map(TopUp::remaining);
+        let lid = self.lid.as_ref().and_then(LidHandler::timeout);
+        let low_battery = self
+            .low_battery
+            .as_ref()
+            .and_then(|low_battery| low_battery.timeout(Instant::now()));
+        let session_idle = self.session_idle.as_ref().map(IdlePolicy::timeout);
+        let countdown = self
+            .countdown
+            .as_ref()
+            .and_then(|countdown| countdown.timeout(Instant::now()));
+        let charged = self
+            .charged
+            .as_ref()
+            .and_then(|charged| charged.timeout(Instant::now()));
+        let hooks = self.hooks.timeout(Instant::now());
+        let cooldowns = self.cooldowns.timeout(Instant::now());
+        [
+            self.power_supply.timeout(),
+            top_up,
+            lid,
+            low_battery,
+            session_idle,
+            countdown,
+            charged,
+            hooks,
+            cooldowns,
+        ]
+        .into_iter()
+        .flatten()
+        .min()
+    }
+
+    fn apply_config(&mut self, config: Config)
+    {
+        self.power_supply.set_debounce(config.debounce);
+        if let Err(err) = self.power_supply.set_receive_buffer(config.receive_buffer) {
+            eprintln!("Failed to resize the monitor socket buffer: {}", err);
+        }
+        self.power_supply.set_capacity_step(config.capacity_step);
+        self.power_supply
+            .set_include_device_scope(config.include_device_scope);
+        self.power_supply
+            .set_device_patterns(config.battery, config.adapter);
+        self.power_supply.set_source_priority(config.sources);
+        self.power_supply
+            .set_refresh_interval(config.refresh_interval);
+        self.power_supply
+            .set_monitor_peripherals(config.monitor_peripherals, config.peripheral_low_capacity);
+        self.rules = RuleEngine::new(config.rules);
+        self.rules.set_inhibited(self.inhibited);
+        self.profiles = config.profiles;
+        self.env_file = config.env_file.map(EnvFile::new);
+        self.set_history(config.history);
+        if self.recorder.as_ref().map(Recorder::path) != config.record.as_deref() {
+            self.recorder = config
+                .record
+                .and_then(|path| match Recorder::create(path.clone()) {
+                    Ok(recorder) => Some(recorder),
+                    Err(err) => {
+                        eprintln!("Failed to create {}: {}", path.display(), err);
+                        None
+                    }
+                });
+        }
+        if self.mqtt.as_ref().map(MqttPublisher::options) != config.mqtt.as_ref() {
+            self.mqtt = config.mqtt.map(MqttPublisher::spawn);
+        }
+        self.delay_sleep = config.delay_sleep;
+        let hook_user = config
+            .hook_user
+            .and_then(|name| match HookUser::lookup(&name) {
+                Ok(user) => Some(user),
+                Err(err) => {
+                    eprintln!("Hooks run as the daemon's user, failed to look up {}", err);
+                    None
+                }
+            });
+        self.hooks.set_user(hook_user);
+        self.hooks.set_policy(config.hook_policy);
+        self.cooldowns = Cooldowns::new(config.cooldowns);
+        self.speech = config.speech;
+        self.sound = config.sound;
+        if self.sway_options != config.sway {
+            self.sway = None;
+            self.sway_options = config.sway;
+        }
+        self.set_notifications(config.notifications);
+        self.set_countdown(config.countdown);
+        self.set_charged(config.charged);
+        self.power_supply.set_drain_limit(config.drain);
+        if self.power_supply.smoothing() != config.smoothing {
+            self.power_supply.set_smoothing(config.smoothing);
+        }
+        self.drain_hook = config.drain_hook;
+        self.power_supply.set_thermal_limit(config.thermal);
+        self.power_supply.set_monitor_throttling(config.throttling);
+        self.hot_hook = config.hot_hook;
+        self.cooled_hook = config.cooled_hook;
+        self.power_supply.set_monitor_fans(config.fans);
+        if let Err(err) = self.power_supply.set_monitor_docks(config.dock) {
+            eprintln!("Failed to enumerate the docks: {}", err);
+        }
+        self.docked_hook = config.docked_hook;
+        self.undocked_hook = config.undocked_hook;
+        self.power_supply
+            .set_detect_weak_charger(config.weak_charger);
+        self.weak_charger_watts = config.weak_charger_watts.filter(|_| config.weak_charger);
+        self.weak_charger_hook = config.weak_charger_hook;
+        self.power_supply.set_energy_budgets(config.energy_budgets);
+        self.energy_budget_notify = config.energy_budget_notify;
+        self.energy_budget_hook = config.energy_budget_hook;
+        self.levels = config.levels;
+        self.format = config.format;
+        self.set_output(config.output);
+        self.icons = config.icons;
+        self.labels = config.labels;
+        self.set_bluetooth(config.bluetooth);
+        // The wattage of chargers is only known from their contracts.
+        self.set_usb_pd(config.usb_pd || self.weak_charger_watts.is_some());
+        self.set_idle(config.idle);
+        self.set_session_idle(config.session_idle);
+        self.tablet_hook = config.tablet_hook;
+        self.laptop_hook = config.laptop_hook;
+        self.set_tablet_mode(config.tablet_mode);
+        self.set_lid(config.lid);
+        self.set_power_button(config.power_button);
+        self.set_dbus(config.dbus);
+        self.set_broadcast(config.broadcast);
+        self.set_tray(config.tray);
+    }
+
+    /// Shows or hides the tray icon.
+    #[cfg(feature = "tray")]
+    fn set_tray(&mut self, enabled: bool)
+    {
+        if enabled == self.tray.is_some() {
+            return;
+        }
+        // The waker of the old icon has to be gone before a new one is made.
+        self.tray = None;
+        if !enabled {
+            return;
+        }
+        match Waker::new(self.poll.registry(), TRAY).and_then(Tray::spawn) {
+            Ok(tray) => {
+                self.tray = Some(tray);
+                self.update_tray(&self.power_supply.snapshot());
+            }
+            Err(err) => eprintln!("Failed to show the tray icon: {}", err),
+        }
+    }
+
+    #[cfg(not(feature = "tray"))]
+    fn set_tray(&mut self, enabled: bool)
+    {
+        if enabled {
+            eprintln!("The tray icon is disabled, lithiumd was built without the `tray` feature");
+        }
+    }
+
+    #[cfg(feature = "tray")]
+    fn update_tray(&self, snapshot: &Snapshot)
+    {
+        if let Some(tray) = &self.tray {
+            tray.update(self.fields(snapshot), self.inhibited);
+        }
+    }
+
+    #[cfg(not(feature = "tray"))]
+    fn update_tray(&self, _snapshot: &Snapshot) {}
+
+    /// Acts on the toggles used in the menu of the tray icon.
+    #[cfg(feature = "tray")]
+    fn process_tray(&mut self)
+    {
+        let actions = match &self.tray {
+            Some(tray) => tray.actions().collect::<Vec<_>>(),
+            None => return,
+        };
+        for action in actions {
+            match action {
+                TrayAction::SetInhibited(inhibited) => self.set_inhibited(inhibited),
+                TrayAction::SetPowerSaver(true) => {
+                    set_power_profile(&mut self.power_profiles, "power-saver")
+                }
+                TrayAction::SetPowerSaver(false) => {
+                    set_power_profile(&mut self.power_profiles, "balanced")
+                }
+            }
+        }
+    }
+
+    /// Listens for polybar actions on stdin while the output is `polybar`.
+    fn set_output(&mut self, output: Output)
+    {
+        self.output = output;
+        match (output, &mut self.polybar) {
+            (Output::Polybar, None) => {
+                let mut polybar = Polybar::default();
+                if let Err(err) = polybar.register(self.poll.registry(), STDIN, Interest::READABLE)
+                {
+                    eprintln!("Polybar actions on stdin are ignored: {}", err);
+                }
+                self.polybar = Some(polybar);
+            }
+            (Output::Polybar, Some(_)) => {}
+            (_, Some(polybar)) => {
+                let _ = polybar.deregister(self.poll.registry());
+                self.polybar = None;
+            }
+            (_, None) => {}
+        }
+    }
+
+    /// Reads polybar actions and prints the output again if they changed it.
+    /// Once stdin is closed no more actions are read.
+    fn read_polybar(&mut self)
+    {
+        let Some(polybar) = &mut self.polybar else {
+            return;
+        };
+        match polybar.read() {
+            Ok(false) => {}
+            Ok(true) => {
+                let fields = self.fields(&self.power_supply.snapshot());
+                self.print_polybar(&fields);
+            }
+            Err(err) => {
+                if err.kind() != io::ErrorKind::UnexpectedEof {
+                    eprintln!("Failed to read polybar actions: {}", err);
+                }
+                let _ = polybar.deregister(self.poll.registry());
+            }
+        }
+    }
+
+    fn print_polybar(&self, fields: &Fields)
+    {
+        if let Some(polybar) = &self.polybar {
+            println!(
+                "{}",
+                polybar.render(&self.icons, self.format.as_ref(), &self.labels, fields)
+            );
+        }
+    }
+
+    /// Returns the values shown by the output formats and the tray icon.
+    fn fields(&self, snapshot: &Snapshot) -> Fields
+    {
+        Fields {
+            status: snapshot.status,
+            capacity: snapshot.capacity(),
+            level: snapshot.level(&self.levels),
+            time_remaining: self.power_supply.time_remaining(),
+        }
+    }
+
+    /// Offers the D-Bus service on `bus`, or stops offering it.
+    fn set_dbus(&mut self, bus: Option<Bus>)
+    {
+        if self.dbus.as_ref().map(DbusService::bus) == bus {
+            return;
+        }
+        if let Some(mut dbus) = self.dbus.take() {
+            let _ = dbus.deregister(self.poll.registry());
+        }
+        let Some(bus) = bus else {
+            return;
+        };
+        let dbus = DbusService::new(bus).and_then(|mut dbus| {
+            dbus.register(self.poll.registry(), DBUS, Interest::READABLE)?;
+            Ok(dbus)
+        });
+        match dbus {
+            Ok(dbus) => self.dbus = Some(dbus),
+            Err(err) => eprintln!("Failed to offer the D-Bus service: {}", err),
+        }
+    }
+
+    /// Starts or stops broadcasting signals on the session bus.
+    fn set_broadcast(&mut self, enabled: bool)
+    {
+        if enabled == self.broadcast.is_some() {
+            return;
+        }
+        self.broadcast = None;
+        if !enabled {
+            return;
+        }
+        match Broadcaster::new() {
+            Ok(broadcast) => self.broadcast = Some(broadcast),
+            Err(err) => eprintln!("Failed to connect to the session bus: {}", err),
+        }
+    }
+
+    fn process_dbus(&mut self)
+    {
+        let Some(dbus) = &mut self.dbus else {
+            return;
+        };
+        if let Err(err) = dbus.process() {
+            eprintln!("Lost the connection to the bus: {}", err);
+            let _ = dbus.deregister(self.poll.registry());
+            self.dbus = None;
+        }
+    }
+
+    /// Takes over or hands back the lid switch if the lid settings changed.
+    /// Without access to the lid switch or logind, logind keeps handling the
+    /// lid.
+    fn set_lid(&mut self, policy: Option<LidPolicy>)
+    {
+        if self.lid.as_ref().map(LidHandler::policy) == policy.as_ref() {
+            return;
+        }
+        if let Some(mut lid) = self.lid.take() {
+            let _ = lid.deregister(self.poll.registry());
+        }
+        if let Some(mut displays) = self.displays.take() {
+            let _ = displays.deregister(self.poll.registry());
+        }
+        let Some(policy) = policy else {
+            return;
+        };
+        if policy.suspend && !policy.suspend_when_docked {
+            let displays = Displays::new().and_then(|mut displays| {
+                displays.register(self.poll.registry(), DISPLAYS, Interest::READABLE)?;
+                Ok(displays)
+            });
+            match displays {
+                Ok(displays) => self.displays = Some(displays),
+                Err(err) => eprintln!("Failed to monitor external displays: {}", err),
+            }
+        }
+        let lid = LidHandler::new(policy).and_then(|mut lid| {
+            lid.register(self.poll.registry(), LID, Interest::READABLE)?;
+            Ok(lid)
+        });
+        match lid {
+            Ok(mut lid) => {
+                lid.set_docked(self.is_docked(), &self.power_supply.snapshot());
+                lid.set_tablet_mode(
+                    self.tablet_mode
+                        .as_ref()
+                        .is_some_and(TabletModeSwitch::is_tablet),
+                );
+                self.lid = Some(lid);
+            }
+            Err(err) => eprintln!("Lid handling is left to logind: {}", err),
+        }
+    }
+
+    /// Whether the lid policy counts the machine as docked, to external
+    /// displays or to a monitored dock.
+    fn is_docked(&self) -> bool
+    {
+        self.displays.as_ref().is_some_and(Displays::is_docked)
+            || !self.power_supply.docks().is_empty()
+    }
+
+    fn process_displays(&mut self)
+    {
+        let Some(displays) = &mut self.displays else {
+            return;
+        };
+        match displays.process() {
+            Ok(false) => {}
+            Ok(true) => {
+                match displays.external() {
+                    [] => println!("Undocked"),
+                    external => println!("Docked to {}", external.join(", ")),
+                }
+                let docked = displays.is_docked() || !self.power_supply.docks().is_empty();
+                if let Some(lid) = &mut self.lid {
+                    lid.set_docked(docked, &self.power_supply.snapshot());
+                }
+            }
+            Err(err) => {
+                eprintln!("Failed to read the display connectors: {}", err);
+                let _ = displays.deregister(self.poll.registry());
+                self.displays = None;
+            }
+        }
+    }
+
+    fn read_lid(&mut self)
+    {
+        let Some(lid) = &mut self.lid else {
+            return;
+        };
+        let closed = match lid.read(&mut self.hooks, &self.power_supply.snapshot()) {
+            Ok(closed) => closed,
+            Err(err) => {
+                eprintln!("Failed to read the lid switch: {}", err);
+                let _ = lid.deregister(self.poll.registry());
+                self.lid = None;
+                return;
+            }
+        };
+        if let (Some(closed), Some(broadcast)) = (closed, &mut self.broadcast) {
+            if let Err(err) = broadcast.lid(closed) {
+                eprintln!("Lost the connection to the session bus: {}", err);
+                self.broadcast = None;
+            }
+        }
+    }
+
+    /// Starts or stops watching the tablet mode switch.
+    fn set_tablet_mode(&mut self, enabled: bool)
+    {
+        if enabled == self.tablet_mode.is_some() {
+            return;
+        }
+        if let Some(mut tablet_mode) = self.tablet_mode.take() {
+            let _ = tablet_mode.deregister(self.poll.registry());
+        }
+        if !enabled {
+            return;
+        }
+        let tablet_mode = TabletModeSwitch::open().and_then(|mut tablet_mode| {
+            tablet_mode.register(self.poll.registry(), TABLET_MODE, Interest::READABLE)?;
+            Ok(tablet_mode)
+        });
+        match tablet_mode {
+            Ok(tablet_mode) => self.tablet_mode = Some(tablet_mode),
+            Err(err) => eprintln!("Failed to watch the tablet mode switch: {}", err),
+        }
+    }
+
+    fn read_tablet_mode(&mut self)
+    {
+        let Some(tablet_mode) = &mut self.tablet_mode else {
+            return;
+        };
+        let tablet = match tablet_mode.read() {
+            Ok(Some(tablet)) => tablet,
+            Ok(None) => return,
+            Err(err) => {
+                eprintln!("Failed to read the tablet mode switch: {}", err);
+                let _ = tablet_mode.deregister(self.poll.registry());
+                self.tablet_mode = None;
+                return;
+            }
+        };
+        println!("Tablet mode {}", if tablet { "on" } else { "off" });
+        if let Some(lid) = &mut self.lid {
+            lid.set_tablet_mode(tablet);
+        }
+        let hook = if tablet {
+            &self.tablet_hook
+        } else {
+            &self.laptop_hook
+        };
+        if let Some(command) = hook {
+            run_hooks(
+                &mut self.hooks,
+                [command.as_str()],
+                &self.power_supply.snapshot(),
+            );
+        }
+    }
+
+    /// Takes over or hands back the power button if its action changed.
+    fn set_power_button(&mut self, action: Option<ButtonAction>)
+    {
+        if self.power_button.as_ref().map(PowerButtonHandler::action) == action.as_ref() {
+            return;
+        }
+        if let Some(mut button) = self.power_button.take() {
+            let _ = button.deregister(self.poll.registry());
+        }
+        let Some(action) = action else {
+            return;
+        };
+        let button = PowerButtonHandler::new(action).and_then(|mut button| {
+            button.register(self.poll.registry(), POWER_BUTTON, Interest::READABLE)?;
+            Ok(button)
+        });
+        match button {
+            Ok(button) => self.power_button = Some(button),
+            Err(err) => eprintln!("The power button is left to logind: {}", err),
+        }
+    }
+
+    fn read_power_button(&mut self)
+    {
+        let Some(button) = &mut self.power_button else {
+            return;
+        };
+        if let Err(err) = button.read(&mut self.hooks, &self.power_supply.snapshot()) {
+            eprintln!("Failed to read the power button: {}", err);
+            let _ = button.deregister(self.poll.registry());
+            self.power_button = None;
+        }
+    }
+
+    /// Replaces the idle monitor if the timeouts changed. Without a Wayland
+    /// compositor that supports ext-idle-notify the timeouts are ignored.
+    fn set_idle(&mut self, timeouts: Vec<IdleTimeout>)
+    {
+        if self
+            .idle
+            .as_ref()
+            .map(IdleMonitor::timeouts)
+            .unwrap_or_default()
+            == timeouts
+        {
+            return;
+        }
+        if let Some(mut idle) = self.idle.take() {
+            let _ = idle.deregister(self.poll.registry());
+        }
+        if timeouts.is_empty() {
+            return;
+        }
+        let idle = IdleMonitor::new(timeouts).and_then(|mut idle| {
+            idle.register(self.poll.registry(), IDLE, Interest::READABLE)?;
+            Ok(idle)
+        });
+        match idle {
+            Ok(mut idle) => {
+                idle.set_inhibited(self.inhibited);
+                self.idle = Some(idle);
+                self.update_idle();
+            }
+            Err(err) => eprintln!("Idle timeouts are disabled: {}", err),
+        }
+    }
+
+    /// Arms the idle timeouts that apply to the current state.
+    fn update_idle(&mut self)
+    {
+        let Some(idle) = &mut self.idle else {
+            return;
+        };
+        let snapshot = self.power_supply.snapshot();
+        match idle.update(&snapshot) {
+            Ok(commands) => run_hooks(&mut self.hooks, commands, &snapshot),
+            Err(err) => {
+                eprintln!("Lost the connection to the compositor: {}", err);
+                self.drop_idle();
+            }
+        }
+    }
+
+    fn dispatch_idle(&mut self)
+    {
+        let Some(idle) = &mut self.idle else {
+            return;
+        };
+        let snapshot = self.power_supply.snapshot();
+        match idle.dispatch() {
+            Ok(commands) => run_hooks(&mut self.hooks, commands, &snapshot),
+            Err(err) => {
+                eprintln!("Lost the connection to the compositor: {}", err);
+                self.drop_idle();
+            }
+        }
+    }
+
+    fn drop_idle(&mut self)
+    {
+        if let Some(mut idle) = self.idle.take() {
+            let _ = idle.deregister(self.poll.registry());
+        }
+    }
+
+    /// Replaces the idle policy if its actions changed.
+    fn set_session_idle(&mut self, actions: Vec<SessionIdle>)
+    {
+        if self
+            .session_idle
+            .as_ref()
+            .map(IdlePolicy::actions)
+            .unwrap_or_default()
+            == actions
+        {
+            return;
+        }
+        self.session_idle = None;
+        if actions.is_empty() {
+            return;
+        }
+        match IdlePolicy::new(actions) {
+            Ok(mut policy) => {
+                policy.set_inhibited(self.inhibited);
+                self.session_idle = Some(policy);
+            }
+            Err(err) => eprintln!("Session idle actions are disabled: {}", err),
+        }
+    }
+
+    /// Takes the session idle actions that are due.
+    fn check_session_idle(&mut self)
+    {
+        let Some(policy) = &mut self.session_idle else {
+            return;
+        };
+        if let Err(err) = policy.check(&mut self.hooks, &self.power_supply.snapshot()) {
+            eprintln!("Failed to query the idle hint: {}", err);
+            self.session_idle = None;
+        }
+    }
+
+    /// Connects to or disconnects from BlueZ. Failing to connect is not fatal,
+    /// the daemon keeps running without Bluetooth devices.
+    fn set_bluetooth(&mut self, enabled: bool)
+    {
+        match (enabled, self.bluez.take()) {
+            (true, Some(bluez)) => self.bluez = Some(bluez),
+            (true, None) => {
+                let bluez = Bluez::new().and_then(|mut bluez| {
+                    bluez.register(self.poll.registry(), BLUETOOTH, Interest::READABLE)?;
+                    Ok(bluez)
+                });
+                match bluez {
+                    Ok(bluez) => {
+                        self.bluez = Some(bluez);
+                        self.update_bluetooth(true);
+                    }
+                    Err(err) => eprintln!("Failed to connect to BlueZ: {}", err),
+                }
+            }
+            (false, Some(mut bluez)) => {
+                let _ = bluez.deregister(self.poll.registry());
+                self.power_supply.set_external_devices("bluez", Vec::new());
+            }
+            (false, None) => {}
+        }
+    }
+
+    /// Connects to the notification server, or disconnects from it.
+    fn set_notifications(&mut self, options: Option<NotifyOptions>)
+    {
+        if self.low_battery.as_ref().map(LowBatteryNotifier::options) == options.as_ref() {
+            return;
+        }
+        if let Some(mut low_battery) = self.low_battery.take() {
+            let _ = low_battery.notifications().deregister(self.poll.registry());
+        }
+        let Some(options) = options else {
+            return;
+        };
+        let low_battery = LowBatteryNotifier::new(options).and_then(|mut low_battery| {
+            low_battery.notifications().register(
+                self.poll.registry(),
+                NOTIFICATIONS,
+                Interest::READABLE,
+            )?;
+            Ok(low_battery)
+        });
+        match low_battery {
+            Ok(low_battery) => self.low_battery = Some(low_battery),
+            Err(err) => eprintln!("Failed to connect to the notification server: {}", err),
+        }
+    }
+
+    /// Shows, repeats or closes the low battery notification.
+    fn update_notifications(&mut self)
+    {
+        let fields = self.fields(&self.power_supply.snapshot());
+        let Some(low_battery) = &mut self.low_battery else {
+            return;
+        };
+        if let Err(err) = low_battery.update(&fields, Instant::now()) {
+            eprintln!("Lost the connection to the notification server: {}", err);
+            let _ = low_battery.notifications().deregister(self.poll.registry());
+            self.low_battery = None;
+        }
+    }
+
+    fn process_notifications(&mut self)
+    {
+        let Some(low_battery) = &mut self.low_battery else {
+            return;
+        };
+        match low_battery.process(Instant::now()) {
+            Ok(actions) => {
+                let snapshot = self.power_supply.snapshot();
+                for action in &actions {
+                    self.run_action(action, &snapshot);
+                }
+            }
+            Err(err) => {
+                eprintln!("Lost the connection to the notification server: {}", err);
+                let _ = low_battery.notifications().deregister(self.poll.registry());
+                self.low_battery = None;
+            }
+        }
+    }
+
+    /// Connects to the notification server for the charged notification, or
+    /// disconnects from it.
+    fn set_charged(&mut self, repeat: Option<Option<Duration>>)
+    {
+        if self.charged.as_ref().map(ChargedNotifier::repeat) == repeat {
+            return;
+        }
+        if let Some(mut charged) = self.charged.take() {
+            let _ = charged.notifications().deregister(self.poll.registry());
+        }
+        let Some(repeat) = repeat else {
+            return;
+        };
+        let charged = ChargedNotifier::new(repeat).and_then(|mut charged| {
+            charged
+                .notifications()
+                .register(self.poll.registry(), CHARGED, Interest::READABLE)?;
+            Ok(charged)
+        });
+        match charged {
+            Ok(charged) => self.charged = Some(charged),
+            Err(err) => eprintln!("Failed to connect to the notification server: {}", err),
+        }
+    }
+
+    /// Shows, repeats or closes the charged notification.
+    fn update_charged(&mut self, snapshot: &Snapshot)
+    {
+        let Some(charged) = &mut self.charged else {
+            return;
+        };
+        if let Err(err) = charged.update(snapshot, Instant::now()) {
+            eprintln!("Lost the connection to the notification server: {}", err);
+            let _ = charged.notifications().deregister(self.poll.registry());
+            self.charged = None;
+        }
+    }
+
+    fn process_charged(&mut self)
+    {
+        let Some(charged) = &mut self.charged else {
+            return;
+        };
+        if let Err(err) = charged.process() {
+            eprintln!("Lost the connection to the notification server: {}", err);
+            let _ = charged.notifications().deregister(self.poll.registry());
+            self.charged = None;
+        }
+    }
+
+    /// Enables or disables the countdown before critical actions. A running
+    /// countdown is dropped along with the old one, and the rule that started
+    /// it does not fire again until it stopped matching.
+    fn set_countdown(&mut self, duration: Option<Duration>)
+    {
+        if self.countdown.as_ref().map(Countdown::duration) == duration {
+            return;
+        }
+        if let Some(notifications) = self
+            .countdown
+            .take()
+            .as_mut()
+            .and_then(Countdown::notifications)
+        {
+            let _ = notifications.deregister(self.poll.registry());
+        }
+        let Some(duration) = duration else {
+            return;
+        };
+        let mut countdown = Countdown::new(duration);
+        if let Some(notifications) = countdown.notifications() {
+            if let Err(err) =
+                notifications.register(self.poll.registry(), COUNTDOWN, Interest::READABLE)
+            {
+                eprintln!("Failed to watch the countdown notification: {}", err);
+            }
+        }
+        self.countdown = Some(countdown);
+    }
+
+    fn process_countdown(&mut self)
+    {
+        if let Some(countdown) = &mut self.countdown {
+            if let Err(err) = countdown.process() {
+                eprintln!("Lost the connection to the notification server: {}", err);
+            }
+        }
+    }
+
+    /// Ticks the countdown before critical actions and takes its action once
+    /// it ran out.
+    fn check_countdown(&mut self, snapshot: &Snapshot)
+    {
+        let action = self
+            .countdown
+            .as_mut()
+            .and_then(|countdown| countdown.update(snapshot, Instant::now()));
+        if let Some(action) = action {
+            self.run_action(&action, snapshot);
+        }
+    }
+
+    fn set_usb_pd(&mut self, enabled: bool)
+    {
+        match (enabled, self.usb_pd.take()) {
+            (true, Some(usb_pd)) => self.usb_pd = Some(usb_pd),
+            (true, None) => {
+                let usb_pd = UsbPd::new().and_then(|mut usb_pd| {
+                    usb_pd.register(self.poll.registry(), USB_PD, Interest::READABLE)?;
+                    Ok(usb_pd)
+                });
+                match usb_pd {
+                    Ok(usb_pd) => self.usb_pd = Some(usb_pd),
+                    Err(err) => eprintln!("Failed to monitor USB PD chargers: {}", err),
+                }
+            }
+            (false, Some(mut usb_pd)) => {
+                let _ = usb_pd.deregister(self.poll.registry());
+            }
+            (false, None) => {}
+        }
+    }
+
+    fn process_usb_pd(&mut self)
+    {
+        let Some(usb_pd) = &mut self.usb_pd else {
+            return;
+        };
+        match usb_pd.process() {
+            Ok(contracts) => {
+                for contract in contracts {
+                    println!("USB PD contract on {}: {}", contract.supply, contract);
+                    let weak = self
+                        .weak_charger_watts
+                        .is_some_and(|min| contract.watts() < min);
+                    if weak {
+                        println!(
+                            "charger too weak: {} delivers {}",
+                            contract.supply, contract
+                        );
+                        if let Some(command) = &self.weak_charger_hook {
+                            let snapshot = self.power_supply.snapshot();
+                            run_hooks(&mut self.hooks, [command.as_str()], &snapshot);
+                        }
+                    }
+                }
+            }
+            Err(err) => {
+                eprintln!("Failed to read the USB PD contracts: {}", err);
+                let _ = usb_pd.deregister(self.poll.registry());
+                self.usb_pd = None;
+            }
+        }
+    }
+
+    /// Reads the Bluetooth devices again if BlueZ signalled a change, or
+    /// unconditionally if `force` is set.
+    fn update_bluetooth(&mut self, force: bool)
+    {
+        let Some(bluez) = &mut self.bluez else {
+            return;
+        };
+        let devices = match bluez.process() {
+            Ok(false) if !force => return,
+            Ok(_) => bluez.devices(),
+            Err(err) => Err(err),
+        };
+        match devices {
+            Ok(devices) => {
+                self.power_supply.set_external_devices("bluez", devices);
+                self.process_events();
+            }
+            Err(err) => {
+                eprintln!("Lost the connection to BlueZ: {}", err);
+                let _ = bluez.deregister(self.poll.registry());
+                self.bluez = None;
+                self.power_supply.set_external_devices("bluez", Vec::new());
+                self.process_events();
+            }
+        }
+    }
+
+    fn handle_signal(&mut self, signal: Signal) -> io::Result<()>
+    {
+        match signal {
+            Signal::Hangup => match Config::load() {
+                Ok(mut config) => {
+                    // The options were validated on startup.
+                    let _ = config.apply_args(&self.args);
+                    self.apply_config(config);
+                    // Rules that already match must not fire just because
+                    // they were reloaded.
+                    let _ = self.rules.evaluate(&self.power_supply.snapshot());
+                    // Device selection may have changed.
+                    self.rescan();
+                }
+                Err(err) => eprintln!("Failed to reload the configuration: {}", err),
+            },
+            Signal::User1 => {
+                self.rescan();
+                print!("{}", self.status());
+            }
+            Signal::User2 => {
+                println!("{}", self.power_supply.metrics());
+                println!("event loop latency: {}", self.loop_latency);
+            }
+            Signal::Child => self.reap_hooks(),
+            Signal::Terminate => unreachable!(),
+        }
+        Ok(())
+    }
+
+    /// Describes the state of the daemon, as printed on `SIGUSR1` and
+    /// returned by the `status` command.
+    fn status(&self) -> String
+    {
+        let mut status = format!("Charging status: {}\n", self.power_supply.charging_status());
+        let snapshot = self.power_supply.snapshot();
+        if let Some(limit) = snapshot.charge_limited() {
+            status += &format!("Charge limit: {}%\n", limit);
+        }
+        if let Some(capacity) = snapshot.capacity() {
+            status += &format!("Capacity: {}%\n", capacity);
+        }
+        if let Some(level) = snapshot.level(&self.levels) {
+            status += &format!("Level: {}\n", level);
+        }
+        if let Some(sessions) = self.power_supply.sessions() {
+            let minutes = |duration: Duration| {
+                let minutes = duration.as_secs() / 60;
+                format!("{}h {}m", minutes / 60, minutes % 60)
+            };
+            status += &format!(
+                "{} ({} on battery, {} on AC in total)\n",
+                sessions,
+                minutes(sessions.on_battery_total),
+                minutes(sessions.on_ac_total)
+            );
+        }
+        if let Some(remaining) = self.power_supply.time_remaining() {
+            let minutes = remaining.as_secs() / 60;
+            let label = match snapshot.status {
+                Status::Charging => "Time to full",
+                _ => "Time remaining",
+            };
+            status += &format!("{}: {}h {}m\n", label, minutes / 60, minutes % 60);
+        }
+        if let Some(speed) = snapshot.charging_speed() {
+            status += &format!("Charging speed: {}\n", speed);
+        }
+        if let Some(source) = &snapshot.source {
+            status += &format!("Powered by: {}\n", source);
+        }
+        // The breakdown is only interesting with more than one battery.
+        if snapshot.batteries().count() > 1 {
+            for bat in snapshot.batteries() {
+                status += &match bat.capacity {
+                    Some(capacity) => format!("  {}: {}% {}\n", bat.sysname, capacity, bat.status),
+                    None => format!("  {}: {}\n", bat.sysname, bat.status),
+                };
+            }
+        }
+        for contract in self.usb_pd.iter().flat_map(UsbPd::contracts) {
+            status += &format!("Charger: {} on {}\n", contract, contract.supply);
+        }
+        for dock in self.power_supply.docks() {
+            status += &format!("Dock: {}\n", dock);
+        }
+        for (level, max) in self.keyboard_backlight.levels().unwrap_or_default() {
+            status += &format!("Keyboard backlight: {}/{}\n", level, max);
+        }
+        if self.inhibited {
+            status += "Inhibited: yes\n";
+        }
+        if let Some(top_up) = &self.top_up {
+            let minutes = top_up.remaining().as_secs() / 60;
+            status += &format!(
+                "Topping up: to {}% for at most {}h {}m\n",
+                top_up.until(),
+                minutes / 60,
+                minutes % 60
+            );
+        }
+        status
+    }
+
+    /// Lists every device with its own status, as returned by the `devices`
+    /// command.
+    fn devices(&self) -> String
+    {
+        let mut devices = String::new();
+        for dev in &self.power_supply.snapshot().devices {
+            devices += &format!(
+                "{}: {} ({}), {}",
+                dev.sysname, dev.device_type, dev.scope, dev.status
+            );
+            if let Some(capacity) = dev.capacity {
+                devices += &format!(", {}%", capacity);
+            }
+            if let Some(voltage) = dev.voltage() {
+                devices += &format!(", {:.2} V", voltage);
+            }
+            if let Some(current) = dev.current() {
+                devices += &format!(", {:.2} A", current);
+            }
+            if let Some(power) = dev.input_power {
+                devices += &format!(", {:.1} W in", power as f64 / 1e6);
+            }
+            if let Some(peak) = dev.peak_input_power {
+                devices += &format!(" (peak {:.1} W)", peak as f64 / 1e6);
+            }
+            let contract = self
+                .usb_pd
+                .iter()
+                .flat_map(UsbPd::contracts)
+                .find(|contract| contract.supply == dev.sysname);
+            if let Some(contract) = contract {
+                devices += &format!(", PD contract {}", contract);
+            }
+            if let Some(model) = &dev.model {
+                devices += &format!(", {}", model);
+            }
+            devices += "\n";
+        }
+        devices
+    }
+
+    /// Answers the pending requests on the control socket.
+    fn handle_requests(&mut self)
+    {
+        for _ in 0..MAX_REQUESTS_PER_WAKEUP {
+            let Some(control) = &mut self.control else {
+                return;
+            };
+            let request = match control.accept() {
+                Ok(Some(request)) => request,
+                Ok(None) => return,
+                Err(err) => {
+                    // The pending connections are retried when the next
+                    // one arrives.
+                    eprintln!("Failed to accept a control connection: {}", err);
+                    return;
+                }
+            };
+            let response = self.handle_request(&request);
+            request.respond(&response);
+        }
+        // More connections may be pending. Registering the socket again makes
+        // the next poll report it, after the other events were handled.
+        if let Some(control) = &mut self.control {
+            if let Err(err) = self
+                .poll
+                .registry()
+                .reregister(control, CONTROL, Interest::READABLE)
+            {
+                eprintln!("Failed to watch the control socket: {}", err);
+            }
+        }
+    }
+
+    /// Runs a command and returns its response. Failed commands respond with
+    /// a line starting with `error: `.
+    fn handle_request(&mut self, request: &Request) -> String
+    {
+        let args = request.args.iter().map(String::as_str).collect::<Vec<_>>();
+        match args.as_slice() {
+            ["status"] => self.status(),
+            ["status", "--verbose"] => {
+                let mut status = self.status();
+                for fan in lithiumd::read_fans() {
+                    status += &format!("Fan: {}\n", fan);
+                }
+                status
+            }
+            ["status", "--json"] => {
+                let snapshot = self.power_supply.snapshot();
+                format!(
+                    "{}\n",
+                    json::status(
+                        &snapshot,
+                        snapshot.level(&self.levels),
+                        self.power_supply.time_remaining(),
+                        self.power_supply.sessions(),
+                        self.inhibited,
+                        &lithiumd::read_fans(),
+                    )
+                )
+            }
+            ["status", "--check", condition @ ..] => match parse_check(condition) {
+                Ok(condition) => format!("{}\n", condition.matches(&self.power_supply.snapshot())),
+                Err(err) => format!("error: {}\n", err),
+            },
+            ["status", ..] => {
+                "error: usage: status [--json | --verbose] | status --check CONDITION\n".to_string()
+            }
+            ["devices"] => self.devices(),
+            ["devices", "--json"] => format!("{}\n", json::devices(&self.power_supply.snapshot())),
+            ["devices", ..] => "error: usage: devices [--json]\n".to_string(),
+            ["health"] => self.health(),
+            ["health", "--json"] => format!(
+                "{}\n",
+                json::health(&self.power_supply.health(self.history.as_ref()))
+            ),
+            ["health", "--trend"] => self.health_trend(),
+            ["health", "--trend", "--json"] => {
+                format!("{}\n", json::health_trends(&self.health_trends()))
+            }
+            ["health", ..] => "error: usage: health [--trend] [--json]\n".to_string(),
+            ["stats", args @ ..] => self.stats(args),
+            ["inhibit", "on"] => {
+                self.set_inhibited(true);
+                "Inhibited\n".to_string()
+            }
+            ["inhibit", "off"] => {
+                self.set_inhibited(false);
+                "Not inhibited\n".to_string()
+            }
+            ["inhibit", ..] => "error: usage: inhibit on|off\n".to_string(),
+            ["top-up", "cancel"] => match self.top_up.take() {
+                Some(top_up) => {
+                    let restored = top_up.restore();
+                    self.power_supply.reread_settings();
+                    match restored {
+                        Ok(()) => "Restored the charge limits\n".to_string(),
+                        Err(err) => {
+                            format!("error: failed to restore the charge limits: {}\n", err)
+                        }
+                    }
+                }
+                None => "error: not topping up\n".to_string(),
+            },
+            ["top-up", args @ ..] => self.start_top_up(args),
+            ["profile", "apply", name] => self.apply_profile(name),
+            ["profile", ..] => "error: usage: profile apply NAME\n".to_string(),
+            [command, ..] => format!("error: unknown command `{}`\n", command),
+            [] => "error: empty request\n".to_string(),
+        }
+    }
+
+    /// Lifts the charge limits until the battery is charged or the deadline
+    /// passes. A top-up that is already running is replaced.
+    fn start_top_up(&mut self, args: &[&str]) -> String
+    {
+        let (until, duration) = match TopUp::parse(args) {
+            Ok(options) => options,
+            Err(err) => {
+                return format!(
+                    "error: {}\nusage: top-up [--until PERCENT] [--for DURATION] | top-up cancel\n",
+                    err
+                )
+            }
+        };
+        if let Some(top_up) = self.top_up.take() {
+            if let Err(err) = top_up.restore() {
+                return format!("error: failed to restore the charge limits: {}\n", err);
+            }
+        }
+        let started = TopUp::start(until, duration);
+        self.power_supply.reread_settings();
+        match started {
+            Ok(top_up) => {
+                self.top_up = Some(top_up);
+                self.check_top_up();
+                match &self.top_up {
+                    Some(_) => format!("Topping up to {}%\n", until),
+                    None => format!("Already charged to {}%\n", until),
+                }
+            }
+            Err(err) => format!("error: failed to lift the charge limits: {}\n", err),
+        }
+    }
+
+    /// Restores the charge limits once the top-up is done.
+    fn check_top_up(&mut self)
+    {
+        if !self
+            .top_up
+            .as_ref()
+            .is_some_and(|top_up| top_up.is_done(&self.power_supply.snapshot()))
+        {
+            return;
+        }
+        if let Some(top_up) = self.top_up.take() {
+            let restored = top_up.restore();
+            self.power_supply.reread_settings();
+            match restored {
+                Ok(()) => println!("Top-up done, restored the charge limits"),
+                Err(err) => eprintln!("Failed to restore the charge limits: {}", err),
+            }
+        }
+    }
+
+    /// Enters or leaves presentation mode, in which idle timeouts and
+    /// inhibitable rules are suspended.
+    fn set_inhibited(&mut self, inhibited: bool)
+    {
+        self.inhibited = inhibited;
+        self.rules.set_inhibited(inhibited);
+        if let Some(idle) = &mut self.idle {
+            idle.set_inhibited(inhibited);
+        }
+        if let Some(policy) = &mut self.session_idle {
+            policy.set_inhibited(inhibited);
+        }
+        self.update_idle();
+        let snapshot = self.power_supply.snapshot();
+        self.write_env_file(&snapshot);
+        self.update_tray(&snapshot);
+    }
+
+    fn set_history(&mut self, path: Option<PathBuf>)
+    {
+        if self.history.as_ref().map(History::path) == path.as_deref() {
+            return;
+        }
+        self.save_history();
+        self.history = path.and_then(|path| match History::load(path.clone()) {
+            Ok(history) => Some(history),
+            Err(err) => {
+                eprintln!("Failed to read {}: {}", path.display(), err);
+                None
+            }
+        });
+    }
+
+    fn record(&mut self, snapshot: &Snapshot, events: &[PowerEvent])
+    {
+        if let Some(recorder) = &mut self.recorder {
+            if let Err(err) = recorder.record(snapshot, events) {
+                eprintln!("Failed to write {}: {}", recorder.path().display(), err);
+                self.recorder = None;
+            }
+        }
+    }
+
+    fn record_history(&mut self, snapshot: &Snapshot)
+    {
+        if let Some(history) = &mut self.history {
+            let mut changed = history.record(snapshot);
+            if history.health_due() {
+                changed |= history.record_health(&self.power_supply.health(None));
+            }
+            if changed {
+                if let Err(err) = history.save() {
+                    eprintln!("Failed to write {}: {}", history.path().display(), err);
+                }
+            }
+        }
+    }
+
+    /// Saves the history with the segment going on.
+    fn save_history(&mut self)
+    {
+        if let Some(history) = &mut self.history {
+            history.finish();
+            if let Err(err) = history.save() {
+                eprintln!("Failed to write {}: {}", history.path().display(), err);
+            }
+        }
+    }
+
+    fn health(&self) -> String
+    {
+        let health = self.power_supply.health(self.history.as_ref());
+        if health.is_empty() {
+            return "No batteries\n".to_string();
+        }
+        health
+            .iter()
+            .map(|health| format!("{}\n", health))
+            .collect()
+    }
+
+    /// Returns the health trend of every system battery that has enough
+    /// history.
+    fn health_trends(&self) -> Vec<HealthTrend>
+    {
+        let Some(history) = &self.history else {
+            return Vec::new();
+        };
+        self.power_supply
+            .snapshot()
+            .batteries()
+            .filter_map(|bat| history.health_trend(&bat.sysname))
+            .collect()
+    }
+
+    fn health_trend(&self) -> String
+    {
+        if self.history.is_none() {
+            return "error: the history is not enabled, see `[history]`\n".to_string();
+        }
+        let trends = self.health_trends();
+        if trends.is_empty() {
+            return "Not enough history yet, the health is sampled daily for at least a week\n"
+                .to_string();
+        }
+        let mut lines = String::new();
+        for trend in trends {
+            lines += &format!(
+                "{}: {:.1}% health, {:+.1}% per year",
+                trend.sysname, trend.health_percent, trend.percent_per_year
+            );
+            match trend.below_80 {
+                Some(at) => lines += &format!(", below 80% around {}", date(at)),
+                None => lines += ", not wearing",
+            }
+            lines += &format!(" ({} samples)\n", trend.samples);
+        }
+        lines
+    }
+
+    fn stats(&self, args: &[&str]) -> String
+    {
+        let options = match stats::Options::parse(args) {
+            Ok(options) => options,
+            Err(err) => return format!("error: {}\nusage: stats [--days N] [--json]\n", err),
+        };
+        let Some(history) = &self.history else {
+            return "error: the history is not enabled, see `[history]`\n".to_string();
+        };
+        let stats = Stats::new(history.segments(), options.days);
+        if options.json {
+            format!("{}\n", stats.json())
+        } else {
+            stats.text()
+        }
+    }
+
+    fn write_env_file(&self, snapshot: &Snapshot)
+    {
+        if let Some(env_file) = &self.env_file {
+            if let Err(err) = env_file.write(snapshot, self.inhibited) {
+                eprintln!("Failed to write {}: {}", env_file.path().display(), err);
+            }
+        }
+    }
+
+    fn shutdown(&mut self) -> io::Result<()>
+    {
+        self.save_history();
+        if let Err(err) = self.state_file.save(&self.power_supply.saved_state()) {
+            eprintln!(
+                "Failed to write {}: {}",
+                self.state_file.path().display(),
+                err
+            );
+        }
+        if let Some(top_up) = self.top_up.take() {
+            if let Err(err) = top_up.restore() {
+                eprintln!("Failed to restore the charge limits: {}", err);
+            }
+        }
+        self.signals.deregister(self.poll.registry())?;
+        self.resume.deregister(self.poll.registry())?;
+        if let Some(bluez) = &mut self.bluez {
+            bluez.deregister(self.poll.registry())?;
+        }
+        if let Some(idle) = &mut self.idle {
+            idle.deregister(self.poll.registry())?;
+        }
+        if let Some(control) = &mut self.control {
+            control.deregister(self.poll.registry())?;
+        }
+        if let Some(lid) = &mut self.lid {
+            lid.deregister(self.poll.registry())?;
+        }
+        if let Some(polybar) = &mut self.polybar {
+            let _ = polybar.deregister(self.poll.registry());
+        }
+        if let Some(button) = &mut self.power_button {
+            button.deregister(self.poll.registry())?;
+        }
+        if let Some(tablet_mode) = &mut self.tablet_mode {
+            tablet_mode.deregister(self.poll.registry())?;
+        }
+        if let Some(displays) = &mut self.displays {
+            displays.deregister(self.poll.registry())?;
+        }
+        if let Some(usb_pd) = &mut self.usb_pd {
+            usb_pd.deregister(self.poll.registry())?;
+        }
+        if let Some(low_battery) = &mut self.low_battery {
+            low_battery
+                .notifications()
+                .deregister(self.poll.registry())?;
+        }
+        if let Some(notifications) = self.countdown.as_mut().and_then(Countdown::notifications) {
+            notifications.deregister(self.poll.registry())?;
+        }
+        if let Some(charged) = &mut self.charged {
+            charged.notifications().deregister(self.poll.registry())?;
+        }
+        if let Some(dbus) = &mut self.dbus {
+            dbus.deregister(self.poll.registry())?;
+        }
+        self.power_supply.deregister(self.poll.registry())
+    }
+
+    fn update(&mut self) -> io::Result<()>
+    {
+        let before = *self.power_supply.metrics();
+        self.power_supply.update()?;
+        let after = self.power_supply.metrics();
+        if after.overruns > before.overruns {
+            eprintln!("Monitor socket buffer overrun, uevents were lost; re-enumerating devices");
+        }
+        if after.reconnects > before.reconnects {
+            eprintln!("The monitor socket failed, opened it again");
+        }
+        self.process_events();
+        Ok(())
+    }
+
+    /// Sets the name of the X root window, connecting first if needed. The
+    /// connection is dropped on failure and made again on the next change.
+    fn set_root_name(&mut self, name: &str)
+    {
+        let set = match &mut self.xroot {
+            Some(xroot) => xroot.set_name(name),
+            None => XRoot::connect().and_then(|xroot| self.xroot.insert(xroot).set_name(name)),
+        };
+        if let Err(err) = set {
+            eprintln!("Failed to set the X root window name: {}", err);
+            self.xroot = None;
+        }
+    }
+
+    /// Reads all devices again after a suspend, when none of the cached
+    /// state can be trusted.
+    fn resumed(&mut self)
+    {
+        if self.wake_alarm {
+            // Rules that hibernated fire again, to warn or hibernate once
+            // more.
+            self.wake_alarm = false;
+            self.rules.reset();
+            if let Err(err) = rtc::clear_wakealarm() {
+                eprintln!("Failed to clear the wake alarm: {}", err);
+            }
+        }
+        if let Err(err) = self.power_supply.resumed() {
+            eprintln!("Failed to enumerate the power supplies: {}", err);
+        }
+        self.process_events();
+        self.update_bluetooth(true);
+    }
+
+    /// Enumerates the power supplies again, keeping the devices found before
+    /// if that fails.
+    fn rescan(&mut self)
+    {
+        if let Err(err) = self.power_supply.rescan() {
+            eprintln!("Failed to enumerate the power supplies: {}", err);
+        }
+        self.process_events();
+    }
+
+    /// Prints the events queued by the power supply and runs the actions of
+    /// rules that started matching.
+    fn process_events(&mut self)
+    {
+        for err in self.power_supply.take_errors() {
+            eprintln!("Power supply: {}", err);
+        }
+        let events = self.power_supply.events().collect::<Vec<_>>();
+        if events.is_empty() {
+            return;
+        }
+
+        self.check_top_up();
+        let snapshot = self.power_supply.snapshot();
+        let fields = self.fields(&snapshot);
+        match (&self.format, self.output) {
+            (Some(format), Output::Xroot) => {
+                self.set_root_name(&format.render_with(&fields, &self.labels))
+            }
+            (None, Output::Xroot) => {
+                let icons = Pretty {
+                    color: false,
+                    ..self.icons.clone()
+                };
+                self.set_root_name(&icons.render(&fields));
+            }
+            (_, Output::Polybar) => self.print_polybar(&fields),
+            (Some(format), _) => println!("{}", format.render_with(&fields, &self.labels)),
+            (None, Output::Pretty) => println!("{}", self.icons.render(&fields)),
+            (None, Output::Events) => {
+                for event in &events {
+                    println!("{}", self.labels.event(event));
+                }
+            }
+        }
+        if let Some(speech) = &self.speech {
+            speech.announce(&mut self.hooks, &events, &snapshot);
+        }
+        if let Some(sound) = &self.sound {
+            sound.play(&mut self.hooks, &events, &snapshot, &self.levels);
+        }
+        if let Some(options) = &self.sway_options {
+            let sent = match &mut self.sway {
+                Some(sway) => Ok(sway),
+                None => Sway::connect(options).map(|sway| self.sway.insert(sway)),
+            }
+            .and_then(|sway| {
+                sway.send(
+                    options,
+                    &events,
+                    &snapshot,
+                    &fields,
+                    &self.labels,
+                    &self.levels,
+                )
+            });
+            if let Err(err) = sent {
+                eprintln!("Failed to send to sway: {}", err);
+                self.sway = None;
+            }
+        }
+        self.update_notifications();
+        if let Some(dbus) = &mut self.dbus {
+            let sent = events
+                .iter()
+                .try_for_each(|event| dbus.emit(event, &snapshot));
+            if let Err(err) = sent {
+                eprintln!("Lost the connection to the bus: {}", err);
+                let _ = dbus.deregister(self.poll.registry());
+                self.dbus = None;
+            }
+        }
+        if let Some(broadcast) = &mut self.broadcast {
+            if let Err(err) = events.iter().try_for_each(|event| broadcast.emit(event)) {
+                eprintln!("Lost the connection to the session bus: {}", err);
+                self.broadcast = None;
+            }
+        }
+        self.record_history(&snapshot);
+        self.record(&snapshot, &events);
+        self.write_env_file(&snapshot);
+        self.update_tray(&snapshot);
+        if let Some(mqtt) = &self.mqtt {
+            mqtt.publish(&snapshot);
+        }
+        if let Some(command) = &self.drain_hook {
+            let drains = events
+                .iter()
+                .any(|event| matches!(event, PowerEvent::HighDrain(_)));
+            if drains {
+                run_hooks(&mut self.hooks, [command.as_str()], &snapshot);
+            }
+        }
+        for event in &events {
+            let command = match event {
+                PowerEvent::Docked(_) => &self.docked_hook,
+                PowerEvent::Undocked(_) => &self.undocked_hook,
+                PowerEvent::WeakCharger { .. } => &self.weak_charger_hook,
+                PowerEvent::EnergyBudget { .. } => {
+                    if self.energy_budget_notify {
+                        show_notification(&Notification {
+                            summary: "Battery low".to_string(),
+                            body: format!("{}.", event),
+                            icon: "battery-caution-symbolic".to_string(),
+                            urgency: Urgency::Critical,
+                            progress: snapshot.capacity(),
+                            actions: Vec::new(),
+                        });
+                    }
+                    &self.energy_budget_hook
+                }
+                PowerEvent::Overheated(_) => &self.hot_hook,
+                PowerEvent::CooledDown(_) => &self.cooled_hook,
+                _ => continue,
+            };
+            if let Some(command) = command {
+                run_hooks(&mut self.hooks, [command.as_str()], &snapshot);
+            }
+        }
+        let docks_changed = events
+            .iter()
+            .any(|event| matches!(event, PowerEvent::Docked(_) | PowerEvent::Undocked(_)));
+        if docks_changed {
+            let docked = self.is_docked();
+            if let Some(lid) = &mut self.lid {
+                lid.set_docked(docked, &snapshot);
+            }
+        }
+        let fired = self
+            .rules
+            .fire(&snapshot)
+            .into_iter()
+            .map(|rule| rule.actions().to_vec())
+            .collect::<Vec<_>>();
+        for actions in fired {
+            self.take_rule_actions(actions, &snapshot);
+        }
+        self.check_countdown(&snapshot);
+        self.update_charged(&snapshot);
+        self.update_idle();
+    }
+
+    /// Takes the actions of a rule that started matching. Suspending and
+    /// hibernating wait for the hooks before them whose failure aborts them,
+    /// and are skipped if one of those cools down and does not run.
+    fn take_rule_actions(&mut self, actions: Vec<Action>, snapshot: &Snapshot)
+    {
+        let mut awaited = Vec::new();
+        let mut cooling = None;
+        for action in actions {
+            match &action {
+                Action::RunHook(hook) => {
+                    let abort = hook.policy.on_failure == OnFailure::Abort;
+                    if !self.cooldowns.admit(&action, Instant::now()) {
+                        if abort {
+                            cooling.get_or_insert_with(|| hook.command.clone());
+                        }
+                        continue;
+                    }
+                    let id = self.run_hook(hook, snapshot);
+                    if abort {
+                        awaited.extend(id);
+                    }
+                }
+                Action::Suspend | Action::Hibernate(_) if cooling.is_some() => {
+                    println!(
+                        "Not {}, hook `{}` cools down",
+                        countdown::verb(&action).to_lowercase(),
+                        cooling.as_deref().unwrap_or_default()
+                    );
+                }
+                Action::Suspend | Action::Hibernate(_) if !awaited.is_empty() => {
+                    self.held.push(HeldAction {
+                        hooks: awaited.clone(),
+                        action,
+                    })
+                }
+                _ => self.take_action(action, snapshot),
+            }
+        }
+    }
+
+    /// Takes an action of a rule unless it cools down, counting down to
+    /// suspending and hibernating if enabled.
+    fn take_action(&mut self, action: Action, snapshot: &Snapshot)
+    {
+        if !self.cooldowns.admit(&action, Instant::now()) {
+            return;
+        }
+        self.start_action(action, snapshot);
+    }
+
+    fn start_action(&mut self, action: Action, snapshot: &Snapshot)
+    {
+        match &mut self.countdown {
+            Some(countdown) if matches!(action, Action::Suspend | Action::Hibernate(_)) => {
+                countdown.start(action, Instant::now())
+            }
+            _ => self.run_action(&action, snapshot),
+        }
+    }
+
+    /// Takes the actions that were put off until their cooldown ended.
+    fn take_due_actions(&mut self)
+    {
+        let due = self.cooldowns.due(Instant::now());
+        if due.is_empty() {
+            return;
+        }
+        let snapshot = self.power_supply.snapshot();
+        for action in due {
+            self.start_action(action, &snapshot);
+        }
+    }
+
+    /// Reaps the hooks that exited, and applies the failure policy of those
+    /// that failed for good.
+    fn reap_hooks(&mut self)
+    {
+        let outcomes = self.hooks.reap();
+        if outcomes.is_empty() {
+            return;
+        }
+        let snapshot = self.power_supply.snapshot();
+        for outcome in outcomes {
+            if let Some(failure) = &outcome.failure {
+                match outcome.attempts {
+                    1 => eprintln!("Hook `{}` {}", outcome.command, failure),
+                    attempts => eprintln!(
+                        "Hook `{}` {}, after {} attempts",
+                        outcome.command, failure, attempts
+                    ),
+                }
+                if outcome.on_failure == OnFailure::Notify {
+                    notify_hook_failure(&outcome, failure);
+                }
+            }
+            let mut ready = Vec::new();
+            self.held.retain_mut(|held| {
+                if !held.hooks.contains(&outcome.id) {
+                    return true;
+                }
+                if outcome.failure.is_some() {
+                    println!(
+                        "Not {}, hook `{}` failed",
+                        countdown::verb(&held.action).to_lowercase(),
+                        outcome.command
+                    );
+                    return false;
+                }
+                held.hooks.retain(|id| *id != outcome.id);
+                if held.hooks.is_empty() {
+                    ready.push(held.action.clone());
+                    return false;
+                }
+                true
+            });
+            for action in ready {
+                self.take_action(action, &snapshot);
+            }
+        }
+    }
+
+    /// Takes all actions of the profile `name` right away, whatever the power
+    /// source, to try it out.
+    fn apply_profile(&mut self, name: &str) -> String
+    {
+        let Some(actions) = self.profiles.get(name).cloned() else {
+            return format!("error: no profile `{}`\n", name);
+        };
+        let snapshot = self.power_supply.snapshot();
+        for action in &actions {
+            self.run_action(action, &snapshot);
+        }
+        format!("Applied the {} profile\n", name)
+    }
+
+    /// Runs the hook of a rule or a profile, holding a delay lock if
+    /// configured, and returns its id.
+    fn run_hook(&mut self, hook: &RunHook, snapshot: &Snapshot) -> Option<u64>
+    {
+        let inhibitor = self
+            .delay_sleep
+            .then(|| delay_sleep(&mut self.logind, &hook.command))
+            .flatten();
+        self.hooks
+            .run_with(&hook.command, snapshot, inhibitor, &hook.policy)
+            .inspect_err(|err| eprintln!("Failed to run hook `{}`: {}", hook.command, err))
+            .ok()
+    }
+
+    /// Takes an action of a rule or a profile.
+    fn run_action(&mut self, action: &Action, snapshot: &Snapshot)
+    {
+        match action {
+            Action::RunHook(hook) => {
+                self.run_hook(hook, snapshot);
+            }
+            Action::SetPowerProfile(profile) => {
+                set_power_profile(&mut self.power_profiles, profile)
+            }
+            Action::SetCpuPolicy(policy) => {
+                if let Err(err) = policy.apply() {
+                    eprintln!("Failed to set the CPU policy: {}", err);
+                }
+            }
+            Action::SetWifiPowerSave(power_save) => {
+                if let Err(err) = power_save.apply() {
+                    eprintln!("Failed to set Wi-Fi power saving: {}", err);
+                }
+            }
+            Action::SetUsbAutosuspend(autosuspend) => {
+                if let Err(err) = autosuspend.apply() {
+                    eprintln!("Failed to set USB autosuspend: {}", err);
+                }
+            }
+            Action::SetPciePolicy(policy) => {
+                if let Err(err) = policy.apply() {
+                    eprintln!("Failed to set the PCIe power policy: {}", err);
+                }
+            }
+            Action::SetBrightness(change) => {
+                if let Err(err) = self.backlight.apply(*change) {
+                    eprintln!("Failed to change the brightness: {}", err);
+                }
+            }
+            Action::SetKeyboardBacklight(brightness) => {
+                if let Err(err) = self.keyboard_backlight.set(*brightness) {
+                    eprintln!("Failed to change the keyboard backlight: {}", err);
+                }
+            }
+            Action::Hibernate(hibernate) => {
+                self.wake_alarm |= hibernate_with_alarm(&mut self.logind, *hibernate)
+            }
+            Action::Suspend => suspend(&mut self.logind),
+        }
+    }
+}
+
+/// Switches the power profile, connecting to power-profiles-daemon first if
+/// needed. The connection is dropped on errors and made again next time, in
+/// case the daemon was restarted.
+fn set_power_profile(power_profiles: &mut Option<PowerProfiles>, profile: &str)
+{
+    let result = match power_profiles {
+        Some(power_profiles) => power_profiles.set_active(profile),
+        None => PowerProfiles::new().and_then(|mut new| {
+            new.set_active(profile)?;
+            *power_profiles = Some(new);
+            Ok(())
+        }),
+    };
+    if let Err(err) = result {
+        eprintln!("Failed to switch to the {} power profile: {}", profile, err);
+        *power_profiles = None;
+    } else {
+        println!("Switched to the {} power profile", profile);
+    }
+}
+
+/// Takes a delay lock for sleep and shutdown while `command` runs,
+/// connecting to logind first if needed. Like [`set_power_profile`], the
+/// connection is made again after errors.
+fn delay_sleep(logind: &mut Option<Logind>, command: &str) -> Option<OwnedFd>
+{
+    let why = format!("Running `{}`", command);
+    let result = match logind {
+        Some(logind) => logind.inhibit("sleep:shutdown", &why, InhibitMode::Delay),
+        None => Logind::new().and_then(|mut new| {
+            let inhibitor = new.inhibit("sleep:shutdown", &why, InhibitMode::Delay)?;
+            *logind = Some(new);
+            Ok(inhibitor)
+        }),
+    };
+    match result {
+        Ok(inhibitor) => Some(inhibitor),
+        Err(err) => {
+            eprintln!("Failed to delay sleep for `{}`: {}", command, err);
+            *logind = None;
+            None
+        }
+    }
+}
+
+/// Suspends through logind, connecting first if needed.
+fn suspend(logind: &mut Option<Logind>)
+{
+    let result = match logind {
+        Some(logind) => logind.suspend(),
+        None => Logind::new().and_then(|mut new| {
+            new.suspend()?;
+            *logind = Some(new);
+            Ok(())
+        }),
+    };
+    match result {
+        Ok(()) => println!("Suspending"),
+        Err(err) => {
+            eprintln!("Failed to suspend: {}", err);
+            *logind = None;
+        }
+    }
+}
+
+/// Hibernates through logind after programming the wake alarm, if any.
+/// Returns whether the alarm was set.
+fn hibernate_with_alarm(logind: &mut Option<Logind>, hibernate: Hibernate) -> bool
+{
+    let alarm = hibernate
+        .wake_after
+        .is_some_and(|after| match rtc::set_wakealarm(after) {
+            Ok(()) => true,
+            Err(err) => {
+                eprintln!("Failed to set the wake alarm: {}", err);
+                false
+            }
+        });
+    let result = match logind {
+        Some(logind) => logind.hibernate(),
+        None => Logind::new().and_then(|mut new| {
+            new.hibernate()?;
+            *logind = Some(new);
+            Ok(())
+        }),
+    };
+    match result {
+        Ok(()) => {
+            println!("Hibernating");
+            alarm
+        }
+        Err(err) => {
+            eprintln!("Failed to hibernate: {}", err);
+            *logind = None;
+            if alarm {
+                let _ = rtc::clear_wakealarm();
+            }
+            false
+        }
+    }
+}
+
+/// Parses the condition of `status --check`: `on-ac`, `on-battery`,
+/// `above PERCENT` or `below PERCENT`.
+fn parse_check(args: &[&str]) -> Result<Condition, String>
+{
+    let percent = |value: &str| {
+        value
+            .parse()
+            .ok()
+            .filter(|percent| *percent <= 100)
+            .ok_or_else(|| format!("`{}` is not a percentage", value))
+    };
+    match args {
+        ["on-ac"] => Ok(Condition::OnAc),
+        ["on-battery"] => Ok(Condition::OnBattery),
+        ["above", value] => percent(value).map(Condition::CapacityAbove),
+        ["below", value] => percent(value).map(Condition::CapacityBelow),
+        _ => Err(
+            "usage: status --check on-ac | on-battery | above PERCENT | below PERCENT".to_string(),
+        ),
+    }
+}
+
+/// Shows a desktop notification that a hook failed.
+fn notify_hook_failure(outcome: &HookOutcome, failure: &str)
+{
+    show_notification(&Notification {
+        summary: "Hook failed".to_string(),
+        body: format!("`{}` {}.", outcome.command, failure),
+        icon: "dialog-error-symbolic".to_string(),
+        urgency: Urgency::Normal,
+        progress: None,
+        actions: Vec::new(),
+    });
+}
+
+/// Shows a one-off desktop notification, over a connection of its own.
+fn show_notification(notification: &Notification)
+{
+    let shown =
+        Notifications::new().and_then(|mut notifications| notifications.notify(0, notification));
+    if let Err(err) = shown {
+        eprintln!(
+            "Failed to show the notification `{}`: {}",
+            notification.summary, err
+        );
+    }
+}
+
+fn run_hooks<'a>(
+    hooks: &mut HookRunner,
+    commands: impl IntoIterator<Item = &'a str>,
+    snapshot: &Snapshot,
+)
+{
+    for command in commands {
+        if let Err(err) = hooks.run(command, snapshot) {
+            eprintln!("Failed to run hook `{}`: {}", command, err);
+        }
+    }
+}
+
+/// Formats `at` as a local date, `YYYY-MM-DD`.
+fn date(at: SystemTime) -> String
+{
+    let secs = at
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs() as libc::time_t;
+    let mut tm = unsafe { std::mem::zeroed::<libc::tm>() };
+    if unsafe { libc::localtime_r(&secs, &mut tm) }.is_null() {
+        return "?".to_string();
+    }
+    format!(
+        "{:04}-{:02}-{:02}",
+        tm.tm_year + 1900,
+        tm.tm_mon + 1,
+        tm.tm_mday
+    )
+}
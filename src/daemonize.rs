@@ -0,0 +1,67 @@
+use std::env;
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::os::fd::AsRawFd;
+use std::path::Path;
+use std::path::PathBuf;
+
+/// Returns the default log file of a detached daemon:
+/// `$XDG_STATE_HOME/lithiumd/log`, `~/.local/state/lithiumd/log`, or
+/// `/var/log/lithiumd.log` for a system-wide daemon.
+pub fn default_log_path() -> PathBuf
+{
+    let dir = env::var_os("XDG_STATE_HOME")
+        .map(PathBuf::from)
+        .filter(|dir| dir.is_absolute())
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/state")));
+    match dir {
+        Some(dir) if unsafe { libc::geteuid() } != 0 => dir.join("lithiumd/log"),
+        _ => PathBuf::from("/var/log/lithiumd.log"),
+    }
+}
+
+/// Detaches the daemon from the terminal for init systems other than
+/// systemd: forks twice around `setsid(2)`, so that the daemon is not a
+/// session leader and cannot acquire a controlling terminal again, and
+/// appends its output to `log`. The original process exits once the log is
+/// open, so that errors opening it still reach the terminal.
+///
+/// Must be called before any thread is spawned.
+pub fn daemonize(log: &Path) -> io::Result<()>
+{
+    if let Some(dir) = log.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let log = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(log)
+        .map_err(|err| io::Error::new(err.kind(), format!("{}: {}", log.display(), err)))?;
+    let null = File::open("/dev/null")?;
+    fork_and_exit_parent()?;
+    if unsafe { libc::setsid() } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    fork_and_exit_parent()?;
+    env::set_current_dir("/")?;
+    for (fd, target) in [
+        (&null, libc::STDIN_FILENO),
+        (&log, libc::STDOUT_FILENO),
+        (&log, libc::STDERR_FILENO),
+    ] {
+        if unsafe { libc::dup2(fd.as_raw_fd(), target) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+fn fork_and_exit_parent() -> io::Result<()>
+{
+    match unsafe { libc::fork() } {
+        -1 => Err(io::Error::last_os_error()),
+        0 => Ok(()),
+        _ => unsafe { libc::_exit(0) },
+    }
+}
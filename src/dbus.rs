@@ -0,0 +1,1315 @@
+use std::collections::VecDeque;
+use std::env;
+use std::fmt::Display;
+use std::io;
+use std::io::BufRead;
+use std::io::BufReader;
+use std::io::Write;
+use std::os::fd::AsRawFd;
+use std::os::fd::FromRawFd;
+use std::os::fd::OwnedFd;
+use std::os::linux::net::SocketAddrExt;
+use std::os::unix::net::SocketAddr;
+use std::os::unix::net::UnixStream;
+use std::time::Duration;
+use std::time::Instant;
+
+#[cfg(feature = "mio")]
+use mio::event::Source;
+#[cfg(feature = "mio")]
+use mio::unix::SourceFd;
+
+const SYSTEM_BUS_ADDRESS: &str = "unix:path=/var/run/dbus/system_bus_socket";
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A value that can be sent over D-Bus.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value
+{
+    Byte(u8),
+    Bool(bool),
+    Int16(i16),
+    UInt16(u16),
+    Int32(i32),
+    UInt32(u32),
+    Int64(i64),
+    UInt64(u64),
+    Double(f64),
+    String(String),
+    ObjectPath(String),
+    Signature(String),
+    /// Index into the file descriptors passed along with the message.
+    UnixFd(u32),
+    /// An array with the signature of its elements, so that empty arrays can
+    /// be encoded.
+    Array(String, Vec<Value>),
+    Struct(Vec<Value>),
+    DictEntry(Box<Value>, Box<Value>),
+    Variant(Box<Value>),
+}
+
+impl Value
+{
+    /// Creates an `a{sv}` dictionary.
+    #[must_use]
+    pub fn dict(entries: Vec<(&str, Value)>) -> Self
+    {
+        Value::Array(
+            "{sv}".to_string(),
+            entries
+                .into_iter()
+                .map(|(key, value)| {
+                    Value::DictEntry(
+                        Box::new(Value::String(key.to_string())),
+                        Box::new(Value::Variant(Box::new(value))),
+                    )
+                })
+                .collect(),
+        )
+    }
+
+    #[must_use]
+    pub fn signature(&self) -> String
+    {
+        match self {
+            Value::Byte(_) => "y".to_string(),
+            Value::Bool(_) => "b".to_string(),
+            Value::Int16(_) => "n".to_string(),
+            Value::UInt16(_) => "q".to_string(),
+            Value::Int32(_) => "i".to_string(),
+            Value::UInt32(_) => "u".to_string(),
+            Value::Int64(_) => "x".to_string(),
+            Value::UInt64(_) => "t".to_string(),
+            Value::Double(_) => "d".to_string(),
+            Value::String(_) => "s".to_string(),
+            Value::ObjectPath(_) => "o".to_string(),
+            Value::Signature(_) => "g".to_string(),
+            Value::UnixFd(_) => "h".to_string(),
+            Value::Array(element, _) => format!("a{}", element),
+            Value::Struct(fields) => {
+                let fields = fields.iter().map(Value::signature).collect::<String>();
+                format!("({})", fields)
+            }
+            Value::DictEntry(key, value) => format!("{{{}{}}}", key.signature(), value.signature()),
+            Value::Variant(_) => "v".to_string(),
+        }
+    }
+
+    /// Looks through variants to the contained value.
+    #[must_use]
+    pub fn inner(&self) -> &Value
+    {
+        match self {
+            Value::Variant(value) => value.inner(),
+            value => value,
+        }
+    }
+
+    #[must_use]
+    pub fn as_str(&self) -> Option<&str>
+    {
+        match self.inner() {
+            Value::String(s) | Value::ObjectPath(s) | Value::Signature(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    #[must_use]
+    pub fn as_bool(&self) -> Option<bool>
+    {
+        match self.inner() {
+            Value::Bool(b) => Some(*b),
+            _ => None,
+        }
+    }
+
+    /// Returns integer values of any width.
+    #[must_use]
+    pub fn as_i64(&self) -> Option<i64>
+    {
+        match *self.inner() {
+            Value::Byte(v) => Some(v.into()),
+            Value::Int16(v) => Some(v.into()),
+            Value::UInt16(v) => Some(v.into()),
+            Value::Int32(v) => Some(v.into()),
+            Value::UInt32(v) => Some(v.into()),
+            Value::Int64(v) => Some(v),
+            Value::UInt64(v) => i64::try_from(v).ok(),
+            Value::UnixFd(v) => Some(v.into()),
+            _ => None,
+        }
+    }
+
+    #[must_use]
+    pub fn as_f64(&self) -> Option<f64>
+    {
+        match self.inner() {
+            Value::Double(v) => Some(*v),
+            value => value.as_i64().map(|v| v as f64),
+        }
+    }
+
+    #[must_use]
+    pub fn as_array(&self) -> Option<&[Value]>
+    {
+        match self.inner() {
+            Value::Array(_, values) => Some(values),
+            _ => None,
+        }
+    }
+
+    /// Looks `key` up in a dictionary such as `a{sv}` or `a{oa{sv}}`.
+    #[must_use]
+    pub fn get(&self, key: &str) -> Option<&Value>
+    {
+        self.as_array()?.iter().find_map(|entry| match entry {
+            Value::DictEntry(k, v) if k.as_str() == Some(key) => Some(v.inner()),
+            _ => None,
+        })
+    }
+
+    /// Iterates over the entries of a dictionary.
+    pub fn entries(&self) -> impl Iterator<Item = (&Value, &Value)>
+    {
+        self.as_array()
+            .unwrap_or_default()
+            .iter()
+            .filter_map(|entry| match entry {
+                Value::DictEntry(k, v) => Some((k.as_ref(), v.inner())),
+                _ => None,
+            })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MessageType
+{
+    MethodCall,
+    MethodReturn,
+    Error,
+    Signal,
+}
+
+/// A D-Bus message.
+#[derive(Debug)]
+pub struct Message
+{
+    pub message_type: MessageType,
+    pub serial: u32,
+    pub no_reply_expected: bool,
+    pub path: Option<String>,
+    pub interface: Option<String>,
+    pub member: Option<String>,
+    pub error_name: Option<String>,
+    pub reply_serial: Option<u32>,
+    pub destination: Option<String>,
+    pub sender: Option<String>,
+    pub body: Vec<Value>,
+    /// File descriptors passed along with a received message, which
+    /// [`Value::UnixFd`]s in the body index into.
+    pub fds: Vec<OwnedFd>,
+}
+
+impl Message
+{
+    fn new(message_type: MessageType) -> Self
+    {
+        Self {
+            message_type,
+            serial: 0,
+            no_reply_expected: false,
+            path: None,
+            interface: None,
+            member: None,
+            error_name: None,
+            reply_serial: None,
+            destination: None,
+            sender: None,
+            body: Vec::new(),
+            fds: Vec::new(),
+        }
+    }
+
+    #[must_use]
+    pub fn method_call(
+        destination: &str,
+        path: &str,
+        interface: &str,
+        member: &str,
+        body: Vec<Value>,
+    ) -> Self
+    {
+        Self {
+            destination: Some(destination.to_string()),
+            path: Some(path.to_string()),
+            interface: Some(interface.to_string()),
+            member: Some(member.to_string()),
+            body,
+            ..Self::new(MessageType::MethodCall)
+        }
+    }
+
+    #[must_use]
+    pub fn signal(path: &str, interface: &str, member: &str, body: Vec<Value>) -> Self
+    {
+        Self {
+            path: Some(path.to_string()),
+            interface: Some(interface.to_string()),
+            member: Some(member.to_string()),
+            body,
+            ..Self::new(MessageType::Signal)
+        }
+    }
+
+    /// Creates the reply to the method call `call`.
+    #[must_use]
+    pub fn method_return(call: &Message, body: Vec<Value>) -> Self
+    {
+        Self {
+            reply_serial: Some(call.serial),
+            destination: call.sender.clone(),
+            body,
+            ..Self::new(MessageType::MethodReturn)
+        }
+    }
+
+    /// Creates an error reply to the method call `call`.
+    #[must_use]
+    pub fn error(call: &Message, name: &str, text: &str) -> Self
+    {
+        Self {
+            reply_serial: Some(call.serial),
+            destination: call.sender.clone(),
+            error_name: Some(name.to_string()),
+            body: vec![Value::String(text.to_string())],
+            ..Self::new(MessageType::Error)
+        }
+    }
+
+    /// Returns whether this is the signal `interface.member`.
+    #[must_use]
+    pub fn is_signal(&self, interface: &str, member: &str) -> bool
+    {
+        self.message_type == MessageType::Signal
+            && self.interface.as_deref() == Some(interface)
+            && self.member.as_deref() == Some(member)
+    }
+
+    /// Returns whether this is a call of the method `interface.member`.
+    #[must_use]
+    pub fn is_method_call(&self, interface: &str, member: &str) -> bool
+    {
+        self.message_type == MessageType::MethodCall
+            && self.interface.as_deref() == Some(interface)
+            && self.member.as_deref() == Some(member)
+    }
+
+    fn encode(&self) -> Vec<u8>
+    {
+        let mut body = Writer::default();
+        self.body.iter().for_each(|value| body.value(value));
+        let signature = self.body.iter().map(Value::signature).collect::<String>();
+
+        let mut fields = Vec::new();
+        let mut field = |code: u8, value: Value| {
+            fields.push(Value::Struct(vec![
+                Value::Byte(code),
+                Value::Variant(Box::new(value)),
+            ]))
+        };
+        if let Some(path) = &self.path {
+            field(1, Value::ObjectPath(path.clone()));
+        }
+        if let Some(interface) = &self.interface {
+            field(2, Value::String(interface.clone()));
+        }
+        if let Some(member) = &self.member {
+            field(3, Value::String(member.clone()));
+        }
+        if let Some(error_name) = &self.error_name {
+            field(4, Value::String(error_name.clone()));
+        }
+        if let Some(reply_serial) = self.reply_serial {
+            field(5, Value::UInt32(reply_serial));
+        }
+        if let Some(destination) = &self.destination {
+            field(6, Value::String(destination.clone()));
+        }
+        if !signature.is_empty() {
+            field(8, Value::Signature(signature));
+        }
+
+        let mut header = Writer::default();
+        header.buf.push(b'l');
+        header.buf.push(match self.message_type {
+            MessageType::MethodCall => 1,
+            MessageType::MethodReturn => 2,
+            MessageType::Error => 3,
+            MessageType::Signal => 4,
+        });
+        header
+            .buf
+            .push(if self.no_reply_expected { 0x1 } else { 0x0 });
+        header.buf.push(1);
+        header.u32(body.buf.len() as u32);
+        header.u32(self.serial);
+        header.value(&Value::Array("(yv)".to_string(), fields));
+        header.align(8);
+        header.buf.extend(body.buf);
+        header.buf
+    }
+
+    /// Returns the length of the message at the start of `buf`, once enough
+    /// of it has been received to tell.
+    fn encoded_len(buf: &[u8]) -> Option<usize>
+    {
+        if buf.len() < 16 {
+            return None;
+        }
+        let u32_at = |offset: usize| {
+            let bytes = [
+                buf[offset],
+                buf[offset + 1],
+                buf[offset + 2],
+                buf[offset + 3],
+            ];
+            if buf[0] == b'B' {
+                u32::from_be_bytes(bytes)
+            } else {
+                u32::from_le_bytes(bytes)
+            }
+        };
+        let body_len = u32_at(4) as usize;
+        let fields_len = u32_at(12) as usize;
+        Some(align_up(16 + fields_len, 8) + body_len)
+    }
+
+    /// Decodes the message at the start of `buf` and returns it together with
+    /// the number of file descriptors that came with it.
+    fn decode(buf: &[u8]) -> io::Result<(Self, usize)>
+    {
+        let mut reader = Reader {
+            buf,
+            pos: 0,
+            big_endian: buf.first() == Some(&b'B'),
+        };
+        reader.pos = 1;
+        let message_type = match reader.byte()? {
+            1 => MessageType::MethodCall,
+            2 => MessageType::MethodReturn,
+            3 => MessageType::Error,
+            4 => MessageType::Signal,
+            _ => return Err(invalid("unknown message type")),
+        };
+        let flags = reader.byte()?;
+        reader.byte()?;
+        reader.u32()?;
+        let mut message = Self::new(message_type);
+        message.no_reply_expected = flags & 0x1 != 0;
+        message.serial = reader.u32()?;
+
+        let mut signature = String::new();
+        let mut unix_fds = 0;
+        if let Value::Array(_, fields) = reader.value("a(yv)")? {
+            for field in fields {
+                let Value::Struct(field) = field else {
+                    continue;
+                };
+                let (Some(Value::Byte(code)), Some(value)) = (field.first(), field.get(1)) else {
+                    continue;
+                };
+                let value = value.inner();
+                let string = value.as_str().map(str::to_string);
+                match code {
+                    1 => message.path = string,
+                    2 => message.interface = string,
+                    3 => message.member = string,
+                    4 => message.error_name = string,
+                    5 => message.reply_serial = value.as_i64().map(|serial| serial as u32),
+                    6 => message.destination = string,
+                    7 => message.sender = string,
+                    8 => signature = string.unwrap_or_default(),
+                    9 => unix_fds = value.as_i64().unwrap_or(0) as usize,
+                    _ => {}
+                }
+            }
+        }
+        reader.align(8)?;
+
+        let mut signature = signature.as_str();
+        while !signature.is_empty() {
+            let (ty, rest) = split_type(signature)?;
+            message.body.push(reader.value(ty)?);
+            signature = rest;
+        }
+        Ok((message, unix_fds))
+    }
+}
+
+/// An error reply to a method call.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Error
+{
+    pub name: String,
+    pub message: String,
+}
+
+impl Display for Error
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result
+    {
+        write!(f, "{}: {}", self.name, self.message)
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl Error
+{
+    /// Returns the D-Bus error wrapped in `err`, if it is one.
+    #[must_use]
+    pub fn of(err: &io::Error) -> Option<&Error>
+    {
+        err.get_ref().and_then(|err| err.downcast_ref::<Error>())
+    }
+
+    /// Returns whether the called service does not exist, i.e. it is not
+    /// running and cannot be activated.
+    #[must_use]
+    pub fn is_service_unknown(err: &io::Error) -> bool
+    {
+        Self::of(err).is_some_and(|err| {
+            matches!(
+                err.name.as_str(),
+                "org.freedesktop.DBus.Error.ServiceUnknown"
+                    | "org.freedesktop.DBus.Error.NameHasNoOwner"
+            )
+        })
+    }
+}
+
+fn invalid(message: &str) -> io::Error
+{
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+fn align_up(pos: usize, alignment: usize) -> usize
+{
+    pos.div_ceil(alignment) * alignment
+}
+
+fn alignment(signature: &str) -> usize
+{
+    match signature.as_bytes().first() {
+        Some(b'n' | b'q') => 2,
+        Some(b'b' | b'i' | b'u' | b's' | b'o' | b'a' | b'h') => 4,
+        Some(b'x' | b't' | b'd' | b'(' | b'{') => 8,
+        _ => 1,
+    }
+}
+
+/// Splits the first complete type off `signature`.
+fn split_type(signature: &str) -> io::Result<(&str, &str)>
+{
+    let bytes = signature.as_bytes();
+    let end = match bytes.first() {
+        None => return Err(invalid("empty signature")),
+        Some(b'a') => 1 + split_type(&signature[1..])?.0.len(),
+        Some(open @ (b'(' | b'{')) => {
+            let close = if *open == b'(' { b')' } else { b'}' };
+            let mut depth = 0;
+            let mut end = None;
+            for (i, c) in bytes.iter().enumerate() {
+                if c == open {
+                    depth += 1;
+                } else if *c == close {
+                    depth -= 1;
+                    if depth == 0 {
+                        end = Some(i + 1);
+                        break;
+                    }
+                }
+            }
+            end.ok_or_else(|| invalid("unbalanced signature"))?
+        }
+        Some(_) => 1,
+    };
+    Ok(signature.split_at(end))
+}
+
+#[derive(Default)]
+struct Writer
+{
+    buf: Vec<u8>,
+}
+
+impl Writer
+{
+    fn align(&mut self, alignment: usize)
+    {
+        let len = align_up(self.buf.len(), alignment);
+        self.buf.resize(len, 0);
+    }
+
+    fn u32(&mut self, value: u32)
+    {
+        self.align(4);
+        self.buf.extend(value.to_le_bytes());
+    }
+
+    fn string(&mut self, value: &str)
+    {
+        self.u32(value.len() as u32);
+        self.buf.extend(value.as_bytes());
+        self.buf.push(0);
+    }
+
+    fn signature(&mut self, value: &str)
+    {
+        self.buf.push(value.len() as u8);
+        self.buf.extend(value.as_bytes());
+        self.buf.push(0);
+    }
+
+    fn value(&mut self, value: &Value)
+    {
+        match value {
+            Value::Byte(v) => self.buf.push(*v),
+            Value::Bool(v) => self.u32(u32::from(*v)),
+            Value::Int16(v) => {
+                self.align(2);
+                self.buf.extend(v.to_le_bytes());
+            }
+            Value::UInt16(v) => {
+                self.align(2);
+                self.buf.extend(v.to_le_bytes());
+            }
+            Value::Int32(v) => {
+                self.align(4);
+                self.buf.extend(v.to_le_bytes());
+            }
+            Value::UInt32(v) | Value::UnixFd(v) => self.u32(*v),
+            Value::Int64(v) => {
+                self.align(8);
+                self.buf.extend(v.to_le_bytes());
+            }
+            Value::UInt64(v) => {
+                self.align(8);
+                self.buf.extend(v.to_le_bytes());
+            }
+            Value::Double(v) => {
+                self.align(8);
+                self.buf.extend(v.to_le_bytes());
+            }
+            Value::String(v) | Value::ObjectPath(v) => self.string(v),
+            Value::Signature(v) => self.signature(v),
+            Value::Array(element, values) => {
+                self.u32(0);
+                let len_pos = self.buf.len() - 4;
+                self.align(alignment(element));
+                let start = self.buf.len();
+                values.iter().for_each(|value| self.value(value));
+                let len = (self.buf.len() - start) as u32;
+                self.buf[len_pos..len_pos + 4].copy_from_slice(&len.to_le_bytes());
+            }
+            Value::Struct(fields) => {
+                self.align(8);
+                fields.iter().for_each(|field| self.value(field));
+            }
+            Value::DictEntry(key, value) => {
+                self.align(8);
+                self.value(key);
+                self.value(value);
+            }
+            Value::Variant(value) => {
+                self.signature(&value.signature());
+                self.value(value);
+            }
+        }
+    }
+}
+
+struct Reader<'a>
+{
+    buf: &'a [u8],
+    pos: usize,
+    big_endian: bool,
+}
+
+impl Reader<'_>
+{
+    fn align(&mut self, alignment: usize) -> io::Result<()>
+    {
+        self.pos = align_up(self.pos, alignment);
+        if self.pos > self.buf.len() {
+            return Err(invalid("truncated message"));
+        }
+        Ok(())
+    }
+
+    fn bytes<const N: usize>(&mut self) -> io::Result<[u8; N]>
+    {
+        self.align(N)?;
+        let bytes = self
+            .buf
+            .get(self.pos..self.pos + N)
+            .ok_or_else(|| invalid("truncated message"))?;
+        self.pos += N;
+        let mut array = [0; N];
+        array.copy_from_slice(bytes);
+        if self.big_endian {
+            array.reverse();
+        }
+        Ok(array)
+    }
+
+    fn byte(&mut self) -> io::Result<u8>
+    {
+        Ok(self.bytes::<1>()?[0])
+    }
+
+    fn u32(&mut self) -> io::Result<u32>
+    {
+        Ok(u32::from_le_bytes(self.bytes()?))
+    }
+
+    fn str(&mut self, len: usize) -> io::Result<String>
+    {
+        let bytes = self
+            .buf
+            .get(self.pos..self.pos + len)
+            .ok_or_else(|| invalid("truncated message"))?;
+        self.pos += len + 1;
+        String::from_utf8(bytes.to_vec()).map_err(|_| invalid("invalid UTF-8"))
+    }
+
+    fn value(&mut self, ty: &str) -> io::Result<Value>
+    {
+        Ok(match ty.as_bytes().first() {
+            Some(b'y') => Value::Byte(self.byte()?),
+            Some(b'b') => Value::Bool(self.u32()? != 0),
+            Some(b'n') => Value::Int16(i16::from_le_bytes(self.bytes()?)),
+            Some(b'q') => Value::UInt16(u16::from_le_bytes(self.bytes()?)),
+            Some(b'i') => Value::Int32(i32::from_le_bytes(self.bytes()?)),
+            Some(b'u') => Value::UInt32(self.u32()?),
+            Some(b'h') => Value::UnixFd(self.u32()?),
+            Some(b'x') => Value::Int64(i64::from_le_bytes(self.bytes()?)),
+            Some(b't') => Value::UInt64(u64::from_le_bytes(self.bytes()?)),
+            Some(b'd') => Value::Double(f64::from_le_bytes(self.bytes()?)),
+            Some(b's') => {
+                let len = self.u32()? as usize;
+                Value::String(self.str(len)?)
+            }
+            Some(b'o') => {
+                let len = self.u32()? as usize;
+                Value::ObjectPath(self.str(len)?)
+            }
+            Some(b'g') => {
+                let len = self.byte()? as usize;
+                Value::Signature(self.str(len)?)
+            }
+            Some(b'v') => {
+                let len = self.byte()? as usize;
+                let signature = self.str(len)?;
+                Value::Variant(Box::new(self.value(&signature)?))
+            }
+            Some(b'a') => {
+                let element = &ty[1..];
+                let len = self.u32()? as usize;
+                self.align(alignment(element))?;
+                let end = self.pos + len;
+                let mut values = Vec::new();
+                while self.pos < end {
+                    values.push(self.value(element)?);
+                }
+                Value::Array(element.to_string(), values)
+            }
+            Some(b'(') => {
+                self.align(8)?;
+                let mut fields = Vec::new();
+                let mut signature = &ty[1..ty.len() - 1];
+                while !signature.is_empty() {
+                    let (field, rest) = split_type(signature)?;
+                    fields.push(self.value(field)?);
+                    signature = rest;
+                }
+                Value::Struct(fields)
+            }
+            Some(b'{') => {
+                self.align(8)?;
+                let (key, value) = split_type(&ty[1..ty.len() - 1])?;
+                let key = self.value(key)?;
+                let value = self.value(value)?;
+                Value::DictEntry(Box::new(key), Box::new(value))
+            }
+            _ => return Err(invalid("unsupported type in signature")),
+        })
+    }
+}
+
+/// A connection to a message bus.
+///
+/// The socket is non-blocking so that the connection can be registered with
+/// a mio `Poll`; [`Connection::call`] still blocks until the reply arrives.
+/// Messages received while waiting for a reply are kept for
+/// [`Connection::read`].
+///
+/// This speaks the wire protocol itself instead of going through zbus, which
+/// only comes in with the `tray` feature. The library needs a handful of
+/// calls, properties and signals on two buses, and a client of its own keeps
+/// the default build at libc and udev, lets the connections share the mio
+/// `Poll` of the daemon without an executor thread, and keeps the system
+/// calls of the monitoring loop few enough for the seccomp filter of
+/// `--sandbox`.
+pub struct Connection
+{
+    stream: UnixStream,
+    serial: u32,
+    buf: Vec<u8>,
+    queue: VecDeque<Message>,
+    /// File descriptors received ahead of the messages they belong to.
+    fds: VecDeque<OwnedFd>,
+    unique_name: String,
+}
+
+impl Connection
+{
+    /// Connects to the system bus.
+    pub fn system() -> io::Result<Self>
+    {
+        let address = env::var("DBUS_SYSTEM_BUS_ADDRESS");
+        Self::open(address.as_deref().unwrap_or(SYSTEM_BUS_ADDRESS))
+    }
+
+    /// Connects to the session bus of the user running the process.
+    pub fn session() -> io::Result<Self>
+    {
+        let address = env::var("DBUS_SESSION_BUS_ADDRESS").map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                "DBUS_SESSION_BUS_ADDRESS is not set",
+            )
+        })?;
+        Self::open(&address)
+    }
+
+    /// Connects to the bus at `address`, for example
+    /// `unix:path=/run/user/1000/bus`.
+    pub fn open(address: &str) -> io::Result<Self>
+    {
+        let mut stream = None;
+        for address in address.split(';') {
+            let Some(params) = address.strip_prefix("unix:") else {
+                continue;
+            };
+            for param in params.split(',') {
+                let addr = if let Some(path) = param.strip_prefix("path=") {
+                    SocketAddr::from_pathname(path)?
+                } else if let Some(name) = param.strip_prefix("abstract=") {
+                    SocketAddr::from_abstract_name(name)?
+                } else {
+                    continue;
+                };
+                if let Ok(s) = UnixStream::connect_addr(&addr) {
+                    stream = Some(s);
+                }
+            }
+        }
+        let stream = stream.ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("cannot connect to the bus at `{}`", address),
+            )
+        })?;
+
+        Self::authenticate(&stream)?;
+        stream.set_nonblocking(true)?;
+        let mut conn = Self {
+            stream,
+            serial: 0,
+            buf: Vec::new(),
+            queue: VecDeque::new(),
+            fds: VecDeque::new(),
+            unique_name: String::new(),
+        };
+        let reply = conn.call(Message::method_call(
+            "org.freedesktop.DBus",
+            "/org/freedesktop/DBus",
+            "org.freedesktop.DBus",
+            "Hello",
+            Vec::new(),
+        ))?;
+        conn.unique_name = reply
+            .body
+            .first()
+            .and_then(Value::as_str)
+            .unwrap_or_default()
+            .to_string();
+        Ok(conn)
+    }
+
+    fn authenticate(stream: &UnixStream) -> io::Result<()>
+    {
+        let uid = unsafe { libc::geteuid() }.to_string();
+        let hex_uid = uid
+            .bytes()
+            .map(|b| format!("{:02x}", b))
+            .collect::<String>();
+        let mut writer = stream;
+        writer.write_all(b"\0")?;
+        writer.write_all(format!("AUTH EXTERNAL {}\r\n", hex_uid).as_bytes())?;
+        let mut reader = BufReader::new(stream);
+        let mut line = String::new();
+        reader.read_line(&mut line)?;
+        if !line.starts_with("OK ") {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                format!("bus authentication failed: {}", line.trim()),
+            ));
+        }
+        // Without file descriptor passing the connection still works, only
+        // methods returning one fail.
+        writer.write_all(b"NEGOTIATE_UNIX_FD\r\n")?;
+        line.clear();
+        reader.read_line(&mut line)?;
+        writer.write_all(b"BEGIN\r\n")
+    }
+
+    /// Returns the unique name assigned to this connection by the bus.
+    #[must_use]
+    pub fn unique_name(&self) -> &str
+    {
+        &self.unique_name
+    }
+
+    /// Sends `message` and returns the serial it was sent with.
+    pub fn send(&mut self, mut message: Message) -> io::Result<u32>
+    {
+        self.serial = self.serial.wrapping_add(1).max(1);
+        message.serial = self.serial;
+        let buf = message.encode();
+        let mut written = 0;
+        while written < buf.len() {
+            match self.stream.write(&buf[written..]) {
+                Ok(len) => written += len,
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => {
+                    self.wait(libc::POLLOUT, DEFAULT_TIMEOUT)?;
+                }
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(message.serial)
+    }
+
+    /// Calls a method and waits for its reply. Error replies are returned as
+    /// an [`Error`] wrapped in an `io::Error`.
+    pub fn call(&mut self, message: Message) -> io::Result<Message>
+    {
+        self.call_with_timeout(message, DEFAULT_TIMEOUT)
+    }
+
+    pub fn call_with_timeout(&mut self, message: Message, timeout: Duration)
+        -> io::Result<Message>
+    {
+        let serial = self.send(message)?;
+        let deadline = Instant::now() + timeout;
+        loop {
+            while let Some(message) = self.receive()? {
+                if message.reply_serial != Some(serial) {
+                    self.queue.push_back(message);
+                    continue;
+                }
+                if message.message_type == MessageType::Error {
+                    let error = Error {
+                        name: message.error_name.unwrap_or_default(),
+                        message: message
+                            .body
+                            .first()
+                            .and_then(Value::as_str)
+                            .unwrap_or_default()
+                            .to_string(),
+                    };
+                    return Err(io::Error::other(error));
+                }
+                return Ok(message);
+            }
+            let left = deadline.saturating_duration_since(Instant::now());
+            if left.is_zero() {
+                return Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    "no reply from the bus",
+                ));
+            }
+            self.wait(libc::POLLIN, left)?;
+        }
+    }
+
+    /// Calls `org.freedesktop.DBus.Properties.Get` and returns the value.
+    pub fn get_property(
+        &mut self,
+        destination: &str,
+        path: &str,
+        interface: &str,
+        property: &str,
+    ) -> io::Result<Value>
+    {
+        let reply = self.call(Message::method_call(
+            destination,
+            path,
+            "org.freedesktop.DBus.Properties",
+            "Get",
+            vec![
+                Value::String(interface.to_string()),
+                Value::String(property.to_string()),
+            ],
+        ))?;
+        reply
+            .body
+            .into_iter()
+            .next()
+            .map(|value| value.inner().clone())
+            .ok_or_else(|| invalid("empty reply"))
+    }
+
+    /// Calls `org.freedesktop.DBus.Properties.Set`.
+    pub fn set_property(
+        &mut self,
+        destination: &str,
+        path: &str,
+        interface: &str,
+        property: &str,
+        value: Value,
+    ) -> io::Result<()>
+    {
+        self.call(Message::method_call(
+            destination,
+            path,
+            "org.freedesktop.DBus.Properties",
+            "Set",
+            vec![
+                Value::String(interface.to_string()),
+                Value::String(property.to_string()),
+                Value::Variant(Box::new(value)),
+            ],
+        ))?;
+        Ok(())
+    }
+
+    /// Asks the bus to route signals matching `rule` to this connection.
+    pub fn add_match(&mut self, rule: &str) -> io::Result<()>
+    {
+        self.call(Message::method_call(
+            "org.freedesktop.DBus",
+            "/org/freedesktop/DBus",
+            "org.freedesktop.DBus",
+            "AddMatch",
+            vec![Value::String(rule.to_string())],
+        ))?;
+        Ok(())
+    }
+
+    /// Returns the next message that was received, without blocking.
+    pub fn read(&mut self) -> io::Result<Option<Message>>
+    {
+        match self.queue.pop_front() {
+            Some(message) => Ok(Some(message)),
+            None => self.receive(),
+        }
+    }
+
+    /// Reads from the socket until a complete message is buffered or the
+    /// socket has no more data.
+    fn receive(&mut self) -> io::Result<Option<Message>>
+    {
+        loop {
+            if let Some(len) = Message::encoded_len(&self.buf) {
+                if self.buf.len() >= len {
+                    let message = Message::decode(&self.buf[..len]);
+                    self.buf.drain(..len);
+                    let (mut message, unix_fds) = message?;
+                    let unix_fds = unix_fds.min(self.fds.len());
+                    message.fds = self.fds.drain(..unix_fds).collect();
+                    return Ok(Some(message));
+                }
+            }
+            let mut chunk = [0; 4096];
+            match self.recv(&mut chunk) {
+                Ok(0) => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::UnexpectedEof,
+                        "the bus closed the connection",
+                    ))
+                }
+                Ok(len) => self.buf.extend_from_slice(&chunk[..len]),
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => return Ok(None),
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    /// Reads from the socket like `read(2)`, keeping the file descriptors
+    /// that came along.
+    fn recv(&mut self, chunk: &mut [u8]) -> io::Result<usize>
+    {
+        const MAX_FDS: usize = 16;
+        let mut control = [0u64; unsafe {
+            libc::CMSG_SPACE((MAX_FDS * std::mem::size_of::<libc::c_int>()) as u32) as usize
+        } / 8];
+        let mut iov = libc::iovec {
+            iov_base: chunk.as_mut_ptr().cast(),
+            iov_len: chunk.len(),
+        };
+        let mut msg: libc::msghdr = unsafe { std::mem::zeroed() };
+        msg.msg_iov = &mut iov;
+        msg.msg_iovlen = 1;
+        msg.msg_control = control.as_mut_ptr().cast();
+        msg.msg_controllen = std::mem::size_of_val(&control);
+        let len =
+            unsafe { libc::recvmsg(self.stream.as_raw_fd(), &mut msg, libc::MSG_CMSG_CLOEXEC) };
+        if len < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let mut cmsg = unsafe { libc::CMSG_FIRSTHDR(&msg) };
+        while !cmsg.is_null() {
+            let header = unsafe { &*cmsg };
+            if header.cmsg_level == libc::SOL_SOCKET && header.cmsg_type == libc::SCM_RIGHTS {
+                let data = unsafe { libc::CMSG_DATA(cmsg) }.cast::<libc::c_int>();
+                let count = (header.cmsg_len as usize - unsafe { libc::CMSG_LEN(0) } as usize)
+                    / std::mem::size_of::<libc::c_int>();
+                for i in 0..count {
+                    let fd = unsafe { data.add(i).read_unaligned() };
+                    self.fds.push_back(unsafe { OwnedFd::from_raw_fd(fd) });
+                }
+            }
+            cmsg = unsafe { libc::CMSG_NXTHDR(&msg, cmsg) };
+        }
+        Ok(len as usize)
+    }
+
+    fn wait(&self, events: libc::c_short, timeout: Duration) -> io::Result<()>
+    {
+        let mut fd = libc::pollfd {
+            fd: self.stream.as_raw_fd(),
+            events,
+            revents: 0,
+        };
+        let timeout = timeout.as_millis().min(i32::MAX as u128) as libc::c_int;
+        if unsafe { libc::poll(&mut fd, 1, timeout) } < 0 {
+            let err = io::Error::last_os_error();
+            if err.kind() != io::ErrorKind::Interrupted {
+                return Err(err);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl AsRawFd for Connection
+{
+    fn as_raw_fd(&self) -> std::os::fd::RawFd
+    {
+        self.stream.as_raw_fd()
+    }
+}
+
+#[cfg(feature = "mio")]
+impl Source for Connection
+{
+    fn register(
+        &mut self,
+        registry: &mio::Registry,
+        token: mio::Token,
+        interests: mio::Interest,
+    ) -> io::Result<()>
+    {
+        SourceFd(&self.stream.as_raw_fd()).register(registry, token, interests)
+    }
+
+    fn reregister(
+        &mut self,
+        registry: &mio::Registry,
+        token: mio::Token,
+        interests: mio::Interest,
+    ) -> io::Result<()>
+    {
+        SourceFd(&self.stream.as_raw_fd()).reregister(registry, token, interests)
+    }
+
+    fn deregister(&mut self, registry: &mio::Registry) -> io::Result<()>
+    {
+        SourceFd(&self.stream.as_raw_fd()).deregister(registry)
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    fn encode(value: &Value) -> Vec<u8>
+    {
+        let mut writer = Writer::default();
+        writer.value(value);
+        writer.buf
+    }
+
+    fn decode(ty: &str, buf: &[u8]) -> io::Result<Value>
+    {
+        Reader {
+            buf,
+            pos: 0,
+            big_endian: false,
+        }
+        .value(ty)
+    }
+
+    #[test]
+    fn marshals_basic_types()
+    {
+        assert_eq!(encode(&Value::String("ab".to_string())), b"\x02\0\0\0ab\0");
+        assert_eq!(encode(&Value::Signature("sv".to_string())), b"\x02sv\0");
+        assert_eq!(encode(&Value::Bool(true)), [1, 0, 0, 0]);
+        assert_eq!(encode(&Value::Int16(-2)), [0xfe, 0xff]);
+        assert_eq!(
+            encode(&Value::Variant(Box::new(Value::UInt32(7)))),
+            [1, b'u', 0, 0, 7, 0, 0, 0]
+        );
+    }
+
+    #[test]
+    fn pads_empty_arrays_to_their_elements()
+    {
+        // The length, then padding to the 8-byte alignment of the structs,
+        // which is there even without elements.
+        assert_eq!(
+            encode(&Value::Array("(ss)".to_string(), Vec::new())),
+            [0; 8]
+        );
+        assert_eq!(
+            decode("a(ss)", &[0; 8]).unwrap(),
+            Value::Array("(ss)".to_string(), Vec::new())
+        );
+    }
+
+    #[test]
+    fn round_trips_values()
+    {
+        let values = [
+            ("y", Value::Byte(200)),
+            ("q", Value::UInt16(65535)),
+            ("i", Value::Int32(-5)),
+            ("x", Value::Int64(i64::MIN)),
+            ("t", Value::UInt64(u64::MAX)),
+            ("d", Value::Double(1.5)),
+            ("o", Value::ObjectPath("/org/lithiumd".to_string())),
+            (
+                "ai",
+                Value::Array("i".to_string(), vec![Value::Int32(1), Value::Int32(2)]),
+            ),
+            (
+                "(ybs)",
+                Value::Struct(vec![
+                    Value::Byte(1),
+                    Value::Bool(false),
+                    Value::String("x".to_string()),
+                ]),
+            ),
+            (
+                "a{sv}",
+                Value::dict(vec![
+                    ("Percentage", Value::Double(80.0)),
+                    ("IconName", Value::String("battery".to_string())),
+                ]),
+            ),
+        ];
+        for (ty, value) in values {
+            assert_eq!(value.signature(), ty);
+            assert_eq!(decode(ty, &encode(&value)).unwrap(), value, "{}", ty);
+        }
+    }
+
+    #[test]
+    fn round_trips_messages()
+    {
+        let mut call = Message::method_call(
+            "org.freedesktop.UPower",
+            "/org/freedesktop/UPower/devices/DisplayDevice",
+            "org.freedesktop.DBus.Properties",
+            "Get",
+            vec![
+                Value::String("org.freedesktop.UPower.Device".to_string()),
+                Value::String("Percentage".to_string()),
+            ],
+        );
+        call.serial = 3;
+        let buf = call.encode();
+        assert_eq!(Message::encoded_len(&buf), Some(buf.len()));
+        assert_eq!(Message::encoded_len(&buf[..15]), None);
+
+        let (decoded, fds) = Message::decode(&buf).unwrap();
+        assert_eq!(fds, 0);
+        assert!(decoded.is_method_call("org.freedesktop.DBus.Properties", "Get"));
+        assert_eq!(decoded.serial, 3);
+        assert_eq!(decoded.destination, call.destination);
+        assert_eq!(decoded.path, call.path);
+        assert_eq!(decoded.body, call.body);
+
+        let reply = Message::error(&decoded, "org.freedesktop.DBus.Error.Failed", "no");
+        let (reply, _) = Message::decode(&reply.encode()).unwrap();
+        assert_eq!(reply.message_type, MessageType::Error);
+        assert_eq!(reply.reply_serial, Some(3));
+        assert_eq!(
+            reply.error_name.as_deref(),
+            Some("org.freedesktop.DBus.Error.Failed")
+        );
+        assert_eq!(reply.body, vec![Value::String("no".to_string())]);
+    }
+
+    #[test]
+    fn decodes_big_endian_messages()
+    {
+        // A method return to serial 7 with the body `u` 42.
+        let mut buf = vec![b'B', 2, 0, 1, 0, 0, 0, 4, 0, 0, 0, 9, 0, 0, 0, 15];
+        buf.extend([5, 1, b'u', 0, 0, 0, 0, 7]);
+        buf.extend([8, 1, b'g', 0, 1, b'u', 0, 0]);
+        buf.extend([0, 0, 0, 42]);
+        assert_eq!(Message::encoded_len(&buf), Some(buf.len()));
+
+        let (message, _) = Message::decode(&buf).unwrap();
+        assert_eq!(message.message_type, MessageType::MethodReturn);
+        assert_eq!(message.serial, 9);
+        assert_eq!(message.reply_serial, Some(7));
+        assert_eq!(message.body, vec![Value::UInt32(42)]);
+    }
+
+    #[test]
+    fn rejects_malformed_messages()
+    {
+        let mut buf = Message::signal("/", "a.b", "C", vec![Value::UInt32(1)]).encode();
+        assert!(Message::decode(&buf[..buf.len() - 2]).is_err());
+        buf[1] = 9;
+        assert!(Message::decode(&buf).is_err());
+        assert!(decode("s", &[10, 0, 0, 0, b'a']).is_err());
+        assert!(decode("z", &[0]).is_err());
+    }
+
+    #[test]
+    fn splits_signatures()
+    {
+        assert_eq!(split_type("a{sv}s").unwrap(), ("a{sv}", "s"));
+        assert_eq!(split_type("(a(ss)u)y").unwrap(), ("(a(ss)u)", "y"));
+        assert_eq!(split_type("aai").unwrap(), ("aai", ""));
+        assert!(split_type("(ss").is_err());
+        assert!(split_type("").is_err());
+    }
+
+    #[test]
+    fn reads_dictionaries()
+    {
+        let dict = Value::dict(vec![("Online", Value::Bool(true))]);
+        assert_eq!(dict.get("Online").and_then(Value::as_bool), Some(true));
+        assert_eq!(dict.get("Missing"), None);
+    }
+}
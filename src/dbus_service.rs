@@ -0,0 +1,251 @@
+use std::io;
+
+use lithiumd::dbus::Connection;
+use lithiumd::dbus::Message;
+use lithiumd::dbus::MessageType;
+use lithiumd::dbus::Value;
+use lithiumd::EventFilter;
+use lithiumd::PowerEvent;
+use lithiumd::Snapshot;
+use mio::event::Source;
+
+const NAME: &str = "org.lithiumd";
+const PATH: &str = "/org/lithiumd";
+const INTERFACE: &str = "org.lithiumd.Daemon";
+
+const INTROSPECTION: &str = r#"<!DOCTYPE node PUBLIC "-//freedesktop//DTD D-BUS Object Introspection 1.0//EN"
+ "http://www.freedesktop.org/standards/dbus/1.0/introspect.dtd">
+<node>
+  <interface name="org.lithiumd.Daemon">
+    <method name="Subscribe">
+      <arg name="filter" type="a{sv}" direction="in"/>
+      <arg name="subscription" type="u" direction="out"/>
+    </method>
+    <method name="Unsubscribe">
+      <arg name="subscription" type="u" direction="in"/>
+    </method>
+    <signal name="Event">
+      <arg name="subscription" type="u"/>
+      <arg name="device" type="s"/>
+      <arg name="description" type="s"/>
+    </signal>
+  </interface>
+</node>
+"#;
+
+/// Which bus the service is offered on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Bus
+{
+    System,
+    Session,
+}
+
+struct Subscription
+{
+    id: u32,
+    /// Unique name of the subscribed client.
+    client: String,
+    filter: EventFilter,
+}
+
+/// The `org.lithiumd` service. Clients call `Subscribe` with a filter, such
+/// as `{"device": <"BAT1">, "capacity_below": <20>}`, and receive the events
+/// passing it as `Event` signals sent to them alone. Subscriptions end with
+/// `Unsubscribe` or when the client disconnects.
+pub struct DbusService
+{
+    conn: Connection,
+    bus: Bus,
+    subscriptions: Vec<Subscription>,
+    next_id: u32,
+}
+
+impl DbusService
+{
+    pub fn new(bus: Bus) -> io::Result<Self>
+    {
+        let mut conn = match bus {
+            Bus::System => Connection::system()?,
+            Bus::Session => Connection::session()?,
+        };
+        // Fail instead of queueing behind another daemon.
+        const DO_NOT_QUEUE: u32 = 0x4;
+        let reply = conn.call(Message::method_call(
+            "org.freedesktop.DBus",
+            "/org/freedesktop/DBus",
+            "org.freedesktop.DBus",
+            "RequestName",
+            vec![Value::String(NAME.to_string()), Value::UInt32(DO_NOT_QUEUE)],
+        ))?;
+        if reply.body.first().and_then(Value::as_i64) != Some(1) {
+            return Err(io::Error::new(
+                io::ErrorKind::AddrInUse,
+                format!("{} is already owned", NAME),
+            ));
+        }
+        conn.add_match(
+            "type='signal',sender='org.freedesktop.DBus',member='NameOwnerChanged',arg2=''",
+        )?;
+        Ok(Self {
+            conn,
+            bus,
+            subscriptions: Vec::new(),
+            next_id: 1,
+        })
+    }
+
+    #[must_use]
+    pub fn bus(&self) -> Bus
+    {
+        self.bus
+    }
+
+    /// Answers pending method calls and drops the subscriptions of clients
+    /// that disconnected.
+    pub fn process(&mut self) -> io::Result<()>
+    {
+        while let Some(message) = self.conn.read()? {
+            if message.is_signal("org.freedesktop.DBus", "NameOwnerChanged") {
+                if let Some(name) = message.body.first().and_then(Value::as_str) {
+                    self.subscriptions.retain(|sub| sub.client != name);
+                }
+                continue;
+            }
+            if message.message_type != MessageType::MethodCall {
+                continue;
+            }
+            let reply = self.handle_call(&message);
+            if !message.no_reply_expected {
+                self.conn.send(reply)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn handle_call(&mut self, call: &Message) -> Message
+    {
+        if call.is_method_call("org.freedesktop.DBus.Introspectable", "Introspect") {
+            return Message::method_return(call, vec![Value::String(INTROSPECTION.to_string())]);
+        }
+        if call.is_method_call("org.freedesktop.DBus.Peer", "Ping") {
+            return Message::method_return(call, Vec::new());
+        }
+        if call.is_method_call(INTERFACE, "Subscribe") {
+            let filter = match call.body.first().map(parse_filter) {
+                Some(Ok(filter)) => filter,
+                Some(Err(err)) => {
+                    return Message::error(call, "org.freedesktop.DBus.Error.InvalidArgs", &err)
+                }
+                None => EventFilter::default(),
+            };
+            let id = self.next_id;
+            self.next_id += 1;
+            self.subscriptions.push(Subscription {
+                id,
+                client: call.sender.clone().unwrap_or_default(),
+                filter,
+            });
+            return Message::method_return(call, vec![Value::UInt32(id)]);
+        }
+        if call.is_method_call(INTERFACE, "Unsubscribe") {
+            let id = call.body.first().and_then(Value::as_i64);
+            let sender = call.sender.as_deref().unwrap_or_default();
+            self.subscriptions
+                .retain(|sub| !(Some(i64::from(sub.id)) == id && sub.client == sender));
+            return Message::method_return(call, Vec::new());
+        }
+        Message::error(
+            call,
+            "org.freedesktop.DBus.Error.UnknownMethod",
+            &format!(
+                "unknown method {}.{}",
+                call.interface.as_deref().unwrap_or_default(),
+                call.member.as_deref().unwrap_or_default()
+            ),
+        )
+    }
+
+    /// Sends `event` to the subscribers whose filter it passes.
+    pub fn emit(&mut self, event: &PowerEvent, snapshot: &Snapshot) -> io::Result<()>
+    {
+        for sub in &self.subscriptions {
+            if !sub.filter.matches(event, snapshot) {
+                continue;
+            }
+            let mut signal = Message::signal(
+                PATH,
+                INTERFACE,
+                "Event",
+                vec![
+                    Value::UInt32(sub.id),
+                    Value::String(event.sysname().unwrap_or_default().to_string()),
+                    Value::String(event.to_string()),
+                ],
+            );
+            signal.destination = Some(sub.client.clone());
+            self.conn.send(signal)?;
+        }
+        Ok(())
+    }
+}
+
+/// Parses the `a{sv}` filter of `Subscribe`.
+fn parse_filter(value: &Value) -> Result<EventFilter, String>
+{
+    let percent = |key: &str, value: &Value| {
+        value
+            .as_i64()
+            .filter(|percent| (0..=100).contains(percent))
+            .map(|percent| percent as u8)
+            .ok_or_else(|| format!("`{}` must be a percentage", key))
+    };
+    let mut filter = EventFilter::default();
+    for (key, value) in value.entries() {
+        match key.as_str() {
+            Some("device") => {
+                let device = value
+                    .as_str()
+                    .ok_or_else(|| "`device` must be a string".to_string())?;
+                filter.device = Some(device.to_string());
+            }
+            Some("capacity_below") => {
+                filter.capacity_below = Some(percent("capacity_below", value)?)
+            }
+            Some("capacity_above") => {
+                filter.capacity_above = Some(percent("capacity_above", value)?)
+            }
+            Some(key) => return Err(format!("unknown filter `{}`", key)),
+            None => return Err("the filter must be of type a{sv}".to_string()),
+        }
+    }
+    Ok(filter)
+}
+
+impl Source for DbusService
+{
+    fn register(
+        &mut self,
+        registry: &mio::Registry,
+        token: mio::Token,
+        interests: mio::Interest,
+    ) -> io::Result<()>
+    {
+        self.conn.register(registry, token, interests)
+    }
+
+    fn reregister(
+        &mut self,
+        registry: &mio::Registry,
+        token: mio::Token,
+        interests: mio::Interest,
+    ) -> io::Result<()>
+    {
+        self.conn.reregister(registry, token, interests)
+    }
+
+    fn deregister(&mut self, registry: &mio::Registry) -> io::Result<()>
+    {
+        self.conn.deregister(registry)
+    }
+}
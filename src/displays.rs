@@ -0,0 +1,131 @@
+use std::fs;
+use std::io;
+#[cfg(feature = "mio")]
+use std::os::fd::AsRawFd;
+
+#[cfg(feature = "mio")]
+use mio::event::Source;
+#[cfg(feature = "mio")]
+use mio::unix::SourceFd;
+use udev::MonitorSocket;
+
+const DRM: &str = "/sys/class/drm";
+
+/// Connector types of built-in panels, which do not count as docking.
+const INTERNAL: [&str; 3] = ["eDP", "LVDS", "DSI"];
+
+/// Tracks whether an external display is connected, from the connectors of
+/// the `drm` subsystem. Connecting or disconnecting a display makes the card
+/// emit a hotplug uevent.
+///
+/// Register it with a mio `Poll` and call [`Displays::process`] when it
+/// becomes readable.
+pub struct Displays
+{
+    socket: MonitorSocket,
+    external: Vec<String>,
+}
+
+impl Displays
+{
+    pub fn new() -> io::Result<Self>
+    {
+        let socket = udev::MonitorBuilder::new()?
+            .match_subsystem("drm")?
+            .listen()?;
+        Ok(Self {
+            socket,
+            external: connected_external()?,
+        })
+    }
+
+    /// Returns whether at least one external display is connected.
+    #[must_use]
+    pub fn is_docked(&self) -> bool
+    {
+        !self.external.is_empty()
+    }
+
+    /// Returns the connected external connectors, e.g. `card1-HDMI-A-1`.
+    #[must_use]
+    pub fn external(&self) -> &[String]
+    {
+        &self.external
+    }
+
+    /// Reads the pending uevents and returns whether the set of connected
+    /// external displays changed.
+    pub fn process(&mut self) -> io::Result<bool>
+    {
+        // The uevents only say that something changed on a card, so the
+        // connectors are read again.
+        if self.socket.iter().count() == 0 {
+            return Ok(false);
+        }
+        let external = connected_external()?;
+        let changed = external != self.external;
+        self.external = external;
+        Ok(changed)
+    }
+}
+
+/// Returns the connectors under `/sys/class/drm` that are connected and are
+/// not a built-in panel, sorted by name.
+fn connected_external() -> io::Result<Vec<String>>
+{
+    let entries = match fs::read_dir(DRM) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err),
+    };
+    let mut external = Vec::new();
+    for entry in entries {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        // Connectors are named `card<N>-<type>-<index>`.
+        let Some((_, connector)) = name.split_once('-') else {
+            continue;
+        };
+        if INTERNAL
+            .iter()
+            .any(|internal| connector.starts_with(internal))
+        {
+            continue;
+        }
+        let status = fs::read_to_string(entry.path().join("status")).unwrap_or_default();
+        if status.trim() == "connected" {
+            external.push(name);
+        }
+    }
+    external.sort();
+    Ok(external)
+}
+
+#[cfg(feature = "mio")]
+impl Source for Displays
+{
+    fn register(
+        &mut self,
+        registry: &mio::Registry,
+        token: mio::Token,
+        interests: mio::Interest,
+    ) -> io::Result<()>
+    {
+        SourceFd(&self.socket.as_raw_fd()).register(registry, token, interests)
+    }
+
+    fn reregister(
+        &mut self,
+        registry: &mio::Registry,
+        token: mio::Token,
+        interests: mio::Interest,
+    ) -> io::Result<()>
+    {
+        SourceFd(&self.socket.as_raw_fd()).reregister(registry, token, interests)
+    }
+
+    fn deregister(&mut self, registry: &mio::Registry) -> io::Result<()>
+    {
+        SourceFd(&self.socket.as_raw_fd()).deregister(registry)
+    }
+}
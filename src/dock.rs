@@ -0,0 +1,65 @@
+use std::ffi::OsStr;
+use std::fmt::Display;
+use std::io;
+
+/// A docking station, or another Thunderbolt or USB4 device attached to the
+/// machine.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Dock
+{
+    /// Kernel name of the device, e.g. `0-1`.
+    pub sysname: String,
+    /// Vendor and model, if the device reports them.
+    pub name: Option<String>,
+}
+
+impl Dock
+{
+    /// Returns the dock that `dev` of the `thunderbolt` subsystem is, or
+    /// `None` for host routers, which are part of the machine, and for the
+    /// domains, services and retimers that the subsystem also has.
+    pub(crate) fn of(dev: &udev::Device) -> Option<Self>
+    {
+        if dev.devtype() != Some(OsStr::new("thunderbolt_device")) {
+            return None;
+        }
+        let sysname = dev.sysname().to_string_lossy().into_owned();
+        // Host routers are the routers at route 0 of their domain, `<N>-0`.
+        if sysname.ends_with("-0") {
+            return None;
+        }
+        let attribute = |name| {
+            dev.attribute_value(name)
+                .map(|value| value.to_string_lossy().trim().to_string())
+                .filter(|value| !value.is_empty())
+        };
+        let name = match (attribute("vendor_name"), attribute("device_name")) {
+            (Some(vendor), Some(device)) => Some(format!("{} {}", vendor, device)),
+            (vendor, device) => vendor.or(device),
+        };
+        Some(Self { sysname, name })
+    }
+
+    /// Returns the docks that are attached.
+    pub(crate) fn scan() -> io::Result<Vec<Self>>
+    {
+        let mut enumerator = udev::Enumerator::new()?;
+        enumerator.match_subsystem("thunderbolt")?;
+        Ok(enumerator
+            .scan_devices()?
+            .filter_map(|dev| Self::of(&dev))
+            .collect())
+    }
+}
+
+impl Display for Dock
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result
+    {
+        match &self.name {
+            Some(name) => write!(f, "{} ({})", name, self.sysname),
+            None => write!(f, "{}", self.sysname),
+        }
+    }
+}
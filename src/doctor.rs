@@ -0,0 +1,181 @@
+use std::io;
+use std::path::Path;
+use std::process::ExitCode;
+use std::time::Duration;
+
+use lithiumd::BackendDevice;
+use lithiumd::PowerBackend;
+use lithiumd::SysfsPoller;
+use lithiumd::UdevBackend;
+
+/// Attributes of batteries and what they are read for. Each entry is met by
+/// any one of its attributes, e.g. `energy_now` or `charge_now`.
+const BATTERY_ATTRIBUTES: &[(&[&str], &str)] = &[
+    (&["status"], "the charging status"),
+    (&["capacity"], "the capacity and the low battery warnings"),
+    (&["energy_now", "charge_now"], "the time remaining"),
+    (&["power_now", "current_now"], "the drain rate"),
+    (
+        &["energy_full_design", "charge_full_design"],
+        "the health of `lithiumd health`",
+    ),
+    (&["cycle_count"], "the cycle count of `lithiumd health`"),
+    (
+        &["charge_control_end_threshold"],
+        "charge limits without a vendor interface",
+    ),
+];
+
+/// Attributes without which the monitor does not work at all.
+const REQUIRED_ATTRIBUTES: &[&str] = &["status", "online"];
+
+/// Runs `lithiumd doctor`: checks that uevents can be received, lists the
+/// power supplies with how they were classified and the attributes they
+/// lack, and prints a hint for every problem. Fails if there are problems,
+/// so that bug reports can start with its output.
+pub fn run() -> io::Result<ExitCode>
+{
+    let mut problems = 0;
+    let mut problem = |message: &str, hint: &str| {
+        problems += 1;
+        println!("  problem: {}", message);
+        println!("  hint: {}", hint);
+    };
+
+    println!("udev:");
+    let mut udev = UdevBackend::new();
+    match udev.fd() {
+        Ok(_) => println!("  monitor socket: ok"),
+        Err(err) => problem(
+            &format!("cannot open the monitor socket: {}", err),
+            "changes are only seen when polling; allow netlink sockets (AF_NETLINK) in the \
+             sandbox or service unit that runs lithiumd",
+        ),
+    }
+    let devices = match udev.enumerate() {
+        Ok(devices) => devices,
+        Err(err) => {
+            problem(
+                &format!("cannot enumerate the devices through udev: {}", err),
+                "check that /run/udev is readable; the devices below were read from sysfs",
+            );
+            SysfsPoller::new(Duration::from_secs(60))?.enumerate()?
+        }
+    };
+
+    println!("devices:");
+    if devices.is_empty() {
+        println!("  none in /sys/class/power_supply");
+    }
+    let mut batteries = 0;
+    let mut adapters = 0;
+    for dev in &devices {
+        let (kind, how) = classify(dev);
+        println!(
+            "  {}: {}, {} (type {}, scope {})",
+            dev.sysname,
+            kind.unwrap_or("ignored"),
+            how,
+            dev.property_value("POWER_SUPPLY_TYPE").unwrap_or("unknown"),
+            dev.property_value("POWER_SUPPLY_SCOPE").unwrap_or("System"),
+        );
+        let attributes: &[(&[&str], &str)] = match kind {
+            Some("battery") if dev.property_value("POWER_SUPPLY_SCOPE") == Some("Device") => {
+                println!("    a peripheral, only tracked with `enabled = true` in [peripherals]");
+                continue;
+            }
+            Some("battery") => {
+                batteries += 1;
+                BATTERY_ATTRIBUTES
+            }
+            Some("adapter") => {
+                adapters += 1;
+                &[(&["online"], "whether the system is on AC")]
+            }
+            Some("wireless charger") => &[(&["online"], "whether the system charges wirelessly")],
+            _ => continue,
+        };
+        for (names, purpose) in attributes {
+            if names.iter().any(|name| has_attribute(&dev.syspath, name)) {
+                continue;
+            }
+            if REQUIRED_ATTRIBUTES.contains(&names[0]) {
+                problem(
+                    &format!(
+                        "{} has no `{}`, needed for {}",
+                        dev.sysname, names[0], purpose
+                    ),
+                    "the driver does not report it; please open an issue with this output",
+                );
+            } else {
+                println!(
+                    "    no `{}`: {} is not available",
+                    names.join("` or `"),
+                    purpose
+                );
+            }
+        }
+    }
+    if batteries == 0 {
+        problem(
+            "no battery was detected",
+            "if the battery has an unusual name, pin it with `--battery GLOB`",
+        );
+    }
+    if adapters == 0 {
+        problem(
+            "no adapter was detected",
+            "if the adapter has an unusual name, pin it with `--adapter GLOB`",
+        );
+    }
+
+    match problems {
+        0 => {
+            println!("no problems found");
+            Ok(ExitCode::SUCCESS)
+        }
+        1 => {
+            println!("1 problem found");
+            Ok(ExitCode::FAILURE)
+        }
+        problems => {
+            println!("{} problems found", problems);
+            Ok(ExitCode::FAILURE)
+        }
+    }
+}
+
+/// Returns what the monitor takes `dev` for and why, following the rules
+/// of the power supply: the type first, then the driver, then the name.
+fn classify(dev: &BackendDevice) -> (Option<&'static str>, String)
+{
+    match dev.property_value("POWER_SUPPLY_TYPE") {
+        Some("Battery") => return (Some("battery"), "by type".to_string()),
+        Some(kind @ ("Mains" | "USB")) => return (Some("adapter"), format!("by type {}", kind)),
+        Some("Wireless") => return (Some("wireless charger"), "by type".to_string()),
+        _ => {}
+    }
+    match dev.driver.as_deref() {
+        Some("battery") => return (Some("battery"), "by driver `battery`".to_string()),
+        Some("ac") => return (Some("adapter"), "by driver `ac`".to_string()),
+        _ => {}
+    }
+    let prefix = |prefixes: &[&'static str]| {
+        prefixes
+            .iter()
+            .find(|prefix| dev.sysname.starts_with(*prefix))
+            .copied()
+    };
+    if let Some(prefix) = prefix(&["BAT", "CMB"]) {
+        (Some("battery"), format!("by the name {}*", prefix))
+    } else if let Some(prefix) = prefix(&["ADP", "AC"]) {
+        (Some("adapter"), format!("by the name {}*", prefix))
+    } else {
+        (None, "neither type, driver nor name match".to_string())
+    }
+}
+
+fn has_attribute(syspath: &Path, name: &str) -> bool
+{
+    syspath.join(name).exists()
+}
@@ -0,0 +1,184 @@
+use std::collections::VecDeque;
+use std::fmt::Display;
+use std::time::Duration;
+use std::time::Instant;
+
+use crate::power_supply::Status;
+use crate::snapshot::Snapshot;
+
+/// How often the batteries are read while the drain is monitored, as they do
+/// not send uevents for every change of their energy.
+const SAMPLE_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Thresholds above which the system batteries drain abnormally fast, e.g.
+/// because a runaway process keeps the laptop busy in a bag. The rate is
+/// measured over a sliding window, so that short bursts of load do not count.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DrainLimit
+{
+    /// Power draw in watts. Only batteries that report energy rather than
+    /// charge are checked against it.
+    pub watts: Option<f64>,
+    /// Share of a full charge used per hour, in percent.
+    pub percent_per_hour: Option<f64>,
+    pub window: Duration,
+}
+
+/// How fast the system batteries drained over the window of a
+/// [`DrainLimit`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DrainRate
+{
+    /// Power draw in µW, for batteries that report energy.
+    pub power: Option<u64>,
+    /// Share of a full charge used per hour, in percent.
+    pub percent_per_hour: u32,
+}
+
+impl Display for DrainRate
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result
+    {
+        write!(f, "{}% per hour", self.percent_per_hour)?;
+        if let Some(power) = self.power {
+            write!(f, ", {:.1} W", power as f64 / 1_000_000.0)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Sample
+{
+    at: Instant,
+    /// Charge left as a fraction of a full charge.
+    level: f64,
+    /// Energy left in µWh, if all batteries report energy.
+    energy: Option<u64>,
+}
+
+/// Tracks the drain of the system batteries against a [`DrainLimit`].
+#[derive(Debug, Clone)]
+pub(crate) struct DrainMonitor
+{
+    limit: DrainLimit,
+    samples: VecDeque<Sample>,
+    /// Set once the limit was exceeded, until the drain is back below it.
+    exceeded: bool,
+}
+
+impl DrainMonitor
+{
+    pub(crate) fn new(limit: DrainLimit) -> Self
+    {
+        Self {
+            limit,
+            samples: VecDeque::new(),
+            exceeded: false,
+        }
+    }
+
+    /// Starts the window over, e.g. after a battery was inserted or pulled,
+    /// which changes the combined level without any drain.
+    pub(crate) fn restart(&mut self)
+    {
+        self.samples.clear();
+    }
+
+    /// Returns when the batteries have to be read again, while on battery.
+    pub(crate) fn next_sample(&self) -> Option<Instant>
+    {
+        self.samples
+            .back()
+            .map(|sample| sample.at + SAMPLE_INTERVAL)
+    }
+
+    /// Records the state of the batteries and returns the drain rate if it
+    /// just exceeded the limit. Charging starts the window over.
+    pub(crate) fn sample(&mut self, now: Instant, snapshot: &Snapshot) -> Option<DrainRate>
+    {
+        if snapshot.status != Status::Discharging {
+            self.samples.clear();
+            self.exceeded = false;
+            return None;
+        }
+        // Readings close to the last one add nothing but noise.
+        if self
+            .samples
+            .back()
+            .is_some_and(|last| now < last.at + SAMPLE_INTERVAL / 2)
+        {
+            return None;
+        }
+        let sample = Sample {
+            at: now,
+            level: level(snapshot)?,
+            energy: energy(snapshot),
+        };
+        self.samples.push_back(sample);
+        // The oldest sample kept is the last one at least a window old.
+        while self
+            .samples
+            .get(1)
+            .is_some_and(|next| now.saturating_duration_since(next.at) >= self.limit.window)
+        {
+            self.samples.pop_front();
+        }
+        let oldest = self.samples.front()?;
+        let elapsed = now.saturating_duration_since(oldest.at);
+        if elapsed < self.limit.window {
+            return None;
+        }
+        let hours = elapsed.as_secs_f64() / 3600.0;
+        let percent_per_hour = (oldest.level - sample.level).max(0.0) * 100.0 / hours;
+        let power = match (oldest.energy, sample.energy) {
+            (Some(old), Some(new)) => Some((old.saturating_sub(new) as f64 / hours) as u64),
+            _ => None,
+        };
+        let exceeded = self
+            .limit
+            .percent_per_hour
+            .is_some_and(|limit| percent_per_hour > limit)
+            || self
+                .limit
+                .watts
+                .zip(power)
+                .is_some_and(|(limit, power)| power as f64 / 1_000_000.0 > limit);
+        let newly = exceeded && !self.exceeded;
+        self.exceeded = exceeded;
+        newly.then_some(DrainRate {
+            power,
+            percent_per_hour: percent_per_hour.round() as u32,
+        })
+    }
+}
+
+/// Returns the charge left in the system batteries as a fraction of a full
+/// charge, from their energy if all of them report it.
+fn level(snapshot: &Snapshot) -> Option<f64>
+{
+    let now = snapshot
+        .batteries()
+        .map(|bat| bat.energy_now)
+        .sum::<Option<u64>>();
+    let full = snapshot
+        .batteries()
+        .map(|bat| bat.energy_full)
+        .sum::<Option<u64>>();
+    match (now, full) {
+        (Some(now), Some(full)) if full > 0 => Some(now as f64 / full as f64),
+        _ => snapshot
+            .capacity()
+            .map(|capacity| f64::from(capacity) / 100.0),
+    }
+}
+
+fn energy(snapshot: &Snapshot) -> Option<u64>
+{
+    let mut batteries = snapshot.batteries().peekable();
+    batteries.peek()?;
+    batteries
+        .map(|bat| bat.energy_now.filter(|_| bat.reports_energy))
+        .sum()
+}
@@ -0,0 +1,51 @@
+use std::fs;
+use std::io;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+
+use lithiumd::Snapshot;
+
+/// Writes the state of the power supply as `KEY=VALUE` lines, for shell
+/// scripts and the `EnvironmentFile=` of systemd units.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EnvFile
+{
+    path: PathBuf,
+}
+
+impl EnvFile
+{
+    #[must_use]
+    pub fn new(path: PathBuf) -> Self
+    {
+        Self { path }
+    }
+
+    #[must_use]
+    pub fn path(&self) -> &Path
+    {
+        &self.path
+    }
+
+    /// Replaces the file atomically, so that readers never see a partially
+    /// written one. Unknown values are written as empty strings.
+    pub fn write(&self, snapshot: &Snapshot, inhibited: bool) -> io::Result<()>
+    {
+        let mut contents = String::new();
+        for (key, value) in snapshot.env_vars() {
+            contents += &format!("{}={}\n", key, value.unwrap_or_default());
+        }
+        contents += &format!("LITHIUMD_INHIBITED={}\n", u8::from(inhibited));
+
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut tmp = self.path.clone().into_os_string();
+        tmp.push(".tmp");
+        let mut file = fs::File::create(&tmp)?;
+        file.write_all(contents.as_bytes())?;
+        file.sync_all()?;
+        fs::rename(&tmp, &self.path)
+    }
+}
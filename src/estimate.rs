@@ -0,0 +1,147 @@
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use crate::power_supply::Status;
+use crate::snapshot::Snapshot;
+
+/// How recent power readings are combined. The instantaneous `power_now` of
+/// a battery jumps with every burst of load, which would make the time
+/// estimates useless.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Smoothing
+{
+    /// Exponentially weighted moving average over about `window` readings.
+    /// Follows lasting changes of the load quickly.
+    Ewma
+    {
+        window: usize
+    },
+    /// Median of the last `window` readings. Ignores short spikes entirely.
+    Median
+    {
+        window: usize
+    },
+}
+
+impl Default for Smoothing
+{
+    fn default() -> Self
+    {
+        Smoothing::Ewma { window: 10 }
+    }
+}
+
+/// Estimates how long the system batteries last, or take to charge, from
+/// smoothed power readings:
+///
+/// ```
+/// use lithiumd::Estimator;
+/// use lithiumd::Smoothing;
+/// use lithiumd::Status;
+///
+/// let mut estimator = Estimator::new(Smoothing::Median { window: 3 });
+/// for power in [10_000_000, 50_000_000, 12_000_000] {
+///     estimator.push(Status::Discharging, power);
+/// }
+/// // The spike to 50 W is ignored.
+/// assert_eq!(estimator.power(), Some(12_000_000));
+/// ```
+#[derive(Debug, Clone)]
+pub struct Estimator
+{
+    smoothing: Smoothing,
+    /// The status the readings were taken in. Readings are only combined
+    /// while it stays the same.
+    status: Status,
+    readings: VecDeque<u64>,
+    ewma: Option<f64>,
+}
+
+impl Estimator
+{
+    #[must_use]
+    pub fn new(smoothing: Smoothing) -> Self
+    {
+        Self {
+            smoothing,
+            status: Status::Unknown,
+            readings: VecDeque::new(),
+            ewma: None,
+        }
+    }
+
+    #[must_use]
+    pub fn smoothing(&self) -> Smoothing
+    {
+        self.smoothing
+    }
+
+    /// Adds a power reading, in µW or µA. A change of `status` starts over,
+    /// since charging and discharging draw unrelated amounts of power.
+    pub fn push(&mut self, status: Status, power: u64)
+    {
+        if status != self.status {
+            self.status = status;
+            self.readings.clear();
+            self.ewma = None;
+        }
+        match self.smoothing {
+            Smoothing::Ewma { window } => {
+                let alpha = 2.0 / (window.max(1) as f64 + 1.0);
+                let power = power as f64;
+                self.ewma = Some(match self.ewma {
+                    Some(ewma) => ewma + alpha * (power - ewma),
+                    None => power,
+                });
+            }
+            Smoothing::Median { window } => {
+                self.readings.push_back(power);
+                while self.readings.len() > window.max(1) {
+                    self.readings.pop_front();
+                }
+            }
+        }
+    }
+
+    /// Returns the smoothed power, if there were any readings.
+    #[must_use]
+    pub fn power(&self) -> Option<u64>
+    {
+        match self.smoothing {
+            Smoothing::Ewma { .. } => self.ewma.map(|ewma| ewma.round() as u64),
+            Smoothing::Median { .. } => {
+                let mut sorted = self.readings.iter().copied().collect::<Vec<_>>();
+                sorted.sort_unstable();
+                sorted.get(sorted.len() / 2).copied()
+            }
+        }
+    }
+
+    /// Returns how long the system batteries in `snapshot` last at the
+    /// smoothed power while discharging, or take to charge fully while
+    /// charging.
+    #[must_use]
+    pub fn time_remaining(&self, snapshot: &Snapshot) -> Option<Duration>
+    {
+        if snapshot.status != self.status {
+            return None;
+        }
+        let power = self.power().filter(|power| *power > 0)?;
+        let now = snapshot
+            .batteries()
+            .map(|bat| bat.energy_now)
+            .sum::<Option<u64>>()?;
+        let energy = match self.status {
+            Status::Discharging => now,
+            Status::Charging => snapshot
+                .batteries()
+                .map(|bat| bat.energy_full)
+                .sum::<Option<u64>>()?
+                .saturating_sub(now),
+            Status::Full | Status::NotCharging | Status::Unknown => return None,
+        };
+        Some(Duration::from_secs_f64(
+            energy as f64 / power as f64 * 3600.0,
+        ))
+    }
+}
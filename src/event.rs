@@ -0,0 +1,271 @@
+use std::collections::vec_deque;
+use std::collections::VecDeque;
+use std::fmt::Display;
+use std::time::Instant;
+
+use crate::charging_speed::ChargingSpeed;
+use crate::dock::Dock;
+use crate::drain::DrainRate;
+use crate::fan::Fan;
+use crate::snapshot::Change;
+use crate::snapshot::DeviceSnapshot;
+use crate::snapshot::Snapshot;
+use crate::thermal::Temperature;
+
+/// An event emitted by [`PowerSupply`](crate::PowerSupply).
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum PowerEvent
+{
+    /// Part of the power supply state changed.
+    Changed(Change),
+    /// The combined capacity of the system batteries crossed a multiple of
+    /// the capacity step, see
+    /// [`PowerSupply::set_capacity_step`](crate::PowerSupply::set_capacity_step).
+    CapacityChanged
+    {
+        old: Option<u8>, new: u8
+    },
+    /// A peripheral battery dropped to the low capacity threshold.
+    LowBattery(DeviceSnapshot),
+    /// The system batteries started charging at another [`ChargingSpeed`],
+    /// see
+    /// [`Snapshot::charging_speed`](crate::Snapshot::charging_speed).
+    ChargingSpeedChanged(ChargingSpeed),
+    /// A system battery dropped to the alarm capacity its firmware set, see
+    /// [`DeviceSnapshot::alarm`].
+    BatteryAlarm(DeviceSnapshot),
+    /// The energy left in the system batteries dropped below a budget while
+    /// discharging, see
+    /// [`PowerSupply::set_energy_budgets`](crate::PowerSupply::set_energy_budgets).
+    /// Both are in µWh.
+    EnergyBudget
+    {
+        budget: u64, remaining: u64
+    },
+    /// The system resumed from suspend. The devices were read again, and
+    /// what changed while suspended follows as further events.
+    Resumed,
+    /// The system batteries drain faster than the
+    /// [`DrainLimit`](crate::DrainLimit) allows.
+    HighDrain(DrainRate),
+    /// A docking station was attached, see
+    /// [`PowerSupply::set_monitor_docks`](crate::PowerSupply::set_monitor_docks).
+    Docked(Dock),
+    /// A docking station was detached.
+    Undocked(Dock),
+    /// A thermal zone reached the temperature of the
+    /// [`ThermalLimit`](crate::ThermalLimit), see
+    /// [`PowerSupply::set_thermal_limit`](crate::PowerSupply::set_thermal_limit).
+    Overheated(Temperature),
+    /// A hot thermal zone cooled down again.
+    CooledDown(Temperature),
+    /// The CPUs are being throttled because they run too hot, see
+    /// [`PowerSupply::set_monitor_throttling`](crate::PowerSupply::set_monitor_throttling).
+    ThrottlingStarted,
+    /// The CPUs were not throttled since the last reading.
+    ThrottlingStopped,
+    /// A fan started spinning, see
+    /// [`PowerSupply::set_monitor_fans`](crate::PowerSupply::set_monitor_fans).
+    FanStarted(Fan),
+    /// A fan stopped.
+    FanStopped(Fan),
+    /// The adapter is online but the system batteries still discharge, e.g.
+    /// with a phone charger on USB-C, see
+    /// [`PowerSupply::set_detect_weak_charger`](crate::PowerSupply::set_detect_weak_charger).
+    WeakCharger
+    {
+        /// Sysname of the adapter.
+        adapter: String,
+        /// Power in µW, or current in µA, still drawn from the batteries, if
+        /// they report it.
+        power: Option<u64>,
+    },
+}
+
+impl PowerEvent
+{
+    /// Returns the device the event concerns, or `None` for the overall
+    /// status.
+    #[must_use]
+    pub fn sysname(&self) -> Option<&str>
+    {
+        match self {
+            PowerEvent::Changed(change) => change.sysname(),
+            PowerEvent::LowBattery(dev) | PowerEvent::BatteryAlarm(dev) => Some(&dev.sysname),
+            PowerEvent::WeakCharger { adapter, .. } => Some(adapter),
+            PowerEvent::CapacityChanged { .. }
+            | PowerEvent::EnergyBudget { .. }
+            | PowerEvent::ChargingSpeedChanged(_)
+            | PowerEvent::Resumed
+            | PowerEvent::HighDrain(_)
+            | PowerEvent::Docked(_)
+            | PowerEvent::Undocked(_)
+            | PowerEvent::Overheated(_)
+            | PowerEvent::CooledDown(_)
+            | PowerEvent::ThrottlingStarted
+            | PowerEvent::ThrottlingStopped
+            | PowerEvent::FanStarted(_)
+            | PowerEvent::FanStopped(_) => None,
+        }
+    }
+}
+
+/// A [`PowerEvent`] together with when it was queued and its place among all
+/// events of the power supply, as returned by
+/// [`PowerSupply::timed_events`](crate::PowerSupply::timed_events).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TimedEvent
+{
+    /// Counts up from 0 with every event, so that consumers can order the
+    /// events of several power supplies, or notice that they missed some.
+    pub seq: u64,
+    /// The time, from the clock of the power supply, of the reading that the
+    /// event resulted from.
+    pub at: Instant,
+    pub event: PowerEvent,
+}
+
+/// The events queued by a power supply, stamped with the time set by
+/// [`EventQueue::set_time`] before the reading they result from.
+pub(crate) struct EventQueue
+{
+    events: VecDeque<TimedEvent>,
+    next_seq: u64,
+    now: Instant,
+}
+
+impl EventQueue
+{
+    pub(crate) fn new(now: Instant) -> Self
+    {
+        Self {
+            events: VecDeque::new(),
+            next_seq: 0,
+            now,
+        }
+    }
+
+    pub(crate) fn set_time(&mut self, now: Instant)
+    {
+        self.now = now;
+    }
+
+    pub(crate) fn push_back(&mut self, event: PowerEvent)
+    {
+        self.events.push_back(TimedEvent {
+            seq: self.next_seq,
+            at: self.now,
+            event,
+        });
+        self.next_seq += 1;
+    }
+
+    pub(crate) fn drain(&mut self) -> vec_deque::Drain<'_, TimedEvent>
+    {
+        self.events.drain(..)
+    }
+}
+
+impl Extend<PowerEvent> for EventQueue
+{
+    fn extend<I: IntoIterator<Item = PowerEvent>>(&mut self, events: I)
+    {
+        for event in events {
+            self.push_back(event);
+        }
+    }
+}
+
+/// Selects events by the device they concern and its capacity, so that
+/// subscribers are only told about what they are interested in.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct EventFilter
+{
+    /// Sysname of the device, e.g. `BAT1`. Changes of the overall status do
+    /// not concern any device.
+    pub device: Option<String>,
+    pub capacity_below: Option<u8>,
+    pub capacity_above: Option<u8>,
+}
+
+impl EventFilter
+{
+    /// Returns whether `event` passes the filter. The capacity is the one of
+    /// the device the event concerns, or the combined capacity from
+    /// `snapshot` for the overall status. Events without a known capacity do
+    /// not pass capacity filters.
+    #[must_use]
+    pub fn matches(&self, event: &PowerEvent, snapshot: &Snapshot) -> bool
+    {
+        let sysname = event.sysname();
+        if self.device.is_some() && self.device.as_deref() != sysname {
+            return false;
+        }
+        if self.capacity_below.is_none() && self.capacity_above.is_none() {
+            return true;
+        }
+        let capacity = match event {
+            PowerEvent::LowBattery(dev) | PowerEvent::BatteryAlarm(dev) => dev.capacity,
+            PowerEvent::Changed(Change::DeviceAdded(dev) | Change::DeviceRemoved(dev)) => {
+                dev.capacity
+            }
+            _ => match sysname {
+                Some(sysname) => snapshot.device(sysname).and_then(|dev| dev.capacity),
+                None => snapshot.capacity(),
+            },
+        };
+        capacity.is_some_and(|capacity| {
+            self.capacity_below.is_none_or(|below| capacity < below)
+                && self.capacity_above.is_none_or(|above| capacity > above)
+        })
+    }
+}
+
+impl Display for PowerEvent
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result
+    {
+        match self {
+            PowerEvent::Changed(change) => change.fmt(f),
+            PowerEvent::CapacityChanged { new, .. } => write!(f, "Capacity changed: {}%", new),
+            PowerEvent::LowBattery(dev) => write!(
+                f,
+                "{} battery low: {}%",
+                dev.sysname,
+                dev.capacity.unwrap_or_default()
+            ),
+            PowerEvent::BatteryAlarm(dev) => write!(
+                f,
+                "{} battery reached its alarm capacity: {}%",
+                dev.sysname,
+                dev.capacity.unwrap_or_default()
+            ),
+            PowerEvent::EnergyBudget { budget, remaining } => write!(
+                f,
+                "Energy below budget: {:.1} Wh left of {:.1} Wh",
+                *remaining as f64 / 1_000_000.0,
+                *budget as f64 / 1_000_000.0
+            ),
+            PowerEvent::ChargingSpeedChanged(speed) => write!(f, "Charging speed: {}", speed),
+            PowerEvent::Resumed => write!(f, "resumed from suspend"),
+            PowerEvent::HighDrain(rate) => write!(f, "battery draining fast: {}", rate),
+            PowerEvent::Docked(dock) => write!(f, "Docked: {}", dock),
+            PowerEvent::Undocked(dock) => write!(f, "Undocked: {}", dock),
+            PowerEvent::Overheated(temperature) => write!(f, "Overheated: {}", temperature),
+            PowerEvent::CooledDown(temperature) => write!(f, "Cooled down: {}", temperature),
+            PowerEvent::ThrottlingStarted => write!(f, "CPU throttling started"),
+            PowerEvent::ThrottlingStopped => write!(f, "CPU throttling stopped"),
+            PowerEvent::FanStarted(fan) => write!(f, "Fan started: {}", fan),
+            PowerEvent::FanStopped(fan) => write!(f, "Fan stopped: {}", fan.name),
+            PowerEvent::WeakCharger { adapter, power } => {
+                write!(f, "charger too weak: {} online but discharging", adapter)?;
+                if let Some(power) = power {
+                    write!(f, " at {:.1} W", *power as f64 / 1_000_000.0)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
@@ -0,0 +1,215 @@
+use std::io;
+use std::time::Duration;
+
+use mio::event::Source;
+use mio::Events;
+use mio::Interest;
+use mio::Poll;
+use mio::Token;
+
+use crate::event::PowerEvent;
+use crate::lid::LidSwitch;
+use crate::lid::PowerButton;
+use crate::lid::TabletModeSwitch;
+use crate::power_supply::PowerSupply;
+use crate::resume::ResumeDetector;
+
+const POWER_SUPPLY: Token = Token(0);
+const RESUME: Token = Token(1);
+const LID: Token = Token(2);
+const TABLET_MODE: Token = Token(3);
+const POWER_BUTTON: Token = Token(4);
+/// The first token handed out by [`LidEventLoop::register`].
+const FIRST_USER_TOKEN: usize = 16;
+
+/// What [`LidEventLoop::poll`] returns.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LoopEvent
+{
+    Power(PowerEvent),
+    /// The lid was closed (`true`) or opened (`false`).
+    Lid(bool),
+    /// The laptop was folded into a tablet (`true`) or back (`false`).
+    TabletMode(bool),
+    PowerButtonPressed,
+    /// A source added with [`LidEventLoop::register`] became ready.
+    Ready(Token),
+}
+
+/// A mio `Poll` with a power supply, the resume detector and optionally the
+/// lid switch, the tablet mode switch and the power button registered under
+/// tokens of its own, for applications that want all of them without
+/// dispatching on tokens themselves:
+///
+/// ```no_run
+/// use lithiumd::LidEventLoop;
+/// use lithiumd::LidSwitch;
+/// use lithiumd::PowerSupply;
+///
+/// let mut event_loop = LidEventLoop::new(PowerSupply::new())?;
+/// event_loop.add_lid_switch(LidSwitch::open()?)?;
+/// loop {
+///     for event in event_loop.poll(None)? {
+///         println!("{:?}", event);
+///     }
+/// }
+/// # Ok::<(), std::io::Error>(())
+/// ```
+///
+/// Each of these sources implements `mio::event::Source` as well, so that
+/// applications with a loop of their own can register them into their own
+/// `Poll` instead.
+pub struct LidEventLoop
+{
+    poll: Poll,
+    events: Events,
+    power_supply: PowerSupply,
+    resume: ResumeDetector,
+    lid: Option<LidSwitch>,
+    tablet_mode: Option<TabletModeSwitch>,
+    power_button: Option<PowerButton>,
+    next_token: usize,
+    /// Whether the power supply was read for the first time.
+    started: bool,
+}
+
+impl LidEventLoop
+{
+    pub fn new(mut power_supply: PowerSupply) -> io::Result<Self>
+    {
+        let poll = Poll::new()?;
+        power_supply.register(poll.registry(), POWER_SUPPLY, Interest::READABLE)?;
+        let mut resume = ResumeDetector::new();
+        resume.register(poll.registry(), RESUME, Interest::READABLE)?;
+        Ok(Self {
+            poll,
+            events: Events::with_capacity(64),
+            power_supply,
+            resume,
+            lid: None,
+            tablet_mode: None,
+            power_button: None,
+            next_token: FIRST_USER_TOKEN,
+            started: false,
+        })
+    }
+
+    pub fn power_supply(&mut self) -> &mut PowerSupply
+    {
+        &mut self.power_supply
+    }
+
+    pub fn add_lid_switch(&mut self, mut lid: LidSwitch) -> io::Result<()>
+    {
+        lid.register(self.poll.registry(), LID, Interest::READABLE)?;
+        self.lid = Some(lid);
+        Ok(())
+    }
+
+    pub fn add_tablet_mode_switch(&mut self, mut tablet_mode: TabletModeSwitch) -> io::Result<()>
+    {
+        tablet_mode.register(self.poll.registry(), TABLET_MODE, Interest::READABLE)?;
+        self.tablet_mode = Some(tablet_mode);
+        Ok(())
+    }
+
+    pub fn add_power_button(&mut self, mut button: PowerButton) -> io::Result<()>
+    {
+        button.register(self.poll.registry(), POWER_BUTTON, Interest::READABLE)?;
+        self.power_button = Some(button);
+        Ok(())
+    }
+
+    /// Registers a source of the application, e.g. a socket, and returns the
+    /// token that [`LoopEvent::Ready`] reports it with. The source is read by
+    /// the application, and deregistered by it with
+    /// [`LidEventLoop::deregister`].
+    pub fn register(&mut self, source: &mut impl Source, interests: Interest) -> io::Result<Token>
+    {
+        let token = Token(self.next_token);
+        source.register(self.poll.registry(), token, interests)?;
+        self.next_token += 1;
+        Ok(token)
+    }
+
+    pub fn deregister(&mut self, source: &mut impl Source) -> io::Result<()>
+    {
+        source.deregister(self.poll.registry())
+    }
+
+    /// Waits until one of the sources becomes ready, a deadline of the power
+    /// supply passes or `timeout` elapses, and returns what happened. The
+    /// first call reads the power supply right away.
+    pub fn poll(&mut self, timeout: Option<Duration>) -> io::Result<Vec<LoopEvent>>
+    {
+        if !self.started {
+            self.started = true;
+            self.power_supply.update()?;
+            return Ok(self.power_events());
+        }
+        let timeout = match (timeout, self.power_supply.timeout()) {
+            (Some(timeout), Some(deadline)) => Some(timeout.min(deadline)),
+            (timeout, deadline) => timeout.or(deadline),
+        };
+        match self.poll.poll(&mut self.events, timeout) {
+            Ok(()) => {}
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => return Ok(Vec::new()),
+            Err(err) => return Err(err),
+        }
+        let mut loop_events = Vec::new();
+        if self.events.is_empty() {
+            // A deadline of the power supply passed.
+            self.power_supply.update()?;
+        }
+        let tokens = self
+            .events
+            .iter()
+            .map(|event| event.token())
+            .collect::<Vec<_>>();
+        for token in tokens {
+            match token {
+                POWER_SUPPLY => self.power_supply.update()?,
+                RESUME => {
+                    if self.resume.process()? {
+                        self.power_supply.resumed()?;
+                    }
+                }
+                LID => {
+                    if let Some(closed) = self.lid.as_mut().map(LidSwitch::read).transpose()? {
+                        loop_events.extend(closed.map(LoopEvent::Lid));
+                    }
+                }
+                TABLET_MODE => {
+                    let tablet = self
+                        .tablet_mode
+                        .as_mut()
+                        .map(TabletModeSwitch::read)
+                        .transpose()?;
+                    if let Some(tablet) = tablet {
+                        loop_events.extend(tablet.map(LoopEvent::TabletMode));
+                    }
+                }
+                POWER_BUTTON => {
+                    let presses = self
+                        .power_button
+                        .as_mut()
+                        .map(PowerButton::read)
+                        .transpose()?
+                        .unwrap_or(0);
+                    loop_events.extend((0..presses).map(|_| LoopEvent::PowerButtonPressed));
+                }
+                token => loop_events.push(LoopEvent::Ready(token)),
+            }
+        }
+        if self.resume.check() {
+            self.power_supply.resumed()?;
+        }
+        loop_events.extend(self.power_events());
+        Ok(loop_events)
+    }
+
+    fn power_events(&mut self) -> Vec<LoopEvent>
+    {
+        self.power_supply.events().map(LoopEvent::Power).collect()
+    }
+}
@@ -0,0 +1,402 @@
+use std::fmt::Display;
+
+use crate::power_supply::Status;
+use crate::snapshot::Snapshot;
+
+/// A condition on the state of the power supply written as an expression,
+/// such as `discharging && capacity < 30`:
+///
+/// - `charging`, `discharging`, `full`, `not_charging` and `unknown` hold while
+///   the status is that one, and `ac` and `battery` while the system runs on
+///   that,
+/// - `capacity` compares with `<`, `<=`, `>`, `>=`, `==` and `!=` to a
+///   percentage, and is false while the capacity is unknown,
+/// - `!`, `&&`, `||` and parentheses combine them.
+///
+/// ```
+/// use lithiumd::Expr;
+///
+/// let expr = Expr::parse("discharging && capacity < 30").unwrap();
+/// assert_eq!(expr.to_string(), "discharging && capacity < 30");
+/// assert!(Expr::parse("capacity < ").is_err());
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Expr
+{
+    source: String,
+    node: Node,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Node
+{
+    Status(Status),
+    OnAc(bool),
+    Capacity(Comparison, u8),
+    Not(Box<Node>),
+    And(Box<Node>, Box<Node>),
+    Or(Box<Node>, Box<Node>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Comparison
+{
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+    Equal,
+    NotEqual,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token<'a>
+{
+    Word(&'a str),
+    Number(u8),
+    Comparison(Comparison),
+    Not,
+    And,
+    Or,
+    Open,
+    Close,
+}
+
+impl Expr
+{
+    pub fn parse(source: &str) -> Result<Self, String>
+    {
+        let tokens = tokenize(source)?;
+        let mut parser = Parser { tokens, pos: 0 };
+        let node = parser.or()?;
+        if let Some(token) = parser.tokens.get(parser.pos) {
+            return Err(format!("unexpected {}", describe(token)));
+        }
+        Ok(Self {
+            source: source.trim().to_string(),
+            node,
+        })
+    }
+
+    /// Returns whether the expression holds for `snapshot`.
+    #[must_use]
+    pub fn matches(&self, snapshot: &Snapshot) -> bool
+    {
+        self.node.matches(snapshot)
+    }
+
+    /// Returns the expression that holds while both `self` and `other` do.
+    #[must_use]
+    pub fn and(self, other: Expr) -> Self
+    {
+        Self {
+            source: format!("({}) && ({})", self.source, other.source),
+            node: Node::And(Box::new(self.node), Box::new(other.node)),
+        }
+    }
+}
+
+impl Display for Expr
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result
+    {
+        write!(f, "{}", self.source)
+    }
+}
+
+impl Node
+{
+    fn matches(&self, snapshot: &Snapshot) -> bool
+    {
+        match self {
+            Node::Status(status) => snapshot.status == *status,
+            Node::OnAc(on_ac) => snapshot.status.is_on_ac() == *on_ac,
+            Node::Capacity(comparison, percent) => {
+                snapshot
+                    .capacity()
+                    .is_some_and(|capacity| match comparison {
+                        Comparison::Less => capacity < *percent,
+                        Comparison::LessEqual => capacity <= *percent,
+                        Comparison::Greater => capacity > *percent,
+                        Comparison::GreaterEqual => capacity >= *percent,
+                        Comparison::Equal => capacity == *percent,
+                        Comparison::NotEqual => capacity != *percent,
+                    })
+            }
+            Node::Not(node) => !node.matches(snapshot),
+            Node::And(left, right) => left.matches(snapshot) && right.matches(snapshot),
+            Node::Or(left, right) => left.matches(snapshot) || right.matches(snapshot),
+        }
+    }
+}
+
+fn tokenize(source: &str) -> Result<Vec<Token<'_>>, String>
+{
+    let mut tokens = Vec::new();
+    let mut rest = source.trim_start();
+    while let Some(c) = rest.chars().next() {
+        let (token, len) = match c {
+            '(' => (Token::Open, 1),
+            ')' => (Token::Close, 1),
+            '&' if rest.starts_with("&&") => (Token::And, 2),
+            '|' if rest.starts_with("||") => (Token::Or, 2),
+            '<' | '>' | '=' | '!' if rest[1..].starts_with('=') => (
+                Token::Comparison(match c {
+                    '<' => Comparison::LessEqual,
+                    '>' => Comparison::GreaterEqual,
+                    '=' => Comparison::Equal,
+                    _ => Comparison::NotEqual,
+                }),
+                2,
+            ),
+            '<' => (Token::Comparison(Comparison::Less), 1),
+            '>' => (Token::Comparison(Comparison::Greater), 1),
+            '!' => (Token::Not, 1),
+            '0'..='9' => {
+                let len = rest
+                    .find(|c: char| !c.is_ascii_digit())
+                    .unwrap_or(rest.len());
+                let percent = rest[..len]
+                    .parse()
+                    .ok()
+                    .filter(|percent| *percent <= 100)
+                    .ok_or_else(|| format!("`{}` is not a percentage", &rest[..len]))?;
+                (Token::Number(percent), len)
+            }
+            c if c.is_ascii_alphabetic() || c == '_' => {
+                let len = rest
+                    .find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+                    .unwrap_or(rest.len());
+                (Token::Word(&rest[..len]), len)
+            }
+            c => return Err(format!("unexpected `{}`", c)),
+        };
+        tokens.push(token);
+        rest = rest[len..].trim_start();
+    }
+    Ok(tokens)
+}
+
+fn describe(token: &Token<'_>) -> String
+{
+    match token {
+        Token::Word(word) => format!("`{}`", word),
+        Token::Number(number) => format!("`{}`", number),
+        Token::Comparison(_) => "comparison".to_string(),
+        Token::Not => "`!`".to_string(),
+        Token::And => "`&&`".to_string(),
+        Token::Or => "`||`".to_string(),
+        Token::Open => "`(`".to_string(),
+        Token::Close => "`)`".to_string(),
+    }
+}
+
+/// A recursive descent parser, in which `||` binds looser than `&&`, which
+/// binds looser than `!`.
+struct Parser<'a>
+{
+    tokens: Vec<Token<'a>>,
+    pos: usize,
+}
+
+impl<'a> Parser<'a>
+{
+    fn next(&mut self) -> Result<Token<'a>, String>
+    {
+        let token = self
+            .tokens
+            .get(self.pos)
+            .cloned()
+            .ok_or_else(|| "unexpected end of the expression".to_string())?;
+        self.pos += 1;
+        Ok(token)
+    }
+
+    fn eat(&mut self, token: &Token<'_>) -> bool
+    {
+        let found = self.tokens.get(self.pos) == Some(token);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn or(&mut self) -> Result<Node, String>
+    {
+        let mut node = self.and()?;
+        while self.eat(&Token::Or) {
+            node = Node::Or(Box::new(node), Box::new(self.and()?));
+        }
+        Ok(node)
+    }
+
+    fn and(&mut self) -> Result<Node, String>
+    {
+        let mut node = self.unary()?;
+        while self.eat(&Token::And) {
+            node = Node::And(Box::new(node), Box::new(self.unary()?));
+        }
+        Ok(node)
+    }
+
+    fn unary(&mut self) -> Result<Node, String>
+    {
+        match self.next()? {
+            Token::Not => Ok(Node::Not(Box::new(self.unary()?))),
+            Token::Open => {
+                let node = self.or()?;
+                match self.next()? {
+                    Token::Close => Ok(node),
+                    token => Err(format!("expected `)`, found {}", describe(&token))),
+                }
+            }
+            Token::Word("capacity") => {
+                let comparison = match self.next()? {
+                    Token::Comparison(comparison) => comparison,
+                    token => {
+                        return Err(format!(
+                            "expected a comparison after `capacity`, found {}",
+                            describe(&token)
+                        ))
+                    }
+                };
+                match self.next()? {
+                    Token::Number(percent) => Ok(Node::Capacity(comparison, percent)),
+                    token => Err(format!("expected a percentage, found {}", describe(&token))),
+                }
+            }
+            Token::Word("charging") => Ok(Node::Status(Status::Charging)),
+            Token::Word("discharging") => Ok(Node::Status(Status::Discharging)),
+            Token::Word("full") => Ok(Node::Status(Status::Full)),
+            Token::Word("not_charging") => Ok(Node::Status(Status::NotCharging)),
+            Token::Word("unknown") => Ok(Node::Status(Status::Unknown)),
+            Token::Word("ac") => Ok(Node::OnAc(true)),
+            Token::Word("battery") => Ok(Node::OnAc(false)),
+            Token::Word(word) => Err(format!("unknown name `{}`", word)),
+            token => Err(format!("unexpected {}", describe(&token))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::scenario::Scenario;
+
+    /// Returns the snapshot of a laptop whose battery reports `status` and
+    /// `capacity`, on AC unless it discharges.
+    fn snapshot(status: &str, capacity: Option<&str>) -> Snapshot
+    {
+        let mut scenario = Scenario::new().unwrap();
+        let mut attributes = vec![("type", "Battery"), ("status", status)];
+        attributes.extend(capacity.map(|capacity| ("capacity", capacity)));
+        scenario
+            .controller()
+            .add(Scenario::BATTERY, &attributes)
+            .unwrap();
+        let online = if status == "Discharging" { "0" } else { "1" };
+        scenario
+            .controller()
+            .add(Scenario::ADAPTER, &[("type", "Mains"), ("online", online)])
+            .unwrap();
+        scenario.update().unwrap();
+        scenario.power_supply().snapshot()
+    }
+
+    fn holds(source: &str, snapshot: &Snapshot) -> bool
+    {
+        Expr::parse(source).unwrap().matches(snapshot)
+    }
+
+    #[test]
+    fn and_binds_tighter_than_or()
+    {
+        let charging = snapshot("Charging", Some("50"));
+        // `charging || (discharging && capacity < 30)`, not
+        // `(charging || discharging) && capacity < 30`.
+        assert!(holds("charging || discharging && capacity < 30", &charging));
+        assert!(!holds(
+            "(charging || discharging) && capacity < 30",
+            &charging
+        ));
+        assert!(holds("discharging && capacity < 30 || charging", &charging));
+    }
+
+    #[test]
+    fn negates_and_groups()
+    {
+        let discharging = snapshot("Discharging", Some("20"));
+        assert!(holds("!charging", &discharging));
+        assert!(!holds("!!charging", &discharging));
+        assert!(holds("!(charging || ac)", &discharging));
+        assert!(!holds("!discharging && battery", &discharging));
+        assert!(holds("((battery)) && !(capacity > 50)", &discharging));
+    }
+
+    #[test]
+    fn compares_the_capacity()
+    {
+        let snapshot = snapshot("Discharging", Some("30"));
+        let cases = [
+            ("<", [false, false, true]),
+            ("<=", [false, true, true]),
+            (">", [true, false, false]),
+            (">=", [true, true, false]),
+            ("==", [false, true, false]),
+            ("!=", [true, false, true]),
+        ];
+        for (comparison, expected) in cases {
+            for (percent, expected) in [29, 30, 31].into_iter().zip(expected) {
+                let source = format!("capacity {} {}", comparison, percent);
+                assert_eq!(holds(&source, &snapshot), expected, "{}", source);
+            }
+        }
+    }
+
+    #[test]
+    fn unknown_capacity_never_compares()
+    {
+        let snapshot = snapshot("Discharging", None);
+        assert!(holds("discharging", &snapshot));
+        for source in ["capacity < 100", "capacity >= 0", "capacity != 50"] {
+            assert!(!holds(source, &snapshot), "{}", source);
+        }
+        assert!(holds("!(capacity < 100)", &snapshot));
+    }
+
+    #[test]
+    fn reports_errors()
+    {
+        let cases = [
+            ("capacity <", "unexpected end of the expression"),
+            ("capacity < 101", "`101` is not a percentage"),
+            ("=", "unexpected `=`"),
+            ("capacity = 5", "unexpected `=`"),
+            ("docked", "unknown name `docked`"),
+            ("(charging", "unexpected end of the expression"),
+            ("(charging || full", "unexpected end of the expression"),
+            ("charging)", "unexpected `)`"),
+            (
+                "capacity charging",
+                "expected a comparison after `capacity`, found `charging`",
+            ),
+        ];
+        for (source, error) in cases {
+            assert_eq!(Expr::parse(source), Err(error.to_string()), "{}", source);
+        }
+    }
+
+    #[test]
+    fn and_keeps_both_expressions()
+    {
+        let discharging = snapshot("Discharging", Some("20"));
+        let expr = Expr::parse("discharging")
+            .unwrap()
+            .and(Expr::parse("capacity < 30 || full").unwrap());
+        assert_eq!(expr.to_string(), "(discharging) && (capacity < 30 || full)");
+        assert!(expr.matches(&discharging));
+        assert!(!expr.matches(&snapshot("Discharging", Some("40"))));
+        assert!(!expr.matches(&snapshot("Full", Some("100"))));
+    }
+}
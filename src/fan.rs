@@ -0,0 +1,135 @@
+use std::collections::BTreeSet;
+use std::fmt::Display;
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+use std::time::Instant;
+
+const HWMON: &str = "/sys/class/hwmon";
+
+/// How often the fans are read, as hwmon does not send uevents.
+const SAMPLE_INTERVAL: Duration = Duration::from_secs(10);
+
+/// A fan reported by a hwmon driver, e.g. `thinkpad` or `dell_smm`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Fan
+{
+    /// Name of the hwmon device and the fan, e.g. `thinkpad/fan1`.
+    pub name: String,
+    /// Label of the fan, if the driver gives one, e.g. `CPU Fan`.
+    pub label: Option<String>,
+    pub rpm: u32,
+}
+
+impl Fan
+{
+    #[must_use]
+    pub fn is_spinning(&self) -> bool
+    {
+        self.rpm > 0
+    }
+}
+
+impl Display for Fan
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result
+    {
+        let name = self.label.as_deref().unwrap_or(&self.name);
+        write!(f, "{} at {} RPM", name, self.rpm)
+    }
+}
+
+/// Reads the speed of every fan that hwmon knows of, sorted by name. Fans
+/// whose speed cannot be read are skipped.
+#[must_use]
+pub fn read_fans() -> Vec<Fan>
+{
+    let Ok(entries) = fs::read_dir(HWMON) else {
+        return Vec::new();
+    };
+    let mut fans = entries
+        .flatten()
+        .flat_map(|entry| read_hwmon(&entry.path()))
+        .collect::<Vec<_>>();
+    fans.sort_by(|a, b| a.name.cmp(&b.name));
+    fans
+}
+
+fn read_hwmon(dir: &Path) -> Vec<Fan>
+{
+    let read = |name: &str| {
+        fs::read_to_string(dir.join(name))
+            .ok()
+            .map(|value| value.trim().to_string())
+    };
+    let device = read("name").unwrap_or_else(|| {
+        dir.file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .into_owned()
+    });
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    entries
+        .flatten()
+        .filter_map(|entry| {
+            let file_name = entry.file_name();
+            let fan = file_name.to_str()?.strip_suffix("_input")?;
+            if !fan.starts_with("fan") {
+                return None;
+            }
+            Some(Fan {
+                name: format!("{}/{}", device, fan),
+                label: read(&format!("{}_label", fan)),
+                rpm: read(&format!("{}_input", fan))?.parse().ok()?,
+            })
+        })
+        .collect()
+}
+
+/// Tracks which fans spin, to report them starting and stopping.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct FanMonitor
+{
+    /// Names of the fans that spin.
+    spinning: BTreeSet<String>,
+    last_sample: Option<Instant>,
+}
+
+impl FanMonitor
+{
+    /// Returns when the fans have to be read again.
+    pub(crate) fn next_sample(&self) -> Option<Instant>
+    {
+        self.last_sample.map(|at| at + SAMPLE_INTERVAL)
+    }
+
+    /// Reads the fans unless they were read less than the sample interval
+    /// ago, and returns those that started spinning and those that stopped,
+    /// in that order. Fans spinning at the first reading are not reported.
+    pub(crate) fn sample(&mut self, now: Instant) -> (Vec<Fan>, Vec<Fan>)
+    {
+        if self
+            .last_sample
+            .is_some_and(|last| now < last + SAMPLE_INTERVAL / 2)
+        {
+            return (Vec::new(), Vec::new());
+        }
+        let first = self.last_sample.is_none();
+        self.last_sample = Some(now);
+        let mut started = Vec::new();
+        let mut stopped = Vec::new();
+        for fan in read_fans() {
+            if fan.is_spinning() {
+                if self.spinning.insert(fan.name.clone()) && !first {
+                    started.push(fan);
+                }
+            } else if self.spinning.remove(&fan.name) {
+                stopped.push(fan);
+            }
+        }
+        (started, stopped)
+    }
+}
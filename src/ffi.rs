@@ -0,0 +1,156 @@
+use std::io;
+use std::os::raw::c_int;
+use std::panic;
+use std::panic::AssertUnwindSafe;
+use std::ptr;
+
+use crate::power_supply::PowerSupply;
+use crate::power_supply::Status;
+use crate::snapshot::Snapshot;
+
+/// A power supply and the state it last reported, behind the opaque `struct
+/// lid` of the C API in `include/lithiumd.h`, for status bars and window
+/// managers written in C or C++ that embed the monitor in their own event
+/// loop.
+pub struct Lid
+{
+    power_supply: PowerSupply,
+    snapshot: Snapshot,
+}
+
+/// Returns the negative errno of `err`, as the functions report failures.
+fn errno(err: &io::Error) -> c_int
+{
+    -err.raw_os_error().unwrap_or(libc::EIO)
+}
+
+/// Runs `f`, turning a panic into `on_panic`, since unwinding into C aborts
+/// the process. The power supply reports its failures as errors; this only
+/// keeps a bug from taking the host program down.
+fn guard<T>(on_panic: T, f: impl FnOnce() -> T) -> T
+{
+    panic::catch_unwind(AssertUnwindSafe(f)).unwrap_or(on_panic)
+}
+
+/// Enumerates the power supply and returns a handle to it, or NULL if that
+/// fails, with the negative errno stored in `error` unless it is NULL. Free
+/// the handle with [`lid_free`].
+///
+/// # Safety
+///
+/// `error` must be NULL or point to an `int` that may be written.
+#[no_mangle]
+pub unsafe extern "C" fn lid_new(error: *mut c_int) -> *mut Lid
+{
+    let lid = guard(Err(-libc::EIO), || {
+        let mut power_supply = PowerSupply::new();
+        power_supply.update().map_err(|err| errno(&err))?;
+        power_supply.events().for_each(drop);
+        let snapshot = power_supply.snapshot();
+        Ok(Box::into_raw(Box::new(Lid {
+            power_supply,
+            snapshot,
+        })))
+    });
+    lid.unwrap_or_else(|code| {
+        if !error.is_null() {
+            *error = code;
+        }
+        ptr::null_mut()
+    })
+}
+
+/// # Safety
+///
+/// `lid` must be NULL or a handle returned by [`lid_new`] that was not
+/// freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn lid_free(lid: *mut Lid)
+{
+    if !lid.is_null() {
+        drop(Box::from_raw(lid));
+    }
+}
+
+/// Returns the file descriptor that becomes readable when the power supply
+/// changes, or a negative errno. It may change after [`lid_update`], which
+/// opens a new socket should the old one fail.
+///
+/// # Safety
+///
+/// `lid` must be a live handle returned by [`lid_new`].
+#[no_mangle]
+pub unsafe extern "C" fn lid_fd(lid: *mut Lid) -> c_int
+{
+    match (*lid).power_supply.monitor_fd() {
+        Ok(fd) => fd,
+        Err(err) => errno(&err),
+    }
+}
+
+/// Returns how many milliseconds the caller's poll may wait at most before
+/// calling [`lid_update`] even though the file descriptor is not readable,
+/// or -1 to wait for it alone.
+///
+/// # Safety
+///
+/// `lid` must be a live handle returned by [`lid_new`].
+#[no_mangle]
+pub unsafe extern "C" fn lid_timeout(lid: *const Lid) -> c_int
+{
+    (*lid).power_supply.timeout().map_or(-1, |timeout| {
+        // Rounded up, so that the deadline has passed once the poll returns.
+        c_int::try_from(timeout.as_nanos().div_ceil(1_000_000)).unwrap_or(c_int::MAX)
+    })
+}
+
+/// Reads the pending uevents. Returns 1 if the status or the capacity
+/// changed, 0 if not, and a negative errno on failure.
+///
+/// # Safety
+///
+/// `lid` must be a live handle returned by [`lid_new`].
+#[no_mangle]
+pub unsafe extern "C" fn lid_update(lid: *mut Lid) -> c_int
+{
+    let lid = &mut *lid;
+    guard(-libc::EIO, || {
+        if let Err(err) = lid.power_supply.update() {
+            return errno(&err);
+        }
+        lid.power_supply.events().for_each(drop);
+        let snapshot = lid.power_supply.snapshot();
+        let changed = snapshot.status != lid.snapshot.status
+            || snapshot.capacity() != lid.snapshot.capacity();
+        lid.snapshot = snapshot;
+        c_int::from(changed)
+    })
+}
+
+/// Returns the charging status as one of the `LID_STATUS_*` constants.
+///
+/// # Safety
+///
+/// `lid` must be a live handle returned by [`lid_new`].
+#[no_mangle]
+pub unsafe extern "C" fn lid_status(lid: *const Lid) -> c_int
+{
+    match (*lid).snapshot.status {
+        Status::Unknown => 0,
+        Status::Discharging => 1,
+        Status::Charging => 2,
+        Status::Full => 3,
+        Status::NotCharging => 4,
+    }
+}
+
+/// Returns the capacity in percent, or -1 if it is not known.
+///
+/// # Safety
+///
+/// `lid` must be a live handle returned by [`lid_new`].
+#[no_mangle]
+pub unsafe extern "C" fn lid_capacity(lid: *const Lid) -> c_int
+{
+    (*lid).snapshot.capacity().map_or(-1, c_int::from)
+}
@@ -0,0 +1,318 @@
+use std::str::FromStr;
+use std::time::Duration;
+
+use crate::event::PowerEvent;
+use crate::level::BatteryLevel;
+use crate::power_supply::Status;
+use crate::snapshot::Change;
+
+/// The values a [`Template`] can refer to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Fields
+{
+    pub status: Status,
+    pub capacity: Option<u8>,
+    pub level: Option<BatteryLevel>,
+    pub time_remaining: Option<Duration>,
+}
+
+/// Names shown for the statuses and battery levels in place of the built-in
+/// ones, e.g. to translate them or to use emoji:
+///
+/// ```
+/// use lithiumd::Labels;
+/// use lithiumd::Status;
+///
+/// let labels = Labels {
+///     statuses: vec![(Status::Discharging, "On battery".to_string())],
+///     ..Labels::default()
+/// };
+/// assert_eq!(labels.status(Status::Discharging), "On battery");
+/// assert_eq!(labels.status(Status::Charging), "Charging");
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Labels
+{
+    pub statuses: Vec<(Status, String)>,
+    pub levels: Vec<(BatteryLevel, String)>,
+}
+
+impl Labels
+{
+    #[must_use]
+    pub fn status(&self, status: Status) -> String
+    {
+        self.statuses
+            .iter()
+            .find(|(s, _)| *s == status)
+            .map_or_else(|| status.to_string(), |(_, label)| label.clone())
+    }
+
+    #[must_use]
+    pub fn level(&self, level: BatteryLevel) -> String
+    {
+        self.levels
+            .iter()
+            .find(|(l, _)| *l == level)
+            .map_or_else(|| level.to_string(), |(_, label)| label.clone())
+    }
+
+    /// Describes `event` like its `Display` does, with the labels for the
+    /// statuses.
+    #[must_use]
+    pub fn event(&self, event: &PowerEvent) -> String
+    {
+        match event {
+            PowerEvent::Changed(Change::Status {
+                new,
+                origin: Some(origin),
+                ..
+            }) => format!(
+                "Charging status changed: {} by {}",
+                self.status(*new),
+                origin
+            ),
+            PowerEvent::Changed(Change::Status { new, .. }) => {
+                format!("Charging status changed: {}", self.status(*new))
+            }
+            PowerEvent::Changed(Change::DeviceStatus { sysname, new, .. }) => {
+                format!("{} status changed: {}", sysname, self.status(*new))
+            }
+            _ => event.to_string(),
+        }
+    }
+}
+
+/// A line of output with placeholders, for status bars and the like:
+///
+/// ```
+/// use lithiumd::Fields;
+/// use lithiumd::Status;
+/// use lithiumd::Template;
+///
+/// let template = "{capacity}%{if charging} (charging){else} {time_remaining}{end}"
+///     .parse::<Template>()
+///     .unwrap();
+/// let fields = Fields {
+///     status: Status::Charging,
+///     capacity: Some(80),
+///     level: None,
+///     time_remaining: None,
+/// };
+/// assert_eq!(template.render(&fields), "80% (charging)");
+/// ```
+///
+/// The placeholders are `{status}`, `{capacity}`, `{level}` and
+/// `{time_remaining}`, and unknown values are left empty. `{if CONDITION}`
+/// starts a part that is only shown if the condition holds, optionally
+/// followed by `{else}`, and `{end}` closes it. Conditions are the statuses
+/// (`charging`, `discharging`, `full`, `not_charging`, `unknown`), `on_ac`,
+/// `on_battery` and the names of the placeholders, which hold if their value
+/// is known. `{{` and `}}` stand for literal braces. [`Template::render_with`]
+/// shows the statuses and levels by their [`Labels`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Template
+{
+    nodes: Vec<Node>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Node
+{
+    Text(String),
+    Field(Field),
+    If
+    {
+        condition: Condition,
+        then: Vec<Node>,
+        otherwise: Vec<Node>,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Field
+{
+    Status,
+    Capacity,
+    Level,
+    TimeRemaining,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Condition
+{
+    Status(Status),
+    OnAc,
+    OnBattery,
+    Known(Field),
+}
+
+/// What ended a sequence of nodes while parsing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Stop
+{
+    Else,
+    End,
+    Input,
+}
+
+impl Template
+{
+    #[must_use]
+    pub fn render(&self, fields: &Fields) -> String
+    {
+        self.render_with(fields, &Labels::default())
+    }
+
+    #[must_use]
+    pub fn render_with(&self, fields: &Fields, labels: &Labels) -> String
+    {
+        let mut output = String::new();
+        render(&self.nodes, fields, labels, &mut output);
+        output
+    }
+}
+
+impl FromStr for Template
+{
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err>
+    {
+        let mut rest = s;
+        match parse(&mut rest)? {
+            (nodes, Stop::Input) => Ok(Self { nodes }),
+            (_, Stop::Else) => Err("`{else}` without `{if}`".to_string()),
+            (_, Stop::End) => Err("`{end}` without `{if}`".to_string()),
+        }
+    }
+}
+
+/// Parses nodes from the start of `rest` up to the next `{else}` or `{end}`
+/// that is not nested, and advances `rest` past it.
+fn parse(rest: &mut &str) -> Result<(Vec<Node>, Stop), String>
+{
+    let mut nodes = Vec::new();
+    let mut text = String::new();
+    loop {
+        let Some(i) = rest.find(['{', '}']) else {
+            text += rest;
+            *rest = "";
+            break;
+        };
+        text += &rest[..i];
+        let brace = &rest[i..];
+        if brace.starts_with("{{") || brace.starts_with("}}") {
+            text += &brace[..1];
+            *rest = &brace[2..];
+            continue;
+        }
+        if brace.starts_with('}') {
+            return Err("unmatched `}`, write `}}` for a literal one".to_string());
+        }
+        let close = brace
+            .find('}')
+            .ok_or_else(|| "unclosed `{`, write `{{` for a literal one".to_string())?;
+        let tag = brace[1..close].trim();
+        *rest = &brace[close + 1..];
+        if !text.is_empty() {
+            nodes.push(Node::Text(std::mem::take(&mut text)));
+        }
+        match tag.split_once(' ') {
+            Some(("if", condition)) => {
+                let condition = parse_condition(condition.trim())?;
+                let (then, stop) = parse(rest)?;
+                let otherwise = match stop {
+                    Stop::Else => match parse(rest)? {
+                        (otherwise, Stop::End) => otherwise,
+                        (_, Stop::Else) => return Err("`{else}` given twice".to_string()),
+                        (_, Stop::Input) => return Err("`{if}` without `{end}`".to_string()),
+                    },
+                    Stop::End => Vec::new(),
+                    Stop::Input => return Err("`{if}` without `{end}`".to_string()),
+                };
+                nodes.push(Node::If {
+                    condition,
+                    then,
+                    otherwise,
+                });
+            }
+            _ => match tag {
+                "else" => return Ok((nodes, Stop::Else)),
+                "end" => return Ok((nodes, Stop::End)),
+                field => nodes.push(Node::Field(parse_field(field)?)),
+            },
+        }
+    }
+    if !text.is_empty() {
+        nodes.push(Node::Text(text));
+    }
+    Ok((nodes, Stop::Input))
+}
+
+fn parse_field(s: &str) -> Result<Field, String>
+{
+    match s {
+        "status" => Ok(Field::Status),
+        "capacity" => Ok(Field::Capacity),
+        "level" => Ok(Field::Level),
+        "time_remaining" => Ok(Field::TimeRemaining),
+        _ => Err(format!("unknown placeholder `{{{}}}`", s)),
+    }
+}
+
+fn parse_condition(s: &str) -> Result<Condition, String>
+{
+    match s {
+        "charging" => Ok(Condition::Status(Status::Charging)),
+        "discharging" => Ok(Condition::Status(Status::Discharging)),
+        "full" => Ok(Condition::Status(Status::Full)),
+        "not_charging" => Ok(Condition::Status(Status::NotCharging)),
+        "unknown" => Ok(Condition::Status(Status::Unknown)),
+        "on_ac" => Ok(Condition::OnAc),
+        "on_battery" => Ok(Condition::OnBattery),
+        field => parse_field(field)
+            .map(Condition::Known)
+            .map_err(|_| format!("unknown condition `{}`", s)),
+    }
+}
+
+fn render(nodes: &[Node], fields: &Fields, labels: &Labels, output: &mut String)
+{
+    for node in nodes {
+        match node {
+            Node::Text(text) => *output += text,
+            Node::Field(field) => {
+                if let Some(value) = value(*field, fields, labels) {
+                    *output += &value;
+                }
+            }
+            Node::If {
+                condition,
+                then,
+                otherwise,
+            } => {
+                let holds = match *condition {
+                    Condition::Status(status) => fields.status == status,
+                    Condition::OnAc => fields.status.is_on_ac(),
+                    Condition::OnBattery => fields.status == Status::Discharging,
+                    Condition::Known(field) => value(field, fields, labels).is_some(),
+                };
+                render(if holds { then } else { otherwise }, fields, labels, output);
+            }
+        }
+    }
+}
+
+fn value(field: Field, fields: &Fields, labels: &Labels) -> Option<String>
+{
+    match field {
+        Field::Status => Some(labels.status(fields.status)),
+        Field::Capacity => fields.capacity.map(|capacity| capacity.to_string()),
+        Field::Level => fields.level.map(|level| labels.level(level)),
+        Field::TimeRemaining => fields.time_remaining.map(|remaining| {
+            let minutes = remaining.as_secs() / 60;
+            format!("{}h {}m", minutes / 60, minutes % 60)
+        }),
+    }
+}
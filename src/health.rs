@@ -0,0 +1,161 @@
+use std::fmt::Display;
+use std::time::Duration;
+use std::time::SystemTime;
+
+use crate::history::History;
+use crate::power_supply::PowerSupply;
+
+/// How worn a system battery is, as returned by [`PowerSupply::health`].
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Health
+{
+    pub sysname: String,
+    /// Charge cycles counted by the battery. Batteries that cannot count
+    /// them often report 0, which is taken as unknown.
+    pub cycle_count: Option<u32>,
+    /// Charge cycles estimated from a [`History`], for batteries that do not
+    /// count them.
+    pub estimated_cycles: Option<f64>,
+    /// Energy in µWh, or charge in µAh, when full now and when new.
+    pub energy_full: Option<u64>,
+    pub energy_full_design: Option<u64>,
+}
+
+impl Health
+{
+    /// Returns the full charge as a share of the design capacity, in percent.
+    #[must_use]
+    pub fn health_percent(&self) -> Option<u8>
+    {
+        let full = self.energy_full?;
+        let design = self.energy_full_design.filter(|design| *design > 0)?;
+        Some((u128::from(full) * 100 / u128::from(design)).min(100) as u8)
+    }
+
+    /// Returns the counted charge cycles, or else the estimated ones.
+    #[must_use]
+    pub fn cycles(&self) -> Option<f64>
+    {
+        self.cycle_count.map(f64::from).or(self.estimated_cycles)
+    }
+}
+
+impl Display for Health
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result
+    {
+        write!(f, "{}:", self.sysname)?;
+        match self.health_percent() {
+            Some(health) => write!(f, " {}% health", health)?,
+            None => write!(f, " unknown health")?,
+        }
+        match (self.cycle_count, self.estimated_cycles) {
+            (Some(count), _) => write!(f, ", {} cycles", count),
+            (None, Some(estimate)) => write!(f, ", about {:.0} cycles", estimate),
+            (None, None) => Ok(()),
+        }
+    }
+}
+
+/// How the health of a battery changes over the samples in a [`History`],
+/// fitted as a straight line.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct HealthTrend
+{
+    pub sysname: String,
+    pub samples: usize,
+    /// Health in percent now, according to the fit.
+    pub health_percent: f64,
+    /// Change of the health per year in percent, negative while wearing.
+    pub percent_per_year: f64,
+    /// When the health drops below 80%, the usual end of a battery's life,
+    /// if it is wearing. May have passed already.
+    pub below_80: Option<SystemTime>,
+}
+
+/// The shortest span of samples a trend is fitted over, as the health
+/// readings of a few days are mostly noise.
+const MIN_TREND_SPAN: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+const YEAR: f64 = 365.25 * 24.0 * 60.0 * 60.0;
+
+impl HealthTrend
+{
+    /// Fits a line through `samples` by least squares.
+    pub(crate) fn fit(sysname: &str, samples: &[(SystemTime, u8)]) -> Option<Self>
+    {
+        let (first, _) = samples.first()?;
+        let years =
+            |at: SystemTime| at.duration_since(*first).unwrap_or_default().as_secs_f64() / YEAR;
+        let points = samples
+            .iter()
+            .map(|(at, percent)| (years(*at), f64::from(*percent)))
+            .collect::<Vec<_>>();
+        let span = points.iter().map(|(x, _)| *x).fold(0.0, f64::max);
+        if span < MIN_TREND_SPAN.as_secs_f64() / YEAR {
+            return None;
+        }
+        let n = points.len() as f64;
+        let mean_x = points.iter().map(|(x, _)| x).sum::<f64>() / n;
+        let mean_y = points.iter().map(|(_, y)| y).sum::<f64>() / n;
+        let covariance = points
+            .iter()
+            .map(|(x, y)| (x - mean_x) * (y - mean_y))
+            .sum::<f64>();
+        let variance = points
+            .iter()
+            .map(|(x, _)| (x - mean_x).powi(2))
+            .sum::<f64>();
+        let slope = covariance / variance;
+        let intercept = mean_y - slope * mean_x;
+        let below_80 = (slope < 0.0).then(|| {
+            let at = ((80.0 - intercept) / slope).max(0.0);
+            *first + Duration::from_secs_f64(at * YEAR)
+        });
+        Some(Self {
+            sysname: sysname.to_string(),
+            samples: samples.len(),
+            health_percent: intercept + slope * years(SystemTime::now()),
+            percent_per_year: slope,
+            below_80,
+        })
+    }
+}
+
+impl PowerSupply
+{
+    /// Returns the wear of the system batteries. Cycles are estimated from
+    /// `history` for batteries that do not count them.
+    #[must_use]
+    pub fn health(&self, history: Option<&History>) -> Vec<Health>
+    {
+        self.snapshot()
+            .batteries()
+            .map(|bat| {
+                let property = |name: &str| self.device_property(&bat.sysname, name);
+                let cycle_count = property("cycle_count")
+                    .and_then(|count| count.trim().parse().ok())
+                    .filter(|count| *count > 0);
+                let design = if bat.reports_energy {
+                    "energy_full_design"
+                } else {
+                    "charge_full_design"
+                };
+                let estimated_cycles = history
+                    .filter(|_| cycle_count.is_none())
+                    .zip(bat.energy_full)
+                    .and_then(|(history, full)| history.estimated_cycles(&bat.sysname, full));
+                Health {
+                    sysname: bat.sysname.clone(),
+                    cycle_count,
+                    estimated_cycles,
+                    energy_full: bat.energy_full,
+                    energy_full_design: property(design)
+                        .and_then(|design| design.trim().parse().ok()),
+                }
+            })
+            .collect()
+    }
+}
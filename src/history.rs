@@ -0,0 +1,380 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+use std::time::Duration;
+use std::time::SystemTime;
+
+use crate::health::Health;
+use crate::health::HealthTrend;
+use crate::power_supply::Status;
+use crate::snapshot::Snapshot;
+
+/// How often the health of the batteries is sampled.
+const HEALTH_INTERVAL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// How long segments are kept for.
+const SEGMENT_RETENTION: Duration = Duration::from_secs(90 * 24 * 60 * 60);
+
+/// A stretch of time with the same charging status, as recorded in a
+/// [`History`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Segment
+{
+    pub start: SystemTime,
+    pub end: SystemTime,
+    /// The overall charging status, never [`Status::Unknown`].
+    pub status: Status,
+    /// Combined capacity of the system batteries at the start and the end.
+    pub capacity_start: Option<u8>,
+    pub capacity_end: Option<u8>,
+}
+
+impl Segment
+{
+    #[must_use]
+    pub fn duration(&self) -> Duration
+    {
+        self.end.duration_since(self.start).unwrap_or_default()
+    }
+}
+
+/// What is kept about a battery across restarts.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct Battery
+{
+    /// Energy in µWh, or charge in µAh, taken from the battery while
+    /// discharging, in total.
+    discharged: u64,
+    /// When the battery was last calibrated, and the full charge measured
+    /// then.
+    calibrated: Option<(SystemTime, u64)>,
+    /// Health in percent over time, oldest first.
+    health: Vec<(SystemTime, u8)>,
+    /// Energy or charge left at the last snapshot, not persisted.
+    last: Option<u64>,
+}
+
+/// A store of what the system batteries went through, kept in a file so that
+/// it outlives the daemon. It adds up how much was taken from each battery,
+/// which estimates the charge cycles of batteries that do not count them,
+/// see [`History::estimated_cycles`], and samples their health once a day for
+/// [`History::health_trend`].
+///
+/// The file has one `discharged SYSNAME AMOUNT` line per battery, a
+/// `calibrated SYSNAME UNIX_TIME AMOUNT` line for calibrated ones and
+/// `health SYSNAME UNIX_TIME PERCENT` lines for the health samples. It also
+/// keeps the charging status over the last 90 days as [`Segment`]s, in
+/// `segment START END STATUS CAPACITY CAPACITY` lines.
+///
+/// The segment going on is only saved by [`History::finish`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct History
+{
+    path: PathBuf,
+    batteries: BTreeMap<String, Battery>,
+    /// Finished segments, oldest first.
+    segments: Vec<Segment>,
+    /// The segment going on, which is only saved once finished.
+    current: Option<Segment>,
+}
+
+impl History
+{
+    /// Reads the history from `path`. A missing file is an empty history.
+    pub fn load(path: PathBuf) -> io::Result<Self>
+    {
+        let mut batteries = BTreeMap::new();
+        let mut segments = Vec::new();
+        let contents = match fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(err) => return Err(err),
+        };
+        for (i, line) in contents.lines().enumerate() {
+            let invalid = || {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("{}:{}: invalid line", path.display(), i + 1),
+                )
+            };
+            match line.split_whitespace().collect::<Vec<_>>().as_slice() {
+                [] => {}
+                ["discharged", sysname, amount] => {
+                    let battery: &mut Battery = batteries.entry(sysname.to_string()).or_default();
+                    battery.discharged = amount.parse().map_err(|_| invalid())?;
+                }
+                ["calibrated", sysname, at, amount] => {
+                    let at = at.parse().map_err(|_| invalid())?;
+                    let amount = amount.parse().map_err(|_| invalid())?;
+                    let battery: &mut Battery = batteries.entry(sysname.to_string()).or_default();
+                    battery.calibrated =
+                        Some((SystemTime::UNIX_EPOCH + Duration::from_secs(at), amount));
+                }
+                ["health", sysname, at, percent] => {
+                    let at = at.parse().map_err(|_| invalid())?;
+                    let percent = percent.parse().map_err(|_| invalid())?;
+                    let battery: &mut Battery = batteries.entry(sysname.to_string()).or_default();
+                    battery
+                        .health
+                        .push((SystemTime::UNIX_EPOCH + Duration::from_secs(at), percent));
+                }
+                ["segment", start, end, status, capacity_start, capacity_end] => {
+                    let time = |at: &str| {
+                        at.parse()
+                            .map(|at| SystemTime::UNIX_EPOCH + Duration::from_secs(at))
+                            .map_err(|_| invalid())
+                    };
+                    let capacity = |capacity: &str| match capacity {
+                        "-" => Ok(None),
+                        capacity => capacity.parse().map(Some).map_err(|_| invalid()),
+                    };
+                    segments.push(Segment {
+                        start: time(start)?,
+                        end: time(end)?,
+                        status: parse_status(status).ok_or_else(invalid)?,
+                        capacity_start: capacity(capacity_start)?,
+                        capacity_end: capacity(capacity_end)?,
+                    });
+                }
+                // Lines of later versions are skipped.
+                [_, ..] => {}
+            }
+        }
+        Ok(Self {
+            path,
+            batteries,
+            segments,
+            current: None,
+        })
+    }
+
+    /// Returns the default location: `$XDG_STATE_HOME/lithiumd/history`,
+    /// `~/.local/state/lithiumd/history`, or `/var/lib/lithiumd/history` for
+    /// a system-wide daemon.
+    #[must_use]
+    pub fn default_path() -> PathBuf
+    {
+        let dir = std::env::var_os("XDG_STATE_HOME")
+            .map(PathBuf::from)
+            .filter(|dir| dir.is_absolute())
+            .or_else(|| {
+                std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/state"))
+            });
+        match dir {
+            Some(dir) if unsafe { libc::geteuid() } != 0 => dir.join("lithiumd/history"),
+            _ => PathBuf::from("/var/lib/lithiumd/history"),
+        }
+    }
+
+    #[must_use]
+    pub fn path(&self) -> &Path
+    {
+        &self.path
+    }
+
+    /// Adds what the system batteries discharged since the last snapshot.
+    /// Returns whether the history changed and should be saved.
+    pub fn record(&mut self, snapshot: &Snapshot) -> bool
+    {
+        let mut changed = false;
+        for bat in snapshot.batteries() {
+            let Some(now) = bat.energy_now else {
+                continue;
+            };
+            let battery = self.batteries.entry(bat.sysname.clone()).or_default();
+            if let Some(last) = battery.last.filter(|_| bat.status == Status::Discharging) {
+                if now < last {
+                    battery.discharged += last - now;
+                    changed = true;
+                }
+            }
+            battery.last = Some(now);
+        }
+        self.record_segment(snapshot) || changed
+    }
+
+    /// Extends the current segment, or starts a new one if the charging
+    /// status changed. Returns whether a segment was finished.
+    fn record_segment(&mut self, snapshot: &Snapshot) -> bool
+    {
+        let now = SystemTime::now();
+        let capacity = snapshot.capacity();
+        if let Some(current) = &mut self.current {
+            current.end = now;
+            current.capacity_end = capacity;
+            if current.status == snapshot.status {
+                return false;
+            }
+        }
+        let finished = self.finish_segment();
+        if snapshot.status != Status::Unknown {
+            self.current = Some(Segment {
+                start: now,
+                end: now,
+                status: snapshot.status,
+                capacity_start: capacity,
+                capacity_end: capacity,
+            });
+        }
+        finished
+    }
+
+    fn finish_segment(&mut self) -> bool
+    {
+        let Some(current) = self.current.take() else {
+            return false;
+        };
+        self.segments.push(current);
+        let now = SystemTime::now();
+        self.segments.retain(|segment| {
+            now.duration_since(segment.end).unwrap_or_default() < SEGMENT_RETENTION
+        });
+        true
+    }
+
+    /// Finishes the segment going on, so that it is saved, e.g. when the
+    /// daemon stops.
+    pub fn finish(&mut self)
+    {
+        self.finish_segment();
+    }
+
+    /// Returns the segments, oldest first, including the one going on.
+    pub fn segments(&self) -> impl Iterator<Item = &Segment>
+    {
+        self.segments.iter().chain(&self.current)
+    }
+
+    /// Returns the charge cycles of the battery `sysname` estimated from how
+    /// much it discharged, in units of its current full charge `energy_full`.
+    #[must_use]
+    pub fn estimated_cycles(&self, sysname: &str, energy_full: u64) -> Option<f64>
+    {
+        let battery = self.batteries.get(sysname)?;
+        (energy_full > 0).then(|| battery.discharged as f64 / energy_full as f64)
+    }
+
+    /// Records the full charge of the battery `sysname` as measured by a
+    /// calibration run, in the unit of its `energy_now`.
+    pub fn record_calibration(&mut self, sysname: &str, measured: u64)
+    {
+        let battery = self.batteries.entry(sysname.to_string()).or_default();
+        battery.calibrated = Some((SystemTime::now(), measured));
+    }
+
+    /// Returns when the battery `sysname` was last calibrated, and the full
+    /// charge measured then.
+    #[must_use]
+    pub fn calibration(&self, sysname: &str) -> Option<(SystemTime, u64)>
+    {
+        self.batteries.get(sysname)?.calibrated
+    }
+
+    /// Returns whether a day passed since the health was last sampled, see
+    /// [`History::record_health`].
+    #[must_use]
+    pub fn health_due(&self) -> bool
+    {
+        let last = self
+            .batteries
+            .values()
+            .filter_map(|battery| battery.health.last())
+            .map(|(at, _)| *at)
+            .max();
+        last.is_none_or(|last| last.elapsed().unwrap_or_default() >= HEALTH_INTERVAL)
+    }
+
+    /// Adds a sample of the health of each battery that reports it. Returns
+    /// whether the history changed and should be saved.
+    pub fn record_health(&mut self, health: &[Health]) -> bool
+    {
+        let now = SystemTime::now();
+        let mut changed = false;
+        for health in health {
+            if let Some(percent) = health.health_percent() {
+                let battery = self.batteries.entry(health.sysname.clone()).or_default();
+                battery.health.push((now, percent));
+                changed = true;
+            }
+        }
+        changed
+    }
+
+    /// Fits a line through the health samples of the battery `sysname`, see
+    /// [`HealthTrend`]. `None` until the samples span a week.
+    #[must_use]
+    pub fn health_trend(&self, sysname: &str) -> Option<HealthTrend>
+    {
+        HealthTrend::fit(sysname, &self.batteries.get(sysname)?.health)
+    }
+
+    /// Replaces the file atomically.
+    pub fn save(&self) -> io::Result<()>
+    {
+        let mut contents = String::new();
+        for (sysname, battery) in &self.batteries {
+            contents += &format!("discharged {} {}\n", sysname, battery.discharged);
+            if let Some((at, measured)) = battery.calibrated {
+                contents += &format!("calibrated {} {} {}\n", sysname, unix_time(at), measured);
+            }
+            for (at, percent) in &battery.health {
+                contents += &format!("health {} {} {}\n", sysname, unix_time(*at), percent);
+            }
+        }
+        for segment in &self.segments {
+            let capacity = |capacity: Option<u8>| {
+                capacity.map_or("-".to_string(), |capacity| capacity.to_string())
+            };
+            contents += &format!(
+                "segment {} {} {} {} {}\n",
+                unix_time(segment.start),
+                unix_time(segment.end),
+                status_name(segment.status),
+                capacity(segment.capacity_start),
+                capacity(segment.capacity_end)
+            );
+        }
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut tmp = self.path.clone().into_os_string();
+        tmp.push(".tmp");
+        let mut file = fs::File::create(&tmp)?;
+        file.write_all(contents.as_bytes())?;
+        file.sync_all()?;
+        fs::rename(&tmp, &self.path)
+    }
+}
+
+fn unix_time(at: SystemTime) -> u64
+{
+    at.duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+fn status_name(status: Status) -> &'static str
+{
+    match status {
+        Status::Discharging => "discharging",
+        Status::Charging => "charging",
+        Status::Full => "full",
+        Status::NotCharging => "not_charging",
+        Status::Unknown => "unknown",
+    }
+}
+
+fn parse_status(s: &str) -> Option<Status>
+{
+    match s {
+        "discharging" => Some(Status::Discharging),
+        "charging" => Some(Status::Charging),
+        "full" => Some(Status::Full),
+        "not_charging" => Some(Status::NotCharging),
+        _ => None,
+    }
+}
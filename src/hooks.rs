@@ -0,0 +1,469 @@
+use std::fs;
+use std::io;
+use std::mem;
+use std::os::fd::OwnedFd;
+use std::os::unix::process::CommandExt;
+use std::path::Path;
+use std::path::PathBuf;
+use std::process::Child;
+use std::process::Command;
+use std::process::Stdio;
+use std::time::Duration;
+use std::time::Instant;
+
+use crate::privileges::Account;
+use crate::snapshot::Snapshot;
+
+/// `PATH` of hooks run as a [`HookUser`].
+const PATH: &str = "/usr/local/bin:/usr/bin:/bin";
+
+/// Runs hook commands with `sh -c` and reaps them once they exit. Hooks are
+/// never waited for: they are collected by [`HookRunner::reap`], e.g. on
+/// `SIGCHLD`, and killed there once their [`HookPolicy::timeout`] passed.
+///
+/// Hooks get the state of the power supply in their environment:
+/// `LITHIUMD_STATUS` holds the charging status and `LITHIUMD_CAPACITY` the
+/// battery capacity in percent, if known.
+#[derive(Debug, Default)]
+pub struct HookRunner
+{
+    children: Vec<Hook>,
+    user: Option<HookUser>,
+    /// The policy of hooks started with [`HookRunner::run`].
+    policy: HookPolicy,
+    next_id: u64,
+    /// Outcomes collected while starting a hook, for the next
+    /// [`HookRunner::reap`].
+    pending: Vec<HookOutcome>,
+}
+
+/// How long a hook may run and what happens when it fails, by exiting with
+/// an error or by running out of time.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HookPolicy
+{
+    /// How long the hook may run before it is killed, along with the
+    /// processes it started. `None` lets it run for as long as it takes.
+    pub timeout: Option<Duration>,
+    /// How often a failed hook is run again before it counts as failed.
+    /// Retries start as soon as the failure is reaped, without a delay.
+    pub retries: u32,
+    pub on_failure: OnFailure,
+}
+
+/// What the daemon does about a hook that failed for good.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OnFailure
+{
+    /// Only log the failure.
+    #[default]
+    Log,
+    /// Show a desktop notification as well.
+    Notify,
+    /// Skip the suspend or hibernation that waits for the hook.
+    Abort,
+}
+
+/// A hook that finished for good, as returned by [`HookRunner::reap`]: it
+/// succeeded, or its retries are used up.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HookOutcome
+{
+    /// What [`HookRunner::run_with`] returned.
+    pub id: u64,
+    pub command: String,
+    pub on_failure: OnFailure,
+    /// Why the last attempt failed, `None` if it succeeded.
+    pub failure: Option<String>,
+    /// How many times the hook ran, including retries.
+    pub attempts: u32,
+}
+
+/// A user that a daemon running as root runs hooks as, in a clean
+/// environment pointing at their desktop session: `XDG_RUNTIME_DIR`,
+/// `DBUS_SESSION_BUS_ADDRESS`, `WAYLAND_DISPLAY` and `DISPLAY` are set if the
+/// user has a session, so that `notify-send` and Wayland tools work.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HookUser
+{
+    name: String,
+    account: Account,
+}
+
+impl HookUser
+{
+    /// Looks up the user named `name`.
+    pub fn lookup(name: &str) -> io::Result<Self>
+    {
+        Ok(Self {
+            name: name.to_string(),
+            account: Account::lookup(name)
+                .map_err(|err| io::Error::new(err.kind(), format!("{}: {}", name, err)))?,
+        })
+    }
+
+    #[must_use]
+    pub fn name(&self) -> &str
+    {
+        &self.name
+    }
+
+    /// Returns the environment of hooks, read anew each time since the user
+    /// may log in after the daemon started.
+    #[must_use]
+    pub fn env(&self) -> Vec<(String, String)>
+    {
+        let mut env = vec![
+            ("PATH".to_string(), PATH.to_string()),
+            ("USER".to_string(), self.name.clone()),
+            ("LOGNAME".to_string(), self.name.clone()),
+            (
+                "HOME".to_string(),
+                self.account.home.to_string_lossy().into_owned(),
+            ),
+            (
+                "SHELL".to_string(),
+                self.account.shell.to_string_lossy().into_owned(),
+            ),
+        ];
+        let runtime_dir = PathBuf::from(format!("/run/user/{}", self.account.uid));
+        if !runtime_dir.is_dir() {
+            return env;
+        }
+        let bus = runtime_dir.join("bus");
+        if bus.exists() {
+            env.push((
+                "DBUS_SESSION_BUS_ADDRESS".to_string(),
+                format!("unix:path={}", bus.display()),
+            ));
+        }
+        if let Some(wayland) = first_socket(&runtime_dir, "wayland-") {
+            env.push(("WAYLAND_DISPLAY".to_string(), wayland));
+        }
+        if let Some(display) = first_socket(Path::new("/tmp/.X11-unix"), "X") {
+            env.push(("DISPLAY".to_string(), format!(":{}", display)));
+        }
+        env.push((
+            "XDG_RUNTIME_DIR".to_string(),
+            runtime_dir.to_string_lossy().into_owned(),
+        ));
+        env
+    }
+}
+
+/// Returns the rest of the name of the first socket in `dir` whose name
+/// starts with `prefix`, e.g. `wayland-1` or `0` for `X0`.
+fn first_socket(dir: &Path, prefix: &str) -> Option<String>
+{
+    let mut names = fs::read_dir(dir)
+        .ok()?
+        .flatten()
+        .filter_map(|entry| entry.file_name().into_string().ok())
+        .filter(|name| name.starts_with(prefix) && !name.ends_with(".lock"))
+        .collect::<Vec<_>>();
+    names.sort();
+    let name = names.into_iter().next()?;
+    Some(match prefix {
+        "X" => name[prefix.len()..].to_string(),
+        _ => name,
+    })
+}
+
+#[derive(Debug)]
+struct Hook
+{
+    id: u64,
+    child: Child,
+    command: String,
+    /// The state the hook was started with, to run it again with.
+    snapshot: Snapshot,
+    policy: HookPolicy,
+    /// How often the hook ran before.
+    attempt: u32,
+    /// When the hook is killed.
+    deadline: Option<Instant>,
+    timed_out: bool,
+    /// A logind inhibitor lock held until the hook exits.
+    _inhibitor: Option<OwnedFd>,
+}
+
+impl HookRunner
+{
+    #[must_use]
+    pub fn new() -> Self
+    {
+        Self::default()
+    }
+
+    /// Runs the hooks as `user` from now on, or as the daemon's user if
+    /// `None`.
+    pub fn set_user(&mut self, user: Option<HookUser>)
+    {
+        self.user = user;
+    }
+
+    /// Sets the policy of the hooks started with [`HookRunner::run`] and
+    /// [`HookRunner::run_holding`] from now on.
+    pub fn set_policy(&mut self, policy: HookPolicy)
+    {
+        self.policy = policy;
+    }
+
+    /// Starts `command` without waiting for it to finish.
+    pub fn run(&mut self, command: &str, snapshot: &Snapshot) -> io::Result<()>
+    {
+        self.run_holding(command, snapshot, None)
+    }
+
+    /// Starts `command` like [`HookRunner::run`] and keeps `inhibitor`, a
+    /// lock from [`Logind::inhibit`](crate::Logind::inhibit), until the hook
+    /// is reaped after it exited.
+    pub fn run_holding(
+        &mut self,
+        command: &str,
+        snapshot: &Snapshot,
+        inhibitor: Option<OwnedFd>,
+    ) -> io::Result<()>
+    {
+        let policy = self.policy.clone();
+        self.run_with(command, snapshot, inhibitor, &policy)
+            .map(drop)
+    }
+
+    /// Starts `command` like [`HookRunner::run_holding`] under `policy`, and
+    /// returns the id that [`HookRunner::reap`] reports its outcome with.
+    pub fn run_with(
+        &mut self,
+        command: &str,
+        snapshot: &Snapshot,
+        inhibitor: Option<OwnedFd>,
+        policy: &HookPolicy,
+    ) -> io::Result<u64>
+    {
+        self.collect();
+        let child = self.spawn(command, snapshot, policy)?;
+        let id = self.next_id;
+        self.next_id += 1;
+        self.children.push(Hook {
+            id,
+            child,
+            command: command.to_string(),
+            snapshot: snapshot.clone(),
+            policy: policy.clone(),
+            attempt: 0,
+            deadline: policy.timeout.map(|timeout| Instant::now() + timeout),
+            timed_out: false,
+            _inhibitor: inhibitor,
+        });
+        Ok(id)
+    }
+
+    fn spawn(&self, command: &str, snapshot: &Snapshot, policy: &HookPolicy) -> io::Result<Child>
+    {
+        let mut cmd = Command::new("sh");
+        cmd.arg("-c").arg(command).stdin(Stdio::null());
+        if let Some(user) = &self.user {
+            cmd.env_clear().envs(user.env());
+            let Account {
+                uid, gid, groups, ..
+            } = user.account.clone();
+            let home = &user.account.home;
+            cmd.current_dir(if home.is_dir() { home } else { Path::new("/") });
+            // Only async-signal-safe calls between fork and exec.
+            unsafe {
+                cmd.pre_exec(move || {
+                    if libc::setgroups(groups.len(), groups.as_ptr()) != 0
+                        || libc::setgid(gid) != 0
+                        || libc::setuid(uid) != 0
+                    {
+                        return Err(io::Error::last_os_error());
+                    }
+                    Ok(())
+                });
+            }
+        }
+        for (key, value) in snapshot.env_vars() {
+            match value {
+                Some(value) => cmd.env(key, value),
+                None => cmd.env_remove(key),
+            };
+        }
+        if policy.timeout.is_some() {
+            // A group of its own, so that killing it gets what it started.
+            cmd.process_group(0);
+        }
+        cmd.spawn()
+    }
+
+    /// Collects hooks that exited, reporting the ones that failed, and
+    /// releases their inhibitor locks. Hooks past their timeout are killed,
+    /// and failed ones run again while they have retries left. Returns the
+    /// hooks that finished for good.
+    pub fn reap(&mut self) -> Vec<HookOutcome>
+    {
+        let now = Instant::now();
+        let mut outcomes = mem::take(&mut self.pending);
+        for mut hook in mem::take(&mut self.children) {
+            if !hook.timed_out && hook.deadline.is_some_and(|deadline| now >= deadline) {
+                // Reaped on the `SIGCHLD` that follows.
+                unsafe {
+                    libc::kill(-(hook.child.id() as libc::pid_t), libc::SIGKILL);
+                }
+                hook.timed_out = true;
+            }
+            let failure = match hook.child.try_wait() {
+                Ok(None) => {
+                    self.children.push(hook);
+                    continue;
+                }
+                Ok(Some(_)) if hook.timed_out => Some(format!(
+                    "timed out after {}s",
+                    hook.policy.timeout.unwrap_or_default().as_secs()
+                )),
+                Ok(Some(status)) if status.success() => None,
+                Ok(Some(status)) => Some(format!("exited with {}", status)),
+                Err(err) => Some(err.to_string()),
+            };
+            let Some(failure) = failure else {
+                outcomes.push(hook.outcome(None));
+                continue;
+            };
+            if hook.attempt >= hook.policy.retries {
+                outcomes.push(hook.outcome(Some(failure)));
+                continue;
+            }
+            match self.spawn(&hook.command, &hook.snapshot, &hook.policy) {
+                Ok(child) => {
+                    hook.child = child;
+                    hook.attempt += 1;
+                    hook.deadline = hook.policy.timeout.map(|timeout| now + timeout);
+                    hook.timed_out = false;
+                    self.children.push(hook);
+                }
+                Err(err) => outcomes.push(hook.outcome(Some(err.to_string()))),
+            }
+        }
+        outcomes
+    }
+
+    /// Returns how long until the next hook runs out of time.
+    #[must_use]
+    pub fn timeout(&self, now: Instant) -> Option<Duration>
+    {
+        self.children
+            .iter()
+            .filter(|hook| !hook.timed_out)
+            .filter_map(|hook| hook.deadline)
+            .min()
+            .map(|deadline| deadline.saturating_duration_since(now))
+    }
+
+    /// Reaps the hooks that exited before starting another, for runners
+    /// whose owner does not reap them.
+    fn collect(&mut self)
+    {
+        self.pending = self.reap();
+    }
+}
+
+impl Hook
+{
+    fn outcome(self, failure: Option<String>) -> HookOutcome
+    {
+        HookOutcome {
+            id: self.id,
+            command: self.command,
+            on_failure: self.policy.on_failure,
+            failure,
+            attempts: self.attempt + 1,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use std::thread;
+
+    use super::*;
+
+    /// Reaps the hooks of `runner` until `count` of them finished for good.
+    fn finish(runner: &mut HookRunner, count: usize) -> Vec<HookOutcome>
+    {
+        let deadline = Instant::now() + Duration::from_secs(10);
+        let mut outcomes = Vec::new();
+        while outcomes.len() < count {
+            assert!(Instant::now() < deadline, "the hooks did not finish");
+            outcomes.extend(runner.reap());
+            thread::sleep(Duration::from_millis(10));
+        }
+        outcomes
+    }
+
+    #[test]
+    fn kills_hooks_that_run_out_of_time()
+    {
+        let mut runner = HookRunner::new();
+        let policy = HookPolicy {
+            timeout: Some(Duration::from_secs(1)),
+            ..HookPolicy::default()
+        };
+        let started = Instant::now();
+        runner
+            .run_with("sleep 5", &Snapshot::default(), None, &policy)
+            .unwrap();
+        let outcome = finish(&mut runner, 1).remove(0);
+        assert!(started.elapsed() < Duration::from_secs(5));
+        assert_eq!(outcome.failure.as_deref(), Some("timed out after 1s"));
+        assert_eq!(outcome.attempts, 1);
+    }
+
+    #[test]
+    fn counts_retries_as_attempts()
+    {
+        let mut runner = HookRunner::new();
+        let policy = HookPolicy {
+            retries: 2,
+            ..HookPolicy::default()
+        };
+        runner
+            .run_with("exit 1", &Snapshot::default(), None, &policy)
+            .unwrap();
+        let outcome = finish(&mut runner, 1).remove(0);
+        assert!(
+            outcome
+                .failure
+                .as_deref()
+                .is_some_and(|failure| failure.starts_with("exited with")),
+            "{:?}",
+            outcome.failure
+        );
+        assert_eq!(outcome.attempts, 3);
+
+        runner
+            .run_with("true", &Snapshot::default(), None, &policy)
+            .unwrap();
+        let outcome = finish(&mut runner, 1).remove(0);
+        assert_eq!(outcome.failure, None);
+        assert_eq!(outcome.attempts, 1);
+    }
+
+    #[test]
+    fn reports_the_policy_of_failed_hooks()
+    {
+        let mut runner = HookRunner::new();
+        let policy = HookPolicy {
+            on_failure: OnFailure::Abort,
+            ..HookPolicy::default()
+        };
+        runner.run("true", &Snapshot::default()).unwrap();
+        let id = runner
+            .run_with("exit 1", &Snapshot::default(), None, &policy)
+            .unwrap();
+        let outcomes = finish(&mut runner, 2);
+        let failed = outcomes.iter().find(|outcome| outcome.id == id).unwrap();
+        assert_eq!(failed.on_failure, OnFailure::Abort);
+        assert_eq!(failed.command, "exit 1");
+        assert!(failed.failure.is_some());
+    }
+}
@@ -0,0 +1,184 @@
+use std::io;
+use std::process::Command;
+use std::process::Stdio;
+use std::thread;
+use std::time::Duration;
+
+use crate::benchmark::parse_duration;
+use crate::ipc;
+use crate::json::quote;
+
+/// Options of `lithiumd hub`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Options
+{
+    /// Hosts to query over SSH; `local` is the daemon on this machine.
+    pub hosts: Vec<String>,
+    pub json: bool,
+    /// Refresh the view at this interval instead of printing it once.
+    pub watch: Option<Duration>,
+}
+
+impl Options
+{
+    pub fn parse(args: &[String]) -> Result<Self, String>
+    {
+        let mut options = Self {
+            hosts: Vec::new(),
+            json: false,
+            watch: None,
+        };
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--json" => options.json = true,
+                "--watch" => {
+                    let value = args
+                        .next()
+                        .ok_or_else(|| "`--watch` needs a value".to_string())?;
+                    options.watch = Some(parse_duration(value)?);
+                }
+                _ if arg.starts_with('-') => return Err(format!("unknown option `{}`", arg)),
+                host => options.hosts.push(host.to_string()),
+            }
+        }
+        if options.hosts.is_empty() {
+            return Err("no hosts given".to_string());
+        }
+        Ok(options)
+    }
+}
+
+/// The status of one host: the `Key: value` lines of its `status` command,
+/// or why it could not be queried.
+pub type HostStatus = Result<Vec<(String, String)>, String>;
+
+/// Queries every host's daemon, all at once, and prints a combined view.
+pub fn run(options: &Options) -> io::Result<()>
+{
+    loop {
+        let statuses = thread::scope(|scope| {
+            let handles = options
+                .hosts
+                .iter()
+                .map(|host| scope.spawn(|| fetch(host)))
+                .collect::<Vec<_>>();
+            handles
+                .into_iter()
+                .map(|handle| {
+                    handle
+                        .join()
+                        .unwrap_or_else(|_| Err("panicked".to_string()))
+                })
+                .collect::<Vec<_>>()
+        });
+        let hosts = options.hosts.iter().zip(&statuses);
+        if options.json {
+            println!("{}", json(hosts));
+        } else {
+            if options.watch.is_some() {
+                // Clear the screen and move the cursor home.
+                print!("\x1b[2J\x1b[H");
+            }
+            print!("{}", table(hosts));
+        }
+        match options.watch {
+            Some(interval) => thread::sleep(interval),
+            None => return Ok(()),
+        }
+    }
+}
+
+fn fetch(host: &str) -> HostStatus
+{
+    let output = if host == "local" {
+        ipc::send(&["status".to_string()]).map_err(|err| err.to_string())?
+    } else {
+        let output = Command::new("ssh")
+            .args(["-o", "BatchMode=yes", "-o", "ConnectTimeout=5", host])
+            .args(["lithiumd", "status"])
+            .stdin(Stdio::null())
+            .output()
+            .map_err(|err| format!("failed to run ssh: {}", err))?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(stderr.lines().last().unwrap_or("ssh failed").to_string());
+        }
+        String::from_utf8_lossy(&output.stdout).into_owned()
+    };
+    Ok(output
+        .lines()
+        .filter_map(|line| line.split_once(": "))
+        .map(|(key, value)| (key.trim().to_string(), value.trim().to_string()))
+        .collect())
+}
+
+pub fn field<'a>(status: &'a [(String, String)], key: &str) -> &'a str
+{
+    status
+        .iter()
+        .find(|(k, _)| k == key)
+        .map_or("-", |(_, value)| value)
+}
+
+pub fn table<'a>(hosts: impl Iterator<Item = (&'a String, &'a HostStatus)>) -> String
+{
+    let rows = hosts
+        .map(|(host, status)| match status {
+            Ok(status) => [
+                host.clone(),
+                field(status, "Charging status").to_string(),
+                field(status, "Level").to_string(),
+                field(status, "Capacity").to_string(),
+            ],
+            Err(err) => [
+                host.clone(),
+                "error".to_string(),
+                "-".to_string(),
+                err.clone(),
+            ],
+        })
+        .collect::<Vec<_>>();
+    let header = [
+        "HOST".to_string(),
+        "STATUS".to_string(),
+        "LEVEL".to_string(),
+        "CAPACITY".to_string(),
+    ];
+    let width = |column: usize| {
+        rows.iter()
+            .chain([&header])
+            .map(|row| row[column].len())
+            .max()
+            .unwrap_or(0)
+    };
+    let (host_width, status_width, level_width) = (width(0), width(1), width(2));
+    let mut table = String::new();
+    for row in [&header].into_iter().chain(&rows) {
+        table += &format!(
+            "{:host_width$}  {:status_width$}  {:level_width$}  {}\n",
+            row[0], row[1], row[2], row[3]
+        );
+    }
+    table
+}
+
+pub fn json<'a>(hosts: impl Iterator<Item = (&'a String, &'a HostStatus)>) -> String
+{
+    let hosts = hosts
+        .map(|(host, status)| {
+            let value = match status {
+                Ok(status) => {
+                    let fields = status
+                        .iter()
+                        .map(|(key, value)| format!("{}:{}", quote(key), quote(value)))
+                        .collect::<Vec<_>>();
+                    format!("{{{}}}", fields.join(","))
+                }
+                Err(err) => format!("{{\"error\":{}}}", quote(err)),
+            };
+            format!("{}:{}", quote(host), value)
+        })
+        .collect::<Vec<_>>();
+    format!("{{{}}}", hosts.join(","))
+}
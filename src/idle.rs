@@ -0,0 +1,207 @@
+use std::io;
+use std::time::Duration;
+
+#[cfg(feature = "mio")]
+use mio::event::Source;
+
+use crate::rules::Condition;
+use crate::snapshot::Snapshot;
+use crate::wayland::Arg;
+use crate::wayland::Connection;
+
+const NOTIFIER_INTERFACE: &str = "ext_idle_notifier_v1";
+
+/// Runs a command once the user has been idle for a while, like a timeout of
+/// swayidle. The timeout only applies while all of its conditions match, so
+/// that e.g. the screen can be locked sooner on battery.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IdleTimeout
+{
+    pub timeout: Duration,
+    pub conditions: Vec<Condition>,
+    /// Command to run when the timeout elapses.
+    pub command: String,
+    /// Command to run when the user is active again.
+    pub resume: Option<String>,
+}
+
+struct Notification
+{
+    /// The `ext_idle_notification_v1` object, while the timeout is armed.
+    object: Option<u32>,
+    idle: bool,
+}
+
+/// Tracks the [`IdleTimeout`]s with the `ext-idle-notify-v1` protocol of the
+/// Wayland compositor.
+///
+/// Register it with a mio `Poll` and call [`IdleMonitor::dispatch`] when it
+/// becomes readable. Both `dispatch` and [`IdleMonitor::update`] return the
+/// commands that have to be run.
+pub struct IdleMonitor
+{
+    conn: Connection,
+    notifier: u32,
+    seat: u32,
+    timeouts: Vec<IdleTimeout>,
+    notifications: Vec<Notification>,
+    inhibited: bool,
+}
+
+impl IdleMonitor
+{
+    /// Connects to the compositor. None of the timeouts is armed before the
+    /// first call to [`IdleMonitor::update`].
+    pub fn new(timeouts: Vec<IdleTimeout>) -> io::Result<Self>
+    {
+        let mut conn = Connection::connect()?;
+        let (registry, globals) = conn.globals()?;
+        let find = |interface: &str| {
+            globals
+                .iter()
+                .find(|global| global.interface == interface)
+                .ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::Unsupported,
+                        format!("the compositor does not support {}", interface),
+                    )
+                })
+        };
+        let notifier = find(NOTIFIER_INTERFACE)?;
+        let seat = find("wl_seat")?;
+        // Version 1 of both interfaces is all that is needed.
+        let notifier = conn.bind(
+            registry,
+            notifier.name,
+            NOTIFIER_INTERFACE,
+            notifier.version.min(1),
+        )?;
+        let seat = conn.bind(registry, seat.name, "wl_seat", seat.version.min(1))?;
+        conn.roundtrip()?;
+        Ok(Self {
+            conn,
+            notifier,
+            seat,
+            notifications: timeouts
+                .iter()
+                .map(|_| Notification {
+                    object: None,
+                    idle: false,
+                })
+                .collect(),
+            timeouts,
+            inhibited: false,
+        })
+    }
+
+    /// While inhibited, no timeout is armed. Takes effect with the next call
+    /// to [`IdleMonitor::update`].
+    pub fn set_inhibited(&mut self, inhibited: bool)
+    {
+        self.inhibited = inhibited;
+    }
+
+    #[must_use]
+    pub fn timeouts(&self) -> &[IdleTimeout]
+    {
+        &self.timeouts
+    }
+
+    /// Arms the timeouts whose conditions match `snapshot` and disarms the
+    /// others, or all of them while inhibited. Returns the resume commands of
+    /// disarmed timeouts that had elapsed.
+    pub fn update(&mut self, snapshot: &Snapshot) -> io::Result<Vec<&str>>
+    {
+        let mut commands = Vec::new();
+        for (timeout, notification) in self.timeouts.iter().zip(&mut self.notifications) {
+            let applies = !self.inhibited
+                && timeout
+                    .conditions
+                    .iter()
+                    .all(|condition| condition.matches(snapshot));
+            match (applies, notification.object) {
+                (true, None) => {
+                    let object = self.conn.new_id();
+                    let ms = timeout.timeout.as_millis().min(u32::MAX as u128) as u32;
+                    self.conn.request(
+                        self.notifier,
+                        1,
+                        &[Arg::NewId(object), Arg::Uint(ms), Arg::Object(self.seat)],
+                    )?;
+                    notification.object = Some(object);
+                }
+                (false, Some(object)) => {
+                    self.conn.request(object, 0, &[])?;
+                    notification.object = None;
+                    if notification.idle {
+                        notification.idle = false;
+                        commands.extend(timeout.resume.as_deref());
+                    }
+                }
+                _ => {}
+            }
+        }
+        Ok(commands)
+    }
+
+    /// Reads the pending events of the compositor and returns the commands
+    /// of the timeouts that elapsed or were resumed from.
+    pub fn dispatch(&mut self) -> io::Result<Vec<&str>>
+    {
+        let mut commands = Vec::new();
+        while let Some(event) = self.conn.read()? {
+            let Some(index) = self
+                .notifications
+                .iter()
+                .position(|notification| notification.object == Some(event.object))
+            else {
+                continue;
+            };
+            let timeout = &self.timeouts[index];
+            let notification = &mut self.notifications[index];
+            match event.opcode {
+                // idled
+                0 => {
+                    notification.idle = true;
+                    commands.push(timeout.command.as_str());
+                }
+                // resumed
+                1 if notification.idle => {
+                    notification.idle = false;
+                    commands.extend(timeout.resume.as_deref());
+                }
+                _ => {}
+            }
+        }
+        Ok(commands)
+    }
+}
+
+#[cfg(feature = "mio")]
+impl Source for IdleMonitor
+{
+    fn register(
+        &mut self,
+        registry: &mio::Registry,
+        token: mio::Token,
+        interests: mio::Interest,
+    ) -> io::Result<()>
+    {
+        self.conn.register(registry, token, interests)
+    }
+
+    fn reregister(
+        &mut self,
+        registry: &mio::Registry,
+        token: mio::Token,
+        interests: mio::Interest,
+    ) -> io::Result<()>
+    {
+        self.conn.reregister(registry, token, interests)
+    }
+
+    fn deregister(&mut self, registry: &mio::Registry) -> io::Result<()>
+    {
+        self.conn.deregister(registry)
+    }
+}
@@ -0,0 +1,126 @@
+use std::io;
+use std::time::Duration;
+use std::time::Instant;
+
+use lithiumd::rules::Condition;
+use lithiumd::HookRunner;
+use lithiumd::Logind;
+use lithiumd::Snapshot;
+
+/// How often logind is asked whether the session is idle.
+const CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+/// What to do once the session has been idle long enough.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IdleAction
+{
+    Suspend,
+    /// Run a hook command.
+    Run(String),
+}
+
+/// An action taken once logind's `IdleHint` has been set for a while, from a
+/// `[[session_idle]]` table. Unlike an [`IdleTimeout`](lithiumd::IdleTimeout)
+/// it needs no Wayland compositor, e.g. on a text console or under X11.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SessionIdle
+{
+    pub timeout: Duration,
+    pub conditions: Vec<Condition>,
+    pub action: IdleAction,
+}
+
+/// Runs the [`SessionIdle`] actions, each once per idle period and only
+/// while its conditions hold, checking logind every 30 seconds.
+pub struct IdlePolicy
+{
+    logind: Logind,
+    actions: Vec<SessionIdle>,
+    /// Whether each action was taken in the current idle period.
+    taken: Vec<bool>,
+    next_check: Instant,
+    inhibited: bool,
+}
+
+impl IdlePolicy
+{
+    pub fn new(actions: Vec<SessionIdle>) -> io::Result<Self>
+    {
+        Ok(Self {
+            logind: Logind::new()?,
+            taken: vec![false; actions.len()],
+            actions,
+            next_check: Instant::now(),
+            inhibited: false,
+        })
+    }
+
+    #[must_use]
+    pub fn actions(&self) -> &[SessionIdle]
+    {
+        &self.actions
+    }
+
+    /// While inhibited, no action is taken.
+    pub fn set_inhibited(&mut self, inhibited: bool)
+    {
+        self.inhibited = inhibited;
+    }
+
+    /// Returns how long the poll may sleep before logind has to be asked
+    /// again.
+    #[must_use]
+    pub fn timeout(&self) -> Duration
+    {
+        self.next_check.saturating_duration_since(Instant::now())
+    }
+
+    /// Asks logind whether the session is idle, if it is time to, and takes
+    /// the actions whose timeout elapsed.
+    pub fn check(&mut self, hooks: &mut HookRunner, snapshot: &Snapshot) -> io::Result<()>
+    {
+        let now = Instant::now();
+        if now < self.next_check {
+            return Ok(());
+        }
+        let Some(idle) = self.logind.idle_time()? else {
+            self.taken.fill(false);
+            self.next_check = now + CHECK_INTERVAL;
+            return Ok(());
+        };
+        let mut next = CHECK_INTERVAL;
+        for (action, taken) in self.actions.iter().zip(&mut self.taken) {
+            if *taken {
+                continue;
+            }
+            if idle < action.timeout {
+                next = next.min(action.timeout - idle);
+                continue;
+            }
+            if self.inhibited
+                || !action
+                    .conditions
+                    .iter()
+                    .all(|condition| condition.matches(snapshot))
+            {
+                continue;
+            }
+            *taken = true;
+            match &action.action {
+                IdleAction::Suspend => {
+                    println!("Idle for {}s, suspending", idle.as_secs());
+                    if let Err(err) = self.logind.suspend() {
+                        eprintln!("Failed to suspend: {}", err);
+                    }
+                }
+                IdleAction::Run(command) => {
+                    if let Err(err) = hooks.run(command, snapshot) {
+                        eprintln!("Failed to run hook `{}`: {}", command, err);
+                    }
+                }
+            }
+        }
+        self.next_check = now + next;
+        Ok(())
+    }
+}
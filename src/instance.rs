@@ -0,0 +1,188 @@
+use std::env;
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::Read;
+use std::io::Seek;
+use std::io::Write;
+use std::os::fd::AsRawFd;
+use std::path::Path;
+use std::path::PathBuf;
+use std::process;
+use std::thread;
+use std::time::Duration;
+use std::time::Instant;
+
+/// How long `--replace` waits for the running daemon to exit.
+const REPLACE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Returns the path of the lock and PID file: `$XDG_RUNTIME_DIR/lithiumd.pid`,
+/// or `/run/lithiumd.pid` for a system-wide daemon.
+pub fn lock_path() -> PathBuf
+{
+    match env::var_os("XDG_RUNTIME_DIR") {
+        Some(dir) => PathBuf::from(dir).join("lithiumd.pid"),
+        None => PathBuf::from("/run/lithiumd.pid"),
+    }
+}
+
+/// An exclusive `flock(2)` on the PID file, so that two daemons do not run
+/// hooks and actions against each other. The kernel releases it when the
+/// daemon exits, however it exits. The file stays in place, as a daemon
+/// that waits for the lock holds the open file and would otherwise lock one
+/// that is gone while another daemon creates and locks a new one.
+pub struct InstanceLock
+{
+    file: File,
+}
+
+impl InstanceLock
+{
+    /// Takes the lock and writes the PID of the daemon to the file. If
+    /// another daemon holds it, this fails, or with `replace` asks that
+    /// daemon to exit and takes over once it did.
+    pub fn acquire(replace: bool) -> io::Result<Self>
+    {
+        Self::acquire_at(&lock_path(), replace)
+    }
+
+    /// Takes the lock on the file `path` instead, see
+    /// [`InstanceLock::acquire`].
+    pub fn acquire_at(path: &Path, replace: bool) -> io::Result<Self>
+    {
+        let mut file = fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)
+            .map_err(|err| io::Error::new(err.kind(), format!("{}: {}", path.display(), err)))?;
+        if !try_lock(&file)? {
+            let mut contents = String::new();
+            file.read_to_string(&mut contents)?;
+            let pid = contents.trim().parse::<libc::pid_t>().ok();
+            let pid = match (pid, replace) {
+                (Some(pid), true) => pid,
+                (Some(pid), false) => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::AddrInUse,
+                        format!(
+                            "another daemon is running (PID {}), pass --replace to take over",
+                            pid
+                        ),
+                    ))
+                }
+                (None, _) => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::AddrInUse,
+                        format!("{} is locked by another daemon", path.display()),
+                    ))
+                }
+            };
+            if unsafe { libc::kill(pid, libc::SIGTERM) } != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            let deadline = Instant::now() + REPLACE_TIMEOUT;
+            while !try_lock(&file)? {
+                if Instant::now() >= deadline {
+                    return Err(io::Error::new(
+                        io::ErrorKind::TimedOut,
+                        format!("the daemon with PID {} did not exit", pid),
+                    ));
+                }
+                thread::sleep(Duration::from_millis(100));
+            }
+        }
+        let mut lock = Self { file };
+        lock.write_pid()?;
+        Ok(lock)
+    }
+
+    /// Writes the PID of this process to the file, e.g. again after the
+    /// daemon forked into the background. The lock is inherited.
+    pub fn write_pid(&mut self) -> io::Result<()>
+    {
+        self.file.set_len(0)?;
+        self.file.rewind()?;
+        writeln!(self.file, "{}", process::id())
+    }
+}
+
+impl Drop for InstanceLock
+{
+    /// Empties the file, so that it names no PID once the lock is released.
+    fn drop(&mut self)
+    {
+        let _ = self.file.set_len(0);
+    }
+}
+
+/// Tries to take the lock without blocking.
+fn try_lock(file: &File) -> io::Result<bool>
+{
+    if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) } == 0 {
+        return Ok(true);
+    }
+    let err = io::Error::last_os_error();
+    match err.kind() {
+        io::ErrorKind::WouldBlock => Ok(false),
+        _ => Err(err),
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use std::sync::atomic::AtomicUsize;
+    use std::sync::atomic::Ordering;
+    use std::sync::Arc;
+
+    use super::*;
+
+    #[test]
+    fn one_daemon_holds_the_lock_at_a_time()
+    {
+        let path = env::temp_dir().join(format!("lithiumd-test-{}.pid", process::id()));
+        let holders = Arc::new(AtomicUsize::new(0));
+        let acquired = Arc::new(AtomicUsize::new(0));
+        let threads = (0..8)
+            .map(|_| {
+                let path = path.clone();
+                let holders = Arc::clone(&holders);
+                let acquired = Arc::clone(&acquired);
+                thread::spawn(move || {
+                    for _ in 0..200 {
+                        let Ok(lock) = InstanceLock::acquire_at(&path, false) else {
+                            continue;
+                        };
+                        assert_eq!(holders.fetch_add(1, Ordering::SeqCst), 0);
+                        acquired.fetch_add(1, Ordering::SeqCst);
+                        thread::yield_now();
+                        holders.fetch_sub(1, Ordering::SeqCst);
+                        drop(lock);
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+        assert!(acquired.load(Ordering::SeqCst) > 0);
+        assert!(path.exists());
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn refuses_a_second_daemon()
+    {
+        let path = env::temp_dir().join(format!("lithiumd-test-second-{}.pid", process::id()));
+        let lock = InstanceLock::acquire_at(&path, false).unwrap();
+        let err = InstanceLock::acquire_at(&path, false).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::AddrInUse);
+        assert!(err.to_string().contains(&format!("PID {}", process::id())));
+        drop(lock);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "");
+        drop(InstanceLock::acquire_at(&path, false).unwrap());
+        let _ = fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,197 @@
+use std::env;
+use std::fs;
+use std::io;
+use std::io::BufRead;
+use std::io::BufReader;
+use std::io::Read;
+use std::io::Write;
+use std::os::fd::FromRawFd;
+use std::os::fd::IntoRawFd;
+use std::os::unix::net::UnixStream;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use mio::event::Source;
+use mio::net::UnixListener;
+
+/// How long the daemon waits for a client to send its request.
+const REQUEST_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// Returns the path of the control socket: `$XDG_RUNTIME_DIR/lithiumd.sock`,
+/// or `/run/lithiumd.sock` for a system-wide daemon.
+pub fn socket_path() -> PathBuf
+{
+    match env::var_os("XDG_RUNTIME_DIR") {
+        Some(dir) => PathBuf::from(dir).join("lithiumd.sock"),
+        None => PathBuf::from("/run/lithiumd.sock"),
+    }
+}
+
+/// The socket the daemon accepts commands on. Every connection carries a
+/// single request line, made of whitespace-separated words, and the daemon
+/// answers with its response before closing the connection.
+pub struct ControlSocket
+{
+    listener: UnixListener,
+    path: PathBuf,
+}
+
+/// A command received on the control socket.
+pub struct Request
+{
+    stream: UnixStream,
+    pub args: Vec<String>,
+}
+
+impl ControlSocket
+{
+    /// Binds the control socket, replacing a stale one left behind by a
+    /// daemon that did not shut down cleanly.
+    pub fn bind() -> io::Result<Self>
+    {
+        Self::bind_at(socket_path())
+    }
+
+    /// Binds the control socket at `path`.
+    pub fn bind_at(path: PathBuf) -> io::Result<Self>
+    {
+        if UnixStream::connect(&path).is_ok() {
+            return Err(io::Error::new(
+                io::ErrorKind::AddrInUse,
+                format!("another daemon is listening on {}", path.display()),
+            ));
+        }
+        let _ = fs::remove_file(&path);
+        let listener = UnixListener::bind(&path)?;
+        Ok(Self { listener, path })
+    }
+
+    /// Accepts the next pending connection and reads its request. Returns
+    /// `None` once there are no more pending connections. Connections that
+    /// fail or send nothing within [`REQUEST_TIMEOUT`] are dropped; an error
+    /// means that the listener itself cannot accept right now, e.g. because
+    /// the daemon ran out of file descriptors.
+    pub fn accept(&mut self) -> io::Result<Option<Request>>
+    {
+        loop {
+            let stream = match self.listener.accept() {
+                Ok((stream, _)) => stream,
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => return Ok(None),
+                Err(err)
+                    if matches!(
+                        err.kind(),
+                        io::ErrorKind::ConnectionAborted | io::ErrorKind::Interrupted
+                    ) =>
+                {
+                    continue
+                }
+                Err(err) => return Err(err),
+            };
+            // The request is read synchronously; clients send it right away.
+            let stream = unsafe { UnixStream::from_raw_fd(stream.into_raw_fd()) };
+            if stream.set_nonblocking(false).is_err()
+                || stream.set_read_timeout(Some(REQUEST_TIMEOUT)).is_err()
+            {
+                continue;
+            }
+            let mut line = String::new();
+            if BufReader::new(&stream).read_line(&mut line).is_err() {
+                continue;
+            }
+            return Ok(Some(Request {
+                stream,
+                args: line.split_whitespace().map(str::to_string).collect(),
+            }));
+        }
+    }
+}
+
+impl Request
+{
+    /// Sends the response and closes the connection.
+    pub fn respond(mut self, response: &str)
+    {
+        let _ = self.stream.write_all(response.as_bytes());
+    }
+}
+
+impl Drop for ControlSocket
+{
+    fn drop(&mut self)
+    {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+impl Source for ControlSocket
+{
+    fn register(
+        &mut self,
+        registry: &mio::Registry,
+        token: mio::Token,
+        interests: mio::Interest,
+    ) -> io::Result<()>
+    {
+        self.listener.register(registry, token, interests)
+    }
+
+    fn reregister(
+        &mut self,
+        registry: &mio::Registry,
+        token: mio::Token,
+        interests: mio::Interest,
+    ) -> io::Result<()>
+    {
+        self.listener.reregister(registry, token, interests)
+    }
+
+    fn deregister(&mut self, registry: &mio::Registry) -> io::Result<()>
+    {
+        self.listener.deregister(registry)
+    }
+}
+
+/// Sends `args` to the running daemon and returns its response.
+pub fn send(args: &[String]) -> io::Result<String>
+{
+    let path = socket_path();
+    let mut stream = UnixStream::connect(&path).map_err(|err| {
+        io::Error::new(
+            err.kind(),
+            format!(
+                "cannot connect to the daemon at {}: {}",
+                path.display(),
+                err
+            ),
+        )
+    })?;
+    stream.write_all(format!("{}\n", args.join(" ")).as_bytes())?;
+    let mut response = String::new();
+    stream.read_to_string(&mut response)?;
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests
+{
+    use std::process;
+
+    use super::*;
+
+    #[test]
+    fn drops_clients_that_send_nothing()
+    {
+        let path = env::temp_dir().join(format!("lithiumd-test-{}.sock", process::id()));
+        let mut control = ControlSocket::bind_at(path.clone()).unwrap();
+        let _idle = UnixStream::connect(&path).unwrap();
+        let mut client = UnixStream::connect(&path).unwrap();
+        client.write_all(b"status --json\n").unwrap();
+        let request = control.accept().unwrap().unwrap();
+        assert_eq!(request.args, ["status", "--json"]);
+        request.respond("ok\n");
+        let mut response = String::new();
+        client.read_to_string(&mut response).unwrap();
+        assert_eq!(response, "ok\n");
+        assert!(control.accept().unwrap().is_none());
+    }
+}
@@ -0,0 +1,241 @@
+use std::fmt::Display;
+use std::time::Duration;
+use std::time::SystemTime;
+
+use lithiumd::BatteryLevel;
+use lithiumd::DeviceSnapshot;
+use lithiumd::DeviceType;
+use lithiumd::Fan;
+use lithiumd::Health;
+use lithiumd::HealthTrend;
+use lithiumd::Scope;
+use lithiumd::Sessions;
+use lithiumd::Snapshot;
+use lithiumd::SourceKind;
+use lithiumd::Status;
+
+/// Describes the state of the power supply as a JSON object, as returned by
+/// `status --json`. The schema is documented in the README and only gains
+/// fields.
+pub fn status(
+    snapshot: &Snapshot,
+    level: Option<BatteryLevel>,
+    time_remaining: Option<Duration>,
+    sessions: Option<Sessions>,
+    inhibited: bool,
+    fans: &[Fan],
+) -> String
+{
+    let sessions = sessions.map(|sessions| {
+        format!(
+            "{{\"source\":{},\"current\":{},\"on_battery_total\":{},\"on_ac_total\":{}}}",
+            quote(if sessions.on_ac { "ac" } else { "battery" }),
+            sessions.current.as_secs(),
+            sessions.on_battery_total.as_secs(),
+            sessions.on_ac_total.as_secs(),
+        )
+    });
+    format!(
+        "{{\"status\":{},\"capacity\":{},\"level\":{},\"time_remaining\":{},\"charging_speed\":{},\"power_source\":{},\"session\":{},\"charge_limited\":{},\"inhibited\":{},\"devices\":{},\"fans\":{}}}",
+        quote(status_name(snapshot.status)),
+        optional(snapshot.capacity()),
+        optional(level.map(|level| quote(&level.to_string()))),
+        optional(time_remaining.map(|remaining| remaining.as_secs())),
+        optional(snapshot.charging_speed().map(|speed| quote(&speed.to_string()))),
+        optional(snapshot.source.as_ref().map(|source| {
+            format!(
+                "{{\"sysname\":{},\"kind\":{}}}",
+                quote(&source.sysname),
+                quote(source_kind_name(source.kind))
+            )
+        })),
+        optional(sessions),
+        snapshot.charge_limited().is_some(),
+        inhibited,
+        devices(snapshot),
+        self::fans(fans),
+    )
+}
+
+fn fans(fans: &[Fan]) -> String
+{
+    let fans = fans
+        .iter()
+        .map(|fan| {
+            format!(
+                "{{\"name\":{},\"label\":{},\"rpm\":{}}}",
+                quote(&fan.name),
+                optional(fan.label.as_deref().map(quote)),
+                fan.rpm
+            )
+        })
+        .collect::<Vec<_>>();
+    format!("[{}]", fans.join(","))
+}
+
+/// Describes every device as an array of JSON objects, as returned by
+/// `devices --json`.
+pub fn devices(snapshot: &Snapshot) -> String
+{
+    let devices = snapshot.devices.iter().map(device).collect::<Vec<_>>();
+    format!("[{}]", devices.join(","))
+}
+
+fn device(dev: &DeviceSnapshot) -> String
+{
+    format!(
+        "{{\"sysname\":{},\"model\":{},\"type\":{},\"scope\":{},\"status\":{},\"capacity\":{},\"energy_full\":{},\"energy_now\":{},\"power_now\":{},\"reports_energy\":{},\"voltage\":{},\"current\":{},\"alarm\":{},\"charge_limit\":{},\"input_power\":{},\"peak_input_power\":{}}}",
+        quote(&dev.sysname),
+        optional(dev.model.as_deref().map(quote)),
+        quote(match dev.device_type {
+            DeviceType::Battery => "battery",
+            DeviceType::Adapter => "adapter",
+            DeviceType::Wireless => "wireless",
+        }),
+        quote(match dev.scope {
+            Scope::System => "system",
+            Scope::Device => "device",
+        }),
+        quote(status_name(dev.status)),
+        optional(dev.capacity),
+        optional(dev.energy_full),
+        optional(dev.energy_now),
+        optional(dev.power_now),
+        dev.reports_energy,
+        optional(dev.voltage()),
+        optional(dev.current()),
+        optional(dev.alarm),
+        optional(dev.charge_limit),
+        optional(dev.input_power),
+        optional(dev.peak_input_power),
+    )
+}
+
+/// Describes the wear of every system battery as an array of JSON objects,
+/// as returned by `health --json`.
+pub fn health(health: &[Health]) -> String
+{
+    let batteries = health
+        .iter()
+        .map(|health| {
+            format!(
+                "{{\"sysname\":{},\"health_percent\":{},\"cycle_count\":{},\"estimated_cycles\":{},\"energy_full\":{},\"energy_full_design\":{}}}",
+                quote(&health.sysname),
+                optional(health.health_percent()),
+                optional(health.cycle_count),
+                optional(health.estimated_cycles.map(|cycles| format!("{:.1}", cycles))),
+                optional(health.energy_full),
+                optional(health.energy_full_design),
+            )
+        })
+        .collect::<Vec<_>>();
+    format!("[{}]", batteries.join(","))
+}
+
+/// Describes the health trend of the system batteries as an array of JSON
+/// objects, as returned by `health --trend --json`.
+pub fn health_trends(trends: &[HealthTrend]) -> String
+{
+    let trends = trends
+        .iter()
+        .map(|trend| {
+            let below_80 = trend.below_80.map(|at| {
+                at.duration_since(SystemTime::UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .as_secs()
+            });
+            format!(
+                "{{\"sysname\":{},\"samples\":{},\"health_percent\":{:.1},\"percent_per_year\":{:.2},\"below_80\":{}}}",
+                quote(&trend.sysname),
+                trend.samples,
+                trend.health_percent,
+                trend.percent_per_year,
+                optional(below_80),
+            )
+        })
+        .collect::<Vec<_>>();
+    format!("[{}]", trends.join(","))
+}
+
+/// Returns the name of `status` in the JSON schema, which does not follow
+/// its `Display` output.
+pub fn status_name(status: Status) -> &'static str
+{
+    match status {
+        Status::Discharging => "discharging",
+        Status::Charging => "charging",
+        Status::Full => "full",
+        Status::NotCharging => "not_charging",
+        Status::Unknown => "unknown",
+    }
+}
+
+fn source_kind_name(kind: SourceKind) -> &'static str
+{
+    match kind {
+        SourceKind::Mains => "mains",
+        SourceKind::Usb => "usb",
+        SourceKind::Wireless => "wireless",
+    }
+}
+
+fn optional(value: Option<impl Display>) -> String
+{
+    value.map_or_else(|| "null".to_string(), |value| value.to_string())
+}
+
+/// Quotes `s` as a JSON string.
+pub fn quote(s: &str) -> String
+{
+    let mut quoted = String::from('"');
+    for c in s.chars() {
+        match c {
+            '"' => quoted += "\\\"",
+            '\\' => quoted += "\\\\",
+            c if u32::from(c) < 0x20 => quoted += &format!("\\u{:04x}", u32::from(c)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn quotes_strings()
+    {
+        assert_eq!(quote(""), r#""""#);
+        assert_eq!(quote("BAT0"), r#""BAT0""#);
+        assert_eq!(quote(r#"a "b" \c"#), r#""a \"b\" \\c""#);
+        assert_eq!(
+            quote("a\nb\t\u{1}\u{1f}"),
+            r#""a\u000ab\u0009\u0001\u001f""#
+        );
+        assert_eq!(quote("µ °C \u{7f} 🔋"), "\"µ °C \u{7f} 🔋\"");
+    }
+
+    #[test]
+    fn describes_an_unknown_power_supply()
+    {
+        let fans = [Fan {
+            name: "thinkpad/fan1".to_string(),
+            label: None,
+            rpm: 2400,
+        }];
+        assert_eq!(
+            status(&Snapshot::default(), None, None, None, false, &fans),
+            concat!(
+                r#"{"status":"unknown","capacity":null,"level":null,"time_remaining":null,"#,
+                r#""charging_speed":null,"power_source":null,"session":null,"#,
+                r#""charge_limited":false,"inhibited":false,"devices":[],"#,
+                r#""fans":[{"name":"thinkpad/fan1","label":null,"rpm":2400}]}"#,
+            )
+        );
+        assert_eq!(devices(&Snapshot::default()), "[]");
+        assert_eq!(health(&[]), "[]");
+    }
+}
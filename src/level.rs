@@ -0,0 +1,83 @@
+use std::fmt::Display;
+
+/// The capacity of a battery in coarse buckets, for consumers that only
+/// care whether it runs low:
+///
+/// ```
+/// use lithiumd::BatteryLevel;
+/// use lithiumd::LevelThresholds;
+///
+/// let thresholds = LevelThresholds::default();
+/// assert_eq!(BatteryLevel::of(4, &thresholds), BatteryLevel::Critical);
+/// assert_eq!(BatteryLevel::of(50, &thresholds), BatteryLevel::Normal);
+/// assert_eq!(BatteryLevel::of(100, &thresholds), BatteryLevel::Full);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum BatteryLevel
+{
+    Critical,
+    Low,
+    Normal,
+    High,
+    Full,
+}
+
+/// Capacities in percent at which the [`BatteryLevel`]s start. A battery is
+/// critical or low at and below its threshold, and high or full at and above
+/// it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LevelThresholds
+{
+    pub critical: u8,
+    pub low: u8,
+    pub high: u8,
+    pub full: u8,
+}
+
+impl Default for LevelThresholds
+{
+    fn default() -> Self
+    {
+        Self {
+            critical: 5,
+            low: 20,
+            high: 80,
+            full: 100,
+        }
+    }
+}
+
+impl BatteryLevel
+{
+    #[must_use]
+    pub fn of(capacity: u8, thresholds: &LevelThresholds) -> Self
+    {
+        if capacity <= thresholds.critical {
+            BatteryLevel::Critical
+        } else if capacity <= thresholds.low {
+            BatteryLevel::Low
+        } else if capacity >= thresholds.full {
+            BatteryLevel::Full
+        } else if capacity >= thresholds.high {
+            BatteryLevel::High
+        } else {
+            BatteryLevel::Normal
+        }
+    }
+}
+
+impl Display for BatteryLevel
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result
+    {
+        match *self {
+            BatteryLevel::Critical => write!(f, "critical"),
+            BatteryLevel::Low => write!(f, "low"),
+            BatteryLevel::Normal => write!(f, "normal"),
+            BatteryLevel::High => write!(f, "high"),
+            BatteryLevel::Full => write!(f, "full"),
+        }
+    }
+}
@@ -0,0 +1,277 @@
+mod backend;
+#[cfg(target_os = "linux")]
+mod backlight;
+#[cfg(target_os = "linux")]
+mod bluez;
+#[cfg(target_os = "linux")]
+mod charging_speed;
+#[cfg(target_os = "linux")]
+mod clock;
+#[cfg(target_os = "linux")]
+mod cpufreq;
+#[cfg(target_os = "linux")]
+pub mod dbus;
+#[cfg(target_os = "linux")]
+mod displays;
+#[cfg(target_os = "linux")]
+mod dock;
+#[cfg(target_os = "linux")]
+mod drain;
+#[cfg(target_os = "linux")]
+mod estimate;
+#[cfg(target_os = "linux")]
+mod event;
+#[cfg(all(target_os = "linux", feature = "mio"))]
+mod event_loop;
+#[cfg(target_os = "linux")]
+mod expr;
+#[cfg(target_os = "linux")]
+mod fan;
+#[cfg(all(target_os = "linux", feature = "ffi"))]
+mod ffi;
+#[cfg(target_os = "linux")]
+mod format;
+#[cfg(target_os = "linux")]
+mod health;
+#[cfg(target_os = "linux")]
+mod history;
+#[cfg(target_os = "linux")]
+mod hooks;
+#[cfg(target_os = "linux")]
+mod idle;
+#[cfg(target_os = "linux")]
+mod level;
+#[cfg(target_os = "linux")]
+mod lid;
+#[cfg(target_os = "linux")]
+mod logind;
+#[cfg(target_os = "linux")]
+mod metrics;
+#[cfg(target_os = "linux")]
+mod mock;
+#[cfg(target_os = "linux")]
+mod monitor;
+#[cfg(target_os = "linux")]
+mod notifications;
+#[cfg(target_os = "linux")]
+mod power_profiles;
+#[cfg(target_os = "linux")]
+mod power_supply;
+#[cfg(target_os = "linux")]
+mod pretty;
+#[cfg(target_os = "linux")]
+pub mod privileges;
+#[cfg(all(target_os = "linux", feature = "async-io"))]
+mod reactor;
+#[cfg(target_os = "linux")]
+mod resume;
+#[cfg(target_os = "linux")]
+pub mod rtc;
+#[cfg(target_os = "linux")]
+pub mod rules;
+#[cfg(target_os = "linux")]
+mod runtime_pm;
+#[cfg(all(target_os = "linux", any(test, feature = "test-util")))]
+mod scenario;
+#[cfg(target_os = "linux")]
+mod session;
+#[cfg(target_os = "linux")]
+mod snapshot;
+#[cfg(target_os = "linux")]
+mod source;
+#[cfg(all(target_os = "linux", feature = "async"))]
+mod stream;
+#[cfg(target_os = "linux")]
+mod sysfs;
+#[cfg(target_os = "linux")]
+mod thermal;
+#[cfg(target_os = "linux")]
+mod upower;
+#[cfg(all(target_os = "linux", feature = "io-uring"))]
+mod uring;
+#[cfg(target_os = "linux")]
+mod usb_pd;
+#[cfg(target_os = "linux")]
+pub mod vendor_quirks;
+#[cfg(target_os = "linux")]
+mod wayland;
+#[cfg(target_os = "linux")]
+mod wifi;
+#[cfg(windows)]
+mod windows;
+
+pub use backend::BackendDevice;
+pub use backend::PowerBackend;
+#[cfg(target_os = "linux")]
+pub use backend::SysfsPoller;
+#[cfg(target_os = "linux")]
+pub use backend::UdevBackend;
+pub use backend::Uevents;
+#[cfg(target_os = "linux")]
+pub use backlight::BacklightFader;
+#[cfg(target_os = "linux")]
+pub use backlight::Brightness;
+#[cfg(target_os = "linux")]
+pub use backlight::BrightnessChange;
+#[cfg(target_os = "linux")]
+pub use backlight::KeyboardBacklight;
+#[cfg(target_os = "linux")]
+pub use backlight::KeyboardBrightness;
+#[cfg(target_os = "linux")]
+pub use bluez::Bluez;
+#[cfg(target_os = "linux")]
+pub use charging_speed::ChargingSpeed;
+#[cfg(target_os = "linux")]
+pub use clock::Clock;
+#[cfg(target_os = "linux")]
+pub use clock::MockClock;
+#[cfg(target_os = "linux")]
+pub use clock::SystemClock;
+#[cfg(target_os = "linux")]
+pub use cpufreq::CpuPolicy;
+#[cfg(target_os = "linux")]
+pub use displays::Displays;
+#[cfg(target_os = "linux")]
+pub use dock::Dock;
+#[cfg(target_os = "linux")]
+pub use drain::DrainLimit;
+#[cfg(target_os = "linux")]
+pub use drain::DrainRate;
+#[cfg(target_os = "linux")]
+pub use estimate::Estimator;
+#[cfg(target_os = "linux")]
+pub use estimate::Smoothing;
+#[cfg(target_os = "linux")]
+pub use event::EventFilter;
+#[cfg(target_os = "linux")]
+pub use event::PowerEvent;
+#[cfg(target_os = "linux")]
+pub use event::TimedEvent;
+#[cfg(all(target_os = "linux", feature = "mio"))]
+pub use event_loop::LidEventLoop;
+#[cfg(all(target_os = "linux", feature = "mio"))]
+pub use event_loop::LoopEvent;
+#[cfg(target_os = "linux")]
+pub use expr::Expr;
+#[cfg(target_os = "linux")]
+pub use fan::read_fans;
+#[cfg(target_os = "linux")]
+pub use fan::Fan;
+#[cfg(target_os = "linux")]
+pub use format::Fields;
+#[cfg(target_os = "linux")]
+pub use format::Labels;
+#[cfg(target_os = "linux")]
+pub use format::Template;
+#[cfg(target_os = "linux")]
+pub use health::Health;
+#[cfg(target_os = "linux")]
+pub use health::HealthTrend;
+#[cfg(target_os = "linux")]
+pub use history::History;
+#[cfg(target_os = "linux")]
+pub use history::Segment;
+#[cfg(target_os = "linux")]
+pub use hooks::HookOutcome;
+#[cfg(target_os = "linux")]
+pub use hooks::HookPolicy;
+#[cfg(target_os = "linux")]
+pub use hooks::HookRunner;
+#[cfg(target_os = "linux")]
+pub use hooks::HookUser;
+#[cfg(target_os = "linux")]
+pub use hooks::OnFailure;
+#[cfg(target_os = "linux")]
+pub use idle::IdleMonitor;
+#[cfg(target_os = "linux")]
+pub use idle::IdleTimeout;
+#[cfg(target_os = "linux")]
+pub use level::BatteryLevel;
+#[cfg(target_os = "linux")]
+pub use level::LevelThresholds;
+#[cfg(target_os = "linux")]
+pub use lid::LidSwitch;
+#[cfg(target_os = "linux")]
+pub use lid::PowerButton;
+#[cfg(target_os = "linux")]
+pub use lid::TabletModeSwitch;
+#[cfg(target_os = "linux")]
+pub use logind::InhibitMode;
+#[cfg(target_os = "linux")]
+pub use logind::Logind;
+#[cfg(target_os = "linux")]
+pub use metrics::Latency;
+#[cfg(target_os = "linux")]
+pub use metrics::Metrics;
+#[cfg(target_os = "linux")]
+pub use mock::MockAction;
+#[cfg(target_os = "linux")]
+pub use mock::MockBackend;
+#[cfg(target_os = "linux")]
+pub use mock::MockController;
+#[cfg(target_os = "linux")]
+pub use notifications::Notification;
+#[cfg(target_os = "linux")]
+pub use notifications::NotificationEvent;
+#[cfg(target_os = "linux")]
+pub use notifications::Notifications;
+#[cfg(target_os = "linux")]
+pub use notifications::Urgency;
+#[cfg(target_os = "linux")]
+pub use power_profiles::PowerProfiles;
+#[cfg(target_os = "linux")]
+pub use power_supply::DeviceType;
+#[cfg(target_os = "linux")]
+pub use power_supply::PowerSupply;
+#[cfg(target_os = "linux")]
+pub use power_supply::SavedState;
+#[cfg(target_os = "linux")]
+pub use power_supply::Scope;
+#[cfg(target_os = "linux")]
+pub use power_supply::Status;
+#[cfg(target_os = "linux")]
+pub use pretty::Pretty;
+#[cfg(all(target_os = "linux", feature = "async-io"))]
+pub use reactor::AsyncIoPowerSupply;
+#[cfg(target_os = "linux")]
+pub use resume::ResumeDetector;
+#[cfg(target_os = "linux")]
+pub use runtime_pm::PciePolicy;
+#[cfg(target_os = "linux")]
+pub use runtime_pm::UsbAutosuspend;
+#[cfg(all(target_os = "linux", any(test, feature = "test-util")))]
+pub use scenario::Scenario;
+#[cfg(target_os = "linux")]
+pub use session::Sessions;
+#[cfg(target_os = "linux")]
+pub use snapshot::Change;
+#[cfg(target_os = "linux")]
+pub use snapshot::DeviceSnapshot;
+#[cfg(target_os = "linux")]
+pub use snapshot::Origin;
+#[cfg(target_os = "linux")]
+pub use snapshot::Snapshot;
+#[cfg(target_os = "linux")]
+pub use source::PowerSource;
+#[cfg(target_os = "linux")]
+pub use source::SourceKind;
+#[cfg(all(target_os = "linux", feature = "async"))]
+pub use stream::AsyncPowerSupply;
+#[cfg(target_os = "linux")]
+pub use thermal::read_zones;
+#[cfg(target_os = "linux")]
+pub use thermal::throttle_count;
+#[cfg(target_os = "linux")]
+pub use thermal::Temperature;
+#[cfg(target_os = "linux")]
+pub use thermal::ThermalLimit;
+#[cfg(target_os = "linux")]
+pub use upower::UPowerBackend;
+#[cfg(target_os = "linux")]
+pub use usb_pd::PdContract;
+#[cfg(target_os = "linux")]
+pub use usb_pd::UsbPd;
+#[cfg(target_os = "linux")]
+pub use wifi::WifiPowerSave;
+#[cfg(windows)]
+pub use windows::WindowsBackend;
@@ -0,0 +1,320 @@
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::Read;
+use std::mem;
+use std::os::fd::AsRawFd;
+use std::os::unix::fs::OpenOptionsExt;
+
+#[cfg(feature = "mio")]
+use mio::event::Source;
+#[cfg(feature = "mio")]
+use mio::unix::SourceFd;
+
+const EV_KEY: u16 = 0x01;
+const EV_SW: u16 = 0x05;
+const SW_LID: u16 = 0x00;
+const SW_TABLET_MODE: u16 = 0x01;
+const KEY_POWER: u16 = 116;
+
+/// `_IOC(_IOC_READ, 'E', nr, len)` of `linux/input.h`.
+const fn eviocg(nr: u32, len: usize) -> libc::c_ulong
+{
+    ((2 << 30) | ((len as u32) << 16) | ((b'E' as u32) << 8) | nr) as libc::c_ulong
+}
+
+/// The lid switch, read from the evdev device that reports `SW_LID`, usually
+/// the ACPI lid button.
+///
+/// Register it with a mio `Poll` and call [`LidSwitch::read`] when it becomes
+/// readable.
+pub struct LidSwitch
+{
+    file: File,
+    closed: bool,
+}
+
+impl LidSwitch
+{
+    /// Finds the lid switch among `/dev/input/event*`. Reading them usually
+    /// requires root or membership in the `input` group.
+    pub fn open() -> io::Result<Self>
+    {
+        let file = open_reporting(EV_SW, SW_LID)?.ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                "no input device reports a lid switch",
+            )
+        })?;
+        let closed = switch_state(&file, SW_LID)?;
+        Ok(Self { file, closed })
+    }
+
+    #[must_use]
+    pub fn is_closed(&self) -> bool
+    {
+        self.closed
+    }
+
+    /// Reads the pending input events and returns the new state of the lid
+    /// if it changed.
+    pub fn read(&mut self) -> io::Result<Option<bool>>
+    {
+        let was_closed = self.closed;
+        read_events(&mut self.file, |event| {
+            if event.type_ == EV_SW && event.code == SW_LID {
+                self.closed = event.value != 0;
+            }
+        })?;
+        Ok(Some(self.closed).filter(|closed| *closed != was_closed))
+    }
+}
+
+/// The tablet mode switch of convertibles, read from the evdev device that
+/// reports `SW_TABLET_MODE`, e.g. `intel-hid` or `thinkpad_acpi`. It is on
+/// while the laptop is folded into a tablet.
+///
+/// Register it with a mio `Poll` and call [`TabletModeSwitch::read`] when it
+/// becomes readable.
+pub struct TabletModeSwitch
+{
+    file: File,
+    tablet: bool,
+}
+
+impl TabletModeSwitch
+{
+    /// Finds the tablet mode switch among `/dev/input/event*`, like
+    /// [`LidSwitch::open`].
+    pub fn open() -> io::Result<Self>
+    {
+        let file = open_reporting(EV_SW, SW_TABLET_MODE)?.ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                "no input device reports a tablet mode switch",
+            )
+        })?;
+        let tablet = switch_state(&file, SW_TABLET_MODE)?;
+        Ok(Self { file, tablet })
+    }
+
+    #[must_use]
+    pub fn is_tablet(&self) -> bool
+    {
+        self.tablet
+    }
+
+    /// Reads the pending input events and returns whether the laptop is in
+    /// tablet mode if that changed.
+    pub fn read(&mut self) -> io::Result<Option<bool>>
+    {
+        let was_tablet = self.tablet;
+        read_events(&mut self.file, |event| {
+            if event.type_ == EV_SW && event.code == SW_TABLET_MODE {
+                self.tablet = event.value != 0;
+            }
+        })?;
+        Ok(Some(self.tablet).filter(|tablet| *tablet != was_tablet))
+    }
+}
+
+/// The power button, read from the evdev device that reports `KEY_POWER`,
+/// usually the ACPI power button.
+///
+/// Register it with a mio `Poll` and call [`PowerButton::read`] when it
+/// becomes readable. logind keeps handling the button unless it is
+/// inhibited with `handle-power-key`.
+pub struct PowerButton
+{
+    file: File,
+}
+
+impl PowerButton
+{
+    /// Finds the power button among `/dev/input/event*`, like
+    /// [`LidSwitch::open`].
+    pub fn open() -> io::Result<Self>
+    {
+        let file = open_reporting(EV_KEY, KEY_POWER)?.ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                "no input device reports a power button",
+            )
+        })?;
+        Ok(Self { file })
+    }
+
+    /// Reads the pending input events and returns how often the button was
+    /// pressed. Keys held down and released are not counted.
+    pub fn read(&mut self) -> io::Result<usize>
+    {
+        let mut presses = 0;
+        read_events(&mut self.file, |event| {
+            if event.type_ == EV_KEY && event.code == KEY_POWER && event.value == 1 {
+                presses += 1;
+            }
+        })?;
+        Ok(presses)
+    }
+}
+
+/// Opens the first of `/dev/input/event*` that reports the event `code` of
+/// type `kind`, or returns `None` if none of those that can be opened does.
+fn open_reporting(kind: u16, code: u16) -> io::Result<Option<File>>
+{
+    let mut entries = fs::read_dir("/dev/input")?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<io::Result<Vec<_>>>()?;
+    entries.sort();
+    for path in entries {
+        let is_event = path
+            .file_name()
+            .is_some_and(|name| name.to_string_lossy().starts_with("event"));
+        if !is_event {
+            continue;
+        }
+        let Ok(file) = fs::OpenOptions::new()
+            .read(true)
+            .custom_flags(libc::O_NONBLOCK | libc::O_CLOEXEC)
+            .open(&path)
+        else {
+            continue;
+        };
+        // Large enough for the bits of every key.
+        let mut bits = [0u8; 96];
+        let len = unsafe {
+            libc::ioctl(
+                file.as_raw_fd(),
+                eviocg(0x20 + u32::from(kind), bits.len()),
+                bits.as_mut_ptr(),
+            )
+        };
+        let byte = usize::from(code / 8);
+        if len > byte as libc::c_int && bits[byte] & (1 << (code % 8)) != 0 {
+            return Ok(Some(file));
+        }
+    }
+    Ok(None)
+}
+
+/// Asks the kernel for the state of the switch `code` of `file`.
+fn switch_state(file: &File, code: u16) -> io::Result<bool>
+{
+    let mut state = [0u8; 8];
+    if unsafe {
+        libc::ioctl(
+            file.as_raw_fd(),
+            eviocg(0x1b, state.len()),
+            state.as_mut_ptr(),
+        )
+    } < 0
+    {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(state[usize::from(code / 8)] & (1 << (code % 8)) != 0)
+}
+
+/// Reads the input events pending on `file` and passes each to `handle`.
+fn read_events(file: &mut File, mut handle: impl FnMut(&libc::input_event)) -> io::Result<()>
+{
+    let mut buf = [0u8; mem::size_of::<libc::input_event>() * 16];
+    loop {
+        let len = match file.read(&mut buf) {
+            Ok(len) => len,
+            Err(err) if err.kind() == io::ErrorKind::WouldBlock => return Ok(()),
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(err) => return Err(err),
+        };
+        for chunk in buf[..len].chunks_exact(mem::size_of::<libc::input_event>()) {
+            let event = unsafe { chunk.as_ptr().cast::<libc::input_event>().read_unaligned() };
+            handle(&event);
+        }
+    }
+}
+
+#[cfg(feature = "mio")]
+impl Source for LidSwitch
+{
+    fn register(
+        &mut self,
+        registry: &mio::Registry,
+        token: mio::Token,
+        interests: mio::Interest,
+    ) -> io::Result<()>
+    {
+        SourceFd(&self.file.as_raw_fd()).register(registry, token, interests)
+    }
+
+    fn reregister(
+        &mut self,
+        registry: &mio::Registry,
+        token: mio::Token,
+        interests: mio::Interest,
+    ) -> io::Result<()>
+    {
+        SourceFd(&self.file.as_raw_fd()).reregister(registry, token, interests)
+    }
+
+    fn deregister(&mut self, registry: &mio::Registry) -> io::Result<()>
+    {
+        SourceFd(&self.file.as_raw_fd()).deregister(registry)
+    }
+}
+
+#[cfg(feature = "mio")]
+impl Source for PowerButton
+{
+    fn register(
+        &mut self,
+        registry: &mio::Registry,
+        token: mio::Token,
+        interests: mio::Interest,
+    ) -> io::Result<()>
+    {
+        SourceFd(&self.file.as_raw_fd()).register(registry, token, interests)
+    }
+
+    fn reregister(
+        &mut self,
+        registry: &mio::Registry,
+        token: mio::Token,
+        interests: mio::Interest,
+    ) -> io::Result<()>
+    {
+        SourceFd(&self.file.as_raw_fd()).reregister(registry, token, interests)
+    }
+
+    fn deregister(&mut self, registry: &mio::Registry) -> io::Result<()>
+    {
+        SourceFd(&self.file.as_raw_fd()).deregister(registry)
+    }
+}
+
+#[cfg(feature = "mio")]
+impl Source for TabletModeSwitch
+{
+    fn register(
+        &mut self,
+        registry: &mio::Registry,
+        token: mio::Token,
+        interests: mio::Interest,
+    ) -> io::Result<()>
+    {
+        SourceFd(&self.file.as_raw_fd()).register(registry, token, interests)
+    }
+
+    fn reregister(
+        &mut self,
+        registry: &mio::Registry,
+        token: mio::Token,
+        interests: mio::Interest,
+    ) -> io::Result<()>
+    {
+        SourceFd(&self.file.as_raw_fd()).reregister(registry, token, interests)
+    }
+
+    fn deregister(&mut self, registry: &mio::Registry) -> io::Result<()>
+    {
+        SourceFd(&self.file.as_raw_fd()).deregister(registry)
+    }
+}
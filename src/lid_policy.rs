@@ -0,0 +1,406 @@
+use std::fs;
+use std::io;
+use std::os::fd::OwnedFd;
+use std::time::Duration;
+use std::time::Instant;
+
+use lithiumd::rules::Condition;
+use lithiumd::HookRunner;
+use lithiumd::InhibitMode;
+use lithiumd::LidSwitch;
+use lithiumd::Logind;
+use lithiumd::Snapshot;
+use mio::event::Source;
+
+/// How often the lock is checked while waiting for it to engage.
+const CHECK_INTERVAL: Duration = Duration::from_millis(100);
+
+/// How to tell that the screen locker engaged.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LockCheck
+{
+    /// The `LockedHint` of the active logind session is set.
+    LockedHint,
+    /// A process with the given name is running.
+    Process(String),
+}
+
+/// What to do if the locker did not engage after all retries.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LockFailure
+{
+    /// Stay awake, so that the machine is never suspended unlocked.
+    Abort,
+    /// Suspend anyway.
+    Suspend,
+}
+
+/// Locking the screen when the lid closes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScreenLock
+{
+    pub command: String,
+    pub check: LockCheck,
+    /// How long to wait for the locker to engage, per attempt.
+    pub timeout: Duration,
+    /// How often the command is run again if the locker did not engage.
+    pub retries: u32,
+    pub on_failure: LockFailure,
+}
+
+/// What happens when the lid closes, from the `[lid]` table.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LidPolicy
+{
+    pub lock: Option<ScreenLock>,
+    /// Whether to suspend at all.
+    pub suspend: bool,
+    /// Conditions that have to hold for the system to suspend, both when
+    /// the lid closes and when the delay is over.
+    pub conditions: Vec<Condition>,
+    /// Grace period between closing the lid and suspending, during which
+    /// opening the lid again cancels the suspend.
+    pub delay: Duration,
+    /// Whether to suspend while an external display is connected. Otherwise
+    /// the laptop keeps running with the lid closed, in clamshell mode.
+    pub suspend_when_docked: bool,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum LockState
+{
+    /// The lock command ran and has not been verified yet.
+    Pending
+    {
+        attempt: u32, deadline: Instant
+    },
+    /// The screen is locked, or is not locked on lid close.
+    Done,
+}
+
+/// The lid is closed and the system is about to suspend, or at least lock.
+#[derive(Debug, Clone, Copy)]
+struct Closed
+{
+    lock: LockState,
+    /// When the system suspends, unless it stays awake.
+    suspend_at: Option<Instant>,
+}
+
+/// Takes over the lid switch from logind: on lid close the screen is locked,
+/// and the system is suspended after the grace period, but only once the
+/// locker is verified to have engaged.
+pub struct LidHandler
+{
+    switch: LidSwitch,
+    logind: Logind,
+    /// Keeps logind from suspending on its own when the lid closes.
+    _inhibitor: OwnedFd,
+    /// Delays sleep until the lock is verified, should something else
+    /// suspend the system meanwhile.
+    delay: Option<OwnedFd>,
+    policy: LidPolicy,
+    closed: Option<Closed>,
+    /// Whether an external display is connected, see
+    /// [`LidHandler::set_docked`].
+    docked: bool,
+    /// Whether a convertible is folded into a tablet, see
+    /// [`LidHandler::set_tablet_mode`].
+    tablet: bool,
+}
+
+impl LidHandler
+{
+    pub fn new(policy: LidPolicy) -> io::Result<Self>
+    {
+        let switch = LidSwitch::open()?;
+        let mut logind = Logind::new()?;
+        let inhibitor = logind.inhibit(
+            "handle-lid-switch",
+            "Handles the lid switch itself",
+            InhibitMode::Block,
+        )?;
+        Ok(Self {
+            switch,
+            logind,
+            _inhibitor: inhibitor,
+            delay: None,
+            policy,
+            closed: None,
+            docked: false,
+            tablet: false,
+        })
+    }
+
+    #[must_use]
+    pub fn policy(&self) -> &LidPolicy
+    {
+        &self.policy
+    }
+
+    /// Returns how long the poll may sleep before the lock has to be checked
+    /// again or the system has to suspend.
+    #[must_use]
+    pub fn timeout(&self) -> Option<Duration>
+    {
+        match self.closed? {
+            Closed {
+                lock: LockState::Pending { .. },
+                ..
+            } => Some(CHECK_INTERVAL),
+            Closed {
+                suspend_at: Some(at),
+                ..
+            } => Some(at.saturating_duration_since(Instant::now())),
+            _ => None,
+        }
+    }
+
+    /// Updates whether an external display is connected. Undocking with the
+    /// lid closed starts the grace period, as if the lid was just closed.
+    pub fn set_docked(&mut self, docked: bool, snapshot: &Snapshot)
+    {
+        if self.docked == docked {
+            return;
+        }
+        self.docked = docked;
+        let should_suspend = self.should_suspend(snapshot);
+        let Some(closed) = &mut self.closed else {
+            return;
+        };
+        if !docked && closed.suspend_at.is_none() && should_suspend {
+            println!(
+                "Undocked with the lid closed, suspending in {}s",
+                self.policy.delay.as_secs()
+            );
+            closed.suspend_at = Some(Instant::now() + self.policy.delay);
+        }
+    }
+
+    /// Updates whether a convertible is folded into a tablet. Some report the
+    /// lid as closed then, so the system does not suspend in tablet mode, and
+    /// folding it cancels a pending suspend.
+    pub fn set_tablet_mode(&mut self, tablet: bool)
+    {
+        self.tablet = tablet;
+        let Some(closed) = &mut self.closed else {
+            return;
+        };
+        if tablet && closed.suspend_at.take().is_some() {
+            println!("Folded into a tablet, not suspending");
+        }
+    }
+
+    /// Reads the lid switch. Closing the lid runs the lock command and starts
+    /// the grace period, opening it cancels the suspend. Returns whether the
+    /// lid was closed or opened, `None` if it did neither.
+    pub fn read(&mut self, hooks: &mut HookRunner, snapshot: &Snapshot)
+        -> io::Result<Option<bool>>
+    {
+        let closed = self.switch.read()?;
+        match closed {
+            Some(true) => {
+                let suspend_at = Some(Instant::now() + self.policy.delay)
+                    .filter(|_| self.should_suspend(snapshot));
+                self.closed = Some(Closed {
+                    lock: LockState::Done,
+                    suspend_at,
+                });
+                match suspend_at {
+                    Some(_) if !self.policy.delay.is_zero() => {
+                        println!("Lid closed, suspending in {}s", self.policy.delay.as_secs())
+                    }
+                    Some(_) => println!("Lid closed, suspending"),
+                    None if self.docked && self.policy.suspend => {
+                        println!("Lid closed while docked, not suspending")
+                    }
+                    None => println!("Lid closed"),
+                }
+                self.run_lock(hooks, snapshot, 0);
+            }
+            Some(false) => {
+                let suspending = self
+                    .closed
+                    .take()
+                    .is_some_and(|closed| closed.suspend_at.is_some());
+                if suspending {
+                    println!("Lid opened, not suspending");
+                }
+                self.delay = None;
+            }
+            None => {}
+        }
+        Ok(closed)
+    }
+
+    /// Checks whether the locker engaged and suspends once it did and the
+    /// grace period is over. Lock attempts that time out are retried, and
+    /// once the retries are used up the configured [`LockFailure`] applies.
+    pub fn check(&mut self, hooks: &mut HookRunner, snapshot: &Snapshot)
+    {
+        let Some(closed) = self.closed else {
+            return;
+        };
+        if let LockState::Pending { attempt, deadline } = closed.lock {
+            if !self.check_lock(hooks, snapshot, attempt, deadline) {
+                return;
+            }
+        }
+        let Some(closed) = self.closed else {
+            return;
+        };
+        if closed.suspend_at.is_some_and(|at| Instant::now() >= at) {
+            self.closed = Some(Closed {
+                suspend_at: None,
+                ..closed
+            });
+            // Plugging in during the grace period cancels the suspend.
+            if self.should_suspend(snapshot) {
+                self.suspend();
+            } else {
+                println!("Not suspending, the conditions no longer hold");
+            }
+        }
+    }
+
+    /// Verifies a pending lock. Returns whether locking is done, successfully
+    /// or not.
+    fn check_lock(
+        &mut self,
+        hooks: &mut HookRunner,
+        snapshot: &Snapshot,
+        attempt: u32,
+        deadline: Instant,
+    ) -> bool
+    {
+        let Some(lock) = &self.policy.lock else {
+            return true;
+        };
+        let locked = match is_locked(&mut self.logind, &lock.check) {
+            Ok(locked) => locked,
+            Err(err) => {
+                eprintln!("Failed to check the screen lock: {}", err);
+                false
+            }
+        };
+        if !locked && Instant::now() < deadline {
+            return false;
+        }
+        if !locked && attempt < lock.retries {
+            eprintln!("The screen did not lock, retrying");
+            self.run_lock(hooks, snapshot, attempt + 1);
+            return false;
+        }
+        let on_failure = lock.on_failure;
+        self.delay = None;
+        if let Some(closed) = &mut self.closed {
+            closed.lock = LockState::Done;
+            if !locked && closed.suspend_at.is_some() {
+                match on_failure {
+                    LockFailure::Abort => {
+                        eprintln!("The screen did not lock, not suspending");
+                        closed.suspend_at = None;
+                    }
+                    LockFailure::Suspend => {
+                        eprintln!("The screen did not lock, suspending anyway")
+                    }
+                }
+            }
+        }
+        true
+    }
+
+    fn should_suspend(&self, snapshot: &Snapshot) -> bool
+    {
+        self.policy.suspend
+            && !self.tablet
+            && (self.policy.suspend_when_docked || !self.docked)
+            && self
+                .policy
+                .conditions
+                .iter()
+                .all(|condition| condition.matches(snapshot))
+    }
+
+    fn run_lock(&mut self, hooks: &mut HookRunner, snapshot: &Snapshot, attempt: u32)
+    {
+        let (Some(lock), Some(closed)) = (&self.policy.lock, &mut self.closed) else {
+            return;
+        };
+        if self.delay.is_none() {
+            let delay =
+                self.logind
+                    .inhibit("sleep:shutdown", "Locking the screen", InhibitMode::Delay);
+            match delay {
+                Ok(delay) => self.delay = Some(delay),
+                Err(err) => eprintln!("Failed to delay sleep while locking: {}", err),
+            }
+        }
+        if let Err(err) = hooks.run(&lock.command, snapshot) {
+            eprintln!("Failed to run `{}`: {}", lock.command, err);
+        }
+        closed.lock = LockState::Pending {
+            attempt,
+            deadline: Instant::now() + lock.timeout,
+        };
+    }
+
+    fn suspend(&mut self)
+    {
+        if let Err(err) = self.logind.suspend() {
+            eprintln!("Failed to suspend: {}", err);
+        }
+    }
+}
+
+fn is_locked(logind: &mut Logind, check: &LockCheck) -> io::Result<bool>
+{
+    match check {
+        LockCheck::LockedHint => logind.active_session_locked(),
+        LockCheck::Process(name) => is_running(name),
+    }
+}
+
+/// Returns whether a process named `name` is running. The kernel truncates
+/// process names to 15 bytes.
+fn is_running(name: &str) -> io::Result<bool>
+{
+    let name = &name.as_bytes()[..name.len().min(15)];
+    for entry in fs::read_dir("/proc")? {
+        let Ok(comm) = fs::read(entry?.path().join("comm")) else {
+            continue;
+        };
+        if comm.strip_suffix(b"\n").unwrap_or(&comm) == name {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+impl Source for LidHandler
+{
+    fn register(
+        &mut self,
+        registry: &mio::Registry,
+        token: mio::Token,
+        interests: mio::Interest,
+    ) -> io::Result<()>
+    {
+        self.switch.register(registry, token, interests)
+    }
+
+    fn reregister(
+        &mut self,
+        registry: &mio::Registry,
+        token: mio::Token,
+        interests: mio::Interest,
+    ) -> io::Result<()>
+    {
+        self.switch.reregister(registry, token, interests)
+    }
+
+    fn deregister(&mut self, registry: &mio::Registry) -> io::Result<()>
+    {
+        self.switch.deregister(registry)
+    }
+}
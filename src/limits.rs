@@ -0,0 +1,216 @@
+use std::io;
+use std::time::Duration;
+use std::time::Instant;
+
+use lithiumd::vendor_quirks::ChargeBehaviour;
+use lithiumd::vendor_quirks::ChargeBehaviourControl;
+use lithiumd::vendor_quirks::ChargeLimit;
+use lithiumd::vendor_quirks::Quirk;
+use lithiumd::Snapshot;
+use lithiumd::Status;
+
+use crate::benchmark::parse_duration;
+
+/// Parses the arguments of `lithiumd limits`: `off`, `conservation`, or the
+/// percentage to stop charging at followed by the one to resume at.
+pub fn parse(args: &[String]) -> Result<Option<ChargeLimit>, String>
+{
+    let percent = |arg: &String| {
+        arg.trim_end_matches('%')
+            .parse::<u8>()
+            .ok()
+            .filter(|percent| *percent <= 100)
+            .ok_or_else(|| format!("`{}` is not a percentage", arg))
+    };
+    match args {
+        [] => Ok(None),
+        [arg] if arg == "off" => Ok(Some(ChargeLimit::Off)),
+        [arg] if arg == "conservation" => Ok(Some(ChargeLimit::Conservation)),
+        [end] => Ok(Some(ChargeLimit::Threshold {
+            start: None,
+            end: percent(end)?,
+        })),
+        [end, start] => {
+            let (end, start) = (percent(end)?, percent(start)?);
+            if start >= end {
+                return Err("the start threshold must be below the end threshold".to_string());
+            }
+            Ok(Some(ChargeLimit::Threshold {
+                start: Some(start),
+                end,
+            }))
+        }
+        _ => Err("too many arguments".to_string()),
+    }
+}
+
+/// Prints the charge limit of every interface, or applies `limit` to all of
+/// them.
+pub fn run(limit: Option<ChargeLimit>) -> io::Result<()>
+{
+    let quirks = Quirk::detect()?;
+    if quirks.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "this machine has no known way of limiting the charge",
+        ));
+    }
+    for quirk in quirks {
+        if let Some(limit) = limit {
+            quirk.set(limit)?;
+        }
+        // The thresholds only apply while the battery charges automatically.
+        let behaviour = match ChargeBehaviourControl::of(&quirk) {
+            Some(control) => Some(control.get()?.0).filter(|b| *b != ChargeBehaviour::Auto),
+            None => None,
+        };
+        match behaviour {
+            Some(behaviour) => println!(
+                "{}: {} (ignored while the charge behaviour is {})",
+                quirk.name(),
+                quirk.get()?,
+                behaviour
+            ),
+            None => println!("{}: {}", quirk.name(), quirk.get()?),
+        }
+    }
+    Ok(())
+}
+
+/// A charge limit that is lifted until the battery reached a capacity or a
+/// deadline passed, e.g. to top up before travelling. The limits are
+/// restored with [`TopUp::restore`].
+pub struct TopUp
+{
+    until: u8,
+    deadline: Instant,
+    saved: Vec<(Quirk, ChargeLimit)>,
+}
+
+impl TopUp
+{
+    /// Parses the arguments of the `top-up` command, `[--until PERCENT]
+    /// [--for DURATION]`, which default to 100% and 12 hours.
+    pub fn parse(args: &[&str]) -> Result<(u8, Duration), String>
+    {
+        let mut until = 100;
+        let mut duration = Duration::from_secs(12 * 60 * 60);
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            let value = args
+                .next()
+                .ok_or_else(|| format!("`{}` needs a value", arg))?;
+            match *arg {
+                "--until" => {
+                    until = value
+                        .trim_end_matches('%')
+                        .parse()
+                        .ok()
+                        .filter(|percent| (1..=100).contains(percent))
+                        .ok_or_else(|| format!("`{}` is not a percentage", value))?;
+                }
+                "--for" => duration = parse_duration(value)?,
+                _ => return Err(format!("unknown option `{}`", arg)),
+            }
+        }
+        Ok((until, duration))
+    }
+
+    /// Saves the current charge limits and raises them to `until` percent
+    /// for at most `duration`.
+    pub fn start(until: u8, duration: Duration) -> io::Result<Self>
+    {
+        let quirks = Quirk::detect()?;
+        if quirks.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "this machine has no known way of limiting the charge",
+            ));
+        }
+        let mut top_up = Self {
+            until,
+            deadline: Instant::now() + duration,
+            saved: Vec::new(),
+        };
+        for quirk in quirks {
+            let limit = quirk.get()?;
+            let raised = match until {
+                100 => Err(io::ErrorKind::Unsupported.into()),
+                end => quirk.set(ChargeLimit::Threshold { start: None, end }),
+            };
+            // Interfaces without thresholds can only be turned off.
+            match raised {
+                Err(err) if err.kind() == io::ErrorKind::Unsupported => {
+                    quirk.set(ChargeLimit::Off)?
+                }
+                result => result?,
+            }
+            top_up.saved.push((quirk, limit));
+        }
+        Ok(top_up)
+    }
+
+    #[must_use]
+    pub fn until(&self) -> u8
+    {
+        self.until
+    }
+
+    /// Returns how long until the deadline passes.
+    #[must_use]
+    pub fn remaining(&self) -> Duration
+    {
+        self.deadline.saturating_duration_since(Instant::now())
+    }
+
+    /// Returns whether the battery reached the requested capacity or is
+    /// full, or the deadline passed.
+    #[must_use]
+    pub fn is_done(&self, snapshot: &Snapshot) -> bool
+    {
+        self.remaining().is_zero()
+            || snapshot.status == Status::Full
+            || snapshot
+                .capacity()
+                .is_some_and(|capacity| capacity >= self.until)
+    }
+
+    /// Puts the saved charge limits back.
+    pub fn restore(self) -> io::Result<()>
+    {
+        for (quirk, limit) in self.saved {
+            quirk.set(limit)?;
+        }
+        Ok(())
+    }
+}
+
+/// Prints the charge behaviour of every battery that supports it, or sets
+/// all of them to `behaviour`.
+pub fn run_behaviour(behaviour: Option<ChargeBehaviour>) -> io::Result<()>
+{
+    let controls = ChargeBehaviourControl::detect()?;
+    if controls.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "no battery supports charge_behaviour",
+        ));
+    }
+    for control in controls {
+        if let Some(behaviour) = behaviour {
+            control.set(behaviour)?;
+        }
+        let (active, supported) = control.get()?;
+        let supported = supported
+            .iter()
+            .map(ChargeBehaviour::to_string)
+            .collect::<Vec<_>>();
+        println!(
+            "{}: {} (supports {})",
+            control.name(),
+            active,
+            supported.join(", ")
+        );
+    }
+    Ok(())
+}
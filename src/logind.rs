@@ -0,0 +1,152 @@
+use std::io;
+use std::os::fd::OwnedFd;
+use std::time::Duration;
+
+use crate::dbus::Connection;
+use crate::dbus::Message;
+use crate::dbus::Value;
+
+const LOGIND: &str = "org.freedesktop.login1";
+const MANAGER_PATH: &str = "/org/freedesktop/login1";
+const MANAGER: &str = "org.freedesktop.login1.Manager";
+
+/// Client of systemd-logind's manager interface.
+pub struct Logind
+{
+    conn: Connection,
+}
+
+/// The kind of an inhibitor lock.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InhibitMode
+{
+    /// Prevents the operation for as long as the lock is held.
+    Block,
+    /// Delays the operation until the lock is released, but at most for
+    /// logind's `InhibitDelayMaxSec`.
+    Delay,
+}
+
+impl Logind
+{
+    /// Connects to the system bus.
+    pub fn new() -> io::Result<Self>
+    {
+        Ok(Self {
+            conn: Connection::system()?,
+        })
+    }
+
+    /// Takes an inhibitor lock on the colon-separated operations in `what`,
+    /// e.g. `sleep` or `handle-lid-switch`. The lock is released when the
+    /// returned file descriptor is closed.
+    pub fn inhibit(&mut self, what: &str, why: &str, mode: InhibitMode) -> io::Result<OwnedFd>
+    {
+        let mode = match mode {
+            InhibitMode::Block => "block",
+            InhibitMode::Delay => "delay",
+        };
+        let mut reply = self.conn.call(Message::method_call(
+            LOGIND,
+            MANAGER_PATH,
+            MANAGER,
+            "Inhibit",
+            vec![
+                Value::String(what.to_string()),
+                Value::String("lithiumd".to_string()),
+                Value::String(why.to_string()),
+                Value::String(mode.to_string()),
+            ],
+        ))?;
+        reply.fds.pop().ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::Unsupported,
+                "logind did not pass an inhibitor file descriptor",
+            )
+        })
+    }
+
+    /// Suspends the system.
+    pub fn suspend(&mut self) -> io::Result<()>
+    {
+        self.conn.call(Message::method_call(
+            LOGIND,
+            MANAGER_PATH,
+            MANAGER,
+            "Suspend",
+            vec![Value::Bool(false)],
+        ))?;
+        Ok(())
+    }
+
+    /// Hibernates the system.
+    pub fn hibernate(&mut self) -> io::Result<()>
+    {
+        self.conn.call(Message::method_call(
+            LOGIND,
+            MANAGER_PATH,
+            MANAGER,
+            "Hibernate",
+            vec![Value::Bool(false)],
+        ))?;
+        Ok(())
+    }
+
+    /// Returns how long the seats have been idle, from the `IdleHint` that
+    /// logind combines from the sessions, or `None` if some session is
+    /// active.
+    pub fn idle_time(&mut self) -> io::Result<Option<Duration>>
+    {
+        let idle = self
+            .conn
+            .get_property(LOGIND, MANAGER_PATH, MANAGER, "IdleHint")?;
+        if idle.as_bool() != Some(true) {
+            return Ok(None);
+        }
+        let since =
+            self.conn
+                .get_property(LOGIND, MANAGER_PATH, MANAGER, "IdleSinceHintMonotonic")?;
+        let Value::UInt64(since) = since else {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "IdleSinceHintMonotonic is not a u64",
+            ));
+        };
+        let mut now = libc::timespec {
+            tv_sec: 0,
+            tv_nsec: 0,
+        };
+        unsafe { libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut now) };
+        let now = now.tv_sec as u64 * 1_000_000 + now.tv_nsec as u64 / 1000;
+        Ok(Some(Duration::from_micros(now.saturating_sub(since))))
+    }
+
+    /// Returns the `LockedHint` of the active session on `seat0`, which
+    /// lockers set while the screen is locked.
+    pub fn active_session_locked(&mut self) -> io::Result<bool>
+    {
+        let session = self.conn.get_property(
+            LOGIND,
+            "/org/freedesktop/login1/seat/seat0",
+            "org.freedesktop.login1.Seat",
+            "ActiveSession",
+        )?;
+        // A struct of the session ID and its object path.
+        let Value::Struct(fields) = session else {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "ActiveSession is not a struct",
+            ));
+        };
+        let Some(Value::ObjectPath(path)) = fields.get(1) else {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                "seat0 has no active session",
+            ));
+        };
+        let locked =
+            self.conn
+                .get_property(LOGIND, path, "org.freedesktop.login1.Session", "LockedHint")?;
+        Ok(locked.as_bool().unwrap_or(false))
+    }
+}
@@ -0,0 +1,251 @@
+use std::io;
+use std::time::Duration;
+use std::time::Instant;
+
+use lithiumd::rules::Action;
+use lithiumd::BatteryLevel;
+use lithiumd::Fields;
+use lithiumd::Notification;
+use lithiumd::NotificationEvent;
+use lithiumd::Notifications;
+use lithiumd::Status;
+use lithiumd::Urgency;
+
+/// How the low battery notifications behave, from the `[notifications]`
+/// table.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NotifyOptions
+{
+    /// How often the notification is shown again while discharging, or
+    /// `None` to show it once per level.
+    pub repeat: Option<Duration>,
+    /// How long the snooze button silences it.
+    pub snooze: Duration,
+    pub buttons: Vec<Button>,
+}
+
+/// A button of the notification.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Button
+{
+    /// Puts the notification off for the snooze time.
+    Snooze,
+    /// Suspends the system right away.
+    Suspend,
+    /// Switches power-profiles-daemon to `power-saver`.
+    PowerSaver,
+    /// Stops the low battery notifications until the daemon exits. Critical
+    /// ones are still shown.
+    Dismiss,
+}
+
+impl Button
+{
+    fn key(self) -> &'static str
+    {
+        match self {
+            Button::Snooze => "snooze",
+            Button::Suspend => "suspend",
+            Button::PowerSaver => "power-saver",
+            Button::Dismiss => "dismiss",
+        }
+    }
+}
+
+/// Shows a desktop notification while the battery is low or critical and
+/// discharging, like the power managers of desktops do. It is shown again
+/// every `repeat` and right away when the battery turns critical, unless
+/// snoozed, and closed once the charger is plugged in. While it is open, the
+/// capacity it shows is updated in place.
+pub struct LowBatteryNotifier
+{
+    options: NotifyOptions,
+    notifications: Notifications,
+    shown: Option<Shown>,
+    /// When the notification is shown again.
+    due: Option<Instant>,
+    /// Whether the low battery notifications were dismissed.
+    dismissed: bool,
+}
+
+/// The notification that was shown last.
+#[derive(Debug, Clone, Copy)]
+struct Shown
+{
+    id: u32,
+    /// The level it warns about.
+    level: BatteryLevel,
+    capacity: Option<u8>,
+    /// Whether it is still open, and not dismissed or snoozed.
+    open: bool,
+}
+
+impl LowBatteryNotifier
+{
+    pub fn new(options: NotifyOptions) -> io::Result<Self>
+    {
+        Ok(Self {
+            options,
+            notifications: Notifications::new()?,
+            shown: None,
+            due: None,
+            dismissed: false,
+        })
+    }
+
+    pub fn options(&self) -> &NotifyOptions
+    {
+        &self.options
+    }
+
+    pub fn notifications(&mut self) -> &mut Notifications
+    {
+        &mut self.notifications
+    }
+
+    /// Shows, repeats or closes the notification for the state in `fields`.
+    pub fn update(&mut self, fields: &Fields, now: Instant) -> io::Result<()>
+    {
+        let level = match fields.level {
+            Some(level @ (BatteryLevel::Critical | BatteryLevel::Low))
+                if fields.status == Status::Discharging =>
+            {
+                level
+            }
+            _ => {
+                self.due = None;
+                if let Some(shown) = self.shown.take().filter(|shown| shown.open) {
+                    self.notifications.close(shown.id)?;
+                }
+                return Ok(());
+            }
+        };
+        if self.dismissed && level == BatteryLevel::Low {
+            return Ok(());
+        }
+        let worse = self.shown.is_none_or(|shown| level < shown.level);
+        let due = self.due.is_some_and(|due| now >= due);
+        let changed = self
+            .shown
+            .is_some_and(|shown| shown.open && shown.capacity != fields.capacity);
+        if !worse && !due && !changed {
+            return Ok(());
+        }
+        let replaces = self
+            .shown
+            .filter(|shown| shown.open)
+            .map_or(0, |shown| shown.id);
+        let id = self
+            .notifications
+            .notify(replaces, &self.notification(fields, level))?;
+        self.shown = Some(Shown {
+            id,
+            level,
+            capacity: fields.capacity,
+            open: true,
+        });
+        if worse || due {
+            self.due = self.options.repeat.map(|repeat| now + repeat);
+        }
+        Ok(())
+    }
+
+    /// Reads the buttons clicked since the last call and returns the actions
+    /// the daemon has to take for them. Snoozing closes the notification and
+    /// puts off showing it again.
+    pub fn process(&mut self, now: Instant) -> io::Result<Vec<Action>>
+    {
+        let mut actions = Vec::new();
+        for event in self.notifications.process()? {
+            let Some(shown) = &mut self.shown else {
+                continue;
+            };
+            match event {
+                NotificationEvent::Action { id, key } if id == shown.id => {
+                    let button = self
+                        .options
+                        .buttons
+                        .iter()
+                        .find(|button| button.key() == key);
+                    match button {
+                        Some(Button::Snooze) => self.due = Some(now + self.options.snooze),
+                        Some(Button::Suspend) => actions.push(Action::Suspend),
+                        Some(Button::PowerSaver) => {
+                            actions.push(Action::SetPowerProfile("power-saver".to_string()))
+                        }
+                        Some(Button::Dismiss) => self.dismissed = true,
+                        None => continue,
+                    }
+                    if shown.open {
+                        shown.open = false;
+                        self.notifications.close(id)?;
+                    }
+                }
+                NotificationEvent::Closed { id } if id == shown.id => shown.open = false,
+                _ => {}
+            }
+        }
+        Ok(actions)
+    }
+
+    /// Returns how long until the notification is shown again.
+    pub fn timeout(&self, now: Instant) -> Option<Duration>
+    {
+        self.due.map(|due| due.saturating_duration_since(now))
+    }
+
+    fn notification(&self, fields: &Fields, level: BatteryLevel) -> Notification
+    {
+        let mut body = match fields.capacity {
+            Some(capacity) => format!("{}% remaining", capacity),
+            None => "Plug in the charger".to_string(),
+        };
+        if let Some(remaining) = fields.time_remaining {
+            let minutes = remaining.as_secs() / 60;
+            body += &format!(" ({}h {}m)", minutes / 60, minutes % 60);
+        }
+        let (summary, urgency) = match level {
+            BatteryLevel::Critical => ("Battery critical", Urgency::Critical),
+            _ => ("Battery low", Urgency::Normal),
+        };
+        let icon = match fields.capacity {
+            Some(capacity) => icon_name(capacity, fields.status),
+            None if level == BatteryLevel::Critical => "battery-caution-symbolic".to_string(),
+            None => "battery-low-symbolic".to_string(),
+        };
+        Notification {
+            summary: summary.to_string(),
+            body,
+            icon,
+            urgency,
+            progress: fields.capacity,
+            actions: self
+                .options
+                .buttons
+                .iter()
+                .map(|button| {
+                    let label = match button {
+                        Button::Snooze => {
+                            format!("Snooze {} min", self.options.snooze.as_secs() / 60)
+                        }
+                        Button::Suspend => "Suspend now".to_string(),
+                        Button::PowerSaver => "Enable power saver".to_string(),
+                        Button::Dismiss => "Dismiss for this session".to_string(),
+                    };
+                    (button.key().to_string(), label)
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Returns the themed battery icon for `capacity`, from the
+/// `battery-level-N-symbolic` icons of Adwaita that come in steps of 10%.
+fn icon_name(capacity: u8, status: Status) -> String
+{
+    let step = (capacity.min(100) + 5) / 10 * 10;
+    match status {
+        Status::Charging => format!("battery-level-{}-charging-symbolic", step),
+        _ => format!("battery-level-{}-symbolic", step),
+    }
+}
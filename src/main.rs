@@ -1,38 +1,337 @@
-mod power_supply;
+mod benchmark;
+mod broadcast;
+mod calibrate;
+mod charged;
+mod config;
+mod config_command;
+mod cooldown;
+mod countdown;
+mod daemon;
+mod daemonize;
+mod dbus_service;
+mod doctor;
+mod env_file;
+mod hub;
+mod idle_policy;
+mod instance;
+mod ipc;
+mod json;
+mod lid_policy;
+mod limits;
+mod low_battery;
+mod mqtt;
+mod polybar;
+mod power_button;
+mod remote;
+mod replay;
+mod sandbox;
+mod signals;
+mod sound;
+mod speech;
+mod state_file;
+mod stats;
+mod sway;
+mod toml;
+#[cfg(feature = "tray")]
+mod tray;
+mod watch;
+mod xroot;
 
+use std::env;
 use std::io;
+use std::path::Path;
+use std::path::PathBuf;
+use std::process::ExitCode;
 
-use mio::event::Source;
-use mio::Events;
-use mio::Interest;
-use mio::Poll;
-use mio::Token;
-pub use power_supply::DeviceType;
-pub use power_supply::PowerSupply;
+use config::Config;
+use daemon::Daemon;
+use instance::InstanceLock;
+use lithiumd::privileges;
+use lithiumd::History;
+use lithiumd::PowerSupply;
+use signals::Signals;
 
-fn main() -> io::Result<()>
-{
-    let mut power_supply = PowerSupply::new();
-
-    let mut poll = Poll::new()?;
-    let mut events = Events::with_capacity(1024);
+const USAGE: &str = "usage: lithiumd [--battery GLOB] [--adapter GLOB] [--format TEMPLATE]
+                [--output events|pretty|xroot|polybar] [--record FILE] [--user NAME]
+                [--sandbox] [--replace] [--daemonize] [--log FILE]
+       lithiumd daemon [OPTIONS]
+       lithiumd replay FILE
+       lithiumd watch --exec COMMAND
+       lithiumd status [--json | --verbose] | devices [--json]
+       lithiumd health [--trend] [--json]
+       lithiumd status --check on-ac | on-battery | above PERCENT | below PERCENT
+       lithiumd status --oneshot [--json]
+       lithiumd stats [--days N] [--json]
+       lithiumd inhibit on|off
+       lithiumd top-up [--until PERCENT] [--for DURATION] | top-up cancel
+       lithiumd profile apply NAME
+       lithiumd limits [off | conservation | END [START]]
+       lithiumd charge-behaviour [auto | inhibit-charge | force-discharge]
+       lithiumd hub [--json] [--watch INTERVAL] HOST...
+       lithiumd agent --connect HOST:PORT [--name NAME]
+       lithiumd remote listen [ADDRESS] | remote list [--json] [--connect HOST:PORT]
+       lithiumd benchmark [--duration 30m] [--brightness PERCENT] [--governor NAME] [--output FILE]
+       lithiumd calibrate [--empty PERCENT]
+       lithiumd config init [PATH] | config check [PATH]
+       lithiumd doctor";
 
-    power_supply.register(
-        poll.registry(),
-        Token(0),
-        Interest::READABLE | Interest::WRITABLE,
-    )?;
+fn main() -> io::Result<ExitCode>
+{
+    let args = env::args().skip(1).collect::<Vec<_>>();
+    match args.first().map(String::as_str) {
+        Some("-h" | "--help") => {
+            println!("{}", USAGE);
+            Ok(ExitCode::SUCCESS)
+        }
+        None => run_daemon(args),
+        Some(arg) if arg.starts_with('-') => run_daemon(args),
+        Some("daemon") => run_daemon(args[1..].to_vec()),
+        Some("benchmark") => match benchmark::Options::parse(&args[1..]) {
+            Ok(options) => {
+                benchmark::run(&options)?;
+                Ok(ExitCode::SUCCESS)
+            }
+            Err(err) => {
+                eprintln!("lithiumd: {}\n{}", err, USAGE);
+                Ok(ExitCode::FAILURE)
+            }
+        },
+        Some("calibrate") => match calibrate::Options::parse(&args[1..]) {
+            Ok(options) => {
+                let history = Config::load()?.history.map(History::load).transpose()?;
+                calibrate::run(options, history)?;
+                Ok(ExitCode::SUCCESS)
+            }
+            Err(err) => {
+                eprintln!("lithiumd: {}\n{}", err, USAGE);
+                Ok(ExitCode::FAILURE)
+            }
+        },
+        Some("config") => match &args[1..] {
+            [command, path @ ..] if path.len() <= 1 => {
+                let path = path.first().map(PathBuf::from);
+                match command.as_str() {
+                    "init" => {
+                        let path = config_command::init(path)?;
+                        println!("Wrote {}", path.display());
+                        Ok(ExitCode::SUCCESS)
+                    }
+                    "check" if config_command::check(path)? => Ok(ExitCode::SUCCESS),
+                    "check" => Ok(ExitCode::FAILURE),
+                    _ => {
+                        eprintln!("lithiumd: unknown config command `{}`\n{}", command, USAGE);
+                        Ok(ExitCode::FAILURE)
+                    }
+                }
+            }
+            _ => {
+                eprintln!("lithiumd: `config` needs init or check\n{}", USAGE);
+                Ok(ExitCode::FAILURE)
+            }
+        },
+        Some("replay") => match &args[1..] {
+            [path] => {
+                replay::run(Path::new(path))?;
+                Ok(ExitCode::SUCCESS)
+            }
+            _ => {
+                eprintln!("lithiumd: `replay` needs a recording\n{}", USAGE);
+                Ok(ExitCode::FAILURE)
+            }
+        },
+        Some("watch") => match watch::Options::parse(&args[1..]) {
+            Ok(options) => {
+                watch::run(&options)?;
+                Ok(ExitCode::SUCCESS)
+            }
+            Err(err) => {
+                eprintln!("lithiumd: {}\n{}", err, USAGE);
+                Ok(ExitCode::FAILURE)
+            }
+        },
+        Some("hub") => match hub::Options::parse(&args[1..]) {
+            Ok(options) => {
+                hub::run(&options)?;
+                Ok(ExitCode::SUCCESS)
+            }
+            Err(err) => {
+                eprintln!("lithiumd: {}\n{}", err, USAGE);
+                Ok(ExitCode::FAILURE)
+            }
+        },
+        Some("agent") => match remote::AgentOptions::parse(&args[1..]) {
+            Ok(options) => {
+                remote::agent(&options)?;
+                Ok(ExitCode::SUCCESS)
+            }
+            Err(err) => {
+                eprintln!("lithiumd: {}\n{}", err, USAGE);
+                Ok(ExitCode::FAILURE)
+            }
+        },
+        Some("remote") => match args.get(1).map(String::as_str) {
+            Some("listen") if args.len() <= 3 => {
+                remote::listen(args.get(2).map_or(remote::DEFAULT_ADDRESS, String::as_str))?;
+                Ok(ExitCode::SUCCESS)
+            }
+            Some("list") => match remote::ListOptions::parse(&args[2..]) {
+                Ok(options) => {
+                    remote::list(&options)?;
+                    Ok(ExitCode::SUCCESS)
+                }
+                Err(err) => {
+                    eprintln!("lithiumd: {}\n{}", err, USAGE);
+                    Ok(ExitCode::FAILURE)
+                }
+            },
+            _ => {
+                eprintln!("lithiumd: `remote` needs listen or list\n{}", USAGE);
+                Ok(ExitCode::FAILURE)
+            }
+        },
+        Some("doctor") if args.len() == 1 => doctor::run(),
+        Some("limits") => match limits::parse(&args[1..]) {
+            Ok(limit) => {
+                limits::run(limit)?;
+                Ok(ExitCode::SUCCESS)
+            }
+            Err(err) => {
+                eprintln!("lithiumd: {}\n{}", err, USAGE);
+                Ok(ExitCode::FAILURE)
+            }
+        },
+        Some("charge-behaviour") => {
+            let behaviour = match &args[1..] {
+                [] => Ok(None),
+                [behaviour] => behaviour.parse().map(Some),
+                _ => Err("too many arguments".to_string()),
+            };
+            match behaviour {
+                Ok(behaviour) => {
+                    limits::run_behaviour(behaviour)?;
+                    Ok(ExitCode::SUCCESS)
+                }
+                Err(err) => {
+                    eprintln!("lithiumd: {}\n{}", err, USAGE);
+                    Ok(ExitCode::FAILURE)
+                }
+            }
+        }
+        Some("status") if args.get(1).map(String::as_str) == Some("--oneshot") => {
+            match &args[2..] {
+                [] => status_oneshot(false),
+                [json] if json == "--json" => status_oneshot(true),
+                _ => {
+                    eprintln!("lithiumd: usage: status --oneshot [--json]\n{}", USAGE);
+                    Ok(ExitCode::FAILURE)
+                }
+            }
+        }
+        Some("status") if args.get(1).map(String::as_str) == Some("--check") => {
+            // Exits with 0 or 1 for the condition, and 2 on errors.
+            let response = match ipc::send(&args) {
+                Ok(response) => response,
+                Err(err) => {
+                    eprintln!("lithiumd: {}", err);
+                    return Ok(ExitCode::from(2));
+                }
+            };
+            if let Some(error) = response.strip_prefix("error: ") {
+                eprint!("lithiumd: {}", error);
+                return Ok(ExitCode::from(2));
+            }
+            Ok(match response.as_str() {
+                "true\n" => ExitCode::SUCCESS,
+                _ => ExitCode::FAILURE,
+            })
+        }
+        Some(_) => {
+            let response = ipc::send(&args)?;
+            if let Some(error) = response.strip_prefix("error: ") {
+                eprint!("lithiumd: {}", error);
+                return Ok(ExitCode::FAILURE);
+            }
+            print!("{}", response);
+            Ok(ExitCode::SUCCESS)
+        }
+    }
+}
 
-    loop {
-        poll.poll(&mut events, None)?;
-        events.clear();
-        power_supply.update()?;
+/// Prints the status read directly from sysfs instead of asking the daemon.
+/// The time remaining and the session are left out, as they take the daemon
+/// watching for a while.
+fn status_oneshot(json: bool) -> io::Result<ExitCode>
+{
+    let snapshot = PowerSupply::read_once()?;
+    let levels = Config::load()?.levels;
+    let level = snapshot.level(&levels);
+    if json {
+        println!(
+            "{}",
+            json::status(&snapshot, level, None, None, false, &lithiumd::read_fans())
+        );
+        return Ok(ExitCode::SUCCESS);
+    }
+    println!("Charging status: {}", snapshot.status);
+    if let Some(capacity) = snapshot.capacity() {
+        println!("Capacity: {}%", capacity);
+    }
+    if let Some(level) = level {
+        println!("Level: {}", level);
+    }
+    if snapshot.batteries().count() > 1 {
+        for bat in snapshot.batteries() {
+            match bat.capacity {
+                Some(capacity) => println!("  {}: {}% {}", bat.sysname, capacity, bat.status),
+                None => println!("  {}: {}", bat.sysname, bat.status),
+            }
+        }
+    }
+    Ok(ExitCode::SUCCESS)
+}
 
-        if power_supply.charging_status_changed() {
-            println!(
-                "Charging status changed: {}",
-                power_supply.charging_status(),
-            );
+fn run_daemon(args: Vec<String>) -> io::Result<ExitCode>
+{
+    let mut config = Config::load()?;
+    if let Err(err) = config.apply_args(&args) {
+        eprintln!("lithiumd: {}\n{}", err, USAGE);
+        return Ok(ExitCode::FAILURE);
+    }
+    let mut lock = match InstanceLock::acquire(config.replace) {
+        Ok(lock) => lock,
+        Err(err) => {
+            eprintln!("lithiumd: {}", err);
+            return Ok(ExitCode::FAILURE);
         }
+    };
+    if config.daemonize {
+        let log = config
+            .log
+            .clone()
+            .unwrap_or_else(daemonize::default_log_path);
+        daemonize::daemonize(&log)?;
+        lock.write_pid()?;
+    }
+    // The signals have to be blocked before any other thread is spawned.
+    let signals = Signals::new()?;
+    // Sysfs attributes, input devices and the control socket are opened as
+    // root, the monitoring loop and the hooks run as the user.
+    let user = config.user.clone();
+    let sandbox = config.sandbox;
+    if user.is_some() {
+        privileges::hold(privileges::attributes());
+    }
+    let mut daemon = Daemon::new(signals, config, args)?;
+    if let Some(user) = user {
+        privileges::drop_to(&user).map_err(|err| {
+            io::Error::new(err.kind(), format!("cannot switch to {}: {}", user, err))
+        })?;
+    }
+    if sandbox {
+        sandbox::install().map_err(|err| {
+            io::Error::new(err.kind(), format!("cannot install the sandbox: {}", err))
+        })?;
     }
+    daemon.run()?;
+    Ok(ExitCode::SUCCESS)
 }
@@ -0,0 +1,81 @@
+use std::fmt::Display;
+use std::time::Duration;
+
+/// Running statistics over a series of durations.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Latency
+{
+    pub samples: u64,
+    pub total: Duration,
+    pub max: Duration,
+    pub last: Duration,
+}
+
+impl Latency
+{
+    pub fn record(&mut self, latency: Duration)
+    {
+        self.samples += 1;
+        self.total += latency;
+        self.max = self.max.max(latency);
+        self.last = latency;
+    }
+
+    #[must_use]
+    pub fn mean(&self) -> Duration
+    {
+        match u32::try_from(self.samples) {
+            Ok(0) => Duration::ZERO,
+            Ok(samples) => self.total / samples,
+            Err(_) => Duration::from_secs_f64(self.total.as_secs_f64() / self.samples as f64),
+        }
+    }
+}
+
+impl Display for Latency
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result
+    {
+        write!(
+            f,
+            "last {:?}, mean {:?}, max {:?} ({} samples)",
+            self.last,
+            self.mean(),
+            self.max,
+            self.samples
+        )
+    }
+}
+
+/// Counters describing how the power supply monitor itself is doing, to tell
+/// whether the daemon is the bottleneck when events seem late or missing.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Metrics
+{
+    /// Number of uevents received from the monitor socket.
+    pub uevents: u64,
+    /// Number of uevents that were folded into a later status refresh by the
+    /// debounce window instead of causing one of their own.
+    pub coalesced: u64,
+    /// Largest number of uevents that were queued on the socket at once.
+    pub max_queue_depth: usize,
+    /// Number of times the socket buffer overflowed and uevents were lost.
+    pub overruns: u64,
+    /// Number of times the socket failed and was opened anew.
+    pub reconnects: u64,
+    /// Time from the first uevent of a burst to the refreshed status.
+    pub settle_latency: Latency,
+}
+
+impl Display for Metrics
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result
+    {
+        writeln!(f, "uevents: {}", self.uevents)?;
+        writeln!(f, "coalesced: {}", self.coalesced)?;
+        writeln!(f, "max queue depth: {}", self.max_queue_depth)?;
+        writeln!(f, "overruns: {}", self.overruns)?;
+        writeln!(f, "reconnects: {}", self.reconnects)?;
+        write!(f, "settle latency: {}", self.settle_latency)
+    }
+}
@@ -0,0 +1,401 @@
+use std::collections::VecDeque;
+use std::fs;
+use std::io;
+use std::os::fd::AsRawFd;
+use std::os::fd::FromRawFd;
+use std::os::fd::OwnedFd;
+use std::os::fd::RawFd;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+use crate::backend;
+use crate::backend::BackendDevice;
+use crate::backend::PowerBackend;
+use crate::backend::Uevents;
+
+/// What happened to a device of a [`MockBackend`], like the action of a
+/// uevent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MockAction
+{
+    Add,
+    Change,
+    Remove,
+}
+
+/// The uevents queued by a [`MockController`], and the eventfd that makes
+/// the backend readable while there are some.
+struct Queue
+{
+    uevents: Mutex<VecDeque<(String, MockAction)>>,
+    /// Whether the next read reports that uevents were lost.
+    overrun: AtomicBool,
+    eventfd: OwnedFd,
+}
+
+/// Devices read from a directory laid out like `/sys/class/power_supply`
+/// instead of from udev, with uevents sent by a [`MockController`]. This
+/// reproduces a laptop from a copy of its sysfs tree, to test against or to
+/// debug odd drivers without the hardware:
+///
+/// ```
+/// use lithiumd::MockBackend;
+/// use lithiumd::PowerSupply;
+/// use lithiumd::Status;
+///
+/// let root = std::env::temp_dir().join(format!("lithiumd-mock-{}", std::process::id()));
+/// let mock = MockBackend::new(&root)?;
+/// let controller = mock.controller();
+/// controller.add("BAT0", &[("type", "Battery"), ("status", "Discharging"), ("capacity", "80")])?;
+/// controller.add("AC", &[("type", "Mains"), ("online", "0")])?;
+///
+/// let mut power_supply = PowerSupply::with_mock(mock);
+/// power_supply.update()?;
+/// assert_eq!(power_supply.charging_status(), Status::Discharging);
+///
+/// controller.play("set AC online 1\nset BAT0 status Charging")?;
+/// power_supply.update()?;
+/// assert_eq!(power_supply.charging_status(), Status::Charging);
+/// # std::fs::remove_dir_all(&root)?;
+/// # Ok::<(), std::io::Error>(())
+/// ```
+///
+/// A device is a directory of attribute files, e.g. `BAT0/capacity`. Its
+/// `type` attribute (`Battery` or `Mains`) stands in for the driver, and
+/// udev properties are read from its `uevent` file if it has one. The
+/// backend is readable in a mio `Poll` while uevents are pending.
+pub struct MockBackend
+{
+    root: PathBuf,
+    queue: Arc<Queue>,
+}
+
+/// Changes the tree of a [`MockBackend`] and sends the matching uevents.
+/// Clones control the same backend, also from other threads.
+#[derive(Clone)]
+pub struct MockController
+{
+    root: PathBuf,
+    queue: Arc<Queue>,
+}
+
+impl MockBackend
+{
+    /// Uses the devices under `root`, which is created if missing.
+    pub fn new(root: impl AsRef<Path>) -> io::Result<Self>
+    {
+        let root = root.as_ref().to_path_buf();
+        fs::create_dir_all(&root)?;
+        let fd = unsafe { libc::eventfd(0, libc::EFD_NONBLOCK | libc::EFD_CLOEXEC) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Self {
+            root,
+            queue: Arc::new(Queue {
+                uevents: Mutex::new(VecDeque::new()),
+                overrun: AtomicBool::new(false),
+                eventfd: unsafe { OwnedFd::from_raw_fd(fd) },
+            }),
+        })
+    }
+
+    #[must_use]
+    pub fn controller(&self) -> MockController
+    {
+        MockController {
+            root: self.root.clone(),
+            queue: Arc::clone(&self.queue),
+        }
+    }
+}
+
+impl PowerBackend for MockBackend
+{
+    fn enumerate(&mut self) -> io::Result<Vec<BackendDevice>>
+    {
+        backend::scan(&self.root)
+    }
+
+    fn fd(&mut self) -> io::Result<RawFd>
+    {
+        Ok(self.queue.eventfd.as_raw_fd())
+    }
+
+    /// Returns the devices of the pending uevents, or an overrun after
+    /// [`MockController::overrun`].
+    fn drain_events(&mut self) -> io::Result<Uevents>
+    {
+        let mut count = 0u64;
+        unsafe {
+            libc::read(
+                self.queue.eventfd.as_raw_fd(),
+                (&mut count as *mut u64).cast(),
+                std::mem::size_of::<u64>(),
+            )
+        };
+        let uevents = self
+            .queue
+            .uevents
+            .lock()
+            .unwrap()
+            .drain(..)
+            .collect::<Vec<_>>();
+        let devices = uevents
+            .into_iter()
+            .map(|(sysname, action)| {
+                let syspath = self.root.join(&sysname);
+                match action {
+                    MockAction::Remove => Ok(BackendDevice::removed(syspath)),
+                    MockAction::Add | MockAction::Change => BackendDevice::read(syspath),
+                }
+            })
+            .collect::<io::Result<_>>()?;
+        Ok(Uevents {
+            devices,
+            overrun: self.queue.overrun.swap(false, Ordering::Relaxed),
+            ..Uevents::default()
+        })
+    }
+}
+
+impl MockController
+{
+    /// Adds the device `sysname` with the given attributes.
+    pub fn add(&self, sysname: &str, attributes: &[(&str, &str)]) -> io::Result<()>
+    {
+        let dir = self.root.join(sysname);
+        fs::create_dir_all(&dir)?;
+        for (name, value) in attributes {
+            fs::write(dir.join(name), format!("{}\n", value))?;
+        }
+        self.send(sysname, MockAction::Add)
+    }
+
+    /// Sets the attribute `name` of the device `sysname`. The file is
+    /// written in place, so that open attributes see the new value.
+    pub fn set(&self, sysname: &str, name: &str, value: &str) -> io::Result<()>
+    {
+        fs::write(self.root.join(sysname).join(name), format!("{}\n", value))?;
+        self.send(sysname, MockAction::Change)
+    }
+
+    /// Makes reads of the attribute `name` of `sysname` fail with `EIO`, like
+    /// those of a flaky embedded controller, by linking it to
+    /// `/proc/self/mem`, whose first page is never mapped. As the power
+    /// supply keeps attributes open, only those it did not read yet fail.
+    pub fn fail(&self, sysname: &str, name: &str) -> io::Result<()>
+    {
+        let path = self.root.join(sysname).join(name);
+        match fs::remove_file(&path) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(err),
+            _ => {}
+        }
+        std::os::unix::fs::symlink("/proc/self/mem", path)
+    }
+
+    /// Removes the device `sysname`.
+    pub fn remove(&self, sysname: &str) -> io::Result<()>
+    {
+        fs::remove_dir_all(self.root.join(sysname))?;
+        self.send(sysname, MockAction::Remove)
+    }
+
+    /// Sends a uevent for `sysname` without changing the tree, e.g. after
+    /// editing its attributes by hand.
+    pub fn send(&self, sysname: &str, action: MockAction) -> io::Result<()>
+    {
+        self.queue
+            .uevents
+            .lock()
+            .unwrap()
+            .push_back((sysname.to_string(), action));
+        self.wake()
+    }
+
+    /// Drops the pending uevents and reports that they were lost, like
+    /// `ENOBUFS` on a netlink socket whose buffer overflowed during a storm
+    /// of uevents.
+    pub fn overrun(&self) -> io::Result<()>
+    {
+        self.discard();
+        self.queue.overrun.store(true, Ordering::Relaxed);
+        self.wake()
+    }
+
+    /// Drops the pending uevents without a trace.
+    pub(crate) fn discard(&self)
+    {
+        self.queue.uevents.lock().unwrap().clear();
+    }
+
+    /// Makes the backend readable.
+    fn wake(&self) -> io::Result<()>
+    {
+        let one = 1u64;
+        let written = unsafe {
+            libc::write(
+                self.queue.eventfd.as_raw_fd(),
+                (&one as *const u64).cast(),
+                std::mem::size_of::<u64>(),
+            )
+        };
+        if written < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    /// Runs a script of one change per line: `add SYSNAME [NAME=VALUE...]`,
+    /// `set SYSNAME NAME VALUE`, `fail SYSNAME NAME`, `remove SYSNAME` or
+    /// `change SYSNAME`. Empty lines and lines starting with `#` are skipped.
+    pub fn play(&self, script: &str) -> io::Result<()>
+    {
+        for (i, line) in script.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let words = line.split_whitespace().collect::<Vec<_>>();
+            let played = self.play_line(&words).and_then(|played| {
+                played.then_some(()).ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        "expected add, set, fail, remove or change",
+                    )
+                })
+            });
+            played.map_err(|err| io::Error::new(err.kind(), format!("line {}: {}", i + 1, err)))?;
+        }
+        Ok(())
+    }
+
+    /// Runs one line of a script split into words, and returns whether it
+    /// is one of the changes of [`MockController::play`].
+    pub(crate) fn play_line(&self, words: &[&str]) -> io::Result<bool>
+    {
+        match words {
+            ["add", sysname, attributes @ ..] => {
+                let attributes = attributes
+                    .iter()
+                    .map(|attribute| {
+                        attribute.split_once('=').ok_or_else(|| {
+                            io::Error::new(io::ErrorKind::InvalidInput, "expected NAME=VALUE")
+                        })
+                    })
+                    .collect::<io::Result<Vec<_>>>()?;
+                self.add(sysname, &attributes)?;
+            }
+            ["set", sysname, name, value @ ..] if !value.is_empty() => {
+                self.set(sysname, name, &value.join(" "))?;
+            }
+            ["fail", sysname, name] => self.fail(sysname, name)?,
+            ["remove", sysname] => self.remove(sysname)?,
+            ["change", sysname] => self.send(sysname, MockAction::Change)?,
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::power_supply::DeviceType;
+    use crate::power_supply::Scope;
+    use crate::power_supply::Status;
+    use crate::scenario::Scenario;
+
+    #[test]
+    fn classifies_devices_of_a_fake_tree()
+    {
+        let mut scenario = Scenario::new().unwrap();
+        // Neither reports a type, so they are told apart by their names.
+        scenario
+            .controller()
+            .play(
+                "
+                add CMB0 status=Discharging capacity=64
+                add ACAD online=0
+                add hidpp_battery_0 type=Battery scope=Device status=Discharging capacity=30
+                add ucsi-source-psy-USBC000:001 type=USB online=0
+                ",
+            )
+            .unwrap();
+        scenario.update().unwrap();
+        let snapshot = scenario.power_supply().snapshot();
+        let devices = snapshot
+            .devices
+            .iter()
+            .map(|dev| (dev.sysname.as_str(), dev.device_type, dev.scope))
+            .collect::<Vec<_>>();
+        assert!(devices.contains(&("CMB0", DeviceType::Battery, Scope::System)));
+        assert!(devices.contains(&("ACAD", DeviceType::Adapter, Scope::System)));
+        assert!(devices
+            .iter()
+            .all(|(sysname, ..)| *sysname != "hidpp_battery_0"));
+        assert_eq!(snapshot.status, Status::Discharging);
+        assert_eq!(snapshot.capacity(), Some(64));
+
+        scenario.controller().set("ACAD", "online", "1").unwrap();
+        scenario
+            .controller()
+            .set("CMB0", "status", "Charging")
+            .unwrap();
+        scenario.update().unwrap();
+        assert_eq!(scenario.power_supply().charging_status(), Status::Charging);
+    }
+
+    #[test]
+    fn reads_devices_and_uevents()
+    {
+        let scenario = Scenario::new().unwrap();
+        let controller = scenario.controller();
+        let mut mock = MockBackend {
+            root: controller.root.clone(),
+            queue: Arc::clone(&controller.queue),
+        };
+        controller
+            .add("BAT1", &[("type", "Battery"), ("status", "Full")])
+            .unwrap();
+        let devices = mock.enumerate().unwrap();
+        assert_eq!(devices.len(), 1);
+        assert_eq!(devices[0].driver.as_deref(), Some("battery"));
+        assert_eq!(
+            devices[0].property_value("POWER_SUPPLY_TYPE"),
+            Some("Battery")
+        );
+
+        controller.remove("BAT1").unwrap();
+        let uevents = mock.drain_events().unwrap();
+        assert_eq!(uevents.devices.len(), 2);
+        assert!(!uevents.devices[0].removed);
+        assert!(uevents.devices[1].removed);
+        assert!(mock.drain_events().unwrap().devices.is_empty());
+
+        controller.send("BAT1", MockAction::Remove).unwrap();
+        controller.overrun().unwrap();
+        let uevents = mock.drain_events().unwrap();
+        assert!(uevents.overrun);
+        assert!(uevents.devices.is_empty());
+        assert!(!mock.drain_events().unwrap().overrun);
+    }
+
+    #[test]
+    fn rejects_bad_scripts()
+    {
+        let scenario = Scenario::new().unwrap();
+        let err = scenario.controller().play("add BAT0 type").unwrap_err();
+        assert_eq!(err.to_string(), "line 1: expected NAME=VALUE");
+        let err = scenario.controller().play("\nset BAT0 status").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "line 2: expected add, set, fail, remove or change"
+        );
+    }
+}
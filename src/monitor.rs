@@ -0,0 +1,80 @@
+use std::io;
+use std::sync::mpsc;
+use std::sync::mpsc::Receiver;
+use std::thread;
+use std::thread::JoinHandle;
+
+use crate::event::PowerEvent;
+use crate::power_supply::PowerSupply;
+
+impl PowerSupply
+{
+    /// Runs a power supply on a thread of its own and sends its events over a
+    /// channel, for applications that cannot poll it in a loop of their own:
+    ///
+    /// ```no_run
+    /// use lithiumd::PowerSupply;
+    ///
+    /// let (_monitor, events) = PowerSupply::spawn_monitor();
+    /// for event in events {
+    ///     println!("{}", event);
+    /// }
+    /// ```
+    ///
+    /// The thread stops at the first event after the receiver was dropped,
+    /// or with the error that made reading the devices fail.
+    #[must_use]
+    pub fn spawn_monitor() -> (JoinHandle<io::Result<()>>, Receiver<PowerEvent>)
+    {
+        Self::spawn_monitor_with(|_| {})
+    }
+
+    /// Like [`PowerSupply::spawn_monitor`], but calls `configure` on the
+    /// power supply before it is first read, e.g. to set the debounce window.
+    pub fn spawn_monitor_with(
+        configure: impl FnOnce(&mut PowerSupply) + Send + 'static,
+    ) -> (JoinHandle<io::Result<()>>, Receiver<PowerEvent>)
+    {
+        let (sender, receiver) = mpsc::channel();
+        let handle = thread::spawn(move || run(configure, |event| sender.send(event).is_ok()));
+        (handle, receiver)
+    }
+}
+
+/// Reads a power supply until `deliver` returns `false` for an event, or
+/// reading fails.
+pub(crate) fn run(
+    configure: impl FnOnce(&mut PowerSupply),
+    mut deliver: impl FnMut(PowerEvent) -> bool,
+) -> io::Result<()>
+{
+    // udev handles cannot move between threads, so the power supply is made
+    // on the thread that reads it.
+    let mut power_supply = PowerSupply::new();
+    configure(&mut power_supply);
+    loop {
+        if !power_supply
+            .process_pending()?
+            .into_iter()
+            .all(&mut deliver)
+        {
+            return Ok(());
+        }
+        let mut fds = [libc::pollfd {
+            fd: power_supply.monitor_fd()?,
+            events: libc::POLLIN,
+            revents: 0,
+        }];
+        // Rounded up, so that the deadline has passed once the poll returns.
+        let timeout = power_supply.timeout().map_or(-1, |timeout| {
+            libc::c_int::try_from(timeout.as_nanos().div_ceil(1_000_000))
+                .unwrap_or(libc::c_int::MAX)
+        });
+        if unsafe { libc::poll(fds.as_mut_ptr(), 1, timeout) } < 0 {
+            let err = io::Error::last_os_error();
+            if err.kind() != io::ErrorKind::Interrupted {
+                return Err(err);
+            }
+        }
+    }
+}
@@ -0,0 +1,539 @@
+use std::io;
+use std::io::Read;
+use std::io::Write;
+use std::net::TcpStream;
+use std::net::ToSocketAddrs;
+use std::sync::mpsc;
+use std::sync::mpsc::RecvTimeoutError;
+use std::thread;
+use std::time::Duration;
+use std::time::Instant;
+
+use lithiumd::Snapshot;
+
+use crate::json;
+use crate::json::quote;
+
+/// How long connecting to the broker and sending to it may take.
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How long the publisher waits before connecting again after a failure,
+/// doubling up to [`MAX_BACKOFF`] while the broker stays unreachable.
+const MIN_BACKOFF: Duration = Duration::from_secs(5);
+const MAX_BACKOFF: Duration = Duration::from_secs(300);
+
+/// Where and how the state is published, from the `[mqtt]` table.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MqttOptions
+{
+    pub host: String,
+    pub port: u16,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    /// Identifies the machine in topics and to Home Assistant. Defaults to
+    /// the hostname.
+    pub node_id: String,
+    /// Prefix of the state topics, `<prefix>/<node_id>/...`.
+    pub topic_prefix: String,
+    /// Prefix Home Assistant watches for discovery messages.
+    pub discovery_prefix: String,
+}
+
+/// A sensor announced to Home Assistant.
+struct Sensor
+{
+    object: &'static str,
+    name: &'static str,
+    /// Key of the value in the state message.
+    key: &'static str,
+    class: Option<&'static str>,
+    unit: Option<&'static str>,
+}
+
+const SENSORS: [Sensor; 3] = [
+    Sensor {
+        object: "capacity",
+        name: "Battery",
+        key: "capacity",
+        class: Some("battery"),
+        unit: Some("%"),
+    },
+    Sensor {
+        object: "power",
+        name: "Power draw",
+        key: "power",
+        class: Some("power"),
+        unit: Some("W"),
+    },
+    Sensor {
+        object: "status",
+        name: "Charging status",
+        key: "status",
+        class: None,
+        unit: None,
+    },
+];
+
+/// Publishes the state to an MQTT broker from a thread of its own, so that
+/// resolving, connecting and sending, which block for up to [`TIMEOUT`]
+/// each, never hold up the event loop, e.g. on a laptop away from the
+/// broker. Only the latest state is kept while it cannot be sent, and
+/// connecting is retried with a backoff. The thread disconnects and exits
+/// once the publisher is dropped.
+pub struct MqttPublisher
+{
+    options: MqttOptions,
+    sender: mpsc::Sender<Snapshot>,
+}
+
+impl MqttPublisher
+{
+    pub fn spawn(options: MqttOptions) -> Self
+    {
+        let (sender, receiver) = mpsc::channel();
+        let thread_options = options.clone();
+        thread::spawn(move || publish_from(&thread_options, &receiver));
+        Self { options, sender }
+    }
+
+    #[must_use]
+    pub fn options(&self) -> &MqttOptions
+    {
+        &self.options
+    }
+
+    /// Hands `snapshot` to the thread, without waiting for it to be sent.
+    pub fn publish(&self, snapshot: &Snapshot)
+    {
+        let _ = self.sender.send(snapshot.clone());
+    }
+}
+
+/// Runs the thread of [`MqttPublisher`] until the publisher is dropped.
+fn publish_from(options: &MqttOptions, receiver: &mpsc::Receiver<Snapshot>)
+{
+    let mut mqtt = None;
+    let mut pending = None;
+    let mut backoff = Backoff::new(MIN_BACKOFF, MAX_BACKOFF);
+    let mut next_attempt = Instant::now();
+    loop {
+        if pending.is_some() && mqtt.is_none() && Instant::now() >= next_attempt {
+            match Mqtt::connect(options) {
+                Ok(connected) => {
+                    backoff.reset();
+                    mqtt = Some(connected);
+                }
+                Err(err) => {
+                    let delay = backoff.next();
+                    eprintln!(
+                        "Failed to connect to {}: {}, retrying in {}s",
+                        options.host,
+                        err,
+                        delay.as_secs()
+                    );
+                    next_attempt = Instant::now() + delay;
+                }
+            }
+        }
+        if let (Some(connected), Some(snapshot)) = (&mut mqtt, &pending) {
+            match connected.publish_state(snapshot) {
+                Ok(()) => pending = None,
+                Err(err) => {
+                    eprintln!("Failed to publish to {}: {}", options.host, err);
+                    // The connection may just have gone stale, so connect
+                    // again right away.
+                    mqtt = None;
+                    continue;
+                }
+            }
+        }
+        let received = match pending {
+            // Not connected, so wait for the next attempt at most.
+            Some(_) => {
+                receiver.recv_timeout(next_attempt.saturating_duration_since(Instant::now()))
+            }
+            None => receiver.recv().map_err(|_| RecvTimeoutError::Disconnected),
+        };
+        match received {
+            Ok(snapshot) => pending = Some(receiver.try_iter().last().unwrap_or(snapshot)),
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => return,
+        }
+    }
+}
+
+/// Delays between attempts that double from `min` up to `max`.
+#[derive(Debug)]
+struct Backoff
+{
+    min: Duration,
+    max: Duration,
+    delay: Duration,
+}
+
+impl Backoff
+{
+    fn new(min: Duration, max: Duration) -> Self
+    {
+        Self {
+            min,
+            max,
+            delay: min,
+        }
+    }
+
+    /// Returns the delay before the next attempt, and doubles the one after.
+    fn next(&mut self) -> Duration
+    {
+        let delay = self.delay;
+        self.delay = (self.delay * 2).min(self.max);
+        delay
+    }
+
+    fn reset(&mut self)
+    {
+        self.delay = self.min;
+    }
+}
+
+/// A connection to an MQTT broker (protocol 3.1.1) that publishes the state
+/// of the power supply as a retained JSON message, and announces it in the
+/// discovery format of Home Assistant so that the machine shows up as a set
+/// of battery sensors. Messages are sent with QoS 0; the broker marks the
+/// machine as offline when the connection drops.
+pub struct Mqtt
+{
+    stream: TcpStream,
+    options: MqttOptions,
+}
+
+impl Mqtt
+{
+    /// Connects to the broker and sends the discovery messages.
+    fn connect(options: &MqttOptions) -> io::Result<Self>
+    {
+        let addr = (options.host.as_str(), options.port)
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| io::Error::other(format!("cannot resolve {}", options.host)))?;
+        let stream = TcpStream::connect_timeout(&addr, TIMEOUT)?;
+        stream.set_read_timeout(Some(TIMEOUT))?;
+        stream.set_write_timeout(Some(TIMEOUT))?;
+        let mut mqtt = Self {
+            stream,
+            options: options.clone(),
+        };
+        mqtt.handshake()?;
+        for sensor in &SENSORS {
+            let topic = format!(
+                "{}/sensor/{}/{}/config",
+                options.discovery_prefix, options.node_id, sensor.object
+            );
+            let payload = mqtt.discovery(sensor);
+            mqtt.publish(&topic, &payload)?;
+        }
+        let availability = mqtt.topic("availability");
+        mqtt.publish(&availability, "online")?;
+        Ok(mqtt)
+    }
+
+    /// Publishes the state in `snapshot`.
+    fn publish_state(&mut self, snapshot: &Snapshot) -> io::Result<()>
+    {
+        // Only batteries that report energy tell the power in watts.
+        let power = snapshot
+            .batteries()
+            .map(|bat| bat.power_now.filter(|_| bat.reports_energy))
+            .sum::<Option<u64>>()
+            .filter(|_| snapshot.batteries().next().is_some())
+            .map_or("null".to_string(), |power| {
+                format!("{:.2}", power as f64 / 1_000_000.0)
+            });
+        let capacity = snapshot
+            .capacity()
+            .map_or("null".to_string(), |capacity| capacity.to_string());
+        let payload = format!(
+            "{{\"status\":{},\"capacity\":{},\"power\":{}}}",
+            quote(json::status_name(snapshot.status)),
+            capacity,
+            power
+        );
+        let topic = self.topic("state");
+        self.publish(&topic, &payload)
+    }
+
+    fn topic(&self, name: &str) -> String
+    {
+        format!(
+            "{}/{}/{}",
+            self.options.topic_prefix, self.options.node_id, name
+        )
+    }
+
+    fn discovery(&self, sensor: &Sensor) -> String
+    {
+        let node_id = &self.options.node_id;
+        let mut config = format!(
+            "{{\"name\":{},\"unique_id\":{},\"state_topic\":{},\"value_template\":{},\"availability_topic\":{}",
+            quote(sensor.name),
+            quote(&format!("lithiumd_{}_{}", node_id, sensor.object)),
+            quote(&self.topic("state")),
+            quote(&format!("{{{{ value_json.{} }}}}", sensor.key)),
+            quote(&self.topic("availability")),
+        );
+        if let Some(class) = sensor.class {
+            config += &format!(",\"device_class\":{}", quote(class));
+        }
+        if let Some(unit) = sensor.unit {
+            config += &format!(",\"unit_of_measurement\":{}", quote(unit));
+        }
+        config += &format!(
+            ",\"device\":{{\"identifiers\":[{}],\"name\":{},\"manufacturer\":\"lithiumd\"}}}}",
+            quote(&format!("lithiumd_{}", node_id)),
+            quote(node_id),
+        );
+        config
+    }
+
+    /// Sends `CONNECT`, with a will that marks the machine offline, and waits
+    /// for `CONNACK`.
+    fn handshake(&mut self) -> io::Result<()>
+    {
+        const CLEAN_SESSION: u8 = 0x02;
+        const WILL: u8 = 0x04;
+        const WILL_RETAIN: u8 = 0x20;
+        const PASSWORD: u8 = 0x40;
+        const USERNAME: u8 = 0x80;
+
+        let mut flags = CLEAN_SESSION | WILL | WILL_RETAIN;
+        let mut body = Vec::new();
+        put_string(&mut body, "MQTT");
+        // Protocol level 4 is MQTT 3.1.1.
+        body.push(4);
+        let flags_at = body.len();
+        body.push(0);
+        // A keep alive of 0 turns it off, so no pings have to be sent.
+        body.extend(0u16.to_be_bytes());
+        put_string(&mut body, &format!("lithiumd-{}", self.options.node_id));
+        put_string(&mut body, &self.topic("availability"));
+        put_string(&mut body, "offline");
+        if let Some(username) = &self.options.username {
+            flags |= USERNAME;
+            put_string(&mut body, username);
+        }
+        if let Some(password) = &self.options.password {
+            flags |= PASSWORD;
+            put_string(&mut body, password);
+        }
+        body[flags_at] = flags;
+        self.send(0x10, &body)?;
+
+        let mut connack = [0; 4];
+        self.stream.read_exact(&mut connack)?;
+        match connack {
+            [0x20, 2, _, 0] => Ok(()),
+            [0x20, 2, _, 4 | 5] => Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                "the broker refused the credentials",
+            )),
+            [0x20, 2, _, code] => Err(io::Error::other(format!(
+                "the broker refused the connection with code {}",
+                code
+            ))),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "expected CONNACK from the broker",
+            )),
+        }
+    }
+
+    /// Sends a retained `PUBLISH` with QoS 0.
+    fn publish(&mut self, topic: &str, payload: &str) -> io::Result<()>
+    {
+        const RETAIN: u8 = 0x01;
+        let mut body = Vec::new();
+        put_string(&mut body, topic);
+        body.extend(payload.as_bytes());
+        self.send(0x30 | RETAIN, &body)
+    }
+
+    fn send(&mut self, header: u8, body: &[u8]) -> io::Result<()>
+    {
+        self.stream.write_all(&packet(header, body))
+    }
+}
+
+impl Drop for Mqtt
+{
+    /// Marks the machine offline and disconnects, as a clean disconnect does
+    /// not publish the will.
+    fn drop(&mut self)
+    {
+        let availability = self.topic("availability");
+        let _ = self.publish(&availability, "offline");
+        let _ = self.send(0xe0, &[]);
+    }
+}
+
+/// Returns the packet of type and flags `header` with `body`.
+fn packet(header: u8, body: &[u8]) -> Vec<u8>
+{
+    let mut packet = vec![header];
+    // The remaining length is encoded 7 bits at a time.
+    let mut len = body.len();
+    loop {
+        let byte = (len % 128) as u8;
+        len /= 128;
+        if len == 0 {
+            packet.push(byte);
+            break;
+        }
+        packet.push(byte | 0x80);
+    }
+    packet.extend(body);
+    packet
+}
+
+fn put_string(buf: &mut Vec<u8>, s: &str)
+{
+    buf.extend((s.len() as u16).to_be_bytes());
+    buf.extend(s.as_bytes());
+}
+
+/// Returns the hostname, as the default node id.
+pub fn hostname() -> String
+{
+    let mut buf = [0u8; 256];
+    let len = unsafe {
+        if libc::gethostname(buf.as_mut_ptr().cast(), buf.len()) != 0 {
+            return "lithiumd".to_string();
+        }
+        buf.iter().position(|b| *b == 0).unwrap_or(buf.len())
+    };
+    // Topics and discovery ids only allow a few characters.
+    String::from_utf8_lossy(&buf[..len])
+        .chars()
+        .map(|c| match c {
+            'a'..='z' | 'A'..='Z' | '0'..='9' | '_' | '-' => c,
+            _ => '_',
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests
+{
+    use std::net::TcpListener;
+
+    use super::*;
+
+    fn options(port: u16) -> MqttOptions
+    {
+        MqttOptions {
+            host: "127.0.0.1".to_string(),
+            port,
+            username: Some("user".to_string()),
+            password: None,
+            node_id: "laptop".to_string(),
+            topic_prefix: "lithiumd".to_string(),
+            discovery_prefix: "homeassistant".to_string(),
+        }
+    }
+
+    /// Accepts one connection, reads the `CONNECT` and answers `connack`,
+    /// and returns the packet it read.
+    fn broker(connack: &'static [u8]) -> (u16, thread::JoinHandle<Vec<u8>>)
+    {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let handle = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut header = [0; 2];
+            stream.read_exact(&mut header).unwrap();
+            let mut body = vec![0; usize::from(header[1])];
+            stream.read_exact(&mut body).unwrap();
+            stream.write_all(connack).unwrap();
+            // Keep the connection open while the client publishes.
+            let mut rest = Vec::new();
+            let _ = stream.read_to_end(&mut rest);
+            [&header[..], &body].concat()
+        });
+        (port, handle)
+    }
+
+    #[test]
+    fn encodes_the_remaining_length()
+    {
+        let length = |len: usize| packet(0x30, &vec![0; len])[1..].to_vec();
+        assert_eq!(length(0)[..1], [0x00]);
+        assert_eq!(length(127)[..1], [0x7f]);
+        assert_eq!(length(128)[..2], [0x80, 0x01]);
+        assert_eq!(length(16_383)[..2], [0xff, 0x7f]);
+        assert_eq!(length(16_384)[..3], [0x80, 0x80, 0x01]);
+        assert_eq!(length(2_097_152)[..4], [0x80, 0x80, 0x80, 0x01]);
+        assert_eq!(packet(0xe0, &[]), [0xe0, 0x00]);
+    }
+
+    #[test]
+    fn sends_connect_with_a_will()
+    {
+        let (port, broker) = broker(&[0x20, 2, 0, 0]);
+        drop(Mqtt::connect(&options(port)).unwrap());
+        let connect = broker.join().unwrap();
+        let mut expected = vec![0x10, 0];
+        let mut body = Vec::new();
+        put_string(&mut body, "MQTT");
+        // Level 4, clean session, retained will and a username.
+        body.extend([4, 0x02 | 0x04 | 0x20 | 0x80, 0, 0]);
+        put_string(&mut body, "lithiumd-laptop");
+        put_string(&mut body, "lithiumd/laptop/availability");
+        put_string(&mut body, "offline");
+        put_string(&mut body, "user");
+        expected[1] = body.len() as u8;
+        expected.extend(body);
+        assert_eq!(connect, expected);
+    }
+
+    #[test]
+    fn reports_refused_connections()
+    {
+        let connect = |connack: &'static [u8]| {
+            let (port, _broker) = broker(connack);
+            Mqtt::connect(&options(port)).err().unwrap()
+        };
+        let refused = connect(&[0x20, 2, 0, 5]);
+        assert_eq!(refused.kind(), io::ErrorKind::PermissionDenied);
+        let refused = connect(&[0x20, 2, 0, 3]);
+        assert_eq!(
+            refused.to_string(),
+            "the broker refused the connection with code 3"
+        );
+        let garbage = connect(&[0x30, 2, 0, 0]);
+        assert_eq!(garbage.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn backs_off_up_to_the_maximum()
+    {
+        let mut backoff = Backoff::new(Duration::from_secs(5), Duration::from_secs(30));
+        let delays = (0..5).map(|_| backoff.next().as_secs()).collect::<Vec<_>>();
+        assert_eq!(delays, [5, 10, 20, 30, 30]);
+        backoff.reset();
+        assert_eq!(backoff.next(), Duration::from_secs(5));
+    }
+
+    #[test]
+    fn publishing_does_not_wait_for_the_broker()
+    {
+        // A broker that accepts the connection but never answers.
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let publisher = MqttPublisher::spawn(options(port));
+        let start = Instant::now();
+        for _ in 0..10 {
+            publisher.publish(&Snapshot::default());
+        }
+        assert!(start.elapsed() < Duration::from_secs(1));
+        drop(listener);
+    }
+}
@@ -0,0 +1,191 @@
+use std::io;
+
+#[cfg(feature = "mio")]
+use mio::event::Source;
+
+use crate::dbus::Connection;
+use crate::dbus::Message;
+use crate::dbus::Value;
+
+const NOTIFICATIONS: &str = "org.freedesktop.Notifications";
+const NOTIFICATIONS_PATH: &str = "/org/freedesktop/Notifications";
+
+/// How urgent a notification is, which notification servers show
+/// differently, e.g. by keeping critical ones open until dismissed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Urgency
+{
+    Low,
+    Normal,
+    Critical,
+}
+
+/// A notification to show.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Notification
+{
+    pub summary: String,
+    pub body: String,
+    /// Icon name from the freedesktop naming spec.
+    pub icon: String,
+    pub urgency: Urgency,
+    /// Percentage shown as a progress bar, by servers that support the
+    /// `value` hint.
+    pub progress: Option<u8>,
+    /// Buttons as pairs of a key, reported by [`NotificationEvent::Action`],
+    /// and a label.
+    pub actions: Vec<(String, String)>,
+}
+
+/// What happened to a notification that was shown.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NotificationEvent
+{
+    /// The button with this key was clicked.
+    Action
+    {
+        id: u32, key: String
+    },
+    /// The notification was dismissed or expired.
+    Closed
+    {
+        id: u32
+    },
+}
+
+/// Client of the desktop notification server on the session bus, as
+/// specified by freedesktop.org.
+///
+/// Register it with a mio `Poll` and call [`Notifications::process`] when it
+/// becomes readable to learn about clicked buttons.
+pub struct Notifications
+{
+    conn: Connection,
+}
+
+impl Notifications
+{
+    /// Connects to the session bus and subscribes to the signals of the
+    /// notification server.
+    pub fn new() -> io::Result<Self>
+    {
+        let mut conn = Connection::session()?;
+        for member in ["ActionInvoked", "NotificationClosed"] {
+            conn.add_match(&format!(
+                "type='signal',interface='{}',member='{}'",
+                NOTIFICATIONS, member
+            ))?;
+        }
+        Ok(Self { conn })
+    }
+
+    /// Shows `notification` in place of the one with the id `replaces`, or as
+    /// a new one if that is 0, and returns its id.
+    pub fn notify(&mut self, replaces: u32, notification: &Notification) -> io::Result<u32>
+    {
+        let urgency = match notification.urgency {
+            Urgency::Low => 0,
+            Urgency::Normal => 1,
+            Urgency::Critical => 2,
+        };
+        let actions = notification
+            .actions
+            .iter()
+            .flat_map(|(key, label)| [key, label])
+            .map(|s| Value::String(s.clone()))
+            .collect();
+        let mut hints = vec![("urgency", Value::Byte(urgency))];
+        if let Some(progress) = notification.progress {
+            hints.push(("value", Value::Int32(progress.into())));
+        }
+        let reply = self.conn.call(Message::method_call(
+            NOTIFICATIONS,
+            NOTIFICATIONS_PATH,
+            NOTIFICATIONS,
+            "Notify",
+            vec![
+                Value::String("lithiumd".to_string()),
+                Value::UInt32(replaces),
+                Value::String(notification.icon.clone()),
+                Value::String(notification.summary.clone()),
+                Value::String(notification.body.clone()),
+                Value::Array("s".to_string(), actions),
+                Value::dict(hints),
+                Value::Int32(-1),
+            ],
+        ))?;
+        match reply.body.first() {
+            Some(Value::UInt32(id)) => Ok(*id),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Notify did not return an id",
+            )),
+        }
+    }
+
+    /// Closes the notification with the id `id`.
+    pub fn close(&mut self, id: u32) -> io::Result<()>
+    {
+        self.conn.call(Message::method_call(
+            NOTIFICATIONS,
+            NOTIFICATIONS_PATH,
+            NOTIFICATIONS,
+            "CloseNotification",
+            vec![Value::UInt32(id)],
+        ))?;
+        Ok(())
+    }
+
+    /// Reads the pending signals. They concern the notifications of every
+    /// application, so callers have to check the ids.
+    pub fn process(&mut self) -> io::Result<Vec<NotificationEvent>>
+    {
+        let mut events = Vec::new();
+        while let Some(message) = self.conn.read()? {
+            let id = match message.body.first() {
+                Some(Value::UInt32(id)) => *id,
+                _ => continue,
+            };
+            if message.is_signal(NOTIFICATIONS, "ActionInvoked") {
+                if let Some(key) = message.body.get(1).and_then(Value::as_str) {
+                    events.push(NotificationEvent::Action {
+                        id,
+                        key: key.to_string(),
+                    });
+                }
+            } else if message.is_signal(NOTIFICATIONS, "NotificationClosed") {
+                events.push(NotificationEvent::Closed { id });
+            }
+        }
+        Ok(events)
+    }
+}
+
+#[cfg(feature = "mio")]
+impl Source for Notifications
+{
+    fn register(
+        &mut self,
+        registry: &mio::Registry,
+        token: mio::Token,
+        interests: mio::Interest,
+    ) -> io::Result<()>
+    {
+        self.conn.register(registry, token, interests)
+    }
+
+    fn reregister(
+        &mut self,
+        registry: &mio::Registry,
+        token: mio::Token,
+        interests: mio::Interest,
+    ) -> io::Result<()>
+    {
+        self.conn.reregister(registry, token, interests)
+    }
+
+    fn deregister(&mut self, registry: &mio::Registry) -> io::Result<()>
+    {
+        self.conn.deregister(registry)
+    }
+}
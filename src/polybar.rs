@@ -0,0 +1,124 @@
+use std::io;
+use std::io::Read;
+
+use lithiumd::BatteryLevel;
+use lithiumd::Fields;
+use lithiumd::Labels;
+use lithiumd::Pretty;
+use lithiumd::Status;
+use lithiumd::Template;
+use mio::event::Source;
+use mio::unix::SourceFd;
+
+const STDIN: i32 = 0;
+
+/// Output for a polybar `custom/script` module with `tail = true`: the
+/// battery glyph and the capacity in polybar's color tags, or with
+/// `toggle` on stdin the format or the time remaining as well. Polybar does
+/// not write to its scripts, so actions reach stdin through a FIFO.
+#[derive(Debug, Default)]
+pub struct Polybar
+{
+    detailed: bool,
+    input: Vec<u8>,
+}
+
+impl Polybar
+{
+    #[must_use]
+    pub fn render(
+        &self,
+        icons: &Pretty,
+        format: Option<&Template>,
+        labels: &Labels,
+        fields: &Fields,
+    ) -> String
+    {
+        let mut output = icons.icon(fields.capacity, fields.status).to_string();
+        if let Some(capacity) = fields.capacity {
+            output += &format!(" {}%", capacity);
+        }
+        let color = match (fields.status, fields.level) {
+            (Status::Charging, _) => Some("#a3be8c"),
+            (_, Some(BatteryLevel::Critical)) => Some("#bf616a"),
+            (_, Some(BatteryLevel::Low)) => Some("#ebcb8b"),
+            _ => None,
+        };
+        if let Some(color) = color.filter(|_| icons.color) {
+            output = format!("%{{F{}}}{}%{{F-}}", color, output);
+        }
+        if !self.detailed {
+            return output;
+        }
+        match (format, fields.time_remaining) {
+            (Some(format), _) => output + " " + &format.render_with(fields, labels),
+            (None, Some(remaining)) => {
+                let minutes = remaining.as_secs() / 60;
+                output + &format!(" {}h {}m", minutes / 60, minutes % 60)
+            }
+            (None, None) => output,
+        }
+    }
+
+    /// Reads the actions on stdin, one per line: `toggle`, `short` and
+    /// `long`. Returns whether the output has to be printed again, or
+    /// `UnexpectedEof` once stdin is closed.
+    pub fn read(&mut self) -> io::Result<bool>
+    {
+        let mut buf = [0; 256];
+        loop {
+            match io::stdin().lock().read(&mut buf) {
+                Ok(0) => return Err(io::ErrorKind::UnexpectedEof.into()),
+                Ok(n) => self.input.extend(&buf[..n]),
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => break,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+                Err(err) => return Err(err),
+            }
+        }
+        let detailed = self.detailed;
+        while let Some(i) = self.input.iter().position(|b| *b == b'\n') {
+            let line = self.input.drain(..=i).collect::<Vec<_>>();
+            match String::from_utf8_lossy(&line).trim() {
+                "toggle" => self.detailed = !self.detailed,
+                "short" => self.detailed = false,
+                "long" => self.detailed = true,
+                "" => {}
+                other => eprintln!("Unknown polybar action `{}`", other),
+            }
+        }
+        Ok(self.detailed != detailed)
+    }
+}
+
+impl Source for Polybar
+{
+    /// Also makes stdin non-blocking.
+    fn register(
+        &mut self,
+        registry: &mio::Registry,
+        token: mio::Token,
+        interests: mio::Interest,
+    ) -> io::Result<()>
+    {
+        let flags = unsafe { libc::fcntl(STDIN, libc::F_GETFL) };
+        if flags < 0 || unsafe { libc::fcntl(STDIN, libc::F_SETFL, flags | libc::O_NONBLOCK) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        SourceFd(&STDIN).register(registry, token, interests)
+    }
+
+    fn reregister(
+        &mut self,
+        registry: &mio::Registry,
+        token: mio::Token,
+        interests: mio::Interest,
+    ) -> io::Result<()>
+    {
+        SourceFd(&STDIN).reregister(registry, token, interests)
+    }
+
+    fn deregister(&mut self, registry: &mio::Registry) -> io::Result<()>
+    {
+        SourceFd(&STDIN).deregister(registry)
+    }
+}
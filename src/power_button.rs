@@ -0,0 +1,109 @@
+use std::io;
+use std::os::fd::OwnedFd;
+
+use lithiumd::HookRunner;
+use lithiumd::InhibitMode;
+use lithiumd::Logind;
+use lithiumd::PowerButton;
+use lithiumd::Snapshot;
+use mio::event::Source;
+
+/// What happens when the power button is pressed, from the `[power_button]`
+/// table.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ButtonAction
+{
+    /// Nothing, e.g. on laptops whose button sits where it is hit by
+    /// accident.
+    Ignore,
+    Suspend,
+    /// Run a hook command.
+    Run(String),
+}
+
+/// Takes over the power button from logind, like acpid does.
+pub struct PowerButtonHandler
+{
+    button: PowerButton,
+    logind: Logind,
+    /// Keeps logind from handling the button on its own.
+    _inhibitor: OwnedFd,
+    action: ButtonAction,
+}
+
+impl PowerButtonHandler
+{
+    pub fn new(action: ButtonAction) -> io::Result<Self>
+    {
+        let button = PowerButton::open()?;
+        let mut logind = Logind::new()?;
+        let inhibitor = logind.inhibit(
+            "handle-power-key",
+            "Handles the power button itself",
+            InhibitMode::Block,
+        )?;
+        Ok(Self {
+            button,
+            logind,
+            _inhibitor: inhibitor,
+            action,
+        })
+    }
+
+    #[must_use]
+    pub fn action(&self) -> &ButtonAction
+    {
+        &self.action
+    }
+
+    /// Reads the button and takes the action once for every press.
+    pub fn read(&mut self, hooks: &mut HookRunner, snapshot: &Snapshot) -> io::Result<()>
+    {
+        for _ in 0..self.button.read()? {
+            match &self.action {
+                ButtonAction::Ignore => println!("Power button pressed, ignoring"),
+                ButtonAction::Suspend => {
+                    println!("Power button pressed, suspending");
+                    if let Err(err) = self.logind.suspend() {
+                        eprintln!("Failed to suspend: {}", err);
+                    }
+                }
+                ButtonAction::Run(command) => {
+                    println!("Power button pressed");
+                    if let Err(err) = hooks.run(command, snapshot) {
+                        eprintln!("Failed to run hook `{}`: {}", command, err);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Source for PowerButtonHandler
+{
+    fn register(
+        &mut self,
+        registry: &mio::Registry,
+        token: mio::Token,
+        interests: mio::Interest,
+    ) -> io::Result<()>
+    {
+        self.button.register(registry, token, interests)
+    }
+
+    fn reregister(
+        &mut self,
+        registry: &mio::Registry,
+        token: mio::Token,
+        interests: mio::Interest,
+    ) -> io::Result<()>
+    {
+        self.button.reregister(registry, token, interests)
+    }
+
+    fn deregister(&mut self, registry: &mio::Registry) -> io::Result<()>
+    {
+        self.button.deregister(registry)
+    }
+}
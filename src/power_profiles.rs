@@ -0,0 +1,72 @@
+use std::io;
+
+use crate::dbus::Connection;
+use crate::dbus::Error;
+use crate::dbus::Value;
+
+/// Bus names and object paths of power-profiles-daemon, current and from
+/// before version 0.20.
+const SERVICES: [(&str, &str); 2] = [
+    (
+        "org.freedesktop.UPower.PowerProfiles",
+        "/org/freedesktop/UPower/PowerProfiles",
+    ),
+    ("net.hadess.PowerProfiles", "/net/hadess/PowerProfiles"),
+];
+
+/// Client of power-profiles-daemon, which switches between the
+/// `power-saver`, `balanced` and `performance` profiles.
+pub struct PowerProfiles
+{
+    conn: Connection,
+    /// Index into `SERVICES` of the name the daemon answered on.
+    service: usize,
+}
+
+impl PowerProfiles
+{
+    /// Connects to the system bus and looks for power-profiles-daemon.
+    pub fn new() -> io::Result<Self>
+    {
+        let mut profiles = Self {
+            conn: Connection::system()?,
+            service: 0,
+        };
+        match profiles.active() {
+            Err(err) if Error::is_service_unknown(&err) => {
+                profiles.service = 1;
+                profiles.active()?;
+            }
+            result => {
+                result?;
+            }
+        }
+        Ok(profiles)
+    }
+
+    /// Returns the name of the active profile.
+    pub fn active(&mut self) -> io::Result<String>
+    {
+        let (name, path) = SERVICES[self.service];
+        let value = self.conn.get_property(name, path, name, "ActiveProfile")?;
+        value.as_str().map(str::to_string).ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidData, "ActiveProfile is not a string")
+        })
+    }
+
+    /// Switches to `profile`, unless it is already active.
+    pub fn set_active(&mut self, profile: &str) -> io::Result<()>
+    {
+        if self.active()? == profile {
+            return Ok(());
+        }
+        let (name, path) = SERVICES[self.service];
+        self.conn.set_property(
+            name,
+            path,
+            name,
+            "ActiveProfile",
+            Value::String(profile.to_string()),
+        )
+    }
+}
@@ -1,59 +1,197 @@
+use std::collections::vec_deque;
+use std::collections::BTreeMap;
 use std::ffi::OsStr;
 use std::fmt::Display;
+use std::fs;
 use std::io;
-use std::os::fd::AsRawFd;
+use std::mem;
+use std::os::fd::RawFd;
+use std::time::Duration;
+use std::time::Instant;
 
+#[cfg(feature = "mio")]
 use mio::event::Source;
+#[cfg(feature = "mio")]
 use mio::unix::SourceFd;
 use udev::MonitorSocket;
 
+use crate::backend::BackendDevice;
+use crate::backend::PowerBackend;
+use crate::backend::UdevBackend;
+use crate::clock::Clock;
+use crate::clock::SystemClock;
+use crate::dock::Dock;
+use crate::drain::DrainLimit;
+use crate::drain::DrainMonitor;
+use crate::estimate::Estimator;
+use crate::estimate::Smoothing;
+use crate::event::EventQueue;
+use crate::event::PowerEvent;
+use crate::event::TimedEvent;
+use crate::fan::FanMonitor;
+use crate::metrics::Metrics;
+use crate::mock::MockBackend;
+use crate::session::SessionTimer;
+use crate::session::Sessions;
+use crate::snapshot::Change;
+use crate::snapshot::DeviceSnapshot;
+use crate::snapshot::Snapshot;
+use crate::source::PowerSource;
+use crate::source::SourceKind;
+use crate::sysfs::AttributeDir;
+use crate::thermal::ThermalLimit;
+use crate::thermal::ThermalMonitor;
+use crate::thermal::ThrottleMonitor;
+#[cfg(feature = "io-uring")]
+use crate::uring;
+
+/// How often the attributes of a battery that hardly ever change are read
+/// again without a uevent.
+const SETTINGS_INTERVAL: Duration = Duration::from_secs(60);
+
+/// How many errors reading devices are kept until they are taken.
+const MAX_ERRORS: usize = 32;
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum DeviceType
 {
     /// Battery device.
     Battery,
     /// AC adapter (i.e., the power supply) of a device.
     Adapter,
+    /// Wireless charger, e.g. the Qi coil of a phone, which is online while
+    /// the device lies on a charging pad.
+    Wireless,
+}
+
+impl Display for DeviceType
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result
+    {
+        match *self {
+            DeviceType::Battery => write!(f, "Battery"),
+            DeviceType::Adapter => write!(f, "Adapter"),
+            DeviceType::Wireless => write!(f, "Wireless"),
+        }
+    }
+}
+
+/// What a power supply provides power to, as reported by
+/// `POWER_SUPPLY_SCOPE`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Scope
+{
+    /// Powers the whole system. Drivers that do not report a scope are
+    /// assumed to be system-wide.
+    System,
+    /// Powers a single peripheral, such as the battery of a wireless mouse or
+    /// keyboard.
+    Device,
+}
+
+impl Scope
+{
+    fn of(dev: &BackendDevice) -> Self
+    {
+        match dev.property_value("POWER_SUPPLY_SCOPE") {
+            Some("Device") => Self::Device,
+            _ => Self::System,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Status
 {
     Discharging,
     Charging,
+    /// On AC with the battery full.
+    Full,
+    /// On AC, but the battery does not charge, e.g. because a charge limit
+    /// was reached.
+    NotCharging,
     Unknown,
 }
 
+impl Display for Scope
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result
+    {
+        match *self {
+            Scope::System => write!(f, "System"),
+            Scope::Device => write!(f, "Device"),
+        }
+    }
+}
+
 impl Status
 {
-    fn read_from_battery_device(dev: &udev::Device) -> Self
+    /// Returns whether the system runs on AC power, whether or not the
+    /// battery charges. This is the distinction that statuses used to be
+    /// limited to.
+    #[must_use]
+    pub fn is_on_ac(self) -> bool
     {
-        let status = dev
-            .property_value("POWER_SUPPLY_STATUS")
-            .map(OsStr::to_str)
-            .unwrap_or_default()
-            .unwrap_or_default();
-        match status {
+        matches!(self, Status::Charging | Status::Full | Status::NotCharging)
+    }
+
+    /// Combines the statuses of several batteries: the system is charging if
+    /// any of them charges, discharging if any of them discharges, and only
+    /// full if all of them are.
+    #[must_use]
+    pub fn combine(statuses: impl IntoIterator<Item = Status>) -> Self
+    {
+        let rank = |status| match status {
+            Status::Charging => 4,
+            Status::Discharging => 3,
+            Status::NotCharging => 2,
+            Status::Full => 1,
+            Status::Unknown => 0,
+        };
+        statuses
+            .into_iter()
+            .max_by_key(|status| rank(*status))
+            .unwrap_or(Status::Unknown)
+    }
+
+    /// Combines the status of the adapter with the combined status of the
+    /// batteries. The adapter decides whether the system runs on AC, unless
+    /// a battery charges regardless, and the batteries tell whether they
+    /// charge.
+    fn with_adapter(adapter: Status, batteries: Status) -> Self
+    {
+        match (adapter, batteries) {
+            (_, Status::Charging) => Status::Charging,
+            (Status::Charging, Status::Full | Status::NotCharging) => batteries,
+            (Status::Charging, _) => Status::Charging,
+            (Status::Discharging, _) => Status::Discharging,
+            (_, batteries) => batteries,
+        }
+    }
+
+    fn read_from_battery_device(dev: &mut PowerDevice) -> io::Result<Self>
+    {
+        let status = dev.attributes.read("status")?.unwrap_or_default();
+        Ok(match status {
             "Charging" => Self::Charging,
-            "Discharging" | "Not charging" => Self::Discharging,
+            "Discharging" => Self::Discharging,
+            "Full" => Self::Full,
+            "Not charging" => Self::NotCharging,
             _ => Self::Unknown,
-        }
+        })
     }
 
-    fn read_from_adapter_device(dev: &udev::Device) -> Self
+    fn read_from_adapter_device(dev: &mut PowerDevice) -> io::Result<Self>
     {
-        let online = dev
-            .property_value("POWER_SUPPLY_ONLINE")
-            .unwrap_or_default()
-            .to_str()
-            .unwrap_or_default()
-            .parse::<i32>()
-            .unwrap_or(-1);
-        match online {
+        let online = dev.attributes.read_parsed::<i32>("online")?.unwrap_or(-1);
+        Ok(match online {
             0 => Status::Discharging,
             1 | 2 => Status::Charging,
             _ => Status::Unknown,
-        }
+        })
     }
 }
 
@@ -64,22 +202,438 @@ impl Display for Status
         match *self {
             Status::Discharging => write!(f, "Discharging"),
             Status::Charging => write!(f, "Charging"),
+            Status::Full => write!(f, "Full"),
+            Status::NotCharging => write!(f, "Not charging"),
             Status::Unknown => write!(f, "Unknown"),
         }
     }
 }
 
+/// A power supply device together with its open sysfs directory, so that
+/// refreshing the status does not have to go through udev again.
+struct PowerDevice
+{
+    device: BackendDevice,
+    device_type: DeviceType,
+    scope: Scope,
+    attributes: AttributeDir,
+    /// Status reported by this device the last time it was read.
+    status: Status,
+    /// Capacity in percent the last time it was read, for batteries.
+    capacity: Option<u8>,
+    /// Energy or charge when full, energy or charge left, and power or
+    /// current drawn the last time they were read, for batteries.
+    energy_full: Option<u64>,
+    energy_now: Option<u64>,
+    power_now: Option<u64>,
+    reports_energy: bool,
+    /// Voltage in µV and current in µA the last time they were read, for
+    /// batteries and adapters.
+    voltage_now: Option<u64>,
+    current_now: Option<i64>,
+    /// Capacity in percent at which the firmware warns, for batteries that
+    /// set one.
+    alarm: Option<u8>,
+    /// Charge thresholds in percent, for batteries whose charging stops
+    /// below full.
+    charge_start: Option<u8>,
+    charge_limit: Option<u8>,
+    /// When the energy when full, the alarm and the charge thresholds were
+    /// last read, or `None` to read them on the next refresh.
+    settings_read: Option<Instant>,
+    /// Power in µW drawn from an adapter, and the most since the charger was
+    /// plugged in.
+    input_power: Option<u64>,
+    peak_input_power: Option<u64>,
+    /// The attributes whose last read failed.
+    failing: Vec<&'static str>,
+    /// Errors reading the device not yet taken by the power supply.
+    errors: Vec<io::Error>,
+}
+
+impl PowerDevice
+{
+    fn new(device: BackendDevice, device_type: DeviceType) -> io::Result<Self>
+    {
+        let attributes = AttributeDir::open(&device.syspath)?;
+        Ok(Self {
+            scope: Scope::of(&device),
+            device,
+            device_type,
+            attributes,
+            status: Status::Unknown,
+            capacity: None,
+            energy_full: None,
+            energy_now: None,
+            power_now: None,
+            reports_energy: false,
+            voltage_now: None,
+            current_now: None,
+            alarm: None,
+            charge_start: None,
+            charge_limit: None,
+            settings_read: None,
+            input_power: None,
+            peak_input_power: None,
+            failing: Vec::new(),
+            errors: Vec::new(),
+        })
+    }
+
+    /// Reads the status and capacity of the device again at `now`. The
+    /// attributes that hardly ever change are only read every
+    /// [`SETTINGS_INTERVAL`] and after a uevent for the device, so that a
+    /// wakeup costs few reads. Only a failure to read the status fails; the
+    /// other attributes are then unknown, see [`PowerDevice::optional`].
+    fn refresh(&mut self, now: Instant) -> io::Result<()>
+    {
+        match self.device_type {
+            DeviceType::Battery => {
+                self.status = Status::read_from_battery_device(self)?;
+                self.capacity = self
+                    .optional::<u8>("capacity")
+                    .map(|capacity| capacity.min(100));
+                // Batteries report either energy and power, or charge and
+                // current.
+                self.energy_now = self.optional::<u64>("energy_now");
+                self.reports_energy = self.energy_now.is_some();
+                if !self.reports_energy {
+                    self.energy_now = self.optional::<u64>("charge_now");
+                }
+                self.power_now = self.read_either("power_now", "current_now");
+                self.voltage_now = self.optional::<u64>("voltage_now");
+                // Some drivers report the current while discharging as
+                // negative.
+                self.current_now = self.optional::<i64>("current_now");
+                let stale = self
+                    .settings_read
+                    .is_none_or(|at| now.saturating_duration_since(at) >= SETTINGS_INTERVAL);
+                if stale {
+                    self.read_settings();
+                    self.settings_read = Some(now);
+                }
+            }
+            DeviceType::Adapter | DeviceType::Wireless => {
+                self.status = Status::read_from_adapter_device(self)?;
+                self.read_input_power();
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads the attribute `name`, or returns `None` if the device does not
+    /// have it or fails to read it. Batteries on a flaky embedded controller
+    /// and HID peripherals fail reads with e.g. `EIO` now and then; the first
+    /// failure of each attribute is kept for [`PowerSupply::take_errors`]
+    /// until it reads again.
+    fn optional<T: std::str::FromStr>(&mut self, name: &'static str) -> Option<T>
+    {
+        match self.attributes.read_parsed::<T>(name) {
+            Ok(value) => {
+                self.failing.retain(|failing| *failing != name);
+                value
+            }
+            Err(err) => {
+                self.fail(name, err);
+                None
+            }
+        }
+    }
+
+    /// Keeps the error reading `name`, unless it already failed before.
+    fn fail(&mut self, name: &'static str, err: io::Error)
+    {
+        if self.failing.contains(&name) {
+            return;
+        }
+        self.failing.push(name);
+        self.errors.push(io::Error::new(
+            err.kind(),
+            format!("cannot read `{}` of {}: {}", name, self.device.sysname, err),
+        ));
+    }
+
+    /// Reads the power drawn from an adapter, e.g. from the `power_now` of a
+    /// mains adapter or the voltage and current of a USB-C port, and keeps
+    /// the most while a charger is plugged in.
+    fn read_input_power(&mut self)
+    {
+        self.voltage_now = self.optional::<u64>("voltage_now");
+        self.current_now = self.optional::<i64>("current_now");
+        self.input_power = match self.optional::<u64>("power_now") {
+            Some(power) => Some(power),
+            None => self
+                .voltage_now
+                .zip(self.current_now)
+                .map(|(voltage, current)| voltage * current.unsigned_abs() / 1_000_000),
+        };
+        self.peak_input_power = if self.status == Status::Charging {
+            self.peak_input_power.max(self.input_power)
+        } else {
+            None
+        };
+    }
+
+    fn read_settings(&mut self)
+    {
+        self.energy_full = self.read_either("energy_full", "charge_full");
+        self.alarm = self.read_alarm();
+        self.charge_start = self.optional::<u8>("charge_control_start_threshold");
+        self.charge_limit = self
+            .optional::<u8>("charge_control_end_threshold")
+            .filter(|&end| end < 100);
+    }
+
+    /// Refreshes the device like [`PowerDevice::refresh`], and returns
+    /// `false` if its sysfs directory vanished, e.g. when a battery was
+    /// pulled from its bay and the `remove` uevent is still settling or was
+    /// lost. The device must then not be kept. A device that fails to read
+    /// its status otherwise keeps the one it had, and the error is kept like
+    /// those of [`PowerDevice::optional`].
+    fn refresh_present(&mut self, now: Instant) -> bool
+    {
+        let result = self.refresh(now);
+        #[cfg(feature = "io-uring")]
+        self.attributes.discard_prefetched();
+        let name = match self.device_type {
+            DeviceType::Battery => "status",
+            DeviceType::Adapter | DeviceType::Wireless => "online",
+        };
+        match result {
+            Ok(()) => {
+                self.failing.retain(|failing| *failing != name);
+                true
+            }
+            Err(_) if !self.device.syspath.exists() => false,
+            Err(err) => {
+                self.fail(name, err);
+                true
+            }
+        }
+    }
+
+    /// Reads the alarm threshold of the battery, either `capacity_alert_min`
+    /// in percent or `alarm` in the unit of `energy_now`, which ACPI
+    /// batteries set from the warning capacity of their firmware. Zero means
+    /// that there is none.
+    fn read_alarm(&mut self) -> Option<u8>
+    {
+        if let Some(alert) = self.optional::<u8>("capacity_alert_min") {
+            return Some(alert.min(100)).filter(|&alert| alert > 0);
+        }
+        let alarm = self.optional::<u64>("alarm");
+        alarm
+            .zip(self.energy_full)
+            .filter(|&(alarm, full)| alarm > 0 && full > 0)
+            .map(|(alarm, full)| (alarm * 100).div_ceil(full).min(100) as u8)
+    }
+
+    /// Returns whether the battery is held at its charge limit, which some
+    /// drivers report as `Unknown`, or as `Discharging` without drawing any
+    /// power, rather than `Not charging`. Only meaningful on AC.
+    fn is_charge_limited(&self) -> bool
+    {
+        let Some(end) = self.charge_limit else {
+            return false;
+        };
+        let idle = match self.status {
+            Status::NotCharging | Status::Unknown => true,
+            Status::Discharging => self.power_now == Some(0),
+            Status::Charging | Status::Full => false,
+        };
+        // Charging resumes below the start threshold, or a few percent below
+        // the end threshold without one.
+        let start = self.charge_start.unwrap_or(end.saturating_sub(5));
+        idle && self.capacity.is_some_and(|capacity| capacity >= start)
+    }
+
+    fn read_either(&mut self, name: &'static str, fallback: &'static str) -> Option<u64>
+    {
+        match self.optional::<u64>(name) {
+            Some(value) => Some(value),
+            None => self.optional::<u64>(fallback),
+        }
+    }
+
+    fn snapshot(&self) -> DeviceSnapshot
+    {
+        DeviceSnapshot {
+            sysname: self.device.sysname.clone(),
+            model: self
+                .device
+                .property_value("POWER_SUPPLY_MODEL_NAME")
+                .map(str::to_string),
+            device_type: self.device_type,
+            scope: self.scope,
+            status: self.status,
+            capacity: self.capacity,
+            energy_full: self.energy_full,
+            energy_now: self.energy_now,
+            power_now: self.power_now,
+            reports_energy: self.reports_energy,
+            voltage_now: self.voltage_now,
+            current_now: self.current_now,
+            alarm: self.alarm,
+            charge_limit: self.charge_limit,
+            input_power: self.input_power,
+            peak_input_power: self.peak_input_power,
+        }
+    }
+
+    /// Returns the udev property `name`, e.g. `POWER_SUPPLY_CYCLE_COUNT`, or
+    /// else the current value of the sysfs attribute `name`, e.g.
+    /// `cycle_count`. This reaches vendor specific attributes that are not
+    /// modelled otherwise.
+    fn property(&self, name: &str) -> Option<String>
+    {
+        if let Some(value) = self.device.property_value(name) {
+            return Some(value.to_string());
+        }
+        read_attribute(&self.device, OsStr::new(name))
+    }
+
+    /// Returns all udev properties and the current values of all readable
+    /// sysfs attributes of the device. Properties are upper case, so they
+    /// never clash with attributes.
+    fn properties(&self) -> BTreeMap<String, String>
+    {
+        // The attribute values cached by libudev may be stale, so only the
+        // names are taken from it.
+        let attributes = self
+            .device
+            .attribute_names()
+            .into_iter()
+            .filter_map(|name| {
+                let value = read_attribute(&self.device, &name)?;
+                Some((name.to_string_lossy().into_owned(), value))
+            });
+        self.device
+            .properties
+            .clone()
+            .into_iter()
+            .chain(attributes)
+            .collect()
+    }
+
+    /// Replaces the device with a newer one received from the monitor. The
+    /// sysfs directory is only reopened if the device moved.
+    fn replace(&mut self, device: BackendDevice) -> io::Result<()>
+    {
+        if device.syspath != self.device.syspath {
+            self.attributes = AttributeDir::open(&device.syspath)?;
+        }
+        self.device = device;
+        self.settings_read = None;
+        Ok(())
+    }
+}
+
+/// What [`PowerSupply::restore`] carries on from, as saved by a previous run
+/// with [`PowerSupply::saved_state`], so that a restarted daemon does not
+/// report the status it had already reported again and keeps counting the
+/// sessions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SavedState
+{
+    /// The last reported overall charging status.
+    pub status: Status,
+    /// The capacity last reported by [`PowerEvent::CapacityChanged`].
+    pub capacity: Option<u8>,
+    pub sessions: Option<Sessions>,
+}
+
 pub struct PowerSupply
 {
-    socket: Option<udev::MonitorSocket>,
+    udev: UdevBackend,
+    /// Read instead of udev if set.
+    backend: Option<Box<dyn PowerBackend>>,
+    /// Where the backend is registered, to register its new file descriptor
+    /// in place of the old one after it failed.
+    #[cfg(feature = "mio")]
+    registration: Option<(mio::Registry, mio::Token, mio::Interest)>,
 
-    bat: Option<udev::Device>,
-    adp: Option<udev::Device>,
+    /// System batteries. Some laptops, e.g. ThinkPads, have two of them.
+    batteries: Vec<PowerDevice>,
+    /// Adapters, USB ports and wireless chargers, of which the one that
+    /// powers the system is chosen by `source_priority`.
+    adapters: Vec<PowerDevice>,
+    source_priority: Vec<SourceKind>,
+    /// Batteries of peripherals, tracked separately in peripheral monitoring
+    /// mode.
+    peripherals: Vec<PowerDevice>,
+    /// Whether the cached devices were enumerated, and since kept up to date
+    /// by uevents.
+    enumerated: bool,
+    /// Devices that do not come from udev, by the source that reported them.
+    external: BTreeMap<String, Vec<DeviceSnapshot>>,
 
     status: Status,
     status_changed: bool,
+
+    /// The state that the queued events lead up to.
+    reported: Snapshot,
+    events: EventQueue,
+    /// When the last [`Change::Status`] was queued.
+    last_transition: Option<Instant>,
+    /// How often the batteries are read again while discharging, without
+    /// waiting for a uevent.
+    refresh_interval: Option<Duration>,
+    /// When the devices were last read.
+    last_refresh: Option<Instant>,
+
+    debounce: Duration,
+    /// Set while a burst of uevents is still settling; the status is not
+    /// recomputed before this instant.
+    settle_deadline: Option<Instant>,
+    /// Arrival of the first uevent of the burst that is being settled, and the
+    /// number of uevents received since.
+    burst_start: Option<Instant>,
+    burst_uevents: u64,
+
+    metrics: Metrics,
+    clock: Box<dyn Clock>,
+
+    include_device_scope: bool,
+    /// Glob patterns pinning the battery and the adapter by sysname.
+    battery_pattern: Option<String>,
+    adapter_pattern: Option<String>,
+    /// udev properties and sysfs attributes, by name, that devices must have
+    /// the given values of to be tracked at all.
+    property_matches: Vec<(String, String)>,
+    attribute_matches: Vec<(String, String)>,
+    /// udev tags that devices must carry to be tracked.
+    tag_matches: Vec<String>,
+    monitor_peripherals: bool,
+    peripheral_low_capacity: u8,
+    drain: Option<DrainMonitor>,
+    thermal: Option<ThermalMonitor>,
+    fans: Option<FanMonitor>,
+    throttling: Option<ThrottleMonitor>,
+    capacity_step: u8,
+    /// The combined capacity last reported by
+    /// [`PowerEvent::CapacityChanged`].
+    reported_capacity: Option<u8>,
+    estimator: Estimator,
+    sessions: SessionTimer,
+    /// Attached docks, while they are monitored.
+    docks: Option<Vec<Dock>>,
+    detect_weak_charger: bool,
+    /// Energies in µWh that [`PowerEvent::EnergyBudget`] is emitted at.
+    energy_budgets: Vec<u64>,
+    /// Errors reading devices, see [`PowerSupply::take_errors`].
+    errors: Vec<io::Error>,
+    /// Set once [`PowerEvent::WeakCharger`] was emitted, until the batteries
+    /// stop discharging or the adapter goes offline.
+    weak_charger: bool,
+
+    /// Used to read the attributes of all devices at once. `None` if the
+    /// kernel does not support io_uring or it is disabled.
+    #[cfg(feature = "io-uring")]
+    ring: Option<uring::Ring>,
 }
 
+#[cfg(feature = "mio")]
 impl Source for PowerSupply
 {
     fn register(
@@ -89,7 +643,9 @@ impl Source for PowerSupply
         interests: mio::Interest,
     ) -> io::Result<()>
     {
-        SourceFd(&self.monitor_socket()?.as_raw_fd()).register(registry, token, interests)
+        SourceFd(&self.monitor_fd()?).register(registry, token, interests)?;
+        self.registration = Some((registry.try_clone()?, token, interests));
+        Ok(())
     }
 
     fn reregister(
@@ -99,12 +655,15 @@ impl Source for PowerSupply
         interests: mio::Interest,
     ) -> io::Result<()>
     {
-        SourceFd(&self.monitor_socket()?.as_raw_fd()).reregister(registry, token, interests)
+        SourceFd(&self.monitor_fd()?).reregister(registry, token, interests)?;
+        self.registration = Some((registry.try_clone()?, token, interests));
+        Ok(())
     }
 
     fn deregister(&mut self, registry: &mio::Registry) -> io::Result<()>
     {
-        SourceFd(&self.monitor_socket()?.as_raw_fd()).deregister(registry)
+        self.registration = None;
+        SourceFd(&self.monitor_fd()?).deregister(registry)
     }
 }
 
@@ -114,23 +673,608 @@ impl PowerSupply
     pub fn new() -> Self
     {
         Self {
-            socket: None,
-            bat: None,
-            adp: None,
+            udev: UdevBackend::new(),
+            backend: None,
+            #[cfg(feature = "mio")]
+            registration: None,
+            batteries: Vec::new(),
+            adapters: Vec::new(),
+            source_priority: SourceKind::DEFAULT_PRIORITY.to_vec(),
+            peripherals: Vec::new(),
+            enumerated: false,
+            external: BTreeMap::new(),
             status: Status::Unknown,
             status_changed: true,
+            reported: Snapshot::default(),
+            events: EventQueue::new(Instant::now()),
+            last_transition: None,
+            refresh_interval: None,
+            last_refresh: None,
+            debounce: Duration::ZERO,
+            settle_deadline: None,
+            burst_start: None,
+            burst_uevents: 0,
+            metrics: Metrics::default(),
+            clock: Box::new(SystemClock),
+            include_device_scope: false,
+            battery_pattern: None,
+            adapter_pattern: None,
+            property_matches: Vec::new(),
+            attribute_matches: Vec::new(),
+            tag_matches: Vec::new(),
+            monitor_peripherals: false,
+            peripheral_low_capacity: 20,
+            drain: None,
+            thermal: None,
+            fans: None,
+            throttling: None,
+            capacity_step: 5,
+            reported_capacity: None,
+            estimator: Estimator::new(Smoothing::default()),
+            sessions: SessionTimer::default(),
+            docks: None,
+            detect_weak_charger: false,
+            energy_budgets: Vec::new(),
+            errors: Vec::new(),
+            weak_charger: false,
+            #[cfg(feature = "io-uring")]
+            ring: uring::Ring::new(8).ok(),
+        }
+    }
+
+    /// Uses a monitor socket made by the application instead of opening one,
+    /// e.g. by a compositor that already listens to uevents, so that no second
+    /// netlink socket is opened. The socket should match the `power_supply`
+    /// subsystem: uevents of other devices are received and dropped.
+    #[must_use]
+    pub fn with_monitor(socket: MonitorSocket) -> Self
+    {
+        Self {
+            udev: UdevBackend::with_socket(socket),
+            ..Self::new()
+        }
+    }
+
+    /// Reads the devices of `mock` instead of udev.
+    #[must_use]
+    pub fn with_mock(mock: MockBackend) -> Self
+    {
+        Self::with_backend(Box::new(mock))
+    }
+
+    /// Reads the devices of `backend` instead of udev, e.g. a
+    /// [`SysfsPoller`](crate::SysfsPoller) where there is no netlink. Docks
+    /// are only monitored through udev.
+    #[must_use]
+    pub fn with_backend(backend: Box<dyn PowerBackend>) -> Self
+    {
+        Self {
+            backend: Some(backend),
+            ..Self::new()
         }
     }
 
+    /// Reads the state once, with a single enumeration and pass over sysfs,
+    /// for prompts and one-off queries:
+    ///
+    /// ```no_run
+    /// let snapshot = lithiumd::PowerSupply::read_once()?;
+    /// println!("{:?}", snapshot.capacity());
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    ///
+    /// No monitor socket is opened, so this also works in sandboxes without
+    /// netlink access.
+    pub fn read_once() -> io::Result<Snapshot>
+    {
+        let mut power_supply = Self::new();
+        power_supply.refresh()?;
+        Ok(power_supply.snapshot())
+    }
+
+    /// Replaces the clock used for debouncing, e.g. with a
+    /// [`MockClock`](crate::MockClock) to test it without waiting.
+    pub fn set_clock(&mut self, clock: impl Clock + 'static)
+    {
+        self.clock = Box::new(clock);
+    }
+
+    /// Sets how long the power supply has to be quiet after a uevent before
+    /// the status is recomputed. Devices such as USB-C chargers emit a burst of
+    /// uevents when plugged in, and only the settled state is of interest.
+    pub fn set_debounce(&mut self, debounce: Duration)
+    {
+        self.debounce = debounce;
+    }
+
+    /// Sets whether power supplies with [`Scope::Device`], i.e. batteries of
+    /// peripherals, take part in device selection. They are ignored by
+    /// default so that a wireless mouse cannot take the place of the laptop
+    /// battery.
+    pub fn set_include_device_scope(&mut self, include: bool)
+    {
+        self.include_device_scope = include;
+    }
+
+    /// Pins the batteries and the adapter that drive the charging status to
+    /// the devices whose sysname matches the glob patterns, e.g. `BAT1` or
+    /// `ADP*`. Pinned devices are used whatever their name or scope, which
+    /// helps with oddly named supplies; other devices of the same type are
+    /// ignored. `None` leaves the selection to the usual heuristics.
+    ///
+    /// Takes effect the next time the devices are enumerated.
+    pub fn set_device_patterns(&mut self, battery: Option<String>, adapter: Option<String>)
+    {
+        self.battery_pattern = battery;
+        self.adapter_pattern = adapter;
+    }
+
+    /// Only tracks devices whose udev property `name` has the value `value`,
+    /// e.g. `match_property("POWER_SUPPLY_NAME", "BAT0")`, so that other
+    /// devices are neither enumerated nor reported. Devices must pass every
+    /// match that was added.
+    ///
+    /// Takes effect the next time the devices are enumerated, and for
+    /// uevents right away.
+    pub fn match_property(&mut self, name: impl Into<String>, value: impl Into<String>)
+    {
+        self.property_matches.push((name.into(), value.into()));
+    }
+
+    /// Only tracks devices whose sysfs attribute `name` has the value
+    /// `value`, e.g. `match_sysattr("type", "Battery")`, like
+    /// [`PowerSupply::match_property`].
+    pub fn match_sysattr(&mut self, name: impl Into<String>, value: impl Into<String>)
+    {
+        self.attribute_matches.push((name.into(), value.into()));
+    }
+
+    /// Only tracks devices that udev rules tagged with `tag`, e.g. with
+    /// `TAG+="lithiumd"`, for setups where the admin classifies the devices.
+    /// The monitor socket filters on the tag in the kernel, so docks are
+    /// only monitored if they carry it too.
+    ///
+    /// Takes effect for monitor sockets opened afterwards and the next time
+    /// the devices are enumerated, and for uevents right away.
+    pub fn match_tag(&mut self, tag: impl Into<String>)
+    {
+        let tag = tag.into();
+        self.udev.match_tag(tag.clone());
+        self.tag_matches.push(tag);
+    }
+
+    /// Sets the size in bytes of the receive buffer of the monitor socket,
+    /// for systems where storms of uevents overrun it, e.g. with some USB-C
+    /// docks. Root may exceed `net.core.rmem_max`, others are capped at it.
+    /// `None` keeps the buffer as it is.
+    ///
+    /// Takes effect right away if the socket is open, and for sockets opened
+    /// later.
+    pub fn set_receive_buffer(&mut self, size: Option<usize>) -> io::Result<()>
+    {
+        self.udev.set_receive_buffer(size)
+    }
+
+    /// Enables peripheral monitoring mode, in which batteries of peripherals
+    /// such as mice, keyboards and gamepads are tracked as devices of their
+    /// own instead of being ignored. [`PowerEvent::LowBattery`] is emitted
+    /// when one of them drops to `low_capacity` percent.
+    ///
+    /// Takes effect the next time the devices are enumerated.
+    pub fn set_monitor_peripherals(&mut self, monitor: bool, low_capacity: u8)
+    {
+        self.monitor_peripherals = monitor;
+        self.peripheral_low_capacity = low_capacity;
+    }
+
+    /// Sets the step in percent that [`PowerEvent::CapacityChanged`] is
+    /// emitted at: with a step of 5, only when the combined capacity crosses
+    /// 95%, 90% and so on, so that consumers are not woken on every 1% tick.
+    /// The step is at least 1.
+    pub fn set_capacity_step(&mut self, step: u8)
+    {
+        self.capacity_step = step.max(1);
+    }
+
+    /// Reads the thermal zones periodically, emitting
+    /// [`PowerEvent::Overheated`] when one reaches the limit and
+    /// [`PowerEvent::CooledDown`] once it cooled down. `None` stops
+    /// monitoring.
+    pub fn set_thermal_limit(&mut self, limit: Option<ThermalLimit>)
+    {
+        self.thermal = limit.map(ThermalMonitor::new);
+    }
+
+    /// Reads the thermal throttle counters of the CPUs periodically,
+    /// emitting [`PowerEvent::ThrottlingStarted`] when they go up and
+    /// [`PowerEvent::ThrottlingStopped`] once they stay put, so that slowdowns
+    /// can be told apart from those of a power profile. Only x86 CPUs have
+    /// the counters.
+    pub fn set_monitor_throttling(&mut self, monitor: bool)
+    {
+        if monitor != self.throttling.is_some() {
+            self.throttling = monitor.then(ThrottleMonitor::default);
+        }
+    }
+
+    /// Reads the fans periodically, emitting [`PowerEvent::FanStarted`] and
+    /// [`PowerEvent::FanStopped`] when one turns on or off, e.g. to tell
+    /// what the power draws while idle.
+    pub fn set_monitor_fans(&mut self, monitor: bool)
+    {
+        if monitor != self.fans.is_some() {
+            self.fans = monitor.then(FanMonitor::default);
+        }
+    }
+
+    /// Monitors the drain of the system batteries while on battery, emitting
+    /// [`PowerEvent::HighDrain`] when it exceeds `limit`. The batteries are
+    /// then read periodically, see [`PowerSupply::timeout`]. `None` stops
+    /// monitoring.
+    pub fn set_drain_limit(&mut self, limit: Option<DrainLimit>)
+    {
+        self.drain = limit.map(DrainMonitor::new);
+    }
+
+    /// Sets whether [`PowerEvent::WeakCharger`] is emitted when an adapter is
+    /// online but the system batteries keep discharging, because the charger
+    /// delivers less than the system draws.
+    pub fn set_detect_weak_charger(&mut self, detect: bool)
+    {
+        self.detect_weak_charger = detect;
+        self.weak_charger = false;
+    }
+
+    /// Emits [`PowerEvent::EnergyBudget`] when the energy left in the system
+    /// batteries drops below one of `budgets`, in µWh, while discharging,
+    /// e.g. `20_000_000` for the 20 Wh a commute takes.
+    pub fn set_energy_budgets(&mut self, budgets: Vec<u64>)
+    {
+        self.energy_budgets = budgets;
+    }
+
+    /// Sets whether docking stations are monitored, emitting
+    /// [`PowerEvent::Docked`] and [`PowerEvent::Undocked`] when one is
+    /// attached or detached. Docks are recognised as Thunderbolt and USB4
+    /// devices, whose uevents the monitor socket receives as well. Fails if
+    /// the docks attached already cannot be enumerated.
+    pub fn set_monitor_docks(&mut self, monitor: bool) -> io::Result<()>
+    {
+        match (monitor, &self.docks) {
+            (true, None) => self.docks = Some(Dock::scan()?),
+            (false, Some(_)) => self.docks = None,
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Returns the attached docks, if they are monitored.
+    #[must_use]
+    pub fn docks(&self) -> &[Dock]
+    {
+        self.docks.as_deref().unwrap_or_default()
+    }
+
+    /// Sets how the power readings behind [`PowerSupply::time_remaining`]
+    /// are smoothed. Readings taken so far are dropped.
+    pub fn set_smoothing(&mut self, smoothing: Smoothing)
+    {
+        self.estimator = Estimator::new(smoothing);
+    }
+
+    #[must_use]
+    pub fn smoothing(&self) -> Smoothing
+    {
+        self.estimator.smoothing()
+    }
+
+    /// Returns how long the system batteries last at the smoothed power
+    /// draw, or take to charge fully while charging, see [`Estimator`].
+    #[must_use]
+    pub fn time_remaining(&self) -> Option<Duration>
+    {
+        self.estimator.time_remaining(&self.snapshot())
+    }
+
+    /// Processes pending uevents and refreshes the charging status.
+    ///
+    /// While the debounce window is open the status is left untouched and
+    /// [`PowerSupply::timeout`] reports when `update` has to be called again.
+    ///
+    /// It is safe to call at any time, also when no uevents are pending, e.g.
+    /// after a spurious wakeup or to refresh the status on a timer: the
+    /// socket is non-blocking and read until it would block, retrying reads
+    /// interrupted by signals.
+    ///
+    /// If the monitor socket fails, a new one is opened and registered with
+    /// the token and interest of the old one, and the devices are enumerated
+    /// again, so callers do not have to rebuild the power supply.
     pub fn update(&mut self) -> io::Result<()>
     {
-        self.monitor_socket()?
-            .iter()
-            .for_each(|event| self.set_device(event.device()));
+        let (devices, overrun) = self.receive()?;
+        let received = overrun || !devices.is_empty();
+        let now = self.clock.now();
+        self.metrics.uevents += devices.len() as u64;
+        self.metrics.max_queue_depth = self.metrics.max_queue_depth.max(devices.len());
+        if received {
+            self.burst_start.get_or_insert(now);
+            self.burst_uevents += devices.len() as u64;
+        }
+        if overrun {
+            // Some uevents were dropped by the kernel, so the cached devices
+            // cannot be trusted anymore. They are enumerated again when the
+            // status is refreshed.
+            self.metrics.overruns += 1;
+            self.forget_devices();
+        } else {
+            for dev in devices {
+                self.set_device(dev)?;
+            }
+        }
+
+        if received && !self.debounce.is_zero() {
+            self.settle_deadline = Some(now + self.debounce);
+        }
+        match self.settle_deadline {
+            Some(deadline) if now < deadline => {
+                self.status_changed = false;
+                return Ok(());
+            }
+            _ => self.settle_deadline = None,
+        }
+
         self.current_charging_status()?;
+        if let Some(start) = self.burst_start.take() {
+            self.metrics
+                .settle_latency
+                .record(self.clock.now().saturating_duration_since(start));
+            self.metrics.coalesced += self.burst_uevents.saturating_sub(1);
+            self.burst_uevents = 0;
+        }
         Ok(())
     }
 
+    /// Returns the events that occurred since the last call, oldest first.
+    pub fn events(&mut self) -> impl Iterator<Item = PowerEvent> + '_
+    {
+        self.events.drain().map(|timed| timed.event)
+    }
+
+    /// Like [`PowerSupply::events`], but with the sequence number and the
+    /// time of each event.
+    pub fn timed_events(&mut self) -> vec_deque::Drain<'_, TimedEvent>
+    {
+        self.events.drain()
+    }
+
+    /// Returns when the overall charging status last changed, by the clock
+    /// of the power supply. `None` until it is reported for the first time.
+    #[must_use]
+    pub fn last_transition_time(&self) -> Option<Instant>
+    {
+        self.last_transition
+    }
+
+    /// Returns the errors reading single devices since the last call, to be
+    /// logged. They leave the attribute unknown, or the device at its last
+    /// status, and are reported once until the attribute reads again.
+    pub fn take_errors(&mut self) -> Vec<io::Error>
+    {
+        std::mem::take(&mut self.errors)
+    }
+
+    /// Returns the counters collected while processing uevents.
+    #[must_use]
+    pub fn metrics(&self) -> &Metrics
+    {
+        &self.metrics
+    }
+
+    /// Returns the time left until the current burst of uevents is considered
+    /// settled, or until the drain, the thermal zones, the fans or the
+    /// throttle counters have to be sampled, or until the batteries have to
+    /// be read again, see [`PowerSupply::set_refresh_interval`], or until the
+    /// backend has to be asked for changes, to be used as the timeout of the
+    /// poll. `None` if no update is pending.
+    #[must_use]
+    pub fn timeout(&self) -> Option<Duration>
+    {
+        let sample = self.drain.as_ref().and_then(DrainMonitor::next_sample);
+        let thermal = self.thermal.as_ref().and_then(ThermalMonitor::next_sample);
+        let fans = self.fans.as_ref().and_then(FanMonitor::next_sample);
+        let throttling = self
+            .throttling
+            .as_ref()
+            .and_then(ThrottleMonitor::next_sample);
+        let backend = match &self.backend {
+            Some(backend) => backend.timeout(),
+            None => self.udev.timeout(),
+        };
+        let refresh = self
+            .refresh_interval
+            .filter(|_| self.status == Status::Discharging)
+            .zip(self.last_refresh)
+            .map(|(interval, at)| at + interval);
+        [
+            self.settle_deadline,
+            sample,
+            thermal,
+            fans,
+            throttling,
+            refresh,
+        ]
+        .into_iter()
+        .flatten()
+        .min()
+        .map(|deadline| deadline.saturating_duration_since(self.clock.now()))
+        .into_iter()
+        .chain(backend)
+        .min()
+    }
+
+    /// Returns how long the system has been on AC or on battery, since the
+    /// last switch and in total. `None` until the status is known.
+    #[must_use]
+    pub fn sessions(&self) -> Option<Sessions>
+    {
+        self.sessions.sessions(self.clock.now())
+    }
+
+    /// Reads the charge thresholds and the other attributes of batteries
+    /// that hardly ever change on the next update, instead of when they are
+    /// due, e.g. after writing the thresholds.
+    pub fn reread_settings(&mut self)
+    {
+        for bat in &mut self.batteries {
+            bat.settings_read = None;
+        }
+    }
+
+    /// Reads the batteries again every `interval` while the system runs on
+    /// battery, for hardware that sends no uevents as the capacity drops, so
+    /// that the capacity and the time remaining stay fresh. The deadline is
+    /// part of [`PowerSupply::timeout`], so no timer has to be polled, and
+    /// none is armed while on AC. `None`, the default, only reads on uevents.
+    pub fn set_refresh_interval(&mut self, interval: Option<Duration>)
+    {
+        self.refresh_interval = interval;
+    }
+
+    /// Sets the kinds of sources that power the system, most preferred first.
+    /// Of several adapters that are online, e.g. a barrel adapter and a
+    /// USB-C charger, the one of the first kind powers the system and is
+    /// reported in [`Snapshot::source`]. Kinds that are left out are tracked
+    /// as devices but do not count as AC, e.g. a wireless pad that merely
+    /// trickles. Defaults to [`SourceKind::DEFAULT_PRIORITY`].
+    pub fn set_source_priority(&mut self, priority: Vec<SourceKind>)
+    {
+        self.source_priority = priority;
+    }
+
+    /// Returns the state to save for [`PowerSupply::restore`].
+    #[must_use]
+    pub fn saved_state(&self) -> SavedState
+    {
+        SavedState {
+            status: self.reported.status,
+            capacity: self.reported_capacity,
+            sessions: self.sessions(),
+        }
+    }
+
+    /// Carries on from `state`, saved `elapsed` ago, before the first update.
+    /// A status and capacity that did not change meanwhile are not reported
+    /// again, and the time in between counts towards the saved session.
+    pub fn restore(&mut self, state: SavedState, elapsed: Duration)
+    {
+        self.status = state.status;
+        self.status_changed = false;
+        self.reported.status = state.status;
+        self.reported_capacity = state.capacity;
+        if let Some(sessions) = state.sessions {
+            self.sessions.restore(sessions, elapsed, self.clock.now());
+        }
+    }
+
+    /// Returns a raw property of the device `sysname`: a udev property such as
+    /// `POWER_SUPPLY_MANUFACTURER`, or else a sysfs attribute such as
+    /// `cycle_count`, read when called. `None` if there is no such device or
+    /// property.
+    #[must_use]
+    pub fn device_property(&self, sysname: &str, name: &str) -> Option<String>
+    {
+        self.device(sysname)?.property(name)
+    }
+
+    /// Returns all udev properties and sysfs attributes of the device
+    /// `sysname`, see [`PowerSupply::device_property`].
+    #[must_use]
+    pub fn device_properties(&self, sysname: &str) -> Option<BTreeMap<String, String>>
+    {
+        Some(self.device(sysname)?.properties())
+    }
+
+    fn device(&self, sysname: &str) -> Option<&PowerDevice>
+    {
+        self.batteries
+            .iter()
+            .chain(&self.adapters)
+            .chain(&self.peripherals)
+            .find(|dev| dev.device.sysname == sysname)
+    }
+
+    /// Returns the current state of the power supply and its devices. Compare
+    /// it to an earlier one with [`Snapshot::diff`] to find out what changed.
+    #[must_use]
+    pub fn snapshot(&self) -> Snapshot
+    {
+        Snapshot {
+            status: self.status,
+            devices: self
+                .batteries
+                .iter()
+                .chain(&self.adapters)
+                .chain(&self.peripherals)
+                .map(PowerDevice::snapshot)
+                .chain(self.external.values().flatten().cloned())
+                .collect(),
+            source: self.source().map(|adapter| PowerSource {
+                sysname: adapter.device.sysname.clone(),
+                kind: SourceKind::of(&adapter.device),
+            }),
+        }
+    }
+
+    /// Replaces the devices reported by `source`, which is not udev, e.g. the
+    /// Bluetooth devices known to [`Bluez`](crate::Bluez). They take part in
+    /// snapshots and events like any other device but never in the charging
+    /// status.
+    pub fn set_external_devices(&mut self, source: &str, devices: Vec<DeviceSnapshot>)
+    {
+        if devices.is_empty() {
+            self.external.remove(source);
+        } else {
+            self.external.insert(source.to_string(), devices);
+        }
+        self.queue_events();
+    }
+
+    /// Reads the attributes of all devices again without waiting for a uevent.
+    /// Batteries do not send uevents for every change of their energy or
+    /// power draw.
+    pub fn refresh(&mut self) -> io::Result<()>
+    {
+        self.current_charging_status()
+    }
+
+    /// Drops the cached devices and enumerates them again before refreshing
+    /// the charging status. If that fails, the devices found before are kept.
+    pub fn rescan(&mut self) -> io::Result<()>
+    {
+        let batteries = mem::take(&mut self.batteries);
+        let adapters = mem::take(&mut self.adapters);
+        let peripherals = mem::take(&mut self.peripherals);
+        let enumerated = mem::replace(&mut self.enumerated, false);
+        let result = self.current_charging_status();
+        if result.is_err() {
+            self.batteries = batteries;
+            self.adapters = adapters;
+            self.peripherals = peripherals;
+            self.enumerated = enumerated;
+        }
+        result
+    }
+
+    /// Reports [`PowerEvent::Resumed`] and enumerates the devices again,
+    /// since their cached state is stale after a suspend, e.g. if the
+    /// charger was plugged in meanwhile.
+    pub fn resumed(&mut self) -> io::Result<()>
+    {
+        self.events.set_time(self.clock.now());
+        self.events.push_back(PowerEvent::Resumed);
+        self.rescan()
+    }
+
     #[must_use]
     pub fn charging_status_changed(&self) -> bool
     {
@@ -146,68 +1290,336 @@ impl PowerSupply
 
     fn enumerate(&mut self) -> io::Result<()>
     {
-        let mut enumerator = udev::Enumerator::new()?;
-        enumerator.match_subsystem("power_supply")?;
-        let devices = enumerator.scan_devices()?.collect::<Vec<_>>();
+        let devices = self.backend().enumerate()?;
+        let (peripherals, devices) = devices
+            .into_iter()
+            .filter(|dev| self.selected(dev) && (self.accepts(dev) || self.is_peripheral(dev)))
+            .partition::<Vec<_>, _>(|dev| self.is_peripheral(dev));
+        for dev in peripherals {
+            self.set_peripheral(dev)?;
+        }
 
-        assert!(
-            devices.len() == 2,
-            "Failed to find two power supply devices!"
-        );
+        // Desktops, VMs and containers have neither a battery nor an
+        // adapter, and are left with the status unknown.
+        for dev in devices {
+            self.set_device(dev)?;
+        }
+        Ok(())
+    }
 
-        devices.into_iter().for_each(|dev| self.set_device(dev));
+    /// Receives the pending changes of the backend. The second value is
+    /// `true` if changes were lost, e.g. because the socket buffer of udev
+    /// overflowed.
+    ///
+    /// If the backend replaced its file descriptor, the new one is
+    /// registered with the token and interest of the old one, and the cached
+    /// devices are dropped so that changes missed meanwhile do not matter.
+    fn receive(&mut self) -> io::Result<(Vec<BackendDevice>, bool)>
+    {
+        let uevents = self.backend().drain_events()?;
+        let _fd = self.monitor_fd()?;
+        if uevents.reconnected {
+            #[cfg(feature = "mio")]
+            if let Some((registry, token, interests)) = &self.registration {
+                SourceFd(&_fd).register(registry, *token, *interests)?;
+            }
+            self.metrics.reconnects += 1;
+            self.forget_devices();
+        }
+        for (dock, attached) in uevents.docks {
+            self.dock_event(dock, attached);
+        }
+        Ok((uevents.devices, uevents.overrun))
+    }
 
-        Ok(())
+    /// Tracks a dock being attached or detached.
+    fn dock_event(&mut self, dock: Dock, attached: bool)
+    {
+        let Some(docks) = &mut self.docks else {
+            return;
+        };
+        let known = docks.iter().position(|known| known.sysname == dock.sysname);
+        self.events.set_time(self.clock.now());
+        match (attached, known) {
+            (true, None) => {
+                docks.push(dock.clone());
+                self.events.push_back(PowerEvent::Docked(dock));
+            }
+            (false, Some(i)) => {
+                // The device is gone, so its name is only known from before.
+                let dock = docks.remove(i);
+                self.events.push_back(PowerEvent::Undocked(dock));
+            }
+            _ => {}
+        }
+    }
+
+    /// Returns the file descriptor of the backend, opening it if it is not
+    /// yet, to be polled by an event loop other than mio. It changes if the
+    /// backend replaced it, e.g. after the udev monitor socket failed, so it
+    /// is best fetched again after each [`PowerSupply::process_pending`].
+    pub fn monitor_fd(&mut self) -> io::Result<RawFd>
+    {
+        self.backend().fd()
+    }
+
+    /// Reads what is pending, like [`PowerSupply::update`], and returns the
+    /// events. Together with [`PowerSupply::monitor_fd`] and
+    /// [`PowerSupply::timeout`] it drives the power supply from any
+    /// level-triggered loop, e.g. poll(2), epoll or a GLib main loop:
+    ///
+    /// ```no_run
+    /// use lithiumd::PowerSupply;
+    ///
+    /// let mut power_supply = PowerSupply::new();
+    /// loop {
+    ///     for event in power_supply.process_pending()? {
+    ///         println!("{}", event);
+    ///     }
+    ///     let mut fds = [libc::pollfd {
+    ///         fd: power_supply.monitor_fd()?,
+    ///         events: libc::POLLIN,
+    ///         revents: 0,
+    ///     }];
+    ///     let timeout = power_supply
+    ///         .timeout()
+    ///         .map_or(-1, |timeout| timeout.as_millis() as i32 + 1);
+    ///     unsafe { libc::poll(fds.as_mut_ptr(), 1, timeout) };
+    /// }
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    pub fn process_pending(&mut self) -> io::Result<Vec<PowerEvent>>
+    {
+        self.update()?;
+        Ok(self.events().collect())
+    }
+
+    /// Returns the backend the devices are read from: udev unless another
+    /// one was given.
+    fn backend(&mut self) -> &mut dyn PowerBackend
+    {
+        match &mut self.backend {
+            Some(backend) => backend.as_mut(),
+            None => &mut self.udev,
+        }
+    }
+
+    /// Returns whether `dev` passes the property, attribute and tag
+    /// matches. The monitor socket cannot filter on the former, and sockets
+    /// made by the application on none of them, so uevents are checked here.
+    fn selected(&self, dev: &BackendDevice) -> bool
+    {
+        let tags = dev.property_value("TAGS").unwrap_or_default();
+        if !self
+            .tag_matches
+            .iter()
+            .all(|tag| tags.split(':').any(|carried| carried == tag))
+        {
+            return false;
+        }
+        self.property_matches
+            .iter()
+            .all(|(name, value)| dev.property_value(name) == Some(value.as_str()))
+            && self
+                .attribute_matches
+                .iter()
+                .all(|(name, value)| read_attribute(dev, OsStr::new(name)).as_ref() == Some(value))
     }
 
-    fn monitor_socket(&mut self) -> io::Result<&MonitorSocket>
+    /// Returns whether `dev` passes the scope filter.
+    fn accepts(&self, dev: &BackendDevice) -> bool
     {
-        if self.socket.is_some() {
-            Ok(unsafe { self.socket.as_ref().unwrap_unchecked() })
+        self.pinned_type(dev).is_some()
+            || self.include_device_scope
+            || Scope::of(dev) == Scope::System
+    }
+
+    /// Returns whether `dev` is tracked as a peripheral.
+    fn is_peripheral(&self, dev: &BackendDevice) -> bool
+    {
+        self.monitor_peripherals
+            && Scope::of(dev) == Scope::Device
+            && self.pinned_type(dev).is_none()
+    }
+
+    /// Returns the type of `dev` if it matches one of the device patterns.
+    fn pinned_type(&self, dev: &BackendDevice) -> Option<DeviceType>
+    {
+        let sysname = &dev.sysname;
+        let matches = |pattern: &Option<String>| {
+            pattern
+                .as_deref()
+                .is_some_and(|pattern| glob_matches(pattern, sysname))
+        };
+        if matches(&self.battery_pattern) {
+            Some(DeviceType::Battery)
+        } else if matches(&self.adapter_pattern) {
+            Some(DeviceType::Adapter)
         } else {
-            self.socket = Some(
-                udev::MonitorBuilder::new()?
-                    .match_subsystem("power_supply")?
-                    .listen()?,
-            );
-            Ok(unsafe { self.socket.as_ref().unwrap_unchecked() })
+            None
         }
     }
 
-    fn set_device(&mut self, dev: udev::Device)
+    fn pattern(&self, device_type: DeviceType) -> Option<&str>
     {
-        match Self::device_type(&dev) {
-            Some(DeviceType::Battery) => self.bat = Some(dev),
-            Some(DeviceType::Adapter) => self.adp = Some(dev),
-            None => todo!(),
+        match device_type {
+            DeviceType::Battery => self.battery_pattern.as_deref(),
+            DeviceType::Adapter => self.adapter_pattern.as_deref(),
+            DeviceType::Wireless => None,
         }
     }
 
+    fn set_device(&mut self, dev: BackendDevice) -> io::Result<()>
+    {
+        // A removed device may not tell its driver or scope anymore, so it is
+        // only matched by its syspath.
+        if dev.removed {
+            self.remove_device(&dev);
+            return Ok(());
+        }
+        if !self.selected(&dev) {
+            return Ok(());
+        }
+        if self.is_peripheral(&dev) {
+            return self.set_peripheral(dev);
+        }
+        if !self.accepts(&dev) {
+            return Ok(());
+        }
+        let device_type = match self.pinned_type(&dev) {
+            Some(device_type) => Some(device_type),
+            // Another device is pinned in place of this one.
+            None => match Self::device_type(&dev) {
+                Some(device_type) if self.pattern(device_type).is_some() => return Ok(()),
+                device_type => device_type,
+            },
+        };
+        match device_type {
+            Some(DeviceType::Battery) => {
+                Self::set_in(&mut self.batteries, dev, DeviceType::Battery)
+            }
+            Some(device_type) => Self::set_in(&mut self.adapters, dev, device_type),
+            None => Ok(()),
+        }
+    }
+
+    /// Drops the cached device that `dev` was received in a `remove` uevent
+    /// for, whether it is a battery, the adapter or a peripheral. The overall
+    /// status is derived from the devices that are left the next time it is
+    /// refreshed.
+    fn remove_device(&mut self, dev: &BackendDevice)
+    {
+        let syspath = &dev.syspath;
+        self.batteries
+            .retain(|battery| battery.device.syspath != *syspath);
+        self.peripherals
+            .retain(|peripheral| peripheral.device.syspath != *syspath);
+        self.adapters
+            .retain(|adapter| adapter.device.syspath != *syspath);
+    }
+
+    /// Adds or updates the peripheral `dev`. Peripherals disappear
+    /// regularly, e.g. when a wireless mouse is switched off.
+    fn set_peripheral(&mut self, dev: BackendDevice) -> io::Result<()>
+    {
+        Self::set_in(&mut self.peripherals, dev, DeviceType::Battery)
+    }
+
+    /// Adds or updates `dev` in `devices`. System batteries and adapters can
+    /// come and go too, e.g. the hot-swappable second battery of a ThinkPad,
+    /// see [`PowerSupply::remove_device`].
+    fn set_in(
+        devices: &mut Vec<PowerDevice>,
+        dev: BackendDevice,
+        device_type: DeviceType,
+    ) -> io::Result<()>
+    {
+        let existing = devices
+            .iter()
+            .position(|cached| cached.device.syspath == dev.syspath);
+        match existing {
+            Some(index) => devices[index].replace(dev)?,
+            None => devices.push(PowerDevice::new(dev, device_type)?),
+        }
+        Ok(())
+    }
+
+    /// Enumerates the devices at first or after the cache was dropped. Once
+    /// the adapter or a battery has been removed, the status is derived from
+    /// the devices that are left.
     fn set_devices_if_not_set(&mut self) -> io::Result<()>
     {
-        if self.bat.is_none() || self.adp.is_none() {
+        if !self.enumerated {
             self.enumerate()?;
+            self.enumerated = true;
         }
         Ok(())
     }
 
+    /// Drops the cached devices, so that they are enumerated again.
+    fn forget_devices(&mut self)
+    {
+        self.batteries.clear();
+        self.adapters.clear();
+        self.peripherals.clear();
+        self.enumerated = false;
+    }
+
+    /// Classifies `dev` by its `POWER_SUPPLY_TYPE`, or failing that by its
+    /// driver or its name, e.g. `BAT0`, `CMB1`, `ADP1`, `AC0` or `ACAD`.
+    /// Devices that are none of these are ignored unless a device pattern
+    /// pins them.
     #[must_use]
-    fn device_type(dev: &udev::Device) -> Option<DeviceType>
+    fn device_type(dev: &BackendDevice) -> Option<DeviceType>
     {
-        match dev.parent()?.driver()?.to_str()? {
-            "battery" => Some(DeviceType::Battery),
-            "ac" => Some(DeviceType::Adapter),
-            _ => {
-                let sysname = dev.sysname().to_string_lossy();
-                if sysname.starts_with("BAT") {
-                    Some(DeviceType::Battery)
-                } else if sysname.starts_with("ADP") {
-                    Some(DeviceType::Adapter)
-                } else {
-                    None
-                }
-            }
+        match dev.property_value("POWER_SUPPLY_TYPE") {
+            Some("Battery") => return Some(DeviceType::Battery),
+            // USB-C ports, e.g. `ucsi-source-psy-*`, report `USB`.
+            Some("Mains" | "USB") => return Some(DeviceType::Adapter),
+            Some("Wireless") => return Some(DeviceType::Wireless),
+            _ => {}
+        }
+        match dev.driver.as_deref() {
+            Some("battery") => return Some(DeviceType::Battery),
+            Some("ac") => return Some(DeviceType::Adapter),
+            _ => {}
         }
+        let sysname = &dev.sysname;
+        if ["BAT", "CMB"]
+            .iter()
+            .any(|prefix| sysname.starts_with(prefix))
+        {
+            Some(DeviceType::Battery)
+        } else if ["ADP", "AC"]
+            .iter()
+            .any(|prefix| sysname.starts_with(prefix))
+        {
+            Some(DeviceType::Adapter)
+        } else {
+            None
+        }
+    }
+
+    /// Returns the rank of `adapter` in the source priority, `None` if its
+    /// kind does not power the system.
+    fn source_rank(&self, adapter: &PowerDevice) -> Option<usize>
+    {
+        let kind = SourceKind::of(&adapter.device);
+        self.source_priority
+            .iter()
+            .position(|&priority| priority == kind)
+    }
+
+    /// Returns the online adapter of the highest priority, which powers the
+    /// system.
+    fn source(&self) -> Option<&PowerDevice>
+    {
+        self.adapters
+            .iter()
+            .filter(|adapter| adapter.status == Status::Charging)
+            .filter_map(|adapter| Some((self.source_rank(adapter)?, adapter)))
+            .min_by_key(|&(rank, _)| rank)
+            .map(|(_, adapter)| adapter)
     }
 
     /// Fetch the current status and set the last charging status to the current
@@ -215,18 +1627,255 @@ impl PowerSupply
     fn current_charging_status(&mut self) -> io::Result<()>
     {
         self.set_devices_if_not_set()?;
-        let status =
-            match Status::read_from_adapter_device(unsafe { self.adp.as_ref().unwrap_unchecked() })
-            {
-                Status::Unknown => Status::read_from_battery_device(unsafe {
-                    self.bat.as_ref().unwrap_unchecked()
-                }),
-                status => status,
-            };
+        #[cfg(feature = "io-uring")]
+        if let Some(ring) = &mut self.ring {
+            let mut dirs = self
+                .batteries
+                .iter_mut()
+                .chain(&mut self.adapters)
+                .chain(&mut self.peripherals)
+                .map(|dev| &mut dev.attributes)
+                .collect::<Vec<_>>();
+            crate::sysfs::prefetch(ring, &mut dirs);
+        }
+        let now = self.clock.now();
+        self.last_refresh = Some(now);
+        let refresh = |dev: &mut PowerDevice| dev.refresh_present(now);
+        self.batteries.retain_mut(refresh);
+        self.peripherals.retain_mut(refresh);
+        self.adapters.retain_mut(refresh);
+        let errors = self
+            .batteries
+            .iter_mut()
+            .chain(&mut self.peripherals)
+            .chain(&mut self.adapters)
+            .flat_map(|dev| dev.errors.drain(..))
+            .collect::<Vec<_>>();
+        self.errors.extend(errors);
+        // Of errors that nobody takes, only the latest are kept.
+        let excess = self.errors.len().saturating_sub(MAX_ERRORS);
+        self.errors.drain(..excess);
+        // The system is on AC while a source that powers it is online, and
+        // on battery while one of them is known to be offline.
+        let source = if self.source().is_some() {
+            Status::Charging
+        } else {
+            Status::combine(
+                self.adapters
+                    .iter()
+                    .filter(|adapter| self.source_rank(adapter).is_some())
+                    .map(|adapter| adapter.status),
+            )
+        };
+        if source == Status::Charging {
+            for bat in &mut self.batteries {
+                if bat.is_charge_limited() {
+                    bat.status = Status::NotCharging;
+                }
+            }
+        }
+        let status = Status::with_adapter(
+            source,
+            Status::combine(self.batteries.iter().map(|bat| bat.status)),
+        );
         self.status_changed = status != self.status;
         self.status = status;
+        self.sessions.update(status, self.clock.now());
+        let power = self
+            .batteries
+            .iter()
+            .map(|bat| bat.power_now)
+            .sum::<Option<u64>>();
+        if let Some(power) = power.filter(|_| !self.batteries.is_empty()) {
+            self.estimator.push(status, power);
+        }
+        self.queue_events();
         Ok(())
     }
+
+    /// Compares the current state to the last reported one and queues the
+    /// resulting events.
+    fn queue_events(&mut self)
+    {
+        let snapshot = self.snapshot();
+        let low = snapshot
+            .devices
+            .iter()
+            .filter(|dev| dev.scope == Scope::Device && self.crossed_low_capacity(dev))
+            .cloned()
+            .collect::<Vec<_>>();
+        let alarms = snapshot
+            .batteries()
+            .filter(|bat| self.crossed_alarm(bat))
+            .cloned()
+            .collect::<Vec<_>>();
+        let changes = self.reported.diff(&snapshot);
+        let now = self.clock.now();
+        self.events.set_time(now);
+        if changes
+            .iter()
+            .any(|change| matches!(change, Change::Status { .. }))
+        {
+            self.last_transition = Some(now);
+        }
+        let swapped = changes.iter().any(|change| match change {
+            Change::DeviceAdded(dev) | Change::DeviceRemoved(dev) => {
+                dev.device_type == DeviceType::Battery && dev.scope == Scope::System
+            }
+            _ => false,
+        });
+        if swapped {
+            if let Some(drain) = &mut self.drain {
+                drain.restart();
+            }
+        }
+        self.events
+            .extend(changes.into_iter().map(PowerEvent::Changed));
+        if let Some(new) = snapshot.capacity() {
+            let step = self.capacity_step;
+            if self.reported_capacity.map(|old| old / step) != Some(new / step) {
+                self.events.push_back(PowerEvent::CapacityChanged {
+                    old: self.reported_capacity,
+                    new,
+                });
+                self.reported_capacity = Some(new);
+            }
+        }
+        self.events
+            .extend(low.into_iter().map(PowerEvent::LowBattery));
+        self.events
+            .extend(alarms.into_iter().map(PowerEvent::BatteryAlarm));
+        if let Some(remaining) = snapshot
+            .energy_remaining()
+            .filter(|_| snapshot.status == Status::Discharging)
+        {
+            let old = self.reported.energy_remaining();
+            for &budget in &self.energy_budgets {
+                if remaining < budget && old.is_none_or(|old| old >= budget) {
+                    self.events
+                        .push_back(PowerEvent::EnergyBudget { budget, remaining });
+                }
+            }
+        }
+        let speed = snapshot.charging_speed();
+        if let Some(speed) = speed.filter(|&speed| Some(speed) != self.reported.charging_speed()) {
+            self.events
+                .push_back(PowerEvent::ChargingSpeedChanged(speed));
+        }
+        if let Some(rate) = self
+            .drain
+            .as_mut()
+            .and_then(|drain| drain.sample(now, &snapshot))
+        {
+            self.events.push_back(PowerEvent::HighDrain(rate));
+        }
+        if let Some(thermal) = &mut self.thermal {
+            let (heated, cooled) = thermal.sample(now);
+            self.events
+                .extend(heated.into_iter().map(PowerEvent::Overheated));
+            self.events
+                .extend(cooled.into_iter().map(PowerEvent::CooledDown));
+        }
+        match self
+            .throttling
+            .as_mut()
+            .and_then(|throttling| throttling.sample(now))
+        {
+            Some(true) => self.events.push_back(PowerEvent::ThrottlingStarted),
+            Some(false) => self.events.push_back(PowerEvent::ThrottlingStopped),
+            None => {}
+        }
+        if let Some(fans) = &mut self.fans {
+            let (started, stopped) = fans.sample(now);
+            self.events
+                .extend(started.into_iter().map(PowerEvent::FanStarted));
+            self.events
+                .extend(stopped.into_iter().map(PowerEvent::FanStopped));
+        }
+        if self.detect_weak_charger {
+            let adapter = weak_charger(&snapshot);
+            if let Some(adapter) = adapter.filter(|_| !self.weak_charger) {
+                let power = snapshot
+                    .batteries()
+                    .filter(|bat| bat.status == Status::Discharging)
+                    .map(|bat| bat.power_now)
+                    .sum();
+                self.events.push_back(PowerEvent::WeakCharger {
+                    adapter: adapter.to_string(),
+                    power,
+                });
+            }
+            self.weak_charger = adapter.is_some();
+        }
+        self.reported = snapshot;
+    }
+
+    /// Returns whether `dev` dropped to the low capacity threshold for
+    /// peripherals since it was last reported.
+    fn crossed_low_capacity(&self, dev: &DeviceSnapshot) -> bool
+    {
+        let low = self.peripheral_low_capacity;
+        let old = self
+            .reported
+            .device(&dev.sysname)
+            .and_then(|dev| dev.capacity);
+        dev.capacity.is_some_and(|capacity| capacity <= low) && old.is_none_or(|old| old > low)
+    }
+
+    /// Returns whether the system battery `bat` dropped to the alarm
+    /// capacity of its firmware since it was last reported.
+    fn crossed_alarm(&self, bat: &DeviceSnapshot) -> bool
+    {
+        let Some(alarm) = bat.alarm else {
+            return false;
+        };
+        let old = self
+            .reported
+            .device(&bat.sysname)
+            .and_then(|bat| bat.capacity);
+        bat.capacity.is_some_and(|capacity| capacity <= alarm) && old.is_none_or(|old| old > alarm)
+    }
+}
+
+/// Reads the sysfs attribute `name` of `device`, with the trailing newline
+/// removed. Unreadable attributes, e.g. write-only ones, are `None`.
+fn read_attribute(device: &BackendDevice, name: &OsStr) -> Option<String>
+{
+    let value = fs::read(device.syspath.join(name)).ok()?;
+    let value = String::from_utf8_lossy(&value);
+    Some(value.trim_end_matches('\n').to_string())
+}
+
+/// Matches `name` against a glob pattern in which `*` stands for any number
+/// of characters and `?` for a single one.
+fn glob_matches(pattern: &str, name: &str) -> bool
+{
+    let pattern = pattern.chars().collect::<Vec<_>>();
+    let name = name.chars().collect::<Vec<_>>();
+    let (mut p, mut n) = (0, 0);
+    // Where to resume after the last `*` if the rest does not match.
+    let mut star = None;
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, n));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match star {
+                Some((star_p, star_n)) => {
+                    p = star_p + 1;
+                    n = star_n + 1;
+                    star = Some((star_p, star_n + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
 }
 
 impl Default for PowerSupply
@@ -236,3 +1885,239 @@ impl Default for PowerSupply
         Self::new()
     }
 }
+
+/// Returns the sysname of an online system adapter while no system battery
+/// charges and one of them discharges.
+fn weak_charger(snapshot: &Snapshot) -> Option<&str>
+{
+    let adapter = snapshot.devices.iter().find(|dev| {
+        dev.device_type == DeviceType::Adapter
+            && dev.scope == Scope::System
+            && dev.status == Status::Charging
+    })?;
+    let mut discharging = false;
+    for bat in snapshot.batteries() {
+        match bat.status {
+            Status::Discharging => discharging = true,
+            Status::Charging => return None,
+            _ => {}
+        }
+    }
+    discharging.then_some(adapter.sysname.as_str())
+}
+
+#[cfg(test)]
+mod tests
+{
+    use std::time::Duration;
+
+    use super::*;
+    use crate::scenario::Scenario;
+
+    #[test]
+    fn no_devices_leave_the_status_unknown()
+    {
+        let mut scenario = Scenario::new().unwrap();
+        scenario.update().unwrap();
+        let power_supply = scenario.power_supply();
+        assert_eq!(power_supply.charging_status(), Status::Unknown);
+        assert!(power_supply.refresh().is_ok());
+        let snapshot = power_supply.snapshot();
+        assert_eq!(snapshot.status, Status::Unknown);
+        assert!(snapshot.devices.is_empty());
+        assert_eq!(snapshot.capacity(), None);
+
+        scenario
+            .controller()
+            .play("add AC type=Mains online=1")
+            .unwrap();
+        scenario.update().unwrap();
+        assert!(scenario.power_supply().charging_status().is_on_ac());
+        scenario.controller().remove("AC").unwrap();
+        scenario.update().unwrap();
+        assert_eq!(scenario.power_supply().snapshot(), Snapshot::default());
+    }
+
+    #[test]
+    fn debounce_waits_for_the_mock_clock()
+    {
+        let mut scenario = Scenario::laptop().unwrap();
+        scenario.power_supply().set_debounce(Duration::from_secs(2));
+        assert_eq!(scenario.power_supply().timeout(), None);
+
+        scenario.plug().unwrap();
+        scenario.update().unwrap();
+        let power_supply = scenario.power_supply();
+        assert_eq!(power_supply.charging_status(), Status::Discharging);
+        assert!(!power_supply.charging_status_changed());
+        assert_eq!(power_supply.timeout(), Some(Duration::from_secs(2)));
+
+        scenario.clock().advance(Duration::from_millis(1500));
+        assert_eq!(
+            scenario.power_supply().timeout(),
+            Some(Duration::from_millis(500))
+        );
+        scenario.update().unwrap();
+        assert_eq!(
+            scenario.power_supply().charging_status(),
+            Status::Discharging
+        );
+
+        scenario.clock().advance(Duration::from_millis(500));
+        assert_eq!(scenario.power_supply().timeout(), Some(Duration::ZERO));
+        scenario.update().unwrap();
+        let power_supply = scenario.power_supply();
+        assert_eq!(power_supply.charging_status(), Status::Charging);
+        assert!(power_supply.charging_status_changed());
+        assert_eq!(power_supply.timeout(), None);
+    }
+
+    #[test]
+    fn uevents_during_the_debounce_restart_it()
+    {
+        let mut scenario = Scenario::laptop().unwrap();
+        scenario.power_supply().set_debounce(Duration::from_secs(2));
+        scenario.plug().unwrap();
+        scenario.update().unwrap();
+
+        scenario.clock().advance(Duration::from_secs(1));
+        scenario.controller().set("BAT0", "capacity", "81").unwrap();
+        scenario.update().unwrap();
+        assert_eq!(
+            scenario.power_supply().timeout(),
+            Some(Duration::from_secs(2))
+        );
+
+        scenario.clock().advance(Duration::from_secs(1));
+        scenario.update().unwrap();
+        assert_eq!(
+            scenario.power_supply().charging_status(),
+            Status::Discharging
+        );
+        scenario.wait(Duration::from_secs(1)).unwrap();
+        assert_eq!(scenario.power_supply().charging_status(), Status::Charging);
+        assert_eq!(scenario.power_supply().snapshot().capacity(), Some(81));
+    }
+
+    #[test]
+    fn energy_budgets_fire_once_per_crossing_while_discharging()
+    {
+        let mut scenario = Scenario::new().unwrap();
+        let battery = Scenario::BATTERY;
+        scenario
+            .controller()
+            .play(
+                "
+                add BAT0 type=Battery status=Discharging energy_full=50000000 energy_now=25000000
+                add AC type=Mains online=0
+                ",
+            )
+            .unwrap();
+        scenario
+            .power_supply()
+            .set_energy_budgets(vec![20_000_000, 10_000_000]);
+        let budgets = |scenario: &mut Scenario| {
+            let events = scenario.events().unwrap();
+            events
+                .into_iter()
+                .filter_map(|event| match event {
+                    PowerEvent::EnergyBudget { budget, .. } => Some(budget),
+                    _ => None,
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(budgets(&mut scenario), []);
+
+        let set = |scenario: &mut Scenario, energy: &str| {
+            scenario
+                .controller()
+                .set(battery, "energy_now", energy)
+                .unwrap();
+        };
+        set(&mut scenario, "19000000");
+        assert_eq!(budgets(&mut scenario), [20_000_000]);
+        set(&mut scenario, "18000000");
+        assert_eq!(budgets(&mut scenario), []);
+
+        // Charging back above the budget and below it again does not count.
+        scenario.plug().unwrap();
+        set(&mut scenario, "22000000");
+        assert_eq!(budgets(&mut scenario), []);
+        set(&mut scenario, "19000000");
+        assert_eq!(budgets(&mut scenario), []);
+
+        // Discharging from above does, and a drop past both budgets fires both.
+        set(&mut scenario, "22000000");
+        assert_eq!(budgets(&mut scenario), []);
+        scenario.unplug().unwrap();
+        assert_eq!(budgets(&mut scenario), []);
+        set(&mut scenario, "9000000");
+        assert_eq!(budgets(&mut scenario), [20_000_000, 10_000_000]);
+    }
+
+    #[test]
+    fn failed_rescans_keep_the_devices()
+    {
+        let mut scenario = Scenario::new().unwrap();
+        let controller = scenario.controller().clone();
+        controller
+            .play("add BAT0 type=Battery status=Discharging capacity=70")
+            .unwrap();
+        scenario.update().unwrap();
+        controller
+            .play("add AC type=Mains\nfail AC uevent")
+            .unwrap();
+        assert!(scenario.power_supply().rescan().is_err());
+        let snapshot = scenario.power_supply().snapshot();
+        assert_eq!(snapshot.status, Status::Discharging);
+        assert_eq!(snapshot.capacity(), Some(70));
+    }
+
+    #[test]
+    fn read_errors_leave_attributes_unknown()
+    {
+        let mut scenario = Scenario::new().unwrap();
+        let controller = scenario.controller();
+        controller
+            .play(
+                "
+                add BAT0 type=Battery status=Discharging capacity=70 voltage_now=12000000
+                add AC type=Mains
+                fail BAT0 voltage_now
+                fail BAT0 alarm
+                fail AC online
+                ",
+            )
+            .unwrap();
+        scenario.update().unwrap();
+        let snapshot = scenario.power_supply().snapshot();
+        assert_eq!(snapshot.status, Status::Discharging);
+        assert_eq!(snapshot.capacity(), Some(70));
+        let battery = snapshot.batteries().next().unwrap();
+        assert_eq!(battery.voltage_now, None);
+        assert_eq!(battery.alarm, None);
+
+        let errors = scenario.power_supply().take_errors();
+        let messages = errors.iter().map(ToString::to_string).collect::<Vec<_>>();
+        assert_eq!(messages.len(), 3, "{:?}", messages);
+        for (name, sysname) in [("voltage_now", "BAT0"), ("alarm", "BAT0"), ("online", "AC")] {
+            let prefix = format!("cannot read `{}` of {}: ", name, sysname);
+            assert!(
+                messages
+                    .iter()
+                    .any(|message| message.starts_with(&prefix) && message.contains("os error 5")),
+                "{:?}",
+                messages
+            );
+        }
+
+        // The failures are reported once, and the status still changes.
+        scenario
+            .controller()
+            .set("BAT0", "status", "Charging")
+            .unwrap();
+        scenario.update().unwrap();
+        assert_eq!(scenario.power_supply().snapshot().status, Status::Charging);
+        assert!(scenario.power_supply().take_errors().is_empty());
+    }
+}
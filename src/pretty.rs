@@ -0,0 +1,110 @@
+use crate::format::Fields;
+use crate::level::BatteryLevel;
+use crate::power_supply::Status;
+
+/// Renders the state as a battery glyph and the capacity, colored by the
+/// battery level, for terminals and status lines:
+///
+/// ```
+/// use lithiumd::BatteryLevel;
+/// use lithiumd::Fields;
+/// use lithiumd::Pretty;
+/// use lithiumd::Status;
+///
+/// let pretty = Pretty {
+///     ramp: vec!["[  ]".to_string(), "[= ]".to_string(), "[==]".to_string()],
+///     color: false,
+///     ..Pretty::default()
+/// };
+/// let fields = Fields {
+///     status: Status::Discharging,
+///     capacity: Some(50),
+///     level: Some(BatteryLevel::Normal),
+///     time_remaining: None,
+/// };
+/// assert_eq!(pretty.render(&fields), "[= ] 50%");
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pretty
+{
+    /// Glyphs from empty to full, picked by splitting the capacity into as
+    /// many equal ranges.
+    pub ramp: Vec<String>,
+    /// Glyph shown while charging, whatever the capacity.
+    pub charging: String,
+    /// Glyph shown while the capacity is not known.
+    pub unknown: String,
+    /// Whether to color the output with ANSI escapes: red when critical,
+    /// yellow when low and green while charging.
+    pub color: bool,
+}
+
+impl Default for Pretty
+{
+    /// The battery glyphs of Nerd Fonts.
+    fn default() -> Self
+    {
+        Self {
+            ramp: [
+                "\u{f007a}",
+                "\u{f007b}",
+                "\u{f007c}",
+                "\u{f007d}",
+                "\u{f007e}",
+                "\u{f007f}",
+                "\u{f0080}",
+                "\u{f0081}",
+                "\u{f0082}",
+                "\u{f0079}",
+            ]
+            .map(str::to_string)
+            .to_vec(),
+            charging: "\u{f0084}".to_string(),
+            unknown: "\u{f0091}".to_string(),
+            color: true,
+        }
+    }
+}
+
+impl Pretty
+{
+    /// Returns the glyph for `capacity` and `status`.
+    #[must_use]
+    pub fn icon(&self, capacity: Option<u8>, status: Status) -> &str
+    {
+        let Some(capacity) = capacity else {
+            return &self.unknown;
+        };
+        if status == Status::Charging {
+            return &self.charging;
+        }
+        if self.ramp.is_empty() {
+            return "";
+        }
+        let i = usize::from(capacity) * self.ramp.len() / 100;
+        &self.ramp[i.min(self.ramp.len() - 1)]
+    }
+
+    #[must_use]
+    pub fn render(&self, fields: &Fields) -> String
+    {
+        let mut output = self.icon(fields.capacity, fields.status).to_string();
+        if let Some(capacity) = fields.capacity {
+            output += &format!(" {}%", capacity);
+        }
+        let color = match (fields.status, fields.level) {
+            (Status::Charging, _) => Some("32"),
+            (_, Some(BatteryLevel::Critical)) => Some("31"),
+            (_, Some(BatteryLevel::Low)) => Some("33"),
+            _ => None,
+        };
+        if let Some(color) = color.filter(|_| self.color) {
+            output = format!("\x1b[{}m{}\x1b[0m", color, output);
+        }
+        if let Some(remaining) = fields.time_remaining {
+            let minutes = remaining.as_secs() / 60;
+            output += &format!(" {}h {}m", minutes / 60, minutes % 60);
+        }
+        output
+    }
+}
@@ -0,0 +1,185 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::ffi::CStr;
+use std::ffi::CString;
+use std::ffi::OsStr;
+use std::fs;
+use std::fs::File;
+use std::fs::OpenOptions;
+use std::io;
+use std::mem;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::FileExt;
+use std::path::Path;
+use std::path::PathBuf;
+use std::ptr;
+use std::sync::Mutex;
+
+/// Attributes opened by [`hold`], by path.
+static HELD: Mutex<BTreeMap<PathBuf, File>> = Mutex::new(BTreeMap::new());
+
+/// Returns the sysfs attributes that rules, profiles and charge limits may
+/// write: cpufreq policies, backlights, runtime power management of PCI and
+/// USB devices, the ASPM policy, charge thresholds and the RTC wake alarm.
+#[must_use]
+pub fn attributes() -> Vec<PathBuf>
+{
+    let mut paths = Vec::new();
+    for policy in children("/sys/devices/system/cpu/cpufreq") {
+        paths.push(policy.join("scaling_governor"));
+        paths.push(policy.join("energy_performance_preference"));
+    }
+    for backlight in children("/sys/class/backlight") {
+        paths.push(backlight.join("brightness"));
+    }
+    for led in children("/sys/class/leds") {
+        if led.to_string_lossy().contains("kbd_backlight") {
+            paths.push(led.join("brightness"));
+        }
+    }
+    for device in children("/sys/bus/pci/devices").chain(children("/sys/bus/usb/devices")) {
+        paths.push(device.join("power/control"));
+    }
+    for supply in children("/sys/class/power_supply") {
+        paths.push(supply.join("charge_control_start_threshold"));
+        paths.push(supply.join("charge_control_end_threshold"));
+        paths.push(supply.join("charge_behaviour"));
+    }
+    for device in children("/sys/bus/platform/drivers/ideapad_acpi") {
+        paths.push(device.join("conservation_mode"));
+    }
+    paths.push(PathBuf::from(
+        "/sys/devices/platform/huawei-wmi/charge_control_thresholds",
+    ));
+    paths.push(PathBuf::from("/sys/module/pcie_aspm/parameters/policy"));
+    paths.push(PathBuf::from("/sys/class/rtc/rtc0/wakealarm"));
+    paths
+}
+
+/// Opens `paths` for writing and keeps them open, so that they stay writable
+/// after [`drop_to`]: sysfs checks the permissions when an attribute is
+/// opened, not on each write. Paths that do not exist or cannot be opened are
+/// skipped. Returns how many are held.
+///
+/// Attributes of devices that show up later cannot be written once
+/// privileges are dropped, and neither can Wi-Fi power saving, which the
+/// kernel checks on every request.
+pub fn hold(paths: impl IntoIterator<Item = PathBuf>) -> usize
+{
+    let mut held = HELD.lock().unwrap_or_else(|err| err.into_inner());
+    for path in paths {
+        if let Ok(file) = OpenOptions::new().write(true).open(&path) {
+            held.insert(path, file);
+        }
+    }
+    held.len()
+}
+
+/// Switches to the user named `user`, with its groups, for good. `HOME`,
+/// `USER` and `LOGNAME` are set to the user's, since hooks inherit them.
+pub fn drop_to(user: &str) -> io::Result<()>
+{
+    let account = Account::lookup(user)?;
+    unsafe {
+        if libc::setgroups(account.groups.len(), account.groups.as_ptr()) != 0
+            || libc::setgid(account.gid) != 0
+            || libc::setuid(account.uid) != 0
+        {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    // Make sure there is no way back.
+    if unsafe { libc::setuid(0) } == 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            "root privileges could be regained",
+        ));
+    }
+    env::set_var("HOME", &account.home);
+    env::set_var("USER", user);
+    env::set_var("LOGNAME", user);
+    Ok(())
+}
+
+/// The entry of a user in the password database, with its groups.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Account
+{
+    pub(crate) uid: libc::uid_t,
+    pub(crate) gid: libc::gid_t,
+    pub(crate) groups: Vec<libc::gid_t>,
+    pub(crate) home: PathBuf,
+    pub(crate) shell: PathBuf,
+}
+
+impl Account
+{
+    pub(crate) fn lookup(user: &str) -> io::Result<Self>
+    {
+        let name = CString::new(user)
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "invalid user name"))?;
+        let mut passwd: libc::passwd = unsafe { mem::zeroed() };
+        let mut buf = vec![0 as libc::c_char; 16 * 1024];
+        let mut result = ptr::null_mut();
+        let err = unsafe {
+            libc::getpwnam_r(
+                name.as_ptr(),
+                &mut passwd,
+                buf.as_mut_ptr(),
+                buf.len(),
+                &mut result,
+            )
+        };
+        if err != 0 {
+            return Err(io::Error::from_raw_os_error(err));
+        }
+        if result.is_null() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("no user `{}`", user),
+            ));
+        }
+        let path = |ptr: *const libc::c_char| {
+            PathBuf::from(OsStr::from_bytes(unsafe { CStr::from_ptr(ptr) }.to_bytes()))
+        };
+        let mut groups = vec![0; 64];
+        loop {
+            let mut len = groups.len() as libc::c_int;
+            let found = unsafe {
+                libc::getgrouplist(name.as_ptr(), passwd.pw_gid, groups.as_mut_ptr(), &mut len)
+            };
+            groups.resize(len as usize, 0);
+            if found >= 0 {
+                break;
+            }
+        }
+        Ok(Self {
+            uid: passwd.pw_uid,
+            gid: passwd.pw_gid,
+            groups,
+            home: path(passwd.pw_dir),
+            shell: path(passwd.pw_shell),
+        })
+    }
+}
+
+/// Writes `value` to the attribute at `path`, through the handle held for
+/// it if there is one.
+pub(crate) fn write(path: &Path, value: &str) -> io::Result<()>
+{
+    let held = HELD.lock().unwrap_or_else(|err| err.into_inner());
+    let result = match held.get(path) {
+        Some(file) => file.write_at(value.as_bytes(), 0).map(drop),
+        None => fs::write(path, value),
+    };
+    result.map_err(|err| io::Error::new(err.kind(), format!("{}: {}", path.display(), err)))
+}
+
+fn children(dir: &str) -> impl Iterator<Item = PathBuf>
+{
+    fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .map(|entry| entry.path())
+}
@@ -0,0 +1,117 @@
+use std::collections::VecDeque;
+use std::future::Future;
+use std::io;
+use std::os::fd::AsFd;
+use std::os::fd::BorrowedFd;
+use std::os::fd::RawFd;
+use std::pin::Pin;
+use std::task::Context;
+use std::task::Poll;
+
+use async_io::Async;
+use async_io::Timer;
+use futures_core::Stream;
+
+use crate::event::PowerEvent;
+use crate::power_supply::PowerSupply;
+
+/// The events of a power supply as a [`Stream`], driven by the reactor of
+/// async-io instead of a thread, for smol and async-std. Unlike
+/// [`AsyncPowerSupply`](crate::AsyncPowerSupply), the power supply stays on
+/// the task that polls the stream and can be configured while it runs.
+pub struct AsyncIoPowerSupply
+{
+    /// Declared first to be dropped first, as it borrows the socket of the
+    /// power supply.
+    socket: Async<MonitorFd>,
+    power_supply: PowerSupply,
+    /// Fires when the debounce window closes or the drain has to be sampled.
+    timer: Timer,
+    /// Events taken from the power supply but not returned yet.
+    pending: VecDeque<PowerEvent>,
+}
+
+/// The udev monitor socket, owned by the power supply.
+struct MonitorFd(RawFd);
+
+impl AsFd for MonitorFd
+{
+    fn as_fd(&self) -> BorrowedFd<'_>
+    {
+        unsafe { BorrowedFd::borrow_raw(self.0) }
+    }
+}
+
+impl AsyncIoPowerSupply
+{
+    pub fn new() -> io::Result<Self>
+    {
+        Self::with(PowerSupply::new())
+    }
+
+    /// Watches an already configured power supply.
+    pub fn with(mut power_supply: PowerSupply) -> io::Result<Self>
+    {
+        let fd = power_supply.monitor_fd()?;
+        let socket = Async::new(MonitorFd(fd))?;
+        power_supply.update()?;
+        Ok(Self {
+            socket,
+            power_supply,
+            timer: Timer::never(),
+            pending: VecDeque::new(),
+        })
+    }
+
+    #[must_use]
+    pub fn power_supply(&self) -> &PowerSupply
+    {
+        &self.power_supply
+    }
+
+    pub fn power_supply_mut(&mut self) -> &mut PowerSupply
+    {
+        &mut self.power_supply
+    }
+}
+
+impl Stream for AsyncIoPowerSupply
+{
+    type Item = io::Result<PowerEvent>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>>
+    {
+        let this = self.get_mut();
+        loop {
+            this.pending.extend(this.power_supply.events());
+            if let Some(event) = this.pending.pop_front() {
+                return Poll::Ready(Some(Ok(event)));
+            }
+            let readable = match this.socket.poll_readable(cx) {
+                Poll::Ready(Ok(())) => true,
+                Poll::Ready(Err(err)) => return Poll::Ready(Some(Err(err))),
+                Poll::Pending => false,
+            };
+            match this.power_supply.timeout() {
+                Some(timeout) => this.timer.set_after(timeout),
+                None => this.timer = Timer::never(),
+            }
+            let timed_out = Pin::new(&mut this.timer).poll(cx).is_ready();
+            if !readable && !timed_out {
+                return Poll::Pending;
+            }
+            if let Err(err) = this.power_supply.update() {
+                return Poll::Ready(Some(Err(err)));
+            }
+            // The power supply opens a new socket if the old one failed.
+            match this.power_supply.monitor_fd() {
+                Ok(fd) if fd != this.socket.get_ref().0 => match Async::new(MonitorFd(fd)) {
+                    Ok(socket) => this.socket = socket,
+                    Err(err) => return Poll::Ready(Some(Err(err))),
+                },
+                Ok(_) => {}
+                Err(err) => return Poll::Ready(Some(Err(err))),
+            }
+        }
+    }
+}
@@ -0,0 +1,534 @@
+use std::collections::BTreeMap;
+use std::io;
+use std::io::BufRead;
+use std::io::BufReader;
+use std::io::Read;
+use std::io::Write;
+use std::mem;
+use std::net::TcpListener;
+use std::net::TcpStream;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+use std::time::Instant;
+
+use lithiumd::PowerSupply;
+use lithiumd::Status;
+use mio::event::Source;
+use mio::Events;
+use mio::Interest;
+use mio::Poll;
+use mio::Token;
+
+use crate::config::Config;
+use crate::hub;
+use crate::mqtt;
+
+/// Address of the collector if none is given.
+pub const DEFAULT_ADDRESS: &str = "127.0.0.1:7373";
+
+/// Agents send their state at least this often, so that the collector can
+/// tell dead connections from quiet ones.
+const HEARTBEAT: Duration = Duration::from_secs(60);
+/// How long an agent waits before connecting again.
+const RECONNECT: Duration = Duration::from_secs(10);
+/// Longest line the collector reads, in bytes.
+const MAX_LINE: u64 = 1024;
+/// Most lines in the state of an agent.
+const MAX_FIELDS: usize = 64;
+/// Most connections the collector serves at a time.
+const MAX_CONNECTIONS: usize = 64;
+
+/// Options of `lithiumd agent`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AgentOptions
+{
+    /// `HOST:PORT` of the collector.
+    pub connect: String,
+    /// Name the collector lists this machine by, the hostname by default.
+    pub name: String,
+}
+
+impl AgentOptions
+{
+    /// Parses `--connect HOST:PORT [--name NAME]`.
+    pub fn parse(args: &[String]) -> Result<Self, String>
+    {
+        let mut connect = None;
+        let mut name = None;
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            let value = match arg.as_str() {
+                "--connect" => &mut connect,
+                "--name" => &mut name,
+                _ => return Err(format!("unknown option `{}`", arg)),
+            };
+            *value = Some(
+                args.next()
+                    .ok_or_else(|| format!("`{}` needs a value", arg))?
+                    .clone(),
+            );
+        }
+        Ok(Self {
+            connect: connect.ok_or("`agent` needs `--connect`")?,
+            name: name.unwrap_or_else(mqtt::hostname),
+        })
+    }
+}
+
+/// Options of `lithiumd remote list`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ListOptions
+{
+    pub connect: String,
+    pub json: bool,
+}
+
+impl ListOptions
+{
+    /// Parses `[--json] [--connect HOST:PORT]`.
+    pub fn parse(args: &[String]) -> Result<Self, String>
+    {
+        let mut options = Self {
+            connect: DEFAULT_ADDRESS.to_string(),
+            json: false,
+        };
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--json" => options.json = true,
+                "--connect" => {
+                    options.connect = args
+                        .next()
+                        .ok_or_else(|| format!("`{}` needs a value", arg))?
+                        .clone();
+                }
+                _ => return Err(format!("unknown option `{}`", arg)),
+            }
+        }
+        Ok(options)
+    }
+}
+
+/// Watches the power supply without the daemon and streams its state to the
+/// collector on every change, as the `Key: value` lines of `lithiumd status`
+/// followed by an empty line. The connection is opened again whenever it
+/// fails.
+pub fn agent(options: &AgentOptions) -> io::Result<()>
+{
+    let config = Config::load()?;
+    let mut power_supply = config.backend.power_supply()?;
+    power_supply.set_debounce(config.debounce);
+    let mut poll = Poll::new()?;
+    power_supply.register(poll.registry(), Token(0), Interest::READABLE)?;
+    let mut events = Events::with_capacity(64);
+    let mut stream: Option<TcpStream> = None;
+    let mut next_connect = Instant::now();
+    let mut next_heartbeat = Instant::now();
+    loop {
+        power_supply.update()?;
+        let changed = power_supply.events().count() > 0;
+        let now = Instant::now();
+        if stream.is_none() && now >= next_connect {
+            match connect(&options.connect, config.remote_token.as_deref()) {
+                Ok(connected) => {
+                    println!("Connected to {}", options.connect);
+                    // Sends the current state right away.
+                    next_heartbeat = now;
+                    stream = Some(connected);
+                }
+                Err(err) => {
+                    eprintln!("Failed to connect to {}: {}", options.connect, err);
+                    next_connect = now + RECONNECT;
+                }
+            }
+        }
+        if let Some(connected) = stream.as_mut().filter(|_| changed || now >= next_heartbeat) {
+            let block = state(&options.name, &power_supply, &config);
+            if let Err(err) = connected.write_all(block.as_bytes()) {
+                eprintln!("Lost the connection to {}: {}", options.connect, err);
+                stream = None;
+                next_connect = now + RECONNECT;
+            }
+            next_heartbeat = now + HEARTBEAT;
+        }
+        let wake = match &stream {
+            Some(_) => next_heartbeat,
+            None => next_connect,
+        };
+        let timeout = [
+            power_supply.timeout(),
+            Some(wake.saturating_duration_since(Instant::now())),
+        ]
+        .into_iter()
+        .flatten()
+        .min();
+        if let Err(err) = poll.poll(&mut events, timeout) {
+            if err.kind() != io::ErrorKind::Interrupted {
+                return Err(err);
+            }
+        }
+    }
+}
+
+/// Connects an agent to the collector at `address` and shows it `token`.
+fn connect(address: &str, token: Option<&str>) -> io::Result<TcpStream>
+{
+    let mut stream = TcpStream::connect(address)?;
+    // The collector may stall, but the power supply is still to be watched.
+    stream.set_write_timeout(Some(RECONNECT))?;
+    if let Some(token) = token {
+        stream.write_all(format!("Token: {}\n", token).as_bytes())?;
+    }
+    Ok(stream)
+}
+
+/// Describes the state of the power supply the way `lithiumd status` does,
+/// headed by the name of the machine.
+fn state(name: &str, power_supply: &PowerSupply, config: &Config) -> String
+{
+    let snapshot = power_supply.snapshot();
+    let mut state = format!("Host: {}\nCharging status: {}\n", name, snapshot.status);
+    if let Some(capacity) = snapshot.capacity() {
+        state += &format!("Capacity: {}%\n", capacity);
+    }
+    if let Some(level) = snapshot.level(&config.levels) {
+        state += &format!("Level: {}\n", level);
+    }
+    if let Some(remaining) = power_supply.time_remaining() {
+        let minutes = remaining.as_secs() / 60;
+        let label = match snapshot.status {
+            Status::Charging => "Time to full",
+            _ => "Time remaining",
+        };
+        state += &format!("{}: {}h {}m\n", label, minutes / 60, minutes % 60);
+    }
+    state + "\n"
+}
+
+/// The last state an agent sent.
+struct Agent
+{
+    fields: Vec<(String, String)>,
+    seen: Instant,
+    connected: bool,
+}
+
+type Agents = Arc<Mutex<BTreeMap<String, Agent>>>;
+
+/// Runs the collector: agents stream their state to it, and `lithiumd remote
+/// list` asks it for the state of all of them. With a `[remote] token`, only
+/// connections that start with it are served.
+pub fn listen(address: &str) -> io::Result<()>
+{
+    let token = Config::load()?.remote_token;
+    let listener = TcpListener::bind(address)
+        .map_err(|err| io::Error::new(err.kind(), format!("{}: {}", address, err)))?;
+    println!("Listening on {}", address);
+    let agents = Agents::default();
+    let connections = Arc::new(AtomicUsize::new(0));
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(err) => {
+                eprintln!("Failed to accept a connection: {}", err);
+                continue;
+            }
+        };
+        let peer = stream
+            .peer_addr()
+            .map_or_else(|_| "?".to_string(), |addr| addr.to_string());
+        if connections.fetch_add(1, Ordering::SeqCst) >= MAX_CONNECTIONS {
+            connections.fetch_sub(1, Ordering::SeqCst);
+            eprintln!("Too many connections, closing the one from {}", peer);
+            continue;
+        }
+        let agents = Arc::clone(&agents);
+        let connections = Arc::clone(&connections);
+        let token = token.clone();
+        thread::spawn(move || {
+            if let Err(err) = serve(stream, &agents, token.as_deref()) {
+                eprintln!("Connection from {} failed: {}", peer, err);
+            }
+            connections.fetch_sub(1, Ordering::SeqCst);
+        });
+    }
+    Ok(())
+}
+
+/// Reads a line of at most [`MAX_LINE`] bytes into `line`. Returns 0 at the
+/// end of the stream.
+fn read_line(reader: &mut impl BufRead, line: &mut String) -> io::Result<usize>
+{
+    line.clear();
+    let read = reader.take(MAX_LINE).read_line(line)?;
+    if read as u64 == MAX_LINE && !line.ends_with('\n') {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "line too long"));
+    }
+    Ok(read)
+}
+
+/// Serves one connection, either a `list` request or the stream of an agent.
+/// The connection starts with `Token: TOKEN` if the collector has a `token`.
+/// An agent keeps the name it first sent for as long as it stays connected.
+fn serve(stream: TcpStream, agents: &Agents, token: Option<&str>) -> io::Result<()>
+{
+    // Agents send a heartbeat, so a silent one is gone.
+    stream.set_read_timeout(Some(HEARTBEAT * 3))?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut first = true;
+    let mut name = None;
+    let mut seen = Instant::now();
+    let mut fields = Vec::new();
+    let mut line = String::new();
+    let result = loop {
+        match read_line(&mut reader, &mut line) {
+            Ok(0) => break Ok(()),
+            Ok(_) => {}
+            Err(err) => break Err(err),
+        }
+        let line = line.trim_end();
+        if mem::take(&mut first) {
+            match (line.strip_prefix("Token: "), token) {
+                (Some(sent), Some(token)) if sent != token => {
+                    break Err(io::Error::new(
+                        io::ErrorKind::PermissionDenied,
+                        "wrong token",
+                    ))
+                }
+                (Some(_), _) => continue,
+                (None, Some(_)) => {
+                    break Err(io::Error::new(io::ErrorKind::PermissionDenied, "no token"))
+                }
+                (None, None) => {}
+            }
+        }
+        if line == "list" && name.is_none() {
+            return list_agents(stream, agents);
+        }
+        if !line.is_empty() {
+            if fields.len() >= MAX_FIELDS {
+                break Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "too many lines in a state",
+                ));
+            }
+            if let Some((key, value)) = line.split_once(": ") {
+                fields.push((key.trim().to_string(), value.trim().to_string()));
+            }
+            continue;
+        }
+        let Some(host) = fields
+            .iter()
+            .position(|(key, _)| key == "Host")
+            .map(|i| fields.remove(i).1)
+        else {
+            fields.clear();
+            continue;
+        };
+        match &name {
+            Some(name) if *name != host => {
+                break Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("agent {} sent the state of {}", name, host),
+                ))
+            }
+            Some(_) => {}
+            None => {
+                println!("Agent {} connected", host);
+                name = Some(host.clone());
+            }
+        }
+        seen = Instant::now();
+        let agent = Agent {
+            fields: mem::take(&mut fields),
+            seen,
+            connected: true,
+        };
+        agents.lock().unwrap().insert(host, agent);
+    };
+    if let Some(name) = name {
+        println!("Agent {} disconnected", name);
+        // Unless the agent connected again in the meantime.
+        if let Some(agent) = agents
+            .lock()
+            .unwrap()
+            .get_mut(&name)
+            .filter(|agent| agent.seen == seen)
+        {
+            agent.connected = false;
+        }
+    }
+    result
+}
+
+/// Answers `list` with the state of every agent, in the same form as they
+/// sent it plus when that was.
+fn list_agents(mut stream: TcpStream, agents: &Agents) -> io::Result<()>
+{
+    let mut response = String::new();
+    for (name, agent) in agents.lock().unwrap().iter() {
+        response += &format!("Host: {}\n", name);
+        for (key, value) in &agent.fields {
+            response += &format!("{}: {}\n", key, value);
+        }
+        response += &format!(
+            "Connected: {}\nLast seen: {}s ago\n\n",
+            if agent.connected { "yes" } else { "no" },
+            agent.seen.elapsed().as_secs()
+        );
+    }
+    stream.write_all(response.as_bytes())
+}
+
+/// Asks the collector for the state of its agents and prints it like
+/// `lithiumd hub` does. Machines whose agent disconnected show when they
+/// were last seen instead.
+pub fn list(options: &ListOptions) -> io::Result<()>
+{
+    let token = Config::load()?.remote_token;
+    let mut stream = TcpStream::connect(&options.connect)
+        .map_err(|err| io::Error::new(err.kind(), format!("{}: {}", options.connect, err)))?;
+    if let Some(token) = token {
+        stream.write_all(format!("Token: {}\n", token).as_bytes())?;
+    }
+    stream.write_all(b"list\n")?;
+    let mut response = String::new();
+    stream.read_to_string(&mut response)?;
+    let mut hosts = Vec::new();
+    for block in response
+        .split("\n\n")
+        .filter(|block| !block.trim().is_empty())
+    {
+        let mut fields = block
+            .lines()
+            .filter_map(|line| line.split_once(": "))
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect::<Vec<_>>();
+        let Some(i) = fields.iter().position(|(key, _)| key == "Host") else {
+            continue;
+        };
+        let host = fields.remove(i).1;
+        let field = |key: &str| hub::field(&fields, key).to_string();
+        let status = match field("Connected").as_str() {
+            "no" => Err(format!("disconnected, last seen {}", field("Last seen"))),
+            _ => Ok(fields),
+        };
+        hosts.push((host, status));
+    }
+    let hosts = hosts.iter().map(|(host, status)| (host, status));
+    if options.json {
+        println!("{}", hub::json(hosts));
+    } else {
+        print!("{}", hub::table(hosts));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests
+{
+    use std::net::Shutdown;
+    use std::net::SocketAddr;
+
+    use super::*;
+
+    /// Starts a collector on a free port.
+    fn collector(token: Option<&'static str>) -> (SocketAddr, Agents)
+    {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let agents = Agents::default();
+        let served = Arc::clone(&agents);
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let agents = Arc::clone(&served);
+                thread::spawn(move || serve(stream.unwrap(), &agents, token));
+            }
+        });
+        (address, agents)
+    }
+
+    /// Sends `request` and returns what the collector answered until it
+    /// closed the connection.
+    fn send(address: SocketAddr, request: &[u8]) -> String
+    {
+        let mut stream = TcpStream::connect(address).unwrap();
+        stream.write_all(request).unwrap();
+        stream.shutdown(Shutdown::Write).unwrap();
+        let mut response = Vec::new();
+        // Refused connections may be reset instead of closed.
+        let _ = stream.read_to_end(&mut response);
+        String::from_utf8(response).unwrap()
+    }
+
+    #[test]
+    fn serves_agents_that_show_the_token()
+    {
+        let (address, agents) = collector(Some("secret"));
+        send(address, b"Token: secret\nHost: a\nCapacity: 50%\n\n");
+        assert_eq!(
+            agents.lock().unwrap()["a"].fields,
+            [("Capacity".to_string(), "50%".to_string())]
+        );
+        let response = send(address, b"Token: secret\nlist\n");
+        assert!(
+            response.starts_with("Host: a\nCapacity: 50%\n"),
+            "{}",
+            response
+        );
+    }
+
+    #[test]
+    fn refuses_connections_without_the_token()
+    {
+        let (address, agents) = collector(Some("secret"));
+        send(address, b"Host: a\n\n");
+        send(address, b"Token: guess\nHost: b\n\n");
+        assert_eq!(send(address, b"list\n"), "");
+        assert!(agents.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn ignores_tokens_it_does_not_need()
+    {
+        let (address, agents) = collector(None);
+        send(address, b"Token: secret\nHost: a\n\n");
+        send(address, b"Host: b\n\n");
+        assert_eq!(agents.lock().unwrap().len(), 2);
+        assert!(agents.lock().unwrap()["a"].fields.is_empty());
+    }
+
+    #[test]
+    fn limits_lines_and_fields()
+    {
+        let (address, agents) = collector(None);
+        let mut long = b"Host: a\nModel: ".to_vec();
+        long.resize(4096, b'x');
+        long.extend(b"\n\n");
+        send(address, &long);
+        let mut many = b"Host: b\n".to_vec();
+        for i in 0..=MAX_FIELDS {
+            many.extend(format!("Key{}: value\n", i).as_bytes());
+        }
+        many.extend(b"\n");
+        send(address, &many);
+        assert!(agents.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn agents_keep_their_name()
+    {
+        let (address, agents) = collector(None);
+        send(
+            address,
+            b"Host: a\nCapacity: 50%\n\nHost: b\nCapacity: 10%\n\n",
+        );
+        let agents = agents.lock().unwrap();
+        assert_eq!(agents.keys().collect::<Vec<_>>(), ["a"]);
+        assert!(!agents["a"].connected);
+    }
+}
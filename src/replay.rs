@@ -0,0 +1,486 @@
+use std::collections::BTreeSet;
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::BufRead;
+use std::io::BufReader;
+use std::io::Write;
+use std::iter::Peekable;
+use std::path::Path;
+use std::path::PathBuf;
+use std::str::Chars;
+use std::time::Duration;
+use std::time::SystemTime;
+
+use lithiumd::MockBackend;
+use lithiumd::MockClock;
+use lithiumd::MockController;
+use lithiumd::PowerEvent;
+use lithiumd::PowerSupply;
+use lithiumd::Snapshot;
+
+use crate::json;
+use crate::json::quote;
+
+/// Writes the state of the power supply and the events it led to as lines of
+/// JSON, for `--record`:
+///
+/// ```text
+/// {"time":1712345678.123,"devices":[...],"events":["unplugged"]}
+/// ```
+///
+/// The devices are in the schema of `devices --json`.
+pub struct Recorder
+{
+    path: PathBuf,
+    file: File,
+}
+
+impl Recorder
+{
+    /// Starts a new recording in `path`, replacing the file.
+    pub fn create(path: PathBuf) -> io::Result<Self>
+    {
+        let file = File::create(&path)?;
+        Ok(Self { path, file })
+    }
+
+    pub fn path(&self) -> &Path
+    {
+        &self.path
+    }
+
+    pub fn record(&mut self, snapshot: &Snapshot, events: &[PowerEvent]) -> io::Result<()>
+    {
+        let time = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default();
+        let events = events
+            .iter()
+            .map(|event| quote(&event.to_string()))
+            .collect::<Vec<_>>();
+        writeln!(
+            self.file,
+            "{{\"time\":{:.3},\"devices\":{},\"events\":[{}]}}",
+            time.as_secs_f64(),
+            json::devices(snapshot),
+            events.join(",")
+        )
+    }
+}
+
+/// Feeds the recording in `path` through a power supply that reads a mock
+/// tree, and prints the events it reports at each step. Where they differ
+/// from the recorded ones, those are printed below.
+pub fn run(path: &Path) -> io::Result<()>
+{
+    let root = std::env::temp_dir().join(format!("lithiumd-replay-{}", std::process::id()));
+    let result = replay(path, &root);
+    let _ = fs::remove_dir_all(&root);
+    result
+}
+
+fn replay(path: &Path, root: &Path) -> io::Result<()>
+{
+    let mock = MockBackend::new(root)?;
+    let controller = mock.controller();
+    let clock = MockClock::new();
+    let mut power_supply = PowerSupply::with_mock(mock);
+    power_supply.set_clock(clock.clone());
+    power_supply.set_monitor_peripherals(true, 20);
+
+    let mut present = BTreeSet::new();
+    let mut start = None;
+    let mut last = 0.0;
+    let mut steps = 0;
+    let mut differing = 0;
+    for (i, line) in BufReader::new(File::open(path)?).lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let step = Step::parse(&line).map_err(|err| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{}:{}: {}", path.display(), i + 1, err),
+            )
+        })?;
+        let start = *start.get_or_insert(step.time);
+        clock.advance(Duration::from_secs_f64((step.time - last).max(0.0)));
+        last = step.time;
+
+        let sysnames = step
+            .devices
+            .iter()
+            .map(|dev| dev.sysname.clone())
+            .collect::<BTreeSet<_>>();
+        for sysname in present.difference(&sysnames) {
+            controller.remove(sysname)?;
+        }
+        for dev in &step.devices {
+            dev.apply(&controller, present.contains(&dev.sysname))?;
+        }
+        present = sysnames;
+        power_supply.update()?;
+
+        let events = power_supply
+            .events()
+            .map(|event| event.to_string())
+            .collect::<Vec<_>>();
+        for event in &events {
+            println!("{:>9.1}s {}", step.time - start, event);
+        }
+        if events != step.events {
+            differing += 1;
+            if step.events.is_empty() {
+                println!("{:>9.1}s   recorded: no events", step.time - start);
+            }
+            for event in &step.events {
+                println!("{:>9.1}s   recorded: {}", step.time - start, event);
+            }
+        }
+        steps += 1;
+    }
+    println!("{} of {} steps differ from the recording", differing, steps);
+    Ok(())
+}
+
+/// A line of a recording.
+struct Step
+{
+    /// Seconds since the epoch.
+    time: f64,
+    devices: Vec<Device>,
+    events: Vec<String>,
+}
+
+/// A device of a recording, with the attributes it is written to the mock
+/// tree with.
+struct Device
+{
+    sysname: String,
+    attributes: Vec<(&'static str, String)>,
+}
+
+impl Step
+{
+    fn parse(line: &str) -> Result<Self, String>
+    {
+        let value = Parser::new(line).parse()?;
+        let time = value
+            .get("time")
+            .and_then(Value::number)
+            .ok_or("expected a number `time`")?;
+        let devices = value
+            .get("devices")
+            .and_then(Value::array)
+            .ok_or("expected an array `devices`")?
+            .iter()
+            .map(Device::parse)
+            .collect::<Result<_, _>>()?;
+        let events = value
+            .get("events")
+            .and_then(Value::array)
+            .ok_or("expected an array `events`")?
+            .iter()
+            .map(|event| event.string().map(str::to_string))
+            .collect::<Option<_>>()
+            .ok_or("expected the events to be strings")?;
+        Ok(Self {
+            time,
+            devices,
+            events,
+        })
+    }
+}
+
+impl Device
+{
+    /// Translates the fields of `devices --json` back to sysfs attributes.
+    /// Unknown values are written as empty attributes, which read as `None`.
+    fn parse(value: &Value) -> Result<Self, String>
+    {
+        let field = |name: &str| value.get(name).filter(|value| **value != Value::Null);
+        let string = |name: &str| field(name).and_then(Value::string);
+        let number = |name: &str| {
+            field(name)
+                .and_then(Value::number)
+                .map_or_else(String::new, |number| number.to_string())
+        };
+        let sysname = string("sysname").ok_or("expected a string `sysname`")?;
+        let scope = match string("scope") {
+            Some("device") => "Device",
+            _ => "System",
+        };
+        let mut attributes = vec![("scope", scope.to_string())];
+        if let Some(model) = string("model") {
+            attributes.push(("model_name", model.to_string()));
+        }
+        let status = string("status").unwrap_or_default();
+        match string("type") {
+            Some("battery") => {
+                let status = match status {
+                    "charging" => "Charging",
+                    "discharging" => "Discharging",
+                    "full" => "Full",
+                    "not_charging" => "Not charging",
+                    _ => "Unknown",
+                };
+                let (full, now, power, unused) = match field("reports_energy") {
+                    Some(Value::Bool(true)) => (
+                        "energy_full",
+                        "energy_now",
+                        "power_now",
+                        ["charge_full", "charge_now", "current_now"],
+                    ),
+                    _ => (
+                        "charge_full",
+                        "charge_now",
+                        "current_now",
+                        ["energy_full", "energy_now", "power_now"],
+                    ),
+                };
+                attributes.extend([
+                    ("type", "Battery".to_string()),
+                    ("status", status.to_string()),
+                    ("capacity", number("capacity")),
+                    (full, number("energy_full")),
+                    (now, number("energy_now")),
+                    (power, number("power_now")),
+                ]);
+                attributes.extend(unused.map(|name| (name, String::new())));
+            }
+            Some(device_type @ ("adapter" | "wireless")) => {
+                let online = match status {
+                    "charging" => "1",
+                    "discharging" => "0",
+                    _ => "",
+                };
+                let device_type = if device_type == "adapter" {
+                    "Mains"
+                } else {
+                    "Wireless"
+                };
+                attributes.extend([
+                    ("type", device_type.to_string()),
+                    ("online", online.to_string()),
+                ]);
+            }
+            _ => return Err(format!("{}: expected a `type`", sysname)),
+        }
+        Ok(Self {
+            sysname: sysname.to_string(),
+            attributes,
+        })
+    }
+
+    /// Writes the device to the mock tree.
+    fn apply(&self, controller: &MockController, present: bool) -> io::Result<()>
+    {
+        if !present {
+            let attributes = self
+                .attributes
+                .iter()
+                .map(|(name, value)| (*name, value.as_str()))
+                .collect::<Vec<_>>();
+            return controller.add(&self.sysname, &attributes);
+        }
+        for (name, value) in &self.attributes {
+            controller.set(&self.sysname, name, value)?;
+        }
+        Ok(())
+    }
+}
+
+/// A JSON value, as far as recordings need one.
+#[derive(Debug, Clone, PartialEq)]
+enum Value
+{
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Value>),
+    Object(Vec<(String, Value)>),
+}
+
+impl Value
+{
+    fn get(&self, key: &str) -> Option<&Value>
+    {
+        match self {
+            Self::Object(fields) => fields
+                .iter()
+                .find(|(name, _)| name == key)
+                .map(|(_, value)| value),
+            _ => None,
+        }
+    }
+
+    fn number(&self) -> Option<f64>
+    {
+        match self {
+            Self::Number(number) => Some(*number),
+            _ => None,
+        }
+    }
+
+    fn string(&self) -> Option<&str>
+    {
+        match self {
+            Self::String(string) => Some(string),
+            _ => None,
+        }
+    }
+
+    fn array(&self) -> Option<&[Value]>
+    {
+        match self {
+            Self::Array(values) => Some(values),
+            _ => None,
+        }
+    }
+}
+
+struct Parser<'a>
+{
+    chars: Peekable<Chars<'a>>,
+}
+
+impl<'a> Parser<'a>
+{
+    fn new(s: &'a str) -> Self
+    {
+        Self {
+            chars: s.chars().peekable(),
+        }
+    }
+
+    fn parse(mut self) -> Result<Value, String>
+    {
+        let value = self.value()?;
+        self.skip_whitespace();
+        match self.chars.next() {
+            None => Ok(value),
+            Some(c) => Err(format!("unexpected `{}` after the value", c)),
+        }
+    }
+
+    fn value(&mut self) -> Result<Value, String>
+    {
+        self.skip_whitespace();
+        match self.chars.peek() {
+            Some('{') => {
+                self.chars.next();
+                let mut fields = Vec::new();
+                self.list('}', |parser| {
+                    parser.skip_whitespace();
+                    let name = parser.string()?;
+                    parser.skip_whitespace();
+                    parser.expect(':')?;
+                    fields.push((name, parser.value()?));
+                    Ok(())
+                })?;
+                Ok(Value::Object(fields))
+            }
+            Some('[') => {
+                self.chars.next();
+                let mut values = Vec::new();
+                self.list(']', |parser| {
+                    values.push(parser.value()?);
+                    Ok(())
+                })?;
+                Ok(Value::Array(values))
+            }
+            Some('"') => Ok(Value::String(self.string()?)),
+            Some('-' | '0'..='9') => {
+                let mut number = String::new();
+                while let Some(c) = self
+                    .chars
+                    .next_if(|c| matches!(c, '-' | '+' | '.' | 'e' | 'E' | '0'..='9'))
+                {
+                    number.push(c);
+                }
+                number
+                    .parse()
+                    .map(Value::Number)
+                    .map_err(|_| format!("`{}` is not a number", number))
+            }
+            Some(_) => {
+                let mut word = String::new();
+                while let Some(c) = self.chars.next_if(char::is_ascii_alphabetic) {
+                    word.push(c);
+                }
+                match word.as_str() {
+                    "null" => Ok(Value::Null),
+                    "true" => Ok(Value::Bool(true)),
+                    "false" => Ok(Value::Bool(false)),
+                    _ => Err(format!("unexpected `{}`", word)),
+                }
+            }
+            None => Err("unexpected end of line".to_string()),
+        }
+    }
+
+    /// Parses comma separated items with `item` up to `end`.
+    fn list(
+        &mut self,
+        end: char,
+        mut item: impl FnMut(&mut Self) -> Result<(), String>,
+    ) -> Result<(), String>
+    {
+        self.skip_whitespace();
+        if self.chars.next_if_eq(&end).is_some() {
+            return Ok(());
+        }
+        loop {
+            item(self)?;
+            self.skip_whitespace();
+            match self.chars.next() {
+                Some(',') => {}
+                Some(c) if c == end => return Ok(()),
+                _ => return Err(format!("expected `,` or `{}`", end)),
+            }
+        }
+    }
+
+    fn string(&mut self) -> Result<String, String>
+    {
+        self.expect('"')?;
+        let mut string = String::new();
+        loop {
+            match self.chars.next() {
+                Some('"') => return Ok(string),
+                Some('\\') => match self.chars.next() {
+                    Some('n') => string.push('\n'),
+                    Some('t') => string.push('\t'),
+                    Some('u') => {
+                        let code = (0..4).filter_map(|_| self.chars.next()).collect::<String>();
+                        let c = u32::from_str_radix(&code, 16)
+                            .ok()
+                            .and_then(char::from_u32)
+                            .ok_or_else(|| format!("invalid escape `\\u{}`", code))?;
+                        string.push(c);
+                    }
+                    Some(c) => string.push(c),
+                    None => return Err("unterminated string".to_string()),
+                },
+                Some(c) => string.push(c),
+                None => return Err("unterminated string".to_string()),
+            }
+        }
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), String>
+    {
+        match self.chars.next() {
+            Some(c) if c == expected => Ok(()),
+            _ => Err(format!("expected `{}`", expected)),
+        }
+    }
+
+    fn skip_whitespace(&mut self)
+    {
+        while self.chars.next_if(|c| c.is_whitespace()).is_some() {}
+    }
+}
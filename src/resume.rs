@@ -0,0 +1,169 @@
+use std::io;
+use std::time::Duration;
+
+#[cfg(feature = "mio")]
+use mio::event::Source;
+
+use crate::dbus::Connection;
+use crate::dbus::MessageType;
+use crate::dbus::Value;
+
+/// How much more `CLOCK_BOOTTIME` has to advance than `CLOCK_MONOTONIC` for
+/// the gap to count as a suspend.
+const MIN_SUSPEND: Duration = Duration::from_secs(1);
+
+/// Notices that the system resumed from suspend, from logind's
+/// `PrepareForSleep` signal and from `CLOCK_BOOTTIME` running ahead of
+/// `CLOCK_MONOTONIC`, as only the former keeps counting while suspended. The
+/// clocks also catch resumes without logind, and whichever notices a resume
+/// first reports it.
+///
+/// Register it with a mio `Poll` and call [`ResumeDetector::process`] when it
+/// becomes readable. [`ResumeDetector::check`] compares the clocks and is
+/// meant to be called on every wakeup.
+pub struct ResumeDetector
+{
+    conn: Option<Connection>,
+    /// Why there is no connection to the system bus.
+    error: Option<io::Error>,
+    /// `PrepareForSleep(true)` was received and no resume was noticed since.
+    sleeping: bool,
+    /// Time spent suspended since boot, as of the last check.
+    suspended: Duration,
+}
+
+impl ResumeDetector
+{
+    /// Subscribes to `PrepareForSleep` on the system bus. Without a system
+    /// bus, only the clocks are compared, see
+    /// [`ResumeDetector::logind_error`].
+    #[must_use]
+    pub fn new() -> Self
+    {
+        let conn = Connection::system().and_then(|mut conn| {
+            conn.add_match(
+                "type='signal',sender='org.freedesktop.login1',interface='org.freedesktop.login1.Manager',member='PrepareForSleep'",
+            )?;
+            Ok(conn)
+        });
+        let (conn, error) = match conn {
+            Ok(conn) => (Some(conn), None),
+            Err(err) => (None, Some(err)),
+        };
+        Self {
+            conn,
+            error,
+            sleeping: false,
+            suspended: suspended(),
+        }
+    }
+
+    /// Returns why logind is not watched for suspends, if it is not.
+    #[must_use]
+    pub fn logind_error(&self) -> Option<&io::Error>
+    {
+        self.error.as_ref()
+    }
+
+    /// Reads the pending signals and returns whether the system resumed.
+    pub fn process(&mut self) -> io::Result<bool>
+    {
+        let Some(conn) = &mut self.conn else {
+            return Ok(false);
+        };
+        let mut resumed = false;
+        while let Some(message) = conn.read()? {
+            if message.message_type != MessageType::Signal
+                || message.member.as_deref() != Some("PrepareForSleep")
+            {
+                continue;
+            }
+            match message.body.first() {
+                Some(Value::Bool(true)) => self.sleeping = true,
+                Some(Value::Bool(false)) if self.sleeping => {
+                    self.sleeping = false;
+                    resumed = true;
+                }
+                _ => {}
+            }
+        }
+        if resumed {
+            self.suspended = suspended();
+        }
+        Ok(resumed)
+    }
+
+    /// Returns whether the system was suspended since the last call.
+    pub fn check(&mut self) -> bool
+    {
+        let suspended = suspended();
+        if suspended < self.suspended + MIN_SUSPEND {
+            return false;
+        }
+        self.suspended = suspended;
+        self.sleeping = false;
+        true
+    }
+}
+
+impl Default for ResumeDetector
+{
+    fn default() -> Self
+    {
+        Self::new()
+    }
+}
+
+/// Returns how long the system was suspended since boot.
+fn suspended() -> Duration
+{
+    clock(libc::CLOCK_BOOTTIME).saturating_sub(clock(libc::CLOCK_MONOTONIC))
+}
+
+fn clock(id: libc::clockid_t) -> Duration
+{
+    let mut ts = libc::timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
+    };
+    unsafe { libc::clock_gettime(id, &mut ts) };
+    Duration::new(ts.tv_sec as u64, ts.tv_nsec as u32)
+}
+
+#[cfg(feature = "mio")]
+impl Source for ResumeDetector
+{
+    fn register(
+        &mut self,
+        registry: &mio::Registry,
+        token: mio::Token,
+        interests: mio::Interest,
+    ) -> io::Result<()>
+    {
+        match &mut self.conn {
+            Some(conn) => conn.register(registry, token, interests),
+            None => Ok(()),
+        }
+    }
+
+    fn reregister(
+        &mut self,
+        registry: &mio::Registry,
+        token: mio::Token,
+        interests: mio::Interest,
+    ) -> io::Result<()>
+    {
+        match &mut self.conn {
+            Some(conn) => conn.reregister(registry, token, interests),
+            None => Ok(()),
+        }
+    }
+
+    fn deregister(&mut self, registry: &mio::Registry) -> io::Result<()>
+    {
+        match &mut self.conn {
+            Some(conn) => conn.deregister(registry),
+            None => Ok(()),
+        }
+    }
+}
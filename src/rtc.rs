@@ -0,0 +1,27 @@
+use std::io;
+use std::path::Path;
+use std::time::Duration;
+
+use crate::privileges;
+
+const WAKEALARM: &str = "/sys/class/rtc/rtc0/wakealarm";
+
+/// Programs the RTC to wake the system `after` from now, even from
+/// hibernation. An alarm that is already set is replaced.
+pub fn set_wakealarm(after: Duration) -> io::Result<()>
+{
+    // The kernel refuses a new alarm while one is pending.
+    clear_wakealarm()?;
+    write(&format!("+{}", after.as_secs()))
+}
+
+/// Cancels the pending alarm, if any.
+pub fn clear_wakealarm() -> io::Result<()>
+{
+    write("0")
+}
+
+fn write(value: &str) -> io::Result<()>
+{
+    privileges::write(Path::new(WAKEALARM), value)
+}
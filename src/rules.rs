@@ -0,0 +1,561 @@
+use std::fmt::Display;
+use std::time::Duration;
+
+use crate::backlight::BrightnessChange;
+use crate::backlight::KeyboardBrightness;
+use crate::cpufreq::CpuPolicy;
+use crate::expr::Expr;
+use crate::hooks::HookPolicy;
+use crate::power_supply::Status;
+use crate::runtime_pm::PciePolicy;
+use crate::runtime_pm::UsbAutosuspend;
+use crate::snapshot::Snapshot;
+use crate::wifi::WifiPowerSave;
+
+/// A condition on the state of the power supply.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Condition
+{
+    /// The system runs on battery.
+    OnBattery,
+    /// The system runs on AC power.
+    OnAc,
+    /// The battery capacity is below the given percentage.
+    CapacityBelow(u8),
+    /// The battery capacity is above the given percentage.
+    CapacityAbove(u8),
+}
+
+impl Condition
+{
+    #[must_use]
+    pub fn matches(&self, snapshot: &Snapshot) -> bool
+    {
+        match *self {
+            Condition::OnBattery => snapshot.status == Status::Discharging,
+            Condition::OnAc => snapshot.status.is_on_ac(),
+            Condition::CapacityBelow(percent) => snapshot
+                .capacity()
+                .is_some_and(|capacity| capacity < percent),
+            Condition::CapacityAbove(percent) => snapshot
+                .capacity()
+                .is_some_and(|capacity| capacity > percent),
+        }
+    }
+
+    /// Returns the condition with its capacity threshold moved by `margin`
+    /// percent in the direction that makes it match longer.
+    #[must_use]
+    pub fn relaxed(self, margin: u8) -> Self
+    {
+        match self {
+            Condition::CapacityBelow(percent) => {
+                Condition::CapacityBelow(percent.saturating_add(margin))
+            }
+            Condition::CapacityAbove(percent) => {
+                Condition::CapacityAbove(percent.saturating_sub(margin))
+            }
+            condition => condition,
+        }
+    }
+}
+
+impl Display for Condition
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result
+    {
+        match *self {
+            Condition::OnBattery => write!(f, "on battery"),
+            Condition::OnAc => write!(f, "on AC"),
+            Condition::CapacityBelow(percent) => write!(f, "capacity below {}%", percent),
+            Condition::CapacityAbove(percent) => write!(f, "capacity above {}%", percent),
+        }
+    }
+}
+
+/// What to do when a rule starts matching.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Action
+{
+    /// Runs a shell command, see [`HookRunner`](crate::HookRunner).
+    RunHook(RunHook),
+    /// Switches the profile of power-profiles-daemon, see
+    /// [`PowerProfiles`](crate::PowerProfiles).
+    SetPowerProfile(String),
+    /// Sets the cpufreq governor and energy preference of all CPUs.
+    SetCpuPolicy(CpuPolicy),
+    /// Changes the brightness of all backlights, see
+    /// [`BacklightFader`](crate::BacklightFader).
+    SetBrightness(BrightnessChange),
+    /// Changes the level of the keyboard backlights, see
+    /// [`KeyboardBacklight`](crate::KeyboardBacklight).
+    SetKeyboardBacklight(KeyboardBrightness),
+    /// Hibernates the system, see [`Hibernate`].
+    Hibernate(Hibernate),
+    /// Suspends the system through logind.
+    Suspend,
+    /// Turns power saving of wireless interfaces on or off.
+    SetWifiPowerSave(WifiPowerSave),
+    /// Lets USB devices autosuspend or keeps them powered.
+    SetUsbAutosuspend(UsbAutosuspend),
+    /// Sets the runtime power management and ASPM policy of PCIe devices.
+    SetPciePolicy(PciePolicy),
+}
+
+/// Typed form of [`Condition::OnBattery`] for the rule builder.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OnBattery;
+
+/// Typed form of [`Condition::OnAc`] for the rule builder.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OnAc;
+
+/// Typed form of [`Condition::CapacityBelow`] for the rule builder.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CapacityBelow(pub u8);
+
+/// Typed form of [`Condition::CapacityAbove`] for the rule builder.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CapacityAbove(pub u8);
+
+/// A shell command that a rule runs, and how long it may run and what
+/// happens when it fails.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RunHook
+{
+    pub command: String,
+    pub policy: HookPolicy,
+}
+
+/// Typed form of [`Action::SetPowerProfile`] for the rule builder.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SetPowerProfile(pub String);
+
+impl SetPowerProfile
+{
+    #[must_use]
+    pub fn new(profile: impl Into<String>) -> Self
+    {
+        Self(profile.into())
+    }
+}
+
+/// Typed form of [`Action::Suspend`] for the rule builder.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Suspend;
+
+/// Hibernates the system, typically at a critical capacity. With
+/// `wake_after`, the RTC wakes the system up again after that long, see
+/// [`rtc::set_wakealarm`](crate::rtc::set_wakealarm), so that it can warn
+/// again or shut down cleanly instead of dying in hibernation.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Hibernate
+{
+    pub wake_after: Option<Duration>,
+}
+
+impl RunHook
+{
+    #[must_use]
+    pub fn new(command: impl Into<String>) -> Self
+    {
+        Self {
+            command: command.into(),
+            policy: HookPolicy::default(),
+        }
+    }
+
+    #[must_use]
+    pub fn policy(mut self, policy: HookPolicy) -> Self
+    {
+        self.policy = policy;
+        self
+    }
+}
+
+impl From<OnBattery> for Condition
+{
+    fn from(_: OnBattery) -> Self
+    {
+        Condition::OnBattery
+    }
+}
+
+impl From<OnAc> for Condition
+{
+    fn from(_: OnAc) -> Self
+    {
+        Condition::OnAc
+    }
+}
+
+impl From<CapacityBelow> for Condition
+{
+    fn from(CapacityBelow(percent): CapacityBelow) -> Self
+    {
+        Condition::CapacityBelow(percent)
+    }
+}
+
+impl From<CapacityAbove> for Condition
+{
+    fn from(CapacityAbove(percent): CapacityAbove) -> Self
+    {
+        Condition::CapacityAbove(percent)
+    }
+}
+
+impl From<RunHook> for Action
+{
+    fn from(hook: RunHook) -> Self
+    {
+        Action::RunHook(hook)
+    }
+}
+
+impl From<CpuPolicy> for Action
+{
+    fn from(policy: CpuPolicy) -> Self
+    {
+        Action::SetCpuPolicy(policy)
+    }
+}
+
+impl From<WifiPowerSave> for Action
+{
+    fn from(power_save: WifiPowerSave) -> Self
+    {
+        Action::SetWifiPowerSave(power_save)
+    }
+}
+
+impl From<UsbAutosuspend> for Action
+{
+    fn from(autosuspend: UsbAutosuspend) -> Self
+    {
+        Action::SetUsbAutosuspend(autosuspend)
+    }
+}
+
+impl From<PciePolicy> for Action
+{
+    fn from(policy: PciePolicy) -> Self
+    {
+        Action::SetPciePolicy(policy)
+    }
+}
+
+impl From<BrightnessChange> for Action
+{
+    fn from(change: BrightnessChange) -> Self
+    {
+        Action::SetBrightness(change)
+    }
+}
+
+impl From<KeyboardBrightness> for Action
+{
+    fn from(brightness: KeyboardBrightness) -> Self
+    {
+        Action::SetKeyboardBacklight(brightness)
+    }
+}
+
+impl From<Hibernate> for Action
+{
+    fn from(hibernate: Hibernate) -> Self
+    {
+        Action::Hibernate(hibernate)
+    }
+}
+
+impl From<Suspend> for Action
+{
+    fn from(_: Suspend) -> Self
+    {
+        Action::Suspend
+    }
+}
+
+impl From<SetPowerProfile> for Action
+{
+    fn from(SetPowerProfile(profile): SetPowerProfile) -> Self
+    {
+        Action::SetPowerProfile(profile)
+    }
+}
+
+/// A set of conditions that all have to match, and the actions to take when
+/// they start to.
+///
+/// Rules are built with [`Rule::when`], which mirrors the `[[rule]]` tables of
+/// the configuration file:
+///
+/// ```
+/// use lithiumd::rules::CapacityBelow;
+/// use lithiumd::rules::OnBattery;
+/// use lithiumd::rules::Rule;
+/// use lithiumd::rules::RunHook;
+///
+/// let rule = Rule::when(OnBattery)
+///     .and(CapacityBelow(20))
+///     .then(RunHook::new("notify-send 'Battery low'"));
+/// assert_eq!(rule.conditions().len(), 2);
+/// ```
+///
+/// A rule cannot be created without at least one condition, or an
+/// [`Expr`], and one action.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rule
+{
+    conditions: Vec<Condition>,
+    expr: Option<Expr>,
+    actions: Vec<Action>,
+    inhibitable: bool,
+    hysteresis: u8,
+}
+
+/// A rule that has conditions but no action yet, returned by [`Rule::when`].
+#[must_use = "a rule without an action does nothing, call `then`"]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RuleBuilder
+{
+    conditions: Vec<Condition>,
+    expr: Option<Expr>,
+}
+
+impl Rule
+{
+    pub fn when(condition: impl Into<Condition>) -> RuleBuilder
+    {
+        RuleBuilder {
+            conditions: vec![condition.into()],
+            expr: None,
+        }
+    }
+
+    /// Starts a rule whose condition is `expr`:
+    ///
+    /// ```
+    /// use lithiumd::rules::Rule;
+    /// use lithiumd::rules::RunHook;
+    /// use lithiumd::Expr;
+    ///
+    /// let expr = Expr::parse("discharging && capacity < 30")?;
+    /// let rule = Rule::when_expr(expr).then(RunHook::new("notify-send 'Battery low'"));
+    /// assert!(rule.conditions().is_empty());
+    /// # Ok::<(), String>(())
+    /// ```
+    pub fn when_expr(expr: Expr) -> RuleBuilder
+    {
+        RuleBuilder {
+            conditions: Vec::new(),
+            expr: Some(expr),
+        }
+    }
+
+    /// Marks the rule as not critical, so that it does not fire while the
+    /// rule engine is inhibited, e.g. for notifications during a
+    /// presentation.
+    #[must_use]
+    pub fn inhibitable(mut self) -> Self
+    {
+        self.inhibitable = true;
+        self
+    }
+
+    #[must_use]
+    pub fn is_inhibitable(&self) -> bool
+    {
+        self.inhibitable
+    }
+
+    /// Only re-arms the rule once the capacity moved `margin` percent past
+    /// its thresholds, so that a capacity wavering around one, e.g. between
+    /// 15% and 16%, does not fire the rule over and over:
+    ///
+    /// ```
+    /// use lithiumd::rules::CapacityBelow;
+    /// use lithiumd::rules::Rule;
+    /// use lithiumd::rules::RunHook;
+    ///
+    /// // Fires below 16% and again only after the capacity was above 18%.
+    /// let rule = Rule::when(CapacityBelow(16))
+    ///     .then(RunHook::new("notify-send 'Battery low'"))
+    ///     .hysteresis(3);
+    /// assert_eq!(rule.margin(), 3);
+    /// ```
+    #[must_use]
+    pub fn hysteresis(mut self, margin: u8) -> Self
+    {
+        self.hysteresis = margin;
+        self
+    }
+
+    /// Returns the hysteresis margin in percent.
+    #[must_use]
+    pub fn margin(&self) -> u8
+    {
+        self.hysteresis
+    }
+
+    /// Adds another action to the rule.
+    #[must_use]
+    pub fn and_then(mut self, action: impl Into<Action>) -> Self
+    {
+        self.actions.push(action.into());
+        self
+    }
+
+    #[must_use]
+    pub fn conditions(&self) -> &[Condition]
+    {
+        &self.conditions
+    }
+
+    #[must_use]
+    pub fn expr(&self) -> Option<&Expr>
+    {
+        self.expr.as_ref()
+    }
+
+    #[must_use]
+    pub fn actions(&self) -> &[Action]
+    {
+        &self.actions
+    }
+
+    /// Returns whether all conditions of the rule match `snapshot`.
+    #[must_use]
+    pub fn matches(&self, snapshot: &Snapshot) -> bool
+    {
+        self.conditions
+            .iter()
+            .all(|condition| condition.matches(snapshot))
+            && self.expr.as_ref().is_none_or(|expr| expr.matches(snapshot))
+    }
+
+    /// Returns whether the rule still counts as matching `snapshot` after it
+    /// matched before, which the hysteresis margin extends. It does not
+    /// extend the expression.
+    #[must_use]
+    pub fn still_matches(&self, snapshot: &Snapshot) -> bool
+    {
+        self.conditions
+            .iter()
+            .all(|condition| condition.relaxed(self.hysteresis).matches(snapshot))
+            && self.expr.as_ref().is_none_or(|expr| expr.matches(snapshot))
+    }
+}
+
+impl RuleBuilder
+{
+    /// Adds another condition that has to match.
+    pub fn and(mut self, condition: impl Into<Condition>) -> Self
+    {
+        self.conditions.push(condition.into());
+        self
+    }
+
+    /// Adds an expression that has to hold, along with any added before.
+    pub fn and_expr(mut self, expr: Expr) -> Self
+    {
+        self.expr = Some(match self.expr.take() {
+            Some(before) => before.and(expr),
+            None => expr,
+        });
+        self
+    }
+
+    /// Completes the rule with its first action.
+    #[must_use]
+    pub fn then(self, action: impl Into<Action>) -> Rule
+    {
+        Rule {
+            conditions: self.conditions,
+            expr: self.expr,
+            actions: vec![action.into()],
+            inhibitable: false,
+            hysteresis: 0,
+        }
+    }
+}
+
+/// Evaluates rules against successive snapshots. A rule fires once when it
+/// starts matching and again only after it stopped matching in between, by
+/// its [hysteresis](Rule::hysteresis) margin.
+#[derive(Debug, Clone, Default)]
+pub struct RuleEngine
+{
+    rules: Vec<Rule>,
+    matching: Vec<bool>,
+    inhibited: bool,
+}
+
+impl RuleEngine
+{
+    #[must_use]
+    pub fn new(rules: Vec<Rule>) -> Self
+    {
+        Self {
+            matching: vec![false; rules.len()],
+            rules,
+            inhibited: false,
+        }
+    }
+
+    /// While inhibited, [inhibitable](Rule::inhibitable) rules do not fire.
+    /// Their state is still tracked, so they do not fire late either when
+    /// the inhibition ends.
+    pub fn set_inhibited(&mut self, inhibited: bool)
+    {
+        self.inhibited = inhibited;
+    }
+
+    #[must_use]
+    pub fn is_inhibited(&self) -> bool
+    {
+        self.inhibited
+    }
+
+    #[must_use]
+    pub fn rules(&self) -> &[Rule]
+    {
+        &self.rules
+    }
+
+    /// Forgets which rules matched, so that the ones that still match fire
+    /// again on the next evaluation.
+    pub fn reset(&mut self)
+    {
+        self.matching.fill(false);
+    }
+
+    /// Evaluates all rules against `snapshot` and returns the actions of the
+    /// rules that started matching.
+    pub fn evaluate(&mut self, snapshot: &Snapshot) -> Vec<&Action>
+    {
+        self.fire(snapshot)
+            .into_iter()
+            .flat_map(Rule::actions)
+            .collect()
+    }
+
+    /// Evaluates all rules like [`RuleEngine::evaluate`], but returns the
+    /// rules that started matching.
+    pub fn fire(&mut self, snapshot: &Snapshot) -> Vec<&Rule>
+    {
+        let mut fired = Vec::new();
+        for (rule, matching) in self.rules.iter().zip(&mut self.matching) {
+            if *matching {
+                *matching = rule.still_matches(snapshot);
+                continue;
+            }
+            *matching = rule.matches(snapshot);
+            if *matching && !(self.inhibited && rule.inhibitable) {
+                fired.push(rule);
+            }
+        }
+        fired
+    }
+}
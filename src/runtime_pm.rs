@@ -0,0 +1,117 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::privileges::write;
+
+const USB_DEVICES: &str = "/sys/bus/usb/devices";
+const PCI_DEVICES: &str = "/sys/bus/pci/devices";
+const ASPM_POLICY: &str = "/sys/module/pcie_aspm/parameters/policy";
+
+/// Runtime power management of USB devices, set through their
+/// `power/control` attribute: `auto` lets idle devices autosuspend, `on`
+/// keeps them powered.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct UsbAutosuspend
+{
+    pub enabled: bool,
+    /// Devices that are left alone, as `vendor:product` ids like `lsusb`
+    /// prints them, e.g. `046d:c52b`.
+    pub deny: Vec<String>,
+    /// Whether keyboards and mice autosuspend too. Many take a keypress or
+    /// a movement to wake up, which is then lost.
+    pub input: bool,
+}
+
+impl UsbAutosuspend
+{
+    /// Writes the policy to every USB device that is not denied. Devices
+    /// that vanish in the meantime are skipped.
+    pub fn apply(&self) -> io::Result<()>
+    {
+        let control = if self.enabled { "auto" } else { "on" };
+        for entry in fs::read_dir(USB_DEVICES)? {
+            let dir = entry?.path();
+            // Interfaces, e.g. 1-2:1.0, have no ids of their own.
+            let (Ok(vendor), Ok(product)) = (
+                fs::read_to_string(dir.join("idVendor")),
+                fs::read_to_string(dir.join("idProduct")),
+            ) else {
+                continue;
+            };
+            let id = format!("{}:{}", vendor.trim(), product.trim());
+            if self.deny.iter().any(|deny| deny.eq_ignore_ascii_case(&id)) {
+                continue;
+            }
+            if self.enabled && !self.input && is_input(&dir) {
+                continue;
+            }
+            set_control(&dir, control)?;
+        }
+        Ok(())
+    }
+}
+
+/// Returns whether one of the interfaces of the USB device at `dir` is a
+/// boot keyboard or mouse.
+fn is_input(dir: &Path) -> bool
+{
+    let Ok(entries) = fs::read_dir(dir) else {
+        return false;
+    };
+    entries.flatten().any(|entry| {
+        let interface = entry.path();
+        let read = |name| fs::read_to_string(interface.join(name)).unwrap_or_default();
+        read("bInterfaceClass").trim() == "03"
+            && matches!(read("bInterfaceProtocol").trim(), "01" | "02")
+    })
+}
+
+/// Power management of PCIe devices and links for one power source, like
+/// `powertop --auto-tune` sets it. Settings that are `None` are left alone.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PciePolicy
+{
+    /// Whether idle PCI devices are suspended, written to the
+    /// `power/control` attribute of each.
+    pub runtime_pm: Option<bool>,
+    /// The ASPM policy of the links, e.g. `default`, `performance`,
+    /// `powersave` or `powersupersave`.
+    pub aspm: Option<String>,
+}
+
+impl PciePolicy
+{
+    #[must_use]
+    pub fn is_empty(&self) -> bool
+    {
+        self.runtime_pm.is_none() && self.aspm.is_none()
+    }
+
+    /// Writes the policy to sysfs. The ASPM policy cannot be changed when
+    /// the firmware keeps control of ASPM or it is disabled on the kernel
+    /// command line, which is reported as an error.
+    pub fn apply(&self) -> io::Result<()>
+    {
+        if let Some(aspm) = &self.aspm {
+            write(Path::new(ASPM_POLICY), aspm)?;
+        }
+        if let Some(runtime_pm) = self.runtime_pm {
+            let control = if runtime_pm { "auto" } else { "on" };
+            for entry in fs::read_dir(PCI_DEVICES)? {
+                set_control(&entry?.path(), control)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Writes the `power/control` attribute of the device at `dir`, skipping
+/// devices that vanished or have none.
+fn set_control(dir: &Path, control: &str) -> io::Result<()>
+{
+    match write(&dir.join("power/control"), control) {
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(()),
+        result => result,
+    }
+}
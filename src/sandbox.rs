@@ -0,0 +1,336 @@
+use std::io;
+
+/// System calls the daemon makes once it is running: polling, reading sysfs
+/// and sockets, D-Bus and the control socket, name lookups, threads for fades
+/// and the hooks it spawns, which switch to `[hooks] user` before they run.
+const ALLOWED: &[libc::c_long] = &[
+    libc::SYS_read,
+    libc::SYS_write,
+    libc::SYS_pread64,
+    libc::SYS_pwrite64,
+    libc::SYS_readv,
+    libc::SYS_writev,
+    libc::SYS_close,
+    libc::SYS_openat,
+    libc::SYS_newfstatat,
+    libc::SYS_fstat,
+    libc::SYS_statx,
+    libc::SYS_lseek,
+    libc::SYS_getdents64,
+    libc::SYS_readlinkat,
+    libc::SYS_faccessat,
+    libc::SYS_faccessat2,
+    libc::SYS_unlinkat,
+    libc::SYS_renameat2,
+    libc::SYS_mkdirat,
+    libc::SYS_fsync,
+    libc::SYS_fdatasync,
+    libc::SYS_ftruncate,
+    libc::SYS_fcntl,
+    libc::SYS_ioctl,
+    libc::SYS_dup,
+    libc::SYS_dup3,
+    libc::SYS_close_range,
+    libc::SYS_fstatfs,
+    libc::SYS_statfs,
+    libc::SYS_umask,
+    libc::SYS_chdir,
+    libc::SYS_fchdir,
+    libc::SYS_pipe2,
+    libc::SYS_getcwd,
+    libc::SYS_mmap,
+    libc::SYS_munmap,
+    libc::SYS_mprotect,
+    libc::SYS_mremap,
+    libc::SYS_madvise,
+    libc::SYS_brk,
+    libc::SYS_epoll_create1,
+    libc::SYS_epoll_ctl,
+    libc::SYS_epoll_pwait,
+    libc::SYS_epoll_pwait2,
+    libc::SYS_ppoll,
+    libc::SYS_eventfd2,
+    libc::SYS_timerfd_create,
+    libc::SYS_timerfd_settime,
+    libc::SYS_signalfd4,
+    libc::SYS_socket,
+    libc::SYS_socketpair,
+    libc::SYS_connect,
+    libc::SYS_bind,
+    libc::SYS_listen,
+    libc::SYS_accept4,
+    libc::SYS_getsockname,
+    libc::SYS_getpeername,
+    libc::SYS_getsockopt,
+    libc::SYS_setsockopt,
+    libc::SYS_sendto,
+    libc::SYS_sendmsg,
+    libc::SYS_sendmmsg,
+    libc::SYS_recvfrom,
+    libc::SYS_recvmsg,
+    libc::SYS_shutdown,
+    libc::SYS_clock_gettime,
+    libc::SYS_clock_nanosleep,
+    libc::SYS_nanosleep,
+    libc::SYS_gettimeofday,
+    libc::SYS_futex,
+    libc::SYS_rt_sigaction,
+    libc::SYS_rt_sigprocmask,
+    libc::SYS_rt_sigreturn,
+    libc::SYS_rt_sigtimedwait,
+    libc::SYS_sigaltstack,
+    libc::SYS_getpid,
+    libc::SYS_getppid,
+    libc::SYS_getpgid,
+    libc::SYS_getresuid,
+    libc::SYS_getresgid,
+    libc::SYS_getgroups,
+    libc::SYS_gettid,
+    libc::SYS_getuid,
+    libc::SYS_geteuid,
+    libc::SYS_getgid,
+    libc::SYS_getegid,
+    libc::SYS_getrandom,
+    libc::SYS_uname,
+    libc::SYS_sysinfo,
+    libc::SYS_prlimit64,
+    libc::SYS_sched_getaffinity,
+    libc::SYS_sched_yield,
+    libc::SYS_set_robust_list,
+    libc::SYS_set_tid_address,
+    libc::SYS_rseq,
+    libc::SYS_clone,
+    libc::SYS_clone3,
+    libc::SYS_execve,
+    libc::SYS_wait4,
+    libc::SYS_waitid,
+    libc::SYS_kill,
+    libc::SYS_setsid,
+    libc::SYS_setpgid,
+    libc::SYS_setgroups,
+    libc::SYS_setgid,
+    libc::SYS_setuid,
+    libc::SYS_prctl,
+    libc::SYS_exit,
+    libc::SYS_exit_group,
+    libc::SYS_io_uring_setup,
+    libc::SYS_io_uring_enter,
+    #[cfg(target_arch = "x86_64")]
+    libc::SYS_open,
+    #[cfg(target_arch = "x86_64")]
+    libc::SYS_stat,
+    #[cfg(target_arch = "x86_64")]
+    libc::SYS_lstat,
+    #[cfg(target_arch = "x86_64")]
+    libc::SYS_dup2,
+    #[cfg(target_arch = "x86_64")]
+    libc::SYS_getpgrp,
+    #[cfg(target_arch = "x86_64")]
+    libc::SYS_poll,
+    #[cfg(target_arch = "x86_64")]
+    libc::SYS_epoll_wait,
+    #[cfg(target_arch = "x86_64")]
+    libc::SYS_access,
+    #[cfg(target_arch = "x86_64")]
+    libc::SYS_readlink,
+    #[cfg(target_arch = "x86_64")]
+    libc::SYS_unlink,
+    #[cfg(target_arch = "x86_64")]
+    libc::SYS_rename,
+    #[cfg(target_arch = "x86_64")]
+    libc::SYS_renameat,
+    #[cfg(target_arch = "x86_64")]
+    libc::SYS_pipe,
+    #[cfg(target_arch = "x86_64")]
+    libc::SYS_vfork,
+    #[cfg(target_arch = "x86_64")]
+    libc::SYS_arch_prctl,
+];
+
+#[cfg(target_arch = "x86_64")]
+const AUDIT_ARCH: u32 = 0xc000_003e;
+#[cfg(target_arch = "aarch64")]
+const AUDIT_ARCH: u32 = 0xc000_00b7;
+
+/// Confines the daemon and everything it spawns to [`ALLOWED`] with a
+/// seccomp filter, for all of its threads. Other system calls fail with
+/// `EPERM` rather than killing the daemon, and so do those of hooks, which
+/// inherit the filter. System calls of another architecture, e.g. 32-bit
+/// ones, kill the process.
+#[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+pub fn install() -> io::Result<()>
+{
+    let mut filter = filter();
+    let program = libc::sock_fprog {
+        len: filter.len() as u16,
+        filter: filter.as_mut_ptr(),
+    };
+    unsafe {
+        // Without it, unprivileged processes may not install filters.
+        if libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        if libc::syscall(
+            libc::SYS_seccomp,
+            libc::SECCOMP_SET_MODE_FILTER,
+            libc::SECCOMP_FILTER_FLAG_TSYNC,
+            &program as *const libc::sock_fprog,
+        ) != 0
+        {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+/// Returns the BPF program of the filter.
+#[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+fn filter() -> Vec<libc::sock_filter>
+{
+    // Offsets into struct seccomp_data.
+    const NR: u32 = 0;
+    const ARCH: u32 = 4;
+    let load = |offset| bpf_stmt(libc::BPF_LD | libc::BPF_W | libc::BPF_ABS, offset);
+    let mut filter = vec![
+        load(ARCH),
+        bpf_jump(
+            libc::BPF_JMP | libc::BPF_JEQ | libc::BPF_K,
+            AUDIT_ARCH,
+            1,
+            0,
+        ),
+        bpf_stmt(libc::BPF_RET | libc::BPF_K, libc::SECCOMP_RET_KILL_PROCESS),
+        load(NR),
+    ];
+    for &syscall in ALLOWED {
+        filter.push(bpf_jump(
+            libc::BPF_JMP | libc::BPF_JEQ | libc::BPF_K,
+            syscall as u32,
+            0,
+            1,
+        ));
+        filter.push(bpf_stmt(
+            libc::BPF_RET | libc::BPF_K,
+            libc::SECCOMP_RET_ALLOW,
+        ));
+    }
+    filter.push(bpf_stmt(
+        libc::BPF_RET | libc::BPF_K,
+        libc::SECCOMP_RET_ERRNO | (libc::EPERM as u32 & libc::SECCOMP_RET_DATA),
+    ));
+    filter
+}
+
+#[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+pub fn install() -> io::Result<()>
+{
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "the sandbox is only available on x86_64 and aarch64",
+    ))
+}
+
+#[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+fn bpf_stmt(code: u32, k: u32) -> libc::sock_filter
+{
+    libc::sock_filter {
+        code: code as u16,
+        jt: 0,
+        jf: 0,
+        k,
+    }
+}
+
+#[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+fn bpf_jump(code: u32, k: u32, jt: u8, jf: u8) -> libc::sock_filter
+{
+    libc::sock_filter {
+        code: code as u16,
+        jt,
+        jf,
+        k,
+    }
+}
+
+#[cfg(all(test, any(target_arch = "x86_64", target_arch = "aarch64")))]
+mod tests
+{
+    use super::*;
+
+    /// Runs the program on a system call `nr` of the architecture `arch`,
+    /// understanding just the instructions that [`filter`] emits.
+    fn run(filter: &[libc::sock_filter], arch: u32, nr: libc::c_long) -> u32
+    {
+        let mut accumulator = 0;
+        let mut pc = 0;
+        loop {
+            let insn = filter[pc];
+            pc += 1;
+            match u32::from(insn.code) {
+                code if code == libc::BPF_LD | libc::BPF_W | libc::BPF_ABS => {
+                    accumulator = match insn.k {
+                        0 => nr as u32,
+                        4 => arch,
+                        k => panic!("load of offset {}", k),
+                    }
+                }
+                code if code == libc::BPF_JMP | libc::BPF_JEQ | libc::BPF_K => {
+                    pc += usize::from(if accumulator == insn.k {
+                        insn.jt
+                    } else {
+                        insn.jf
+                    });
+                }
+                code if code == libc::BPF_RET | libc::BPF_K => return insn.k,
+                code => panic!("unexpected instruction {:#x}", code),
+            }
+        }
+    }
+
+    #[test]
+    fn allows_the_listed_system_calls()
+    {
+        let filter = filter();
+        let eperm = libc::SECCOMP_RET_ERRNO | libc::EPERM as u32;
+        for nr in [
+            libc::SYS_read,
+            libc::SYS_sendmmsg,
+            libc::SYS_setgroups,
+            libc::SYS_setgid,
+            libc::SYS_setuid,
+            libc::SYS_prctl,
+            libc::SYS_io_uring_enter,
+        ] {
+            assert_eq!(
+                run(&filter, AUDIT_ARCH, nr),
+                libc::SECCOMP_RET_ALLOW,
+                "{}",
+                nr
+            );
+        }
+        for nr in [libc::SYS_mount, libc::SYS_ptrace, libc::SYS_init_module] {
+            assert_eq!(run(&filter, AUDIT_ARCH, nr), eperm, "{}", nr);
+        }
+        // i386 system calls on x86_64, whose numbers mean other calls.
+        assert_eq!(
+            run(&filter, 0x4000_0003, libc::SYS_read),
+            libc::SECCOMP_RET_KILL_PROCESS
+        );
+    }
+
+    #[test]
+    fn fits_the_length_of_a_program()
+    {
+        let filter = filter();
+        assert_eq!(filter.len(), 4 + 2 * ALLOWED.len() + 1);
+        assert!(filter.len() <= usize::from(u16::MAX));
+        let mut allowed = ALLOWED.to_vec();
+        allowed.sort_unstable();
+        allowed.dedup();
+        assert_eq!(
+            allowed.len(),
+            ALLOWED.len(),
+            "a system call is listed twice"
+        );
+    }
+}
@@ -0,0 +1,316 @@
+use std::collections::VecDeque;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::process;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+
+use crate::clock::MockClock;
+use crate::event::PowerEvent;
+use crate::mock::MockBackend;
+use crate::mock::MockController;
+use crate::power_supply::PowerSupply;
+
+/// Numbers the trees of the scenarios of this process.
+static NEXT_TREE: AtomicU64 = AtomicU64::new(0);
+
+/// A power supply on a [`MockBackend`] and a [`MockClock`] in a tree of its
+/// own, driven step by step and checked against the events it emits, for
+/// regression tests of what a laptop in an issue does:
+///
+/// ```
+/// use lithiumd::Scenario;
+///
+/// let mut scenario = Scenario::laptop()?;
+/// scenario.play(
+///     "
+///     plug
+///     expect Charging status changed: Charging
+///     set BAT0 capacity 79
+///     overrun
+///     expect Capacity changed: 79%
+///     expect nothing
+///     ",
+/// )?;
+/// # Ok::<(), std::io::Error>(())
+/// ```
+///
+/// Changes to the tree take effect when the power supply reads its uevents,
+/// which it does before each expectation and on [`Scenario::update`], like
+/// the monitor does when its socket becomes readable. The tree is removed
+/// when the scenario is dropped.
+pub struct Scenario
+{
+    root: PathBuf,
+    controller: MockController,
+    clock: MockClock,
+    power_supply: PowerSupply,
+    /// The events emitted and not yet expected, oldest first.
+    events: VecDeque<PowerEvent>,
+    suspended: bool,
+}
+
+impl Scenario
+{
+    /// The adapter of [`Scenario::laptop`].
+    pub const ADAPTER: &'static str = "AC";
+    /// The battery of [`Scenario::laptop`], and the one that
+    /// [`Scenario::plug`] and [`Scenario::unplug`] charge and discharge.
+    pub const BATTERY: &'static str = "BAT0";
+
+    /// Starts with an empty tree.
+    pub fn new() -> io::Result<Self>
+    {
+        let root = std::env::temp_dir().join(format!(
+            "lithiumd-scenario-{}-{}",
+            process::id(),
+            NEXT_TREE.fetch_add(1, Ordering::Relaxed)
+        ));
+        let mock = MockBackend::new(&root)?;
+        let controller = mock.controller();
+        let clock = MockClock::new();
+        let mut power_supply = PowerSupply::with_mock(mock);
+        power_supply.set_clock(clock.clone());
+        Ok(Self {
+            root,
+            controller,
+            clock,
+            power_supply,
+            events: VecDeque::new(),
+            suspended: false,
+        })
+    }
+
+    /// Starts with a laptop on battery: [`Scenario::BATTERY`] discharging at
+    /// 80% and [`Scenario::ADAPTER`] offline, already read and with its
+    /// events dropped.
+    pub fn laptop() -> io::Result<Self>
+    {
+        let mut scenario = Self::new()?;
+        scenario.controller.add(
+            Self::BATTERY,
+            &[
+                ("type", "Battery"),
+                ("status", "Discharging"),
+                ("capacity", "80"),
+            ],
+        )?;
+        scenario
+            .controller
+            .add(Self::ADAPTER, &[("type", "Mains"), ("online", "0")])?;
+        scenario.update()?;
+        scenario.events.clear();
+        Ok(scenario)
+    }
+
+    /// Returns the power supply, e.g. to set its debounce or thresholds
+    /// before playing the scenario.
+    pub fn power_supply(&mut self) -> &mut PowerSupply
+    {
+        &mut self.power_supply
+    }
+
+    #[must_use]
+    pub fn controller(&self) -> &MockController
+    {
+        &self.controller
+    }
+
+    #[must_use]
+    pub fn clock(&self) -> &MockClock
+    {
+        &self.clock
+    }
+
+    /// Plugs the charger in: the adapter goes online and the battery starts
+    /// charging.
+    pub fn plug(&mut self) -> io::Result<()>
+    {
+        self.controller.set(Self::ADAPTER, "online", "1")?;
+        self.controller.set(Self::BATTERY, "status", "Charging")
+    }
+
+    /// Unplugs the charger: the adapter goes offline and the battery starts
+    /// discharging.
+    pub fn unplug(&mut self) -> io::Result<()>
+    {
+        self.controller.set(Self::ADAPTER, "online", "0")?;
+        self.controller.set(Self::BATTERY, "status", "Discharging")
+    }
+
+    /// Pulls the battery out, as on laptops with a removable one.
+    pub fn remove_battery(&mut self) -> io::Result<()>
+    {
+        self.controller.remove(Self::BATTERY)
+    }
+
+    /// Drops the pending uevents and reports that they were lost, see
+    /// [`MockController::overrun`].
+    pub fn overrun(&mut self) -> io::Result<()>
+    {
+        self.controller.overrun()
+    }
+
+    /// Suspends the system: the power supply reads nothing until
+    /// [`Scenario::resume`], and the uevents sent meanwhile are lost, so that
+    /// only the rescan on resume picks up the changes.
+    pub fn suspend(&mut self)
+    {
+        self.suspended = true;
+    }
+
+    /// Resumes the system after it slept for `duration`.
+    pub fn resume(&mut self, duration: Duration) -> io::Result<()>
+    {
+        self.suspended = false;
+        self.controller.discard();
+        self.clock.advance(duration);
+        self.power_supply.resumed()?;
+        self.collect();
+        Ok(())
+    }
+
+    /// Lets `duration` pass and reads the power supply, so that deadlines
+    /// such as the end of the debounce are reached.
+    pub fn wait(&mut self, duration: Duration) -> io::Result<()>
+    {
+        self.clock.advance(duration);
+        self.update()
+    }
+
+    /// Reads the pending uevents, unless the system is suspended.
+    pub fn update(&mut self) -> io::Result<()>
+    {
+        if !self.suspended {
+            self.power_supply.update()?;
+            self.collect();
+        }
+        Ok(())
+    }
+
+    /// Returns the events that were emitted and not yet expected.
+    pub fn events(&mut self) -> io::Result<Vec<PowerEvent>>
+    {
+        self.update()?;
+        Ok(self.events.drain(..).collect())
+    }
+
+    /// Skips ahead to the first event whose description starts with
+    /// `prefix`, e.g. `Charging status changed: Charging`, and returns it.
+    /// Fails if there is none.
+    pub fn expect(&mut self, prefix: &str) -> io::Result<PowerEvent>
+    {
+        self.update()?;
+        let index = self
+            .events
+            .iter()
+            .position(|event| event.to_string().starts_with(prefix))
+            .ok_or_else(|| {
+                io::Error::other(format!(
+                    "expected an event starting with `{}`, {}",
+                    prefix,
+                    self.pending()
+                ))
+            })?;
+        let event = self.events.drain(..=index).next_back();
+        Ok(event.unwrap())
+    }
+
+    /// Fails if an event was emitted and not expected.
+    pub fn expect_nothing(&mut self) -> io::Result<()>
+    {
+        self.update()?;
+        if self.events.is_empty() {
+            return Ok(());
+        }
+        Err(io::Error::other(format!(
+            "expected no events, {}",
+            self.pending()
+        )))
+    }
+
+    /// Plays a script of one step per line: the changes of
+    /// [`MockController::play`], `plug`, `unplug`, `remove-battery`,
+    /// `overrun`, `suspend`, `resume SECS`, `wait SECS`, `update`,
+    /// `expect PREFIX` and `expect nothing`. Empty lines and lines starting
+    /// with `#` are skipped.
+    pub fn play(&mut self, script: &str) -> io::Result<()>
+    {
+        for (i, line) in script.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            self.step(line)
+                .map_err(|err| io::Error::new(err.kind(), format!("line {}: {}", i + 1, err)))?;
+        }
+        Ok(())
+    }
+
+    fn step(&mut self, line: &str) -> io::Result<()>
+    {
+        let words = line.split_whitespace().collect::<Vec<_>>();
+        let seconds = |secs: &str| {
+            secs.parse().map(Duration::from_secs_f64).map_err(|_| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("`{}` is not a number of seconds", secs),
+                )
+            })
+        };
+        match words.as_slice() {
+            ["plug"] => self.plug(),
+            ["unplug"] => self.unplug(),
+            ["remove-battery"] => self.remove_battery(),
+            ["overrun"] => self.overrun(),
+            ["suspend"] => {
+                self.suspend();
+                Ok(())
+            }
+            ["resume", secs] => self.resume(seconds(secs)?),
+            ["wait", secs] => self.wait(seconds(secs)?),
+            ["update"] => self.update(),
+            ["expect", "nothing"] => self.expect_nothing(),
+            ["expect", _, ..] => {
+                let prefix = line["expect".len()..].trim();
+                self.expect(prefix).map(|_| ())
+            }
+            words => match self.controller.play_line(words)? {
+                true => Ok(()),
+                false => Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("unknown step `{}`", line),
+                )),
+            },
+        }
+    }
+
+    fn collect(&mut self)
+    {
+        self.events.extend(self.power_supply.events());
+    }
+
+    /// Describes the events that were not expected yet, for failures.
+    fn pending(&self) -> String
+    {
+        if self.events.is_empty() {
+            return "but there were none".to_string();
+        }
+        let events = self
+            .events
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>();
+        format!("but there were: {}", events.join("; "))
+    }
+}
+
+impl Drop for Scenario
+{
+    fn drop(&mut self)
+    {
+        let _ = fs::remove_dir_all(&self.root);
+    }
+}
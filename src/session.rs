@@ -0,0 +1,104 @@
+use std::fmt::Display;
+use std::time::Duration;
+use std::time::Instant;
+
+use crate::power_supply::Status;
+
+/// How long the system ran on each power source, as returned by
+/// [`PowerSupply::sessions`](crate::PowerSupply::sessions). Time is counted
+/// from when the power supply was first read, which is boot for a daemon
+/// started with the system.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Sessions
+{
+    /// Whether the current source is AC.
+    pub on_ac: bool,
+    /// How long since the last switch between AC and battery.
+    pub current: Duration,
+    /// How long on battery in total.
+    pub on_battery_total: Duration,
+    /// How long on AC in total.
+    pub on_ac_total: Duration,
+}
+
+impl Display for Sessions
+{
+    /// Formats the current session, e.g. `On battery for 2h 13m`.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result
+    {
+        let minutes = self.current.as_secs() / 60;
+        let source = if self.on_ac { "On AC" } else { "On battery" };
+        write!(f, "{} for {}h {}m", source, minutes / 60, minutes % 60)
+    }
+}
+
+/// Tracks the switches between AC and battery.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct SessionTimer
+{
+    /// The current source and since when, once the status is known.
+    current: Option<(bool, Instant)>,
+    on_battery: Duration,
+    on_ac: Duration,
+}
+
+impl SessionTimer
+{
+    /// Notes the charging status read at `now`. Unknown statuses keep the
+    /// current session going.
+    pub(crate) fn update(&mut self, status: Status, now: Instant)
+    {
+        if status == Status::Unknown {
+            return;
+        }
+        let on_ac = status.is_on_ac();
+        match self.current {
+            Some((current, _)) if current == on_ac => {}
+            Some((current, since)) => {
+                self.add(current, now.saturating_duration_since(since));
+                self.current = Some((on_ac, now));
+            }
+            None => self.current = Some((on_ac, now)),
+        }
+    }
+
+    /// Carries on from `sessions`, saved `elapsed` ago.
+    pub(crate) fn restore(&mut self, sessions: Sessions, elapsed: Duration, now: Instant)
+    {
+        self.on_battery = sessions.on_battery_total;
+        self.on_ac = sessions.on_ac_total;
+        // The totals include the current session, which goes on.
+        let total = if sessions.on_ac {
+            &mut self.on_ac
+        } else {
+            &mut self.on_battery
+        };
+        *total = total.saturating_sub(sessions.current);
+        let since = now.checked_sub(sessions.current + elapsed).unwrap_or(now);
+        self.current = Some((sessions.on_ac, since));
+    }
+
+    fn add(&mut self, on_ac: bool, duration: Duration)
+    {
+        if on_ac {
+            self.on_ac += duration;
+        } else {
+            self.on_battery += duration;
+        }
+    }
+
+    pub(crate) fn sessions(&self, now: Instant) -> Option<Sessions>
+    {
+        let (on_ac, since) = self.current?;
+        let current = now.saturating_duration_since(since);
+        let mut totals = self.clone();
+        totals.add(on_ac, current);
+        Some(Sessions {
+            on_ac,
+            current,
+            on_battery_total: totals.on_battery,
+            on_ac_total: totals.on_ac,
+        })
+    }
+}
@@ -0,0 +1,135 @@
+use std::fs::File;
+use std::io;
+use std::io::Read;
+use std::mem;
+use std::os::fd::AsRawFd;
+use std::os::fd::FromRawFd;
+
+use mio::event::Source;
+use mio::unix::SourceFd;
+
+/// Signals the daemon reacts to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Signal
+{
+    /// `SIGHUP`: reload the configuration.
+    Hangup,
+    /// `SIGUSR1`: re-enumerate the devices and print the status again.
+    User1,
+    /// `SIGUSR2`: print the daemon's own metrics.
+    User2,
+    /// `SIGTERM` or `SIGINT`: shut down cleanly.
+    Terminate,
+    /// `SIGCHLD`: a hook exited.
+    Child,
+}
+
+impl Signal
+{
+    const ALL: [libc::c_int; 6] = [
+        libc::SIGHUP,
+        libc::SIGUSR1,
+        libc::SIGUSR2,
+        libc::SIGTERM,
+        libc::SIGINT,
+        libc::SIGCHLD,
+    ];
+
+    fn from_raw(signo: libc::c_int) -> Option<Self>
+    {
+        match signo {
+            libc::SIGHUP => Some(Self::Hangup),
+            libc::SIGUSR1 => Some(Self::User1),
+            libc::SIGUSR2 => Some(Self::User2),
+            libc::SIGTERM | libc::SIGINT => Some(Self::Terminate),
+            libc::SIGCHLD => Some(Self::Child),
+            _ => None,
+        }
+    }
+}
+
+/// A `signalfd(2)` that can be registered with a mio `Poll`.
+///
+/// Creating it blocks the handled signals for the calling thread, so it must
+/// be created before any other threads are spawned for the mask to be
+/// inherited.
+pub struct Signals
+{
+    fd: File,
+}
+
+impl Signals
+{
+    pub fn new() -> io::Result<Self>
+    {
+        let fd = unsafe {
+            let mut mask = mem::zeroed::<libc::sigset_t>();
+            libc::sigemptyset(&mut mask);
+            for signo in Signal::ALL {
+                libc::sigaddset(&mut mask, signo);
+            }
+            // pthread_sigmask returns the error instead of setting errno.
+            let ret = libc::pthread_sigmask(libc::SIG_BLOCK, &mask, std::ptr::null_mut());
+            if ret != 0 {
+                return Err(io::Error::from_raw_os_error(ret));
+            }
+            libc::signalfd(-1, &mask, libc::SFD_NONBLOCK | libc::SFD_CLOEXEC)
+        };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Self {
+            fd: unsafe { File::from_raw_fd(fd) },
+        })
+    }
+
+    /// Returns all signals that arrived since the last call.
+    pub fn read(&mut self) -> io::Result<Vec<Signal>>
+    {
+        let mut signals = Vec::new();
+        let mut info = [0u8; mem::size_of::<libc::signalfd_siginfo>()];
+        loop {
+            match self.fd.read(&mut info) {
+                Ok(len) if len == info.len() => {
+                    let info = unsafe {
+                        std::ptr::read_unaligned(info.as_ptr().cast::<libc::signalfd_siginfo>())
+                    };
+                    signals.extend(Signal::from_raw(info.ssi_signo as libc::c_int));
+                }
+                Ok(_) => break,
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => break,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(signals)
+    }
+}
+
+impl Source for Signals
+{
+    fn register(
+        &mut self,
+        registry: &mio::Registry,
+        token: mio::Token,
+        interests: mio::Interest,
+    ) -> io::Result<()>
+    {
+        SourceFd(&self.fd.as_raw_fd()).register(registry, token, interests)
+    }
+
+    fn reregister(
+        &mut self,
+        registry: &mio::Registry,
+        token: mio::Token,
+        interests: mio::Interest,
+    ) -> io::Result<()>
+    {
+        SourceFd(&self.fd.as_raw_fd()).reregister(registry, token, interests)
+    }
+
+    fn deregister(&mut self, registry: &mio::Registry) -> io::Result<()>
+    {
+        SourceFd(&self.fd.as_raw_fd()).deregister(registry)
+    }
+}
@@ -0,0 +1,400 @@
+use std::fmt::Display;
+
+use crate::charging_speed::ChargingSpeed;
+use crate::level::BatteryLevel;
+use crate::level::LevelThresholds;
+use crate::power_supply::DeviceType;
+use crate::power_supply::Scope;
+use crate::power_supply::Status;
+use crate::source::PowerSource;
+
+/// State of a single power supply device at some point in time.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DeviceSnapshot
+{
+    /// Kernel name of the device, e.g. `BAT0`.
+    pub sysname: String,
+    /// Human readable name of the device, if it reports one.
+    pub model: Option<String>,
+    pub device_type: DeviceType,
+    pub scope: Scope,
+    /// Status reported by this device alone.
+    pub status: Status,
+    /// Remaining capacity in percent, for batteries that report it.
+    pub capacity: Option<u8>,
+    /// Energy in µWh, or charge in µAh for batteries that do not report
+    /// energy, when the battery is full. Used to weight batteries against each
+    /// other.
+    pub energy_full: Option<u64>,
+    /// Energy or charge left, in the same unit as `energy_full`.
+    pub energy_now: Option<u64>,
+    /// Power in µW, or current in µA, drawn from or into the battery.
+    pub power_now: Option<u64>,
+    /// Whether the battery reports energy and power rather than charge and
+    /// current.
+    pub reports_energy: bool,
+    /// Voltage in µV, for batteries and adapters that report it.
+    pub voltage_now: Option<u64>,
+    /// Current in µA, for batteries and adapters that report it. Negative
+    /// while discharging with some drivers, positive either way with others.
+    pub current_now: Option<i64>,
+    /// Capacity in percent at which the firmware of the battery warns, from
+    /// `capacity_alert_min` or `alarm`.
+    pub alarm: Option<u8>,
+    /// Charge limit in percent, the `charge_control_end_threshold` of
+    /// batteries that stop charging below full.
+    pub charge_limit: Option<u8>,
+    /// Power in µW drawn from an adapter, from its `power_now` or its voltage
+    /// and current, for adapters that report them.
+    pub input_power: Option<u64>,
+    /// The highest `input_power` since the charger was plugged in, to tell
+    /// whether it delivers what it is rated for.
+    pub peak_input_power: Option<u64>,
+}
+
+impl DeviceSnapshot
+{
+    /// Returns the voltage in V.
+    #[must_use]
+    pub fn voltage(&self) -> Option<f64>
+    {
+        self.voltage_now.map(|voltage| voltage as f64 / 1e6)
+    }
+
+    /// Returns the current in A.
+    #[must_use]
+    pub fn current(&self) -> Option<f64>
+    {
+        self.current_now.map(|current| current as f64 / 1e6)
+    }
+}
+
+/// State of the power supply at some point in time, as returned by
+/// [`PowerSupply::snapshot`](crate::PowerSupply::snapshot).
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Snapshot
+{
+    /// The overall charging status.
+    pub status: Status,
+    pub devices: Vec<DeviceSnapshot>,
+    /// The adapter that powers the system, `None` on battery.
+    pub source: Option<PowerSource>,
+}
+
+impl Default for Snapshot
+{
+    fn default() -> Self
+    {
+        Self {
+            status: Status::Unknown,
+            devices: Vec::new(),
+            source: None,
+        }
+    }
+}
+
+/// The device that caused a change of the overall status.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Origin
+{
+    pub sysname: String,
+    pub device_type: DeviceType,
+}
+
+impl Display for Origin
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result
+    {
+        write!(f, "{} ({})", self.sysname, self.device_type)
+    }
+}
+
+/// A single difference between two snapshots.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Change
+{
+    /// The overall charging status changed.
+    Status
+    {
+        old: Status,
+        new: Status,
+        /// The device whose own status changed to the new one, or failing
+        /// that the first one whose status changed or that was added or
+        /// removed, so that it is known which of several batteries started
+        /// discharging. `None` if no device changed.
+        origin: Option<Origin>,
+    },
+    DeviceAdded(DeviceSnapshot),
+    DeviceRemoved(DeviceSnapshot),
+    /// The status reported by a device that is present in both snapshots
+    /// changed.
+    DeviceStatus
+    {
+        sysname: String,
+        old: Status,
+        new: Status,
+    },
+    /// The capacity of a battery that is present in both snapshots changed.
+    DeviceCapacity
+    {
+        sysname: String,
+        old: Option<u8>,
+        new: Option<u8>,
+    },
+}
+
+impl Change
+{
+    /// Returns the device the change concerns, or `None` for the overall
+    /// status.
+    #[must_use]
+    pub fn sysname(&self) -> Option<&str>
+    {
+        match self {
+            Change::Status { .. } => None,
+            Change::DeviceAdded(dev) | Change::DeviceRemoved(dev) => Some(&dev.sysname),
+            Change::DeviceStatus { sysname, .. } | Change::DeviceCapacity { sysname, .. } => {
+                Some(sysname)
+            }
+        }
+    }
+}
+
+impl Display for Change
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result
+    {
+        match self {
+            Change::Status {
+                new,
+                origin: Some(origin),
+                ..
+            } => write!(f, "Charging status changed: {} by {}", new, origin),
+            Change::Status { new, .. } => write!(f, "Charging status changed: {}", new),
+            Change::DeviceAdded(dev) => {
+                write!(f, "Device added: {} ({})", dev.sysname, dev.device_type)
+            }
+            Change::DeviceRemoved(dev) => {
+                write!(f, "Device removed: {} ({})", dev.sysname, dev.device_type)
+            }
+            Change::DeviceStatus { sysname, new, .. } => {
+                write!(f, "{} status changed: {}", sysname, new)
+            }
+            Change::DeviceCapacity {
+                sysname,
+                new: Some(new),
+                ..
+            } => write!(f, "{} capacity changed: {}%", sysname, new),
+            Change::DeviceCapacity { sysname, .. } => {
+                write!(f, "{} capacity changed: Unknown", sysname)
+            }
+        }
+    }
+}
+
+impl Snapshot
+{
+    /// Returns the changes that lead from `self` to the newer snapshot
+    /// `other`. Devices are matched by their sysname. The overall status
+    /// change, if any, comes last.
+    #[must_use]
+    pub fn diff(&self, other: &Snapshot) -> Vec<Change>
+    {
+        let mut changes = Vec::new();
+        for old in &self.devices {
+            let Some(new) = other.device(&old.sysname) else {
+                changes.push(Change::DeviceRemoved(old.clone()));
+                continue;
+            };
+            if new.status != old.status {
+                changes.push(Change::DeviceStatus {
+                    sysname: old.sysname.clone(),
+                    old: old.status,
+                    new: new.status,
+                });
+            }
+            if new.capacity != old.capacity {
+                changes.push(Change::DeviceCapacity {
+                    sysname: old.sysname.clone(),
+                    old: old.capacity,
+                    new: new.capacity,
+                });
+            }
+        }
+        changes.extend(
+            other
+                .devices
+                .iter()
+                .filter(|new| self.device(&new.sysname).is_none())
+                .cloned()
+                .map(Change::DeviceAdded),
+        );
+        if self.status != other.status {
+            changes.push(Change::Status {
+                old: self.status,
+                new: other.status,
+                origin: self.origin(other, &changes),
+            });
+        }
+        changes
+    }
+
+    /// Finds the device that caused the overall status to change from
+    /// `self` to `other`, among the device `changes` between them.
+    fn origin(&self, other: &Snapshot, changes: &[Change]) -> Option<Origin>
+    {
+        let device = |sysname: &str| {
+            let dev = other.device(sysname).or_else(|| self.device(sysname))?;
+            Some(Origin {
+                sysname: dev.sysname.clone(),
+                device_type: dev.device_type,
+            })
+        };
+        changes
+            .iter()
+            .find_map(|change| match change {
+                Change::DeviceStatus { sysname, new, .. } if *new == other.status => {
+                    device(sysname)
+                }
+                _ => None,
+            })
+            .or_else(|| {
+                changes.iter().find_map(|change| match change {
+                    Change::DeviceStatus { sysname, .. } => device(sysname),
+                    Change::DeviceAdded(dev) | Change::DeviceRemoved(dev) => device(&dev.sysname),
+                    _ => None,
+                })
+            })
+    }
+
+    /// Returns the energy left in the system batteries in µWh, converting the
+    /// charge of those that do not report energy by their voltage. `None`
+    /// without batteries or if one of them reports neither.
+    #[must_use]
+    pub fn energy_remaining(&self) -> Option<u64>
+    {
+        let mut batteries = self.batteries().peekable();
+        batteries.peek()?;
+        batteries
+            .map(|bat| match bat.reports_energy {
+                true => bat.energy_now,
+                false => Some(
+                    (u128::from(bat.energy_now?) * u128::from(bat.voltage_now?) / 1_000_000) as u64,
+                ),
+            })
+            .sum()
+    }
+
+    /// Returns the combined capacity of the system batteries in percent, if
+    /// known. With several batteries, each one is weighted by how much energy
+    /// it holds when full, or all count the same if one of them does not
+    /// report that.
+    #[must_use]
+    pub fn capacity(&self) -> Option<u8>
+    {
+        let batteries = self
+            .batteries()
+            .filter_map(|dev| Some((dev.capacity?, dev.energy_full)))
+            .collect::<Vec<_>>();
+        if batteries.is_empty() {
+            return None;
+        }
+        let weighted = batteries
+            .iter()
+            .map(|(capacity, energy_full)| {
+                Some(u128::from(*capacity) * u128::from((*energy_full)?))
+            })
+            .sum::<Option<u128>>();
+        let total = batteries
+            .iter()
+            .map(|(_, energy_full)| energy_full.map(u128::from))
+            .sum::<Option<u128>>();
+        let capacity = match (weighted, total) {
+            (Some(weighted), Some(total)) if total > 0 => weighted / total,
+            _ => {
+                let sum = batteries
+                    .iter()
+                    .map(|(capacity, _)| u128::from(*capacity))
+                    .sum::<u128>();
+                sum / batteries.len() as u128
+            }
+        };
+        Some(capacity.min(100) as u8)
+    }
+
+    /// Returns the [`BatteryLevel`] of the combined capacity, if known.
+    #[must_use]
+    pub fn level(&self, thresholds: &LevelThresholds) -> Option<BatteryLevel>
+    {
+        self.capacity()
+            .map(|capacity| BatteryLevel::of(capacity, thresholds))
+    }
+
+    /// Returns the charge limit that holds the system batteries, if they do
+    /// not charge on AC because of one rather than some other fault.
+    #[must_use]
+    pub fn charge_limited(&self) -> Option<u8>
+    {
+        if self.status != Status::NotCharging {
+            return None;
+        }
+        self.batteries()
+            .filter(|bat| bat.status == Status::NotCharging)
+            .filter_map(|bat| bat.charge_limit)
+            .min()
+    }
+
+    /// Returns how fast the system batteries charge, from the power or
+    /// current flowing into the charging ones relative to what they hold when
+    /// full. `None` unless charging, or if they do not report both.
+    #[must_use]
+    pub fn charging_speed(&self) -> Option<ChargingSpeed>
+    {
+        if self.status != Status::Charging {
+            return None;
+        }
+        let charging = self
+            .batteries()
+            .filter(|bat| bat.status == Status::Charging)
+            .map(|bat| Some((bat.power_now?, bat.energy_full?)))
+            .collect::<Option<Vec<_>>>()?;
+        ChargingSpeed::of(
+            charging.iter().map(|(power, _)| power).sum(),
+            charging.iter().map(|(_, full)| full).sum(),
+        )
+    }
+
+    /// Returns the batteries that power the system.
+    pub fn batteries(&self) -> impl Iterator<Item = &DeviceSnapshot>
+    {
+        self.devices
+            .iter()
+            .filter(|dev| dev.device_type == DeviceType::Battery && dev.scope == Scope::System)
+    }
+
+    /// Returns the state as environment variables, as passed to hooks:
+    /// `LITHIUMD_STATUS` and `LITHIUMD_CAPACITY`. Values that are not known
+    /// are `None`.
+    #[must_use]
+    pub fn env_vars(&self) -> Vec<(&'static str, Option<String>)>
+    {
+        vec![
+            ("LITHIUMD_STATUS", Some(self.status.to_string())),
+            (
+                "LITHIUMD_CAPACITY",
+                self.capacity().map(|capacity| capacity.to_string()),
+            ),
+        ]
+    }
+
+    /// Returns the device named `sysname`, if it is part of the snapshot.
+    #[must_use]
+    pub fn device(&self, sysname: &str) -> Option<&DeviceSnapshot>
+    {
+        self.devices.iter().find(|dev| dev.sysname == sysname)
+    }
+}
@@ -0,0 +1,114 @@
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::PathBuf;
+
+use lithiumd::Change;
+use lithiumd::HookRunner;
+use lithiumd::LevelThresholds;
+use lithiumd::PowerEvent;
+use lithiumd::Snapshot;
+use lithiumd::Status;
+
+use crate::speech;
+
+/// Plays a sound when the charger is connected or disconnected and when the
+/// battery turns critical, for users without a notification daemon. Events
+/// without a sound file ring the terminal bell instead.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sound
+{
+    /// Command that gets the sound file as its last argument.
+    pub player: String,
+    pub connected: Option<PathBuf>,
+    pub disconnected: Option<PathBuf>,
+    pub critical: Option<PathBuf>,
+}
+
+/// What a sound is played for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Alert
+{
+    Connected,
+    Disconnected,
+    Critical,
+}
+
+impl Sound
+{
+    /// Plays the sound of the most important of `events`, so that sounds do
+    /// not overlap.
+    pub fn play(
+        &self,
+        hooks: &mut HookRunner,
+        events: &[PowerEvent],
+        snapshot: &Snapshot,
+        levels: &LevelThresholds,
+    )
+    {
+        let Some(alert) = most_important(events, snapshot, levels) else {
+            return;
+        };
+        let file = match alert {
+            Alert::Connected => &self.connected,
+            Alert::Disconnected => &self.disconnected,
+            Alert::Critical => &self.critical,
+        };
+        let Some(file) = file else {
+            ring_bell();
+            return;
+        };
+        let command = format!("{} {}", self.player, speech::quote(&file.to_string_lossy()));
+        if let Err(err) = hooks.run(&command, snapshot) {
+            eprintln!("Failed to run `{}`: {}", self.player, err);
+        }
+    }
+}
+
+/// Returns the most important alert of `events`: the battery turning
+/// critical, then the charger being disconnected, then connected.
+pub fn most_important(
+    events: &[PowerEvent],
+    snapshot: &Snapshot,
+    levels: &LevelThresholds,
+) -> Option<Alert>
+{
+    let alerts = events
+        .iter()
+        .filter_map(|event| alert(event, snapshot, levels))
+        .collect::<Vec<_>>();
+    [Alert::Critical, Alert::Disconnected, Alert::Connected]
+        .into_iter()
+        .find(|alert| alerts.contains(alert))
+}
+
+fn alert(event: &PowerEvent, snapshot: &Snapshot, levels: &LevelThresholds) -> Option<Alert>
+{
+    match event {
+        PowerEvent::Changed(Change::Status { old, new, .. }) => {
+            // Not when the status first becomes known on startup.
+            if new.is_on_ac() && *old == Status::Discharging {
+                Some(Alert::Connected)
+            } else if *new == Status::Discharging && old.is_on_ac() {
+                Some(Alert::Disconnected)
+            } else {
+                None
+            }
+        }
+        PowerEvent::CapacityChanged { old, new }
+            if snapshot.status == Status::Discharging
+                && *new <= levels.critical
+                && old.is_none_or(|old| old > levels.critical) =>
+        {
+            Some(Alert::Critical)
+        }
+        _ => None,
+    }
+}
+
+/// Writes a BEL to the controlling terminal, if there is one.
+fn ring_bell()
+{
+    if let Ok(mut tty) = OpenOptions::new().write(true).open("/dev/tty") {
+        let _ = tty.write_all(b"\x07");
+    }
+}
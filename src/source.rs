@@ -0,0 +1,64 @@
+use std::fmt::Display;
+
+use crate::backend::BackendDevice;
+
+/// The kind of an adapter that can power the system, by its
+/// `POWER_SUPPLY_TYPE`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum SourceKind
+{
+    /// A barrel or mains adapter, `Mains`.
+    Mains,
+    /// A USB port, e.g. `USB` for the USB-C ports of `ucsi-source-psy-*`.
+    Usb,
+    /// A wireless charger, `Wireless`.
+    Wireless,
+}
+
+impl SourceKind
+{
+    /// The order in which sources power the system by default: an adapter
+    /// over a USB port over a wireless pad.
+    pub const DEFAULT_PRIORITY: [SourceKind; 3] =
+        [SourceKind::Mains, SourceKind::Usb, SourceKind::Wireless];
+
+    pub(crate) fn of(dev: &BackendDevice) -> Self
+    {
+        match dev.property_value("POWER_SUPPLY_TYPE") {
+            Some("Wireless") => SourceKind::Wireless,
+            Some(kind) if kind.starts_with("USB") => SourceKind::Usb,
+            _ => SourceKind::Mains,
+        }
+    }
+}
+
+impl Display for SourceKind
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result
+    {
+        match *self {
+            SourceKind::Mains => write!(f, "mains"),
+            SourceKind::Usb => write!(f, "USB"),
+            SourceKind::Wireless => write!(f, "wireless"),
+        }
+    }
+}
+
+/// The adapter that powers the system, as chosen among those that are
+/// online by [`PowerSupply::set_source_priority`](crate::PowerSupply::set_source_priority).
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PowerSource
+{
+    pub sysname: String,
+    pub kind: SourceKind,
+}
+
+impl Display for PowerSource
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result
+    {
+        write!(f, "{} ({})", self.sysname, self.kind)
+    }
+}
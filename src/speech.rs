@@ -0,0 +1,133 @@
+use lithiumd::Change;
+use lithiumd::DeviceSnapshot;
+use lithiumd::DeviceType;
+use lithiumd::HookRunner;
+use lithiumd::PowerEvent;
+use lithiumd::Scope;
+use lithiumd::Snapshot;
+use lithiumd::Status;
+
+/// How much is spoken.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Verbosity
+{
+    /// Plugging in, unplugging, and low batteries.
+    Minimal,
+    /// Also devices that appear or disappear.
+    Normal,
+    /// Also the capacity at every capacity step.
+    Verbose,
+}
+
+/// Speaks important transitions through a text-to-speech command, for users
+/// who cannot see a battery indicator. The sentence is appended to the
+/// command as a single argument.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Speech
+{
+    pub command: String,
+    pub verbosity: Verbosity,
+}
+
+impl Speech
+{
+    /// Speaks the events that are important enough for the verbosity, all
+    /// in one go so that they do not interrupt each other.
+    pub fn announce(&self, hooks: &mut HookRunner, events: &[PowerEvent], snapshot: &Snapshot)
+    {
+        let sentences = events
+            .iter()
+            .filter_map(|event| self.sentence(event, snapshot))
+            .collect::<Vec<_>>();
+        if sentences.is_empty() {
+            return;
+        }
+        let command = format!("{} {}", self.command, quote(&sentences.join(" ")));
+        if let Err(err) = hooks.run(&command, snapshot) {
+            eprintln!("Failed to run `{}`: {}", self.command, err);
+        }
+    }
+
+    fn sentence(&self, event: &PowerEvent, snapshot: &Snapshot) -> Option<String>
+    {
+        let capacity = || match snapshot.capacity() {
+            Some(capacity) => format!(", battery at {} percent.", capacity),
+            None => ".".to_string(),
+        };
+        match event {
+            PowerEvent::LowBattery(dev) => Some(format!(
+                "{} battery low, {} percent.",
+                name(dev),
+                dev.capacity.unwrap_or_default()
+            )),
+            PowerEvent::BatteryAlarm(dev) => Some(format!(
+                "Battery low, {} percent.",
+                dev.capacity.unwrap_or_default()
+            )),
+            PowerEvent::EnergyBudget { remaining, .. } => Some(format!(
+                "Battery low, {:.1} watt hours left.",
+                *remaining as f64 / 1_000_000.0
+            )),
+            PowerEvent::HighDrain(rate) => Some(format!(
+                "Battery draining fast, {} percent per hour.",
+                rate.percent_per_hour
+            )),
+            PowerEvent::WeakCharger { .. } => {
+                Some("Charger too weak, the battery is still draining.".to_string())
+            }
+            PowerEvent::Changed(Change::Status { old, new, .. }) => match new {
+                _ if new.is_on_ac() && !old.is_on_ac() => {
+                    Some(format!("Charger connected{}", capacity()))
+                }
+                Status::Full => Some("Battery full.".to_string()),
+                Status::Discharging => Some(format!("On battery{}", capacity())),
+                _ => None,
+            },
+            PowerEvent::Changed(Change::DeviceAdded(dev))
+                if self.verbosity >= Verbosity::Normal =>
+            {
+                Some(format!("{} connected.", name(dev)))
+            }
+            PowerEvent::Changed(Change::DeviceRemoved(dev))
+                if self.verbosity >= Verbosity::Normal =>
+            {
+                Some(format!("{} disconnected.", name(dev)))
+            }
+            PowerEvent::CapacityChanged { new, .. } if self.verbosity >= Verbosity::Verbose => {
+                Some(format!("Battery at {} percent.", new))
+            }
+            // The system batteries are covered by the combined capacity.
+            PowerEvent::Changed(Change::DeviceCapacity {
+                sysname,
+                new: Some(new),
+                ..
+            }) if self.verbosity >= Verbosity::Verbose => {
+                let dev = snapshot
+                    .device(sysname)
+                    .filter(|dev| dev.scope == Scope::Device)?;
+                Some(format!("{} at {} percent.", name(dev), new))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Names a device the way it would be spoken: by its model, or as the
+/// battery or the charger.
+fn name(dev: &DeviceSnapshot) -> &str
+{
+    match &dev.model {
+        Some(model) => model,
+        None => match dev.device_type {
+            DeviceType::Battery => "Battery",
+            DeviceType::Adapter => "Charger",
+            DeviceType::Wireless => "Wireless charger",
+        },
+    }
+}
+
+/// Quotes `s` as a single shell word.
+pub fn quote(s: &str) -> String
+{
+    format!("'{}'", s.replace('\'', r"'\''"))
+}
@@ -0,0 +1,136 @@
+use std::env;
+use std::fs;
+use std::io;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+use std::time::Duration;
+use std::time::SystemTime;
+
+use lithiumd::SavedState;
+use lithiumd::Sessions;
+use lithiumd::Status;
+
+use crate::json;
+
+/// The state that the daemon saves on exit and picks up on start, so that a
+/// restart neither runs the hooks of a status that did not change nor resets
+/// the time on battery. It lives in the runtime directory, which is emptied
+/// on reboot.
+pub struct StateFile
+{
+    path: PathBuf,
+}
+
+impl StateFile
+{
+    /// Uses `$XDG_RUNTIME_DIR/lithiumd.state`, or `/run/lithiumd.state` for a
+    /// system-wide daemon.
+    #[must_use]
+    pub fn new() -> Self
+    {
+        let path = match env::var_os("XDG_RUNTIME_DIR") {
+            Some(dir) => PathBuf::from(dir).join("lithiumd.state"),
+            None => PathBuf::from("/run/lithiumd.state"),
+        };
+        Self { path }
+    }
+
+    #[must_use]
+    pub fn path(&self) -> &Path
+    {
+        &self.path
+    }
+
+    /// Reads the saved state and how long ago it was saved, `None` if there
+    /// is none.
+    pub fn load(&self) -> io::Result<Option<(SavedState, Duration)>>
+    {
+        let contents = match fs::read_to_string(&self.path) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err),
+        };
+        let mut state = SavedState {
+            status: Status::Unknown,
+            capacity: None,
+            sessions: None,
+        };
+        let mut saved = None;
+        for (i, line) in contents.lines().enumerate() {
+            let invalid = || {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("{}:{}: invalid line", self.path.display(), i + 1),
+                )
+            };
+            let secs = |s: &str| s.parse().map(Duration::from_secs).map_err(|_| invalid());
+            match line.split_whitespace().collect::<Vec<_>>().as_slice() {
+                [] => {}
+                ["saved", at] => saved = Some(SystemTime::UNIX_EPOCH + secs(at)?),
+                ["status", status] => state.status = parse_status(status).ok_or_else(invalid)?,
+                ["capacity", capacity] => {
+                    state.capacity = Some(capacity.parse().map_err(|_| invalid())?)
+                }
+                ["session", source, current, on_battery_total, on_ac_total] => {
+                    state.sessions = Some(Sessions {
+                        on_ac: match *source {
+                            "ac" => true,
+                            "battery" => false,
+                            _ => return Err(invalid()),
+                        },
+                        current: secs(current)?,
+                        on_battery_total: secs(on_battery_total)?,
+                        on_ac_total: secs(on_ac_total)?,
+                    })
+                }
+                _ => return Err(invalid()),
+            }
+        }
+        let elapsed = saved
+            .and_then(|saved| SystemTime::now().duration_since(saved).ok())
+            .unwrap_or_default();
+        Ok(Some((state, elapsed)))
+    }
+
+    /// Replaces the file atomically with `state`.
+    pub fn save(&self, state: &SavedState) -> io::Result<()>
+    {
+        let now = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default();
+        let mut contents = format!("saved {}\n", now.as_secs());
+        contents += &format!("status {}\n", json::status_name(state.status));
+        if let Some(capacity) = state.capacity {
+            contents += &format!("capacity {}\n", capacity);
+        }
+        if let Some(sessions) = &state.sessions {
+            contents += &format!(
+                "session {} {} {} {}\n",
+                if sessions.on_ac { "ac" } else { "battery" },
+                sessions.current.as_secs(),
+                sessions.on_battery_total.as_secs(),
+                sessions.on_ac_total.as_secs()
+            );
+        }
+
+        let mut tmp = self.path.clone().into_os_string();
+        tmp.push(".tmp");
+        let mut file = fs::File::create(&tmp)?;
+        file.write_all(contents.as_bytes())?;
+        file.sync_all()?;
+        fs::rename(&tmp, &self.path)
+    }
+}
+
+fn parse_status(s: &str) -> Option<Status>
+{
+    match s {
+        "discharging" => Some(Status::Discharging),
+        "charging" => Some(Status::Charging),
+        "full" => Some(Status::Full),
+        "not_charging" => Some(Status::NotCharging),
+        "unknown" => Some(Status::Unknown),
+        _ => None,
+    }
+}
@@ -0,0 +1,204 @@
+use std::time::Duration;
+use std::time::SystemTime;
+
+use lithiumd::Segment;
+use lithiumd::Status;
+
+/// Options of the `stats` command.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Options
+{
+    /// How many days back to summarize.
+    pub days: u32,
+    pub json: bool,
+}
+
+impl Options
+{
+    /// Parses `[--days N] [--json]`.
+    pub fn parse(args: &[&str]) -> Result<Self, String>
+    {
+        let mut options = Self {
+            days: 7,
+            json: false,
+        };
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match *arg {
+                "--days" => {
+                    let value = args
+                        .next()
+                        .ok_or_else(|| format!("`{}` needs a value", arg))?;
+                    options.days = value
+                        .parse()
+                        .ok()
+                        .filter(|days| *days > 0)
+                        .ok_or_else(|| format!("`{}` is not a number of days", value))?;
+                }
+                "--json" => options.json = true,
+                _ => return Err(format!("unknown option `{}`", arg)),
+            }
+        }
+        Ok(options)
+    }
+}
+
+/// A summary of the segments of a [`History`](lithiumd::History).
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Stats
+{
+    /// How many days the segments cover.
+    pub days: f64,
+    pub on_battery: Duration,
+    pub on_ac: Duration,
+    /// Average drain while discharging, in percent per hour.
+    pub drain: Option<f64>,
+    /// Average rate while charging, in percent per hour.
+    pub charge: Option<f64>,
+    /// Switches from battery to AC.
+    pub plug_ins: u32,
+}
+
+impl Stats
+{
+    /// Summarizes the segments that started in the last `days` days.
+    pub fn new<'a>(segments: impl IntoIterator<Item = &'a Segment>, days: u32) -> Self
+    {
+        let now = SystemTime::now();
+        let since = now - Duration::from_secs(u64::from(days) * 24 * 60 * 60);
+        let mut stats = Self::default();
+        let mut first = None;
+        let mut previous: Option<Status> = None;
+        let mut drained = Rate::default();
+        let mut charged = Rate::default();
+        for segment in segments
+            .into_iter()
+            .filter(|segment| segment.start >= since)
+        {
+            first.get_or_insert(segment.start);
+            let duration = segment.duration();
+            let change = segment
+                .capacity_start
+                .zip(segment.capacity_end)
+                .map(|(start, end)| f64::from(end) - f64::from(start));
+            match segment.status {
+                Status::Discharging => {
+                    stats.on_battery += duration;
+                    drained.add(change.map(|change| -change), duration);
+                }
+                status => {
+                    stats.on_ac += duration;
+                    if status == Status::Charging {
+                        charged.add(change, duration);
+                    }
+                    if previous == Some(Status::Discharging) {
+                        stats.plug_ins += 1;
+                    }
+                }
+            }
+            previous = Some(segment.status);
+        }
+        stats.days = first.map_or(0.0, |first| {
+            now.duration_since(first).unwrap_or_default().as_secs_f64() / (24.0 * 60.0 * 60.0)
+        });
+        stats.drain = drained.per_hour();
+        stats.charge = charged.per_hour();
+        stats
+    }
+
+    /// Returns how long a full charge lasts at the average drain.
+    #[must_use]
+    pub fn runtime(&self) -> Option<Duration>
+    {
+        let drain = self.drain.filter(|drain| *drain > 0.0)?;
+        Some(Duration::from_secs_f64(100.0 / drain * 60.0 * 60.0))
+    }
+
+    /// Returns the plug-ins per day, once the segments cover a day.
+    #[must_use]
+    pub fn plug_ins_per_day(&self) -> Option<f64>
+    {
+        (self.days >= 1.0).then(|| f64::from(self.plug_ins) / self.days)
+    }
+
+    pub fn text(&self) -> String
+    {
+        if self.days == 0.0 {
+            return "No history recorded yet\n".to_string();
+        }
+        let mut text = format!(
+            "Over {:.1} days: {} on battery, {} on AC\n",
+            self.days,
+            hours(self.on_battery),
+            hours(self.on_ac)
+        );
+        if let Some(drain) = self.drain {
+            text += &format!("Average drain on battery: {:.1}% per hour\n", drain);
+        }
+        if let Some(runtime) = self.runtime() {
+            text += &format!("Estimated runtime on a full charge: {}\n", hours(runtime));
+        }
+        if let Some(charge) = self.charge {
+            text += &format!("Average charge rate: {:.1}% per hour\n", charge);
+        }
+        match self.plug_ins_per_day() {
+            Some(per_day) => {
+                text += &format!(
+                    "Plugged in {} times, {:.1} per day\n",
+                    self.plug_ins, per_day
+                )
+            }
+            None => text += &format!("Plugged in {} times\n", self.plug_ins),
+        }
+        text
+    }
+
+    pub fn json(&self) -> String
+    {
+        let optional = |value: Option<String>| value.unwrap_or_else(|| "null".to_string());
+        format!(
+            "{{\"days\":{:.2},\"on_battery\":{},\"on_ac\":{},\"drain_per_hour\":{},\"charge_per_hour\":{},\"runtime\":{},\"plug_ins\":{},\"plug_ins_per_day\":{}}}",
+            self.days,
+            self.on_battery.as_secs(),
+            self.on_ac.as_secs(),
+            optional(self.drain.map(|drain| format!("{:.2}", drain))),
+            optional(self.charge.map(|charge| format!("{:.2}", charge))),
+            optional(self.runtime().map(|runtime| runtime.as_secs().to_string())),
+            self.plug_ins,
+            optional(self.plug_ins_per_day().map(|per_day| format!("{:.2}", per_day))),
+        )
+    }
+}
+
+/// Adds up capacity changes over time.
+#[derive(Debug, Clone, Copy, Default)]
+struct Rate
+{
+    percent: f64,
+    time: Duration,
+}
+
+impl Rate
+{
+    /// Adds a segment, unless its change is not known.
+    fn add(&mut self, change: Option<f64>, duration: Duration)
+    {
+        if let Some(change) = change {
+            self.percent += change.max(0.0);
+            self.time += duration;
+        }
+    }
+
+    /// Returns the rate, once there is at least a quarter hour to go by.
+    fn per_hour(&self) -> Option<f64>
+    {
+        let hours = self.time.as_secs_f64() / (60.0 * 60.0);
+        (hours >= 0.25).then(|| self.percent / hours)
+    }
+}
+
+fn hours(duration: Duration) -> String
+{
+    let minutes = duration.as_secs() / 60;
+    format!("{}h {}m", minutes / 60, minutes % 60)
+}
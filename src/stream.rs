@@ -0,0 +1,130 @@
+use std::collections::VecDeque;
+use std::io;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::task::Context;
+use std::task::Poll;
+use std::task::Waker;
+use std::thread;
+use std::thread::JoinHandle;
+
+use futures_core::Stream;
+
+use crate::event::PowerEvent;
+use crate::monitor;
+use crate::power_supply::PowerSupply;
+
+/// The events of a power supply as a [`Stream`], for async code on any
+/// executor. The power supply runs on a thread of its own, see
+/// [`PowerSupply::spawn_monitor`], which wakes the task polling the stream for
+/// every event. The stream ends if reading the devices fails, and
+/// [`AsyncPowerSupply::error`] then tells why.
+pub struct AsyncPowerSupply
+{
+    shared: Arc<Mutex<Shared>>,
+    thread: Option<JoinHandle<io::Result<()>>>,
+    error: Option<io::Error>,
+}
+
+/// State handed from the monitor thread to the stream.
+#[derive(Default)]
+struct Shared
+{
+    events: VecDeque<PowerEvent>,
+    waker: Option<Waker>,
+    /// Set by the thread once it stopped.
+    done: bool,
+    /// Set by the stream once it was dropped.
+    closed: bool,
+}
+
+impl AsyncPowerSupply
+{
+    #[must_use]
+    pub fn new() -> Self
+    {
+        Self::with(|_| {})
+    }
+
+    /// Calls `configure` on the power supply before it is first read.
+    pub fn with(configure: impl FnOnce(&mut PowerSupply) + Send + 'static) -> Self
+    {
+        let shared = Arc::new(Mutex::new(Shared::default()));
+        let thread = {
+            let shared = Arc::clone(&shared);
+            thread::spawn(move || {
+                let result = monitor::run(configure, |event| {
+                    let mut shared = shared.lock().unwrap();
+                    shared.events.push_back(event);
+                    if let Some(waker) = shared.waker.take() {
+                        waker.wake();
+                    }
+                    !shared.closed
+                });
+                let mut shared = shared.lock().unwrap();
+                shared.done = true;
+                if let Some(waker) = shared.waker.take() {
+                    waker.wake();
+                }
+                result
+            })
+        };
+        Self {
+            shared,
+            thread: Some(thread),
+            error: None,
+        }
+    }
+
+    /// Returns the error that ended the stream, if any.
+    #[must_use]
+    pub fn error(&self) -> Option<&io::Error>
+    {
+        self.error.as_ref()
+    }
+}
+
+impl Default for AsyncPowerSupply
+{
+    fn default() -> Self
+    {
+        Self::new()
+    }
+}
+
+impl Stream for AsyncPowerSupply
+{
+    type Item = PowerEvent;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>>
+    {
+        let mut shared = self.shared.lock().unwrap();
+        if let Some(event) = shared.events.pop_front() {
+            return Poll::Ready(Some(event));
+        }
+        if !shared.done {
+            shared.waker = Some(cx.waker().clone());
+            return Poll::Pending;
+        }
+        drop(shared);
+        if let Some(thread) = self.thread.take() {
+            self.error = match thread.join() {
+                Ok(result) => result.err(),
+                Err(_) => Some(io::Error::other("the monitor thread panicked")),
+            };
+        }
+        Poll::Ready(None)
+    }
+}
+
+impl Drop for AsyncPowerSupply
+{
+    fn drop(&mut self)
+    {
+        // The thread notices at its next event, and is not waited for.
+        if let Ok(mut shared) = self.shared.lock() {
+            shared.closed = true;
+        }
+    }
+}
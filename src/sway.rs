@@ -0,0 +1,124 @@
+use std::env;
+use std::io;
+use std::io::Read;
+use std::io::Write;
+use std::os::unix::net::UnixStream;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use lithiumd::Fields;
+use lithiumd::Labels;
+use lithiumd::LevelThresholds;
+use lithiumd::PowerEvent;
+use lithiumd::Snapshot;
+use lithiumd::Template;
+
+use crate::sound;
+use crate::sound::Alert;
+
+/// How long sending a command may take, since the event loop waits for it.
+const TIMEOUT: Duration = Duration::from_secs(2);
+
+const MAGIC: &[u8; 6] = b"i3-ipc";
+const RUN_COMMAND: u32 = 0;
+
+/// Commands sent to sway or i3, from the `[sway]` table.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SwayOptions
+{
+    /// Path of the IPC socket, or `$SWAYSOCK` and then `$I3SOCK`.
+    pub socket: Option<PathBuf>,
+    /// Command sent on every change, e.g. to update a bar.
+    pub update: Option<Template>,
+    /// Commands sent for the same events as the `[sound]` ones.
+    pub connected: Option<String>,
+    pub disconnected: Option<String>,
+    pub critical: Option<String>,
+}
+
+/// A connection to the IPC socket of sway or i3, which run commands the way
+/// `swaymsg` and `i3-msg` do without a shell in between.
+pub struct Sway
+{
+    stream: UnixStream,
+}
+
+impl Sway
+{
+    pub fn connect(options: &SwayOptions) -> io::Result<Self>
+    {
+        let socket = options
+            .socket
+            .clone()
+            .or_else(|| env::var_os("SWAYSOCK").map(PathBuf::from))
+            .or_else(|| env::var_os("I3SOCK").map(PathBuf::from))
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::NotFound,
+                    "neither SWAYSOCK nor I3SOCK is set",
+                )
+            })?;
+        let stream = UnixStream::connect(&socket)
+            .map_err(|err| io::Error::new(err.kind(), format!("{}: {}", socket.display(), err)))?;
+        stream.set_read_timeout(Some(TIMEOUT))?;
+        stream.set_write_timeout(Some(TIMEOUT))?;
+        Ok(Self { stream })
+    }
+
+    /// Sends the commands for `events`: the alert of the most important one
+    /// and the update.
+    pub fn send(
+        &mut self,
+        options: &SwayOptions,
+        events: &[PowerEvent],
+        snapshot: &Snapshot,
+        fields: &Fields,
+        labels: &Labels,
+        levels: &LevelThresholds,
+    ) -> io::Result<()>
+    {
+        let alert = match sound::most_important(events, snapshot, levels) {
+            Some(Alert::Connected) => &options.connected,
+            Some(Alert::Disconnected) => &options.disconnected,
+            Some(Alert::Critical) => &options.critical,
+            None => &None,
+        };
+        if let Some(command) = alert {
+            self.run_command(command)?;
+        }
+        if let Some(update) = &options.update {
+            self.run_command(&update.render_with(fields, labels))?;
+        }
+        Ok(())
+    }
+
+    /// Sends `RUN_COMMAND` and waits for the reply, which fails if sway
+    /// rejected the command.
+    pub fn run_command(&mut self, command: &str) -> io::Result<()>
+    {
+        let mut message = MAGIC.to_vec();
+        message.extend((command.len() as u32).to_ne_bytes());
+        message.extend(RUN_COMMAND.to_ne_bytes());
+        message.extend(command.as_bytes());
+        self.stream.write_all(&message)?;
+
+        let mut header = [0; 14];
+        self.stream.read_exact(&mut header)?;
+        if &header[..6] != MAGIC {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "expected an i3-ipc reply",
+            ));
+        }
+        let len = u32::from_ne_bytes([header[6], header[7], header[8], header[9]]);
+        let mut reply = vec![0; len as usize];
+        self.stream.read_exact(&mut reply)?;
+        let reply = String::from_utf8_lossy(&reply);
+        // The reply is an array with a result for every command, and failed
+        // ones carry `"success":false` next to their error.
+        if reply.replace(' ', "").contains("\"success\":false") {
+            return Err(io::Error::other(format!("`{}` failed: {}", command, reply)));
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,165 @@
+use std::ffi::CString;
+use std::fs::File;
+use std::io;
+use std::os::fd::AsRawFd;
+use std::os::fd::FromRawFd;
+use std::os::fd::OwnedFd;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::FileExt;
+use std::path::Path;
+use std::str::FromStr;
+
+#[cfg(feature = "io-uring")]
+use crate::uring;
+
+/// Size of the buffer that attribute values are read into. sysfs never
+/// returns more than a page for a single attribute.
+const PAGE_SIZE: usize = 4096;
+
+/// An open sysfs attribute. `file` is `None` if the kernel does not expose
+/// the attribute for this device, in which case we do not keep asking.
+struct Attribute
+{
+    name: &'static str,
+    file: Option<File>,
+    buf: Box<[u8; PAGE_SIZE]>,
+    /// Length of a value that was read ahead of time by a batched refresh and
+    /// has not been consumed yet.
+    prefetched: Option<usize>,
+}
+
+/// A device directory under `/sys` whose attributes are read repeatedly.
+///
+/// The directory is opened once and each attribute is opened relative to it
+/// with `openat(2)` the first time it is requested. The attribute file is kept
+/// open afterwards so that a refresh only costs a single `pread(2)` at offset
+/// zero, which makes sysfs regenerate the value.
+pub struct AttributeDir
+{
+    dir: OwnedFd,
+    attributes: Vec<Attribute>,
+}
+
+impl AttributeDir
+{
+    pub fn open(path: &Path) -> io::Result<Self>
+    {
+        let path = CString::new(path.as_os_str().as_bytes())?;
+        let fd = unsafe {
+            libc::open(
+                path.as_ptr(),
+                libc::O_PATH | libc::O_DIRECTORY | libc::O_CLOEXEC,
+            )
+        };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Self {
+            dir: unsafe { OwnedFd::from_raw_fd(fd) },
+            attributes: Vec::new(),
+        })
+    }
+
+    /// Reads the current value of the attribute `name`, with the trailing
+    /// newline removed. Returns `None` if the device does not have such an
+    /// attribute or its value is not valid UTF-8.
+    ///
+    /// If the value was prefetched, it is returned without reading again.
+    pub fn read(&mut self, name: &'static str) -> io::Result<Option<&str>>
+    {
+        let index = match self.attributes.iter().position(|attr| attr.name == name) {
+            Some(index) => index,
+            None => {
+                let file = self.open_attribute(name)?;
+                self.attributes.push(Attribute {
+                    name,
+                    file,
+                    buf: Box::new([0; PAGE_SIZE]),
+                    prefetched: None,
+                });
+                self.attributes.len() - 1
+            }
+        };
+        let attr = &mut self.attributes[index];
+        let Some(file) = &attr.file else {
+            return Ok(None);
+        };
+        let len = match attr.prefetched.take() {
+            Some(len) => len,
+            None => match file.read_at(&mut attr.buf[..], 0) {
+                Ok(len) => len,
+                // Some drivers refuse to report an attribute while the device
+                // is in a transient state (e.g. the battery is being probed).
+                Err(err) if err.raw_os_error() == Some(libc::ENODATA) => return Ok(None),
+                Err(err) => return Err(err),
+            },
+        };
+        Ok(std::str::from_utf8(&attr.buf[..len])
+            .ok()
+            .map(|value| value.trim_end_matches('\n')))
+    }
+
+    /// Forgets prefetched values that were not consumed, so that the next
+    /// read goes to the kernel again.
+    #[cfg(feature = "io-uring")]
+    pub fn discard_prefetched(&mut self)
+    {
+        self.attributes
+            .iter_mut()
+            .for_each(|attr| attr.prefetched = None);
+    }
+
+    /// Reads the attribute `name` and parses it, returning `None` if it is
+    /// missing or malformed.
+    pub fn read_parsed<T: FromStr>(&mut self, name: &'static str) -> io::Result<Option<T>>
+    {
+        Ok(self.read(name)?.and_then(|value| value.parse().ok()))
+    }
+
+    fn open_attribute(&self, name: &'static str) -> io::Result<Option<File>>
+    {
+        let name = CString::new(name)?;
+        let fd = unsafe {
+            libc::openat(
+                self.dir.as_raw_fd(),
+                name.as_ptr(),
+                libc::O_RDONLY | libc::O_CLOEXEC,
+            )
+        };
+        if fd < 0 {
+            let err = io::Error::last_os_error();
+            return match err.kind() {
+                io::ErrorKind::NotFound | io::ErrorKind::PermissionDenied => Ok(None),
+                _ => Err(err),
+            };
+        }
+        Ok(Some(unsafe { File::from_raw_fd(fd) }))
+    }
+}
+
+/// Reads every attribute that was read before from all of `dirs` with a single
+/// io_uring submission. The values are handed out by the next call to
+/// [`AttributeDir::read`]; attributes whose read failed are read again
+/// synchronously at that point, so that the error is reported there.
+#[cfg(feature = "io-uring")]
+pub fn prefetch(ring: &mut uring::Ring, dirs: &mut [&mut AttributeDir])
+{
+    let mut attributes = dirs
+        .iter_mut()
+        .flat_map(|dir| dir.attributes.iter_mut())
+        .filter(|attr| attr.file.is_some())
+        .collect::<Vec<_>>();
+    let mut reads = attributes
+        .iter_mut()
+        .map(|attr| uring::Read {
+            // Checked by the filter above.
+            fd: attr.file.as_ref().map_or(-1, AsRawFd::as_raw_fd),
+            buf: &mut attr.buf[..],
+        })
+        .collect::<Vec<_>>();
+    let results = ring.read_all(&mut reads);
+    drop(reads);
+    for (attr, result) in attributes.iter_mut().zip(results) {
+        attr.prefetched = result.ok();
+    }
+}
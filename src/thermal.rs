@@ -0,0 +1,216 @@
+use std::collections::BTreeSet;
+use std::fmt::Display;
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+use std::time::Instant;
+
+const THERMAL: &str = "/sys/class/thermal";
+const CPUS: &str = "/sys/devices/system/cpu";
+
+/// How often the thermal zones are read, as most do not send uevents when
+/// their temperature changes.
+const SAMPLE_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Temperature above which thermal zones count as hot, e.g. to stop charging
+/// or warn while the laptop sits on a blanket.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ThermalLimit
+{
+    /// Types of the zones to watch, e.g. `x86_pkg_temp` or `acpitz`, or all
+    /// zones if empty.
+    pub zones: Vec<String>,
+    /// Temperature in °C at which a zone gets hot.
+    pub hot: f64,
+    /// How many °C a hot zone has to cool below `hot` to count as cool
+    /// again, so that a zone hovering around the limit is not reported over
+    /// and over.
+    pub hysteresis: f64,
+}
+
+/// The temperature of a thermal zone.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Temperature
+{
+    /// Kernel name of the zone, e.g. `thermal_zone3`.
+    pub sysname: String,
+    /// Type of the zone, e.g. `x86_pkg_temp`.
+    pub zone: String,
+    /// Temperature in m°C.
+    pub millicelsius: i32,
+}
+
+impl Display for Temperature
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result
+    {
+        write!(
+            f,
+            "{} at {:.1} °C",
+            self.zone,
+            f64::from(self.millicelsius) / 1000.0
+        )
+    }
+}
+
+/// Tracks the thermal zones against a [`ThermalLimit`].
+#[derive(Debug, Clone)]
+pub(crate) struct ThermalMonitor
+{
+    limit: ThermalLimit,
+    /// Sysnames of the zones that are hot.
+    hot: BTreeSet<String>,
+    last_sample: Option<Instant>,
+}
+
+impl ThermalMonitor
+{
+    pub(crate) fn new(limit: ThermalLimit) -> Self
+    {
+        Self {
+            limit,
+            hot: BTreeSet::new(),
+            last_sample: None,
+        }
+    }
+
+    /// Returns when the zones have to be read again.
+    pub(crate) fn next_sample(&self) -> Option<Instant>
+    {
+        self.last_sample.map(|at| at + SAMPLE_INTERVAL)
+    }
+
+    /// Reads the zones unless they were read less than the sample interval
+    /// ago, and returns the temperatures of those that just got hot and of
+    /// those that cooled down, in that order.
+    pub(crate) fn sample(&mut self, now: Instant) -> (Vec<Temperature>, Vec<Temperature>)
+    {
+        if self
+            .last_sample
+            .is_some_and(|last| now < last + SAMPLE_INTERVAL / 2)
+        {
+            return (Vec::new(), Vec::new());
+        }
+        self.last_sample = Some(now);
+        let hot = (self.limit.hot * 1000.0) as i32;
+        let cool = ((self.limit.hot - self.limit.hysteresis) * 1000.0) as i32;
+        let mut heated = Vec::new();
+        let mut cooled = Vec::new();
+        for temperature in read_zones(&self.limit.zones) {
+            if temperature.millicelsius >= hot {
+                if self.hot.insert(temperature.sysname.clone()) {
+                    heated.push(temperature);
+                }
+            } else if temperature.millicelsius < cool && self.hot.remove(&temperature.sysname) {
+                cooled.push(temperature);
+            }
+        }
+        (heated, cooled)
+    }
+}
+
+/// Reads the temperatures of the thermal zones of the given types, or of all
+/// of them. Zones that cannot be read, e.g. of a sensor that is powered down,
+/// are skipped.
+#[must_use]
+pub fn read_zones(zones: &[String]) -> Vec<Temperature>
+{
+    let Ok(entries) = fs::read_dir(THERMAL) else {
+        return Vec::new();
+    };
+    let mut temperatures = entries
+        .flatten()
+        .filter(|entry| {
+            entry
+                .file_name()
+                .to_string_lossy()
+                .starts_with("thermal_zone")
+        })
+        .filter_map(|entry| read_zone(&entry.path()))
+        .filter(|temperature| zones.is_empty() || zones.contains(&temperature.zone))
+        .collect::<Vec<_>>();
+    temperatures.sort_by(|a, b| a.sysname.cmp(&b.sysname));
+    temperatures
+}
+
+fn read_zone(dir: &Path) -> Option<Temperature>
+{
+    let zone = fs::read_to_string(dir.join("type")).ok()?;
+    let millicelsius = fs::read_to_string(dir.join("temp"))
+        .ok()?
+        .trim()
+        .parse()
+        .ok()?;
+    Some(Temperature {
+        sysname: dir.file_name()?.to_string_lossy().into_owned(),
+        zone: zone.trim().to_string(),
+        millicelsius,
+    })
+}
+
+/// Counts how often the CPUs were throttled because they ran too hot, from
+/// the `thermal_throttle` counters of x86 CPUs. `None` if there are none.
+#[must_use]
+pub fn throttle_count() -> Option<u64>
+{
+    let entries = fs::read_dir(CPUS).ok()?;
+    let mut total = None;
+    for entry in entries.flatten() {
+        let dir = entry.path().join("thermal_throttle");
+        for counter in ["core_throttle_count", "package_throttle_count"] {
+            let Some(count) = fs::read_to_string(dir.join(counter))
+                .ok()
+                .and_then(|count| count.trim().parse::<u64>().ok())
+            else {
+                continue;
+            };
+            *total.get_or_insert(0) += count;
+        }
+    }
+    total
+}
+
+/// Tracks the throttle counters, to report the CPUs being throttled and
+/// running at full speed again.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct ThrottleMonitor
+{
+    count: Option<u64>,
+    throttling: bool,
+    last_sample: Option<Instant>,
+}
+
+impl ThrottleMonitor
+{
+    /// Returns when the counters have to be read again.
+    pub(crate) fn next_sample(&self) -> Option<Instant>
+    {
+        self.last_sample.map(|at| at + SAMPLE_INTERVAL)
+    }
+
+    /// Reads the counters unless they were read less than the sample
+    /// interval ago. Returns `Some(true)` if the CPUs started being
+    /// throttled since, and `Some(false)` if they were and no longer are.
+    pub(crate) fn sample(&mut self, now: Instant) -> Option<bool>
+    {
+        if self
+            .last_sample
+            .is_some_and(|last| now < last + SAMPLE_INTERVAL / 2)
+        {
+            return None;
+        }
+        self.last_sample = Some(now);
+        let count = throttle_count();
+        let throttling = match (self.count, count) {
+            (Some(old), Some(new)) => new > old,
+            _ => false,
+        };
+        self.count = count;
+        if throttling == self.throttling {
+            return None;
+        }
+        self.throttling = throttling;
+        Some(throttling)
+    }
+}
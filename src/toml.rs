@@ -0,0 +1,421 @@
+use std::collections::BTreeMap;
+use std::fmt::Display;
+
+/// A table of keys to values, as found at the top level of a document or
+/// under a `[header]`.
+pub type Table = BTreeMap<String, Value>;
+
+/// A value in the subset of TOML understood by the configuration parser.
+///
+/// Dates, inline tables and multi-line strings are not supported.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value
+{
+    String(String),
+    Integer(i64),
+    Float(f64),
+    Boolean(bool),
+    Array(Vec<Value>),
+    Table(Table),
+}
+
+impl Value
+{
+    /// Returns a human-readable name of the type of the value, for error
+    /// messages.
+    #[must_use]
+    pub fn type_name(&self) -> &'static str
+    {
+        match self {
+            Value::String(_) => "string",
+            Value::Integer(_) => "integer",
+            Value::Float(_) => "float",
+            Value::Boolean(_) => "boolean",
+            Value::Array(_) => "array",
+            Value::Table(_) => "table",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Error
+{
+    pub line: usize,
+    pub message: String,
+}
+
+impl Display for Error
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result
+    {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Parses a TOML document into its top-level table.
+pub fn parse(input: &str) -> Result<Table, Error>
+{
+    let mut root = Table::new();
+    // Path of the table that key/value pairs are currently inserted into.
+    let mut current = Vec::<String>::new();
+    let mut lines = input.lines().enumerate().peekable();
+
+    while let Some((index, line)) = lines.next() {
+        let line_no = index + 1;
+        let err = |message: String| Error {
+            line: line_no,
+            message,
+        };
+        let mut line = strip_comment(line).trim().to_string();
+        if line.is_empty() {
+            continue;
+        }
+
+        if let Some(header) = line.strip_prefix("[[") {
+            let header = header
+                .strip_suffix("]]")
+                .ok_or_else(|| err("unterminated table header".to_string()))?;
+            current = parse_key(header).map_err(err)?;
+            let (last, parents) = current.split_last().unwrap();
+            let parent = table_at(&mut root, parents).map_err(err)?;
+            match parent
+                .entry(last.clone())
+                .or_insert_with(|| Value::Array(Vec::new()))
+            {
+                Value::Array(tables) => tables.push(Value::Table(Table::new())),
+                _ => return Err(err(format!("`{}` is not an array of tables", last))),
+            }
+            continue;
+        }
+
+        if let Some(header) = line.strip_prefix('[') {
+            let header = header
+                .strip_suffix(']')
+                .ok_or_else(|| err("unterminated table header".to_string()))?;
+            current = parse_key(header).map_err(err)?;
+            table_at(&mut root, &current).map_err(err)?;
+            continue;
+        }
+
+        // Arrays may span several lines; keep reading until the brackets
+        // are balanced.
+        while !brackets_balanced(&line) {
+            match lines.next() {
+                Some((_, next)) => {
+                    line.push(' ');
+                    line.push_str(strip_comment(next).trim());
+                }
+                None => return Err(err("unterminated array".to_string())),
+            }
+        }
+
+        let (key, value) = line
+            .split_once('=')
+            .ok_or_else(|| err("expected `key = value`".to_string()))?;
+        let mut key = parse_key(key).map_err(err)?;
+        let value = parse_value(value.trim()).map_err(err)?;
+        let last = key.pop().unwrap();
+        let mut path = current.clone();
+        path.extend(key);
+        let table = table_at(&mut root, &path).map_err(err)?;
+        if table.insert(last.clone(), value).is_some() {
+            return Err(err(format!("duplicate key `{}`", last)));
+        }
+    }
+
+    Ok(root)
+}
+
+/// Returns the table at `path`, creating intermediate tables as needed. If a
+/// path component names an array of tables, its last element is used.
+fn table_at<'a>(root: &'a mut Table, path: &[String]) -> Result<&'a mut Table, String>
+{
+    let mut table = root;
+    for key in path {
+        let value = table
+            .entry(key.clone())
+            .or_insert_with(|| Value::Table(Table::new()));
+        table = match value {
+            Value::Table(table) => table,
+            Value::Array(values) => match values.last_mut() {
+                Some(Value::Table(table)) => table,
+                _ => return Err(format!("`{}` is not a table", key)),
+            },
+            _ => return Err(format!("`{}` is not a table", key)),
+        };
+    }
+    Ok(table)
+}
+
+fn strip_comment(line: &str) -> &str
+{
+    let mut in_string = None;
+    let mut escaped = false;
+    for (i, c) in line.char_indices() {
+        match (in_string, c) {
+            (Some('"'), '\\') if !escaped => {
+                escaped = true;
+                continue;
+            }
+            (Some(quote), c) if c == quote && !escaped => in_string = None,
+            (None, '"' | '\'') => in_string = Some(c),
+            (None, '#') => return &line[..i],
+            _ => {}
+        }
+        escaped = false;
+    }
+    line
+}
+
+fn brackets_balanced(line: &str) -> bool
+{
+    let mut depth = 0i32;
+    let mut in_string = None;
+    let mut escaped = false;
+    for c in line.chars() {
+        match (in_string, c) {
+            (Some('"'), '\\') if !escaped => {
+                escaped = true;
+                continue;
+            }
+            (Some(quote), c) if c == quote && !escaped => in_string = None,
+            (None, '"' | '\'') => in_string = Some(c),
+            (None, '[') => depth += 1,
+            (None, ']') => depth -= 1,
+            _ => {}
+        }
+        escaped = false;
+    }
+    depth <= 0
+}
+
+fn parse_key(key: &str) -> Result<Vec<String>, String>
+{
+    key.split('.')
+        .map(|part| {
+            let part = part.trim();
+            if let Some(quoted) = part.strip_prefix('"').and_then(|p| p.strip_suffix('"')) {
+                Ok(quoted.to_string())
+            } else if !part.is_empty()
+                && part
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+            {
+                Ok(part.to_string())
+            } else {
+                Err(format!("invalid key `{}`", key.trim()))
+            }
+        })
+        .collect()
+}
+
+fn parse_value(input: &str) -> Result<Value, String>
+{
+    let (value, rest) = parse_value_prefix(input)?;
+    if rest.trim().is_empty() {
+        Ok(value)
+    } else {
+        Err(format!("unexpected `{}` after value", rest.trim()))
+    }
+}
+
+/// Parses a value at the start of `input` and returns it together with the
+/// remaining input.
+fn parse_value_prefix(input: &str) -> Result<(Value, &str), String>
+{
+    let input = input.trim_start();
+    if let Some(rest) = input.strip_prefix('"') {
+        let mut value = String::new();
+        let mut chars = rest.char_indices();
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '"' => return Ok((Value::String(value), &rest[i + 1..])),
+                '\\' => match chars.next().map(|(_, c)| c) {
+                    Some('n') => value.push('\n'),
+                    Some('t') => value.push('\t'),
+                    Some('\\') => value.push('\\'),
+                    Some('"') => value.push('"'),
+                    Some(c) => return Err(format!("unknown escape `\\{}`", c)),
+                    None => break,
+                },
+                c => value.push(c),
+            }
+        }
+        Err("unterminated string".to_string())
+    } else if let Some(rest) = input.strip_prefix('\'') {
+        let end = rest
+            .find('\'')
+            .ok_or_else(|| "unterminated string".to_string())?;
+        Ok((Value::String(rest[..end].to_string()), &rest[end + 1..]))
+    } else if let Some(mut rest) = input.strip_prefix('[') {
+        let mut values = Vec::new();
+        loop {
+            rest = rest.trim_start();
+            if let Some(rest) = rest.strip_prefix(']') {
+                return Ok((Value::Array(values), rest));
+            }
+            let (value, remaining) = parse_value_prefix(rest)?;
+            values.push(value);
+            rest = remaining.trim_start();
+            if let Some(remaining) = rest.strip_prefix(',') {
+                rest = remaining;
+            } else if !rest.starts_with(']') {
+                return Err("expected `,` or `]` in array".to_string());
+            }
+        }
+    } else {
+        let end = input
+            .find(|c: char| c == ',' || c == ']' || c.is_whitespace())
+            .unwrap_or(input.len());
+        let (token, rest) = input.split_at(end);
+        let value = match token {
+            "true" => Value::Boolean(true),
+            "false" => Value::Boolean(false),
+            _ => {
+                let number = token.replace('_', "");
+                if let Ok(integer) = number.parse::<i64>() {
+                    Value::Integer(integer)
+                } else if let Ok(float) = number.parse::<f64>() {
+                    Value::Float(float)
+                } else {
+                    return Err(format!("invalid value `{}`", token));
+                }
+            }
+        };
+        Ok((value, rest))
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    fn string(value: &str) -> Value
+    {
+        Value::String(value.to_string())
+    }
+
+    #[test]
+    fn keeps_comment_signs_inside_strings()
+    {
+        let table = parse(
+            r##"
+            # A comment.
+            run = "echo '#1' \"#2\"" # Another one.
+            path = '/tmp/#dir'
+            "##,
+        )
+        .unwrap();
+        assert_eq!(table["run"], string("echo '#1' \"#2\""));
+        assert_eq!(table["path"], string("/tmp/#dir"));
+    }
+
+    #[test]
+    fn reads_arrays_across_lines()
+    {
+        let table = parse(
+            r#"
+            cmd = ["a\"]", # The bracket is quoted.
+                   "b]",
+                   [1, 2.5, true],
+            ]
+            after = 1
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            table["cmd"],
+            Value::Array(vec![
+                string("a\"]"),
+                string("b]"),
+                Value::Array(vec![
+                    Value::Integer(1),
+                    Value::Float(2.5),
+                    Value::Boolean(true)
+                ]),
+            ])
+        );
+        assert_eq!(table["after"], Value::Integer(1));
+    }
+
+    #[test]
+    fn reads_arrays_of_tables_with_subtables()
+    {
+        let table = parse(
+            "
+            [[rule]]
+            run = 'first'
+            [rule.when]
+            capacity_below = 10
+            [[rule]]
+            run = 'second'
+            ",
+        )
+        .unwrap();
+        let Value::Array(rules) = &table["rule"] else {
+            panic!("`rule` is {:?}", table["rule"]);
+        };
+        assert_eq!(rules.len(), 2);
+        let Value::Table(first) = &rules[0] else {
+            panic!("the first rule is {:?}", rules[0]);
+        };
+        assert_eq!(first["run"], string("first"));
+        let Value::Table(when) = &first["when"] else {
+            panic!("`when` is {:?}", first["when"]);
+        };
+        assert_eq!(when["capacity_below"], Value::Integer(10));
+        let Value::Table(second) = &rules[1] else {
+            panic!("the second rule is {:?}", rules[1]);
+        };
+        assert!(!second.contains_key("when"));
+    }
+
+    #[test]
+    fn rejects_duplicate_keys()
+    {
+        let err = parse("[a]\nb = 1\nc = 2\nb = 3\n").unwrap_err();
+        assert_eq!(
+            err,
+            Error {
+                line: 4,
+                message: "duplicate key `b`".to_string()
+            }
+        );
+        assert!(parse("a.b = 1\n[a]\nb = 2\n").is_err());
+    }
+
+    #[test]
+    fn rejects_malformed_documents()
+    {
+        for input in [
+            "[",
+            "[[",
+            "[]",
+            "[[]]",
+            "[a",
+            "[[a]",
+            "a",
+            "= 1",
+            "a =",
+            "a = \"",
+            "a = \"\\",
+            "a = \"\\x\"",
+            "a = '",
+            "a = [",
+            "a = [1,",
+            "a = [1 2]",
+            "a = ]",
+            "a = 1 2",
+            "a = 1\na.b = 2",
+            "a.b = 1\n[[a.b]]",
+            "a = \"é\" ü",
+            "\"a = 1",
+            "a..b = 1",
+        ] {
+            assert!(parse(input).is_err(), "{:?} parsed", input);
+        }
+    }
+}
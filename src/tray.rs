@@ -0,0 +1,178 @@
+use std::io;
+use std::sync::mpsc;
+use std::sync::mpsc::Receiver;
+use std::sync::mpsc::Sender;
+
+use ksni::blocking::Handle;
+use ksni::blocking::TrayMethods;
+use ksni::menu::CheckmarkItem;
+use ksni::MenuItem;
+use ksni::ToolTip;
+use lithiumd::Fields;
+use lithiumd::Status;
+use mio::Waker;
+
+/// A toggle in the menu of the tray icon, for the daemon to act on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TrayAction
+{
+    /// Turn presentation mode on or off.
+    SetInhibited(bool),
+    /// Switch to the power-saver profile, or back to balanced.
+    SetPowerSaver(bool),
+}
+
+/// A battery icon in the system tray, served over StatusNotifierItem by a
+/// thread of ksni. The menu sends its toggles back through a channel and
+/// wakes the poll of the daemon, which reads them with [`Tray::actions`].
+pub struct Tray
+{
+    handle: Handle<Item>,
+    actions: Receiver<TrayAction>,
+}
+
+/// What the tray thread shows.
+struct Item
+{
+    fields: Fields,
+    inhibited: bool,
+    power_saver: bool,
+    actions: Sender<TrayAction>,
+    waker: Waker,
+}
+
+impl Tray
+{
+    /// Shows the icon. `waker` is woken whenever the menu was used.
+    pub fn spawn(waker: Waker) -> io::Result<Self>
+    {
+        let (sender, actions) = mpsc::channel();
+        let item = Item {
+            fields: Fields {
+                status: Status::Unknown,
+                capacity: None,
+                level: None,
+                time_remaining: None,
+            },
+            inhibited: false,
+            power_saver: false,
+            actions: sender,
+            waker,
+        };
+        let handle = item
+            .spawn()
+            .map_err(|err| io::Error::other(err.to_string()))?;
+        Ok(Self { handle, actions })
+    }
+
+    /// Updates the icon and the tooltip.
+    pub fn update(&self, fields: Fields, inhibited: bool)
+    {
+        self.handle.update(|item| {
+            item.fields = fields;
+            item.inhibited = inhibited;
+        });
+    }
+
+    /// Returns the toggles used since the last call.
+    pub fn actions(&self) -> impl Iterator<Item = TrayAction> + '_
+    {
+        self.actions.try_iter()
+    }
+}
+
+impl Drop for Tray
+{
+    fn drop(&mut self)
+    {
+        self.handle.shutdown().wait();
+    }
+}
+
+impl Item
+{
+    fn send(&self, action: TrayAction)
+    {
+        if self.actions.send(action).is_ok() {
+            let _ = self.waker.wake();
+        }
+    }
+}
+
+impl ksni::Tray for Item
+{
+    fn id(&self) -> String
+    {
+        "lithiumd".to_string()
+    }
+
+    fn title(&self) -> String
+    {
+        "Battery".to_string()
+    }
+
+    /// Picks one of the `battery-level-*` icons of the freedesktop naming
+    /// spec, which come in steps of ten percent.
+    fn icon_name(&self) -> String
+    {
+        let Some(capacity) = self.fields.capacity else {
+            return "battery-missing-symbolic".to_string();
+        };
+        let level = (u32::from(capacity) + 5) / 10 * 10;
+        match self.fields.status {
+            Status::Charging => format!("battery-level-{}-charging-symbolic", level),
+            Status::Full | Status::NotCharging if level == 100 => {
+                "battery-level-100-charged-symbolic".to_string()
+            }
+            _ => format!("battery-level-{}-symbolic", level),
+        }
+    }
+
+    fn tool_tip(&self) -> ToolTip
+    {
+        let mut description = match self.fields.capacity {
+            Some(capacity) => format!("{}%, {}", capacity, self.fields.status),
+            None => self.fields.status.to_string(),
+        };
+        if let Some(remaining) = self.fields.time_remaining {
+            let minutes = remaining.as_secs() / 60;
+            let label = match self.fields.status {
+                Status::Charging => "until full",
+                _ => "remaining",
+            };
+            description += &format!(", {}h {}m {}", minutes / 60, minutes % 60, label);
+        }
+        ToolTip {
+            icon_name: self.icon_name(),
+            title: "Battery".to_string(),
+            description,
+            ..ToolTip::default()
+        }
+    }
+
+    fn menu(&self) -> Vec<MenuItem<Self>>
+    {
+        vec![
+            CheckmarkItem {
+                label: "Presentation mode".to_string(),
+                checked: self.inhibited,
+                activate: Box::new(|item: &mut Self| {
+                    item.inhibited = !item.inhibited;
+                    item.send(TrayAction::SetInhibited(item.inhibited));
+                }),
+                ..CheckmarkItem::default()
+            }
+            .into(),
+            CheckmarkItem {
+                label: "Power saver".to_string(),
+                checked: self.power_saver,
+                activate: Box::new(|item: &mut Self| {
+                    item.power_saver = !item.power_saver;
+                    item.send(TrayAction::SetPowerSaver(item.power_saver));
+                }),
+                ..CheckmarkItem::default()
+            }
+            .into(),
+        ]
+    }
+}
@@ -0,0 +1,151 @@
+use std::collections::BTreeMap;
+use std::io;
+use std::os::fd::AsRawFd;
+use std::os::fd::RawFd;
+use std::path::PathBuf;
+
+use crate::backend::BackendDevice;
+use crate::backend::PowerBackend;
+use crate::backend::Uevents;
+use crate::dbus::Connection;
+use crate::dbus::Error;
+use crate::dbus::Message;
+use crate::dbus::Value;
+
+const UPOWER: &str = "org.freedesktop.UPower";
+const UPOWER_DEVICE: &str = "org.freedesktop.UPower.Device";
+
+/// The power supply devices that UPower knows about, followed over the
+/// system bus, for sandboxes that can read sysfs but not receive uevents.
+/// UPower names each device by its `NativePath`, from which the attributes
+/// are read in `/sys/class/power_supply` like with the other backends.
+/// Devices without one there, e.g. the display device, are left out.
+pub struct UPowerBackend
+{
+    conn: Connection,
+    /// Native paths of the devices by their UPower object.
+    devices: BTreeMap<String, PathBuf>,
+}
+
+impl UPowerBackend
+{
+    /// Connects to the system bus and subscribes to the devices of UPower.
+    /// UPower itself does not have to be running yet.
+    pub fn new() -> io::Result<Self>
+    {
+        let mut conn = Connection::system()?;
+        for member in ["DeviceAdded", "DeviceRemoved"] {
+            conn.add_match(&format!(
+                "type='signal',sender='{}',interface='{}',member='{}'",
+                UPOWER, UPOWER, member
+            ))?;
+        }
+        conn.add_match(&format!(
+            "type='signal',sender='{}',interface='org.freedesktop.DBus.Properties',member='PropertiesChanged',arg0='{}'",
+            UPOWER, UPOWER_DEVICE
+        ))?;
+        conn.add_match(&format!(
+            "type='signal',sender='org.freedesktop.DBus',member='NameOwnerChanged',arg0='{}'",
+            UPOWER
+        ))?;
+        Ok(Self {
+            conn,
+            devices: BTreeMap::new(),
+        })
+    }
+
+    /// Returns the sysfs directory of the UPower device `path`, if it has
+    /// one.
+    fn syspath(&mut self, path: &str) -> io::Result<Option<PathBuf>>
+    {
+        let native = self
+            .conn
+            .get_property(UPOWER, path, UPOWER_DEVICE, "NativePath")?;
+        let Some(native) = native.as_str().filter(|native| !native.is_empty()) else {
+            return Ok(None);
+        };
+        // Relative to the class for devices of `power_supply`.
+        let syspath = PathBuf::from("/sys/class/power_supply").join(native);
+        Ok(syspath.is_dir().then_some(syspath))
+    }
+
+    /// Reads the UPower device `path` and remembers its native path.
+    fn read(&mut self, path: &str) -> io::Result<Option<BackendDevice>>
+    {
+        let Some(syspath) = self.syspath(path)? else {
+            return Ok(None);
+        };
+        self.devices.insert(path.to_string(), syspath.clone());
+        BackendDevice::read(syspath).map(Some)
+    }
+}
+
+impl PowerBackend for UPowerBackend
+{
+    /// Returns no devices while UPower is not running.
+    fn enumerate(&mut self) -> io::Result<Vec<BackendDevice>>
+    {
+        self.devices.clear();
+        let reply = self.conn.call(Message::method_call(
+            UPOWER,
+            "/org/freedesktop/UPower",
+            UPOWER,
+            "EnumerateDevices",
+            Vec::new(),
+        ));
+        let reply = match reply {
+            Ok(reply) => reply,
+            Err(err) if Error::is_service_unknown(&err) => return Ok(Vec::new()),
+            Err(err) => return Err(err),
+        };
+        let paths = reply
+            .body
+            .first()
+            .and_then(Value::as_array)
+            .unwrap_or_default()
+            .iter()
+            .filter_map(Value::as_str)
+            .map(str::to_string)
+            .collect::<Vec<_>>();
+        let mut devices = Vec::new();
+        for path in paths {
+            devices.extend(self.read(&path)?);
+        }
+        Ok(devices)
+    }
+
+    fn fd(&mut self) -> io::Result<RawFd>
+    {
+        Ok(self.conn.as_raw_fd())
+    }
+
+    fn drain_events(&mut self) -> io::Result<Uevents>
+    {
+        let mut uevents = Uevents::default();
+        while let Some(message) = self.conn.read()? {
+            let path = message
+                .body
+                .first()
+                .and_then(Value::as_str)
+                .map(str::to_string);
+            if message.is_signal(UPOWER, "DeviceAdded") {
+                if let Some(path) = path {
+                    uevents.devices.extend(self.read(&path)?);
+                }
+            } else if message.is_signal(UPOWER, "DeviceRemoved") {
+                if let Some(syspath) = path.and_then(|path| self.devices.remove(&path)) {
+                    uevents.devices.push(BackendDevice::removed(syspath));
+                }
+            } else if message.is_signal("org.freedesktop.DBus.Properties", "PropertiesChanged") {
+                if let Some(syspath) = message.path.and_then(|path| self.devices.get(&path)) {
+                    uevents.devices.push(BackendDevice::read(syspath.clone())?);
+                }
+            } else if message.is_signal("org.freedesktop.DBus", "NameOwnerChanged") {
+                // UPower started or stopped, its devices are to be asked for
+                // again.
+                uevents.overrun = true;
+            }
+        }
+        Ok(uevents)
+    }
+}
@@ -0,0 +1,401 @@
+use std::io;
+use std::os::fd::AsRawFd;
+use std::os::fd::FromRawFd;
+use std::os::fd::OwnedFd;
+use std::os::fd::RawFd;
+use std::process;
+use std::ptr;
+use std::sync::atomic::AtomicU32;
+use std::sync::atomic::Ordering;
+
+const IORING_OFF_SQ_RING: libc::off_t = 0;
+const IORING_OFF_CQ_RING: libc::off_t = 0x8000000;
+const IORING_OFF_SQES: libc::off_t = 0x10000000;
+const IORING_FEAT_SINGLE_MMAP: u32 = 1 << 0;
+const IORING_ENTER_GETEVENTS: libc::c_uint = 1 << 0;
+const IORING_OP_READ: u8 = 22;
+
+#[repr(C)]
+#[derive(Default)]
+struct SqringOffsets
+{
+    head: u32,
+    tail: u32,
+    ring_mask: u32,
+    ring_entries: u32,
+    flags: u32,
+    dropped: u32,
+    array: u32,
+    resv1: u32,
+    user_addr: u64,
+}
+
+#[repr(C)]
+#[derive(Default)]
+struct CqringOffsets
+{
+    head: u32,
+    tail: u32,
+    ring_mask: u32,
+    ring_entries: u32,
+    overflow: u32,
+    cqes: u32,
+    flags: u32,
+    resv1: u32,
+    user_addr: u64,
+}
+
+#[repr(C)]
+#[derive(Default)]
+struct Params
+{
+    sq_entries: u32,
+    cq_entries: u32,
+    flags: u32,
+    sq_thread_cpu: u32,
+    sq_thread_idle: u32,
+    features: u32,
+    wq_fd: u32,
+    resv: [u32; 3],
+    sq_off: SqringOffsets,
+    cq_off: CqringOffsets,
+}
+
+#[repr(C)]
+struct Sqe
+{
+    opcode: u8,
+    flags: u8,
+    ioprio: u16,
+    fd: i32,
+    off: u64,
+    addr: u64,
+    len: u32,
+    rw_flags: u32,
+    user_data: u64,
+    buf_index: u16,
+    personality: u16,
+    splice_fd_in: i32,
+    addr3: u64,
+    pad: u64,
+}
+
+#[repr(C)]
+struct Cqe
+{
+    user_data: u64,
+    res: i32,
+    flags: u32,
+}
+
+/// A memory mapping of one of the ring regions.
+struct Mmap
+{
+    ptr: *mut libc::c_void,
+    len: usize,
+}
+
+impl Mmap
+{
+    fn new(fd: RawFd, len: usize, offset: libc::off_t) -> io::Result<Self>
+    {
+        let ptr = unsafe {
+            libc::mmap(
+                ptr::null_mut(),
+                len,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_SHARED | libc::MAP_POPULATE,
+                fd,
+                offset,
+            )
+        };
+        if ptr == libc::MAP_FAILED {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Self { ptr, len })
+    }
+
+    /// Returns a pointer `offset` bytes into the mapping.
+    fn at<T>(&self, offset: u32) -> *mut T
+    {
+        unsafe { self.ptr.cast::<u8>().add(offset as usize).cast() }
+    }
+}
+
+impl Drop for Mmap
+{
+    fn drop(&mut self)
+    {
+        unsafe { libc::munmap(self.ptr, self.len) };
+    }
+}
+
+/// A read to be submitted as part of a batch: `buf` is filled from offset
+/// zero of `fd`.
+pub struct Read<'a>
+{
+    pub fd: RawFd,
+    pub buf: &'a mut [u8],
+}
+
+/// A minimal io_uring instance that only knows how to submit batches of
+/// reads and wait for all of them to complete.
+pub struct Ring
+{
+    // The mappings have to be unmapped before the ring is closed.
+    sq: Mmap,
+    cq: Option<Mmap>,
+    sqes: Mmap,
+    fd: OwnedFd,
+    params: Params,
+    /// Tags the `user_data` of the reads of the current batch.
+    batch: u32,
+}
+
+impl Ring
+{
+    pub fn new(entries: u32) -> io::Result<Self>
+    {
+        let mut params = Params::default();
+        let fd = unsafe { libc::syscall(libc::SYS_io_uring_setup, entries, &mut params) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let fd = unsafe { OwnedFd::from_raw_fd(fd as RawFd) };
+
+        let sq_len = params.sq_off.array as usize + params.sq_entries as usize * 4;
+        let cq_len =
+            params.cq_off.cqes as usize + params.cq_entries as usize * std::mem::size_of::<Cqe>();
+        let single_mmap = params.features & IORING_FEAT_SINGLE_MMAP != 0;
+        let sq = Mmap::new(
+            fd.as_raw_fd(),
+            if single_mmap {
+                sq_len.max(cq_len)
+            } else {
+                sq_len
+            },
+            IORING_OFF_SQ_RING,
+        )?;
+        let cq = if single_mmap {
+            None
+        } else {
+            Some(Mmap::new(fd.as_raw_fd(), cq_len, IORING_OFF_CQ_RING)?)
+        };
+        let sqes = Mmap::new(
+            fd.as_raw_fd(),
+            params.sq_entries as usize * std::mem::size_of::<Sqe>(),
+            IORING_OFF_SQES,
+        )?;
+
+        Ok(Self {
+            sq,
+            cq,
+            sqes,
+            fd,
+            params,
+            batch: 0,
+        })
+    }
+
+    /// Submits all `reads` and waits for them to complete, using as few
+    /// `io_uring_enter(2)` calls as the ring size allows. The result of each
+    /// read is returned in the same order. If waiting fails, the reads that
+    /// were submitted are still waited for before this returns, so that none
+    /// of them completes into a buffer that was given back meanwhile.
+    pub fn read_all(&mut self, reads: &mut [Read<'_>]) -> Vec<io::Result<usize>>
+    {
+        let mut results = Vec::with_capacity(reads.len());
+        for chunk in reads.chunks_mut(self.params.sq_entries as usize) {
+            let start = results.len();
+            results.extend(chunk.iter().map(|_| Ok(0)));
+            if let Err(err) = self.submit_and_wait(chunk, &mut results[start..]) {
+                let kind = err.kind();
+                results[start..]
+                    .iter_mut()
+                    .for_each(|result| *result = Err(io::Error::from(kind)));
+            }
+        }
+        results
+    }
+
+    fn submit_and_wait(
+        &mut self,
+        reads: &mut [Read<'_>],
+        results: &mut [io::Result<usize>],
+    ) -> io::Result<()>
+    {
+        // Completions of an earlier batch that were not reaped must not be
+        // taken for those of this one.
+        self.batch = self.batch.wrapping_add(1);
+        let batch = u64::from(self.batch) << 32;
+
+        let sq_off = &self.params.sq_off;
+        let sq_head = unsafe { &*self.sq.at::<AtomicU32>(sq_off.head) };
+        let sq_tail = unsafe { &*self.sq.at::<AtomicU32>(sq_off.tail) };
+        let sq_mask = unsafe { *self.sq.at::<u32>(sq_off.ring_mask) };
+        let sq_array = self.sq.at::<u32>(sq_off.array);
+        let sqes = self.sqes.at::<Sqe>(0);
+
+        let mut tail = sq_tail.load(Ordering::Acquire);
+        for (i, read) in reads.iter_mut().enumerate() {
+            let index = tail & sq_mask;
+            unsafe {
+                sqes.add(index as usize).write(Sqe {
+                    opcode: IORING_OP_READ,
+                    flags: 0,
+                    ioprio: 0,
+                    fd: read.fd,
+                    off: 0,
+                    addr: read.buf.as_mut_ptr() as u64,
+                    len: read.buf.len() as u32,
+                    rw_flags: 0,
+                    user_data: batch | i as u64,
+                    buf_index: 0,
+                    personality: 0,
+                    splice_fd_in: 0,
+                    addr3: 0,
+                    pad: 0,
+                });
+                *sq_array.add(index as usize) = index;
+            }
+            tail = tail.wrapping_add(1);
+        }
+        sq_tail.store(tail, Ordering::Release);
+
+        let mut submitted = 0;
+        let mut completed = 0;
+        let mut error = None;
+        while completed < reads.len() {
+            match self.enter(reads.len() - submitted, reads.len() - completed) {
+                Ok(count) => submitted += count,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(err) => {
+                    error = Some(err);
+                    break;
+                }
+            }
+            completed += self.reap(batch, results);
+        }
+        let Some(err) = error else {
+            return Ok(());
+        };
+
+        // The kernel only consumes submissions inside `io_uring_enter(2)`, so
+        // those it did not take yet can be taken back.
+        sq_tail.store(sq_head.load(Ordering::Acquire), Ordering::Release);
+        // The buffers belong to the caller once this returns, so the reads
+        // in flight have to complete first.
+        while completed < submitted {
+            match self.enter(0, submitted - completed) {
+                Ok(_) => {}
+                Err(err)
+                    if matches!(
+                        err.raw_os_error(),
+                        Some(libc::EINTR | libc::EAGAIN | libc::EBUSY)
+                    ) => {}
+                // The kernel could write into freed memory.
+                Err(_) => process::abort(),
+            }
+            completed += self.reap(batch, results);
+        }
+        Err(err)
+    }
+
+    /// Submits `to_submit` entries and waits for `min_complete` completions.
+    /// Returns how many entries were submitted.
+    fn enter(&self, to_submit: usize, min_complete: usize) -> io::Result<usize>
+    {
+        let ret = unsafe {
+            libc::syscall(
+                libc::SYS_io_uring_enter,
+                self.fd.as_raw_fd(),
+                to_submit as libc::c_uint,
+                min_complete as libc::c_uint,
+                IORING_ENTER_GETEVENTS,
+                ptr::null::<libc::sigset_t>(),
+                0usize,
+            )
+        };
+        if ret < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(ret as usize)
+    }
+
+    /// Stores the results of the completions of `batch` that are pending
+    /// and returns how many there were. Completions of other batches are
+    /// dropped.
+    fn reap(&self, batch: u64, results: &mut [io::Result<usize>]) -> usize
+    {
+        let cq_map = self.cq.as_ref().unwrap_or(&self.sq);
+        let cq_off = &self.params.cq_off;
+        let cq_head = unsafe { &*cq_map.at::<AtomicU32>(cq_off.head) };
+        let cq_tail = unsafe { &*cq_map.at::<AtomicU32>(cq_off.tail) };
+        let cq_mask = unsafe { *cq_map.at::<u32>(cq_off.ring_mask) };
+        let cqes = cq_map.at::<Cqe>(cq_off.cqes);
+
+        let mut reaped = 0;
+        let mut head = cq_head.load(Ordering::Acquire);
+        let tail = cq_tail.load(Ordering::Acquire);
+        while head != tail {
+            let cqe = unsafe { &*cqes.add((head & cq_mask) as usize) };
+            let index = (cqe.user_data & u64::from(u32::MAX)) as usize;
+            if cqe.user_data & !u64::from(u32::MAX) == batch {
+                if let Some(result) = results.get_mut(index) {
+                    *result = if cqe.res < 0 {
+                        Err(io::Error::from_raw_os_error(-cqe.res))
+                    } else {
+                        Ok(cqe.res as usize)
+                    };
+                    reaped += 1;
+                }
+            }
+            head = head.wrapping_add(1);
+        }
+        cq_head.store(head, Ordering::Release);
+        reaped
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use std::env;
+    use std::fs;
+    use std::fs::File;
+
+    use super::*;
+
+    #[test]
+    fn reads_more_files_than_the_ring_holds()
+    {
+        // io_uring may be disabled, e.g. by `kernel.io_uring_disabled`.
+        let Ok(mut ring) = Ring::new(2) else {
+            return;
+        };
+        let dir = env::temp_dir().join(format!("lithiumd-uring-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let files = (0..5)
+            .map(|i| {
+                let path = dir.join(i.to_string());
+                fs::write(&path, "x".repeat(i + 1)).unwrap();
+                File::open(path).unwrap()
+            })
+            .collect::<Vec<_>>();
+        for _ in 0..3 {
+            let mut bufs = vec![[0u8; 16]; files.len()];
+            let mut reads = files
+                .iter()
+                .zip(&mut bufs)
+                .map(|(file, buf)| Read {
+                    fd: file.as_raw_fd(),
+                    buf,
+                })
+                .collect::<Vec<_>>();
+            let results = ring.read_all(&mut reads);
+            let lengths = results.into_iter().map(Result::unwrap).collect::<Vec<_>>();
+            assert_eq!(lengths, [1, 2, 3, 4, 5]);
+        }
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,172 @@
+use std::fmt::Display;
+use std::fs;
+use std::io;
+#[cfg(feature = "mio")]
+use std::os::fd::AsRawFd;
+use std::path::Path;
+
+#[cfg(feature = "mio")]
+use mio::event::Source;
+#[cfg(feature = "mio")]
+use mio::unix::SourceFd;
+use udev::MonitorSocket;
+
+const POWER_SUPPLY: &str = "/sys/class/power_supply";
+
+/// A USB Power Delivery contract negotiated with a charger.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PdContract
+{
+    /// Sysname of the power supply of the port, e.g.
+    /// `ucsi-source-psy-USBC000:001`.
+    pub supply: String,
+    /// Negotiated voltage in mV.
+    pub voltage: u32,
+    /// Maximum current the charger offers at that voltage, in mA.
+    pub current: u32,
+}
+
+impl PdContract
+{
+    /// Returns the power of the contract in watts.
+    #[must_use]
+    pub fn watts(&self) -> f64
+    {
+        f64::from(self.voltage) * f64::from(self.current) / 1_000_000.0
+    }
+}
+
+impl Display for PdContract
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result
+    {
+        write!(
+            f,
+            "{:.0} W ({:.1} V, {:.2} A)",
+            self.watts(),
+            f64::from(self.voltage) / 1000.0,
+            f64::from(self.current) / 1000.0
+        )
+    }
+}
+
+/// Tracks the USB PD contracts of the Type-C ports, to tell whether a charger
+/// delivers 60 W or only 20 W. The port drivers (UCSI, TCPM) expose the
+/// negotiated contract as a power supply with `usb_type` PD; attaching a
+/// partner emits uevents in the `typec` subsystem, and a new contract in the
+/// `power_supply` one.
+///
+/// Register it with a mio `Poll` and call [`UsbPd::process`] when it becomes
+/// readable.
+pub struct UsbPd
+{
+    socket: MonitorSocket,
+    contracts: Vec<PdContract>,
+}
+
+impl UsbPd
+{
+    pub fn new() -> io::Result<Self>
+    {
+        let socket = udev::MonitorBuilder::new()?
+            .match_subsystem("typec")?
+            .match_subsystem("power_supply")?
+            .listen()?;
+        Ok(Self {
+            socket,
+            contracts: negotiated()?,
+        })
+    }
+
+    /// Returns the contracts in effect, sorted by supply.
+    #[must_use]
+    pub fn contracts(&self) -> &[PdContract]
+    {
+        &self.contracts
+    }
+
+    /// Reads the pending uevents and returns the contracts that were newly
+    /// negotiated.
+    pub fn process(&mut self) -> io::Result<Vec<PdContract>>
+    {
+        if self.socket.iter().count() == 0 {
+            return Ok(Vec::new());
+        }
+        let contracts = negotiated()?;
+        let new = contracts
+            .iter()
+            .filter(|contract| !self.contracts.contains(contract))
+            .cloned()
+            .collect();
+        self.contracts = contracts;
+        Ok(new)
+    }
+}
+
+/// Returns the contracts of the online PD power supplies.
+fn negotiated() -> io::Result<Vec<PdContract>>
+{
+    let entries = match fs::read_dir(POWER_SUPPLY) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err),
+    };
+    let mut contracts = Vec::new();
+    for entry in entries {
+        let entry = entry?;
+        let path = entry.path();
+        // The type in effect is in brackets, e.g. `C [PD] PD_PPS`.
+        let usb_type = read(&path, "usb_type").unwrap_or_default();
+        if !usb_type.contains("[PD") || read(&path, "online").as_deref() != Some("1") {
+            continue;
+        }
+        let microvolts = read(&path, "voltage_now").and_then(|value| value.parse::<u64>().ok());
+        let microamps = read(&path, "current_max").and_then(|value| value.parse::<u64>().ok());
+        let (Some(microvolts), Some(microamps)) = (microvolts, microamps) else {
+            continue;
+        };
+        contracts.push(PdContract {
+            supply: entry.file_name().to_string_lossy().into_owned(),
+            voltage: (microvolts / 1000) as u32,
+            current: (microamps / 1000) as u32,
+        });
+    }
+    contracts.sort_by(|a, b| a.supply.cmp(&b.supply));
+    Ok(contracts)
+}
+
+fn read(dir: &Path, attribute: &str) -> Option<String>
+{
+    fs::read_to_string(dir.join(attribute))
+        .ok()
+        .map(|value| value.trim().to_string())
+}
+
+#[cfg(feature = "mio")]
+impl Source for UsbPd
+{
+    fn register(
+        &mut self,
+        registry: &mio::Registry,
+        token: mio::Token,
+        interests: mio::Interest,
+    ) -> io::Result<()>
+    {
+        SourceFd(&self.socket.as_raw_fd()).register(registry, token, interests)
+    }
+
+    fn reregister(
+        &mut self,
+        registry: &mio::Registry,
+        token: mio::Token,
+        interests: mio::Interest,
+    ) -> io::Result<()>
+    {
+        SourceFd(&self.socket.as_raw_fd()).reregister(registry, token, interests)
+    }
+
+    fn deregister(&mut self, registry: &mio::Registry) -> io::Result<()>
+    {
+        SourceFd(&self.socket.as_raw_fd()).deregister(registry)
+    }
+}
@@ -0,0 +1,343 @@
+use std::fmt::Display;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use crate::privileges::write;
+
+/// A limit on how far the battery is charged, to reduce wear on laptops that
+/// stay plugged in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChargeLimit
+{
+    /// The battery charges to 100%.
+    Off,
+    /// Charging stops at `end` percent and, if `start` is set, only resumes
+    /// once the capacity dropped below `start`.
+    Threshold
+    {
+        start: Option<u8>, end: u8
+    },
+    /// A fixed limit chosen by the firmware, such as the conservation mode of
+    /// IdeaPads, which usually stops at 60% or 80%.
+    Conservation,
+}
+
+impl Display for ChargeLimit
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result
+    {
+        match *self {
+            ChargeLimit::Off => write!(f, "off"),
+            ChargeLimit::Threshold {
+                start: Some(start),
+                end,
+            } => write!(f, "charge from {}% to {}%", start, end),
+            ChargeLimit::Threshold { start: None, end } => write!(f, "charge to {}%", end),
+            ChargeLimit::Conservation => write!(f, "conservation mode"),
+        }
+    }
+}
+
+/// A vendor specific way of limiting the charge.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Quirk
+{
+    /// `charge_control_start_threshold` and `charge_control_end_threshold`
+    /// of a battery, the kernel's generic interface. ThinkPads and recent
+    /// ASUS, Dell and Framework laptops use it.
+    Kernel(PathBuf),
+    /// `conservation_mode` of the ideapad_acpi driver.
+    Ideapad(PathBuf),
+    /// `charge_control_thresholds` of the huawei-wmi driver.
+    Huawei(PathBuf),
+}
+
+const POWER_SUPPLY: &str = "/sys/class/power_supply";
+const IDEAPAD: &str = "/sys/bus/platform/drivers/ideapad_acpi";
+const HUAWEI: &str = "/sys/devices/platform/huawei-wmi/charge_control_thresholds";
+
+impl Quirk
+{
+    /// Returns the charge limit interfaces of this machine. Batteries that
+    /// can be limited on their own are returned separately.
+    pub fn detect() -> io::Result<Vec<Quirk>>
+    {
+        let mut quirks = Vec::new();
+        for dir in read_dir(POWER_SUPPLY)? {
+            if dir.join("charge_control_end_threshold").exists() {
+                quirks.push(Quirk::Kernel(dir));
+            }
+        }
+        for dir in read_dir(IDEAPAD)? {
+            let path = dir.join("conservation_mode");
+            if path.exists() {
+                quirks.push(Quirk::Ideapad(path));
+            }
+        }
+        // huawei-wmi also provides the kernel interface on newer kernels.
+        if quirks.is_empty() && Path::new(HUAWEI).exists() {
+            quirks.push(Quirk::Huawei(PathBuf::from(HUAWEI)));
+        }
+        Ok(quirks)
+    }
+
+    /// Describes the interface, e.g. `BAT0` or `ideapad conservation mode`.
+    #[must_use]
+    pub fn name(&self) -> String
+    {
+        match self {
+            Quirk::Kernel(dir) => dir
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+            Quirk::Ideapad(_) => "ideapad conservation mode".to_string(),
+            Quirk::Huawei(_) => "huawei battery protection".to_string(),
+        }
+    }
+
+    pub fn get(&self) -> io::Result<ChargeLimit>
+    {
+        match self {
+            Quirk::Kernel(dir) => {
+                let end = read_percent(&dir.join("charge_control_end_threshold"))?;
+                let start = match read_percent(&dir.join("charge_control_start_threshold")) {
+                    Ok(start) => Some(start),
+                    Err(err) if err.kind() == io::ErrorKind::NotFound => None,
+                    Err(err) => return Err(err),
+                };
+                Ok(threshold(start, end))
+            }
+            Quirk::Ideapad(path) => Ok(match read(path)?.as_str() {
+                "0" => ChargeLimit::Off,
+                _ => ChargeLimit::Conservation,
+            }),
+            Quirk::Huawei(path) => {
+                let (start, end) = parse_pair(&read(path)?)?;
+                Ok(threshold(Some(start), end))
+            }
+        }
+    }
+
+    /// Applies `limit`. Interfaces that cannot express it return an error of
+    /// kind `Unsupported`.
+    pub fn set(&self, limit: ChargeLimit) -> io::Result<()>
+    {
+        match (self, limit) {
+            (Quirk::Kernel(dir), ChargeLimit::Off) => set_kernel(dir, Some(0), 100),
+            (Quirk::Kernel(dir), ChargeLimit::Threshold { start, end }) => {
+                set_kernel(dir, start, end)
+            }
+            (Quirk::Ideapad(path), ChargeLimit::Off) => write(path, "0"),
+            (Quirk::Ideapad(path), ChargeLimit::Conservation) => write(path, "1"),
+            (Quirk::Huawei(path), ChargeLimit::Off) => write(path, "0 100"),
+            (Quirk::Huawei(path), ChargeLimit::Threshold { start, end }) => {
+                write(path, &format!("{} {}", start.unwrap_or(0), end))
+            }
+            (quirk, limit) => Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!("{} does not support `{}`", quirk.name(), limit),
+            )),
+        }
+    }
+}
+
+/// What a battery does while plugged in, as set through the kernel's
+/// `charge_behaviour` attribute.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChargeBehaviour
+{
+    /// Charge normally, respecting the charge thresholds.
+    Auto,
+    /// Do not charge, even below the thresholds.
+    InhibitCharge,
+    /// Run from the battery even though the adapter is plugged in, e.g. to
+    /// calibrate it.
+    ForceDischarge,
+}
+
+impl Display for ChargeBehaviour
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result
+    {
+        match *self {
+            ChargeBehaviour::Auto => write!(f, "auto"),
+            ChargeBehaviour::InhibitCharge => write!(f, "inhibit-charge"),
+            ChargeBehaviour::ForceDischarge => write!(f, "force-discharge"),
+        }
+    }
+}
+
+impl FromStr for ChargeBehaviour
+{
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err>
+    {
+        match s {
+            "auto" => Ok(ChargeBehaviour::Auto),
+            "inhibit-charge" => Ok(ChargeBehaviour::InhibitCharge),
+            "force-discharge" => Ok(ChargeBehaviour::ForceDischarge),
+            _ => Err(format!(
+                "unknown charge behaviour `{}`, expected auto, inhibit-charge or force-discharge",
+                s
+            )),
+        }
+    }
+}
+
+/// The `charge_behaviour` attribute of a battery. The kernel lists all
+/// supported behaviours in it and marks the active one with brackets, e.g.
+/// `[auto] inhibit-charge force-discharge`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChargeBehaviourControl
+{
+    dir: PathBuf,
+}
+
+impl ChargeBehaviourControl
+{
+    /// Returns the batteries that expose `charge_behaviour`.
+    pub fn detect() -> io::Result<Vec<Self>>
+    {
+        Ok(read_dir(POWER_SUPPLY)?
+            .into_iter()
+            .filter(|dir| dir.join("charge_behaviour").exists())
+            .map(|dir| Self { dir })
+            .collect())
+    }
+
+    /// Returns the control of the battery that `quirk` limits, if it has one.
+    #[must_use]
+    pub fn of(quirk: &Quirk) -> Option<Self>
+    {
+        match quirk {
+            Quirk::Kernel(dir) if dir.join("charge_behaviour").exists() => {
+                Some(Self { dir: dir.clone() })
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns the sysname of the battery.
+    #[must_use]
+    pub fn name(&self) -> String
+    {
+        self.dir
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default()
+    }
+
+    /// Returns the active behaviour and all supported ones.
+    pub fn get(&self) -> io::Result<(ChargeBehaviour, Vec<ChargeBehaviour>)>
+    {
+        let value = read(&self.dir.join("charge_behaviour"))?;
+        let mut active = None;
+        let mut supported = Vec::new();
+        for word in value.split_whitespace() {
+            let name = word.trim_start_matches('[').trim_end_matches(']');
+            // Behaviours added by newer kernels are skipped.
+            let Ok(behaviour) = name.parse::<ChargeBehaviour>() else {
+                continue;
+            };
+            if word.starts_with('[') {
+                active = Some(behaviour);
+            }
+            supported.push(behaviour);
+        }
+        let active = active.ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("no active charge behaviour in `{}`", value),
+            )
+        })?;
+        Ok((active, supported))
+    }
+
+    pub fn set(&self, behaviour: ChargeBehaviour) -> io::Result<()>
+    {
+        let (_, supported) = self.get()?;
+        if !supported.contains(&behaviour) {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!("{} does not support `{}`", self.name(), behaviour),
+            ));
+        }
+        write(&self.dir.join("charge_behaviour"), &behaviour.to_string())
+    }
+}
+
+fn set_kernel(dir: &Path, start: Option<u8>, end: u8) -> io::Result<()>
+{
+    let end_path = dir.join("charge_control_end_threshold");
+    let start_path = dir.join("charge_control_start_threshold");
+    let Some(start) = start.filter(|_| start_path.exists()) else {
+        return write(&end_path, &end.to_string());
+    };
+    // The start threshold has to stay below the end threshold at all
+    // times, so the order of the writes depends on the current values.
+    let current_end = read_percent(&end_path)?;
+    if start < current_end {
+        write(&start_path, &start.to_string())?;
+        write(&end_path, &end.to_string())
+    } else {
+        write(&end_path, &end.to_string())?;
+        write(&start_path, &start.to_string())
+    }
+}
+
+fn threshold(start: Option<u8>, end: u8) -> ChargeLimit
+{
+    if end >= 100 {
+        ChargeLimit::Off
+    } else {
+        ChargeLimit::Threshold {
+            start: start.filter(|start| *start > 0),
+            end,
+        }
+    }
+}
+
+fn read_dir(path: &str) -> io::Result<Vec<PathBuf>>
+{
+    match fs::read_dir(path) {
+        Ok(entries) => entries
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect(),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(err) => Err(err),
+    }
+}
+
+fn read(path: &Path) -> io::Result<String>
+{
+    Ok(fs::read_to_string(path)?.trim().to_string())
+}
+
+fn read_percent(path: &Path) -> io::Result<u8>
+{
+    read(path)?
+        .parse::<u8>()
+        .map(|percent| percent.min(100))
+        .map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{}: not a percentage", path.display()),
+            )
+        })
+}
+
+fn parse_pair(value: &str) -> io::Result<(u8, u8)>
+{
+    let mut values = value.split_whitespace().map(str::parse::<u8>);
+    match (values.next(), values.next()) {
+        (Some(Ok(start)), Some(Ok(end))) => Ok((start, end.min(100))),
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("invalid thresholds `{}`", value),
+        )),
+    }
+}
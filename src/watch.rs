@@ -0,0 +1,86 @@
+use std::io;
+
+use lithiumd::Fields;
+use lithiumd::HookRunner;
+use lithiumd::PowerSupply;
+use lithiumd::Template;
+use mio::event::Source;
+use mio::Events;
+use mio::Interest;
+use mio::Poll;
+use mio::Token;
+
+use crate::config::Config;
+
+/// Options of `lithiumd watch`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Options
+{
+    /// Command run with `sh -c` whenever its rendering changes.
+    pub exec: Template,
+}
+
+impl Options
+{
+    /// Parses `--exec TEMPLATE`.
+    pub fn parse(args: &[String]) -> Result<Self, String>
+    {
+        let mut exec = None;
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--exec" => {
+                    let value = args
+                        .next()
+                        .ok_or_else(|| format!("`{}` needs a value", arg))?;
+                    exec = Some(value.parse().map_err(|err| format!("`--exec`: {}", err))?);
+                }
+                _ => return Err(format!("unknown option `{}`", arg)),
+            }
+        }
+        Ok(Self {
+            exec: exec.ok_or("`watch` needs `--exec`")?,
+        })
+    }
+}
+
+/// Watches the power supply without the daemon and runs the command of
+/// `options` on startup and whenever a change renders it differently, e.g.
+/// once per status change for `notify-send {status}`. Hooks see the same
+/// environment as the ones of rules.
+pub fn run(options: &Options) -> io::Result<()>
+{
+    let config = Config::load()?;
+    let mut power_supply = PowerSupply::new();
+    power_supply.set_debounce(config.debounce);
+    let mut poll = Poll::new()?;
+    power_supply.register(poll.registry(), Token(0), Interest::READABLE)?;
+    let mut events = Events::with_capacity(64);
+    let mut hooks = HookRunner::new();
+    let mut last = None;
+    loop {
+        power_supply.update()?;
+        if power_supply.events().count() > 0 {
+            let snapshot = power_supply.snapshot();
+            let fields = Fields {
+                status: snapshot.status,
+                capacity: snapshot.capacity(),
+                level: snapshot.level(&config.levels),
+                time_remaining: power_supply.time_remaining(),
+            };
+            let command = options.exec.render_with(&fields, &config.labels);
+            if last.as_ref() != Some(&command) {
+                if let Err(err) = hooks.run(&command, &snapshot) {
+                    eprintln!("Failed to run `{}`: {}", command, err);
+                }
+                last = Some(command);
+            }
+        }
+        hooks.reap();
+        if let Err(err) = poll.poll(&mut events, power_supply.timeout()) {
+            if err.kind() != io::ErrorKind::Interrupted {
+                return Err(err);
+            }
+        }
+    }
+}
@@ -0,0 +1,455 @@
+use std::env;
+use std::io;
+use std::io::Read;
+use std::io::Write;
+use std::os::fd::AsRawFd;
+use std::os::unix::net::UnixStream;
+use std::path::PathBuf;
+use std::time::Duration;
+
+#[cfg(feature = "mio")]
+use mio::event::Source;
+#[cfg(feature = "mio")]
+use mio::unix::SourceFd;
+
+const DISPLAY: u32 = 1;
+const ROUNDTRIP_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// An argument of a request.
+pub enum Arg<'a>
+{
+    Uint(u32),
+    Str(&'a str),
+    Object(u32),
+    NewId(u32),
+}
+
+/// An event received from the compositor. The arguments are kept in their
+/// wire format and read in order with the accessors.
+pub struct Event
+{
+    pub object: u32,
+    pub opcode: u16,
+    args: Vec<u8>,
+    pos: usize,
+}
+
+impl Event
+{
+    pub fn uint(&mut self) -> io::Result<u32>
+    {
+        let bytes = self
+            .args
+            .get(self.pos..self.pos + 4)
+            .ok_or_else(|| invalid("truncated event"))?;
+        self.pos += 4;
+        Ok(u32::from_ne_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    pub fn string(&mut self) -> io::Result<String>
+    {
+        let len = self.uint()? as usize;
+        let bytes = self
+            .args
+            .get(self.pos..self.pos + len)
+            .ok_or_else(|| invalid("truncated event"))?;
+        self.pos += len.next_multiple_of(4);
+        let bytes = bytes.strip_suffix(&[0]).unwrap_or(bytes);
+        String::from_utf8(bytes.to_vec()).map_err(|_| invalid("invalid UTF-8"))
+    }
+}
+
+fn invalid(message: &str) -> io::Error
+{
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+/// A global object announced by the registry.
+pub struct Global
+{
+    pub name: u32,
+    pub interface: String,
+    pub version: u32,
+}
+
+/// A client connection to a Wayland compositor, just enough to bind globals
+/// and exchange messages that do not carry file descriptors.
+pub struct Connection
+{
+    stream: UnixStream,
+    buf: Vec<u8>,
+    next_id: u32,
+}
+
+impl Connection
+{
+    /// Connects to the compositor named by `WAYLAND_DISPLAY`.
+    pub fn connect() -> io::Result<Self>
+    {
+        let display = env::var_os("WAYLAND_DISPLAY").unwrap_or_else(|| "wayland-0".into());
+        let mut path = PathBuf::from(&display);
+        if path.is_relative() {
+            let runtime_dir = env::var_os("XDG_RUNTIME_DIR").ok_or_else(|| {
+                io::Error::new(io::ErrorKind::NotFound, "XDG_RUNTIME_DIR is not set")
+            })?;
+            path = PathBuf::from(runtime_dir).join(display);
+        }
+        let stream = UnixStream::connect(&path).map_err(|err| {
+            io::Error::new(
+                err.kind(),
+                format!("cannot connect to {}: {}", path.display(), err),
+            )
+        })?;
+        stream.set_nonblocking(true)?;
+        Ok(Self {
+            stream,
+            buf: Vec::new(),
+            next_id: DISPLAY + 1,
+        })
+    }
+
+    /// Allocates an id for a new object.
+    pub fn new_id(&mut self) -> u32
+    {
+        let id = self.next_id;
+        self.next_id += 1;
+        id
+    }
+
+    pub fn request(&mut self, object: u32, opcode: u16, args: &[Arg]) -> io::Result<()>
+    {
+        let mut body = Vec::new();
+        for arg in args {
+            match *arg {
+                Arg::Uint(value) | Arg::Object(value) | Arg::NewId(value) => {
+                    body.extend(value.to_ne_bytes());
+                }
+                Arg::Str(value) => {
+                    body.extend((value.len() as u32 + 1).to_ne_bytes());
+                    body.extend(value.as_bytes());
+                    body.push(0);
+                    body.resize(body.len().next_multiple_of(4), 0);
+                }
+            }
+        }
+        let size = (8 + body.len()) as u32;
+        let mut message = Vec::with_capacity(size as usize);
+        message.extend(object.to_ne_bytes());
+        message.extend((size << 16 | u32::from(opcode)).to_ne_bytes());
+        message.extend(body);
+
+        let mut written = 0;
+        while written < message.len() {
+            match self.stream.write(&message[written..]) {
+                Ok(len) => written += len,
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => {
+                    self.wait(libc::POLLOUT, ROUNDTRIP_TIMEOUT)?;
+                }
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the next event, without blocking. Protocol errors reported by
+    /// the compositor are returned as errors.
+    pub fn read(&mut self) -> io::Result<Option<Event>>
+    {
+        loop {
+            if self.buf.len() >= 8 {
+                let word = |i: usize| {
+                    u32::from_ne_bytes([
+                        self.buf[i],
+                        self.buf[i + 1],
+                        self.buf[i + 2],
+                        self.buf[i + 3],
+                    ])
+                };
+                let object = word(0);
+                let size = (word(4) >> 16) as usize;
+                let opcode = (word(4) & 0xffff) as u16;
+                if size < 8 {
+                    return Err(invalid("invalid message size"));
+                }
+                if self.buf.len() >= size {
+                    let args = self.buf[8..size].to_vec();
+                    self.buf.drain(..size);
+                    let mut event = Event {
+                        object,
+                        opcode,
+                        args,
+                        pos: 0,
+                    };
+                    if object == DISPLAY && opcode == 0 {
+                        let object = event.uint()?;
+                        let code = event.uint()?;
+                        let message = event.string()?;
+                        return Err(io::Error::other(format!(
+                            "protocol error on object {} ({}): {}",
+                            object, code, message
+                        )));
+                    }
+                    return Ok(Some(event));
+                }
+            }
+            let mut chunk = [0; 4096];
+            match self.stream.read(&mut chunk) {
+                Ok(0) => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::UnexpectedEof,
+                        "the compositor closed the connection",
+                    ))
+                }
+                Ok(len) => self.buf.extend_from_slice(&chunk[..len]),
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => return Ok(None),
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    /// Waits until the compositor processed all requests sent so far and
+    /// returns the events received in the meantime.
+    pub fn roundtrip(&mut self) -> io::Result<Vec<Event>>
+    {
+        let callback = self.new_id();
+        self.request(DISPLAY, 0, &[Arg::NewId(callback)])?;
+        let mut events = Vec::new();
+        loop {
+            while let Some(event) = self.read()? {
+                if event.object == callback {
+                    return Ok(events);
+                }
+                events.push(event);
+            }
+            self.wait(libc::POLLIN, ROUNDTRIP_TIMEOUT)?;
+        }
+    }
+
+    /// Creates a registry and returns it together with the globals it
+    /// announced.
+    pub fn globals(&mut self) -> io::Result<(u32, Vec<Global>)>
+    {
+        let registry = self.new_id();
+        self.request(DISPLAY, 1, &[Arg::NewId(registry)])?;
+        let mut globals = Vec::new();
+        for mut event in self.roundtrip()? {
+            if event.object == registry && event.opcode == 0 {
+                globals.push(Global {
+                    name: event.uint()?,
+                    interface: event.string()?,
+                    version: event.uint()?,
+                });
+            }
+        }
+        Ok((registry, globals))
+    }
+
+    /// Binds the global `name` of the registry to a new object.
+    pub fn bind(
+        &mut self,
+        registry: u32,
+        name: u32,
+        interface: &str,
+        version: u32,
+    ) -> io::Result<u32>
+    {
+        let id = self.new_id();
+        self.request(
+            registry,
+            0,
+            &[
+                Arg::Uint(name),
+                Arg::Str(interface),
+                Arg::Uint(version),
+                Arg::NewId(id),
+            ],
+        )?;
+        Ok(id)
+    }
+
+    fn wait(&self, events: libc::c_short, timeout: Duration) -> io::Result<()>
+    {
+        let mut fd = libc::pollfd {
+            fd: self.stream.as_raw_fd(),
+            events,
+            revents: 0,
+        };
+        let timeout = timeout.as_millis().min(i32::MAX as u128) as libc::c_int;
+        match unsafe { libc::poll(&mut fd, 1, timeout) } {
+            0 => Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                "the compositor did not respond",
+            )),
+            n if n < 0 => {
+                let err = io::Error::last_os_error();
+                if err.kind() == io::ErrorKind::Interrupted {
+                    Ok(())
+                } else {
+                    Err(err)
+                }
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(feature = "mio")]
+impl Source for Connection
+{
+    fn register(
+        &mut self,
+        registry: &mio::Registry,
+        token: mio::Token,
+        interests: mio::Interest,
+    ) -> io::Result<()>
+    {
+        SourceFd(&self.stream.as_raw_fd()).register(registry, token, interests)
+    }
+
+    fn reregister(
+        &mut self,
+        registry: &mio::Registry,
+        token: mio::Token,
+        interests: mio::Interest,
+    ) -> io::Result<()>
+    {
+        SourceFd(&self.stream.as_raw_fd()).reregister(registry, token, interests)
+    }
+
+    fn deregister(&mut self, registry: &mio::Registry) -> io::Result<()>
+    {
+        SourceFd(&self.stream.as_raw_fd()).deregister(registry)
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    /// Returns a connection to the other end of a socket pair.
+    fn connection() -> (Connection, UnixStream)
+    {
+        let (stream, server) = UnixStream::pair().unwrap();
+        stream.set_nonblocking(true).unwrap();
+        let connection = Connection {
+            stream,
+            buf: Vec::new(),
+            next_id: DISPLAY + 1,
+        };
+        (connection, server)
+    }
+
+    /// Encodes an event of `object` with arguments already in wire format.
+    fn event(object: u32, opcode: u16, args: &[u8]) -> Vec<u8>
+    {
+        let mut message = object.to_ne_bytes().to_vec();
+        message.extend((((8 + args.len()) as u32) << 16 | u32::from(opcode)).to_ne_bytes());
+        message.extend(args);
+        message
+    }
+
+    fn string(value: &str) -> Vec<u8>
+    {
+        let mut bytes = (value.len() as u32 + 1).to_ne_bytes().to_vec();
+        bytes.extend(value.as_bytes());
+        bytes.push(0);
+        bytes.resize(bytes.len().next_multiple_of(4), 0);
+        bytes
+    }
+
+    #[test]
+    fn encodes_requests()
+    {
+        let (mut connection, mut server) = connection();
+        assert_eq!(connection.bind(2, 7, "wl_seat", 5).unwrap(), 2);
+        let mut request = [0; 32];
+        server.read_exact(&mut request).unwrap();
+        let mut args = 7u32.to_ne_bytes().to_vec();
+        args.extend(8u32.to_ne_bytes());
+        args.extend(b"wl_seat\0");
+        args.extend(5u32.to_ne_bytes());
+        args.extend(2u32.to_ne_bytes());
+        assert_eq!(request[..], event(2, 0, &args)[..]);
+    }
+
+    #[test]
+    fn lists_the_globals()
+    {
+        let (mut connection, mut server) = connection();
+        // The registry is object 2 and the callback of the roundtrip 3.
+        let mut global = 9u32.to_ne_bytes().to_vec();
+        global.extend(string("ext_idle_notifier_v1"));
+        global.extend(1u32.to_ne_bytes());
+        let mut events = event(2, 0, &global);
+        events.extend(event(2, 1, &9u32.to_ne_bytes()));
+        events.extend(event(3, 0, &0u32.to_ne_bytes()));
+        server.write_all(&events).unwrap();
+        let (registry, globals) = connection.globals().unwrap();
+        assert_eq!(registry, 2);
+        assert_eq!(globals.len(), 1);
+        assert_eq!(globals[0].name, 9);
+        assert_eq!(globals[0].interface, "ext_idle_notifier_v1");
+        assert_eq!(globals[0].version, 1);
+    }
+
+    #[test]
+    fn reads_events_split_across_reads()
+    {
+        let (mut connection, mut server) = connection();
+        let message = event(5, 1, &string("hello"));
+        server.write_all(&message[..5]).unwrap();
+        assert!(connection.read().unwrap().is_none());
+        server.write_all(&message[5..]).unwrap();
+        let mut event = connection.read().unwrap().unwrap();
+        assert_eq!((event.object, event.opcode), (5, 1));
+        assert_eq!(event.string().unwrap(), "hello");
+        assert!(event.uint().is_err());
+
+        drop(server);
+        let err = connection.read().err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn reports_protocol_errors()
+    {
+        let (mut connection, mut server) = connection();
+        let mut args = 4u32.to_ne_bytes().to_vec();
+        args.extend(2u32.to_ne_bytes());
+        args.extend(string("invalid method"));
+        server.write_all(&event(DISPLAY, 0, &args)).unwrap();
+        let err = connection.read().err().unwrap();
+        assert_eq!(
+            err.to_string(),
+            "protocol error on object 4 (2): invalid method"
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_events()
+    {
+        let (mut connection, mut server) = connection();
+        server.write_all(&event(5, 0, &[])[..4]).unwrap();
+        server.write_all(&0u32.to_ne_bytes()).unwrap();
+        assert!(connection.read().is_err());
+
+        for args in [
+            &[][..],
+            &[1, 0],
+            &u32::MAX.to_ne_bytes(),
+            &[3, 0, 0, 0, b'a', b'b'],
+            &[2, 0, 0, 0, 0xff, 0, 0, 0],
+        ] {
+            let mut event = Event {
+                object: 5,
+                opcode: 0,
+                args: args.to_vec(),
+                pos: 0,
+            };
+            assert!(event.string().is_err(), "{:?}", args);
+        }
+    }
+}
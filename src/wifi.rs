@@ -0,0 +1,339 @@
+use std::ffi::CString;
+use std::fs;
+use std::io;
+use std::mem;
+use std::os::fd::AsRawFd;
+use std::os::fd::FromRawFd;
+use std::os::fd::OwnedFd;
+
+const NET: &str = "/sys/class/net";
+
+const NL80211_CMD_SET_POWER_SAVE: u8 = 61;
+const NL80211_ATTR_IFINDEX: u16 = 3;
+const NL80211_ATTR_PS_STATE: u16 = 93;
+const CTRL_ATTR_FAMILY_NAME: u16 = 2;
+
+/// Power saving of wireless interfaces, set over nl80211 like
+/// `iw dev wlan0 set power_save on` does.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct WifiPowerSave
+{
+    /// Names of the interfaces to change, or all wireless interfaces if
+    /// empty.
+    pub interfaces: Vec<String>,
+    pub enabled: bool,
+}
+
+impl WifiPowerSave
+{
+    /// Turns power saving on or off on the interfaces. Interfaces that are
+    /// named but missing are an error, so that typos do not go unnoticed.
+    pub fn apply(&self) -> io::Result<()>
+    {
+        let interfaces = if self.interfaces.is_empty() {
+            wireless_interfaces()?
+        } else {
+            self.interfaces.clone()
+        };
+        if interfaces.is_empty() {
+            return Ok(());
+        }
+        let mut socket = GenericNetlink::new()?;
+        let family = socket.family("nl80211")?;
+        for interface in &interfaces {
+            let index = if_index(interface)?;
+            let mut attributes = Vec::new();
+            attribute(&mut attributes, NL80211_ATTR_IFINDEX, &index.to_ne_bytes());
+            attribute(
+                &mut attributes,
+                NL80211_ATTR_PS_STATE,
+                &u32::from(self.enabled).to_ne_bytes(),
+            );
+            socket
+                .request(family, NL80211_CMD_SET_POWER_SAVE, &attributes)
+                .map_err(|err| io::Error::new(err.kind(), format!("{}: {}", interface, err)))?;
+        }
+        Ok(())
+    }
+}
+
+/// Lists the interfaces with a `wireless` directory in sysfs.
+fn wireless_interfaces() -> io::Result<Vec<String>>
+{
+    let mut interfaces = Vec::new();
+    for entry in fs::read_dir(NET)? {
+        let entry = entry?;
+        if entry.path().join("wireless").exists() {
+            interfaces.push(entry.file_name().to_string_lossy().into_owned());
+        }
+    }
+    interfaces.sort();
+    Ok(interfaces)
+}
+
+fn if_index(interface: &str) -> io::Result<u32>
+{
+    let name = CString::new(interface)
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "invalid interface name"))?;
+    match unsafe { libc::if_nametoindex(name.as_ptr()) } {
+        0 => Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("no interface `{}`", interface),
+        )),
+        index => Ok(index),
+    }
+}
+
+/// Appends a netlink attribute, padded to 4 bytes.
+fn attribute(buf: &mut Vec<u8>, kind: u16, data: &[u8])
+{
+    let len = 4 + data.len();
+    buf.extend_from_slice(&(len as u16).to_ne_bytes());
+    buf.extend_from_slice(&kind.to_ne_bytes());
+    buf.extend_from_slice(data);
+    buf.resize(buf.len() + (4 - len % 4) % 4, 0);
+}
+
+/// Blocking generic netlink socket, just enough to send requests and wait
+/// for their acknowledgements.
+struct GenericNetlink
+{
+    fd: OwnedFd,
+    seq: u32,
+}
+
+impl GenericNetlink
+{
+    fn new() -> io::Result<Self>
+    {
+        let fd = unsafe {
+            libc::socket(
+                libc::AF_NETLINK,
+                libc::SOCK_RAW | libc::SOCK_CLOEXEC,
+                libc::NETLINK_GENERIC,
+            )
+        };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Self {
+            fd: unsafe { OwnedFd::from_raw_fd(fd) },
+            seq: 0,
+        })
+    }
+
+    /// Resolves the id of the generic netlink family `name`.
+    fn family(&mut self, name: &str) -> io::Result<u16>
+    {
+        let mut attributes = Vec::new();
+        let mut name_bytes = name.as_bytes().to_vec();
+        name_bytes.push(0);
+        attribute(&mut attributes, CTRL_ATTR_FAMILY_NAME, &name_bytes);
+        let reply = self.request(
+            libc::GENL_ID_CTRL as u16,
+            libc::CTRL_CMD_GETFAMILY as u8,
+            &attributes,
+        )?;
+        family_id(&reply).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("no netlink family `{}`", name),
+            )
+        })
+    }
+
+    /// Sends the command `cmd` to `family` and waits for the kernel to
+    /// acknowledge it. Returns the payload of the reply, if there is one
+    /// before the acknowledgement.
+    fn request(&mut self, family: u16, cmd: u8, attributes: &[u8]) -> io::Result<Vec<u8>>
+    {
+        self.seq += 1;
+        let message = encode(family, cmd, self.seq, attributes);
+        let mut addr: libc::sockaddr_nl = unsafe { mem::zeroed() };
+        addr.nl_family = libc::AF_NETLINK as libc::sa_family_t;
+        let sent = unsafe {
+            libc::sendto(
+                self.fd.as_raw_fd(),
+                message.as_ptr().cast(),
+                message.len(),
+                0,
+                (&addr as *const libc::sockaddr_nl).cast(),
+                mem::size_of::<libc::sockaddr_nl>() as libc::socklen_t,
+            )
+        };
+        if sent < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let mut payload = Vec::new();
+        let mut buf = vec![0u8; 8192];
+        loop {
+            let len =
+                unsafe { libc::recv(self.fd.as_raw_fd(), buf.as_mut_ptr().cast(), buf.len(), 0) };
+            if len < 0 {
+                let err = io::Error::last_os_error();
+                if err.kind() == io::ErrorKind::Interrupted {
+                    continue;
+                }
+                return Err(err);
+            }
+            if let Some(result) = read_replies(&buf[..len as usize], self.seq, &mut payload) {
+                return result.map(|()| payload);
+            }
+        }
+    }
+}
+
+/// Encodes the generic netlink request `cmd` to `family`, asking for an
+/// acknowledgement.
+fn encode(family: u16, cmd: u8, seq: u32, attributes: &[u8]) -> Vec<u8>
+{
+    let len = mem::size_of::<libc::nlmsghdr>() + 4 + attributes.len();
+    let mut message = Vec::with_capacity(len);
+    message.extend_from_slice(&(len as u32).to_ne_bytes());
+    message.extend_from_slice(&family.to_ne_bytes());
+    message.extend_from_slice(&((libc::NLM_F_REQUEST | libc::NLM_F_ACK) as u16).to_ne_bytes());
+    message.extend_from_slice(&seq.to_ne_bytes());
+    message.extend_from_slice(&0u32.to_ne_bytes());
+    message.extend_from_slice(&[cmd, 1, 0, 0]);
+    message.extend_from_slice(attributes);
+    message
+}
+
+/// Reads the replies to the request `seq` among `messages`, keeping the
+/// payload of the last one in `payload`. Returns the result of the request
+/// once its acknowledgement is among them.
+fn read_replies(mut messages: &[u8], seq: u32, payload: &mut Vec<u8>) -> Option<io::Result<()>>
+{
+    while messages.len() >= mem::size_of::<libc::nlmsghdr>() {
+        let len = u32::from_ne_bytes(messages[0..4].try_into().unwrap()) as usize;
+        let kind = u16::from_ne_bytes([messages[4], messages[5]]);
+        let reply_seq = u32::from_ne_bytes(messages[8..12].try_into().unwrap());
+        if len < mem::size_of::<libc::nlmsghdr>() || len > messages.len() {
+            break;
+        }
+        let body = &messages[mem::size_of::<libc::nlmsghdr>()..len];
+        if reply_seq == seq {
+            if kind == libc::NLMSG_ERROR as u16 {
+                let error = body
+                    .get(0..4)
+                    .map_or(0, |error| i32::from_ne_bytes(error.try_into().unwrap()));
+                if error != 0 {
+                    return Some(Err(io::Error::from_raw_os_error(-error)));
+                }
+                return Some(Ok(()));
+            }
+            *payload = body.to_vec();
+        }
+        messages = messages.get((len + 3) & !3..).unwrap_or_default();
+    }
+    None
+}
+
+/// Returns the family id in the reply to `CTRL_CMD_GETFAMILY`.
+fn family_id(reply: &[u8]) -> Option<u16>
+{
+    // Skip the generic netlink header of the reply.
+    let mut attributes = reply.get(4..).unwrap_or_default();
+    while attributes.len() >= 4 {
+        let len = usize::from(u16::from_ne_bytes([attributes[0], attributes[1]]));
+        let kind = u16::from_ne_bytes([attributes[2], attributes[3]]);
+        if len < 4 || len > attributes.len() {
+            break;
+        }
+        if kind == libc::CTRL_ATTR_FAMILY_ID as u16 && len >= 6 {
+            return Some(u16::from_ne_bytes([attributes[4], attributes[5]]));
+        }
+        attributes = attributes.get((len + 3) & !3..).unwrap_or_default();
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    /// Encodes a netlink message of `kind` answering the request `seq`.
+    fn reply(kind: u16, seq: u32, body: &[u8]) -> Vec<u8>
+    {
+        let mut message = ((16 + body.len()) as u32).to_ne_bytes().to_vec();
+        message.extend(kind.to_ne_bytes());
+        message.extend(0u16.to_ne_bytes());
+        message.extend(seq.to_ne_bytes());
+        message.extend(0u32.to_ne_bytes());
+        message.extend(body);
+        message.resize(message.len().next_multiple_of(4), 0);
+        message
+    }
+
+    #[test]
+    fn encodes_power_save_requests()
+    {
+        let mut attributes = Vec::new();
+        attribute(&mut attributes, NL80211_ATTR_IFINDEX, &3u32.to_ne_bytes());
+        attribute(&mut attributes, NL80211_ATTR_PS_STATE, &1u32.to_ne_bytes());
+        let mut expected = 36u32.to_ne_bytes().to_vec();
+        expected.extend(28u16.to_ne_bytes());
+        expected.extend(5u16.to_ne_bytes());
+        expected.extend(7u32.to_ne_bytes());
+        expected.extend(0u32.to_ne_bytes());
+        expected.extend([NL80211_CMD_SET_POWER_SAVE, 1, 0, 0]);
+        for (kind, value) in [(3u16, 3u32), (93, 1)] {
+            expected.extend(8u16.to_ne_bytes());
+            expected.extend(kind.to_ne_bytes());
+            expected.extend(value.to_ne_bytes());
+        }
+        assert_eq!(
+            encode(28, NL80211_CMD_SET_POWER_SAVE, 7, &attributes),
+            expected
+        );
+
+        let mut padded = Vec::new();
+        attribute(&mut padded, CTRL_ATTR_FAMILY_NAME, b"nl80211\0");
+        attribute(&mut padded, CTRL_ATTR_FAMILY_NAME, b"a\0");
+        let mut last = 6u16.to_ne_bytes().to_vec();
+        last.extend(CTRL_ATTR_FAMILY_NAME.to_ne_bytes());
+        last.extend(b"a\0\0\0");
+        assert_eq!(padded[12..], last[..]);
+    }
+
+    #[test]
+    fn finds_the_family_id()
+    {
+        let mut reply = vec![libc::CTRL_CMD_NEWFAMILY as u8, 2, 0, 0];
+        attribute(&mut reply, CTRL_ATTR_FAMILY_NAME, b"nl80211\0");
+        attribute(
+            &mut reply,
+            libc::CTRL_ATTR_FAMILY_ID as u16,
+            &28u16.to_ne_bytes(),
+        );
+        assert_eq!(family_id(&reply), Some(28));
+        for len in 0..reply.len() - 4 {
+            assert_eq!(family_id(&reply[..len]), None);
+        }
+        assert_eq!(family_id(&[0, 0, 0, 0, 0, 0, 2, 0]), None);
+    }
+
+    #[test]
+    fn waits_for_the_acknowledgement()
+    {
+        let mut payload = Vec::new();
+        let mut messages = reply(28, 6, b"stale");
+        messages.extend(reply(28, 7, b"data"));
+        assert!(read_replies(&messages, 7, &mut payload).is_none());
+        assert_eq!(payload, b"data");
+
+        let ack = reply(libc::NLMSG_ERROR as u16, 7, &0i32.to_ne_bytes());
+        assert!(matches!(read_replies(&ack, 7, &mut payload), Some(Ok(()))));
+        let nack = reply(libc::NLMSG_ERROR as u16, 7, &(-libc::EPERM).to_ne_bytes());
+        let err = read_replies(&nack, 7, &mut payload).unwrap().unwrap_err();
+        assert_eq!(err.raw_os_error(), Some(libc::EPERM));
+
+        for len in 0..ack.len() {
+            assert!(read_replies(&ack[..len], 7, &mut payload).is_none());
+        }
+        let mut short = ack.clone();
+        short[..4].copy_from_slice(&4u32.to_ne_bytes());
+        assert!(read_replies(&short, 7, &mut payload).is_none());
+    }
+}
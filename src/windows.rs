@@ -0,0 +1,208 @@
+use std::collections::BTreeMap;
+use std::io;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use crate::backend::BackendDevice;
+use crate::backend::PowerBackend;
+use crate::backend::Uevents;
+
+/// `SYSTEM_POWER_STATUS` of the Windows API.
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+struct SystemPowerStatus
+{
+    /// 0 offline, 1 online, 255 unknown.
+    ac_line_status: u8,
+    /// Flags of the battery: 8 charging, 128 no system battery, or 255
+    /// unknown.
+    battery_flag: u8,
+    /// 0 to 100, or 255 unknown.
+    battery_life_percent: u8,
+    system_status_flag: u8,
+    battery_life_time: u32,
+    battery_full_life_time: u32,
+}
+
+const BATTERY_CHARGING: u8 = 8;
+const NO_SYSTEM_BATTERY: u8 = 128;
+const UNKNOWN: u8 = 255;
+
+#[link(name = "kernel32")]
+extern "system" {
+    fn GetSystemPowerStatus(status: *mut SystemPowerStatus) -> i32;
+}
+
+/// The battery and the adapter that Windows reports through
+/// `GetSystemPowerStatus`, polled at an interval, for applications that
+/// read the power supply through the same [`PowerBackend`] on Linux and
+/// Windows. The devices are `BAT0` and `AC` with the drivers `battery` and
+/// `ac`, and carry the `POWER_SUPPLY_STATUS`, `POWER_SUPPLY_CAPACITY` and
+/// `POWER_SUPPLY_ONLINE` properties that udev has on Linux. Their `syspath`
+/// is only their name, as there are no attributes to read.
+pub struct WindowsBackend
+{
+    interval: Duration,
+    /// The devices as they were last read.
+    known: Vec<BackendDevice>,
+}
+
+impl WindowsBackend
+{
+    /// Reads the power status again every `interval`.
+    #[must_use]
+    pub fn new(interval: Duration) -> Self
+    {
+        Self {
+            interval,
+            known: Vec::new(),
+        }
+    }
+
+    #[must_use]
+    pub fn interval(&self) -> Duration
+    {
+        self.interval
+    }
+}
+
+impl PowerBackend for WindowsBackend
+{
+    fn enumerate(&mut self) -> io::Result<Vec<BackendDevice>>
+    {
+        let mut status = SystemPowerStatus::default();
+        if unsafe { GetSystemPowerStatus(&mut status) } == 0 {
+            return Err(io::Error::last_os_error());
+        }
+        self.known = devices(&status);
+        Ok(self.known.clone())
+    }
+
+    /// Reads the power status and returns the devices that appeared,
+    /// changed or disappeared since the last call.
+    fn drain_events(&mut self) -> io::Result<Uevents>
+    {
+        let known = std::mem::take(&mut self.known);
+        let devices = self.enumerate()?;
+        let mut uevents = Uevents::default();
+        uevents.devices.extend(
+            known
+                .iter()
+                .filter(|old| !devices.iter().any(|dev| dev.sysname == old.sysname))
+                .map(|old| BackendDevice::removed(old.syspath.clone())),
+        );
+        uevents
+            .devices
+            .extend(devices.into_iter().filter(|dev| !known.contains(dev)));
+        Ok(uevents)
+    }
+
+    fn timeout(&self) -> Option<Duration>
+    {
+        Some(self.interval)
+    }
+}
+
+/// Returns the adapter and, unless there is none, the battery that `status`
+/// describes.
+fn devices(status: &SystemPowerStatus) -> Vec<BackendDevice>
+{
+    let online = match status.ac_line_status {
+        0 => Some("0"),
+        1 => Some("1"),
+        _ => None,
+    };
+    let mut adapter = BTreeMap::from([("POWER_SUPPLY_TYPE", "Mains")]);
+    adapter.extend(online.map(|online| ("POWER_SUPPLY_ONLINE", online)));
+    let mut devices = vec![device("AC", "ac", adapter)];
+    if status.battery_flag == NO_SYSTEM_BATTERY {
+        return devices;
+    }
+    let capacity = Some(status.battery_life_percent).filter(|percent| *percent <= 100);
+    let battery_status = match (status.battery_flag, online) {
+        (UNKNOWN, _) => "Unknown",
+        (flag, _) if flag & BATTERY_CHARGING != 0 => "Charging",
+        (_, Some("1")) if capacity == Some(100) => "Full",
+        (_, Some("1")) => "Not charging",
+        (_, Some(_)) => "Discharging",
+        (_, None) => "Unknown",
+    };
+    let capacity = capacity.map(|capacity| capacity.to_string());
+    let mut battery = BTreeMap::from([
+        ("POWER_SUPPLY_TYPE", "Battery"),
+        ("POWER_SUPPLY_STATUS", battery_status),
+    ]);
+    battery.extend(
+        capacity
+            .as_deref()
+            .map(|capacity| ("POWER_SUPPLY_CAPACITY", capacity)),
+    );
+    devices.push(device("BAT0", "battery", battery));
+    devices
+}
+
+fn device(sysname: &str, driver: &str, properties: BTreeMap<&str, &str>) -> BackendDevice
+{
+    BackendDevice {
+        sysname: sysname.to_string(),
+        syspath: PathBuf::from(sysname),
+        properties: properties
+            .into_iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect(),
+        driver: Some(driver.to_string()),
+        removed: false,
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    fn status(ac_line_status: u8, battery_flag: u8, battery_life_percent: u8) -> SystemPowerStatus
+    {
+        SystemPowerStatus {
+            ac_line_status,
+            battery_flag,
+            battery_life_percent,
+            ..SystemPowerStatus::default()
+        }
+    }
+
+    fn battery(status: &SystemPowerStatus) -> Option<(String, Option<String>)>
+    {
+        let devices = devices(status);
+        let battery = devices.iter().find(|dev| dev.sysname == "BAT0")?;
+        Some((
+            battery.property_value("POWER_SUPPLY_STATUS")?.to_string(),
+            battery
+                .property_value("POWER_SUPPLY_CAPACITY")
+                .map(str::to_string),
+        ))
+    }
+
+    #[test]
+    fn reports_status_and_capacity()
+    {
+        let state =
+            |status: &str, capacity: &str| Some((status.to_string(), Some(capacity.to_string())));
+        assert_eq!(battery(&status(0, 1, 80)), state("Discharging", "80"));
+        assert_eq!(battery(&status(1, 8 | 1, 80)), state("Charging", "80"));
+        assert_eq!(battery(&status(1, 1, 100)), state("Full", "100"));
+        assert_eq!(battery(&status(1, 1, 80)), state("Not charging", "80"));
+        assert_eq!(
+            battery(&status(UNKNOWN, UNKNOWN, UNKNOWN)),
+            Some(("Unknown".to_string(), None))
+        );
+    }
+
+    #[test]
+    fn desktops_only_have_the_adapter()
+    {
+        let devices = devices(&status(1, NO_SYSTEM_BATTERY, UNKNOWN));
+        assert_eq!(devices.len(), 1);
+        assert_eq!(devices[0].sysname, "AC");
+        assert_eq!(devices[0].property_value("POWER_SUPPLY_ONLINE"), Some("1"));
+    }
+}
@@ -0,0 +1,366 @@
+use std::env;
+use std::fs;
+use std::io;
+use std::io::Read;
+use std::io::Write;
+use std::os::unix::net::UnixStream;
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// How long talking to the X server may take, since the event loop waits for
+/// it.
+const TIMEOUT: Duration = Duration::from_secs(2);
+
+const CHANGE_PROPERTY: u8 = 18;
+const PROP_MODE_REPLACE: u8 = 0;
+/// Predefined atoms, which need no `InternAtom` round trip.
+const WM_NAME: u32 = 39;
+const STRING: u32 = 31;
+
+const FAMILY_LOCAL: u16 = 256;
+const FAMILY_WILD: u16 = 65535;
+const MIT_MAGIC_COOKIE: &[u8] = b"MIT-MAGIC-COOKIE-1";
+
+/// A connection to the local X server that sets the name of the root window,
+/// like `xsetroot -name` does, which dwm shows as its status.
+pub struct XRoot
+{
+    stream: UnixStream,
+    root: u32,
+}
+
+impl XRoot
+{
+    /// Connects to the display in `$DISPLAY`, authenticating with the cookie
+    /// from `$XAUTHORITY` or `~/.Xauthority` if there is one.
+    pub fn connect() -> io::Result<Self>
+    {
+        let display = env::var("DISPLAY")
+            .map_err(|_| io::Error::new(io::ErrorKind::NotFound, "DISPLAY is not set"))?;
+        let number = parse_display(&display).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!("`{}` is not a local display", display),
+            )
+        })?;
+        let path = format!("/tmp/.X11-unix/X{}", number);
+        let stream = UnixStream::connect(&path)
+            .map_err(|err| io::Error::new(err.kind(), format!("{}: {}", path, err)))?;
+        stream.set_read_timeout(Some(TIMEOUT))?;
+        stream.set_write_timeout(Some(TIMEOUT))?;
+        let mut xroot = Self { stream, root: 0 };
+        xroot.root = xroot.setup(&cookie(number).unwrap_or_default())?;
+        Ok(xroot)
+    }
+
+    /// Sets the name of the root window to `name`. Latin-1 is what `STRING`
+    /// holds, so other characters are replaced.
+    pub fn set_name(&mut self, name: &str) -> io::Result<()>
+    {
+        let data = name
+            .chars()
+            .map(|c| u8::try_from(u32::from(c)).unwrap_or(b'?'))
+            .collect::<Vec<_>>();
+        let mut request = vec![CHANGE_PROPERTY, PROP_MODE_REPLACE];
+        let len = 6 + data.len().div_ceil(4);
+        let len = u16::try_from(len)
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "the name is too long"))?;
+        request.extend(len.to_ne_bytes());
+        request.extend(self.root.to_ne_bytes());
+        request.extend(WM_NAME.to_ne_bytes());
+        request.extend(STRING.to_ne_bytes());
+        // 8 bits per element.
+        request.extend([8, 0, 0, 0]);
+        request.extend((data.len() as u32).to_ne_bytes());
+        request.extend(&data);
+        pad(&mut request);
+        self.stream.write_all(&request)
+    }
+
+    /// Sends the connection setup and returns the root window of the first
+    /// screen.
+    fn setup(&mut self, cookie: &[u8]) -> io::Result<u32>
+    {
+        let name = if cookie.is_empty() {
+            &[][..]
+        } else {
+            MIT_MAGIC_COOKIE
+        };
+        let order = if cfg!(target_endian = "little") {
+            b'l'
+        } else {
+            b'B'
+        };
+        let mut request = vec![order, 0];
+        request.extend(11u16.to_ne_bytes());
+        request.extend(0u16.to_ne_bytes());
+        request.extend((name.len() as u16).to_ne_bytes());
+        request.extend((cookie.len() as u16).to_ne_bytes());
+        request.extend([0, 0]);
+        request.extend(name);
+        pad(&mut request);
+        request.extend(cookie);
+        pad(&mut request);
+        self.stream.write_all(&request)?;
+
+        let mut header = [0; 8];
+        self.stream.read_exact(&mut header)?;
+        let len = u16::from_ne_bytes([header[6], header[7]]) as usize * 4;
+        let mut body = vec![0; len];
+        self.stream.read_exact(&mut body)?;
+        if header[0] != 1 {
+            let reason = body.get(..header[1] as usize).unwrap_or(&body);
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                format!(
+                    "the X server refused the connection: {}",
+                    String::from_utf8_lossy(reason).trim()
+                ),
+            ));
+        }
+        if body.len() < 32 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "short connection setup reply",
+            ));
+        }
+        let u16_at = |i: usize| u16::from_ne_bytes([body[i], body[i + 1]]) as usize;
+        let vendor = u16_at(16).next_multiple_of(4);
+        let formats = body[21] as usize * 8;
+        let screen = 32 + vendor + formats;
+        let root = body
+            .get(screen..screen + 4)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "no screen"))?;
+        Ok(u32::from_ne_bytes([root[0], root[1], root[2], root[3]]))
+    }
+}
+
+/// Returns the number of a local display, e.g. 0 for `:0.0` or `unix:0`.
+fn parse_display(display: &str) -> Option<u32>
+{
+    let (host, rest) = display.rsplit_once(':')?;
+    if !host.is_empty() && host != "unix" {
+        return None;
+    }
+    let number = rest.split('.').next()?;
+    number.parse().ok()
+}
+
+/// Reads the MIT-MAGIC-COOKIE-1 of the local display `number` from the
+/// Xauthority file.
+fn cookie(number: u32) -> Option<Vec<u8>>
+{
+    let path = env::var_os("XAUTHORITY")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".Xauthority")))?;
+    find_cookie(&fs::read(path).ok()?, &hostname(), number)
+}
+
+/// Finds the MIT-MAGIC-COOKIE-1 of the local display `number` in the
+/// contents of an Xauthority file.
+fn find_cookie(data: &[u8], hostname: &[u8], number: u32) -> Option<Vec<u8>>
+{
+    let number = number.to_string();
+    let mut rest = data;
+    // Each entry is a family followed by the address, display number, name
+    // and data, all big-endian and prefixed with their length.
+    while rest.len() >= 2 {
+        let family = u16::from_be_bytes([rest[0], rest[1]]);
+        rest = &rest[2..];
+        let mut field = || {
+            let len = u16::from_be_bytes([*rest.first()?, *rest.get(1)?]) as usize;
+            let value = rest.get(2..2 + len)?;
+            rest = &rest[2 + len..];
+            Some(value)
+        };
+        let address = field()?;
+        let display = field()?;
+        let name = field()?;
+        let cookie = field()?;
+        let local = family == FAMILY_WILD || (family == FAMILY_LOCAL && address == hostname);
+        if local && (display.is_empty() || display == number.as_bytes()) && name == MIT_MAGIC_COOKIE
+        {
+            return Some(cookie.to_vec());
+        }
+    }
+    None
+}
+
+fn hostname() -> Vec<u8>
+{
+    let mut buf = [0u8; 256];
+    if unsafe { libc::gethostname(buf.as_mut_ptr().cast(), buf.len()) } != 0 {
+        return Vec::new();
+    }
+    let len = buf.iter().position(|b| *b == 0).unwrap_or(buf.len());
+    buf[..len].to_vec()
+}
+
+/// Pads a request to a multiple of 4 bytes.
+fn pad(request: &mut Vec<u8>)
+{
+    request.resize(request.len().next_multiple_of(4), 0);
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    /// Appends an Xauthority entry to `data`.
+    fn entry(data: &mut Vec<u8>, family: u16, fields: [&[u8]; 4])
+    {
+        data.extend(family.to_be_bytes());
+        for field in fields {
+            data.extend((field.len() as u16).to_be_bytes());
+            data.extend(field);
+        }
+    }
+
+    /// Returns a successful connection setup reply whose first screen has
+    /// the root window `root`.
+    fn setup_reply(root: u32) -> Vec<u8>
+    {
+        let vendor = b"Xorg!";
+        let mut body = vec![0; 32];
+        body[16..18].copy_from_slice(&(vendor.len() as u16).to_ne_bytes());
+        // One pixmap format.
+        body[21] = 1;
+        body.extend(vendor);
+        pad(&mut body);
+        body.extend([0; 8]);
+        body.extend(root.to_ne_bytes());
+        body.extend([0; 36]);
+        let mut reply = vec![1, 0, 11, 0, 0, 0];
+        reply.extend(((body.len() / 4) as u16).to_ne_bytes());
+        reply.extend(body);
+        reply
+    }
+
+    #[test]
+    fn parses_local_displays()
+    {
+        assert_eq!(parse_display(":0"), Some(0));
+        assert_eq!(parse_display(":1.0"), Some(1));
+        assert_eq!(parse_display("unix:2"), Some(2));
+        assert_eq!(parse_display("remote:0"), None);
+        assert_eq!(parse_display(":"), None);
+        assert_eq!(parse_display(""), None);
+    }
+
+    #[test]
+    fn finds_the_cookie_of_the_display()
+    {
+        let mut data = Vec::new();
+        entry(
+            &mut data,
+            FAMILY_LOCAL,
+            [b"other", b"0", MIT_MAGIC_COOKIE, b"no"],
+        );
+        entry(
+            &mut data,
+            FAMILY_LOCAL,
+            [b"host", b"1", MIT_MAGIC_COOKIE, b"one"],
+        );
+        entry(
+            &mut data,
+            FAMILY_WILD,
+            [b"", b"0", b"XDM-AUTHORIZATION-1", b"no"],
+        );
+        entry(
+            &mut data,
+            FAMILY_LOCAL,
+            [b"host", b"0", MIT_MAGIC_COOKIE, b"zero"],
+        );
+        assert_eq!(
+            find_cookie(&data, b"host", 0).as_deref(),
+            Some(&b"zero"[..])
+        );
+        assert_eq!(find_cookie(&data, b"host", 1).as_deref(), Some(&b"one"[..]));
+        assert_eq!(find_cookie(&data, b"host", 2), None);
+        for len in 0..data.len() {
+            let _ = find_cookie(&data[..len], b"host", 0);
+        }
+    }
+
+    #[test]
+    fn sets_up_the_connection()
+    {
+        let (stream, mut server) = UnixStream::pair().unwrap();
+        let mut xroot = XRoot { stream, root: 0 };
+        server.write_all(&setup_reply(0x1234)).unwrap();
+        assert_eq!(xroot.setup(b"0123456789abcdef").unwrap(), 0x1234);
+
+        let mut request = [0; 12 + 20 + 16];
+        server.read_exact(&mut request).unwrap();
+        let order = if cfg!(target_endian = "little") {
+            b'l'
+        } else {
+            b'B'
+        };
+        let mut expected = vec![order, 0];
+        expected.extend(11u16.to_ne_bytes());
+        expected.extend(0u16.to_ne_bytes());
+        expected.extend(18u16.to_ne_bytes());
+        expected.extend(16u16.to_ne_bytes());
+        expected.extend([0, 0]);
+        expected.extend(b"MIT-MAGIC-COOKIE-1\0\0");
+        expected.extend(b"0123456789abcdef");
+        assert_eq!(request[..], expected[..]);
+    }
+
+    #[test]
+    fn reports_refused_and_malformed_setups()
+    {
+        let (stream, mut server) = UnixStream::pair().unwrap();
+        let mut xroot = XRoot { stream, root: 0 };
+        let mut refusal = vec![0, 21, 11, 0, 0, 0];
+        refusal.extend(6u16.to_ne_bytes());
+        refusal.extend(b"No protocol specified\0\0\0");
+        server.write_all(&refusal).unwrap();
+        let err = xroot.setup(&[]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::PermissionDenied);
+        assert!(
+            err.to_string().ends_with(": No protocol specified"),
+            "{}",
+            err
+        );
+
+        let reply = setup_reply(1);
+        for len in [8, 16, 40, 52] {
+            let (stream, mut server) = UnixStream::pair().unwrap();
+            let mut xroot = XRoot { stream, root: 0 };
+            let mut short = reply[..len].to_vec();
+            short[6..8].copy_from_slice(&(((len - 8) / 4) as u16).to_ne_bytes());
+            server.write_all(&short).unwrap();
+            assert!(xroot.setup(&[]).is_err(), "{} bytes", len);
+        }
+        let (stream, server) = UnixStream::pair().unwrap();
+        let mut xroot = XRoot { stream, root: 0 };
+        drop(server);
+        assert!(xroot.setup(&[]).is_err());
+    }
+
+    #[test]
+    fn sets_the_name_as_latin_1()
+    {
+        let (stream, mut server) = UnixStream::pair().unwrap();
+        let mut xroot = XRoot {
+            stream,
+            root: 0x1234,
+        };
+        xroot.set_name("75% °C €").unwrap();
+        drop(xroot);
+        let mut request = Vec::new();
+        server.read_to_end(&mut request).unwrap();
+        let mut expected = vec![CHANGE_PROPERTY, PROP_MODE_REPLACE];
+        expected.extend(8u16.to_ne_bytes());
+        expected.extend(0x1234u32.to_ne_bytes());
+        expected.extend(WM_NAME.to_ne_bytes());
+        expected.extend(STRING.to_ne_bytes());
+        expected.extend([8, 0, 0, 0]);
+        expected.extend(8u32.to_ne_bytes());
+        expected.extend(b"75% \xb0C ?");
+        assert_eq!(request, expected);
+    }
+}
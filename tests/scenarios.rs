@@ -0,0 +1,166 @@
+//! Regression scenarios played against a mock laptop. A behavior reported in
+//! an issue goes here as a script, see `Scenario::play`.
+
+#![cfg(target_os = "linux")]
+
+use std::time::Duration;
+
+use lithiumd::Scenario;
+use lithiumd::Status;
+
+fn play(script: &str) -> Scenario
+{
+    let mut scenario = Scenario::laptop().unwrap();
+    if let Err(err) = scenario.play(script) {
+        panic!("{}", err);
+    }
+    scenario
+}
+
+#[test]
+fn plug()
+{
+    let mut scenario = play(
+        "
+        plug
+        expect BAT0 status changed: Charging
+        expect AC status changed: Charging
+        expect Charging status changed: Charging by BAT0
+        expect nothing
+        ",
+    );
+    assert_eq!(scenario.power_supply().charging_status(), Status::Charging);
+}
+
+#[test]
+fn unplug()
+{
+    let mut scenario = play(
+        "
+        plug
+        expect Charging status changed: Charging
+        unplug
+        expect BAT0 status changed: Discharging
+        expect AC status changed: Discharging
+        expect Charging status changed: Discharging
+        expect nothing
+        ",
+    );
+    assert_eq!(
+        scenario.power_supply().charging_status(),
+        Status::Discharging
+    );
+}
+
+#[test]
+fn adapter_reports_before_the_battery()
+{
+    // Some batteries only update their status a while after the adapter.
+    play(
+        "
+        set AC online 1
+        expect Charging status changed: Charging by AC
+        set BAT0 status Charging
+        expect BAT0 status changed: Charging
+        expect nothing
+        ",
+    );
+}
+
+#[test]
+fn battery_removal()
+{
+    let mut scenario = play(
+        "
+        remove-battery
+        expect Device removed: BAT0
+        expect nothing
+        add BAT0 type=Battery status=Discharging capacity=50
+        expect Device added: BAT0
+        expect Capacity changed: 50%
+        ",
+    );
+    let snapshot = scenario.power_supply().snapshot();
+    assert_eq!(snapshot.capacity(), Some(50));
+}
+
+#[test]
+fn overrun_resynchronizes()
+{
+    // The uevents of these changes are lost, so only the enumeration after
+    // the overrun can tell the charger was plugged in.
+    let mut scenario = play(
+        "
+        set BAT0 capacity 79
+        set AC online 1
+        set BAT0 status Charging
+        overrun
+        expect Charging status changed: Charging
+        expect Capacity changed: 79%
+        expect nothing
+        ",
+    );
+    assert_eq!(scenario.power_supply().metrics().overruns, 1);
+}
+
+#[test]
+fn suspend_and_resume()
+{
+    let mut scenario = play(
+        "
+        suspend
+        plug
+        expect nothing
+        resume 600
+        expect resumed from suspend
+        expect Charging status changed: Charging
+        expect nothing
+        ",
+    );
+    assert_eq!(scenario.power_supply().charging_status(), Status::Charging);
+}
+
+#[test]
+fn debounce_settles_bursts()
+{
+    let mut scenario = Scenario::laptop().unwrap();
+    scenario.power_supply().set_debounce(Duration::from_secs(2));
+    let coalesced = scenario.power_supply().metrics().coalesced;
+    scenario
+        .play(
+            "
+            set AC online 1
+            expect nothing
+            wait 1
+            set AC online 0
+            expect nothing
+            wait 1.5
+            expect nothing
+            wait 0.5
+            expect nothing
+            ",
+        )
+        .unwrap();
+    // The adapter went back offline before the burst settled, so nothing
+    // changed, and the two uevents made a single refresh.
+    assert_eq!(scenario.power_supply().metrics().coalesced, coalesced + 1);
+}
+
+#[test]
+fn failed_expectations_name_the_events()
+{
+    let mut scenario = Scenario::laptop().unwrap();
+    let err = scenario.play("plug\nexpect Device added").unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "line 2: expected an event starting with `Device added`, but there were: BAT0 status \
+         changed: Charging; AC status changed: Charging; Charging status changed: Charging by \
+         BAT0 (Battery)"
+    );
+    let err = scenario.play("\n\nexpect nothing").unwrap_err();
+    assert!(err.to_string().starts_with("line 3: expected no events"));
+    let err = scenario.play("# a comment\nunplug twice").unwrap_err();
+    assert_eq!(err.to_string(), "line 2: unknown step `unplug twice`");
+    let err = scenario.play("wait soon").unwrap_err();
+    assert_eq!(err.to_string(), "line 1: `soon` is not a number of seconds");
+}